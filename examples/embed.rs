@@ -0,0 +1,65 @@
+//! Embedding imagen in another program, with an image generator of your own.
+//!
+//! `ServiceContext` holds the ports a run talks to. Any `ImageGenerator` can stand in
+//! for the live providers: here one that paints a solid color, so the example runs
+//! without an API key. Its images are saved through `imagen::output` like the CLI's.
+//!
+//! ```bash
+//! cargo run --example embed
+//! ```
+
+use std::io::Cursor;
+
+use image::{DynamicImage, Rgb, RgbImage};
+use imagen::context::ServiceContext;
+use imagen::error::ImageError;
+use imagen::model::Model;
+use imagen::output::{resolve_output_path, save_image, Naming, PostProcess};
+use imagen::params::{aspect_ratio_to_dimensions, AspectRatio};
+use imagen::ports::image_generator::{GenerateFuture, GeneratedImage, ImageResponse};
+use imagen::ports::{ImageGenerator, ImageRequest};
+
+/// Answers every request with images of one color, in the requested aspect ratio.
+struct SolidColor(Rgb<u8>);
+
+impl ImageGenerator for SolidColor {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        let (width, height) = aspect_ratio_to_dimensions(&request.aspect_ratio, 512);
+        let count = request.count;
+        Box::pin(async move {
+            let mut png = Cursor::new(Vec::new());
+            DynamicImage::ImageRgb8(RgbImage::from_pixel(width, height, self.0))
+                .write_to(&mut png, image::ImageFormat::Png)
+                .map_err(|e| ImageError::ImageConversion(e.to_string()))?;
+            let image = GeneratedImage {
+                data: png.into_inner(),
+                mime_type: "image/png".to_string(),
+                signature: None,
+                url: None,
+            };
+            let images = (0..count).map(|_| image.clone()).collect();
+            Ok(ImageResponse { images, failures: Vec::new(), usage: None })
+        })
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), ImageError> {
+    let ctx =
+        ServiceContext { generator: Box::new(SolidColor(Rgb([255, 128, 0]))), enhancer: None };
+    let request = ImageRequest::builder()
+        .prompt("a wall of orange")
+        .model(Model::NanoBanana)
+        .aspect_ratio(AspectRatio::Wide16x9)
+        .format("png")
+        .build()?;
+
+    let response = ctx.generator.generate(&request).await?;
+    let naming = Naming { dir: Some(std::env::temp_dir()), ..Naming::default() };
+    let path =
+        resolve_output_path(None, &request.prompt, &request.format, &naming, &request.request_id());
+    let image = &response.images[0];
+    save_image(&image.data, &image.mime_type, &request.format, &path, &PostProcess::default())?;
+    println!("Saved: {}", path.display());
+    Ok(())
+}
@@ -0,0 +1,28 @@
+//! Bridges the `ImageEditor` port onto the `ImageGenerator` port.
+//!
+//! `imagen edit` wraps its live editor in this adapter so the retry, recording,
+//! and replaying adapters — which all speak `ImageGenerator` — apply to edits
+//! unchanged. Cassettes store edits like any other request: keyed by the
+//! request, input images included.
+
+use crate::ports::image_editor::ImageEditor;
+use crate::ports::image_generator::{GenerateFuture, ImageGenerator, ImageRequest};
+
+/// Serves every `generate` call by editing the request's input images.
+pub struct EditingImageGenerator {
+    inner: Box<dyn ImageEditor>,
+}
+
+impl EditingImageGenerator {
+    /// Wrap a live editor.
+    #[must_use]
+    pub fn new(inner: Box<dyn ImageEditor>) -> Self {
+        Self { inner }
+    }
+}
+
+impl ImageGenerator for EditingImageGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        self.inner.edit(request)
+    }
+}
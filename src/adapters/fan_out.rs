@@ -0,0 +1,221 @@
+//! Fan-out decorator for the `ImageGenerator` port.
+//!
+//! Some providers return one image per call whatever `count` says (Gemini renders a
+//! single image per response), and the rest cap how many one call returns. This decorator
+//! serves `--count N` on them by sending requests of at most `per_call` images, at most
+//! `max_parallel` at a time, and merging the results in request order. With a seed, each
+//! call starts at the seed of its first image, so the images differ but the run can be
+//! repeated. It sits inside the recording adapter, so a cassette holds one interaction
+//! with the original count and the merged images, identical from run to run.
+
+use futures_util::stream::{self, StreamExt};
+
+use crate::ports::image_generator::{
+    GenerateFuture, ImageFailure, ImageGenerator, ImageRequest, ImageResponse, PartialSink, Usage,
+};
+
+/// Splits `count` into requests of at most `per_call` images, issues them concurrently,
+/// and merges their images.
+pub struct FanOutImageGenerator {
+    inner: Box<dyn ImageGenerator>,
+    max_parallel: usize,
+    per_call: u32,
+}
+
+impl FanOutImageGenerator {
+    /// Wrap `inner` with single-image requests, keeping at most `max_parallel` in flight.
+    #[must_use]
+    pub fn new(inner: Box<dyn ImageGenerator>, max_parallel: usize) -> Self {
+        Self { inner, max_parallel: max_parallel.max(1), per_call: 1 }
+    }
+
+    /// Ask for up to `per_call` images in each request instead of one.
+    #[must_use]
+    pub fn per_call(self, per_call: u32) -> Self {
+        Self { per_call: per_call.max(1), ..self }
+    }
+}
+
+impl ImageGenerator for FanOutImageGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        if request.count <= self.per_call {
+            return self.inner.generate(request);
+        }
+        let request = request.clone();
+        Box::pin(async move {
+            let calls: Vec<ImageRequest> = (0..request.count)
+                .step_by(self.per_call as usize)
+                .map(|first| ImageRequest {
+                    count: self.per_call.min(request.count - first),
+                    seed: request.image_seed(first),
+                    ..request.clone()
+                })
+                .collect();
+            let results: Vec<_> = stream::iter(0..calls.len())
+                .map(|call| self.inner.generate(&calls[call]))
+                .buffered(self.max_parallel)
+                .collect()
+                .await;
+
+            // A failed call takes the place of the images it would have produced, so the
+            // remaining images keep their numbering.
+            let mut images = Vec::new();
+            let mut failures = Vec::new();
+            let mut usage: Option<Usage> = None;
+            let mut first_error = None;
+            for (result, call) in results.into_iter().zip(&calls) {
+                let index = images.len() + failures.len();
+                match result {
+                    Ok(response) => {
+                        if let Some(call_usage) = response.usage {
+                            *usage.get_or_insert_default() += call_usage;
+                        }
+                        failures.extend(response.failures.into_iter().map(|failure| {
+                            ImageFailure { index: index + failure.index, ..failure }
+                        }));
+                        images.extend(response.images);
+                    }
+                    Err(e) => {
+                        failures.extend(
+                            (index..index + call.count as usize)
+                                .map(|index| ImageFailure { index, reason: e.to_string() }),
+                        );
+                        first_error.get_or_insert(e);
+                    }
+                }
+            }
+            match first_error {
+                // Every call failed: report the error itself rather than a summary.
+                Some(e) if images.is_empty() => Err(e),
+                _ => Ok(ImageResponse { usage, ..ImageResponse::partial(images, failures)? }),
+            }
+        })
+    }
+
+    fn generate_streaming(
+        &self,
+        request: &ImageRequest,
+        partials: PartialSink,
+    ) -> GenerateFuture<'_> {
+        // Streaming is limited to single images, which are never fanned out.
+        if request.count <= self.per_call {
+            return self.inner.generate_streaming(request, partials);
+        }
+        drop(partials);
+        self.generate(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use crate::error::ImageError;
+    use crate::ports::image_generator::GeneratedImage;
+
+    /// Answers call N with one-byte images `[N]`, later calls finishing sooner; fails
+    /// the calls listed in `fail`. `peak` records the most calls in flight at once.
+    struct Numbered {
+        calls: AtomicU32,
+        in_flight: AtomicU32,
+        peak: Arc<AtomicU32>,
+        fail: Vec<u32>,
+    }
+
+    fn numbered(fail: Vec<u32>) -> (Box<Numbered>, Arc<AtomicU32>) {
+        let peak = Arc::new(AtomicU32::new(0));
+        let generator = Numbered {
+            calls: AtomicU32::new(0),
+            in_flight: AtomicU32::new(0),
+            peak: Arc::clone(&peak),
+            fail,
+        };
+        (Box::new(generator), peak)
+    }
+
+    impl ImageGenerator for Numbered {
+        fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            let count = request.count as usize;
+            Box::pin(async move {
+                let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.peak.fetch_max(now, Ordering::SeqCst);
+                let delay = u64::from(10 - call.min(10)) * 5;
+                tokio::time::sleep(Duration::from_millis(delay)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                if self.fail.contains(&call) {
+                    return Err(ImageError::Api { status: 500, message: format!("call {call}") });
+                }
+                let image = GeneratedImage {
+                    data: vec![u8::try_from(call).unwrap()],
+                    mime_type: "image/png".into(),
+                    signature: None,
+                    url: None,
+                };
+                Ok(ImageResponse {
+                    images: vec![image; count],
+                    failures: Vec::new(),
+                    usage: Some(Usage { input_tokens: 10, output_tokens: 100 }),
+                })
+            })
+        }
+    }
+
+    fn request(count: u32) -> ImageRequest {
+        ImageRequest {
+            format: "png".into(),
+            count,
+            ..ImageRequest::sample("gemini-3.1-flash-image-preview", "a cat")
+        }
+    }
+
+    #[tokio::test]
+    async fn merges_images_in_request_order_with_bounded_concurrency() {
+        let (inner, peak) = numbered(vec![]);
+        let generator = FanOutImageGenerator::new(inner, 2);
+        let response = generator.generate(&request(5)).await.unwrap();
+        let data: Vec<u8> = response.images.iter().map(|image| image.data[0]).collect();
+        assert_eq!(data, vec![0, 1, 2, 3, 4]);
+        assert_eq!(response.usage, Some(Usage { input_tokens: 50, output_tokens: 500 }));
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_calls_become_numbered_failures() {
+        let generator = FanOutImageGenerator::new(numbered(vec![1]).0, 4);
+        let response = generator.generate(&request(3)).await.unwrap();
+        assert_eq!(response.images.len(), 2);
+        assert_eq!(
+            response.failures,
+            vec![ImageFailure { index: 1, reason: "API error (500): call 1".into() }]
+        );
+    }
+
+    #[tokio::test]
+    async fn all_calls_failing_returns_the_error() {
+        let generator = FanOutImageGenerator::new(numbered(vec![0, 1]).0, 4);
+        let err = generator.generate(&request(2)).await.unwrap_err();
+        assert!(matches!(err, ImageError::Api { status: 500, .. }));
+    }
+
+    #[tokio::test]
+    async fn counts_over_the_limit_are_split_into_full_calls() {
+        let generator = FanOutImageGenerator::new(numbered(vec![1]).0, 4).per_call(2);
+        let response = generator.generate(&request(5)).await.unwrap();
+        let data: Vec<u8> = response.images.iter().map(|image| image.data[0]).collect();
+        assert_eq!(data, vec![0, 0, 2]);
+        let failed: Vec<usize> = response.failures.iter().map(|failure| failure.index).collect();
+        assert_eq!(failed, vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn single_images_pass_straight_through() {
+        let (inner, peak) = numbered(vec![]);
+        let generator = FanOutImageGenerator::new(inner, 4);
+        assert_eq!(generator.generate(&request(1)).await.unwrap().images.len(), 1);
+        assert_eq!(peak.load(Ordering::SeqCst), 1);
+    }
+}
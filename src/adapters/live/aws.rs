@@ -0,0 +1,342 @@
+//! AWS credential resolution and Signature Version 4 request signing.
+//!
+//! Credentials follow the standard AWS chain, minus the SSO and instance-metadata
+//! steps: `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY` / `AWS_SESSION_TOKEN`, then the
+//! selected profile in `~/.aws/credentials`, then the same profile in `~/.aws/config`.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+/// Static AWS credentials used to sign requests.
+#[derive(Clone, PartialEq, Eq)]
+pub struct AwsCredentials {
+    /// Access key ID (`AKIA...` or `ASIA...`).
+    pub access_key_id: String,
+    /// Secret access key.
+    pub secret_access_key: String,
+    /// Session token for temporary credentials.
+    pub session_token: Option<String>,
+}
+
+impl std::fmt::Debug for AwsCredentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AwsCredentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field("session_token", &self.session_token.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl AwsCredentials {
+    /// Read credentials from `AWS_ACCESS_KEY_ID` / `AWS_SECRET_ACCESS_KEY` / `AWS_SESSION_TOKEN`.
+    #[must_use]
+    pub fn from_env() -> Option<Self> {
+        Some(Self {
+            access_key_id: std::env::var("AWS_ACCESS_KEY_ID").ok()?,
+            secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+
+    /// Read credentials from a section of a shared credentials/config file.
+    fn from_section(section: &HashMap<String, String>) -> Option<Self> {
+        Some(Self {
+            access_key_id: section.get("aws_access_key_id")?.clone(),
+            secret_access_key: section.get("aws_secret_access_key")?.clone(),
+            session_token: section.get("aws_session_token").cloned(),
+        })
+    }
+}
+
+/// Resolve the AWS profile name: `AWS_PROFILE`, then `configured`, then `"default"`.
+#[must_use]
+pub fn resolve_profile(configured: Option<&str>) -> String {
+    std::env::var("AWS_PROFILE")
+        .ok()
+        .or_else(|| configured.map(ToString::to_string))
+        .unwrap_or_else(|| "default".to_string())
+}
+
+/// Resolve credentials from the environment, then the shared credentials and config files.
+#[must_use]
+pub fn resolve_credentials(profile: &str) -> Option<AwsCredentials> {
+    AwsCredentials::from_env()
+        .or_else(|| {
+            let section = read_section(&credentials_file_path()?, profile)?;
+            AwsCredentials::from_section(&section)
+        })
+        .or_else(|| {
+            let section = read_section(&config_file_path()?, &config_section_name(profile))?;
+            AwsCredentials::from_section(&section)
+        })
+}
+
+/// Resolve the region: `AWS_REGION`, `AWS_DEFAULT_REGION`, `configured`, then the profile in
+/// `~/.aws/config`.
+#[must_use]
+pub fn resolve_region(profile: &str, configured: Option<&str>) -> Option<String> {
+    std::env::var("AWS_REGION")
+        .ok()
+        .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok())
+        .or_else(|| configured.map(ToString::to_string))
+        .or_else(|| {
+            let section = read_section(&config_file_path()?, &config_section_name(profile))?;
+            section.get("region").cloned()
+        })
+}
+
+/// `~/.aws/config` names non-default profiles `[profile <name>]`.
+fn config_section_name(profile: &str) -> String {
+    if profile == "default" {
+        profile.to_string()
+    } else {
+        format!("profile {profile}")
+    }
+}
+
+fn credentials_file_path() -> Option<PathBuf> {
+    std::env::var("AWS_SHARED_CREDENTIALS_FILE")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var("HOME").ok()?).join(".aws/credentials")))
+}
+
+fn config_file_path() -> Option<PathBuf> {
+    std::env::var("AWS_CONFIG_FILE")
+        .ok()
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(std::env::var("HOME").ok()?).join(".aws/config")))
+}
+
+fn read_section(path: &Path, name: &str) -> Option<HashMap<String, String>> {
+    parse_ini_section(&std::fs::read_to_string(path).ok()?, name)
+}
+
+/// Extract the `key = value` pairs of one `[name]` section from an INI document.
+fn parse_ini_section(contents: &str, name: &str) -> Option<HashMap<String, String>> {
+    let mut found = None;
+    let mut in_section = false;
+    for line in contents.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(header) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            in_section = header.trim() == name;
+            if in_section {
+                found.get_or_insert_with(HashMap::new);
+            }
+            continue;
+        }
+        if let (true, Some(section), Some((key, value))) =
+            (in_section, found.as_mut(), line.split_once('='))
+        {
+            section.insert(key.trim().to_string(), value.trim().to_string());
+        }
+    }
+    found
+}
+
+/// Percent-encode a string per RFC 3986, leaving only unreserved characters as-is.
+#[must_use]
+pub fn uri_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut out, b| {
+        let _ = write!(out, "{b:02x}");
+        out
+    })
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// A request to be signed with `SigV4`.
+pub struct SigningRequest<'a> {
+    /// HTTP method (e.g., `"POST"`).
+    pub method: &'a str,
+    /// Host header value.
+    pub host: &'a str,
+    /// Request path, already percent-encoded once as sent on the wire.
+    pub path: &'a str,
+    /// Additional headers to sign (lowercase names), such as `content-type`.
+    pub headers: &'a [(&'a str, &'a str)],
+    /// Request body.
+    pub payload: &'a [u8],
+}
+
+/// Sign a request with AWS Signature Version 4.
+///
+/// Returns the headers to attach: `x-amz-date`, `x-amz-security-token` (for temporary
+/// credentials), and `authorization`. The path is encoded a second time for the canonical
+/// request, as every service except S3 expects.
+#[must_use]
+pub fn sign(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    request: &SigningRequest<'_>,
+    now: DateTime<Utc>,
+) -> Vec<(&'static str, String)> {
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+
+    let mut headers: Vec<(String, String)> = request
+        .headers
+        .iter()
+        .map(|(k, v)| (k.to_ascii_lowercase(), v.trim().to_string()))
+        .collect();
+    headers.push(("host".into(), request.host.to_string()));
+    headers.push(("x-amz-date".into(), amz_date.clone()));
+    if let Some(ref token) = credentials.session_token {
+        headers.push(("x-amz-security-token".into(), token.clone()));
+    }
+    headers.sort();
+
+    let canonical_uri: Vec<String> = request.path.split('/').map(uri_encode).collect();
+    let canonical_headers = headers.iter().fold(String::new(), |mut out, (k, v)| {
+        let _ = writeln!(out, "{k}:{v}");
+        out
+    });
+    let signed_headers: Vec<&str> = headers.iter().map(|(k, _)| k.as_str()).collect();
+    let signed_headers = signed_headers.join(";");
+    let canonical_request = format!(
+        "{}\n{}\n\n{canonical_headers}\n{signed_headers}\n{}",
+        request.method,
+        canonical_uri.join("/"),
+        hex(&Sha256::digest(request.payload)),
+    );
+
+    let scope = format!("{date}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{amz_date}\n{scope}\n{}",
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+
+    let k_date = hmac_sha256(format!("AWS4{}", credentials.secret_access_key).as_bytes(), &date);
+    let k_region = hmac_sha256(&k_date, region);
+    let k_service = hmac_sha256(&k_region, service);
+    let k_signing = hmac_sha256(&k_service, "aws4_request");
+    let signature = hex(&hmac_sha256(&k_signing, &string_to_sign));
+
+    let mut out = vec![("x-amz-date", amz_date)];
+    if let Some(ref token) = credentials.session_token {
+        out.push(("x-amz-security-token", token.clone()));
+    }
+    out.push((
+        "authorization",
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, \
+             Signature={signature}",
+            credentials.access_key_id
+        ),
+    ));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn example_credentials() -> AwsCredentials {
+        AwsCredentials {
+            access_key_id: "AKIDEXAMPLE".into(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into(),
+            session_token: None,
+        }
+    }
+
+    #[test]
+    fn sign_matches_aws_test_suite_get_vanilla() {
+        let request = SigningRequest {
+            method: "GET",
+            host: "example.amazonaws.com",
+            path: "/",
+            headers: &[],
+            payload: b"",
+        };
+        let now = Utc.with_ymd_and_hms(2015, 8, 30, 12, 36, 0).unwrap();
+        let headers = sign(&example_credentials(), "us-east-1", "service", &request, now);
+        let auth = &headers.iter().find(|(k, _)| *k == "authorization").unwrap().1;
+        assert_eq!(
+            auth,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn sign_includes_session_token() {
+        let mut credentials = example_credentials();
+        credentials.session_token = Some("token".into());
+        let request = SigningRequest {
+            method: "GET",
+            host: "example.amazonaws.com",
+            path: "/",
+            headers: &[],
+            payload: b"",
+        };
+        let headers = sign(&credentials, "us-east-1", "service", &request, Utc::now());
+        assert!(headers.iter().any(|(k, v)| *k == "x-amz-security-token" && v == "token"));
+        let auth = &headers.iter().find(|(k, _)| *k == "authorization").unwrap().1;
+        assert!(auth.contains("SignedHeaders=host;x-amz-date;x-amz-security-token"));
+    }
+
+    #[test]
+    fn uri_encode_reserved_characters() {
+        assert_eq!(
+            uri_encode("amazon.titan-image-generator-v2:0"),
+            "amazon.titan-image-generator-v2%3A0"
+        );
+        assert_eq!(uri_encode("a b/c~"), "a%20b%2Fc~");
+    }
+
+    #[test]
+    fn parse_ini_profiles() {
+        let ini = "[default]\naws_access_key_id = AKIA1\naws_secret_access_key = s1\n\n\
+                   # comment\n[profile work]\nregion = eu-west-1\n";
+        let default = parse_ini_section(ini, "default").unwrap();
+        assert_eq!(default.get("aws_access_key_id").unwrap(), "AKIA1");
+        let creds = AwsCredentials::from_section(&default).unwrap();
+        assert_eq!(creds.secret_access_key, "s1");
+        assert!(creds.session_token.is_none());
+
+        let work = parse_ini_section(ini, "profile work").unwrap();
+        assert_eq!(work.get("region").unwrap(), "eu-west-1");
+        assert!(AwsCredentials::from_section(&work).is_none());
+        assert!(parse_ini_section(ini, "missing").is_none());
+    }
+
+    #[test]
+    fn config_section_names() {
+        assert_eq!(config_section_name("default"), "default");
+        assert_eq!(config_section_name("work"), "profile work");
+    }
+
+    #[test]
+    fn debug_redacts_secrets() {
+        let debug = format!("{:?}", example_credentials());
+        assert!(debug.contains("AKIDEXAMPLE"));
+        assert!(!debug.contains("EXAMPLEKEY"));
+    }
+}
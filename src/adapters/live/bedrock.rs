@@ -0,0 +1,320 @@
+//! Live adapter for image models on AWS Bedrock.
+//!
+//! Bedrock hosts several model families behind one `InvokeModel` endpoint, each with its
+//! own request schema. Amazon Titan Image Generator and Nova Canvas share the
+//! `taskType` / `imageGenerationConfig` schema; Stability models on Bedrock take a flat
+//! prompt body and return one image per call. Requests are signed with `SigV4`.
+
+use base64::Engine;
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::aws::{sign, uri_encode, AwsCredentials, SigningRequest};
+use super::http::{api_error, build_client, HttpSettings};
+use crate::error::ImageError;
+use crate::params::aspect_ratio_to_dimensions;
+use crate::ports::image_generator::{
+    GenerateFuture, GeneratedImage, ImageFailure, ImageGenerator, ImageRequest, ImageResponse,
+};
+
+/// `SigV4` service name for the Bedrock runtime API.
+const BEDROCK_SERVICE: &str = "bedrock";
+
+/// Strength used for Stability image-to-image requests on Bedrock.
+const IMAGE_TO_IMAGE_STRENGTH: f64 = 0.6;
+
+/// Live Bedrock image generator.
+pub struct BedrockGenerator {
+    client: Client,
+    credentials: AwsCredentials,
+    region: String,
+    endpoint: String,
+}
+
+impl BedrockGenerator {
+    /// Create a new Bedrock generator.
+    ///
+    /// `base_url` overrides the regional `bedrock-runtime` endpoint (e.g., for a VPC endpoint).
+    #[must_use]
+    pub fn new(
+        credentials: AwsCredentials,
+        region: String,
+        base_url: Option<&str>,
+        http: &HttpSettings,
+    ) -> Self {
+        let endpoint = base_url.map_or_else(
+            || format!("https://bedrock-runtime.{region}.amazonaws.com"),
+            |url| url.trim_end_matches('/').to_string(),
+        );
+        Self { client: build_client(http), credentials, region, endpoint }
+    }
+
+    /// Sign and send one `InvokeModel` call, returning the response body.
+    async fn invoke(&self, model: &str, body: &serde_json::Value) -> Result<String, ImageError> {
+        let path = format!("/model/{}/invoke", uri_encode(model));
+        let url = format!("{}{path}", self.endpoint);
+        let host = reqwest::Url::parse(&url)
+            .ok()
+            .and_then(|u| {
+                let host = u.host_str()?.to_string();
+                Some(u.port().map_or_else(|| host.clone(), |port| format!("{host}:{port}")))
+            })
+            .ok_or_else(|| ImageError::Config(format!("Invalid Bedrock endpoint: {url}")))?;
+        let payload = serde_json::to_vec(body).expect("JSON body is always serializable");
+
+        let content_headers =
+            [("accept", "application/json"), ("content-type", "application/json")];
+        let signed = sign(
+            &self.credentials,
+            &self.region,
+            BEDROCK_SERVICE,
+            &SigningRequest {
+                method: "POST",
+                host: &host,
+                path: &path,
+                headers: &content_headers,
+                payload: &payload,
+            },
+            chrono::Utc::now(),
+        );
+
+        let mut builder = self.client.post(&url).body(payload);
+        for (name, value) in content_headers {
+            builder = builder.header(name, value);
+        }
+        for (name, value) in signed {
+            builder = builder.header(name, value);
+        }
+
+        let response = builder.send().await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        let text = response.text().await?;
+        Ok(text)
+    }
+}
+
+/// Model families served by Bedrock, each with its own request schema.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ModelFamily {
+    /// Amazon Titan Image Generator.
+    Titan,
+    /// Amazon Nova Canvas (Titan-compatible schema, larger sizes).
+    NovaCanvas,
+    /// Stability AI models (`stability.*`).
+    Stability,
+}
+
+fn model_family(model: &str) -> ModelFamily {
+    if model.starts_with("stability.") {
+        ModelFamily::Stability
+    } else if model.starts_with("amazon.nova-canvas") {
+        ModelFamily::NovaCanvas
+    } else {
+        ModelFamily::Titan
+    }
+}
+
+/// Build a Titan / Nova Canvas request body.
+///
+/// Titan tops out at 1408px, so it always renders at 1024px on the long edge; Nova Canvas
+/// renders 2K and 4K requests at 2048px (its pixel-count limit).
+fn amazon_body(request: &ImageRequest, family: ModelFamily) -> serde_json::Value {
+    let long_edge =
+        if family == ModelFamily::NovaCanvas && request.size != "1K" { 2048 } else { 1024 };
+    let (width, height) = aspect_ratio_to_dimensions(&request.aspect_ratio, long_edge);
+    let quality = if request.quality == "high" { "premium" } else { "standard" };
+    let mut config = serde_json::json!({
+        "numberOfImages": request.count,
+        "width": width,
+        "height": height,
+        "quality": quality,
+    });
+    if let Some(seed) = request.seed {
+        config["seed"] = seed.into();
+    }
+
+    if request.input_images.is_empty() {
+        serde_json::json!({
+            "taskType": "TEXT_IMAGE",
+            "textToImageParams": {"text": request.prompt},
+            "imageGenerationConfig": config,
+        })
+    } else {
+        let images: Vec<String> = request
+            .input_images
+            .iter()
+            .map(|img| base64::engine::general_purpose::STANDARD.encode(&img.data))
+            .collect();
+        serde_json::json!({
+            "taskType": "IMAGE_VARIATION",
+            "imageVariationParams": {"text": request.prompt, "images": images},
+            "imageGenerationConfig": config,
+        })
+    }
+}
+
+/// Build a Stability-on-Bedrock request body for image `index` (one image per call).
+fn stability_body(request: &ImageRequest, index: u32) -> serde_json::Value {
+    let output_format = if request.format == "jpeg" { "jpeg" } else { "png" };
+    let mut body = serde_json::json!({
+        "prompt": request.prompt,
+        "output_format": output_format,
+    });
+    if let Some(img) = request.input_images.first() {
+        body["mode"] = "image-to-image".into();
+        body["image"] = base64::engine::general_purpose::STANDARD.encode(&img.data).into();
+        body["strength"] = IMAGE_TO_IMAGE_STRENGTH.into();
+    } else {
+        body["mode"] = "text-to-image".into();
+        body["aspect_ratio"] = request.aspect_ratio.clone().into();
+    }
+    if let Some(seed) = request.image_seed(index) {
+        body["seed"] = seed.into();
+    }
+    body
+}
+
+/// Parse a Bedrock image response (`{"images": [...]}`) into generated images.
+fn parse_response(response_text: &str, mime_type: &str) -> Result<ImageResponse, ImageError> {
+    let parsed: BedrockResponse = serde_json::from_str(response_text).map_err(|e| {
+        ImageError::Api { status: 200, message: format!("Failed to parse response: {e}") }
+    })?;
+
+    if let Some(error) = parsed.error {
+        return Err(ImageError::Api { status: 200, message: error });
+    }
+    if let Some(reason) = parsed.finish_reasons.into_iter().flatten().next() {
+        return Err(ImageError::Api {
+            status: 200,
+            message: format!("Image was blocked by Bedrock: {reason}"),
+        });
+    }
+
+    let mut images = Vec::new();
+    let mut failures = Vec::new();
+    for (index, b64) in parsed.images.iter().enumerate() {
+        match base64::engine::general_purpose::STANDARD.decode(b64) {
+            Ok(data) => images.push(GeneratedImage {
+                data,
+                mime_type: mime_type.to_string(),
+                signature: None,
+                url: None,
+            }),
+            Err(e) => failures.push(ImageFailure { index, reason: format!("invalid base64: {e}") }),
+        }
+    }
+
+    if images.is_empty() && failures.is_empty() {
+        return Err(ImageError::NoImages {
+            finish_reason: None,
+            detail: "Bedrock returned an empty image list".into(),
+        });
+    }
+    ImageResponse::partial(images, failures)
+}
+
+impl ImageGenerator for BedrockGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let family = model_family(&request.model);
+            if family != ModelFamily::Stability {
+                let text = self.invoke(&request.model, &amazon_body(&request, family)).await?;
+                return parse_response(&text, "image/png");
+            }
+
+            // Stability models on Bedrock return a single image per call.
+            let mime_type = if request.format == "jpeg" { "image/jpeg" } else { "image/png" };
+            let mut images = Vec::new();
+            let mut failures = Vec::new();
+            for index in 0..request.count {
+                let text = self.invoke(&request.model, &stability_body(&request, index)).await?;
+                match parse_response(&text, mime_type) {
+                    Ok(response) => images.extend(response.images),
+                    Err(e) => failures.push(ImageFailure {
+                        index: images.len() + failures.len(),
+                        reason: e.to_string(),
+                    }),
+                }
+            }
+            ImageResponse::partial(images, failures)
+        })
+    }
+}
+
+// --- Bedrock API response types ---
+
+#[derive(Deserialize)]
+struct BedrockResponse {
+    #[serde(default)]
+    images: Vec<String>,
+    #[serde(default)]
+    error: Option<String>,
+    #[serde(default)]
+    finish_reasons: Vec<Option<String>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(model: &str) -> ImageRequest {
+        ImageRequest {
+            aspect_ratio: "16:9".into(),
+            count: 2,
+            ..ImageRequest::sample(model, "a lighthouse")
+        }
+    }
+
+    #[test]
+    fn families() {
+        assert_eq!(model_family("amazon.titan-image-generator-v2:0"), ModelFamily::Titan);
+        assert_eq!(model_family("amazon.nova-canvas-v1:0"), ModelFamily::NovaCanvas);
+        assert_eq!(model_family("stability.sd3-5-large-v1:0"), ModelFamily::Stability);
+    }
+
+    #[test]
+    fn titan_text_to_image_body() {
+        let body = amazon_body(&request("amazon.titan-image-generator-v2:0"), ModelFamily::Titan);
+        assert_eq!(body["taskType"], "TEXT_IMAGE");
+        assert_eq!(body["textToImageParams"]["text"], "a lighthouse");
+        assert_eq!(body["imageGenerationConfig"]["numberOfImages"], 2);
+        assert_eq!(body["imageGenerationConfig"]["width"], 1024);
+        assert_eq!(body["imageGenerationConfig"]["height"], 576);
+        assert_eq!(body["imageGenerationConfig"]["quality"], "standard");
+    }
+
+    #[test]
+    fn stability_body_modes() {
+        let body = stability_body(&request("stability.sd3-5-large-v1:0"), 0);
+        assert_eq!(body["mode"], "text-to-image");
+        assert_eq!(body["aspect_ratio"], "16:9");
+        assert_eq!(body["output_format"], "jpeg");
+        assert!(body.get("seed").is_none());
+    }
+
+    #[test]
+    fn stability_bodies_take_consecutive_seeds() {
+        let request = ImageRequest { seed: Some(7), ..request("stability.sd3-5-large-v1:0") };
+        assert_eq!(stability_body(&request, 0)["seed"], 7);
+        assert_eq!(stability_body(&request, 2)["seed"], 9);
+    }
+
+    #[test]
+    fn parse_success_response() {
+        let images = parse_response(r#"{"images": ["/9j/2Q=="], "error": null}"#, "image/png")
+            .unwrap()
+            .images;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].mime_type, "image/png");
+    }
+
+    #[test]
+    fn parse_filtered_response() {
+        let body = r#"{"images": [], "finish_reasons": ["Filter reason: prompt"]}"#;
+        let err = parse_response(body, "image/png").unwrap_err();
+        assert!(err.to_string().contains("Filter reason: prompt"), "got: {err}");
+    }
+}
@@ -1,27 +1,107 @@
 //! Live adapter for the Gemini image generation API.
+//!
+//! The same `generateContent` call also serves `--enhance`, with a text model such as
+//! `gemini-2.5-flash` rewriting the prompt.
+//!
+//! Requests go either to the Google AI developer endpoint (`x-goog-api-key`) or to
+//! Vertex AI, which authenticates with an OAuth access token (see `google_auth.rs`).
 
 use base64::Engine;
 use reqwest::Client;
 use serde::Deserialize;
 
+use super::google_auth::{GoogleCredentials, TokenSource};
+use super::http::{api_error, build_client, HttpSettings};
 use crate::error::ImageError;
+use crate::params::gemini_safety_threshold;
+use crate::ports::image_editor::ImageEditor;
 use crate::ports::image_generator::{
-    GenerateFuture, GeneratedImage, ImageGenerator, ImageRequest, ImageResponse,
+    GenerateFuture, GeneratedImage, ImageFailure, ImageGenerator, ImageRequest, ImageResponse,
+    InputImage, Usage,
 };
+use crate::ports::prompt_enhancer::{EnhanceFuture, EnhanceRequest, PromptEnhancer};
 
 const GEMINI_API_BASE: &str = "https://generativelanguage.googleapis.com/v1beta/models";
 
+/// Caption introducing `--ref` images, so the model borrows from them rather than editing them.
+const REFERENCE_CAPTION: &str =
+    "Reference images (match their style and composition; do not reproduce them directly):";
+
+/// A Vertex AI project and location to send Gemini requests to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VertexTarget {
+    /// Google Cloud project ID.
+    pub project: String,
+    /// Vertex AI location, e.g. `global` or `us-central1`.
+    pub location: String,
+}
+
+/// How requests are addressed and authenticated.
+enum Backend {
+    /// Google AI developer API, authenticated with an API key.
+    AiStudio { api_key: String },
+    /// Vertex AI, authenticated with an OAuth access token.
+    Vertex { target: VertexTarget, tokens: TokenSource },
+}
+
 /// Live Gemini image generator that calls the Google AI API.
 pub struct GeminiGenerator {
     client: Client,
-    api_key: String,
+    backend: Backend,
 }
 
 impl GeminiGenerator {
-    /// Create a new Gemini generator with the given API key.
+    /// Create a new Gemini generator with the given API key and HTTP settings.
+    #[must_use]
+    pub fn new(api_key: String, http: &HttpSettings) -> Self {
+        Self { client: build_client(http), backend: Backend::AiStudio { api_key } }
+    }
+
+    /// Create a generator that calls Gemini through Vertex AI.
     #[must_use]
-    pub fn new(api_key: String) -> Self {
-        Self { client: Client::new(), api_key }
+    pub fn vertex(
+        target: VertexTarget,
+        credentials: GoogleCredentials,
+        http: &HttpSettings,
+    ) -> Self {
+        let tokens = TokenSource::new(credentials);
+        Self { client: build_client(http), backend: Backend::Vertex { target, tokens } }
+    }
+
+    /// Build an authenticated `generateContent` request for `model`.
+    async fn post(&self, model: &str) -> Result<reqwest::RequestBuilder, ImageError> {
+        match &self.backend {
+            Backend::AiStudio { api_key } => {
+                let url = format!("{GEMINI_API_BASE}/{model}:generateContent");
+                Ok(self.client.post(url).header("x-goog-api-key", api_key))
+            }
+            Backend::Vertex { target, tokens } => {
+                let token = tokens.token(&self.client).await?;
+                Ok(self.client.post(vertex_url(target, model)).bearer_auth(token))
+            }
+        }
+    }
+}
+
+/// Vertex AI `generateContent` URL; the `global` location has no regional host prefix.
+fn vertex_url(target: &VertexTarget, model: &str) -> String {
+    let VertexTarget { project, location } = target;
+    let host = if location == "global" {
+        "aiplatform.googleapis.com".to_string()
+    } else {
+        format!("{location}-aiplatform.googleapis.com")
+    };
+    format!(
+        "https://{host}/v1/projects/{project}/locations/{location}/publishers/google/models/\
+         {model}:generateContent"
+    )
+}
+
+// Gemini edits through the same `generateContent` call: the source images are sent as
+// inline parts next to the instruction.
+impl ImageEditor for GeminiGenerator {
+    fn edit(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        self.generate(request)
     }
 }
 
@@ -29,8 +109,6 @@ impl ImageGenerator for GeminiGenerator {
     fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
         let request = request.clone();
         Box::pin(async move {
-            let url = format!("{GEMINI_API_BASE}/{}:generateContent", request.model);
-
             let mut generation_config = serde_json::json!({
                 "responseModalities": ["IMAGE"],
                 "imageConfig": {
@@ -39,97 +117,290 @@ impl ImageGenerator for GeminiGenerator {
                 }
             });
 
+            if let Some(seed) = request.seed {
+                generation_config["seed"] = seed.into();
+            }
             if let Some(ref thinking) = request.thinking {
                 generation_config["thinkingConfig"] = serde_json::json!({
                     "thinkingLevel": thinking.to_uppercase()
                 });
             }
 
-            // Build parts: text prompt + any inline image data
-            let mut parts = vec![serde_json::json!({"text": request.prompt})];
-            for img in &request.input_images {
-                let b64 = base64::engine::general_purpose::STANDARD.encode(&img.data);
-                parts.push(serde_json::json!({
-                    "inlineData": {
-                        "mimeType": img.mime_type,
-                        "data": b64
-                    }
-                }));
-            }
-
-            let body = serde_json::json!({
-                "contents": [{
-                    "parts": parts
-                }],
+            let mut body = serde_json::json!({
+                "contents": contents(&request),
                 "generationConfig": generation_config
             });
+            if let Some(ref level) = request.safety {
+                body["safetySettings"] = safety_settings(level);
+            }
+
+            let response = self.post(&request.model).await?.json(&body).send().await?;
 
-            let response = self
-                .client
-                .post(&url)
-                .header("x-goog-api-key", &self.api_key)
-                .json(&body)
-                .send()
-                .await?;
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
 
-            let status = response.status();
             let response_text = response.text().await?;
 
-            if !status.is_success() {
-                return Err(ImageError::Api { status: status.as_u16(), message: response_text });
+            parse_response(&response_text)
+        })
+    }
+}
+
+impl PromptEnhancer for GeminiGenerator {
+    fn enhance(&self, request: &EnhanceRequest) -> EnhanceFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let body = serde_json::json!({
+                "systemInstruction": { "parts": [{ "text": request.instructions }] },
+                "contents": [{ "role": "user", "parts": [{ "text": request.prompt }] }],
+            });
+            let response = self.post(&request.model).await?.json(&body).send().await?;
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
             }
+            parse_text_response(&response.text().await?)
+        })
+    }
+}
+
+/// Harm categories that `--safety` applies to.
+const HARM_CATEGORIES: [&str; 4] = [
+    "HARM_CATEGORY_HARASSMENT",
+    "HARM_CATEGORY_HATE_SPEECH",
+    "HARM_CATEGORY_SEXUALLY_EXPLICIT",
+    "HARM_CATEGORY_DANGEROUS_CONTENT",
+];
 
-            let parsed: GeminiResponse = serde_json::from_str(&response_text).map_err(|e| {
-                ImageError::Api { status: 200, message: format!("Failed to parse response: {e}") }
-            })?;
-
-            let mut images = Vec::new();
-            for candidate in parsed.candidates {
-                let parts = match candidate.content {
-                    Some(c) => c.parts,
-                    None => continue,
-                };
-                for part in parts {
-                    if let Some(inline) = part.inline_data {
-                        let data = base64::engine::general_purpose::STANDARD
-                            .decode(&inline.data)
-                            .map_err(|e| ImageError::Api {
-                            status: 200,
-                            message: format!("Failed to decode base64: {e}"),
-                        })?;
-                        images.push(GeneratedImage { data, mime_type: inline.mime_type });
-                    }
+/// `safetySettings` applying the `--safety` level's threshold to every harm category.
+fn safety_settings(level: &str) -> serde_json::Value {
+    let threshold = gemini_safety_threshold(level).unwrap_or("BLOCK_MEDIUM_AND_ABOVE");
+    HARM_CATEGORIES
+        .iter()
+        .map(|category| serde_json::json!({"category": category, "threshold": threshold}))
+        .collect()
+}
+
+/// Build the conversation: earlier session turns (user instruction, then the model's
+/// images with their thought signatures), followed by the current instruction and any
+/// reference images.
+fn contents(request: &ImageRequest) -> Vec<serde_json::Value> {
+    let mut contents = Vec::new();
+    for turn in &request.history {
+        contents.push(user_content(&turn.prompt, &turn.input_images));
+        let parts: Vec<serde_json::Value> = turn
+            .images
+            .iter()
+            .map(|img| {
+                let mut part = inline_part(&img.mime_type, &img.data);
+                if let Some(ref signature) = img.signature {
+                    part["thoughtSignature"] = serde_json::json!(signature);
                 }
-            }
+                part
+            })
+            .collect();
+        contents.push(serde_json::json!({"role": "model", "parts": parts}));
+    }
+    let mut current = user_content(&request.prompt, &request.input_images);
+    if !request.reference_images.is_empty() {
+        let parts = current["parts"].as_array_mut().expect("user content has parts");
+        parts.push(serde_json::json!({"text": REFERENCE_CAPTION}));
+        for img in &request.reference_images {
+            parts.push(inline_part(&img.mime_type, &img.data));
+        }
+    }
+    contents.push(current);
+    contents
+}
 
-            if images.is_empty() {
-                let truncated = if response_text.len() > 500 {
-                    format!("{}...", &response_text[..500])
-                } else {
-                    response_text.clone()
-                };
-                return Err(ImageError::Api {
-                    status: 200,
-                    message: format!("No images in response. Body: {truncated}"),
-                });
+/// A user turn: text prompt + inline image data in command-line order.
+/// Labelled images are preceded by a short caption so the prompt can refer to them.
+fn user_content(prompt: &str, input_images: &[InputImage]) -> serde_json::Value {
+    let mut parts = vec![serde_json::json!({"text": prompt})];
+    for (i, img) in input_images.iter().enumerate() {
+        if let Some(ref role) = img.role {
+            parts.push(serde_json::json!({"text": format!("Image {} ({role}):", i + 1)}));
+        }
+        parts.push(inline_part(&img.mime_type, &img.data));
+    }
+    serde_json::json!({"role": "user", "parts": parts})
+}
+
+fn inline_part(mime_type: &str, data: &[u8]) -> serde_json::Value {
+    let b64 = base64::engine::general_purpose::STANDARD.encode(data);
+    serde_json::json!({
+        "inlineData": {
+            "mimeType": mime_type,
+            "data": b64
+        }
+    })
+}
+
+/// Parse a `generateContent` response into generated images.
+///
+/// A prompt rejected outright (`promptFeedback.blockReason`) becomes
+/// [`ImageError::PromptBlocked`]; a response that simply has no image parts becomes
+/// [`ImageError::NoImages`], naming any safety categories that were flagged.
+fn parse_response(response_text: &str) -> Result<ImageResponse, ImageError> {
+    let parsed: GeminiResponse = serde_json::from_str(response_text).map_err(|e| {
+        ImageError::Api { status: 200, message: format!("Failed to parse response: {e}") }
+    })?;
+
+    check_prompt_feedback(parsed.prompt_feedback)?;
+
+    let usage = parsed.usage_metadata.map(Usage::from);
+    let finish_reason = parsed.candidates.iter().find_map(|c| c.finish_reason.clone());
+    let mut flagged = Vec::new();
+    let mut images = Vec::new();
+    let mut failures = Vec::new();
+    let mut index = 0;
+    for candidate in parsed.candidates {
+        flagged.extend(flagged_categories(&candidate.safety_ratings));
+        let parts = match candidate.content {
+            Some(c) => c.parts,
+            None => continue,
+        };
+        for part in parts {
+            if let Some(inline) = part.inline_data {
+                match base64::engine::general_purpose::STANDARD.decode(&inline.data) {
+                    Ok(data) => images.push(GeneratedImage {
+                        data,
+                        mime_type: inline.mime_type,
+                        signature: part.thought_signature,
+                        url: None,
+                    }),
+                    Err(e) => failures
+                        .push(ImageFailure { index, reason: format!("invalid base64: {e}") }),
+                }
+                index += 1;
             }
+        }
+    }
 
-            Ok(ImageResponse { images })
-        })
+    if images.is_empty() && failures.is_empty() {
+        let detail = if flagged.is_empty() {
+            let truncated = if response_text.len() > 500 {
+                format!("{}...", &response_text[..500])
+            } else {
+                response_text.to_string()
+            };
+            format!("Body: {truncated}")
+        } else {
+            format!("Flagged: {}", flagged.join(", "))
+        };
+        return Err(ImageError::NoImages { finish_reason, detail });
+    }
+
+    Ok(ImageResponse { usage, ..ImageResponse::partial(images, failures)? })
+}
+
+/// The text a text model answered with, for `--enhance`.
+fn parse_text_response(response_text: &str) -> Result<String, ImageError> {
+    let parsed: GeminiResponse = serde_json::from_str(response_text).map_err(|e| {
+        ImageError::Api { status: 200, message: format!("Failed to parse response: {e}") }
+    })?;
+    check_prompt_feedback(parsed.prompt_feedback)?;
+    let text: String = parsed
+        .candidates
+        .into_iter()
+        .next()
+        .and_then(|candidate| candidate.content)
+        .map(|content| content.parts.into_iter().filter_map(|part| part.text).collect())
+        .unwrap_or_default();
+    match text.trim() {
+        "" => Err(ImageError::Api { status: 200, message: "Response contained no text".into() }),
+        text => Ok(text.to_string()),
     }
 }
 
+/// Fail with [`ImageError::PromptBlocked`] when the prompt was refused outright.
+fn check_prompt_feedback(feedback: Option<PromptFeedback>) -> Result<(), ImageError> {
+    let Some(feedback) = feedback else { return Ok(()) };
+    let Some(reason) = feedback.block_reason else { return Ok(()) };
+    let reason = match feedback.block_reason_message {
+        Some(message) => format!("{reason}: {message}"),
+        None => reason,
+    };
+    Err(ImageError::PromptBlocked { reason, flagged: flagged_categories(&feedback.safety_ratings) })
+}
+
+/// Describe safety ratings that blocked content or rated it medium/high risk,
+/// e.g. `HARM_CATEGORY_DANGEROUS_CONTENT (HIGH)`.
+fn flagged_categories(ratings: &[SafetyRating]) -> Vec<String> {
+    ratings
+        .iter()
+        .filter(|r| r.blocked || matches!(r.probability.as_deref(), Some("MEDIUM" | "HIGH")))
+        .map(|r| match r.probability {
+            Some(ref probability) => format!("{} ({probability})", r.category),
+            None => r.category.clone(),
+        })
+        .collect()
+}
+
 // --- Gemini API response types ---
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiResponse {
+    #[serde(default)]
     candidates: Vec<GeminiCandidate>,
+    #[serde(default)]
+    prompt_feedback: Option<PromptFeedback>,
+    #[serde(default)]
+    usage_metadata: Option<UsageMetadata>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[allow(clippy::struct_field_names)] // Mirrors the API's field names.
+struct UsageMetadata {
+    #[serde(default)]
+    prompt_token_count: u64,
+    #[serde(default)]
+    candidates_token_count: u64,
+    /// Thinking tokens, billed as output.
+    #[serde(default)]
+    thoughts_token_count: u64,
+}
+
+impl From<UsageMetadata> for Usage {
+    fn from(usage: UsageMetadata) -> Self {
+        Self {
+            input_tokens: usage.prompt_token_count,
+            output_tokens: usage.candidates_token_count + usage.thoughts_token_count,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PromptFeedback {
+    #[serde(default)]
+    block_reason: Option<String>,
+    #[serde(default)]
+    block_reason_message: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
+}
+
+#[derive(Deserialize)]
+struct SafetyRating {
+    category: String,
+    #[serde(default)]
+    probability: Option<String>,
+    #[serde(default)]
+    blocked: bool,
 }
 
 #[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
 struct GeminiCandidate {
     #[serde(default)]
     content: Option<GeminiContent>,
+    #[serde(default)]
+    finish_reason: Option<String>,
+    #[serde(default)]
+    safety_ratings: Vec<SafetyRating>,
 }
 
 #[derive(Deserialize)]
@@ -141,9 +412,10 @@ struct GeminiContent {
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GeminiPart {
-    #[allow(dead_code)]
     text: Option<String>,
     inline_data: Option<GeminiInlineData>,
+    #[serde(default)]
+    thought_signature: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -152,3 +424,139 @@ struct GeminiInlineData {
     mime_type: String,
     data: String,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::image_generator::ConversationTurn;
+
+    #[test]
+    fn vertex_regional_url() {
+        let target = VertexTarget { project: "my-project".into(), location: "us-central1".into() };
+        assert_eq!(
+            vertex_url(&target, "gemini-3-pro-image-preview"),
+            "https://us-central1-aiplatform.googleapis.com/v1/projects/my-project/locations/\
+             us-central1/publishers/google/models/gemini-3-pro-image-preview:generateContent"
+        );
+    }
+
+    #[test]
+    fn prompt_block_reason_is_surfaced() {
+        let body = r#"{"promptFeedback": {"blockReason": "SAFETY", "safetyRatings": [
+            {"category": "HARM_CATEGORY_DANGEROUS_CONTENT", "probability": "HIGH", "blocked": true},
+            {"category": "HARM_CATEGORY_HARASSMENT", "probability": "NEGLIGIBLE"}
+        ]}}"#;
+        let err = parse_response(body).unwrap_err();
+        assert!(!err.is_retryable());
+        assert_eq!(
+            err.to_string(),
+            "Prompt blocked (reason: SAFETY). Flagged: HARM_CATEGORY_DANGEROUS_CONTENT (HIGH)"
+        );
+    }
+
+    #[test]
+    fn candidate_safety_ratings_replace_raw_body() {
+        let body = r#"{"candidates": [{"finishReason": "IMAGE_SAFETY", "safetyRatings": [
+            {"category": "HARM_CATEGORY_SEXUALLY_EXPLICIT", "probability": "MEDIUM"}
+        ]}]}"#;
+        let err = parse_response(body).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No images in response (finish reason: IMAGE_SAFETY). \
+             Flagged: HARM_CATEGORY_SEXUALLY_EXPLICIT (MEDIUM)"
+        );
+    }
+
+    #[test]
+    fn image_parts_are_decoded() {
+        let body = r#"{"candidates": [{"content": {"parts": [
+            {"text": "here you go"}, {"inlineData": {"mimeType": "image/png", "data": "iVBORw=="}}
+        ]}}]}"#;
+        let images = parse_response(body).unwrap().images;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].mime_type, "image/png");
+    }
+
+    #[test]
+    fn enhanced_prompt_is_the_response_text() {
+        let body = r#"{"candidates": [{"content": {"parts": [
+            {"text": "A tabby cat asleep "}, {"text": "on a sunlit windowsill\n"}
+        ]}}]}"#;
+        assert_eq!(parse_text_response(body).unwrap(), "A tabby cat asleep on a sunlit windowsill");
+        let empty = r#"{"candidates": [{"content": {"parts": []}}]}"#;
+        assert!(matches!(parse_text_response(empty), Err(ImageError::Api { .. })));
+        let blocked = r#"{"promptFeedback": {"blockReason": "SAFETY"}}"#;
+        assert!(matches!(parse_text_response(blocked), Err(ImageError::PromptBlocked { .. })));
+    }
+
+    #[test]
+    fn usage_counts_thinking_as_output() {
+        let body = r#"{"candidates": [{"content": {"parts": [
+            {"inlineData": {"mimeType": "image/png", "data": "iVBORw=="}}
+        ]}}], "usageMetadata": {"promptTokenCount": 9, "candidatesTokenCount": 1290,
+            "thoughtsTokenCount": 200, "totalTokenCount": 1499}}"#;
+        let usage = parse_response(body).unwrap().usage;
+        assert_eq!(usage, Some(Usage { input_tokens: 9, output_tokens: 1490 }));
+    }
+
+    #[test]
+    fn safety_level_applies_to_every_category() {
+        let settings = safety_settings("few");
+        let settings = settings.as_array().unwrap();
+        assert_eq!(settings.len(), HARM_CATEGORIES.len());
+        assert!(settings.iter().all(|s| s["threshold"] == "BLOCK_ONLY_HIGH"));
+        assert_eq!(settings[0]["category"], "HARM_CATEGORY_HARASSMENT");
+    }
+
+    #[test]
+    fn history_precedes_the_current_turn() {
+        let body = r#"{"candidates": [{"content": {"parts": [
+            {"inlineData": {"mimeType": "image/png", "data": "iVBORw=="}, "thoughtSignature": "sig"}
+        ]}}]}"#;
+        let images = parse_response(body).unwrap().images;
+        assert_eq!(images[0].signature.as_deref(), Some("sig"));
+
+        let request = ImageRequest {
+            format: "png".into(),
+            history: vec![ConversationTurn {
+                prompt: "a red square".into(),
+                input_images: vec![],
+                images,
+            }],
+            ..ImageRequest::sample("gemini-3.1-flash-image-preview", "now make it blue")
+        };
+        let contents = contents(&request);
+        let roles: Vec<&str> = contents.iter().map(|c| c["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, ["user", "model", "user"]);
+        assert_eq!(contents[1]["parts"][0]["thoughtSignature"], "sig");
+        assert_eq!(contents[2]["parts"][0]["text"], "now make it blue");
+    }
+
+    #[test]
+    fn reference_images_follow_inputs() {
+        let image = |name: &str| InputImage {
+            data: vec![1],
+            mime_type: "image/png".into(),
+            filename: name.into(),
+            role: None,
+        };
+        let request = ImageRequest {
+            format: "png".into(),
+            input_images: vec![image("photo.png")],
+            reference_images: vec![image("style.png"), image("layout.png")],
+            ..ImageRequest::sample("gemini-3.1-flash-image-preview", "a lighthouse")
+        };
+        let contents = contents(&request);
+        assert_eq!(contents.len(), 1);
+        let parts = contents[0]["parts"].as_array().unwrap();
+        assert_eq!(parts.len(), 5);
+        assert_eq!(parts[2]["text"], REFERENCE_CAPTION);
+        assert!(parts[3].get("inlineData").is_some() && parts[4].get("inlineData").is_some());
+    }
+
+    #[test]
+    fn vertex_global_url() {
+        let target = VertexTarget { project: "p".into(), location: "global".into() };
+        assert!(vertex_url(&target, "m").starts_with("https://aiplatform.googleapis.com/"));
+    }
+}
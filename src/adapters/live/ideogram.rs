@@ -0,0 +1,245 @@
+//! Live adapter for the Ideogram 3.0 API.
+//!
+//! Ideogram is strong at rendering legible text inside images. Requests are multipart
+//! forms; responses carry short-lived image URLs, which are downloaded before returning
+//! unless the request asks for URL delivery.
+
+use reqwest::multipart;
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::http::{api_error, build_client, HttpSettings};
+use crate::error::ImageError;
+use crate::ports::image_generator::{
+    GenerateFuture, GeneratedImage, ImageFailure, ImageGenerator, ImageRequest, ImageResponse,
+};
+
+const IDEOGRAM_API_BASE: &str = "https://api.ideogram.ai/v1/ideogram-v3";
+
+/// Most images Ideogram returns from one call.
+const MAX_IMAGES_PER_CALL: u32 = 8;
+
+/// How strongly a remix follows the input image (1-100).
+const REMIX_IMAGE_WEIGHT: &str = "50";
+
+/// Live Ideogram generator.
+pub struct IdeogramGenerator {
+    client: Client,
+    api_key: String,
+}
+
+impl IdeogramGenerator {
+    /// Create a new Ideogram generator with the given API key and HTTP settings.
+    #[must_use]
+    pub fn new(api_key: String, http: &HttpSettings) -> Self {
+        Self { client: build_client(http), api_key }
+    }
+
+    /// Download a single output image.
+    async fn download(&self, url: &str) -> Result<GeneratedImage, ImageError> {
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        let mime_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| v.starts_with("image/"))
+            .map_or_else(|| "image/png".to_string(), ToString::to_string);
+        let data = response.bytes().await?.to_vec();
+        Ok(GeneratedImage { data, mime_type, signature: None, url: None })
+    }
+}
+
+/// Map `--quality` to Ideogram's rendering speed.
+fn rendering_speed(quality: &str) -> &'static str {
+    match quality {
+        "low" => "TURBO",
+        "high" => "QUALITY",
+        _ => "DEFAULT",
+    }
+}
+
+/// The text fields of a generate or remix request, in form order.
+fn form_fields(request: &ImageRequest) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("prompt", request.prompt.clone()),
+        // Ideogram writes ratios as `16x9`.
+        ("aspect_ratio", request.aspect_ratio.replace(':', "x")),
+        ("rendering_speed", rendering_speed(&request.quality).to_string()),
+        ("num_images", request.count.to_string()),
+    ];
+    if let Some(ref magic_prompt) = request.magic_prompt {
+        fields.push(("magic_prompt", magic_prompt.to_uppercase()));
+    }
+    if let Some(ref style_type) = request.style_type {
+        fields.push(("style_type", style_type.to_uppercase()));
+    }
+    if let Some(seed) = request.seed {
+        fields.push(("seed", seed.to_string()));
+    }
+    fields
+}
+
+/// Parse an Ideogram response into image URLs, one per image in response order.
+///
+/// Images that fail Ideogram's safety check are returned without a URL.
+fn parse_response(response_text: &str) -> Result<Vec<Option<String>>, ImageError> {
+    let parsed: IdeogramResponse = serde_json::from_str(response_text).map_err(|e| {
+        ImageError::Api { status: 200, message: format!("Failed to parse response: {e}") }
+    })?;
+    let unsafe_count = parsed.data.iter().filter(|item| item.is_image_safe == Some(false)).count();
+    let urls: Vec<Option<String>> = parsed.data.into_iter().map(|item| item.url).collect();
+    if urls.iter().all(Option::is_none) {
+        return Err(ImageError::NoImages {
+            finish_reason: (unsafe_count > 0).then(|| "UNSAFE_IMAGE".to_string()),
+            detail: format!(
+                "Ideogram withheld {unsafe_count} image(s) that failed its safety check"
+            ),
+        });
+    }
+    Ok(urls)
+}
+
+impl ImageGenerator for IdeogramGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            if request.count > MAX_IMAGES_PER_CALL {
+                return Err(ImageError::InvalidArgument(format!(
+                    "Ideogram returns at most {MAX_IMAGES_PER_CALL} images per request"
+                )));
+            }
+            if request.input_images.len() > 1 {
+                return Err(ImageError::InvalidArgument(
+                    "Ideogram accepts at most one input image".to_string(),
+                ));
+            }
+
+            let mut form = multipart::Form::new();
+            for (name, value) in form_fields(&request) {
+                form = form.text(name, value);
+            }
+            // An input image turns the request into a remix of that image.
+            let operation = if let Some(img) = request.input_images.first() {
+                let part = multipart::Part::bytes(img.data.clone())
+                    .file_name(img.filename.clone())
+                    .mime_str(&img.mime_type)
+                    .map_err(|e| ImageError::Api {
+                        status: 0,
+                        message: format!("Failed to build multipart: {e}"),
+                    })?;
+                form = form.part("image", part).text("image_weight", REMIX_IMAGE_WEIGHT);
+                "remix"
+            } else {
+                "generate"
+            };
+
+            let response = self
+                .client
+                .post(format!("{IDEOGRAM_API_BASE}/{operation}"))
+                .header("Api-Key", &self.api_key)
+                .multipart(form)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+
+            let text = response.text().await?;
+
+            let mut images = Vec::new();
+            let mut failures = Vec::new();
+            for (index, url) in parse_response(&text)?.into_iter().enumerate() {
+                let Some(url) = url else {
+                    let reason = "withheld by Ideogram's safety check".to_string();
+                    failures.push(ImageFailure { index, reason });
+                    continue;
+                };
+                if request.return_urls {
+                    images.push(GeneratedImage {
+                        data: Vec::new(),
+                        mime_type: "image/png".into(),
+                        signature: None,
+                        url: Some(url),
+                    });
+                } else {
+                    match self.download(&url).await {
+                        Ok(image) => images.push(image),
+                        Err(e) => failures.push(ImageFailure { index, reason: e.to_string() }),
+                    }
+                }
+            }
+            ImageResponse::partial(images, failures)
+        })
+    }
+}
+
+// --- Ideogram API response types ---
+
+#[derive(Deserialize)]
+struct IdeogramResponse {
+    #[serde(default)]
+    data: Vec<IdeogramImage>,
+}
+
+#[derive(Deserialize)]
+struct IdeogramImage {
+    #[serde(default)]
+    url: Option<String>,
+    #[serde(default)]
+    is_image_safe: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> ImageRequest {
+        ImageRequest {
+            aspect_ratio: "16:9".into(),
+            quality: "high".into(),
+            format: "png".into(),
+            count: 2,
+            magic_prompt: Some("off".into()),
+            style_type: Some("design".into()),
+            ..ImageRequest::sample("ideogram-v3", "a poster that says OPEN LATE")
+        }
+    }
+
+    #[test]
+    fn form_fields_map_options() {
+        let fields = form_fields(&request());
+        let get = |name: &str| fields.iter().find(|(n, _)| *n == name).map(|(_, v)| v.as_str());
+        assert_eq!(get("aspect_ratio"), Some("16x9"));
+        assert_eq!(get("rendering_speed"), Some("QUALITY"));
+        assert_eq!(get("num_images"), Some("2"));
+        assert_eq!(get("magic_prompt"), Some("OFF"));
+        assert_eq!(get("style_type"), Some("DESIGN"));
+    }
+
+    #[test]
+    fn options_omitted_when_unset() {
+        let mut req = request();
+        req.magic_prompt = None;
+        req.style_type = None;
+        let fields = form_fields(&req);
+        assert!(fields.iter().all(|(n, _)| *n != "magic_prompt" && *n != "style_type"));
+    }
+
+    #[test]
+    fn parse_urls_and_unsafe_images() {
+        let body = r#"{"created": "2025-01-01T00:00:00Z", "data": [
+            {"url": "https://ideogram.ai/api/images/ephemeral/a.png", "is_image_safe": true},
+            {"is_image_safe": false}
+        ]}"#;
+        let urls = parse_response(body).unwrap();
+        assert!(urls[0].is_some());
+        assert_eq!(urls[1], None);
+
+        let err = parse_response(r#"{"data": [{"is_image_safe": false}]}"#).unwrap_err();
+        assert!(err.to_string().contains("UNSAFE_IMAGE"), "got: {err}");
+    }
+}
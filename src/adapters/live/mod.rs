@@ -1,4 +1,13 @@
 //! Live adapters that make real API calls.
 
+pub mod aws;
+pub mod bedrock;
 pub mod gemini;
+pub mod google_auth;
+pub mod http;
+pub mod ideogram;
 pub mod openai;
+pub mod replicate;
+pub mod sd_webui;
+pub mod stability;
+pub mod together;
@@ -1,31 +1,139 @@
 //! Live adapter for the `OpenAI` image generation API.
+//!
+//! `--enhance` with a `gpt` text model goes through the Chat Completions API instead.
 
 use base64::Engine;
-use reqwest::Client;
 use reqwest::multipart;
+use reqwest::{Client, RequestBuilder};
 use serde::Deserialize;
 
+use super::http::{api_error, build_client, HttpSettings};
 use crate::error::ImageError;
 use crate::params::aspect_ratio_to_openai_size;
+use crate::ports::image_editor::ImageEditor;
 use crate::ports::image_generator::{
-    GenerateFuture, GeneratedImage, ImageGenerator, ImageRequest, ImageResponse,
+    GenerateFuture, GeneratedImage, ImageFailure, ImageGenerator, ImageRequest, ImageResponse,
+    PartialImage, PartialSink, Usage,
 };
+use crate::ports::prompt_enhancer::{EnhanceFuture, EnhanceRequest, PromptEnhancer};
 
-const OPENAI_API_URL: &str = "https://api.openai.com/v1/images/generations";
-const OPENAI_EDITS_API_URL: &str = "https://api.openai.com/v1/images/edits";
+/// Default Images API base; `--api-base` points at an `OpenAI`-compatible gateway instead.
+const OPENAI_API_BASE: &str = "https://api.openai.com/v1";
 
-/// Live `OpenAI` image generator that calls the `OpenAI` Images API.
+/// Previews requested per image by `--stream` (the API allows 0-3).
+const PARTIAL_IMAGES: u32 = 2;
+
+/// Model-name prefix that routes a request to an Azure deployment (`azure/<deployment>`).
+const AZURE_MODEL_PREFIX: &str = "azure/";
+
+/// An Azure `OpenAI` resource serving `gpt-image` deployments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AzureDeployment {
+    /// Resource endpoint (e.g., `https://my-resource.openai.azure.com`).
+    pub endpoint: String,
+    /// Deployment used when the model is plain `azure` rather than `azure/<deployment>`.
+    pub deployment: Option<String>,
+    /// Value of the `api-version` query parameter.
+    pub api_version: String,
+}
+
+/// Where requests are sent and how they authenticate.
+enum Endpoint {
+    /// `api.openai.com` (or a compatible gateway at `base_url`) with bearer-token auth.
+    OpenAi { base_url: String },
+    /// An Azure `OpenAI` deployment with `api-key` header auth.
+    Azure(AzureDeployment),
+}
+
+/// Live `OpenAI` image generator that calls the `OpenAI` Images API (directly or via Azure).
 pub struct OpenAiGenerator {
     client: Client,
     api_key: String,
+    endpoint: Endpoint,
 }
 
 impl OpenAiGenerator {
-    /// Create a new `OpenAI` generator with the given API key.
+    /// Create a new `OpenAI` generator with the given API key and HTTP settings.
+    ///
+    /// `base_url` replaces `https://api.openai.com/v1` for `OpenAI`-compatible gateways.
     #[must_use]
-    pub fn new(api_key: String) -> Self {
-        Self { client: Client::new(), api_key }
+    pub fn new(api_key: String, base_url: Option<&str>, http: &HttpSettings) -> Self {
+        let base_url = base_url.unwrap_or(OPENAI_API_BASE).trim_end_matches('/').to_string();
+        Self { client: build_client(http), api_key, endpoint: Endpoint::OpenAi { base_url } }
+    }
+
+    /// Create a generator that calls an Azure `OpenAI` deployment.
+    #[must_use]
+    pub fn azure(api_key: String, azure: AzureDeployment, http: &HttpSettings) -> Self {
+        Self { client: build_client(http), api_key, endpoint: Endpoint::Azure(azure) }
+    }
+
+    /// URL for an Images API operation (`"generations"` or `"edits"`).
+    fn url(&self, operation: &str, model: &str) -> Result<String, ImageError> {
+        match self.endpoint {
+            Endpoint::OpenAi { ref base_url } => Ok(format!("{base_url}/images/{operation}")),
+            Endpoint::Azure(ref azure) => azure_url(azure, operation, model),
+        }
+    }
+
+    /// Start a POST request with the endpoint's auth header.
+    fn post(&self, url: &str) -> RequestBuilder {
+        let builder = self.client.post(url);
+        match self.endpoint {
+            Endpoint::OpenAi { .. } => {
+                builder.header("Authorization", format!("Bearer {}", self.api_key))
+            }
+            Endpoint::Azure(_) => builder.header("api-key", &self.api_key),
+        }
+    }
+
+    /// Whether to send the `model` field; Azure selects the model by deployment instead.
+    fn sends_model(&self) -> bool {
+        matches!(self.endpoint, Endpoint::OpenAi { .. })
     }
+
+    /// JSON body for a text-to-image request to `/generations`.
+    fn generations_body(&self, request: &ImageRequest) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "prompt": request.prompt,
+            "n": request.count,
+            "size": openai_size(request),
+            "quality": request.quality,
+            "output_format": request.format,
+        });
+        if self.sends_model() {
+            body["model"] = serde_json::Value::String(request.model.clone());
+        }
+        if let Some(ref bg) = request.background {
+            body["background"] = serde_json::Value::String(bg.clone());
+        }
+        if let Some(ref moderation) = request.moderation {
+            body["moderation"] = serde_json::Value::String(moderation.clone());
+        }
+        if let Some(compression) = request.output_compression {
+            body["output_compression"] = compression.into();
+        }
+        body
+    }
+}
+
+/// Build an Azure Images API URL, taking the deployment from `azure/<deployment>` or config.
+fn azure_url(azure: &AzureDeployment, operation: &str, model: &str) -> Result<String, ImageError> {
+    let deployment = model
+        .strip_prefix(AZURE_MODEL_PREFIX)
+        .filter(|d| !d.is_empty())
+        .or(azure.deployment.as_deref())
+        .ok_or_else(|| {
+            ImageError::Config(
+                "No Azure deployment. Use --model azure/<deployment> or set [azure] deployment."
+                    .into(),
+            )
+        })?;
+    Ok(format!(
+        "{}/openai/deployments/{deployment}/images/{operation}?api-version={}",
+        azure.endpoint.trim_end_matches('/'),
+        azure.api_version
+    ))
 }
 
 /// Parse an `OpenAI` image response body into `ImageResponse`.
@@ -36,124 +144,465 @@ fn parse_response(response_text: &str, format: &str) -> Result<ImageResponse, Im
 
     let mime_type = format!("image/{format}");
     let mut images = Vec::new();
-    for item in parsed.data {
-        let data = base64::engine::general_purpose::STANDARD
-            .decode(&item.b64_json)
-            .map_err(|e| ImageError::Api {
-                status: 200,
-                message: format!("Failed to decode base64: {e}"),
-            })?;
-        images.push(GeneratedImage { data, mime_type: mime_type.clone() });
+    let mut failures = Vec::new();
+    for (index, item) in parsed.data.into_iter().enumerate() {
+        match base64::engine::general_purpose::STANDARD.decode(&item.b64_json) {
+            Ok(data) => images.push(GeneratedImage {
+                data,
+                mime_type: mime_type.clone(),
+                signature: None,
+                url: None,
+            }),
+            Err(e) => failures.push(ImageFailure { index, reason: format!("invalid base64: {e}") }),
+        }
     }
 
-    if images.is_empty() {
+    if images.is_empty() && failures.is_empty() {
         let truncated = if response_text.len() > 500 {
             format!("{}...", &response_text[..500])
         } else {
             response_text.to_string()
         };
-        return Err(ImageError::Api {
-            status: 200,
-            message: format!("No images in response. Body: {truncated}"),
+        return Err(ImageError::NoImages {
+            finish_reason: None,
+            detail: format!("Body: {truncated}"),
         });
     }
 
-    Ok(ImageResponse { images })
+    let usage = parsed.usage.map(Usage::from);
+    Ok(ImageResponse { usage, ..ImageResponse::partial(images, failures)? })
 }
 
 impl ImageGenerator for OpenAiGenerator {
     fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        if !request.input_images.is_empty() {
+            return self.edit(request);
+        }
         let request = request.clone();
         Box::pin(async move {
-            // OpenAI only supports 1K-range sizes (1024px); for 2K/4K use "auto".
-            let size = if request.size == "1K" {
-                aspect_ratio_to_openai_size(&request.aspect_ratio)
-            } else {
-                "auto"
-            };
+            // --- Text-to-image: JSON POST to /generations ---
+            let body = self.generations_body(&request);
+            let url = self.url("generations", &request.model)?;
+            let response = self.post(&url).json(&body).send().await?;
 
-            let response_text = if request.input_images.is_empty() {
-                // --- Text-to-image: JSON POST to /generations ---
-                let mut body = serde_json::json!({
-                    "model": request.model,
-                    "prompt": request.prompt,
-                    "n": request.count,
-                    "size": size,
-                    "quality": request.quality,
-                    "output_format": request.format,
-                });
-                if let Some(ref bg) = request.background {
-                    body["background"] = serde_json::Value::String(bg.clone());
-                }
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
 
-                let response = self
-                    .client
-                    .post(OPENAI_API_URL)
-                    .header("Authorization", format!("Bearer {}", self.api_key))
-                    .json(&body)
-                    .send()
-                    .await?;
-
-                let status = response.status();
-                let text = response.text().await?;
-                if !status.is_success() {
-                    return Err(ImageError::Api { status: status.as_u16(), message: text });
-                }
-                text
-            } else {
-                // --- Image editing: multipart POST to /edits ---
-                let mut form = multipart::Form::new()
-                    .text("model", request.model.clone())
-                    .text("prompt", request.prompt.clone())
-                    .text("n", request.count.to_string())
-                    .text("size", size.to_string())
-                    .text("quality", request.quality.clone())
-                    .text("output_format", request.format.clone());
-
-                if let Some(ref bg) = request.background {
-                    form = form.text("background", bg.clone());
-                }
+            let text = response.text().await?;
+            parse_response(&text, &request.format)
+        })
+    }
 
-                for img in &request.input_images {
-                    let part = multipart::Part::bytes(img.data.clone())
-                        .file_name(img.filename.clone())
-                        .mime_str(&img.mime_type)
-                        .map_err(|e| ImageError::Api {
-                            status: 0,
-                            message: format!("Failed to build multipart: {e}"),
-                        })?;
-                    form = form.part("image[]", part);
-                }
+    fn generate_streaming(
+        &self,
+        request: &ImageRequest,
+        partials: PartialSink,
+    ) -> GenerateFuture<'_> {
+        if !request.input_images.is_empty() {
+            return self.edit(request);
+        }
+        let request = request.clone();
+        Box::pin(async move {
+            // --- Text-to-image with previews: server-sent events from /generations ---
+            let mut body = self.generations_body(&request);
+            body["stream"] = serde_json::Value::Bool(true);
+            body["partial_images"] = serde_json::json!(PARTIAL_IMAGES);
+            let url = self.url("generations", &request.model)?;
+            let mut response = self.post(&url).json(&body).send().await?;
+
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
 
-                let response = self
-                    .client
-                    .post(OPENAI_EDITS_API_URL)
-                    .header("Authorization", format!("Bearer {}", self.api_key))
-                    .multipart(form)
-                    .send()
-                    .await?;
-
-                let status = response.status();
-                let text = response.text().await?;
-                if !status.is_success() {
-                    return Err(ImageError::Api { status: status.as_u16(), message: text });
+            let mime_type = format!("image/{}", request.format);
+            let mut events = SseBuffer::default();
+            let mut completed = Vec::new();
+            while let Some(chunk) = response.chunk().await? {
+                for event in events.push(&chunk) {
+                    match parse_stream_event(&event)? {
+                        StreamEvent::Partial { frame, b64_json } => {
+                            // A preview that fails to decode is simply skipped.
+                            let engine = base64::engine::general_purpose::STANDARD;
+                            let Ok(data) = engine.decode(b64_json) else { continue };
+                            let mime_type = mime_type.clone();
+                            let _ = partials.send(PartialImage { frame, data, mime_type });
+                        }
+                        StreamEvent::Completed { b64_json, usage } => {
+                            completed.push((b64_json, usage));
+                        }
+                        StreamEvent::Other => {}
+                    }
                 }
-                text
-            };
+            }
+            completed_response(completed, &mime_type)
+        })
+    }
+}
+
+impl ImageEditor for OpenAiGenerator {
+    fn edit(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            // --- Image editing: multipart POST to /edits ---
+            let mut form = multipart::Form::new()
+                .text("prompt", request.prompt.clone())
+                .text("n", request.count.to_string())
+                .text("size", openai_size(&request).to_string())
+                .text("quality", request.quality.clone())
+                .text("output_format", request.format.clone());
+
+            if self.sends_model() {
+                form = form.text("model", request.model.clone());
+            }
+            if let Some(ref bg) = request.background {
+                form = form.text("background", bg.clone());
+            }
+            if let Some(ref moderation) = request.moderation {
+                form = form.text("moderation", moderation.clone());
+            }
+            if let Some(compression) = request.output_compression {
+                form = form.text("output_compression", compression.to_string());
+            }
 
-            parse_response(&response_text, &request.format)
+            for img in &request.input_images {
+                let part = multipart::Part::bytes(img.data.clone())
+                    .file_name(img.filename.clone())
+                    .mime_str(&img.mime_type)
+                    .map_err(|e| ImageError::Api {
+                        status: 0,
+                        message: format!("Failed to build multipart: {e}"),
+                    })?;
+                form = form.part("image[]", part);
+            }
+
+            let url = self.url("edits", &request.model)?;
+            let response = self.post(&url).multipart(form).send().await?;
+
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+
+            let text = response.text().await?;
+            parse_response(&text, &request.format)
         })
     }
 }
 
+impl PromptEnhancer for OpenAiGenerator {
+    fn enhance(&self, request: &EnhanceRequest) -> EnhanceFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let Endpoint::OpenAi { ref base_url } = self.endpoint else {
+                return Err(ImageError::Config(
+                    "--enhance cannot use an Azure deployment; set [enhance] model to a gpt or \
+                     gemini model"
+                        .into(),
+                ));
+            };
+            let body = serde_json::json!({
+                "model": request.model,
+                "messages": [
+                    { "role": "system", "content": request.instructions },
+                    { "role": "user", "content": request.prompt },
+                ],
+            });
+            let url = format!("{base_url}/chat/completions");
+            let response = self.post(&url).json(&body).send().await?;
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+            parse_chat_response(&response.text().await?)
+        })
+    }
+}
+
+/// The reply of a Chat Completions response, for `--enhance`.
+fn parse_chat_response(response_text: &str) -> Result<String, ImageError> {
+    let parsed: ChatResponse = serde_json::from_str(response_text).map_err(|e| {
+        ImageError::Api { status: 200, message: format!("Failed to parse response: {e}") }
+    })?;
+    let content = parsed.choices.into_iter().next().and_then(|choice| choice.message.content);
+    match content.as_deref().map(str::trim) {
+        None | Some("") => {
+            Err(ImageError::Api { status: 200, message: "Response contained no text".into() })
+        }
+        Some(text) => Ok(text.to_string()),
+    }
+}
+
+/// `OpenAI` only supports 1K-range sizes (1024px); for 2K/4K use `"auto"`.
+fn openai_size(request: &ImageRequest) -> &'static str {
+    if request.size == "1K" {
+        aspect_ratio_to_openai_size(&request.aspect_ratio)
+    } else {
+        "auto"
+    }
+}
+
+/// Splits a server-sent event stream into events as chunks arrive.
+#[derive(Default)]
+struct SseBuffer {
+    pending: Vec<u8>,
+    /// Bytes of `pending` already searched for a terminator. Events carrying a whole
+    /// image run to megabytes, so each chunk only searches what is new.
+    scanned: usize,
+}
+
+impl SseBuffer {
+    /// Append a chunk and return every event it completes, without the blank-line
+    /// terminator.
+    fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        self.pending.extend(chunk.iter().filter(|&&b| b != b'\r'));
+        let mut events = Vec::new();
+        loop {
+            let start = self.scanned.saturating_sub(1);
+            let Some(offset) = self.pending[start..].windows(2).position(|w| w == b"\n\n") else {
+                self.scanned = self.pending.len();
+                return events;
+            };
+            let end = start + offset;
+            let event: Vec<u8> = self.pending.drain(..end + 2).collect();
+            events.push(String::from_utf8_lossy(&event[..end]).into_owned());
+            self.scanned = 0;
+        }
+    }
+}
+
+/// One event of a streamed generation.
+#[derive(Debug, PartialEq)]
+enum StreamEvent {
+    /// A preview of the image so far.
+    Partial { frame: u32, b64_json: String },
+    /// The finished image, and the tokens it was billed for.
+    Completed { b64_json: String, usage: Option<Usage> },
+    /// Anything else (keep-alives).
+    Other,
+}
+
+/// Parse the `data:` payload of one server-sent event.
+///
+/// An `error` event becomes [`ImageError::Api`].
+fn parse_stream_event(event: &str) -> Result<StreamEvent, ImageError> {
+    let data: String =
+        event.lines().filter_map(|line| line.strip_prefix("data:")).map(str::trim_start).collect();
+    if data.is_empty() || data == "[DONE]" {
+        return Ok(StreamEvent::Other);
+    }
+    let parsed: OpenAiStreamEvent = serde_json::from_str(&data).map_err(|e| ImageError::Api {
+        status: 200,
+        message: format!("Failed to parse stream event: {e}"),
+    })?;
+    Ok(match (parsed.kind.as_str(), parsed.b64_json) {
+        (kind, Some(b64_json)) if kind.ends_with(".partial_image") => {
+            StreamEvent::Partial { frame: parsed.partial_image_index.unwrap_or(0), b64_json }
+        }
+        (kind, Some(b64_json)) if kind.ends_with(".completed") => {
+            StreamEvent::Completed { b64_json, usage: parsed.usage.map(Usage::from) }
+        }
+        ("error", _) => {
+            let message = parsed.error.map_or_else(|| data.clone(), |e| e.message);
+            return Err(ImageError::Api { status: 200, message });
+        }
+        _ => StreamEvent::Other,
+    })
+}
+
+/// Build the final response from the images and usage of every `completed` event.
+fn completed_response(
+    completed: Vec<(String, Option<Usage>)>,
+    mime_type: &str,
+) -> Result<ImageResponse, ImageError> {
+    if completed.is_empty() {
+        return Err(ImageError::NoImages {
+            finish_reason: None,
+            detail: "The stream ended without a completed image".into(),
+        });
+    }
+    let mut images = Vec::new();
+    let mut failures = Vec::new();
+    let mut usage: Option<Usage> = None;
+    for (index, (b64_json, image_usage)) in completed.into_iter().enumerate() {
+        if let Some(image_usage) = image_usage {
+            *usage.get_or_insert_default() += image_usage;
+        }
+        match base64::engine::general_purpose::STANDARD.decode(b64_json) {
+            Ok(data) => images.push(GeneratedImage {
+                data,
+                mime_type: mime_type.to_string(),
+                signature: None,
+                url: None,
+            }),
+            Err(e) => failures.push(ImageFailure { index, reason: format!("invalid base64: {e}") }),
+        }
+    }
+    Ok(ImageResponse { usage, ..ImageResponse::partial(images, failures)? })
+}
+
 // --- OpenAI API response types ---
 
 #[derive(Deserialize)]
 struct OpenAiResponse {
     data: Vec<OpenAiImageData>,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+/// Token usage of a `gpt-image` response; `dall-e` models and compatible gateways may
+/// leave it out.
+#[derive(Deserialize)]
+struct OpenAiUsage {
+    input_tokens: u64,
+    output_tokens: u64,
+}
+
+impl From<OpenAiUsage> for Usage {
+    fn from(usage: OpenAiUsage) -> Self {
+        Self { input_tokens: usage.input_tokens, output_tokens: usage.output_tokens }
+    }
 }
 
 #[derive(Deserialize)]
 struct OpenAiImageData {
     b64_json: String,
 }
+
+#[derive(Deserialize)]
+struct OpenAiStreamEvent {
+    #[serde(rename = "type")]
+    kind: String,
+    b64_json: Option<String>,
+    partial_image_index: Option<u32>,
+    error: Option<OpenAiStreamError>,
+    #[serde(default)]
+    usage: Option<OpenAiUsage>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiStreamError {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    #[serde(default)]
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatMessage,
+}
+
+#[derive(Deserialize)]
+struct ChatMessage {
+    content: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn azure(deployment: Option<&str>) -> AzureDeployment {
+        AzureDeployment {
+            endpoint: "https://my-resource.openai.azure.com/".into(),
+            deployment: deployment.map(Into::into),
+            api_version: "2025-04-01-preview".into(),
+        }
+    }
+
+    #[test]
+    fn default_and_custom_base_urls() {
+        let http = HttpSettings::default();
+        let openai = OpenAiGenerator::new("sk-test".into(), None, &http);
+        assert_eq!(
+            openai.url("generations", "gpt-image-1").unwrap(),
+            "https://api.openai.com/v1/images/generations"
+        );
+        let gateway =
+            OpenAiGenerator::new("sk-test".into(), Some("http://localhost:4000/v1/"), &http);
+        assert_eq!(
+            gateway.url("edits", "gpt-image-1").unwrap(),
+            "http://localhost:4000/v1/images/edits"
+        );
+        assert!(gateway.sends_model());
+    }
+
+    #[test]
+    fn azure_url_uses_deployment_from_model() {
+        let url =
+            azure_url(&azure(Some("fallback")), "generations", "azure/gpt-image-prod").unwrap();
+        assert_eq!(
+            url,
+            "https://my-resource.openai.azure.com/openai/deployments/gpt-image-prod/images/\
+             generations?api-version=2025-04-01-preview"
+        );
+    }
+
+    #[test]
+    fn azure_url_falls_back_to_configured_deployment() {
+        let url = azure_url(&azure(Some("fallback")), "edits", "azure").unwrap();
+        assert!(url.contains("/deployments/fallback/images/edits?"), "got: {url}");
+        assert!(azure_url(&azure(None), "edits", "azure").is_err());
+    }
+
+    #[test]
+    fn enhanced_prompt_is_the_reply() {
+        let body = r#"{"choices": [{"message": {"role": "assistant",
+            "content": " A tabby cat asleep on a sunlit windowsill\n"}}]}"#;
+        assert_eq!(parse_chat_response(body).unwrap(), "A tabby cat asleep on a sunlit windowsill");
+        let refused = r#"{"choices": [{"message": {"content": null, "refusal": "No."}}]}"#;
+        assert!(matches!(parse_chat_response(refused), Err(ImageError::Api { .. })));
+    }
+
+    #[test]
+    fn sse_events_survive_chunk_boundaries() {
+        let mut buffer = SseBuffer::default();
+        assert!(buffer.push(b"event: a\r\ndata: {\"x\"").is_empty());
+        assert!(buffer.push(b": 1}\r\n\r").is_empty());
+        let events = buffer.push(b"\ndata: 2\n\ndata: 3");
+        assert_eq!(events, ["event: a\ndata: {\"x\": 1}", "data: 2"]);
+        assert_eq!(buffer.push(b"\n\n"), ["data: 3"]);
+    }
+
+    #[test]
+    fn stream_events() {
+        let partial = r#"data: {"type": "image_generation.partial_image", "b64_json": "AA==", "partial_image_index": 1}"#;
+        assert_eq!(
+            parse_stream_event(partial).unwrap(),
+            StreamEvent::Partial { frame: 1, b64_json: "AA==".into() }
+        );
+        let done = r#"data: {"type": "image_generation.completed", "b64_json": "AQ==", "usage": {"input_tokens": 50, "output_tokens": 4160, "total_tokens": 4210}}"#;
+        let usage = Usage { input_tokens: 50, output_tokens: 4160 };
+        assert_eq!(
+            parse_stream_event(done).unwrap(),
+            StreamEvent::Completed { b64_json: "AQ==".into(), usage: Some(usage) }
+        );
+        assert_eq!(parse_stream_event(": keep-alive").unwrap(), StreamEvent::Other);
+
+        let error = r#"data: {"type": "error", "error": {"message": "moderation_blocked"}}"#;
+        assert!(parse_stream_event(error).unwrap_err().to_string().contains("moderation_blocked"));
+
+        let response = completed_response(vec![("AQ==".into(), Some(usage))], "image/png").unwrap();
+        assert_eq!(response.images[0].data, [1]);
+        assert_eq!(response.usage, Some(usage));
+        assert!(completed_response(vec![], "image/png").is_err());
+    }
+
+    #[test]
+    fn undecodable_image_does_not_sink_the_batch() {
+        let body = r#"{"data": [{"b64_json": "iVBORw=="}, {"b64_json": "not base64!"}]}"#;
+        let response = parse_response(body, "png").unwrap();
+        assert_eq!(response.images.len(), 1);
+        assert_eq!(response.failures.len(), 1);
+        assert_eq!(response.failures[0].index, 1);
+        assert_eq!(response.usage, None);
+
+        let body = r#"{"data": [{"b64_json": "AQ=="}], "usage": {"input_tokens": 12, "input_tokens_details": {"text_tokens": 12, "image_tokens": 0}, "output_tokens": 272, "total_tokens": 284}}"#;
+        let usage = parse_response(body, "png").unwrap().usage;
+        assert_eq!(usage, Some(Usage { input_tokens: 12, output_tokens: 272 }));
+
+        let err = parse_response(r#"{"data": [{"b64_json": "%%"}]}"#, "png").unwrap_err();
+        assert!(err.to_string().contains("No usable images"));
+    }
+}
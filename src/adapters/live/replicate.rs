@@ -0,0 +1,299 @@
+//! Live adapter for Replicate predictions.
+//!
+//! Replicate runs models asynchronously: the adapter creates a prediction,
+//! polls it with exponential backoff until it settles, then downloads the
+//! output files (or, for URL delivery, hands back their URLs). Only the final
+//! result crosses the port boundary, so the recording and replaying adapters see
+//! a single interaction per call.
+
+use std::time::Duration;
+
+use base64::Engine;
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::http::{api_error, build_client, HttpSettings};
+use crate::error::ImageError;
+use crate::ports::image_generator::{
+    GenerateFuture, GeneratedImage, ImageFailure, ImageGenerator, ImageRequest, ImageResponse,
+};
+use crate::ports::upscaler::{UpscaleRequest, Upscaler};
+
+const REPLICATE_API_BASE: &str = "https://api.replicate.com/v1";
+
+/// Model-name prefix that routes a request to Replicate (`replicate/<owner>/<name>`).
+const REPLICATE_MODEL_PREFIX: &str = "replicate/";
+
+/// Pinned version of `nightmareai/real-esrgan`, used for upscaling. Community models
+/// are addressed by version rather than by name.
+const ESRGAN_VERSION: &str = "f121d640bd286e1fdc67f9799164c1d5be36ff74576ee11c803ae5b665dd46aa";
+
+/// First delay between polls.
+const POLL_INITIAL: Duration = Duration::from_millis(500);
+/// Upper bound for the delay between polls.
+const POLL_MAX: Duration = Duration::from_secs(5);
+/// Give up on a prediction that has not settled after this long.
+const POLL_DEADLINE: Duration = Duration::from_secs(600);
+
+/// Live Replicate generator that creates and polls predictions.
+pub struct ReplicateGenerator {
+    client: Client,
+    api_token: String,
+}
+
+impl ReplicateGenerator {
+    /// Create a new Replicate generator with the given API token and HTTP settings.
+    #[must_use]
+    pub fn new(api_token: String, http: &HttpSettings) -> Self {
+        Self { client: build_client(http), api_token }
+    }
+
+    /// GET a Replicate API URL and parse it as a prediction.
+    async fn get_prediction(&self, url: &str) -> Result<Prediction, ImageError> {
+        let response = self
+            .client
+            .get(url)
+            .header("Authorization", format!("Bearer {}", self.api_token))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        let text = response.text().await?;
+        parse_prediction(&text)
+    }
+
+    /// Download a single output file.
+    async fn download(&self, url: &str, format: &str) -> Result<GeneratedImage, ImageError> {
+        let response = self.client.get(url).send().await?;
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+        let mime_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .filter(|v| v.starts_with("image/"))
+            .map_or_else(|| format!("image/{format}"), ToString::to_string);
+        let data = response.bytes().await?.to_vec();
+        Ok(GeneratedImage { data, mime_type, signature: None, url: None })
+    }
+
+    /// Create a prediction at `url`, wait for it to settle, and download its outputs
+    /// (or return their URLs when `return_urls` is set).
+    async fn run_prediction(
+        &self,
+        url: &str,
+        body: &serde_json::Value,
+        format: &str,
+        return_urls: bool,
+    ) -> Result<ImageResponse, ImageError> {
+        let response = self
+            .client
+            .post(url)
+            .header("Authorization", format!("Bearer {}", self.api_token))
+            .json(body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(api_error(response).await);
+        }
+
+        let text = response.text().await?;
+        let mut prediction = parse_prediction(&text)?;
+
+        let started = std::time::Instant::now();
+        let mut attempt = 0;
+        while !prediction.is_settled() {
+            if started.elapsed() > POLL_DEADLINE {
+                return Err(ImageError::Api {
+                    status: 0,
+                    message: format!(
+                        "Prediction {} did not finish within {}s",
+                        prediction.id,
+                        POLL_DEADLINE.as_secs()
+                    ),
+                });
+            }
+            tokio::time::sleep(poll_delay(attempt)).await;
+            attempt += 1;
+            prediction = self.get_prediction(&prediction.urls.get).await?;
+        }
+
+        if prediction.status != "succeeded" {
+            let reason = prediction.error.unwrap_or_else(|| prediction.status.clone());
+            return Err(ImageError::Api {
+                status: 200,
+                message: format!("Prediction {} {}: {reason}", prediction.id, prediction.status),
+            });
+        }
+
+        let urls = prediction.output_urls();
+        if urls.is_empty() {
+            return Err(ImageError::Api {
+                status: 200,
+                message: format!("No images in prediction {}", prediction.id),
+            });
+        }
+
+        let mut images = Vec::new();
+        let mut failures = Vec::new();
+        for (index, url) in urls.into_iter().enumerate() {
+            if return_urls {
+                images.push(GeneratedImage {
+                    data: Vec::new(),
+                    mime_type: format!("image/{format}"),
+                    signature: None,
+                    url: Some(url),
+                });
+            } else {
+                match self.download(&url, format).await {
+                    Ok(image) => images.push(image),
+                    Err(e) => failures.push(ImageFailure { index, reason: e.to_string() }),
+                }
+            }
+        }
+        ImageResponse::partial(images, failures)
+    }
+}
+
+/// Delay before the poll following `attempt` (0-based): doubles from 500ms, capped at 5s.
+fn poll_delay(attempt: u32) -> Duration {
+    POLL_INITIAL.saturating_mul(2u32.saturating_pow(attempt)).min(POLL_MAX)
+}
+
+/// Replicate's image models name JPEG output `jpg`.
+fn replicate_output_format(format: &str) -> &str {
+    if format == "jpeg" {
+        "jpg"
+    } else {
+        format
+    }
+}
+
+/// Parse a prediction JSON body.
+fn parse_prediction(text: &str) -> Result<Prediction, ImageError> {
+    serde_json::from_str(text).map_err(|e| ImageError::Api {
+        status: 200,
+        message: format!("Failed to parse prediction: {e}"),
+    })
+}
+
+impl ImageGenerator for ReplicateGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let model_path =
+                request.model.strip_prefix(REPLICATE_MODEL_PREFIX).unwrap_or(&request.model);
+            let url = format!("{REPLICATE_API_BASE}/models/{model_path}/predictions");
+
+            let mut body = serde_json::json!({
+                "input": {
+                    "prompt": request.prompt,
+                    "aspect_ratio": request.aspect_ratio,
+                    "num_outputs": request.count,
+                    "output_format": replicate_output_format(&request.format),
+                }
+            });
+            if let Some(seed) = request.seed {
+                body["input"]["seed"] = seed.into();
+            }
+
+            self.run_prediction(&url, &body, &request.format, request.return_urls).await
+        })
+    }
+}
+
+impl Upscaler for ReplicateGenerator {
+    fn upscale(&self, request: &UpscaleRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let b64 = base64::engine::general_purpose::STANDARD.encode(&request.image.data);
+            let body = serde_json::json!({
+                "version": ESRGAN_VERSION,
+                "input": {
+                    "image": format!("data:{};base64,{b64}", request.image.mime_type),
+                    "scale": request.factor,
+                }
+            });
+            let url = format!("{REPLICATE_API_BASE}/predictions");
+            self.run_prediction(&url, &body, "png", false).await
+        })
+    }
+}
+
+// --- Replicate API response types ---
+
+#[derive(Deserialize)]
+struct Prediction {
+    id: String,
+    status: String,
+    #[serde(default)]
+    output: Option<serde_json::Value>,
+    #[serde(default)]
+    error: Option<String>,
+    urls: PredictionUrls,
+}
+
+#[derive(Deserialize)]
+struct PredictionUrls {
+    get: String,
+}
+
+impl Prediction {
+    /// Whether the prediction has reached a terminal state.
+    fn is_settled(&self) -> bool {
+        matches!(self.status.as_str(), "succeeded" | "failed" | "canceled")
+    }
+
+    /// Output file URLs; models return either a single URL or a list.
+    fn output_urls(&self) -> Vec<String> {
+        match &self.output {
+            Some(serde_json::Value::String(url)) => vec![url.clone()],
+            Some(serde_json::Value::Array(items)) => {
+                items.iter().filter_map(|v| v.as_str().map(ToString::to_string)).collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn poll_delay_backs_off_and_caps() {
+        assert_eq!(poll_delay(0), Duration::from_millis(500));
+        assert_eq!(poll_delay(1), Duration::from_secs(1));
+        assert_eq!(poll_delay(2), Duration::from_secs(2));
+        assert_eq!(poll_delay(4), POLL_MAX);
+        assert_eq!(poll_delay(40), POLL_MAX);
+    }
+
+    #[test]
+    fn prediction_output_list_and_string() {
+        let list = parse_prediction(
+            r#"{"id":"p1","status":"succeeded","output":["https://a/1.webp","https://a/2.webp"],
+               "urls":{"get":"https://api/p1"}}"#,
+        )
+        .unwrap();
+        assert!(list.is_settled());
+        assert_eq!(list.output_urls().len(), 2);
+
+        let single = parse_prediction(
+            r#"{"id":"p2","status":"processing","output":"https://a/1.png",
+               "urls":{"get":"https://api/p2"}}"#,
+        )
+        .unwrap();
+        assert!(!single.is_settled());
+        assert_eq!(single.output_urls(), vec!["https://a/1.png".to_string()]);
+    }
+
+    #[test]
+    fn jpeg_maps_to_jpg() {
+        assert_eq!(replicate_output_format("jpeg"), "jpg");
+        assert_eq!(replicate_output_format("png"), "png");
+    }
+}
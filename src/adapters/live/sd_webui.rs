@@ -0,0 +1,112 @@
+//! Live adapter for a local Stable Diffusion web UI (Automatic1111 API).
+
+use base64::Engine;
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::http::{api_error, build_client, HttpSettings};
+use crate::error::ImageError;
+use crate::params::aspect_ratio_to_dimensions;
+use crate::ports::image_generator::{
+    GenerateFuture, GeneratedImage, ImageFailure, ImageGenerator, ImageRequest, ImageResponse,
+};
+
+/// Live generator for a self-hosted SD web UI started with `--api`.
+pub struct SdWebUiGenerator {
+    client: Client,
+    base_url: String,
+}
+
+impl SdWebUiGenerator {
+    /// Create a new generator targeting the web UI at `base_url`.
+    #[must_use]
+    pub fn new(base_url: &str, http: &HttpSettings) -> Self {
+        let base_url = base_url.trim_end_matches('/').to_string();
+        Self { client: build_client(http), base_url }
+    }
+}
+
+/// Long edge in pixels for each `--size` value.
+fn long_edge(size: &str) -> u32 {
+    match size {
+        "2K" => 2048,
+        "4K" => 4096,
+        _ => 1024,
+    }
+}
+
+impl ImageGenerator for SdWebUiGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let (width, height) =
+                aspect_ratio_to_dimensions(&request.aspect_ratio, long_edge(&request.size));
+
+            let mut body = serde_json::json!({
+                "prompt": request.prompt,
+                "width": width,
+                "height": height,
+                "batch_size": request.count,
+                // -1 asks the web UI for a random seed.
+                "seed": request.seed.map_or(-1, i64::from),
+            });
+
+            let endpoint = if request.input_images.is_empty() {
+                "txt2img"
+            } else {
+                let init_images: Vec<String> = request
+                    .input_images
+                    .iter()
+                    .map(|img| base64::engine::general_purpose::STANDARD.encode(&img.data))
+                    .collect();
+                body["init_images"] = serde_json::json!(init_images);
+                "img2img"
+            };
+
+            let url = format!("{}/sdapi/v1/{endpoint}", self.base_url);
+            let response = self.client.post(&url).json(&body).send().await?;
+
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+
+            let text = response.text().await?;
+
+            let parsed: SdWebUiResponse = serde_json::from_str(&text).map_err(|e| {
+                ImageError::Api { status: 200, message: format!("Failed to parse response: {e}") }
+            })?;
+
+            let mut images = Vec::new();
+            let mut failures = Vec::new();
+            for (index, b64) in parsed.images.iter().enumerate() {
+                match base64::engine::general_purpose::STANDARD.decode(b64) {
+                    Ok(data) => images.push(GeneratedImage {
+                        data,
+                        mime_type: "image/png".to_string(),
+                        signature: None,
+                        url: None,
+                    }),
+                    Err(e) => failures
+                        .push(ImageFailure { index, reason: format!("invalid base64: {e}") }),
+                }
+            }
+
+            if images.is_empty() && failures.is_empty() {
+                return Err(ImageError::NoImages {
+                    finish_reason: None,
+                    detail: "The web UI returned an empty image list".into(),
+                });
+            }
+
+            ImageResponse::partial(images, failures)
+        })
+    }
+}
+
+// --- SD web UI API response types ---
+
+#[derive(Deserialize)]
+struct SdWebUiResponse {
+    #[serde(default)]
+    images: Vec<String>,
+}
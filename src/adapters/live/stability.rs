@@ -0,0 +1,214 @@
+//! Live adapter for the Stability AI Stable Image API.
+
+use base64::Engine;
+use reqwest::multipart;
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::http::{api_error, build_client, HttpSettings};
+use crate::error::ImageError;
+use crate::ports::image_generator::{
+    GenerateFuture, GeneratedImage, ImageGenerator, ImageRequest, ImageResponse,
+};
+use crate::ports::upscaler::{UpscaleRequest, Upscaler};
+
+const STABILITY_API_BASE: &str = "https://api.stability.ai/v2beta/stable-image/generate";
+
+/// Fast upscaler endpoint: a fixed 4x enlargement with no prompt.
+const STABILITY_UPSCALE_URL: &str = "https://api.stability.ai/v2beta/stable-image/upscale/fast";
+
+/// Scale factor of the fast upscaler.
+const FAST_UPSCALE_FACTOR: u32 = 4;
+
+/// Default strength applied when an input image is supplied (0 = keep input, 1 = ignore it).
+const IMAGE_TO_IMAGE_STRENGTH: &str = "0.6";
+
+/// Live Stability AI generator that calls the Stable Image v2beta API.
+pub struct StabilityGenerator {
+    client: Client,
+    api_key: String,
+}
+
+impl StabilityGenerator {
+    /// Create a new Stability generator with the given API key and HTTP settings.
+    #[must_use]
+    pub fn new(api_key: String, http: &HttpSettings) -> Self {
+        Self { client: build_client(http), api_key }
+    }
+}
+
+/// Map a resolved model name to its endpoint path and optional `model` form field.
+///
+/// The Ultra and Core services have their own endpoints; all SD3.x models share `/sd3`.
+pub(crate) fn endpoint_for_model(model: &str) -> (&'static str, Option<&str>) {
+    match model {
+        "stable-image-ultra" => ("ultra", None),
+        "stable-image-core" => ("core", None),
+        other => ("sd3", Some(other)),
+    }
+}
+
+/// Parse a Stability JSON response body into a single generated image.
+fn parse_response(response_text: &str, format: &str) -> Result<GeneratedImage, ImageError> {
+    let parsed: StabilityResponse = serde_json::from_str(response_text).map_err(|e| {
+        ImageError::Api { status: 200, message: format!("Failed to parse response: {e}") }
+    })?;
+
+    if parsed.finish_reason.as_deref() == Some("CONTENT_FILTERED") {
+        return Err(ImageError::Api {
+            status: 200,
+            message: "Image was blocked by the Stability content filter".to_string(),
+        });
+    }
+
+    let data = base64::engine::general_purpose::STANDARD.decode(&parsed.image).map_err(|e| {
+        ImageError::Api { status: 200, message: format!("Failed to decode base64: {e}") }
+    })?;
+    Ok(GeneratedImage { data, mime_type: format!("image/{format}"), signature: None, url: None })
+}
+
+impl ImageGenerator for StabilityGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            if request.input_images.len() > 1 {
+                return Err(ImageError::InvalidArgument(
+                    "Stability models accept at most one input image".to_string(),
+                ));
+            }
+
+            let (endpoint, model_field) = endpoint_for_model(&request.model);
+            let url = format!("{STABILITY_API_BASE}/{endpoint}");
+
+            // The API returns one image per call. `ServiceContext::live` fans --count out
+            // into concurrent single-image calls; other callers get sequential requests.
+            let mut images = Vec::new();
+            for index in 0..request.count.max(1) {
+                let mut form = multipart::Form::new()
+                    .text("prompt", request.prompt.clone())
+                    .text("output_format", request.format.clone());
+                if let Some(seed) = request.image_seed(index) {
+                    form = form.text("seed", seed.to_string());
+                }
+
+                if let Some(model) = model_field {
+                    form = form.text("model", model.to_string());
+                }
+
+                if let Some(img) = request.input_images.first() {
+                    let part = multipart::Part::bytes(img.data.clone())
+                        .file_name(img.filename.clone())
+                        .mime_str(&img.mime_type)
+                        .map_err(|e| ImageError::Api {
+                            status: 0,
+                            message: format!("Failed to build multipart: {e}"),
+                        })?;
+                    form = form.part("image", part).text("strength", IMAGE_TO_IMAGE_STRENGTH);
+                    if model_field.is_some() {
+                        form = form.text("mode", "image-to-image");
+                    }
+                } else {
+                    // aspect_ratio is only accepted for text-to-image requests.
+                    form = form.text("aspect_ratio", request.aspect_ratio.clone());
+                }
+
+                let response = self
+                    .client
+                    .post(&url)
+                    .header("Authorization", format!("Bearer {}", self.api_key))
+                    .header("Accept", "application/json")
+                    .multipart(form)
+                    .send()
+                    .await?;
+
+                if !response.status().is_success() {
+                    return Err(api_error(response).await);
+                }
+
+                let text = response.text().await?;
+                images.push(parse_response(&text, &request.format)?);
+            }
+
+            Ok(ImageResponse { images, failures: Vec::new(), usage: None })
+        })
+    }
+}
+
+impl Upscaler for StabilityGenerator {
+    fn upscale(&self, request: &UpscaleRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            if request.factor > FAST_UPSCALE_FACTOR {
+                return Err(ImageError::InvalidArgument(format!(
+                    "Stability upscaling is limited to {FAST_UPSCALE_FACTOR}x"
+                )));
+            }
+            let part = multipart::Part::bytes(request.image.data.clone())
+                .file_name(request.image.filename.clone())
+                .mime_str(&request.image.mime_type)
+                .map_err(|e| ImageError::Api {
+                    status: 0,
+                    message: format!("Failed to build multipart: {e}"),
+                })?;
+            let form = multipart::Form::new()
+                .part("image", part)
+                .text("output_format", request.format.clone());
+
+            let response = self
+                .client
+                .post(STABILITY_UPSCALE_URL)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Accept", "application/json")
+                .multipart(form)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+
+            let text = response.text().await?;
+            Ok(ImageResponse {
+                images: vec![parse_response(&text, &request.format)?],
+                failures: Vec::new(),
+                usage: None,
+            })
+        })
+    }
+}
+
+// --- Stability API response types ---
+
+#[derive(Deserialize)]
+struct StabilityResponse {
+    image: String,
+    #[serde(default)]
+    finish_reason: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endpoint_mapping() {
+        assert_eq!(endpoint_for_model("stable-image-ultra"), ("ultra", None));
+        assert_eq!(endpoint_for_model("stable-image-core"), ("core", None));
+        assert_eq!(endpoint_for_model("sd3.5-large"), ("sd3", Some("sd3.5-large")));
+    }
+
+    #[test]
+    fn parse_success_response() {
+        let body = r#"{"image":"AQID","finish_reason":"SUCCESS","seed":42}"#;
+        let image = parse_response(body, "png").unwrap();
+        assert_eq!(image.data, vec![1, 2, 3]);
+        assert_eq!(image.mime_type, "image/png");
+    }
+
+    #[test]
+    fn parse_content_filtered_response() {
+        let body = r#"{"image":"","finish_reason":"CONTENT_FILTERED"}"#;
+        let err = parse_response(body, "png").unwrap_err();
+        assert!(err.to_string().contains("content filter"));
+    }
+}
@@ -0,0 +1,230 @@
+//! Live adapter for Flux models on the Together AI images API.
+//!
+//! Together serves Black Forest Labs' Flux family (`black-forest-labs/FLUX.*`) behind an
+//! OpenAI-style `/v1/images/generations` endpoint that returns base64 images inline, or
+//! download URLs when the request asks for them.
+
+use base64::Engine;
+use reqwest::Client;
+use serde::Deserialize;
+
+use super::http::{api_error, build_client, HttpSettings};
+use crate::error::ImageError;
+use crate::params::aspect_ratio_to_dimensions;
+use crate::ports::image_generator::{
+    GenerateFuture, GeneratedImage, ImageFailure, ImageGenerator, ImageRequest, ImageResponse,
+};
+
+const TOGETHER_API_URL: &str = "https://api.together.xyz/v1/images/generations";
+
+/// Most images Together returns from one call.
+const MAX_IMAGES_PER_CALL: u32 = 4;
+
+/// Flux Schnell is distilled to a few sampling steps and rejects more than this.
+const SCHNELL_STEPS: u32 = 4;
+
+/// Live Together AI generator for Flux models.
+pub struct TogetherGenerator {
+    client: Client,
+    api_key: String,
+}
+
+impl TogetherGenerator {
+    /// Create a new Together generator with the given API key and HTTP settings.
+    #[must_use]
+    pub fn new(api_key: String, http: &HttpSettings) -> Self {
+        Self { client: build_client(http), api_key }
+    }
+}
+
+/// Build the JSON request body.
+///
+/// Flux renders up to 1440px, so 2K and 4K requests use that long edge. An input image is
+/// passed as a data URL in `image_url`, which Flux Kontext models use as the edit source.
+fn request_body(request: &ImageRequest) -> Result<serde_json::Value, ImageError> {
+    if request.count > MAX_IMAGES_PER_CALL {
+        return Err(ImageError::InvalidArgument(format!(
+            "Together returns at most {MAX_IMAGES_PER_CALL} images per request"
+        )));
+    }
+    if request.input_images.len() > 1 {
+        return Err(ImageError::InvalidArgument(
+            "Flux models accept at most one input image".to_string(),
+        ));
+    }
+
+    let long_edge = if request.size == "1K" { 1024 } else { 1440 };
+    let (width, height) = aspect_ratio_to_dimensions(&request.aspect_ratio, long_edge);
+    let output_format = if request.format == "png" { "png" } else { "jpeg" };
+    let mut body = serde_json::json!({
+        "model": request.model,
+        "prompt": request.prompt,
+        "width": width,
+        "height": height,
+        "n": request.count,
+        "response_format": if request.return_urls { "url" } else { "b64_json" },
+        "output_format": output_format,
+    });
+    if request.model.contains("schnell") {
+        body["steps"] = SCHNELL_STEPS.into();
+    }
+    if let Some(seed) = request.seed {
+        body["seed"] = seed.into();
+    }
+    if let Some(img) = request.input_images.first() {
+        let b64 = base64::engine::general_purpose::STANDARD.encode(&img.data);
+        body["image_url"] = format!("data:{};base64,{b64}", img.mime_type).into();
+    }
+    Ok(body)
+}
+
+/// Parse a Together images response into generated images.
+fn parse_response(response_text: &str, mime_type: &str) -> Result<ImageResponse, ImageError> {
+    let parsed: TogetherResponse = serde_json::from_str(response_text).map_err(|e| {
+        ImageError::Api { status: 200, message: format!("Failed to parse response: {e}") }
+    })?;
+
+    let mut images = Vec::new();
+    let mut failures = Vec::new();
+    for (index, item) in parsed.data.into_iter().enumerate() {
+        let image = |data, url| GeneratedImage {
+            data,
+            mime_type: mime_type.to_string(),
+            signature: None,
+            url,
+        };
+        match (item.b64_json, item.url) {
+            (Some(b64), _) => match base64::engine::general_purpose::STANDARD.decode(b64) {
+                Ok(data) => images.push(image(data, None)),
+                Err(e) => {
+                    failures.push(ImageFailure { index, reason: format!("invalid base64: {e}") });
+                }
+            },
+            (None, Some(url)) => images.push(image(Vec::new(), Some(url))),
+            (None, None) => {}
+        }
+    }
+
+    if images.is_empty() && failures.is_empty() {
+        return Err(ImageError::NoImages {
+            finish_reason: None,
+            detail: "Together returned an empty image list".into(),
+        });
+    }
+    ImageResponse::partial(images, failures)
+}
+
+impl ImageGenerator for TogetherGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let body = request_body(&request)?;
+            let mime_type = format!("image/{}", body["output_format"].as_str().unwrap_or("jpeg"));
+
+            let response = self
+                .client
+                .post(TOGETHER_API_URL)
+                .bearer_auth(&self.api_key)
+                .json(&body)
+                .send()
+                .await?;
+
+            if !response.status().is_success() {
+                return Err(api_error(response).await);
+            }
+
+            let text = response.text().await?;
+
+            parse_response(&text, &mime_type)
+        })
+    }
+}
+
+// --- Together API response types ---
+
+#[derive(Deserialize)]
+struct TogetherResponse {
+    #[serde(default)]
+    data: Vec<TogetherImage>,
+}
+
+#[derive(Deserialize)]
+struct TogetherImage {
+    #[serde(default)]
+    b64_json: Option<String>,
+    #[serde(default)]
+    url: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::InputImage;
+
+    fn request(model: &str) -> ImageRequest {
+        ImageRequest {
+            aspect_ratio: "16:9".into(),
+            format: "png".into(),
+            count: 2,
+            ..ImageRequest::sample(model, "a red fox in snow")
+        }
+    }
+
+    #[test]
+    fn schnell_body() {
+        let body = request_body(&request("black-forest-labs/FLUX.1-schnell")).unwrap();
+        assert_eq!(body["width"], 1024);
+        assert_eq!(body["height"], 576);
+        assert_eq!(body["n"], 2);
+        assert_eq!(body["steps"], SCHNELL_STEPS);
+        assert_eq!(body["output_format"], "png");
+        assert!(body.get("image_url").is_none());
+    }
+
+    #[test]
+    fn pro_body_leaves_steps_to_the_model() {
+        let body = request_body(&request("black-forest-labs/FLUX.1.1-pro")).unwrap();
+        assert!(body.get("steps").is_none());
+    }
+
+    #[test]
+    fn input_image_becomes_data_url() {
+        let mut req = request("black-forest-labs/FLUX.1-kontext-pro");
+        req.input_images.push(InputImage {
+            data: vec![1, 2, 3],
+            mime_type: "image/png".into(),
+            filename: "a.png".into(),
+            role: None,
+        });
+        let body = request_body(&req).unwrap();
+        assert_eq!(body["image_url"], "data:image/png;base64,AQID");
+    }
+
+    #[test]
+    fn too_many_images_rejected() {
+        let mut req = request("black-forest-labs/FLUX.1-schnell");
+        req.count = 5;
+        assert!(matches!(request_body(&req), Err(ImageError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn parse_success_response() {
+        let body =
+            r#"{"id": "x", "object": "list", "data": [{"index": 0, "b64_json": "iVBORw=="}]}"#;
+        let images = parse_response(body, "image/png").unwrap().images;
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].data, vec![0x89, 0x50, 0x4e, 0x47]);
+    }
+
+    #[test]
+    fn url_delivery() {
+        let mut req = request("black-forest-labs/FLUX.1-schnell");
+        req.return_urls = true;
+        assert_eq!(request_body(&req).unwrap()["response_format"], "url");
+
+        let body = r#"{"data": [{"index": 0, "url": "https://cdn.example.com/a.png"}]}"#;
+        let images = parse_response(body, "image/png").unwrap().images;
+        assert!(images[0].data.is_empty());
+        assert_eq!(images[0].url.as_deref(), Some("https://cdn.example.com/a.png"));
+    }
+}
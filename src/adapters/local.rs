@@ -0,0 +1,67 @@
+//! In-process adapters that need no external service.
+
+use std::io::Cursor;
+
+use crate::error::ImageError;
+use crate::ports::image_generator::{GenerateFuture, GeneratedImage, ImageResponse};
+use crate::ports::upscaler::{UpscaleRequest, Upscaler};
+
+/// Upscales with the `image` crate's Lanczos resampling.
+///
+/// No detail is invented, so results are softer than a model-based upscaler, but it
+/// works offline and costs nothing.
+pub struct LocalUpscaler;
+
+impl Upscaler for LocalUpscaler {
+    fn upscale(&self, request: &UpscaleRequest) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let img = image::load_from_memory(&request.image.data)
+                .map_err(|e| ImageError::ImageConversion(format!("Failed to decode image: {e}")))?;
+            let resized = img.resize_exact(
+                img.width() * request.factor,
+                img.height() * request.factor,
+                image::imageops::FilterType::Lanczos3,
+            );
+            let mut png = Cursor::new(Vec::new());
+            resized.write_to(&mut png, image::ImageFormat::Png).map_err(|e| {
+                ImageError::ImageConversion(format!("Failed to encode upscaled image: {e}"))
+            })?;
+            let image = GeneratedImage {
+                data: png.into_inner(),
+                mime_type: "image/png".into(),
+                signature: None,
+                url: None,
+            };
+            Ok(ImageResponse { images: vec![image], failures: Vec::new(), usage: None })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::InputImage;
+
+    #[tokio::test]
+    async fn local_upscale_multiplies_dimensions() {
+        let mut source = Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(5, 3))
+            .write_to(&mut source, image::ImageFormat::Png)
+            .unwrap();
+        let request = UpscaleRequest {
+            image: InputImage {
+                data: source.into_inner(),
+                mime_type: "image/png".into(),
+                filename: "small.png".into(),
+                role: None,
+            },
+            factor: 3,
+            format: "png".into(),
+        };
+
+        let response = LocalUpscaler.upscale(&request).await.unwrap();
+        let out = image::load_from_memory(&response.images[0].data).unwrap();
+        assert_eq!((out.width(), out.height()), (15, 9));
+    }
+}
@@ -3,7 +3,15 @@
 //! - `live/` — Real API implementations
 //! - `recording/` — Record interactions to cassettes
 //! - `replaying/` — Replay interactions from cassettes
+//! - `retrying` — Retry transient failures of a live adapter
+//! - `editing` — Serve an image editor through the generator port
+//! - `fan_out` — Serve `count` on providers that return one image per call
+//! - `local` — In-process adapters (no network)
 
+pub mod editing;
+pub mod fan_out;
 pub mod live;
+pub mod local;
 pub mod recording;
 pub mod replaying;
+pub mod retrying;
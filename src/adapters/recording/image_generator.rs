@@ -4,7 +4,7 @@ use std::sync::{Arc, Mutex};
 
 use super::record_result;
 use crate::cassette::recorder::CassetteRecorder;
-use crate::ports::image_generator::{GenerateFuture, ImageGenerator, ImageRequest};
+use crate::ports::image_generator::{GenerateFuture, ImageGenerator, ImageRequest, PartialSink};
 
 /// Records image generation interactions while delegating to an inner implementation.
 pub struct RecordingImageGenerator {
@@ -17,17 +17,42 @@ impl RecordingImageGenerator {
     pub fn new(inner: Box<dyn ImageGenerator>, recorder: Arc<Mutex<CassetteRecorder>>) -> Self {
         Self { inner, recorder }
     }
-}
 
-impl ImageGenerator for RecordingImageGenerator {
-    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+    /// Delegate to the inner generator and record the final result; previews streamed
+    /// to `partials` are not recorded.
+    fn record(&self, request: &ImageRequest, partials: Option<PartialSink>) -> GenerateFuture<'_> {
         let request_clone = request.clone();
         let recorder = Arc::clone(&self.recorder);
 
         Box::pin(async move {
-            let result = self.inner.generate(&request_clone).await;
-            record_result(&recorder, "image_generator", "generate", &request_clone, &result);
+            let result = match partials {
+                Some(sink) => self.inner.generate_streaming(&request_clone, sink).await,
+                None => self.inner.generate(&request_clone).await,
+            };
+            let request_id = Some(request_clone.request_id());
+            record_result(
+                &recorder,
+                "image_generator",
+                "generate",
+                request_id,
+                &request_clone,
+                &result,
+            );
             result
         })
     }
 }
+
+impl ImageGenerator for RecordingImageGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        self.record(request, None)
+    }
+
+    fn generate_streaming(
+        &self,
+        request: &ImageRequest,
+        partials: PartialSink,
+    ) -> GenerateFuture<'_> {
+        self.record(request, Some(partials))
+    }
+}
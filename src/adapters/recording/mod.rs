@@ -3,6 +3,7 @@
 //! Placeholder for Phase 3 implementation.
 
 pub mod image_generator;
+pub mod prompt_enhancer;
 
 use std::sync::{Arc, Mutex};
 
@@ -15,6 +16,7 @@ pub(crate) fn record_result<T, E, I>(
     recorder: &Arc<Mutex<CassetteRecorder>>,
     port: &str,
     method: &str,
+    request_id: Option<String>,
     input: &I,
     result: &Result<T, E>,
 ) where
@@ -33,5 +35,5 @@ pub(crate) fn record_result<T, E, I>(
     };
 
     let mut guard = recorder.lock().expect("recorder lock poisoned");
-    guard.record(port, method, input_json, output_json);
+    guard.record(port, method, request_id, input_json, output_json);
 }
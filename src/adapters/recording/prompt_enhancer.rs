@@ -0,0 +1,31 @@
+//! Recording adapter for the `PromptEnhancer` port.
+
+use std::sync::{Arc, Mutex};
+
+use super::record_result;
+use crate::cassette::recorder::CassetteRecorder;
+use crate::ports::prompt_enhancer::{EnhanceFuture, EnhanceRequest, PromptEnhancer};
+
+/// Records prompt enhancement interactions while delegating to an inner implementation.
+pub struct RecordingPromptEnhancer {
+    inner: Box<dyn PromptEnhancer>,
+    recorder: Arc<Mutex<CassetteRecorder>>,
+}
+
+impl RecordingPromptEnhancer {
+    /// Creates a new recording enhancer wrapping the given implementation.
+    pub fn new(inner: Box<dyn PromptEnhancer>, recorder: Arc<Mutex<CassetteRecorder>>) -> Self {
+        Self { inner, recorder }
+    }
+}
+
+impl PromptEnhancer for RecordingPromptEnhancer {
+    fn enhance(&self, request: &EnhanceRequest) -> EnhanceFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let result = self.inner.enhance(&request).await;
+            record_result(&self.recorder, "prompt_enhancer", "enhance", None, &request, &result);
+            result
+        })
+    }
+}
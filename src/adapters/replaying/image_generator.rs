@@ -3,8 +3,8 @@
 use std::sync::{Arc, Mutex};
 
 use super::{next_output, replay_result};
+use crate::cassette::format::recorded_error;
 use crate::cassette::replayer::CassetteReplayer;
-use crate::error::ImageError;
 use crate::ports::image_generator::{GenerateFuture, ImageGenerator, ImageRequest, ImageResponse};
 
 /// Serves recorded image generation results from a cassette.
@@ -24,8 +24,7 @@ impl ImageGenerator for ReplayingImageGenerator {
     fn generate(&self, _request: &ImageRequest) -> GenerateFuture<'_> {
         let output = next_output(self.replayer.as_ref(), "image_generator", "generate");
         Box::pin(async move {
-            replay_result::<ImageResponse>(output)
-                .map_err(|e| ImageError::Api { status: 0, message: e.to_string() })
+            replay_result::<ImageResponse>(output).map_err(|e| recorded_error(&e.to_string()))
         })
     }
 }
@@ -1,6 +1,7 @@
 //! Replaying adapters that serve recorded interactions from cassettes.
 
 pub mod image_generator;
+pub mod prompt_enhancer;
 
 use std::sync::{Arc, Mutex};
 
@@ -0,0 +1,30 @@
+//! Replaying adapter for the `PromptEnhancer` port.
+
+use std::sync::{Arc, Mutex};
+
+use super::{next_output, replay_result};
+use crate::cassette::format::recorded_error;
+use crate::cassette::replayer::CassetteReplayer;
+use crate::ports::prompt_enhancer::{EnhanceFuture, EnhanceRequest, PromptEnhancer};
+
+/// Serves recorded prompt enhancements from a cassette.
+pub struct ReplayingPromptEnhancer {
+    replayer: Option<Arc<Mutex<CassetteReplayer>>>,
+}
+
+impl ReplayingPromptEnhancer {
+    /// Create a replaying enhancer backed by the given replayer.
+    #[must_use]
+    pub fn new(replayer: Arc<Mutex<CassetteReplayer>>) -> Self {
+        Self { replayer: Some(replayer) }
+    }
+}
+
+impl PromptEnhancer for ReplayingPromptEnhancer {
+    fn enhance(&self, _request: &EnhanceRequest) -> EnhanceFuture<'_> {
+        let output = next_output(self.replayer.as_ref(), "prompt_enhancer", "enhance");
+        Box::pin(async move {
+            replay_result::<String>(output).map_err(|e| recorded_error(&e.to_string()))
+        })
+    }
+}
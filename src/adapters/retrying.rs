@@ -0,0 +1,203 @@
+//! Retry decorator for the `ImageGenerator` port.
+//!
+//! Wraps a live adapter and re-sends the same request when the failure is
+//! transient (see [`crate::error::ImageError::is_retryable`]), backing off exponentially
+//! between attempts. When a rate-limited provider says how long to wait (`Retry-After`),
+//! that wait is used instead. It sits inside the recording adapter, so a cassette
+//! captures only the final outcome of a call.
+
+use std::time::Duration;
+
+use tracing::Instrument as _;
+
+use crate::events;
+use crate::ports::image_generator::{GenerateFuture, ImageGenerator, ImageRequest, PartialSink};
+use crate::report;
+
+/// Longest `Retry-After` wait honored; a provider asking for more fails the call instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(300);
+
+/// Retries transient failures of an inner generator.
+pub struct RetryingImageGenerator {
+    inner: Box<dyn ImageGenerator>,
+    max_attempts: u32,
+    base_delay: Duration,
+}
+
+impl RetryingImageGenerator {
+    /// Wrap `inner`, making up to `max_attempts` calls with delays of
+    /// `base_delay`, `2 * base_delay`, `4 * base_delay`, ... between them.
+    #[must_use]
+    pub fn new(inner: Box<dyn ImageGenerator>, max_attempts: u32, base_delay: Duration) -> Self {
+        Self { inner, max_attempts: max_attempts.max(1), base_delay }
+    }
+
+    /// Delay after failed attempt `attempt` (1-based).
+    fn delay(&self, attempt: u32) -> Duration {
+        self.base_delay.saturating_mul(1 << (attempt - 1).min(16))
+    }
+
+    /// Call the inner generator until it succeeds, fails permanently, or runs out of
+    /// attempts. Each attempt streams its previews to `partials`, when given.
+    fn retry(&self, request: &ImageRequest, partials: Option<PartialSink>) -> GenerateFuture<'_> {
+        let request = request.clone();
+        Box::pin(async move {
+            let mut attempt = 1;
+            loop {
+                let call = match partials {
+                    Some(ref sink) => self.inner.generate_streaming(&request, sink.clone()),
+                    None => self.inner.generate(&request),
+                };
+                let span = tracing::debug_span!("http", model = %request.model, attempt);
+                match call.instrument(span).await {
+                    Err(e) if e.is_retryable() && attempt < self.max_attempts => {
+                        let (delay, source) = match e.retry_after() {
+                            Some(wait) if wait > MAX_RETRY_AFTER => {
+                                report::warn(format_args!(
+                                    "the provider asked to wait {}s before retrying, longer \
+                                     than imagen waits",
+                                    wait.as_secs()
+                                ));
+                                return Err(e);
+                            }
+                            Some(wait) => (wait, " as the provider asked (Retry-After)"),
+                            None => (self.delay(attempt), ""),
+                        };
+                        attempt += 1;
+                        report::warn(format_args!(
+                            "{e}\nRetrying in {}s{source} (attempt {attempt}/{})...",
+                            delay.as_secs_f32(),
+                            self.max_attempts
+                        ));
+                        events::retrying(&request, attempt, self.max_attempts, delay, &e);
+                        tokio::time::sleep(delay).await;
+                    }
+                    result => return result,
+                }
+            }
+        })
+    }
+}
+
+impl ImageGenerator for RetryingImageGenerator {
+    fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_> {
+        self.retry(request, None)
+    }
+
+    fn generate_streaming(
+        &self,
+        request: &ImageRequest,
+        partials: PartialSink,
+    ) -> GenerateFuture<'_> {
+        self.retry(request, Some(partials))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+
+    use crate::error::ImageError;
+    use crate::ports::image_generator::{GeneratedImage, ImageResponse};
+
+    /// Fails with `error` for the first `failures` calls, then succeeds.
+    struct Flaky {
+        calls: Arc<AtomicU32>,
+        failures: u32,
+        error: fn() -> ImageError,
+    }
+
+    impl ImageGenerator for Flaky {
+        fn generate(&self, _request: &ImageRequest) -> GenerateFuture<'_> {
+            Box::pin(async move {
+                let call = self.calls.fetch_add(1, Ordering::SeqCst);
+                if call < self.failures {
+                    return Err((self.error)());
+                }
+                Ok(ImageResponse {
+                    images: vec![GeneratedImage {
+                        data: vec![1],
+                        mime_type: "image/png".into(),
+                        signature: None,
+                        url: None,
+                    }],
+                    failures: Vec::new(),
+                    usage: None,
+                })
+            })
+        }
+    }
+
+    fn request() -> ImageRequest {
+        ImageRequest {
+            format: "png".into(),
+            ..ImageRequest::sample("gemini-3-pro-image-preview", "a cat")
+        }
+    }
+
+    fn flaky(failures: u32, error: fn() -> ImageError) -> (RetryingImageGenerator, Arc<AtomicU32>) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let inner = Flaky { calls: Arc::clone(&calls), failures, error };
+        (RetryingImageGenerator::new(Box::new(inner), 3, Duration::ZERO), calls)
+    }
+
+    fn no_images() -> ImageError {
+        ImageError::NoImages { finish_reason: Some("STOP".into()), detail: String::new() }
+    }
+
+    #[tokio::test]
+    async fn retries_empty_response_until_success() {
+        let (generator, calls) = flaky(2, no_images);
+        assert!(generator.generate(&request()).await.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_attempts() {
+        let (generator, calls) = flaky(5, no_images);
+        let err = generator.generate(&request()).await.unwrap_err();
+        assert!(matches!(err, ImageError::NoImages { .. }));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_are_not_retried() {
+        let (generator, calls) =
+            flaky(1, || ImageError::Api { status: 400, message: "bad request".into() });
+        assert!(generator.generate(&request()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn rate_limit_waits_as_asked() {
+        let (generator, calls) = flaky(1, || ImageError::RateLimited {
+            message: "slow down".into(),
+            retry_after: Duration::from_millis(20),
+        });
+        let generator = RetryingImageGenerator { base_delay: Duration::from_secs(60), ..generator };
+        let started = std::time::Instant::now();
+        assert!(generator.generate(&request()).await.is_ok());
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn overlong_retry_after_fails_fast() {
+        let (generator, calls) = flaky(1, || ImageError::RateLimited {
+            message: "daily quota".into(),
+            retry_after: Duration::from_secs(3600),
+        });
+        assert!(generator.generate(&request()).await.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn delay_doubles() {
+        let (generator, _) = flaky(0, no_images);
+        let generator = RetryingImageGenerator { base_delay: Duration::from_secs(2), ..generator };
+        assert_eq!(generator.delay(1), Duration::from_secs(2));
+        assert_eq!(generator.delay(2), Duration::from_secs(4));
+    }
+}
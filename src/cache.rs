@@ -0,0 +1,117 @@
+//! Result cache (`--cache`): answer a request with images saved earlier for an identical one.
+//!
+//! The cache is the generation history. A request hits when the history holds at least
+//! `count` distinct images with its request ID (a hash of the prompt, model, every
+//! setting, and any input images), saved in the same output format and save steps and
+//! enhanced by the same text model, whose files are still on disk unchanged. Those images
+//! are then saved again as they are, without calling the API or re-running the steps.
+
+use std::collections::{BTreeMap, HashSet};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::ImageError;
+use crate::history::{Filter, History};
+use crate::ports::image_generator::{GeneratedImage, ImageResponse};
+use crate::ports::ImageRequest;
+use crate::report;
+use crate::store::Store;
+
+/// Settings left out of the request ID, so a hit must match them too: how the file is
+/// saved, and the text model that rewrote the prompt.
+const FINISH: &[&str] = &[
+    "format",
+    "bit_depth",
+    "grayscale",
+    "compression",
+    "strip",
+    "dimensions",
+    "post",
+    "watermark",
+    "watermark_image",
+    "watermark_position",
+    "watermark_opacity",
+    "thumbnail",
+    "enhance",
+];
+
+/// Most history entries examined per request.
+const MAX_CANDIDATES: usize = 100;
+
+/// For each request, a response made of earlier images, or `None` when it must be sent.
+///
+/// `parameters` are the run's effective settings. A history that cannot be read is
+/// reported and every request is sent.
+#[must_use]
+pub fn lookup(
+    requests: &[ImageRequest],
+    request_ids: &[String],
+    parameters: &BTreeMap<String, String>,
+) -> Vec<Option<ImageResponse>> {
+    let misses = || requests.iter().map(|_| None).collect();
+    let Some(path) = Store::default_path().filter(|path| path.exists()) else {
+        return misses();
+    };
+    let history = match History::open(&path) {
+        Ok(history) => history,
+        Err(e) => {
+            report::warn(format_args!("cache unavailable: {e}"));
+            return misses();
+        }
+    };
+    requests
+        .iter()
+        .zip(request_ids)
+        .map(|(request, request_id)| {
+            cached(&history, request, request_id, parameters).unwrap_or_else(|e| {
+                report::warn(format_args!("cache lookup for request {request_id} failed: {e}"));
+                None
+            })
+        })
+        .collect()
+}
+
+/// The newest `count` intact images saved for `request_id` with the same finish.
+fn cached(
+    history: &History,
+    request: &ImageRequest,
+    request_id: &str,
+    parameters: &BTreeMap<String, String>,
+) -> Result<Option<ImageResponse>, ImageError> {
+    let wanted = request.count as usize;
+    let filter = Filter {
+        request_id: Some(request_id.to_string()),
+        limit: MAX_CANDIDATES,
+        ..Filter::default()
+    };
+    let mime_type = format!("image/{}", parameters.get("format").map_or("png", String::as_str));
+    // Re-running into the same output path records the same file again; count it once.
+    let mut seen = HashSet::new();
+    let mut images = Vec::new();
+    for entry in history.query(&filter)? {
+        if images.len() == wanted {
+            break;
+        }
+        let same_finish =
+            FINISH.iter().all(|name| entry.parameters.get(*name) == parameters.get(*name));
+        if !same_finish || !seen.insert(entry.sha256.clone()) {
+            continue;
+        }
+        let Ok(data) = std::fs::read(&entry.output_path) else { continue };
+        if format!("{:x}", Sha256::digest(&data)) != entry.sha256 {
+            continue;
+        }
+        images.push(GeneratedImage {
+            data,
+            mime_type: mime_type.clone(),
+            signature: None,
+            url: None,
+        });
+    }
+    if images.len() < wanted {
+        return Ok(None);
+    }
+    let plural = if wanted == 1 { "" } else { "s" };
+    tracing::info!("Cached: reusing {wanted} image{plural} saved earlier for request {request_id}");
+    Ok(Some(ImageResponse { images, failures: Vec::new(), usage: None }))
+}
@@ -5,17 +5,26 @@ use std::path::Path;
 use super::format::Cassette;
 use super::replayer::CassetteReplayer;
 
-/// Load a cassette file and create a replayer.
+/// Read and parse a cassette file.
 ///
 /// # Errors
 ///
 /// Returns an error if the file cannot be read or parsed.
-pub fn load_cassette(path: &Path) -> Result<CassetteReplayer, String> {
+pub fn read_cassette(path: &Path) -> Result<Cassette, String> {
     let content = std::fs::read_to_string(path)
         .map_err(|e| format!("Failed to read cassette file {}: {e}", path.display()))?;
-    let cassette: Cassette = serde_yaml::from_str(&content)
-        .map_err(|e| format!("Failed to parse cassette file {}: {e}", path.display()))?;
-    Ok(CassetteReplayer::new(&cassette))
+    serde_yaml::from_str(&content)
+        .map_err(|e| format!("Failed to parse cassette file {}: {e}", path.display()))
+}
+
+/// Load a cassette file and create a replayer.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or parsed.
+#[tracing::instrument(name = "cassette_read", level = "debug", fields(path = %path.display()))]
+pub fn load_cassette(path: &Path) -> Result<CassetteReplayer, String> {
+    Ok(CassetteReplayer::new(&read_cassette(path)?))
 }
 
 #[cfg(test)]
@@ -35,10 +44,12 @@ mod tests {
             name: "test".into(),
             recorded_at: Utc::now(),
             commit: "abc".into(),
+            invocation: None,
             interactions: vec![Interaction {
                 seq: 0,
                 port: "image_generator".into(),
                 method: "generate".into(),
+                request_id: None,
                 input: json!({}),
                 output: json!({"Ok": {"images": []}}),
             }],
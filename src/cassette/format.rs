@@ -1,8 +1,12 @@
 //! Cassette data structures for recording and replaying interactions.
 
+use std::collections::BTreeMap;
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
+use crate::error::ImageError;
+
 /// A single recorded interaction with an external port.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Interaction {
@@ -12,6 +16,9 @@ pub struct Interaction {
     pub port: String,
     /// Method name invoked on the port.
     pub method: String,
+    /// Content hash of the request, when the port input has one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
     /// Input data sent to the port.
     pub input: serde_json::Value,
     /// Output data returned from the port.
@@ -27,10 +34,47 @@ pub struct Cassette {
     pub recorded_at: DateTime<Utc>,
     /// Git commit hash at recording time.
     pub commit: String,
+    /// How the recording was produced, when the recorder knew it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub invocation: Option<Invocation>,
     /// Ordered list of interactions.
     pub interactions: Vec<Interaction>,
 }
 
+/// The command line behind a recording and the parameters it resolved to.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct Invocation {
+    /// Command-line arguments, with secrets replaced by `<redacted>`.
+    pub argv: Vec<String>,
+    /// Effective parameters after config defaults and alias resolution.
+    pub parameters: BTreeMap<String, String>,
+}
+
+/// Rebuild an error from the text a cassette recorded for it (`{"Err": "<message>"}`).
+///
+/// API errors keep their status and body, and blocked prompts their reason and flagged
+/// categories. Anything else becomes an API error with status 0.
+#[must_use]
+pub fn recorded_error(message: &str) -> ImageError {
+    let api_error = message.strip_prefix("API error (").and_then(|rest| {
+        let (status, body) = rest.split_once("): ")?;
+        Some((status.parse::<u16>().ok()?, body))
+    });
+    if let Some((status, body)) = api_error {
+        return ImageError::Api { status, message: body.to_string() };
+    }
+    if let Some(rest) = message.strip_prefix("Prompt blocked (reason: ") {
+        if let Some((reason, flagged)) = rest.split_once(')') {
+            let flagged = flagged
+                .strip_prefix(". Flagged: ")
+                .map(|list| list.split(", ").map(str::to_string).collect())
+                .unwrap_or_default();
+            return ImageError::PromptBlocked { reason: reason.to_string(), flagged };
+        }
+    }
+    ImageError::Api { status: 0, message: message.to_string() }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -41,11 +85,16 @@ mod tests {
             name: "test-cassette".into(),
             recorded_at: Utc::now(),
             commit: "abc123".into(),
+            invocation: Some(Invocation {
+                argv: vec!["imagen".into(), "a cat".into()],
+                parameters: BTreeMap::from([("model".into(), "gpt-image-1".into())]),
+            }),
             interactions: vec![
                 Interaction {
                     seq: 0,
                     port: "image_generator".into(),
                     method: "generate".into(),
+                    request_id: None,
                     input: json!({"prompt": "a cat"}),
                     output: json!({"Ok": {"images": []}}),
                 },
@@ -53,6 +102,7 @@ mod tests {
                     seq: 1,
                     port: "image_generator".into(),
                     method: "generate".into(),
+                    request_id: None,
                     input: json!({"prompt": "a dog"}),
                     output: json!({"Ok": {"images": []}}),
                 },
@@ -68,6 +118,22 @@ mod tests {
         assert_eq!(cassette, deserialized);
     }
 
+    #[test]
+    fn recorded_errors_keep_their_shape() {
+        let err = recorded_error("API error (429): {\"error\": \"slow down\"}");
+        assert!(
+            matches!(err, ImageError::Api { status: 429, ref message } if message == "{\"error\": \"slow down\"}")
+        );
+
+        let text = "Prompt blocked (reason: SAFETY). Flagged: HARM_CATEGORY_X (HIGH), Y";
+        let err = recorded_error(text);
+        assert_eq!(err.to_string(), text);
+        assert!(matches!(err, ImageError::PromptBlocked { ref flagged, .. } if flagged.len() == 2));
+
+        let err = recorded_error("Network error: connection refused");
+        assert_eq!(err.to_string(), "API error (0): Network error: connection refused");
+    }
+
     #[test]
     fn json_round_trip() {
         let cassette = sample_cassette();
@@ -0,0 +1,420 @@
+//! Export cassettes as HTTP mock-server stubs.
+//!
+//! Cassettes record port-level interactions (`ImageRequest` in, `ImageResponse` out). This
+//! module maps each interaction back onto the provider's HTTP endpoint and wire format so the
+//! recording can be served by `WireMock` (stub mappings) or Prism (an `OpenAPI` document with
+//! response examples).
+
+use base64::Engine;
+use serde_json::{json, Map, Value};
+
+use super::format::{recorded_error, Cassette, Interaction};
+use crate::adapters::live::aws::uri_encode;
+use crate::adapters::live::stability::endpoint_for_model;
+use crate::error::ImageError;
+use crate::model::{detect_provider, Provider};
+use crate::ports::image_generator::{ImageRequest, ImageResponse};
+
+/// Supported mock-server output formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MockFormat {
+    /// `WireMock` JSON stub mappings (`{"mappings": [...]}`).
+    WireMock,
+    /// `OpenAPI` 3 document with response examples, as served by Prism.
+    Prism,
+}
+
+impl MockFormat {
+    /// Parse a format name (`"wiremock"` or `"prism"`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unknown format names.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "wiremock" => Ok(Self::WireMock),
+            "prism" => Ok(Self::Prism),
+            other => Err(format!("Invalid mock server format '{other}'. Valid: wiremock, prism")),
+        }
+    }
+}
+
+/// Result of exporting a cassette.
+#[derive(Debug)]
+pub struct MockExport {
+    /// The generated stub document.
+    pub document: Value,
+    /// Interactions that could not be exported, with the reason.
+    pub skipped: Vec<String>,
+}
+
+/// An HTTP request/response pair reconstructed from one interaction.
+#[derive(Debug, PartialEq)]
+struct HttpStub {
+    seq: u64,
+    request_id: Option<String>,
+    path: String,
+    /// JSON path and expected value used to tell stubs on the same path apart.
+    body_match: Option<(&'static str, String)>,
+    response: StubResponse,
+}
+
+/// The recorded outcome, in wire form.
+#[derive(Debug, PartialEq)]
+enum StubResponse {
+    /// An HTTP response with a status code and body.
+    Body { status: u16, body: Value },
+    /// The connection failed before a response arrived.
+    ConnectionReset,
+}
+
+/// Export every `image_generator::generate` interaction in `cassette` to `format`.
+#[must_use]
+pub fn export(cassette: &Cassette, format: MockFormat) -> MockExport {
+    let mut stubs = Vec::new();
+    let mut skipped = Vec::new();
+    for interaction in &cassette.interactions {
+        match http_stub(interaction) {
+            Ok(stub) => stubs.push(stub),
+            Err(reason) => skipped.push(format!("seq {}: {reason}", interaction.seq)),
+        }
+    }
+
+    let document = match format {
+        MockFormat::WireMock => wiremock_document(&stubs),
+        MockFormat::Prism => openapi_document(&cassette.name, &stubs, &mut skipped),
+    };
+    MockExport { document, skipped }
+}
+
+/// Reconstruct the provider HTTP exchange for a single interaction.
+fn http_stub(interaction: &Interaction) -> Result<HttpStub, String> {
+    if interaction.port != "image_generator" || interaction.method != "generate" {
+        return Err(format!(
+            "unsupported interaction {}::{}",
+            interaction.port, interaction.method
+        ));
+    }
+    let request: ImageRequest = serde_json::from_value(interaction.input.clone())
+        .map_err(|e| format!("invalid request: {e}"))?;
+    let provider = detect_provider(&request.model)?;
+    let has_inputs = !request.input_images.is_empty();
+
+    let (path, body_match) = match provider {
+        Provider::Gemini => (
+            format!("/v1beta/models/{}:generateContent", request.model),
+            Some(("$.contents[0].parts[0].text", request.prompt.clone())),
+        ),
+        Provider::OpenAi if has_inputs => ("/v1/images/edits".to_string(), None),
+        Provider::OpenAi | Provider::Together => {
+            ("/v1/images/generations".to_string(), Some(("$.prompt", request.prompt.clone())))
+        }
+        Provider::AzureOpenAi => {
+            let deployment = request
+                .model
+                .strip_prefix("azure/")
+                .ok_or("Azure deployment comes from config, not the recorded model")?;
+            let operation = if has_inputs { "edits" } else { "generations" };
+            let path = format!("/openai/deployments/{deployment}/images/{operation}");
+            (path, (!has_inputs).then(|| ("$.prompt", request.prompt.clone())))
+        }
+        Provider::Stability => {
+            let (endpoint, _) = endpoint_for_model(&request.model);
+            (format!("/v2beta/stable-image/generate/{endpoint}"), None)
+        }
+        Provider::SdLocal => {
+            let endpoint = if has_inputs { "img2img" } else { "txt2img" };
+            (format!("/sdapi/v1/{endpoint}"), Some(("$.prompt", request.prompt.clone())))
+        }
+        Provider::Bedrock => {
+            let prompt_path = if request.model.starts_with("stability.") {
+                "$.prompt"
+            } else {
+                "$.textToImageParams.text"
+            };
+            let path = format!("/model/{}/invoke", uri_encode(&request.model));
+            (path, (!has_inputs).then(|| (prompt_path, request.prompt.clone())))
+        }
+        Provider::Replicate => {
+            return Err("Replicate predictions are asynchronous and cannot be stubbed".into());
+        }
+        Provider::Ideogram => {
+            return Err("Ideogram returns image URLs, which cannot be stubbed".into());
+        }
+    };
+
+    let response = if let Some(err) = interaction.output.get("Err") {
+        let message = err.as_str().unwrap_or_default();
+        if message.starts_with("No images in response") {
+            // The provider answered 200 with no image parts.
+            let empty = ImageResponse { images: Vec::new(), failures: Vec::new(), usage: None };
+            StubResponse::Body { status: 200, body: success_body(provider, &empty) }
+        } else if let Some(rest) = message.strip_prefix("Prompt blocked (reason: ") {
+            // Gemini reports a blocked prompt as a 200 with `promptFeedback`.
+            let reason = rest.split([')', ':']).next().unwrap_or_default();
+            let body = json!({"promptFeedback": {"blockReason": reason}});
+            StubResponse::Body { status: 200, body }
+        } else {
+            error_response(message)
+        }
+    } else {
+        let ok = interaction.output.get("Ok").cloned().unwrap_or(Value::Null);
+        let parsed: ImageResponse =
+            serde_json::from_value(ok).map_err(|e| format!("invalid response: {e}"))?;
+        StubResponse::Body { status: 200, body: success_body(provider, &parsed) }
+    };
+
+    Ok(HttpStub {
+        seq: interaction.seq,
+        request_id: interaction.request_id.clone(),
+        path,
+        body_match,
+        response,
+    })
+}
+
+/// Encode a successful response in the provider's wire format.
+fn success_body(provider: Provider, response: &ImageResponse) -> Value {
+    let b64 = |data: &[u8]| base64::engine::general_purpose::STANDARD.encode(data);
+    match provider {
+        Provider::Gemini => {
+            let parts: Vec<Value> = response
+                .images
+                .iter()
+                .map(|img| {
+                    json!({"inlineData": {"mimeType": img.mime_type, "data": b64(&img.data)}})
+                })
+                .collect();
+            json!({"candidates": [{"content": {"parts": parts}}]})
+        }
+        Provider::OpenAi | Provider::AzureOpenAi | Provider::Together => {
+            let data: Vec<Value> =
+                response.images.iter().map(|img| json!({"b64_json": b64(&img.data)})).collect();
+            json!({"data": data})
+        }
+        Provider::Stability => {
+            // Stability returns one image per request; stub the first.
+            let image = response.images.first().map(|img| b64(&img.data)).unwrap_or_default();
+            json!({"image": image, "finish_reason": "SUCCESS", "seed": 0})
+        }
+        Provider::SdLocal | Provider::Bedrock => {
+            let images: Vec<Value> =
+                response.images.iter().map(|img| Value::String(b64(&img.data))).collect();
+            json!({"images": images})
+        }
+        Provider::Replicate | Provider::Ideogram => Value::Null,
+    }
+}
+
+/// Turn a recorded error message back into an HTTP response.
+///
+/// API errors are recorded as `API error (<status>): <body>`; anything else (network
+/// failures, timeouts) becomes a dropped connection.
+fn error_response(message: &str) -> StubResponse {
+    match recorded_error(message) {
+        ImageError::Api { status, message: body } if status != 0 => {
+            let body = serde_json::from_str(&body).unwrap_or_else(|_| json!({"error": body}));
+            StubResponse::Body { status, body }
+        }
+        _ => StubResponse::ConnectionReset,
+    }
+}
+
+/// Build a `WireMock` mappings document.
+fn wiremock_document(stubs: &[HttpStub]) -> Value {
+    let mappings: Vec<Value> = stubs
+        .iter()
+        .map(|stub| {
+            let mut request = json!({"method": "POST", "urlPath": stub.path});
+            if let Some((expression, ref expected)) = stub.body_match {
+                request["bodyPatterns"] = json!([{
+                    "matchesJsonPath": {"expression": expression, "equalTo": expected}
+                }]);
+            }
+            let response = match stub.response {
+                StubResponse::Body { status, ref body } => json!({
+                    "status": status,
+                    "headers": {"Content-Type": "application/json"},
+                    "jsonBody": body,
+                }),
+                StubResponse::ConnectionReset => json!({"fault": "CONNECTION_RESET_BY_PEER"}),
+            };
+            json!({
+                "name": format!("imagen seq {}", stub.seq),
+                "request": request,
+                "response": response,
+                "metadata": {"imagen": {"seq": stub.seq, "request_id": stub.request_id}},
+            })
+        })
+        .collect();
+    json!({"mappings": mappings})
+}
+
+/// Build an `OpenAPI` 3 document whose response examples Prism serves as mocks.
+///
+/// Dropped connections have no `OpenAPI` representation and are reported as skipped.
+fn openapi_document(name: &str, stubs: &[HttpStub], skipped: &mut Vec<String>) -> Value {
+    let mut paths = Map::new();
+    for stub in stubs {
+        let StubResponse::Body { status, ref body } = stub.response else {
+            skipped.push(format!("seq {}: connection failures cannot be expressed", stub.seq));
+            continue;
+        };
+        let examples = &mut paths
+            .entry(stub.path.clone())
+            .or_insert_with(|| json!({"post": {"responses": {}}}))["post"]["responses"]
+            [status.to_string()];
+        if examples.is_null() {
+            *examples = json!({
+                "description": format!("Recorded HTTP {status}"),
+                "content": {"application/json": {"examples": {}}},
+            });
+        }
+        examples["content"]["application/json"]["examples"][format!("seq-{}", stub.seq)] =
+            json!({"value": body});
+    }
+    json!({
+        "openapi": "3.0.3",
+        "info": {"title": format!("imagen cassette: {name}"), "version": "1.0.0"},
+        "paths": paths,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn interaction(seq: u64, model: &str, output: Value) -> Interaction {
+        Interaction {
+            seq,
+            port: "image_generator".into(),
+            method: "generate".into(),
+            request_id: Some("0123456789abcdef".into()),
+            input: json!({
+                "model": model,
+                "prompt": "a cat",
+                "aspect_ratio": "1:1",
+                "size": "1K",
+                "quality": "auto",
+                "format": "png",
+                "count": 1,
+            }),
+            output,
+        }
+    }
+
+    fn cassette(interactions: Vec<Interaction>) -> Cassette {
+        Cassette {
+            name: "mock".into(),
+            recorded_at: Utc::now(),
+            commit: "abc123".into(),
+            invocation: None,
+            interactions,
+        }
+    }
+
+    fn ok_png() -> Value {
+        json!({"Ok": {"images": [{"data": "iVBORw==", "mime_type": "image/png"}]}})
+    }
+
+    #[test]
+    fn parse_format_names() {
+        assert_eq!(MockFormat::parse("wiremock").unwrap(), MockFormat::WireMock);
+        assert_eq!(MockFormat::parse("prism").unwrap(), MockFormat::Prism);
+        assert!(MockFormat::parse("mockserver").is_err());
+    }
+
+    #[test]
+    fn gemini_stub_matches_prompt_and_encodes_inline_data() {
+        let stub = http_stub(&interaction(0, "gemini-3-pro-image-preview", ok_png())).unwrap();
+        assert_eq!(stub.path, "/v1beta/models/gemini-3-pro-image-preview:generateContent");
+        assert_eq!(stub.body_match, Some(("$.contents[0].parts[0].text", "a cat".to_string())));
+        let StubResponse::Body { status, body } = stub.response else { panic!("expected body") };
+        assert_eq!(status, 200);
+        assert_eq!(body["candidates"][0]["content"]["parts"][0]["inlineData"]["data"], "iVBORw==");
+    }
+
+    #[test]
+    fn openai_stub_uses_b64_json() {
+        let stub = http_stub(&interaction(0, "gpt-image-1", ok_png())).unwrap();
+        assert_eq!(stub.path, "/v1/images/generations");
+        let StubResponse::Body { body, .. } = stub.response else { panic!("expected body") };
+        assert_eq!(body["data"][0]["b64_json"], "iVBORw==");
+    }
+
+    #[test]
+    fn api_error_keeps_status_and_json_body() {
+        let output = json!({"Err": "API error (429): {\"error\":{\"message\":\"slow down\"}}"});
+        let stub = http_stub(&interaction(0, "gpt-image-1", output)).unwrap();
+        assert_eq!(
+            stub.response,
+            StubResponse::Body { status: 429, body: json!({"error": {"message": "slow down"}}) }
+        );
+    }
+
+    #[test]
+    fn network_error_becomes_fault() {
+        let output = json!({"Err": "Network error: connection refused"});
+        let stub = http_stub(&interaction(0, "gpt-image-1", output)).unwrap();
+        assert_eq!(stub.response, StubResponse::ConnectionReset);
+    }
+
+    #[test]
+    fn blocked_prompt_becomes_prompt_feedback() {
+        let output =
+            json!({"Err": "Prompt blocked (reason: SAFETY). Flagged: HARM_CATEGORY_X (HIGH)"});
+        let stub = http_stub(&interaction(0, "gemini-3-pro-image-preview", output)).unwrap();
+        assert_eq!(
+            stub.response,
+            StubResponse::Body {
+                status: 200,
+                body: json!({"promptFeedback": {"blockReason": "SAFETY"}})
+            }
+        );
+    }
+
+    #[test]
+    fn empty_response_becomes_empty_success() {
+        let output = json!({"Err": "No images in response (finish reason: none). Body: {}"});
+        let stub = http_stub(&interaction(0, "gpt-image-1", output)).unwrap();
+        assert_eq!(stub.response, StubResponse::Body { status: 200, body: json!({"data": []}) });
+    }
+
+    #[test]
+    fn replicate_is_skipped() {
+        let c = cassette(vec![interaction(0, "replicate/black-forest-labs/flux", ok_png())]);
+        let export = export(&c, MockFormat::WireMock);
+        assert_eq!(export.document["mappings"].as_array().unwrap().len(), 0);
+        assert_eq!(export.skipped.len(), 1);
+    }
+
+    #[test]
+    fn wiremock_document_shape() {
+        let c = cassette(vec![interaction(0, "gpt-image-1", ok_png())]);
+        let export = export(&c, MockFormat::WireMock);
+        let mapping = &export.document["mappings"][0];
+        assert_eq!(mapping["request"]["urlPath"], "/v1/images/generations");
+        assert_eq!(
+            mapping["request"]["bodyPatterns"][0]["matchesJsonPath"]["expression"],
+            "$.prompt"
+        );
+        assert_eq!(mapping["response"]["status"], 200);
+        assert_eq!(mapping["metadata"]["imagen"]["request_id"], "0123456789abcdef");
+    }
+
+    #[test]
+    fn prism_document_groups_examples_by_path_and_status() {
+        let c = cassette(vec![
+            interaction(0, "gpt-image-1", ok_png()),
+            interaction(1, "gpt-image-1", ok_png()),
+            interaction(2, "gpt-image-1", json!({"Err": "Network error: reset"})),
+        ]);
+        let export = export(&c, MockFormat::Prism);
+        let examples = &export.document["paths"]["/v1/images/generations"]["post"]["responses"]
+            ["200"]["content"]["application/json"]["examples"];
+        assert!(examples["seq-0"]["value"]["data"].is_array());
+        assert!(examples["seq-1"]["value"]["data"].is_array());
+        assert_eq!(export.skipped.len(), 1);
+    }
+}
@@ -2,5 +2,7 @@
 
 pub mod config;
 pub mod format;
+pub mod mock_server;
 pub mod recorder;
 pub mod replayer;
+pub mod synth;
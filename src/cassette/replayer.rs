@@ -79,6 +79,7 @@ mod tests {
             name: "test".into(),
             recorded_at: Utc::now(),
             commit: "abc".into(),
+            invocation: None,
             interactions,
         }
     }
@@ -90,6 +91,7 @@ mod tests {
                 seq: 0,
                 port: "image_generator".into(),
                 method: "generate".into(),
+                request_id: None,
                 input: json!({"prompt": "first"}),
                 output: json!({"Ok": {"images": []}}),
             },
@@ -97,6 +99,7 @@ mod tests {
                 seq: 1,
                 port: "image_generator".into(),
                 method: "generate".into(),
+                request_id: None,
                 input: json!({"prompt": "second"}),
                 output: json!({"Ok": {"images": []}}),
             },
@@ -118,6 +121,7 @@ mod tests {
             seq: 0,
             port: "image_generator".into(),
             method: "generate".into(),
+            request_id: None,
             input: json!({}),
             output: json!({}),
         }]);
@@ -0,0 +1,374 @@
+//! Synthetic cassettes that reproduce provider failures.
+//!
+//! Each scenario becomes a one-interaction cassette whose recorded error is what the live
+//! adapter reports for that failure, using the provider's own status codes and error
+//! bodies. Replaying it (or exporting it with `to-mock-server`) drives the same error
+//! paths a real outage would, without one ever having happened.
+
+use chrono::Utc;
+use serde_json::json;
+
+use super::format::{Cassette, Interaction};
+use crate::error::ImageError;
+use crate::model::Provider;
+use crate::ports::ImageRequest;
+
+/// A provider failure to reproduce.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    /// The request was throttled (HTTP 429).
+    RateLimit,
+    /// The API key or token was rejected.
+    AuthFailure,
+    /// The provider's content filter refused the prompt.
+    ContentBlock,
+    /// The provider answered 200 with a body that is not valid JSON.
+    MalformedJson,
+}
+
+impl Scenario {
+    /// Parse an `--error` value: `429`/`rate-limit`, `401`/`auth`, `content-block`, or
+    /// `malformed-json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unknown scenario names.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "429" | "rate-limit" => Ok(Self::RateLimit),
+            "401" | "403" | "auth" => Ok(Self::AuthFailure),
+            "content-block" => Ok(Self::ContentBlock),
+            "malformed-json" => Ok(Self::MalformedJson),
+            other => Err(format!(
+                "Unknown error scenario '{other}'. Valid: 429 (rate-limit), 401 (auth), \
+                 content-block, malformed-json"
+            )),
+        }
+    }
+
+    /// Short name used in the cassette's `name` field.
+    fn slug(self) -> &'static str {
+        match self {
+            Self::RateLimit => "rate-limit",
+            Self::AuthFailure => "auth",
+            Self::ContentBlock => "content-block",
+            Self::MalformedJson => "malformed-json",
+        }
+    }
+}
+
+/// Build a cassette in which `request` fails with `scenario`, as `provider` reports it.
+///
+/// # Errors
+///
+/// Returns an error when the provider cannot fail that way (a local SD web UI has no
+/// rate limit or content filter).
+///
+/// # Panics
+///
+/// Panics if `request` cannot be serialized into the cassette.
+pub fn synthesize(
+    scenario: Scenario,
+    provider: Provider,
+    request: &ImageRequest,
+) -> Result<Cassette, String> {
+    let error = provider_error(scenario, provider)?;
+    let provider_name = format!("{provider:?}").to_lowercase();
+    Ok(Cassette {
+        name: format!("synthetic-{provider_name}-{}", scenario.slug()),
+        recorded_at: Utc::now(),
+        commit: "synthetic".into(),
+        invocation: None,
+        interactions: vec![Interaction {
+            seq: 0,
+            port: "image_generator".into(),
+            method: "generate".into(),
+            request_id: Some(request.request_id()),
+            input: serde_json::to_value(request).expect("ImageRequest is always serializable"),
+            output: json!({ "Err": error.to_string() }),
+        }],
+    })
+}
+
+/// The error the provider's live adapter returns for `scenario`.
+fn provider_error(scenario: Scenario, provider: Provider) -> Result<ImageError, String> {
+    let error = match scenario {
+        Scenario::RateLimit => rate_limit(provider),
+        Scenario::AuthFailure => Some(auth_failure(provider)),
+        Scenario::ContentBlock => content_block(provider),
+        Scenario::MalformedJson => {
+            let e = serde_json::from_str::<serde_json::Value>(r#"{"data": [{"b64_json": "iVBO"#)
+                .expect_err("truncated JSON never parses");
+            let what = if provider == Provider::Replicate { "prediction" } else { "response" };
+            Some(ImageError::Api { status: 200, message: format!("Failed to parse {what}: {e}") })
+        }
+    };
+    error.ok_or_else(|| {
+        format!("A local SD web UI cannot fail with {}; try 401 or malformed-json", scenario.slug())
+    })
+}
+
+/// An API error with a JSON body, as the live adapters record it.
+fn api(status: u16, body: &serde_json::Value) -> ImageError {
+    ImageError::Api { status, message: body.to_string() }
+}
+
+/// HTTP 429 in the provider's error format; `None` for a local SD web UI.
+fn rate_limit(provider: Provider) -> Option<ImageError> {
+    let error = match provider {
+        Provider::Gemini => api(
+            429,
+            &json!({"error": {
+                "code": 429,
+                "message": "Resource has been exhausted (e.g. check quota).",
+                "status": "RESOURCE_EXHAUSTED"
+            }}),
+        ),
+        Provider::OpenAi => api(
+            429,
+            &json!({"error": {
+                "message": "Rate limit reached for images per minute. Please try again in 12s.",
+                "type": "requests",
+                "code": "rate_limit_exceeded"
+            }}),
+        ),
+        Provider::AzureOpenAi => api(
+            429,
+            &json!({"error": {
+                "code": "429",
+                "message": "Requests to the Images_Generations API have exceeded the rate \
+                            limit of your current tier. Please retry after 12 seconds."
+            }}),
+        ),
+        Provider::Stability => api(
+            429,
+            &json!({
+                "id": "synthetic",
+                "name": "rate_limit_exceeded",
+                "errors": ["You have exceeded the rate limit of 150 requests within a 10 \
+                            second period."]
+            }),
+        ),
+        Provider::Replicate => api(
+            429,
+            &json!({
+                "title": "Request was throttled.",
+                "detail": "Request was throttled. Expected available in 12 seconds.",
+                "status": 429
+            }),
+        ),
+        Provider::Bedrock => {
+            api(429, &json!({"message": "Too many requests, please wait before trying again."}))
+        }
+        Provider::Together => api(
+            429,
+            &json!({"error": {
+                "message": "You have exceeded the rate limit for this model. Please try \
+                            again later.",
+                "type": "rate_limit",
+                "code": "rate_limit_exceeded"
+            }}),
+        ),
+        Provider::Ideogram => api(429, &json!({"detail": "Too many requests. Please slow down."})),
+        Provider::SdLocal => return None,
+    };
+    Some(error)
+}
+
+/// A rejected API key or token in the provider's error format.
+fn auth_failure(provider: Provider) -> ImageError {
+    match provider {
+        Provider::Gemini => api(
+            400,
+            &json!({"error": {
+                "code": 400,
+                "message": "API key not valid. Please pass a valid API key.",
+                "status": "INVALID_ARGUMENT"
+            }}),
+        ),
+        Provider::OpenAi => api(
+            401,
+            &json!({"error": {
+                "message": "Incorrect API key provided: sk-synth***. You can find your API key \
+                            at https://platform.openai.com/account/api-keys.",
+                "type": "invalid_request_error",
+                "code": "invalid_api_key"
+            }}),
+        ),
+        Provider::AzureOpenAi => api(
+            401,
+            &json!({"error": {
+                "code": "401",
+                "message": "Access denied due to invalid subscription key or wrong API \
+                            endpoint."
+            }}),
+        ),
+        Provider::Stability => api(
+            401,
+            &json!({
+                "id": "synthetic",
+                "name": "unauthorized",
+                "errors": ["authentication failed"]
+            }),
+        ),
+        Provider::Replicate => api(
+            401,
+            &json!({
+                "title": "Unauthenticated",
+                "detail": "You did not pass a valid authentication token",
+                "status": 401
+            }),
+        ),
+        Provider::Bedrock => {
+            api(403, &json!({"message": "The security token included in the request is invalid."}))
+        }
+        Provider::Together => api(
+            401,
+            &json!({"error": {
+                "message": "Invalid API key provided.",
+                "type": "invalid_request_error",
+                "code": "invalid_api_key"
+            }}),
+        ),
+        Provider::Ideogram => api(401, &json!({"detail": "Invalid API key."})),
+        Provider::SdLocal => api(401, &json!({"detail": "Incorrect credentials."})),
+    }
+}
+
+/// A prompt refused by the provider's content filter; `None` for a local SD web UI.
+fn content_block(provider: Provider) -> Option<ImageError> {
+    let error = match provider {
+        Provider::Gemini => ImageError::PromptBlocked {
+            reason: "SAFETY".into(),
+            flagged: vec!["HARM_CATEGORY_DANGEROUS_CONTENT (HIGH)".into()],
+        },
+        Provider::OpenAi | Provider::AzureOpenAi => api(
+            400,
+            &json!({"error": {
+                "message": "Your request was rejected as a result of our safety system. Your \
+                            request may contain content that is not allowed by our safety system.",
+                "type": "image_generation_user_error",
+                "code": "moderation_blocked"
+            }}),
+        ),
+        Provider::Stability => api(
+            403,
+            &json!({
+                "id": "synthetic",
+                "name": "content_moderation",
+                "errors": ["Your request was flagged by our content moderation system, as a \
+                            result your request was denied and you were not charged."]
+            }),
+        ),
+        Provider::Replicate => ImageError::Api {
+            status: 200,
+            message: "Prediction synthetic failed: NSFW content detected. Try running it \
+                      again, or try a different prompt."
+                .into(),
+        },
+        Provider::Bedrock => api(
+            400,
+            &json!({"message": "This request has been blocked by our content filters. Please \
+                               adjust your text prompt to submit a new request."}),
+        ),
+        Provider::Together => api(
+            400,
+            &json!({"error": {
+                "message": "Your prompt was flagged by the content filter.",
+                "type": "invalid_request_error",
+                "code": "content_policy_violation"
+            }}),
+        ),
+        Provider::Ideogram => ImageError::Api {
+            status: 200,
+            message: "No usable images: withheld by Ideogram's safety check".into(),
+        },
+        Provider::SdLocal => return None,
+    };
+    Some(error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cassette::format::recorded_error;
+
+    fn request(model: &str) -> ImageRequest {
+        ImageRequest { format: "png".into(), ..ImageRequest::sample(model, "a cat") }
+    }
+
+    fn recorded(cassette: &Cassette) -> ImageError {
+        let message = cassette.interactions[0].output["Err"].as_str().unwrap();
+        recorded_error(message)
+    }
+
+    #[test]
+    fn parse_scenario_names() {
+        assert_eq!(Scenario::parse("429"), Ok(Scenario::RateLimit));
+        assert_eq!(Scenario::parse("auth"), Ok(Scenario::AuthFailure));
+        assert_eq!(Scenario::parse("content-block"), Ok(Scenario::ContentBlock));
+        assert_eq!(Scenario::parse("malformed-json"), Ok(Scenario::MalformedJson));
+        assert!(Scenario::parse("500").unwrap_err().contains("Valid: 429"));
+    }
+
+    #[test]
+    fn rate_limit_keeps_status_and_body() {
+        let cassette =
+            synthesize(Scenario::RateLimit, Provider::OpenAi, &request("gpt-image-1")).unwrap();
+        assert_eq!(cassette.name, "synthetic-openai-rate-limit");
+        assert_eq!(cassette.interactions[0].request_id, Some(request("gpt-image-1").request_id()));
+        let err = recorded(&cassette);
+        assert!(err.is_retryable());
+        let ImageError::Api { status: 429, message } = err else { panic!("got {err:?}") };
+        let body: serde_json::Value = serde_json::from_str(&message).unwrap();
+        assert_eq!(body["error"]["code"], "rate_limit_exceeded");
+    }
+
+    #[test]
+    fn gemini_content_block_is_a_blocked_prompt() {
+        let cassette = synthesize(
+            Scenario::ContentBlock,
+            Provider::Gemini,
+            &request("gemini-3.1-flash-image-preview"),
+        )
+        .unwrap();
+        assert!(matches!(recorded(&cassette), ImageError::PromptBlocked { .. }));
+    }
+
+    #[test]
+    fn every_content_block_is_a_refusal() {
+        let providers = [
+            Provider::Gemini,
+            Provider::OpenAi,
+            Provider::AzureOpenAi,
+            Provider::Stability,
+            Provider::Replicate,
+            Provider::Bedrock,
+            Provider::Together,
+            Provider::Ideogram,
+        ];
+        for provider in providers {
+            let err = content_block(provider).unwrap();
+            assert!(err.is_refusal(), "{provider:?}: {err}");
+            assert!(recorded_error(&err.to_string()).is_refusal(), "{provider:?} replayed");
+        }
+    }
+
+    #[test]
+    fn malformed_json_is_a_parse_failure() {
+        let cassette =
+            synthesize(Scenario::MalformedJson, Provider::Stability, &request("sd3.5-large"))
+                .unwrap();
+        let err = recorded(&cassette);
+        assert!(!err.is_retryable());
+        assert!(err.to_string().starts_with("API error (200): Failed to parse response: EOF"));
+    }
+
+    #[test]
+    fn sd_local_cannot_be_rate_limited() {
+        assert!(synthesize(Scenario::RateLimit, Provider::SdLocal, &request("sd-local"))
+            .unwrap_err()
+            .contains("cannot fail with rate-limit"));
+        assert!(synthesize(Scenario::AuthFailure, Provider::SdLocal, &request("sd-local")).is_ok());
+    }
+}
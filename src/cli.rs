@@ -1,27 +1,70 @@
 //! CLI argument parsing with clap.
 
-use clap::Parser;
+use std::collections::HashMap;
+use std::io::IsTerminal as _;
 
-/// AI image generation CLI - unified interface for Gemini and `OpenAI`.
+use clap::{ArgAction, Args, Parser, Subcommand};
+
+/// AI image generation CLI - unified interface for Gemini, `OpenAI`, Stability AI, and Replicate.
 #[derive(Parser, Debug)]
-#[command(name = "imagen", version, about)]
+#[command(name = "imagen", version, about, args_conflicts_with_subcommands = true)]
 pub struct Cli {
-    /// Text prompt describing the desired image.
+    /// Generation options, used when no subcommand is given.
+    #[command(flatten)]
+    pub args: GenerateArgs,
+
+    /// Utility subcommand; without one, imagen generates an image from the prompt.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Prompt and generation options shared by plain `imagen` and `imagen edit`.
+#[derive(Args, Debug)]
+#[allow(clippy::struct_excessive_bools)] // Independent on/off flags.
+pub struct GenerateArgs {
+    /// Text prompt describing the desired image, or `@name` for a prompt from the config's
+    /// `[prompts]`, optionally followed by more text. `-` reads the prompt from stdin, as
+    /// does leaving it out when stdin is piped.
     #[arg(conflicts_with = "prompt_file")]
     pub prompt: Option<String>,
 
-    /// Path to a file containing the prompt text.
+    /// Path to a file containing the prompt text (`-` for stdin).
     #[arg(short = 'p', long, conflicts_with = "prompt")]
     pub prompt_file: Option<String>,
 
+    /// Style preset from the config's `[styles]` to add to the prompt (repeatable or a comma
+    /// list, applied in order).
+    #[arg(long = "style", value_name = "NAME", value_delimiter = ',')]
+    pub styles: Vec<String>,
+
+    /// Run again whenever the prompt file changes, saving each run as a new `-v<N>` version.
+    #[arg(long)]
+    pub watch: bool,
+
     /// Model name or short alias.
     #[arg(short, long, default_value = "nano-banana")]
     pub model: String,
 
-    /// Aspect ratio (e.g., 1:1, 16:9, 9:16).
+    /// Models to try in order when a request to --model still fails after its retries with
+    /// a transient error (an outage, rate limit, or timeout), e.g. `gpt-1,flux`.
+    #[arg(long, value_name = "MODELS", value_delimiter = ',')]
+    pub fallback: Vec<String>,
+
+    /// When the provider refuses the prompt on safety grounds, send it once more: `soften`
+    /// has the --enhance-model text model tone the prompt down, and a model name sends the
+    /// prompt unchanged to that model.
+    #[arg(long, value_name = "soften|MODEL")]
+    pub on_refusal: Option<String>,
+
+    /// Aspect ratio (e.g., 1:1, 16:9, 2.39:1); a comma list generates one image set per
+    /// ratio. Ratios the provider lacks use the nearest one it has.
     #[arg(short, long, default_value = "1:1")]
     pub aspect_ratio: String,
 
+    /// Fail on an aspect ratio the provider does not support, instead of using the nearest.
+    #[arg(long)]
+    pub strict_ratio: bool,
+
     /// Image size: 1K, 2K, 4K.
     #[arg(short, long, default_value = "1K")]
     pub size: String,
@@ -30,7 +73,7 @@ pub struct Cli {
     #[arg(short, long, default_value = "auto")]
     pub quality: String,
 
-    /// Output format: jpeg, png, webp.
+    /// Output format: jpeg, png, webp, tiff, bmp, gif, avif.
     #[arg(short, long, default_value = "jpeg")]
     pub format: String,
 
@@ -38,50 +81,767 @@ pub struct Cli {
     #[arg(short, long)]
     pub output: Option<String>,
 
+    /// Directory for auto-generated filenames, created if missing (and for batch jobs'
+    /// relative outputs).
+    #[arg(long, value_name = "DIR", conflicts_with = "output")]
+    pub output_dir: Option<String>,
+
+    /// Auto-generated filename slug: prompt, hash, counter, ulid [default: prompt].
+    #[arg(long)]
+    pub slug: Option<String>,
+
+    /// Auto-generated filename template, e.g. '{date}/{model}/{prompt:40}-{seed}.{ext}'
+    /// (replaces --slug; see README for placeholders, including the image number).
+    #[arg(long, value_name = "TEMPLATE", conflicts_with = "slug")]
+    pub name_template: Option<String>,
+
+    /// Overwrite output files that already exist.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Refuse to replace an existing output file; with --no-clobber=false, save under the
+    /// next free name instead (cat-2.png, cat-3.png, ...).
+    #[arg(
+        long,
+        value_name = "BOOL",
+        action = ArgAction::Set,
+        num_args = 0..=1,
+        require_equals = true,
+        default_value_t = true,
+        default_missing_value = "true"
+    )]
+    pub no_clobber: bool,
+
+    /// Write a JSON sidecar next to each image (cat.jpg.json) with the request, model,
+    /// timings, cost, and provider response metadata.
+    #[arg(long)]
+    pub sidecar: bool,
+
+    /// Strip all metadata from saved images: whatever the provider embedded (EXIF, XMP,
+    /// C2PA, color profile) and imagen's own parameters chunk and sidecar.
+    #[arg(long, conflicts_with = "sidecar")]
+    pub strip: bool,
+
+    /// Also write a JPEG thumbnail of each image (cat.thumb.jpg) that fits in a SIZE x SIZE
+    /// square, for galleries and quick review.
+    #[arg(long, value_name = "SIZE", value_parser = clap::value_parser!(u32).range(1..))]
+    pub thumbnail: Option<u32>,
+
+    /// Also write one labeled grid of every image of the run (cat-sheet.jpg), for runs
+    /// with -n above 1 or an aspect-ratio sweep.
+    #[arg(long)]
+    pub contact_sheet: bool,
+
     /// Number of images to generate.
     #[arg(short = 'n', long, default_value = "1")]
     pub count: u32,
 
+    /// Send a --count above the provider's per-request limit as several requests
+    /// instead of failing.
+    #[arg(long)]
+    pub split: bool,
+
+    /// Seed for reproducible results (not `OpenAI`); each extra image of --count gets the
+    /// next seed where images are rendered one at a time.
+    #[arg(long)]
+    pub seed: Option<u32>,
+
     /// Config file path override.
     #[arg(long)]
     pub config: Option<String>,
 
+    /// Named profile from the config's `[profiles]` (or `IMAGEN_PROFILE`).
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
     /// Thinking level (Gemini only): none, minimal, low, medium, high.
     #[arg(short, long)]
     pub thinking: Option<String>,
 
-    /// Input image file paths for image editing (repeatable).
+    /// Input image for editing (repeatable, order preserved). Use `ROLE=PATH` to label it.
     #[arg(short, long = "input", num_args = 1)]
     pub input: Vec<String>,
 
-    /// Background mode: auto, transparent (`OpenAI` only).
+    /// Style/composition reference image (repeatable, Gemini only); guides the result
+    /// without being edited.
+    #[arg(long = "ref", value_name = "PATH", num_args = 1)]
+    pub reference: Vec<String>,
+
+    /// Base URL of an `OpenAI`-compatible images API, e.g. `http://localhost:4000/v1`.
+    #[arg(long, value_name = "URL")]
+    pub api_base: Option<String>,
+
+    /// Seconds allowed for each HTTP request before it is abandoned (and retried).
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub timeout: Option<u64>,
+
+    /// Background mode: auto, transparent, opaque (`OpenAI` only).
     #[arg(short, long)]
     pub background: Option<String>,
 
-    /// Verbose output.
+    /// Magic prompt expansion: auto, on, off (Ideogram only).
+    #[arg(long, value_name = "MODE")]
+    pub magic_prompt: Option<String>,
+
+    /// Style type: auto, general, realistic, design, fiction (Ideogram only).
+    #[arg(long, value_name = "STYLE")]
+    pub style_type: Option<String>,
+
+    /// Content moderation: auto, low (`OpenAI` only).
+    #[arg(long, value_name = "LEVEL")]
+    pub moderation: Option<String>,
+
+    /// How much Gemini's safety filters block: none, few, some, most (Gemini only).
+    #[arg(long, value_name = "LEVEL")]
+    pub safety: Option<String>,
+
+    /// Encoder quality for jpeg, webp, and avif output, 0-100: lower makes smaller files.
+    /// Sent to `OpenAI` as `output_compression`; other providers' images are re-encoded.
+    #[arg(long, value_name = "0-100", value_parser = clap::value_parser!(u8).range(0..=100))]
+    pub compression: Option<u8>,
+
+    /// Bits per channel of the saved image: 8, 16 (16 needs png or tiff).
+    #[arg(long, value_name = "BITS")]
+    pub bit_depth: Option<u8>,
+
+    /// Save the image in grayscale.
+    #[arg(long)]
+    pub grayscale: bool,
+
+    /// Post-processing steps for each saved image, in order: resize=WxH, crop=center or
+    /// crop=smart (after a resize), pad=WxH[:RRGGBB], rotate=90/180/270.
+    #[arg(long, value_name = "STEPS")]
+    pub post: Option<String>,
+
+    /// Stamp this text over each saved image.
+    #[arg(long, value_name = "TEXT", conflicts_with = "watermark_image")]
+    pub watermark: Option<String>,
+
+    /// Stamp this logo over each saved image, scaled down to at most a quarter of it.
+    #[arg(long, value_name = "PATH")]
+    pub watermark_image: Option<String>,
+
+    /// Where the watermark goes: top-left, top, top-right, left, center, right,
+    /// bottom-left, bottom, bottom-right.
+    #[arg(long, value_name = "POSITION", default_value = "bottom-right",
+          value_parser = crate::watermark::POSITIONS)]
+    pub watermark_position: String,
+
+    /// How opaque the watermark is, from 0.0 to 1.0.
+    #[arg(long, value_name = "OPACITY", default_value_t = 0.5)]
+    pub watermark_opacity: f32,
+
+    /// Exact width in pixels: the nearest ratio and size are generated, then resized and
+    /// center-cropped to `--width` x `--height`.
+    #[arg(long, value_name = "PX", requires = "height", conflicts_with_all = ["aspect_ratio", "size"],
+          value_parser = clap::value_parser!(u32).range(16..=16384))]
+    pub width: Option<u32>,
+
+    /// Exact height in pixels (with `--width`).
+    #[arg(long, value_name = "PX", requires = "width",
+          value_parser = clap::value_parser!(u32).range(16..=16384))]
+    pub height: Option<u32>,
+
+    /// Have the provider return image URLs and stream each download straight to disk
+    /// (Replicate, Together, Ideogram).
+    #[arg(long)]
+    pub stream_download: bool,
+
+    /// Fail instead of shrinking input images that exceed the provider's size limits.
+    #[arg(long)]
+    pub no_auto_downscale: bool,
+
+    /// Write progressive previews to `.partial/` next to the output while the image
+    /// renders (`OpenAI` only).
+    #[arg(long)]
+    pub stream: bool,
+
+    /// Open the saved images in the default viewer, or only the contact sheet when one
+    /// is written.
+    #[arg(long)]
+    pub open: bool,
+
+    /// Draw each saved image in the terminal: kitty, iterm, sixel, blocks, or auto to
+    /// detect the terminal (the default).
+    #[arg(long, value_name = "PROTOCOL", num_args = 0..=1, require_equals = true,
+          default_missing_value = "auto",
+          value_parser = ["auto", "kitty", "iterm", "sixel", "blocks"])]
+    pub preview: Option<String>,
+
+    /// Keep the run's images only if every one of them succeeds; otherwise discard all.
+    #[arg(long)]
+    pub transactional: bool,
+
+    /// Reuse images saved earlier for an identical request instead of calling the API.
+    #[arg(long)]
+    pub cache: bool,
+
+    /// Have a text model expand the prompt into a detailed one before generating.
+    #[arg(long)]
+    pub enhance: bool,
+
+    /// Text model for --enhance (e.g., gemini-2.5-flash, gpt-4.1-mini).
+    #[arg(long, value_name = "MODEL", requires = "enhance")]
+    pub enhance_model: Option<String>,
+
+    /// Show the final prompt and settings, and ask y/N before sending the request.
+    #[arg(long)]
+    pub confirm: bool,
+
+    /// Print the projected cost of the run and exit without calling the API.
+    #[arg(long)]
+    pub estimate: bool,
+
+    /// Refuse to run if the projected cost in USD exceeds this budget.
+    #[arg(long, value_name = "USD")]
+    pub max_cost: Option<f64>,
+
+    /// Print the request ID (a stable hash of the request) to stdout.
+    #[arg(long)]
+    pub id: bool,
+
+    /// Print one JSON object describing the run to stdout: files, settings, timing, cost,
+    /// and warnings. Messages stay on stderr.
+    #[arg(long, conflicts_with_all = ["id", "watch"])]
+    pub json: bool,
+
+    /// Stream progress events to stdout as they happen: ndjson (one JSON object per line).
+    #[arg(long, value_name = "FORMAT", value_parser = ["ndjson"],
+          conflicts_with_all = ["json", "id", "estimate"])]
+    pub events: Option<String>,
+
+    /// Where images come from: live, record, record:<path>, or replay:<path>.
+    ///
+    /// Defaults to live; the deprecated `IMAGEN_RECORD` / `IMAGEN_REPLAY` variables are
+    /// still honored when it is not given.
+    #[arg(long, value_name = "MODE")]
+    pub mode: Option<String>,
+
+    /// Forbid all network access (only cassette replay and localhost sd-local are allowed).
+    #[arg(long)]
+    pub offline: bool,
+
+    /// More detail on stderr: `-v` for debug messages, `-vv` to also time HTTP calls,
+    /// conversion, and cassette IO, `-vvv` to include the libraries underneath.
+    #[arg(short, long, action = ArgAction::Count)]
+    pub verbose: u8,
+
+    /// How stderr messages are written: `text`, or `json` for one object per line.
+    #[arg(long, value_name = "FORMAT", value_parser = ["text", "json"], default_value = "text")]
+    pub log_format: String,
+}
+
+/// Subcommands.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Modify existing images: `imagen edit -i photo.png "make it nighttime"` (Gemini, `OpenAI`).
+    Edit(Box<GenerateArgs>),
+
+    /// Generate an image set per prompt of a file: `imagen batch prompts.txt` (or `.jsonl`/`.csv`).
+    Batch(Box<BatchArgs>),
+
+    /// Run one prompt on several models at once: `imagen compare "a cat" --models nano-banana,gpt-1`.
+    Compare(Box<CompareArgs>),
+
+    /// Refine an image over several prompts, each editing the last result (Gemini).
+    Session(SessionArgs),
+
+    /// Inspect and convert recorded cassettes.
+    #[command(subcommand)]
+    Cassette(CassetteCommand),
+
+    /// Enlarge an existing image: `imagen upscale photo.jpg --factor 4`.
+    Upscale(UpscaleArgs),
+
+    /// Run multi-step pipelines defined in YAML.
+    #[command(subcommand)]
+    Pipeline(PipelineCommand),
+
+    /// Compare latency and success rates across providers with a fixed tiny prompt.
+    Bench {
+        /// Providers to benchmark, each with its fastest model (e.g. `gemini,openai`).
+        #[arg(long, value_delimiter = ',')]
+        providers: Vec<String>,
+
+        /// Specific models or aliases to benchmark.
+        #[arg(long, value_delimiter = ',')]
+        models: Vec<String>,
+
+        /// Requests per model.
+        #[arg(long, default_value = "3")]
+        runs: u32,
+
+        /// Config file path override.
+        #[arg(long)]
+        config: Option<String>,
+
+        /// Named profile from the config's `[profiles]` (or `IMAGEN_PROFILE`).
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+    },
+
+    /// Measure how far two images differ; fails when they differ beyond the threshold.
+    Diff {
+        /// Reference image.
+        a: String,
+
+        /// Image to compare against it.
+        b: String,
+
+        /// Largest accepted distance, from 0 (identical) to 1.
+        #[arg(long, default_value = "0.02")]
+        threshold: f64,
+
+        /// Distance compared to the threshold: ssim (1 - SSIM), phash (difference hash).
+        #[arg(long, default_value = "ssim")]
+        metric: String,
+
+        /// Write a visual diff (changed pixels in red) to this path.
+        #[arg(long, value_name = "PATH")]
+        diff_output: Option<String>,
+    },
+
+    /// Find earlier generations: `imagen history search castle --since 7d`.
+    #[command(subcommand)]
+    History(HistoryCommand),
+
+    /// List model names, their providers, and the settings each supports.
+    Models {
+        /// Print a JSON array instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+
+    /// Create, inspect, and edit the config file: `imagen config set defaults.model gpt-1`.
+    #[command(subcommand)]
+    Config(ConfigCommand),
+
+    /// Reclaim disk space used by local state: prunes expired history, stale cache
+    /// entries, leftover run directories, and unreadable cassettes, then compacts the store.
+    Gc {
+        /// Report what would be removed and reclaimed without changing anything.
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Days of history to keep; 0 keeps everything [default: `[history] retention_days`, else 90].
+        #[arg(long, value_name = "DAYS")]
+        retention_days: Option<u32>,
+
+        /// Config file path override.
+        #[arg(long)]
+        config: Option<String>,
+
+        /// Named profile from the config's `[profiles]` (or `IMAGEN_PROFILE`).
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+    },
+}
+
+/// Options for `imagen batch`.
+#[derive(Args, Debug)]
+pub struct BatchArgs {
+    /// Prompts file (one per line), or a `.jsonl` / `.csv` manifest with per-job settings.
+    pub file: String,
+
+    /// Record finished prompts in this state file, skipping those it already lists.
+    #[arg(long, value_name = "STATE")]
+    pub resume: Option<String>,
+
+    /// Generation options applied to every prompt.
+    #[command(flatten)]
+    pub args: GenerateArgs,
+}
+
+/// Options for `imagen compare`.
+#[derive(Args, Debug)]
+pub struct CompareArgs {
+    /// Models or aliases to run the prompt on, each saving its images with the model in
+    /// the file name (cat-nano-banana.png). Replaces --model.
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub models: Vec<String>,
+
+    /// Also write every model's images into one grid labeled with the models
+    /// (cat-compare.jpg).
+    #[arg(long)]
+    pub composite: bool,
+
+    /// Generation options applied to every model.
+    #[command(flatten)]
+    pub args: GenerateArgs,
+}
+
+/// Options for `imagen upscale`.
+#[derive(Args, Debug)]
+pub struct UpscaleArgs {
+    /// Image to upscale.
+    pub input: String,
+
+    /// Scale factor applied to width and height (2-8).
+    #[arg(long, default_value = "2")]
+    pub factor: u32,
+
+    /// Upscaler: local (Lanczos resampling), stability (fast 4x), replicate (Real-ESRGAN).
+    #[arg(long = "with", value_name = "UPSCALER", default_value = "local")]
+    pub upscaler: String,
+
+    /// Output file path [default: `<input>-x<factor>.<ext>` next to the input].
+    #[arg(short, long)]
+    pub output: Option<String>,
+
+    /// Output format: jpeg, png, webp, tiff, bmp, gif, avif [default: the input's format].
+    #[arg(short, long)]
+    pub format: Option<String>,
+
+    /// Config file path override.
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Named profile from the config's `[profiles]` (or `IMAGEN_PROFILE`).
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+}
+
+/// Options for `imagen session`.
+#[derive(Args, Debug)]
+pub struct SessionArgs {
+    /// Gemini model name or short alias.
+    #[arg(short, long, default_value = "nano-banana")]
+    pub model: String,
+
+    /// Aspect ratio (e.g., 1:1, 16:9, 9:16).
+    #[arg(short, long, default_value = "1:1")]
+    pub aspect_ratio: String,
+
+    /// Image size: 1K, 2K, 4K.
+    #[arg(short, long, default_value = "1K")]
+    pub size: String,
+
+    /// Output format: jpeg, png, webp, tiff, bmp, gif, avif.
+    #[arg(short, long, default_value = "png")]
+    pub format: String,
+
+    /// Thinking level: none, minimal, low, medium, high.
+    #[arg(short, long)]
+    pub thinking: Option<String>,
+
+    /// Starting image for the first turn (repeatable). Use `ROLE=PATH` to label it.
+    #[arg(short, long = "input", num_args = 1)]
+    pub input: Vec<String>,
+
+    /// Directory for the per-turn images [default: `imagen-session-<timestamp>`].
+    #[arg(short, long)]
+    pub dir: Option<String>,
+
+    /// Config file path override.
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Named profile from the config's `[profiles]` (or `IMAGEN_PROFILE`).
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+}
+
+/// Pipeline subcommands.
+#[derive(Subcommand, Debug)]
+pub enum PipelineCommand {
+    /// Run a pipeline file, skipping steps whose inputs and options are unchanged.
+    Run {
+        /// Pipeline file, e.g. `brand-kit.yaml`.
+        file: String,
+
+        /// Ignore results from earlier runs and execute every step.
+        #[arg(long)]
+        fresh: bool,
+
+        /// Config file path override.
+        #[arg(long)]
+        config: Option<String>,
+
+        /// Named profile from the config's `[profiles]` (or `IMAGEN_PROFILE`).
+        #[arg(long, value_name = "NAME")]
+        profile: Option<String>,
+    },
+}
+
+/// History subcommands.
+#[derive(Subcommand, Debug)]
+pub enum HistoryCommand {
+    /// List saved images, newest first.
+    List(HistoryArgs),
+
+    /// List saved images whose prompt contains some text (ignoring case).
+    Search {
+        /// Text to look for in the prompt.
+        query: String,
+
+        /// Filters and output format, as for `list`.
+        #[command(flatten)]
+        args: HistoryArgs,
+    },
+}
+
+/// Filters shared by the history subcommands.
+#[derive(Args, Debug)]
+pub struct HistoryArgs {
+    /// Only images from this model (name or alias).
     #[arg(short, long)]
-    pub verbose: bool,
+    pub model: Option<String>,
+
+    /// Only images saved since then: a duration back from now (30m, 12h, 7d, 2w) or a
+    /// date (2026-03-01).
+    #[arg(long, value_name = "WHEN")]
+    pub since: Option<String>,
+
+    /// Only images whose prompt contains this text (ignoring case).
+    #[arg(long, value_name = "TEXT")]
+    pub grep: Option<String>,
+
+    /// Most images to show.
+    #[arg(long, default_value = "20")]
+    pub limit: usize,
+
+    /// Print the matches as a JSON array, with every recorded field.
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// Config subcommands.
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// Write a commented starter config file.
+    Init {
+        /// Overwrite an existing file.
+        #[arg(long)]
+        force: bool,
+
+        /// Config file path override.
+        #[arg(long)]
+        config: Option<String>,
+    },
+
+    /// Print the effective config (file, profile, and API keys from the keychain and
+    /// environment), with API keys redacted.
+    Show(ConfigFileArgs),
+
+    /// Set one value in the config file, keeping its comments and layout.
+    Set {
+        /// Dotted key (e.g., `defaults.model`, `defaults.per_model."gpt-image-1".quality`).
+        key: String,
+
+        /// New value: TOML (`true`, `300`, `"text"`), or else taken as plain text.
+        value: String,
+
+        /// Which config file to edit.
+        #[command(flatten)]
+        file: ConfigFileArgs,
+    },
+
+    /// Store a provider's API key in the OS keychain, reading it from standard input.
+    ///
+    /// A stored key takes precedence over the environment and the config file.
+    SetKey {
+        /// Provider the key is for.
+        #[arg(value_parser = [
+            "gemini", "openai", "stability", "replicate", "azure_openai", "together", "ideogram",
+        ])]
+        provider: String,
+
+        /// Remove the stored key instead.
+        #[arg(long)]
+        delete: bool,
+    },
+
+    /// Report unknown settings, invalid default values, and missing credentials for the
+    /// default model, exiting non-zero if there are any.
+    Check(ConfigFileArgs),
+
+    /// Print the path of the config file in use.
+    Path {
+        /// Config file path override.
+        #[arg(long)]
+        config: Option<String>,
+    },
+}
+
+/// The config file and profile a config subcommand works on.
+#[derive(Args, Debug)]
+pub struct ConfigFileArgs {
+    /// Config file path override.
+    #[arg(long)]
+    pub config: Option<String>,
+
+    /// Named profile from the config's `[profiles]` (or `IMAGEN_PROFILE`); `set` writes
+    /// under `[profiles.<NAME>]`.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+}
+
+/// Cassette subcommands.
+#[derive(Subcommand, Debug)]
+pub enum CassetteCommand {
+    /// Export a cassette as HTTP mock-server stubs (`WireMock` mappings or a Prism `OpenAPI` doc).
+    ToMockServer {
+        /// Path to the cassette file.
+        cassette: String,
+
+        /// Stub format: wiremock, prism.
+        #[arg(long, default_value = "wiremock")]
+        format: String,
+
+        /// Output file path (stdout if not specified).
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+
+    /// Write a synthetic cassette that replays a provider failure.
+    Synth {
+        /// Failure to reproduce: 429 (rate-limit), 401 (auth), content-block, malformed-json.
+        #[arg(long)]
+        error: String,
+
+        /// Provider whose error format to use: gemini, openai, azure, stability, replicate,
+        /// sd-local, bedrock, together, ideogram.
+        #[arg(long)]
+        provider: String,
+
+        /// Model recorded in the request [default: the provider's cheapest model].
+        #[arg(short, long)]
+        model: Option<String>,
+
+        /// Prompt recorded in the request.
+        #[arg(long, default_value = "a cat")]
+        prompt: String,
+
+        /// Output file path (stdout if not specified).
+        #[arg(short, long)]
+        output: Option<String>,
+    },
 }
 
-impl Cli {
-    /// Resolve the prompt from either the positional argument or the file flag.
+impl GenerateArgs {
+    /// `--width` and `--height`, when given.
+    #[must_use]
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.width.zip(self.height)
+    }
+
+    /// Resolve the prompt from the positional argument, the file flag, or stdin.
+    ///
+    /// A positional prompt of `@name` is replaced by the `name` entry of `library` (the
+    /// config's `[prompts]`), followed by any text after the name: `@hero_banner at night`.
+    /// A leading `@@` stands for a literal `@`. A prompt or prompt file of `-` is read from
+    /// stdin, as is a missing prompt when stdin is not a terminal. Stdin is read as a
+    /// prompt file is, so `cat p.txt | imagen` and `imagen -p p.txt` send the same prompt.
     ///
     /// # Errors
     ///
-    /// Returns an error if neither prompt nor prompt-file is provided,
-    /// or if the file cannot be read.
-    pub fn resolve_prompt(&self) -> Result<String, std::io::Error> {
-        if let Some(ref text) = self.prompt {
-            Ok(text.clone())
-        } else if let Some(ref path) = self.prompt_file {
-            std::fs::read_to_string(path)
-        } else {
-            Err(std::io::Error::new(
+    /// Returns an error if no prompt is provided, if the file or stdin cannot be read, if
+    /// stdin is needed for both the prompt and `--confirm`, or if `@name` is not in
+    /// `library`.
+    pub fn resolve_prompt(
+        &self,
+        library: &HashMap<String, String>,
+    ) -> Result<String, std::io::Error> {
+        let stdin = std::io::stdin();
+        self.resolve_prompt_from(library, !stdin.is_terminal(), stdin)
+    }
+
+    /// [`Self::resolve_prompt`], reading `stdin` when the prompt comes from there. `piped`
+    /// is whether it is a pipe or file rather than a terminal.
+    fn resolve_prompt_from(
+        &self,
+        library: &HashMap<String, String>,
+        piped: bool,
+        stdin: impl std::io::Read,
+    ) -> Result<String, std::io::Error> {
+        let from_stdin = match (self.prompt.as_deref(), self.prompt_file.as_deref()) {
+            (Some("-"), _) | (_, Some("-")) => true,
+            (Some(text), _) => {
+                return expand_named_prompt(text, library)
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidInput, e));
+            }
+            (None, Some(path)) => return std::fs::read_to_string(path),
+            (None, None) => piped,
+        };
+        let prompt = if from_stdin { self.read_stdin_prompt(stdin)? } else { String::new() };
+        if prompt.trim().is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Provide a prompt string, use -p/--prompt-file, or pipe it on stdin",
+            ));
+        }
+        Ok(prompt)
+    }
+
+    /// Read the whole of `stdin` as the prompt.
+    fn read_stdin_prompt(&self, mut stdin: impl std::io::Read) -> Result<String, std::io::Error> {
+        if self.confirm {
+            return Err(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
-                "Provide a prompt string or use -p/--prompt-file",
-            ))
+                "--confirm reads its answer from stdin, so the prompt cannot come from there; \
+                 pass it as an argument or with -p/--prompt-file",
+            ));
+        }
+        let mut prompt = String::new();
+        stdin.read_to_string(&mut prompt)?;
+        Ok(prompt)
+    }
+
+    /// `prompt` with the text of each `--style` appended in the order given, separated
+    /// by commas.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a style is not in `library`.
+    pub fn apply_styles(
+        &self,
+        prompt: &str,
+        library: &HashMap<String, String>,
+    ) -> Result<String, String> {
+        let mut styled = prompt.trim_end().to_string();
+        for name in &self.styles {
+            let Some(style) = library.get(name.trim()) else {
+                return Err(format!(
+                    "Unknown style '{name}'; define it under [styles] in the config (known: {})",
+                    known_names(library)
+                ));
+            };
+            styled.push_str(", ");
+            styled.push_str(style.trim());
         }
+        Ok(styled)
+    }
+}
+
+/// The names defined in a `[prompts]` or `[styles]` table, sorted, for error messages.
+fn known_names(library: &HashMap<String, String>) -> String {
+    let mut known: Vec<&str> = library.keys().map(String::as_str).collect();
+    known.sort_unstable();
+    if known.is_empty() {
+        "none".to_string()
+    } else {
+        known.join(", ")
+    }
+}
+
+/// Expand a leading `@name` from the prompt library, keeping any text after it.
+fn expand_named_prompt(text: &str, library: &HashMap<String, String>) -> Result<String, String> {
+    if let Some(literal) = text.strip_prefix("@@") {
+        return Ok(format!("@{literal}"));
     }
+    let Some(named) = text.strip_prefix('@') else {
+        return Ok(text.to_string());
+    };
+    let end = named.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '-'));
+    let (name, extra) = named.split_at(end.unwrap_or(named.len()));
+    let Some(prompt) = library.get(name) else {
+        return Err(format!(
+            "Unknown prompt '@{name}'; define it under [prompts] in the config (known: {})",
+            known_names(library)
+        ));
+    };
+    let extra = extra.trim();
+    Ok(if extra.is_empty() { prompt.clone() } else { format!("{prompt} {extra}") })
 }
 
 #[cfg(test)]
@@ -91,9 +851,43 @@ mod tests {
     #[test]
     fn positional_prompt() {
         let cli = Cli::parse_from(["imagen", "a cat"]);
-        assert_eq!(cli.prompt.as_deref(), Some("a cat"));
-        assert!(cli.prompt_file.is_none());
-        assert_eq!(cli.resolve_prompt().unwrap(), "a cat");
+        assert_eq!(cli.args.prompt.as_deref(), Some("a cat"));
+        assert!(cli.args.prompt_file.is_none());
+        assert_eq!(cli.args.resolve_prompt(&HashMap::new()).unwrap(), "a cat");
+    }
+
+    #[test]
+    fn named_prompts_expand_from_the_library() {
+        let library =
+            HashMap::from([("hero_banner".to_string(), "wide cinematic banner".to_string())]);
+        let resolve = |prompt| Cli::parse_from(["imagen", prompt]).args.resolve_prompt(&library);
+        assert_eq!(resolve("@hero_banner").unwrap(), "wide cinematic banner");
+        assert_eq!(
+            resolve("@hero_banner  of a castle ").unwrap(),
+            "wide cinematic banner of a castle"
+        );
+        assert_eq!(resolve("@@home").unwrap(), "@home");
+        assert_eq!(resolve("a cat @hero_banner").unwrap(), "a cat @hero_banner");
+        let err = resolve("@hero").unwrap_err().to_string();
+        assert!(err.contains("'@hero'") && err.contains("known: hero_banner"), "got: {err}");
+    }
+
+    #[test]
+    fn styles_compose_in_flag_order() {
+        let library = HashMap::from([
+            ("watercolor".to_string(), "soft watercolor, paper texture".to_string()),
+            ("noir".to_string(), "high-contrast black and white ".to_string()),
+        ]);
+        let args = |argv: &[&str]| Cli::parse_from(argv).args;
+        let styled = args(&["imagen", "--style", "noir", "--style", "watercolor", "a cat"])
+            .apply_styles("a cat ", &library)
+            .unwrap();
+        assert_eq!(styled, "a cat, high-contrast black and white, soft watercolor, paper texture");
+        let listed = args(&["imagen", "--style", "watercolor,noir", "a cat"]);
+        assert_eq!(listed.styles, ["watercolor", "noir"]);
+        assert_eq!(args(&["imagen", "a cat"]).apply_styles("a cat", &library).unwrap(), "a cat");
+        let err = args(&["imagen", "--style", "ink", "x"]).apply_styles("x", &library).unwrap_err();
+        assert!(err.contains("'ink'") && err.contains("known: noir, watercolor"), "got: {err}");
     }
 
     #[test]
@@ -104,9 +898,9 @@ mod tests {
         std::fs::write(&path, "prompt from file").unwrap();
 
         let cli = Cli::parse_from(["imagen", "-p", path.to_str().unwrap()]);
-        assert!(cli.prompt.is_none());
-        assert!(cli.prompt_file.is_some());
-        assert_eq!(cli.resolve_prompt().unwrap(), "prompt from file");
+        assert!(cli.args.prompt.is_none());
+        assert!(cli.args.prompt_file.is_some());
+        assert_eq!(cli.args.resolve_prompt(&HashMap::new()).unwrap(), "prompt from file");
 
         let _ = std::fs::remove_dir_all(&dir);
     }
@@ -114,14 +908,16 @@ mod tests {
     #[test]
     fn default_values() {
         let cli = Cli::parse_from(["imagen", "a cat"]);
-        assert_eq!(cli.model, "nano-banana");
-        assert_eq!(cli.aspect_ratio, "1:1");
-        assert_eq!(cli.size, "1K");
-        assert_eq!(cli.quality, "auto");
-        assert_eq!(cli.format, "jpeg");
-        assert!(cli.output.is_none());
-        assert_eq!(cli.count, 1);
-        assert!(!cli.verbose);
+        assert_eq!(cli.args.model, "nano-banana");
+        assert_eq!(cli.args.aspect_ratio, "1:1");
+        assert_eq!(cli.args.size, "1K");
+        assert_eq!(cli.args.quality, "auto");
+        assert_eq!(cli.args.format, "jpeg");
+        assert!(cli.args.output.is_none());
+        assert_eq!(cli.args.count, 1);
+        assert!(!cli.args.offline);
+        assert_eq!(cli.args.verbose, 0);
+        assert_eq!(cli.args.log_format, "text");
     }
 
     #[test]
@@ -142,23 +938,141 @@ mod tests {
             "out.png",
             "-n",
             "3",
-            "-v",
+            "-vv",
             "a landscape",
         ]);
-        assert_eq!(cli.model, "gpt-1");
-        assert_eq!(cli.aspect_ratio, "16:9");
-        assert_eq!(cli.size, "4K");
-        assert_eq!(cli.quality, "high");
-        assert_eq!(cli.format, "png");
-        assert_eq!(cli.output.as_deref(), Some("out.png"));
-        assert_eq!(cli.count, 3);
-        assert!(cli.verbose);
-        assert_eq!(cli.prompt.as_deref(), Some("a landscape"));
+        assert_eq!(cli.args.model, "gpt-1");
+        assert_eq!(cli.args.aspect_ratio, "16:9");
+        assert_eq!(cli.args.size, "4K");
+        assert_eq!(cli.args.quality, "high");
+        assert_eq!(cli.args.format, "png");
+        assert_eq!(cli.args.output.as_deref(), Some("out.png"));
+        assert_eq!(cli.args.count, 3);
+        assert_eq!(cli.args.verbose, 2);
+        assert_eq!(cli.args.prompt.as_deref(), Some("a landscape"));
+    }
+
+    #[test]
+    fn cassette_subcommand() {
+        let cli = Cli::parse_from([
+            "imagen",
+            "cassette",
+            "to-mock-server",
+            "run.cassette.yaml",
+            "--format",
+            "prism",
+        ]);
+        assert!(cli.args.prompt.is_none());
+        let Some(Command::Cassette(CassetteCommand::ToMockServer { cassette, format, output })) =
+            cli.command
+        else {
+            panic!("expected cassette to-mock-server");
+        };
+        assert_eq!(cassette, "run.cassette.yaml");
+        assert_eq!(format, "prism");
+        assert!(output.is_none());
+    }
+
+    #[test]
+    fn upscale_subcommand() {
+        let cli = Cli::parse_from(["imagen", "upscale", "photo.jpg", "--factor", "4"]);
+        let Some(Command::Upscale(args)) = cli.command else {
+            panic!("expected upscale");
+        };
+        assert_eq!(args.input, "photo.jpg");
+        assert_eq!(args.factor, 4);
+        assert_eq!(args.upscaler, "local");
+        assert!(args.format.is_none());
+    }
+
+    #[test]
+    fn bench_subcommand() {
+        let cli = Cli::parse_from([
+            "imagen",
+            "bench",
+            "--providers",
+            "gemini,openai",
+            "--runs",
+            "5",
+            "--profile",
+            "work",
+        ]);
+        let Some(Command::Bench { providers, models, runs, config, profile }) = cli.command else {
+            panic!("expected bench");
+        };
+        assert_eq!(providers, ["gemini", "openai"]);
+        assert!(models.is_empty());
+        assert_eq!(runs, 5);
+        assert!(config.is_none());
+        assert_eq!(profile.as_deref(), Some("work"));
+    }
+
+    #[test]
+    fn gc_subcommand() {
+        let cli = Cli::parse_from(["imagen", "gc", "--dry-run", "--retention-days", "30"]);
+        assert!(matches!(
+            cli.command,
+            Some(Command::Gc { dry_run: true, retention_days: Some(30), .. })
+        ));
+    }
+
+    #[test]
+    fn history_subcommand() {
+        let cli = Cli::parse_from([
+            "imagen", "history", "search", "castle", "--model", "gpt-1", "--since", "7d", "--json",
+        ]);
+        let Some(Command::History(HistoryCommand::Search { query, args })) = cli.command else {
+            panic!("expected history search");
+        };
+        assert_eq!(query, "castle");
+        assert_eq!(args.model.as_deref(), Some("gpt-1"));
+        assert_eq!(args.since.as_deref(), Some("7d"));
+        assert_eq!(args.limit, 20);
+        assert!(args.json && args.grep.is_none());
+    }
+
+    #[test]
+    fn edit_subcommand() {
+        let cli =
+            Cli::parse_from(["imagen", "edit", "-i", "photo.png", "-m", "gpt-image-1", "at night"]);
+        let Some(Command::Edit(args)) = cli.command else {
+            panic!("expected edit");
+        };
+        assert_eq!(args.input, ["photo.png"]);
+        assert_eq!(args.model, "gpt-image-1");
+        assert_eq!(args.prompt.as_deref(), Some("at night"));
+    }
+
+    #[test]
+    fn batch_subcommand() {
+        let cli =
+            Cli::parse_from(["imagen", "batch", "jobs.jsonl", "-m", "gpt-image-1", "-n", "2"]);
+        let Some(Command::Batch(batch)) = cli.command else {
+            panic!("expected batch");
+        };
+        assert_eq!(batch.file, "jobs.jsonl");
+        assert_eq!(batch.args.model, "gpt-image-1");
+        assert_eq!(batch.args.count, 2);
+        assert!(batch.args.prompt.is_none());
     }
 
     #[test]
     fn no_prompt_errors() {
         let cli = Cli::parse_from(["imagen"]);
-        assert!(cli.resolve_prompt().is_err());
+        assert!(cli.args.resolve_prompt_from(&HashMap::new(), false, "a cat".as_bytes()).is_err());
+        assert!(cli.args.resolve_prompt_from(&HashMap::new(), true, " \n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn prompt_from_stdin() {
+        let resolve = |args: &[&str], piped| {
+            let cli = Cli::parse_from(args);
+            cli.args.resolve_prompt_from(&HashMap::new(), piped, "a cat\n".as_bytes())
+        };
+        assert_eq!(resolve(&["imagen"], true).unwrap(), "a cat\n");
+        assert_eq!(resolve(&["imagen", "-"], false).unwrap(), "a cat\n");
+        assert_eq!(resolve(&["imagen", "-p", "-"], false).unwrap(), "a cat\n");
+        assert_eq!(resolve(&["imagen", "a dog"], true).unwrap(), "a dog");
+        assert!(resolve(&["imagen", "--confirm", "-"], false).is_err());
     }
 }
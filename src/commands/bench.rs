@@ -0,0 +1,203 @@
+//! `imagen bench`: compare provider latency and reliability with a fixed tiny prompt.
+//!
+//! Each run is a single attempt (no retry decorator), so failures count against the
+//! model's success rate.
+
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use crate::config::{self, Config};
+use crate::context::ServiceContext;
+use crate::error::ImageError;
+use crate::model::{detect_provider, provider_model, resolve_model};
+use crate::ports::ImageRequest;
+
+/// Prompt sent on every run: quick to render on any model.
+const BENCH_PROMPT: &str = "a small red circle on a plain white background";
+
+/// Outcome of one benchmark call.
+struct Sample {
+    latency: Duration,
+    error: Option<String>,
+}
+
+/// Latency and success figures for one model.
+#[derive(Debug, PartialEq)]
+struct Summary {
+    model: String,
+    runs: usize,
+    ok: usize,
+    /// Median, min, and max latency over successful runs.
+    latency: Option<(Duration, Duration, Duration)>,
+}
+
+/// Run the benchmark and print a comparison table, fastest model first.
+///
+/// # Errors
+///
+/// Returns an error for an unknown provider or model name, or an invalid config file.
+pub async fn run(
+    providers: &[String],
+    models: &[String],
+    runs: u32,
+    config_path: Option<&str>,
+    profile: Option<&str>,
+) -> Result<(), ImageError> {
+    let targets = bench_models(providers, models).map_err(ImageError::InvalidArgument)?;
+    let config = Config::load(&config::discover_config_path(config_path), profile)
+        .map_err(ImageError::Config)?;
+    let runs = runs.max(1);
+
+    let mut summaries = Vec::new();
+    for model in targets {
+        tracing::info!(model, runs, "Benchmarking {model} ({runs} runs)...");
+        let samples = bench_model(&model, runs, &config).await;
+        summaries.push(summarize(&model, &samples));
+    }
+
+    summaries.sort_by_key(|s| (s.latency.is_none(), s.latency.map(|(median, _, _)| median)));
+    print!("{}", render_table(&summaries));
+    Ok(())
+}
+
+/// Resolve `--providers` names and `--models` aliases into the models to benchmark.
+fn bench_models(providers: &[String], models: &[String]) -> Result<Vec<String>, String> {
+    if providers.is_empty() && models.is_empty() {
+        return Err("Pass --providers and/or --models to choose what to benchmark".to_string());
+    }
+    let mut targets = Vec::new();
+    for name in providers {
+        targets.push(provider_model(name)?);
+    }
+    for name in models {
+        let model = resolve_model(name);
+        detect_provider(&model)?;
+        targets.push(model);
+    }
+    targets.dedup();
+    Ok(targets)
+}
+
+/// Time `runs` single-attempt requests against `model`.
+async fn bench_model(model: &str, runs: u32, config: &Config) -> Vec<Sample> {
+    let provider = detect_provider(model).expect("bench models are validated up front");
+    let ctx = match ServiceContext::single_attempt(provider, config) {
+        Ok(ctx) => ctx,
+        Err(e) => {
+            tracing::info!(model, "  skipped: {e}");
+            return Vec::new();
+        }
+    };
+    let request = ImageRequest {
+        model: model.to_string(),
+        prompt: BENCH_PROMPT.to_string(),
+        aspect_ratio: "1:1".into(),
+        size: "1K".into(),
+        quality: "low".into(),
+        format: "jpeg".into(),
+        count: 1,
+        seed: None,
+        thinking: None,
+        input_images: vec![],
+        background: None,
+        magic_prompt: None,
+        style_type: None,
+        moderation: None,
+        output_compression: None,
+        safety: None,
+        reference_images: vec![],
+        return_urls: false,
+        history: vec![],
+    };
+
+    let mut samples = Vec::new();
+    for i in 1..=runs {
+        let started = Instant::now();
+        let result = ctx.generator.generate(&request).await;
+        let latency = started.elapsed();
+        let error = result.err().map(|e| e.to_string());
+        let secs = latency.as_secs_f64();
+        if let Some(ref e) = error {
+            tracing::info!(model, "  run {i}/{runs} failed after {secs:.2}s: {e}");
+        } else {
+            tracing::info!(model, "  run {i}/{runs}: {secs:.2}s");
+        }
+        samples.push(Sample { latency, error });
+    }
+    samples
+}
+
+fn summarize(model: &str, samples: &[Sample]) -> Summary {
+    let mut ok: Vec<Duration> =
+        samples.iter().filter(|s| s.error.is_none()).map(|s| s.latency).collect();
+    ok.sort();
+    let latency = (!ok.is_empty()).then(|| (ok[ok.len() / 2], ok[0], ok[ok.len() - 1]));
+    Summary { model: model.to_string(), runs: samples.len(), ok: ok.len(), latency }
+}
+
+fn render_table(summaries: &[Summary]) -> String {
+    let width = summaries.iter().map(|s| s.model.len()).max().unwrap_or(0).max("MODEL".len());
+    let mut out = format!(
+        "{:<width$}  {:>4}  {:>4}  {:>8}  {:>8}  {:>8}\n",
+        "MODEL", "RUNS", "OK", "MEDIAN", "MIN", "MAX"
+    );
+    for s in summaries {
+        let secs = |d: Duration| format!("{:.2}s", d.as_secs_f64());
+        let (median, min, max) = s.latency.map_or_else(
+            || ("-".to_string(), "-".to_string(), "-".to_string()),
+            |(median, min, max)| (secs(median), secs(min), secs(max)),
+        );
+        let _ = writeln!(
+            out,
+            "{:<width$}  {:>4}  {:>4}  {median:>8}  {min:>8}  {max:>8}",
+            s.model, s.runs, s.ok
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(ms: u64, ok: bool) -> Sample {
+        Sample { latency: Duration::from_millis(ms), error: (!ok).then(|| "HTTP 503".to_string()) }
+    }
+
+    #[test]
+    fn providers_and_models_resolve() {
+        let models =
+            bench_models(&["gemini".into(), "openai".into()], &["flux-dev".into()]).unwrap();
+        assert_eq!(
+            models,
+            ["gemini-3.1-flash-image-preview", "gpt-image-1-mini", "black-forest-labs/FLUX.1-dev"]
+        );
+        assert!(bench_models(&["midjourney".into()], &[]).unwrap_err().contains("Valid: gemini"));
+        assert!(bench_models(&[], &["dall-e-3".into()]).is_err());
+        assert!(bench_models(&[], &[]).is_err());
+    }
+
+    #[test]
+    fn summary_ignores_failed_runs() {
+        let samples = [sample(900, true), sample(50, false), sample(700, true), sample(800, true)];
+        let summary = summarize("m", &samples);
+        assert_eq!(summary.runs, 4);
+        assert_eq!(summary.ok, 3);
+        let ms = Duration::from_millis;
+        assert_eq!(summary.latency, Some((ms(800), ms(700), ms(900))));
+        assert_eq!(summarize("m", &[sample(10, false)]).latency, None);
+    }
+
+    #[test]
+    fn table_layout() {
+        let table = render_table(&[
+            summarize("gpt-image-1-mini", &[sample(1500, true)]),
+            summarize("sd-local", &[sample(5, false)]),
+        ]);
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("MODEL             RUNS"), "got: {}", lines[0]);
+        assert!(lines[1].ends_with("1.50s     1.50s     1.50s"), "got: {}", lines[1]);
+        assert!(lines[2].ends_with("-         -         -"), "got: {}", lines[2]);
+    }
+}
@@ -0,0 +1,102 @@
+//! `imagen cassette` subcommands.
+
+use std::path::Path;
+
+use crate::cassette::config::read_cassette;
+use crate::cassette::mock_server::{export, MockFormat};
+use crate::cassette::synth::{synthesize, Scenario};
+use crate::cli::CassetteCommand;
+use crate::error::ImageError;
+use crate::model::{detect_provider, provider_model, resolve_model};
+use crate::ports::ImageRequest;
+use crate::report;
+
+/// Run a cassette subcommand.
+///
+/// # Errors
+///
+/// Returns an error if the cassette cannot be read or the output cannot be written.
+pub fn run(command: &CassetteCommand) -> Result<(), ImageError> {
+    match command {
+        CassetteCommand::ToMockServer { cassette, format, output } => {
+            to_mock_server(cassette, format, output.as_deref())
+        }
+        CassetteCommand::Synth { error, provider, model, prompt, output } => {
+            synth(error, provider, model.as_deref(), prompt, output.as_deref())
+        }
+    }
+}
+
+/// Export a cassette as mock-server stubs, writing to `output` or stdout.
+fn to_mock_server(cassette: &str, format: &str, output: Option<&str>) -> Result<(), ImageError> {
+    let format = MockFormat::parse(format).map_err(ImageError::InvalidArgument)?;
+    let cassette = read_cassette(Path::new(cassette))
+        .map_err(|e| ImageError::Config(format!("Failed to load cassette: {e}")))?;
+
+    let exported = export(&cassette, format);
+    for reason in &exported.skipped {
+        report::warn(format_args!("skipped {reason}"));
+    }
+
+    let json = serde_json::to_string_pretty(&exported.document)
+        .expect("serde_json::Value is always serializable");
+    match output {
+        Some(path) => {
+            std::fs::write(path, format!("{json}\n"))?;
+            tracing::info!(path, "Saved: {path}");
+        }
+        None => println!("{json}"),
+    }
+    Ok(())
+}
+
+/// Write a cassette in which a single request fails with the `error` scenario.
+fn synth(
+    error: &str,
+    provider: &str,
+    model: Option<&str>,
+    prompt: &str,
+    output: Option<&str>,
+) -> Result<(), ImageError> {
+    let scenario = Scenario::parse(error).map_err(ImageError::InvalidArgument)?;
+    let default_model = provider_model(provider).map_err(ImageError::InvalidArgument)?;
+    let provider = detect_provider(&default_model).map_err(ImageError::InvalidArgument)?;
+    let model = model.map_or(default_model, resolve_model);
+    if detect_provider(&model).map_err(ImageError::InvalidArgument)? != provider {
+        return Err(ImageError::InvalidArgument(format!(
+            "Model '{model}' does not belong to provider {provider:?}"
+        )));
+    }
+
+    let request = ImageRequest {
+        model,
+        prompt: prompt.to_string(),
+        aspect_ratio: "1:1".into(),
+        size: "1K".into(),
+        quality: "auto".into(),
+        format: "png".into(),
+        count: 1,
+        seed: None,
+        thinking: None,
+        input_images: vec![],
+        background: None,
+        magic_prompt: None,
+        style_type: None,
+        moderation: None,
+        output_compression: None,
+        safety: None,
+        reference_images: vec![],
+        return_urls: false,
+        history: vec![],
+    };
+    let cassette = synthesize(scenario, provider, &request).map_err(ImageError::InvalidArgument)?;
+    let yaml = serde_yaml::to_string(&cassette).expect("a cassette is always serializable");
+    match output {
+        Some(path) => {
+            std::fs::write(path, yaml)?;
+            tracing::info!(path, "Saved: {path}");
+        }
+        None => print!("{yaml}"),
+    }
+    Ok(())
+}
@@ -0,0 +1,398 @@
+//! `imagen config`: create, inspect, and edit the config file.
+
+use std::io::IsTerminal;
+use std::path::Path;
+
+use toml_edit::{DocumentMut, Item, Key};
+
+use crate::cassette::recorder::{redact_url_userinfo, REDACTED};
+use crate::cli::{ConfigCommand, ConfigFileArgs};
+use crate::config::{
+    discover_config_path, invalid_settings, selected_profile, Config, ModelDefaults,
+};
+use crate::context::check_credentials;
+use crate::error::ImageError;
+use crate::keychain;
+use crate::model::{detect_provider, resolve_model, Provider};
+use crate::output::SlugStrategy;
+use crate::params::{
+    fit_aspect_ratio, parse_ratio_list, validate_format, validate_quality, validate_size,
+};
+
+/// Starter file written by `imagen config init`: every section, commented out.
+const STARTER: &str = r#"# imagen configuration. Uncomment and edit what you need;
+# `imagen config show` prints the settings in effect.
+
+[keys]
+# gemini = "your-gemini-api-key"      # or GEMINI_API_KEY
+# openai = "your-openai-api-key"      # or OPENAI_API_KEY
+# stability = "your-stability-key"    # or STABILITY_API_KEY
+# replicate = "your-replicate-token"  # or REPLICATE_API_TOKEN
+# together = "your-together-key"      # or TOGETHER_API_KEY
+# ideogram = "your-ideogram-key"      # or IDEOGRAM_API_KEY
+
+[defaults]
+# model = "nano-banana"
+# aspect_ratio = "1:1"
+# size = "1K"
+# quality = "auto"
+# format = "jpeg"
+# fallback = ["gpt-1"]                # models to try when the default's provider is down
+# on_refusal = "soften"               # or a model, for prompts refused on safety grounds
+
+# Defaults for one model, by name or alias, over [defaults].
+# [defaults.per_model."gpt-image-1"]
+# quality = "high"
+
+[output]
+# slug = "prompt"                     # prompt, hash, counter, ulid
+# name_template = "{date}/{prompt:40}-{n}.{ext}"
+# dir = "renders"
+# sidecar = false                     # write cat.jpg.json next to each image
+
+# Named prompts, used as `imagen @hero_banner`.
+[prompts]
+# hero_banner = "wide cinematic banner, golden hour light"
+
+# Style presets, added to the prompt with `--style watercolor`.
+[styles]
+# watercolor = "soft watercolor, visible paper texture, muted palette"
+
+[history]
+# enabled = true
+# retention_days = 90
+
+# A profile, selected with `--profile work`, is merged over everything above.
+# [profiles.work.keys]
+# openai = "your-work-openai-key"
+# [profiles.work.defaults]
+# model = "gpt-1"
+"#;
+
+/// How `config show` marks a key that comes from the OS keychain.
+const KEYCHAIN: &str = "<keychain>";
+
+/// API keys in `[keys]`, with the environment variable that overrides each.
+const KEY_VARIABLES: &[(&str, &str)] = &[
+    ("gemini", "GEMINI_API_KEY"),
+    ("openai", "OPENAI_API_KEY"),
+    ("stability", "STABILITY_API_KEY"),
+    ("replicate", "REPLICATE_API_TOKEN"),
+    ("azure_openai", "AZURE_OPENAI_API_KEY"),
+    ("together", "TOGETHER_API_KEY"),
+    ("ideogram", "IDEOGRAM_API_KEY"),
+];
+
+/// Run a config subcommand.
+///
+/// # Errors
+///
+/// Returns an error if the config file cannot be read, parsed, or written, or if `init`
+/// would overwrite a file without `--force`.
+pub fn run(command: &ConfigCommand) -> Result<(), ImageError> {
+    match command {
+        ConfigCommand::Init { force, config } => {
+            init(&discover_config_path(config.as_deref()), *force)
+        }
+        ConfigCommand::Show(file) => {
+            let path = discover_config_path(file.config.as_deref());
+            let profile = selected_profile(file.profile.as_deref());
+            let config = Config::load(&path, profile.as_deref()).map_err(ImageError::Config)?;
+            let source = if path.exists() { "" } else { " (not found; built-in defaults)" };
+            match profile {
+                Some(profile) => println!("# {}{source}, profile {profile}", path.display()),
+                None => println!("# {}{source}", path.display()),
+            }
+            print!("{}", render(&config));
+            Ok(())
+        }
+        ConfigCommand::Set { key, value, file } => set(file, key, value),
+        ConfigCommand::SetKey { provider, delete } => set_key(provider, *delete),
+        ConfigCommand::Check(file) => check(file),
+        ConfigCommand::Path { config } => {
+            println!("{}", discover_config_path(config.as_deref()).display());
+            Ok(())
+        }
+    }
+}
+
+/// Print every problem with the config file, or report that it is fine.
+fn check(file: &ConfigFileArgs) -> Result<(), ImageError> {
+    let path = discover_config_path(file.config.as_deref());
+    let profile = selected_profile(file.profile.as_deref());
+    let mut problems =
+        if path.exists() { file_problems(&std::fs::read_to_string(&path)?) } else { Vec::new() };
+    // Settings are only checked once the file loads; until then its errors say why not.
+    if problems.is_empty() {
+        match Config::load(&path, profile.as_deref()) {
+            Ok(config) => problems.extend(setting_problems(&config)),
+            Err(e) => problems.push(e),
+        }
+    }
+    if problems.is_empty() {
+        eprintln!("{}: OK", path.display());
+        return Ok(());
+    }
+    for problem in &problems {
+        println!("{problem}");
+    }
+    let plural = if problems.len() == 1 { "" } else { "s" };
+    Err(ImageError::Config(format!("{} problem{plural} in {}", problems.len(), path.display())))
+}
+
+/// Syntax errors, then unknown or mistyped settings in the file and each of its profiles.
+fn file_problems(text: &str) -> Vec<String> {
+    let mut table: toml::Table = match toml::from_str(text) {
+        Ok(table) => table,
+        Err(e) => return vec![e.to_string().trim().to_string()],
+    };
+    let profiles = table.remove("profiles");
+    let mut problems = invalid_settings(&table, &[]);
+    match profiles {
+        None => {}
+        Some(toml::Value::Table(profiles)) => {
+            for (name, profile) in &profiles {
+                match profile {
+                    toml::Value::Table(profile) => {
+                        problems.extend(invalid_settings(profile, &["profiles", name]));
+                    }
+                    _ => problems.push(format!("profiles.{name}: a profile must be a table")),
+                }
+            }
+        }
+        Some(_) => problems.push("profiles: must be a table of profiles".into()),
+    }
+    problems
+}
+
+/// Default values the model's provider would reject, unknown fallback and refusal models,
+/// an unknown slug strategy, and missing credentials for the default model.
+fn setting_problems(config: &Config) -> Vec<String> {
+    let defaults = &config.defaults;
+    let provider = detect_provider(&resolve_model(&defaults.model));
+    let mut problems = Vec::new();
+    if let Err(e) = &provider {
+        problems.push(format!("defaults.model: {e}"));
+    }
+    let general = ModelDefaults {
+        aspect_ratio: Some(defaults.aspect_ratio.clone()),
+        size: Some(defaults.size.clone()),
+        quality: Some(defaults.quality.clone()),
+        format: Some(defaults.format.clone()),
+    };
+    problems.extend(value_problems("defaults", &general, provider.as_ref().ok().copied()));
+    for name in &defaults.fallback {
+        if let Err(e) = detect_provider(&resolve_model(name)) {
+            problems.push(format!("defaults.fallback: {e}"));
+        }
+    }
+    if let Some(model) = defaults.on_refusal.as_deref().filter(|action| *action != "soften") {
+        if let Err(e) = detect_provider(&resolve_model(model)) {
+            problems.push(format!("defaults.on_refusal: {e}"));
+        }
+    }
+
+    let mut models: Vec<_> = defaults.per_model.iter().collect();
+    models.sort_by_key(|(name, _)| name.as_str());
+    for (name, entry) in models {
+        let section = format!("defaults.per_model.{}", Key::new(name.as_str()).display_repr());
+        let model_provider = detect_provider(&resolve_model(name));
+        if let Err(e) = &model_provider {
+            problems.push(format!("{section}: {e}"));
+        }
+        problems.extend(value_problems(&section, entry, model_provider.ok()));
+    }
+
+    if let Some(slug) = &config.output.slug {
+        if let Err(e) = SlugStrategy::parse(slug) {
+            problems.push(format!("output.slug: {e}"));
+        }
+    }
+    if let Ok(provider) = provider {
+        if let Err(e) = check_credentials(provider, config) {
+            problems.push(format!("defaults.model: {} cannot run: {e}", defaults.model));
+        }
+    }
+    problems
+}
+
+/// The values in `entry` (under `section`) that are not valid, aspect ratios judged
+/// against `provider` when it is known.
+fn value_problems(section: &str, entry: &ModelDefaults, provider: Option<Provider>) -> Vec<String> {
+    let ratio = entry.aspect_ratio.as_deref().map(|ratio| {
+        parse_ratio_list(ratio).and_then(|ratios| match provider {
+            Some(provider) => ratios
+                .iter()
+                .try_for_each(|ratio| fit_aspect_ratio(ratio, provider, false).map(drop)),
+            None => Ok(()),
+        })
+    });
+    [
+        ("aspect_ratio", ratio),
+        ("size", entry.size.as_deref().map(validate_size)),
+        ("quality", entry.quality.as_deref().map(validate_quality)),
+        ("format", entry.format.as_deref().map(validate_format)),
+    ]
+    .into_iter()
+    .filter_map(|(name, result)| Some(format!("{section}.{name}: {}", result?.err()?)))
+    .collect()
+}
+
+/// Write the starter file to `path`, creating its directory.
+fn init(path: &Path, force: bool) -> Result<(), ImageError> {
+    if path.exists() && !force {
+        return Err(ImageError::InvalidArgument(format!(
+            "{} already exists; pass --force to overwrite it",
+            path.display()
+        )));
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, STARTER)?;
+    eprintln!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// The config as TOML: API keys (including those from the keychain and environment)
+/// redacted, and empty sections left out.
+fn render(config: &Config) -> String {
+    let Ok(toml::Value::Table(mut table)) = toml::Value::try_from(config) else {
+        unreachable!("the config serializes to a table");
+    };
+    let keys = table.entry("keys").or_insert_with(|| toml::Table::new().into());
+    if let Some(keys) = keys.as_table_mut() {
+        for (name, variable) in KEY_VARIABLES {
+            let value = if keychain::get(name).is_some() {
+                KEYCHAIN.to_string()
+            } else if std::env::var(variable).is_ok() {
+                format!("${variable}")
+            } else if keys.contains_key(*name) {
+                REDACTED.to_string()
+            } else {
+                continue;
+            };
+            keys.insert((*name).to_string(), value.into());
+        }
+        if let Some(toml::Value::String(url)) = keys.get_mut("openai_base_url") {
+            *url = redact_url_userinfo(url);
+        }
+    }
+    prune_empty(&mut table);
+    toml::to_string_pretty(&table).expect("a table serializes")
+}
+
+/// Remove tables that are empty, or hold only empty tables.
+fn prune_empty(table: &mut toml::Table) {
+    table.retain(|_, value| match value {
+        toml::Value::Table(inner) => {
+            prune_empty(inner);
+            !inner.is_empty()
+        }
+        _ => true,
+    });
+}
+
+/// Set `key` to `value` in the config file (under `[profiles.<name>]` with a profile),
+/// keeping the rest of the file as written.
+fn set(file: &ConfigFileArgs, key: &str, value: &str) -> Result<(), ImageError> {
+    let path = discover_config_path(file.config.as_deref());
+    let profile = selected_profile(file.profile.as_deref());
+    let text = if path.exists() { std::fs::read_to_string(&path)? } else { String::new() };
+    let mut document: DocumentMut = text.parse().map_err(|e| {
+        ImageError::Config(format!("Failed to parse config {}: {e}", path.display()))
+    })?;
+    let mut keys = Key::parse(key)
+        .map_err(|e| ImageError::InvalidArgument(format!("Invalid key '{key}': {e}")))?;
+    if let Some(ref profile) = profile {
+        keys.splice(0..0, [Key::new("profiles"), Key::new(profile.as_str())]);
+    }
+    let dotted: Vec<_> = keys.iter().map(Key::display_repr).collect();
+    let dotted = dotted.join(".");
+    let Some((last, parents)) = keys.split_last() else {
+        return Err(ImageError::InvalidArgument("Provide a key such as defaults.model".into()));
+    };
+    let mut table = document.as_table_mut() as &mut dyn toml_edit::TableLike;
+    for part in parents {
+        let item = table.entry(part.get()).or_insert_with(|| {
+            let mut implicit = toml_edit::Table::new();
+            implicit.set_implicit(true);
+            Item::Table(implicit)
+        });
+        table = item.as_table_like_mut().ok_or_else(|| {
+            ImageError::InvalidArgument(format!(
+                "Cannot set {dotted}: {} is not a table",
+                part.get()
+            ))
+        })?;
+    }
+    table.insert(last.get(), toml_edit::value(parse_value(value)));
+
+    // Refuse to write a file that imagen could no longer load.
+    let updated = document.to_string();
+    Config::parse_unexpanded(&updated, profile.as_deref())
+        .map_err(|e| ImageError::InvalidArgument(format!("Cannot set {dotted} to {value}: {e}")))?;
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, updated)?;
+    eprintln!("Set {dotted} in {}", path.display());
+    Ok(())
+}
+
+/// Store the key read from standard input in the OS keychain, or remove it.
+fn set_key(provider: &str, delete: bool) -> Result<(), ImageError> {
+    if delete {
+        if keychain::delete(provider)? {
+            eprintln!("Removed the {provider} key from the OS keychain");
+        } else {
+            eprintln!("No {provider} key in the OS keychain");
+        }
+        return Ok(());
+    }
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        eprint!("Paste the {provider} API key and press Enter: ");
+    }
+    let mut key = String::new();
+    stdin.read_line(&mut key)?;
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(ImageError::InvalidArgument(format!("No {provider} key given on stdin")));
+    }
+    keychain::set(provider, key)?;
+    eprintln!("Stored the {provider} key in the OS keychain");
+    Ok(())
+}
+
+/// `value` as TOML when it is valid TOML (`true`, `300`, `"text"`), else as a string.
+fn parse_value(value: &str) -> toml_edit::Value {
+    value.parse::<toml_edit::Value>().map_or_else(
+        |_| toml_edit::Value::from(value),
+        |mut parsed| {
+            parsed.decor_mut().clear();
+            parsed
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starter_file_is_a_valid_config() {
+        let config = Config::parse(STARTER, None).unwrap();
+        assert_eq!(config.defaults.model, "nano-banana");
+        assert!(config.keys.gemini.is_none());
+    }
+
+    #[test]
+    fn values_are_typed_when_they_are_toml() {
+        assert_eq!(parse_value("true").as_bool(), Some(true));
+        assert_eq!(parse_value("300").as_integer(), Some(300));
+        assert_eq!(parse_value("\"1:1\"").as_str(), Some("1:1"));
+        assert_eq!(parse_value("gpt-1").as_str(), Some("gpt-1"));
+        assert_eq!(parse_value("16:9").as_str(), Some("16:9"));
+    }
+}
@@ -0,0 +1,235 @@
+//! `imagen diff`: measure how far two images differ, for golden-image checks.
+//!
+//! The distance is `1 - SSIM` over the luma channel by default, or the Hamming distance
+//! between 64-bit difference hashes (`--metric phash`). Both run from 0 (identical) to
+//! 1, so one `--threshold` works for either.
+
+use image::{DynamicImage, GenericImageView, GrayImage, Rgb, RgbImage};
+
+use crate::error::ImageError;
+
+/// Side of the square windows SSIM is averaged over.
+const SSIM_WINDOW: u32 = 8;
+/// Step between SSIM windows (windows overlap by half).
+const SSIM_STEP: usize = 4;
+/// SSIM stabilizing constants for 8-bit values: `(0.01 * 255)^2` and `(0.03 * 255)^2`.
+const SSIM_C1: f64 = 6.5025;
+const SSIM_C2: f64 = 58.5225;
+
+/// How the distance between two images is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    /// `1 - SSIM`: sensitive to local structure and contrast changes.
+    Ssim,
+    /// Difference-hash distance: tolerant of re-encoding, flags composition changes.
+    Phash,
+}
+
+impl Metric {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "ssim" => Ok(Self::Ssim),
+            "phash" => Ok(Self::Phash),
+            other => Err(format!("Unknown metric '{other}'. Valid: ssim, phash")),
+        }
+    }
+}
+
+/// Compare `a` and `b`, print both distances, and write a diff image when asked.
+///
+/// # Errors
+///
+/// Returns [`ImageError::ImagesDiffer`] when the chosen distance exceeds `threshold`, and
+/// an error if an image cannot be read, the sizes differ, or the diff cannot be saved.
+pub fn run(
+    a: &str,
+    b: &str,
+    threshold: f64,
+    metric: &str,
+    diff_output: Option<&str>,
+) -> Result<(), ImageError> {
+    let metric = Metric::parse(metric).map_err(ImageError::InvalidArgument)?;
+    if !(0.0..=1.0).contains(&threshold) {
+        return Err(ImageError::InvalidArgument(format!(
+            "--threshold must be between 0 and 1, got {threshold}"
+        )));
+    }
+    let first = open(a)?;
+    let second = open(b)?;
+    if first.dimensions() != second.dimensions() {
+        let (w1, h1) = first.dimensions();
+        let (w2, h2) = second.dimensions();
+        return Err(ImageError::InvalidArgument(format!(
+            "Images differ in size: {a} is {w1}x{h1}, {b} is {w2}x{h2}"
+        )));
+    }
+
+    let ssim_distance = 1.0 - ssim(&first.to_luma8(), &second.to_luma8());
+    let hash_bits = (dhash(&first) ^ dhash(&second)).count_ones();
+    println!("ssim distance: {ssim_distance:.4}");
+    println!("phash distance: {:.4} ({hash_bits}/64 bits)", f64::from(hash_bits) / 64.0);
+
+    if let Some(path) = diff_output {
+        diff_image(&first.to_rgb8(), &second.to_rgb8())
+            .save(path)
+            .map_err(|e| ImageError::ImageConversion(format!("Failed to save {path}: {e}")))?;
+        tracing::info!(path, "Saved: {path}");
+    }
+
+    let distance = match metric {
+        Metric::Ssim => ssim_distance,
+        Metric::Phash => f64::from(hash_bits) / 64.0,
+    };
+    if distance > threshold {
+        return Err(ImageError::ImagesDiffer { distance, threshold });
+    }
+    Ok(())
+}
+
+fn open(path: &str) -> Result<DynamicImage, ImageError> {
+    image::open(path)
+        .map_err(|e| ImageError::ImageConversion(format!("Failed to read {path}: {e}")))
+}
+
+/// Mean structural similarity of two equally sized grayscale images (1.0 = identical).
+fn ssim(a: &GrayImage, b: &GrayImage) -> f64 {
+    let (width, height) = a.dimensions();
+    let window = |len: u32| SSIM_WINDOW.min(len);
+    let (win_w, win_h) = (window(width), window(height));
+    let starts = |len: u32, win: u32| (0..=len - win).step_by(SSIM_STEP);
+
+    let mut total = 0.0;
+    let mut count = 0u32;
+    for y in starts(height, win_h) {
+        for x in starts(width, win_w) {
+            total += window_ssim(a, b, x, y, win_w, win_h);
+            count += 1;
+        }
+    }
+    total / f64::from(count)
+}
+
+/// SSIM of one `width` x `height` window at (`left`, `top`).
+fn window_ssim(a: &GrayImage, b: &GrayImage, left: u32, top: u32, width: u32, height: u32) -> f64 {
+    let pixels = f64::from(width * height);
+    let (mut total_a, mut total_b) = (0.0, 0.0);
+    let (mut squares_a, mut squares_b, mut products) = (0.0, 0.0, 0.0);
+    for y in top..top + height {
+        for x in left..left + width {
+            let pa = f64::from(a.get_pixel(x, y)[0]);
+            let pb = f64::from(b.get_pixel(x, y)[0]);
+            total_a += pa;
+            total_b += pb;
+            squares_a += pa * pa;
+            squares_b += pb * pb;
+            products += pa * pb;
+        }
+    }
+    let (mean_a, mean_b) = (total_a / pixels, total_b / pixels);
+    let var_a = squares_a / pixels - mean_a * mean_a;
+    let var_b = squares_b / pixels - mean_b * mean_b;
+    let covariance = products / pixels - mean_a * mean_b;
+    ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * covariance + SSIM_C2))
+        / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2))
+}
+
+/// 64-bit difference hash: each bit says whether a pixel of a 9x8 thumbnail is brighter
+/// than its right-hand neighbor.
+fn dhash(image: &DynamicImage) -> u64 {
+    let thumb = image.resize_exact(9, 8, image::imageops::FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = thumb.get_pixel(x, y)[0] > thumb.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | u64::from(brighter);
+        }
+    }
+    hash
+}
+
+/// `a` dimmed to gray, with every differing pixel painted red in proportion to how much
+/// it changed.
+fn diff_image(a: &RgbImage, b: &RgbImage) -> RgbImage {
+    RgbImage::from_fn(a.width(), a.height(), |x, y| {
+        let (pa, pb) = (a.get_pixel(x, y), b.get_pixel(x, y));
+        let change = pa.0.iter().zip(pb.0).map(|(ca, cb)| ca.abs_diff(cb)).max().unwrap_or(0);
+        let luma = (u16::from(pa[0]) * 3 + u16::from(pa[1]) * 6 + u16::from(pa[2])) / 10;
+        let gray = u8::try_from(luma / 4).unwrap_or(u8::MAX);
+        if change == 0 {
+            Rgb([gray, gray, gray])
+        } else {
+            Rgb([gray.saturating_add(change.max(64)), gray, gray])
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A horizontal gradient with a bright square, so SSIM and dHash have structure to see.
+    fn sample() -> RgbImage {
+        RgbImage::from_fn(32, 32, |x, y| {
+            let v = u8::try_from(x * 8).unwrap();
+            if (8..16).contains(&x) && (8..16).contains(&y) {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([v, v / 2, 255 - v])
+            }
+        })
+    }
+
+    #[test]
+    fn identical_images_have_zero_distance() {
+        let a = DynamicImage::ImageRgb8(sample());
+        assert!((ssim(&a.to_luma8(), &a.to_luma8()) - 1.0).abs() < 1e-9);
+        assert_eq!(dhash(&a), dhash(&a.clone()));
+    }
+
+    #[test]
+    fn changes_increase_the_distance() {
+        let a = sample();
+        let mut slight = a.clone();
+        slight.put_pixel(0, 0, Rgb([0, 0, 0]));
+        let flipped = image::imageops::flip_horizontal(&a);
+
+        let distance = |b: &RgbImage| {
+            1.0 - ssim(
+                &DynamicImage::ImageRgb8(a.clone()).to_luma8(),
+                &DynamicImage::ImageRgb8(b.clone()).to_luma8(),
+            )
+        };
+        assert!(distance(&slight) > 0.0);
+        assert!(distance(&slight) < 0.02);
+        assert!(distance(&flipped) > 0.1);
+        let bits = (dhash(&DynamicImage::ImageRgb8(a.clone()))
+            ^ dhash(&DynamicImage::ImageRgb8(flipped)))
+        .count_ones();
+        assert!(bits > 16, "got {bits}");
+    }
+
+    #[test]
+    fn tiny_images_use_one_window() {
+        let a = GrayImage::from_pixel(3, 2, image::Luma([10]));
+        let b = GrayImage::from_pixel(3, 2, image::Luma([200]));
+        assert!(ssim(&a, &b) < 0.5);
+    }
+
+    #[test]
+    fn diff_image_marks_changed_pixels() {
+        let a = sample();
+        let mut b = a.clone();
+        b.put_pixel(3, 4, Rgb([0, 0, 0]));
+        let diff = diff_image(&a, &b);
+        let changed = diff.get_pixel(3, 4);
+        assert!(changed[0] > changed[1]);
+        let same = diff.get_pixel(20, 20);
+        assert_eq!(same[0], same[1]);
+    }
+
+    #[test]
+    fn metric_names() {
+        assert_eq!(Metric::parse("phash"), Ok(Metric::Phash));
+        assert!(Metric::parse("mse").unwrap_err().contains("Valid: ssim"));
+    }
+}
@@ -0,0 +1,460 @@
+//! `imagen gc`: prune stale local imagen state and reclaim the disk space it used.
+//!
+//! In order: history entries older than the retention window, what runs left behind
+//! (`--transactional` staging directories, `--stream` previews, sessions that never
+//! saved a turn), and cassettes that no longer parse or hold no interactions. The
+//! store is compacted last, so it gives back the pages the deleted rows freed.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use chrono::{TimeDelta, Utc};
+
+use super::session::SESSION_DIR_PREFIX;
+use crate::cassette::config::read_cassette;
+use crate::config::{self, Config};
+use crate::error::ImageError;
+use crate::history::History;
+use crate::output::{is_preview_file_name, PREVIEW_DIR, STAGING_PREFIX};
+use crate::report;
+use crate::store::Store;
+
+/// Days of history kept when neither `--retention-days` nor the config says.
+const DEFAULT_RETENTION_DAYS: u32 = 90;
+
+/// Filename ending of the cassettes gc considers; other YAML files are never touched.
+const CASSETTE_SUFFIX: &str = ".cassette.yaml";
+
+/// Age past which a run's leftover directory is removed; younger ones may belong to a
+/// run still in progress.
+const LEFTOVER_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Run garbage collection, printing what was (or with `dry_run`, would be) removed
+/// and reclaimed.
+///
+/// # Errors
+///
+/// Returns an error if the config cannot be loaded, or the store cannot be opened,
+/// pruned, or compacted.
+pub fn run(
+    dry_run: bool,
+    retention_days: Option<u32>,
+    config_path: Option<&str>,
+    profile: Option<&str>,
+) -> Result<(), ImageError> {
+    let config = Config::load(&config::discover_config_path(config_path), profile)
+        .map_err(ImageError::Config)?;
+    let retention_days =
+        retention_days.or(config.history.retention_days).unwrap_or(DEFAULT_RETENTION_DAYS);
+    let store = Store::default_path().filter(|path| path.exists());
+
+    let mut dirs: BTreeSet<PathBuf> = std::env::current_dir().into_iter().collect();
+    if let Some(path) = &store {
+        dirs.extend(prune_history(path, retention_days, dry_run)?);
+    }
+    let mut total = prune_leftovers(&dirs, SystemTime::now() - LEFTOVER_AGE, dry_run);
+    let root = repository_root();
+    let cassette_dirs = std::iter::once(config.cassette_dir())
+        .chain(std::env::var_os("IMAGEN_REC_DIR").map(PathBuf::from));
+    for dir in cassette_dirs {
+        match anchored(&dir, root.as_deref()) {
+            Some(dir) => total += prune_cassettes(&dir, dry_run),
+            None => println!("Skipped {}: relative, outside a git repository", dir.display()),
+        }
+    }
+    if let Some(path) = &store {
+        total += compact_store(path, dry_run)?;
+    }
+
+    let verb = if dry_run { "Would reclaim" } else { "Reclaimed" };
+    println!("{verb} {} total.", format_bytes(total));
+    Ok(())
+}
+
+/// "Removed", or "Would remove" for a dry run.
+fn removing(dry_run: bool) -> &'static str {
+    if dry_run {
+        "Would remove"
+    } else {
+        "Removed"
+    }
+}
+
+/// Delete history entries older than `retention_days`, unless it is 0. Returns the
+/// directories the history saved into, where runs may have left directories behind.
+///
+/// Younger entries stay even when their file has since changed or gone: they still
+/// answer `imagen history`, and `--cache` checks each file's hash before reusing it.
+fn prune_history(
+    path: &Path,
+    retention_days: u32,
+    dry_run: bool,
+) -> Result<BTreeSet<PathBuf>, ImageError> {
+    let mut history = History::open(path)?;
+    let files = history.saved_files()?;
+    if retention_days > 0 {
+        let expiry = Utc::now() - TimeDelta::days(i64::from(retention_days));
+        let expired: Vec<i64> =
+            files.iter().filter(|file| file.created_at < expiry).map(|file| file.id).collect();
+        if !dry_run {
+            history.delete(&expired)?;
+        }
+        let count = expired.len();
+        println!("{} {count} history entries older than {retention_days} days", removing(dry_run));
+    }
+    Ok(files.iter().filter_map(|file| file.output_path.parent()).map(Path::to_path_buf).collect())
+}
+
+/// Remove the `--transactional` staging directories, `--stream` previews, and empty
+/// default-named session directories in `dirs` last modified before `cutoff`,
+/// returning the bytes freed (or freeable, for a dry run).
+fn prune_leftovers(dirs: &BTreeSet<PathBuf>, cutoff: SystemTime, dry_run: bool) -> u64 {
+    let mut freed = 0;
+    for entry in dirs.iter().filter_map(|dir| std::fs::read_dir(dir).ok()).flatten().flatten() {
+        let path = entry.path();
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name == PREVIEW_DIR && entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            freed += prune_previews(&path, cutoff, dry_run);
+            continue;
+        }
+        let leftover = name.starts_with(STAGING_PREFIX)
+            || (name.starts_with(SESSION_DIR_PREFIX)
+                && std::fs::read_dir(&path).is_ok_and(|mut turns| turns.next().is_none()));
+        let stale = entry.metadata().is_ok_and(|metadata| {
+            metadata.is_dir() && metadata.modified().is_ok_and(|modified| modified < cutoff)
+        });
+        if !leftover || !stale {
+            continue;
+        }
+        let bytes = disk_usage(&path);
+        if !dry_run {
+            if let Err(e) = std::fs::remove_dir_all(&path) {
+                report::warn(format_args!("could not remove {}: {e}", path.display()));
+                continue;
+            }
+        }
+        println!("{} {}: {}", removing(dry_run), path.display(), format_bytes(bytes));
+        freed += bytes;
+    }
+    freed
+}
+
+/// Remove the previews in the `--stream` preview directory `dir` written before
+/// `cutoff`, and the directory once that empties it, returning the bytes freed (or
+/// freeable, for a dry run). Files without a preview's name are left alone.
+fn prune_previews(dir: &Path, cutoff: SystemTime, dry_run: bool) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut freed = 0;
+    for entry in entries.flatten() {
+        let stale = entry.metadata().is_ok_and(|metadata| {
+            metadata.is_file() && metadata.modified().is_ok_and(|modified| modified < cutoff)
+        });
+        if !stale || !is_preview_file_name(&entry.file_name().to_string_lossy()) {
+            continue;
+        }
+        let path = entry.path();
+        let bytes = disk_usage(&path);
+        if !dry_run {
+            if let Err(e) = std::fs::remove_file(&path) {
+                report::warn(format_args!("could not remove {}: {e}", path.display()));
+                continue;
+            }
+        }
+        freed += bytes;
+    }
+    if freed > 0 {
+        if !dry_run {
+            // Fails, harmlessly, while other files remain.
+            let _ = std::fs::remove_dir(dir);
+        }
+        println!("{} previews in {}: {}", removing(dry_run), dir.display(), format_bytes(freed));
+    }
+    freed
+}
+
+/// Remove the cassettes under `dir` that fail to parse or hold no interactions,
+/// returning the bytes freed (or freeable, for a dry run). Directories left empty go
+/// with them.
+fn prune_cassettes(dir: &Path, dry_run: bool) -> u64 {
+    let mut freed = 0;
+    for path in cassette_files(dir) {
+        let reason = match read_cassette(&path) {
+            Ok(cassette) if cassette.interactions.is_empty() => "no interactions".to_string(),
+            Ok(_) => continue,
+            Err(e) => e,
+        };
+        let bytes = disk_usage(&path);
+        if !dry_run {
+            if let Err(e) = std::fs::remove_file(&path) {
+                report::warn(format_args!("could not remove {}: {e}", path.display()));
+                continue;
+            }
+            if let Some(parent) = path.parent().filter(|parent| *parent != dir) {
+                // Fails, harmlessly, while other recordings remain.
+                let _ = std::fs::remove_dir(parent);
+            }
+        }
+        println!("{} {} ({reason}): {}", removing(dry_run), path.display(), format_bytes(bytes));
+        freed += bytes;
+    }
+    freed
+}
+
+/// Every `*.cassette.yaml` file under `dir`, at any depth. Symlinks are not followed.
+fn cassette_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            files.extend(cassette_files(&entry.path()));
+        } else if file_type.is_file()
+            && entry.file_name().to_string_lossy().ends_with(CASSETTE_SUFFIX)
+        {
+            files.push(entry.path());
+        }
+    }
+    files
+}
+
+/// `dir` if absolute, else `dir` under the repository `root`, so a relative cassette
+/// directory names the same place wherever in the project gc runs. `None` for a
+/// relative `dir` outside any repository.
+fn anchored(dir: &Path, root: Option<&Path>) -> Option<PathBuf> {
+    if dir.is_absolute() {
+        Some(dir.to_path_buf())
+    } else {
+        root.map(|root| root.join(dir))
+    }
+}
+
+/// Top of the git work tree the current directory is in.
+fn repository_root() -> Option<PathBuf> {
+    std::process::Command::new("git")
+        .args(["rev-parse", "--show-toplevel"])
+        .output()
+        .ok()
+        .filter(|o| o.status.success())
+        .and_then(|o| String::from_utf8(o.stdout).ok())
+        .map(|s| PathBuf::from(s.trim()))
+}
+
+/// Total size of the file, or of every file under the directory, at `path`. Symlinks
+/// count as themselves.
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::symlink_metadata(path) else {
+        return 0;
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+    std::fs::read_dir(path)
+        .map_or(0, |entries| entries.flatten().map(|entry| disk_usage(&entry.path())).sum())
+}
+
+/// Compact the shared store, returning the bytes freed (or freeable, for a dry run).
+fn compact_store(path: &Path, dry_run: bool) -> Result<u64, ImageError> {
+    if !path.exists() {
+        return Ok(0);
+    }
+    let mut store = Store::open(path)?;
+    let reclaimed = if dry_run {
+        store.reclaimable_bytes(path)?
+    } else {
+        let before = Store::disk_usage(path);
+        store.compact()?;
+        before.saturating_sub(Store::disk_usage(path))
+    };
+    println!("Store {}: {}", path.display(), format_bytes(reclaimed));
+    Ok(reclaimed)
+}
+
+/// Human-readable byte count (`512 B`, `1.5 KB`, `12.0 MB`).
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    #[allow(clippy::cast_precision_loss)] // Display only.
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::history::Entry;
+    use crate::manifest::file_sha256;
+
+    /// An empty directory of its own for one test.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("imagen_gc_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn saved(path: &Path, days_ago: i64) -> Entry {
+        Entry {
+            created_at: Utc::now() - TimeDelta::days(days_ago),
+            request_id: "3f2a9c1e7b4d5a60".into(),
+            operation: "generate".into(),
+            provider: "openai".into(),
+            model: "gpt-image-1".into(),
+            prompt: "a cat".into(),
+            parameters: BTreeMap::new(),
+            output_path: path.to_path_buf(),
+            sha256: file_sha256(path).unwrap(),
+            cost_usd: None,
+        }
+    }
+
+    #[test]
+    fn only_expired_history_is_pruned() {
+        let dir = scratch("history");
+        let db = dir.join("imagen.db");
+        let write = |name: &str, contents: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, contents).unwrap();
+            path
+        };
+        let (old, kept) = (write("old.png", "old"), write("kept.png", "kept"));
+        let (changed, gone) = (write("changed.png", "before"), write("gone.png", "gone"));
+        let entries = [saved(&old, 100), saved(&kept, 1), saved(&changed, 1), saved(&gone, 1)];
+        History::open(&db).unwrap().record(&entries).unwrap();
+        std::fs::write(&changed, "after").unwrap();
+        std::fs::remove_file(&gone).unwrap();
+        let remaining = || -> Vec<PathBuf> {
+            let files = History::open(&db).unwrap().saved_files().unwrap();
+            files.into_iter().map(|file| file.output_path).collect()
+        };
+
+        assert_eq!(prune_history(&db, 90, true).unwrap(), BTreeSet::from([dir.clone()]));
+        assert_eq!(remaining().len(), 4);
+        // Entries whose file changed or went stay for `imagen history`.
+        prune_history(&db, 90, false).unwrap();
+        assert_eq!(remaining(), [kept.clone(), changed.clone(), gone.clone()]);
+
+        // A retention of 0 keeps entries of any age.
+        History::open(&db).unwrap().record(&[saved(&old, 1000)]).unwrap();
+        prune_history(&db, 0, false).unwrap();
+        assert_eq!(remaining(), [old, kept, changed, gone]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn leftover_run_directories_are_removed() {
+        let dir = scratch("leftovers");
+        for (leftover, file) in [
+            (".imagen-staging-01JQ3", Some("cat.png")),
+            (".partial", Some("3f2a9c1e7b4d5a60-1.png")),
+            (".partial", Some("reference.png")),
+            ("imagen-session-2026-03-01T10-00-00", None),
+            ("imagen-session-2026-03-02T10-00-00", Some("01-a-cat.png")),
+            ("renders", Some("cat.png")),
+        ] {
+            std::fs::create_dir_all(dir.join(leftover)).unwrap();
+            if let Some(file) = file {
+                std::fs::write(dir.join(leftover).join(file), "1234").unwrap();
+            }
+        }
+        let dirs = BTreeSet::from([dir.clone()]);
+        let later = SystemTime::now() + Duration::from_secs(60);
+
+        // Directories younger than the cutoff may belong to a run still going.
+        assert_eq!(prune_leftovers(&dirs, SystemTime::now() - LEFTOVER_AGE, false), 0);
+        assert_eq!(prune_leftovers(&dirs, later, true), 8);
+        assert!(dir.join(".partial/3f2a9c1e7b4d5a60-1.png").exists());
+        assert_eq!(prune_leftovers(&dirs, later, false), 8);
+        let names = |dir: &Path| {
+            let mut names: Vec<String> = std::fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+                .collect();
+            names.sort();
+            names
+        };
+        assert_eq!(names(&dir), [".partial", "imagen-session-2026-03-02T10-00-00", "renders"]);
+        // Only the previews go; a file imagen did not name keeps the directory.
+        assert_eq!(names(&dir.join(".partial")), ["reference.png"]);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unreadable_and_empty_cassettes_are_removed() {
+        let dir = scratch("cassettes");
+        let fixture =
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("test_fixtures/gemini_cat.cassette.yaml");
+        let cassette = |name: &str| {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            path
+        };
+        let good = cassette("2026-03-01T10-00-00/image_generator.cassette.yaml");
+        std::fs::copy(&fixture, &good).unwrap();
+        let broken = cassette("2026-03-02T10-00-00/image_generator.cassette.yaml");
+        std::fs::write(&broken, "name: [truncated").unwrap();
+        let empty = cassette("empty.cassette.yaml");
+        std::fs::write(
+            &empty,
+            "name: empty\nrecorded_at: 2026-03-01T10:00:00Z\ncommit: abc1234\ninteractions: []\n",
+        )
+        .unwrap();
+        let notes = cassette("notes.txt");
+        std::fs::write(&notes, "not a cassette").unwrap();
+        let pipeline = cassette("pipeline.yaml");
+        std::fs::write(&pipeline, "steps: [").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(&dir, dir.join("loop")).unwrap();
+
+        assert!(prune_cassettes(&dir, true) > 0);
+        assert!(broken.exists() && empty.exists());
+        prune_cassettes(&dir, false);
+        assert!(good.exists() && notes.exists() && pipeline.exists());
+        assert!(!empty.exists());
+        assert!(!broken.parent().unwrap().exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn relative_cassette_dirs_are_taken_from_the_repository() {
+        let root = Path::new("/work/project");
+        let absolute = Path::new("/var/cassettes");
+        assert_eq!(anchored(absolute, None).unwrap(), absolute);
+        assert_eq!(
+            anchored(Path::new(".imagen/cassettes"), Some(root)).unwrap(),
+            root.join(".imagen/cassettes")
+        );
+        assert_eq!(anchored(Path::new(".imagen/cassettes"), None), None);
+    }
+
+    #[test]
+    fn byte_formatting() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1536), "1.5 KB");
+        assert_eq!(format_bytes(12 * 1024 * 1024), "12.0 MB");
+    }
+
+    #[test]
+    fn missing_store_is_skipped() {
+        let path = std::env::temp_dir().join("imagen_gc_missing").join("imagen.db");
+        assert_eq!(compact_store(&path, false).unwrap(), 0);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,115 @@
+//! `imagen history`: find earlier generations in the local history.
+
+use chrono::{DateTime, NaiveDate, TimeDelta, Utc};
+
+use crate::cli::{HistoryArgs, HistoryCommand};
+use crate::error::ImageError;
+use crate::history::{Entry, Filter, History};
+use crate::model::resolve_model;
+use crate::store::Store;
+
+/// Print the history entries the command asks for, newest first.
+///
+/// # Errors
+///
+/// Returns an error for an invalid `--since`, or if the store cannot be read.
+///
+/// # Panics
+///
+/// Panics if an entry fails to serialize, which its plain fields rule out.
+pub fn run(command: &HistoryCommand) -> Result<(), ImageError> {
+    let (args, query) = match command {
+        HistoryCommand::List(args) => (args, None),
+        HistoryCommand::Search { query, args } => (args, Some(query)),
+    };
+    let filter = filter(args, query, Utc::now()).map_err(ImageError::InvalidArgument)?;
+    let path = Store::default_path().ok_or_else(|| {
+        ImageError::Config("Cannot locate the data directory; set IMAGEN_DATA_DIR".into())
+    })?;
+    let entries = if path.exists() { History::open(&path)?.query(&filter)? } else { Vec::new() };
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&entries).expect("history entries serialize");
+        println!("{json}");
+    } else if entries.is_empty() {
+        eprintln!("No matching images in history.");
+    } else {
+        for entry in &entries {
+            print!("{}", describe(entry));
+        }
+    }
+    Ok(())
+}
+
+/// The query for `args`, plus the `search` text.
+fn filter(
+    args: &HistoryArgs,
+    query: Option<&String>,
+    now: DateTime<Utc>,
+) -> Result<Filter, String> {
+    Ok(Filter {
+        request_id: None,
+        model: args.model.as_deref().map(resolve_model),
+        since: args.since.as_deref().map(|since| parse_since(since, now)).transpose()?,
+        prompt_contains: query.into_iter().chain(&args.grep).cloned().collect(),
+        limit: args.limit,
+    })
+}
+
+/// `7d` (or `30m`, `12h`, `2w`) before `now`, or the start of a `2026-03-01` day in UTC.
+fn parse_since(since: &str, now: DateTime<Utc>) -> Result<DateTime<Utc>, String> {
+    let invalid = || {
+        format!(
+            "Invalid --since '{since}': use a duration like 12h or 7d, or a date like 2026-03-01"
+        )
+    };
+    if let Ok(date) = NaiveDate::parse_from_str(since, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight exists").and_utc());
+    }
+    let split = since.find(|c: char| !c.is_ascii_digit()).ok_or_else(invalid)?;
+    let amount: i64 = since[..split].parse().map_err(|_| invalid())?;
+    let delta = match &since[split..] {
+        "m" => TimeDelta::try_minutes(amount),
+        "h" => TimeDelta::try_hours(amount),
+        "d" => TimeDelta::try_days(amount),
+        "w" => TimeDelta::try_weeks(amount),
+        _ => None,
+    };
+    delta.and_then(|delta| now.checked_sub_signed(delta)).ok_or_else(invalid)
+}
+
+/// An entry as a few lines: when, which model, and where, then the prompt and settings.
+fn describe(entry: &Entry) -> String {
+    let settings: Vec<String> = entry
+        .parameters
+        .iter()
+        .filter(|(name, _)| !matches!(name.as_str(), "model" | "request_id"))
+        .map(|(name, value)| format!("{name}={value}"))
+        .collect();
+    format!(
+        "{}  {}  {}\n  {}\n  {}\n",
+        entry.created_at.format("%Y-%m-%d %H:%M"),
+        entry.model,
+        entry.output_path.display(),
+        entry.prompt,
+        settings.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_takes_durations_and_dates() {
+        let now = DateTime::parse_from_rfc3339("2026-03-10T12:00:00Z").unwrap().to_utc();
+        let at = |text| DateTime::parse_from_rfc3339(text).unwrap().to_utc();
+        assert_eq!(parse_since("7d", now).unwrap(), at("2026-03-03T12:00:00Z"));
+        assert_eq!(parse_since("12h", now).unwrap(), at("2026-03-10T00:00:00Z"));
+        assert_eq!(parse_since("2w", now).unwrap(), at("2026-02-24T12:00:00Z"));
+        assert_eq!(parse_since("2026-03-01", now).unwrap(), at("2026-03-01T00:00:00Z"));
+        for bad in ["7", "d", "7y", "-7d", "yesterday"] {
+            assert!(parse_since(bad, now).is_err(), "{bad} should be rejected");
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! Utility subcommands that run instead of image generation.
+
+pub mod bench;
+pub mod cassette;
+pub mod config;
+pub mod diff;
+pub mod gc;
+pub mod history;
+pub mod models;
+pub mod pipeline;
+pub mod session;
+pub mod upscale;
+
+use crate::cli::Command;
+use crate::error::ImageError;
+
+/// Dispatch a parsed subcommand.
+///
+/// # Errors
+///
+/// Returns whatever error the subcommand reports.
+pub async fn run(command: &Command) -> Result<(), ImageError> {
+    match command {
+        Command::Bench { providers, models, runs, config, profile } => {
+            bench::run(providers, models, *runs, config.as_deref(), profile.as_deref()).await
+        }
+        Command::Cassette(cmd) => cassette::run(cmd),
+        Command::Config(cmd) => config::run(cmd),
+        Command::Diff { a, b, threshold, metric, diff_output } => {
+            diff::run(a, b, *threshold, metric, diff_output.as_deref())
+        }
+        Command::Gc { dry_run, retention_days, config, profile } => {
+            gc::run(*dry_run, *retention_days, config.as_deref(), profile.as_deref())
+        }
+        Command::History(cmd) => history::run(cmd),
+        Command::Models { json } => {
+            models::run(*json);
+            Ok(())
+        }
+        Command::Pipeline(cmd) => pipeline::run(cmd).await,
+        Command::Session(args) => session::run(args).await,
+        Command::Upscale(args) => upscale::run(args).await,
+        Command::Edit(_) | Command::Batch(_) | Command::Compare(_) => {
+            unreachable!("imagen edit, batch, and compare run through the generation pipeline")
+        }
+    }
+}
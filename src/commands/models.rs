@@ -0,0 +1,112 @@
+//! `imagen models`: the built-in model names and what each provider supports.
+
+use std::fmt::Write as _;
+
+use serde::Serialize;
+
+use crate::model::{detect_provider, known_models, resolve_model, Provider};
+use crate::params::{
+    aspect_ratios, max_count, supported_qualities, supported_sizes, THINKING_LEVELS,
+};
+
+/// One model name and the settings its provider accepts.
+#[derive(Debug, Serialize)]
+struct ModelInfo {
+    /// Name as typed with `--model`.
+    name: &'static str,
+    /// Model it resolves to.
+    model: String,
+    /// Provider that serves it.
+    provider: String,
+    /// `--aspect-ratio` values.
+    aspect_ratios: &'static [&'static str],
+    /// `--size` values that render differently; empty when the provider ignores size.
+    sizes: &'static [&'static str],
+    /// `--quality` values the provider honors; empty when it ignores quality.
+    qualities: &'static [&'static str],
+    /// `--thinking` levels; empty when the provider has none.
+    thinking: &'static [&'static str],
+    /// Most images one request returns; `None` when they are rendered one at a time or
+    /// the limit depends on the model.
+    max_count: Option<u32>,
+}
+
+/// Print the known models as a table, or as JSON with `json`.
+///
+/// # Panics
+///
+/// Panics if the catalog fails to serialize, which its plain fields rule out.
+pub fn run(json: bool) {
+    let models = catalog();
+    if json {
+        println!("{}", serde_json::to_string_pretty(&models).expect("model info serializes"));
+    } else {
+        print!("{}", render_table(&models));
+    }
+}
+
+/// Every alias and representative model, with its provider's capabilities.
+fn catalog() -> Vec<ModelInfo> {
+    known_models()
+        .into_iter()
+        .filter_map(|name| {
+            let model = resolve_model(name);
+            let provider = detect_provider(&model).ok()?;
+            Some(ModelInfo {
+                name,
+                provider: format!("{provider:?}").to_lowercase(),
+                aspect_ratios: aspect_ratios(provider),
+                sizes: supported_sizes(provider, &model),
+                qualities: supported_qualities(provider, &model),
+                thinking: if provider == Provider::Gemini { THINKING_LEVELS } else { &[] },
+                max_count: max_count(provider),
+                model,
+            })
+        })
+        .collect()
+}
+
+/// The models as aligned columns, one per line.
+fn render_table(models: &[ModelInfo]) -> String {
+    let list = |values: &[&str]| if values.is_empty() { "-".to_string() } else { values.join(",") };
+    let name_width = models.iter().map(|m| m.name.len()).max().unwrap_or(0).max("NAME".len());
+    let model_width = models.iter().map(|m| m.model.len()).max().unwrap_or(0).max("MODEL".len());
+    let mut out = format!(
+        "{:<name_width$}  {:<model_width$}  {:<11}  {:<8}  {:<20}  {:<8}  {:>3}  {}\n",
+        "NAME", "MODEL", "PROVIDER", "SIZES", "QUALITY", "THINKING", "MAX", "ASPECT RATIOS"
+    );
+    for m in models {
+        let max = m.max_count.map_or_else(|| "-".to_string(), |max| max.to_string());
+        let thinking = if m.thinking.is_empty() { "-" } else { "yes" };
+        let _ = writeln!(
+            out,
+            "{:<name_width$}  {:<model_width$}  {:<11}  {:<8}  {:<20}  {thinking:<8}  {max:>3}  {}",
+            m.name,
+            m.model,
+            m.provider,
+            list(m.sizes),
+            list(m.qualities),
+            list(m.aspect_ratios)
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn catalog_lists_aliases_and_provider_models() {
+        let models = catalog();
+        let banana = models.iter().find(|m| m.name == "nano-banana").unwrap();
+        assert_eq!(banana.model, "gemini-3.1-flash-image-preview");
+        assert_eq!(banana.provider, "gemini");
+        assert_eq!(banana.max_count, Some(1));
+        assert!(banana.qualities.is_empty() && !banana.thinking.is_empty());
+        let core = models.iter().find(|m| m.name == "stable-image-core").unwrap();
+        assert_eq!((core.provider.as_str(), core.max_count), ("stability", None));
+        assert!(core.sizes.is_empty());
+        assert!(models.iter().any(|m| m.name == "sd-local"));
+    }
+}
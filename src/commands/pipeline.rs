@@ -0,0 +1,377 @@
+//! `imagen pipeline run`: execute a pipeline file wave by wave.
+//!
+//! Steps of one wave run concurrently. Each step's images are saved to the output
+//! directory as soon as it finishes, and the run state is written after every wave, so
+//! an interrupted or failed run picks up where it stopped: steps whose options and input
+//! files are unchanged are reported as cached and not sent again.
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use futures_util::future::join_all;
+use image::{DynamicImage, GenericImageView};
+
+use crate::cli::PipelineCommand;
+use crate::commands::upscale::{fit_to, MAX_EDGE};
+use crate::config::{self, Config};
+use crate::context::{self, Operation, ServiceContext};
+use crate::error::ImageError;
+use crate::input::fit_input_images;
+use crate::model::{detect_provider, resolve_model};
+use crate::output::{check_encoder, save_image, PostProcess};
+use crate::params::{
+    format_extension, mime_type_from_extension, nearest_aspect_ratio, provider_format,
+    warn_unsupported,
+};
+use crate::pipeline::{
+    cache_key, parse_position, parse_size, Action, FinishedStep, GenerateStep, Pipeline, RunState,
+    Step, WatermarkStep,
+};
+use crate::ports::{ImageRequest, InputImage, UpscaleRequest};
+use crate::post::{self, Crop};
+use crate::report;
+use crate::watermark::{Mark, Position, Watermark};
+
+/// Prompt sent with `remove-bg` steps.
+const REMOVE_BG_PROMPT: &str =
+    "Remove the background completely, keeping the subject exactly as it is.";
+
+/// An image a step produced, before it is saved.
+struct Produced {
+    /// Name suffix distinguishing it from the step's other images (e.g. `1200x630`).
+    label: Option<String>,
+    data: Vec<u8>,
+    mime_type: String,
+}
+
+/// Dispatch a pipeline subcommand.
+///
+/// # Errors
+///
+/// Returns an error if the pipeline file is invalid or a step fails.
+pub async fn run(command: &PipelineCommand) -> Result<(), ImageError> {
+    let PipelineCommand::Run { file, fresh, config, profile } = command;
+    let text = std::fs::read_to_string(file)
+        .map_err(|e| ImageError::InvalidArgument(format!("Failed to read {file}: {e}")))?;
+    let pipeline =
+        Pipeline::parse(&text).map_err(|e| ImageError::InvalidArgument(format!("{file}: {e}")))?;
+    check_encoder(&pipeline.format).map_err(ImageError::ImageConversion)?;
+    let config = Config::load(&config::discover_config_path(config.as_deref()), profile.as_deref())
+        .map_err(ImageError::Config)?;
+
+    let file = Path::new(file);
+    let runner = Runner {
+        pipeline: &pipeline,
+        config: &config,
+        base: file.parent().unwrap_or(Path::new("")).to_path_buf(),
+        dir: pipeline.output_dir(file),
+    };
+    std::fs::create_dir_all(&runner.dir)?;
+    let mut state = if *fresh { RunState::default() } else { RunState::load(&runner.dir) };
+
+    for wave in pipeline.waves().map_err(ImageError::InvalidArgument)? {
+        let steps: Vec<&Step> = wave.iter().map(|&i| &pipeline.steps[i]).collect();
+        let results = join_all(steps.iter().map(|step| runner.step(step, &state))).await;
+        let mut failure = None;
+        for (step, result) in steps.iter().zip(results) {
+            match result {
+                Ok(finished) => {
+                    state.steps.insert(step.id.clone(), finished);
+                }
+                Err(e) if failure.is_none() => failure = Some((step, e)),
+                Err(e) => report::warn(format_args!("step '{}' failed: {e}", step.id)),
+            }
+        }
+        state.save(&runner.dir)?;
+        if let Some((step, e)) = failure {
+            report::warn(format_args!(
+                "step '{}' failed; finished steps are kept, so re-running resumes",
+                step.id
+            ));
+            return Err(e);
+        }
+    }
+    tracing::info!("Pipeline finished: {} steps in {}", pipeline.steps.len(), runner.dir.display());
+    Ok(())
+}
+
+/// Shared settings for running the steps of one pipeline.
+struct Runner<'a> {
+    pipeline: &'a Pipeline,
+    config: &'a Config,
+    /// Directory of the pipeline file, which relative paths in it refer to.
+    base: PathBuf,
+    /// Output directory.
+    dir: PathBuf,
+}
+
+impl Runner<'_> {
+    /// Run one step (or reuse its earlier outputs) and save what it produces.
+    async fn step(&self, step: &Step, state: &RunState) -> Result<FinishedStep, ImageError> {
+        let inputs = match step.action.input() {
+            Some(input) => self.read_outputs(&state.steps[input])?,
+            None => Vec::new(),
+        };
+        let logo = match step.action {
+            Action::Watermark(ref mark) => Some(read(&self.base.join(&mark.image))?),
+            _ => None,
+        };
+        let key = cache_key(
+            &step.action,
+            inputs.iter().map(|image| image.data.as_slice()).chain(logo.as_deref()),
+        );
+        if let Some(outputs) = state.cached(&step.id, &key, &self.dir) {
+            tracing::info!("[{}] cached", step.id);
+            return Ok(FinishedStep { key, outputs: outputs.to_vec() });
+        }
+
+        tracing::info!("[{}] running", step.id);
+        let mut produced = Vec::new();
+        if let Action::Generate(ref generate) = step.action {
+            produced.extend(self.generate(generate).await?.into_iter().enumerate());
+        }
+        for (index, input) in inputs.iter().enumerate() {
+            let images = self.transform(&step.action, input, logo.as_deref()).await?;
+            produced.extend(images.into_iter().map(|image| (index, image)));
+        }
+
+        let numbered = produced.iter().any(|&(index, _)| index > 0);
+        let ext = format_extension(&self.pipeline.format);
+        let mut outputs = Vec::new();
+        for (index, image) in produced {
+            let mut name = step.id.clone();
+            if numbered {
+                name = format!("{name}-{}", index + 1);
+            }
+            if let Some(label) = image.label {
+                name = format!("{name}-{label}");
+            }
+            let name = format!("{name}.{ext}");
+            let path = self.dir.join(&name);
+            let post = PostProcess::default();
+            save_image(&image.data, &image.mime_type, &self.pipeline.format, &path, &post)?;
+            tracing::info!(path = %path.display(), "Saved: {}", path.display());
+            outputs.push(name);
+        }
+        Ok(FinishedStep { key, outputs })
+    }
+
+    /// The saved images of a finished step, ready to send as inputs.
+    fn read_outputs(&self, step: &FinishedStep) -> Result<Vec<InputImage>, ImageError> {
+        step.outputs
+            .iter()
+            .map(|name| {
+                let path = self.dir.join(name);
+                Ok(InputImage {
+                    data: read(&path)?,
+                    mime_type: mime_type_from_extension(&path.to_string_lossy())
+                        .map_err(ImageError::InvalidArgument)?
+                        .to_string(),
+                    filename: name.clone(),
+                    role: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Run a `generate` step.
+    async fn generate(&self, step: &GenerateStep) -> Result<Vec<Produced>, ImageError> {
+        let model = resolve_model(&step.model);
+        let provider = detect_provider(&model).map_err(ImageError::InvalidArgument)?;
+        let aspect_ratio = nearest_aspect_ratio(&step.aspect_ratio, provider)
+            .map_err(ImageError::InvalidArgument)?;
+        warn_unsupported(&step.size, &step.quality, provider, &model);
+        let request = ImageRequest {
+            aspect_ratio,
+            size: step.size.clone(),
+            quality: step.quality.clone(),
+            count: step.count,
+            seed: None,
+            ..self.request(&step.model, &step.prompt, Vec::new())
+        };
+        self.send(request, Operation::Generate).await
+    }
+
+    /// Apply a non-generate step to one input image.
+    async fn transform(
+        &self,
+        action: &Action,
+        input: &InputImage,
+        logo: Option<&[u8]>,
+    ) -> Result<Vec<Produced>, ImageError> {
+        match action {
+            Action::Generate(_) => unreachable!("generate steps have no input"),
+            Action::Edit(step) => {
+                let request = self.request(&step.model, &step.prompt, vec![input.clone()]);
+                self.send(request, Operation::Edit).await
+            }
+            Action::RemoveBg(step) => {
+                let request = ImageRequest {
+                    format: "png".into(),
+                    background: Some("transparent".into()),
+                    ..self.request(&step.model, REMOVE_BG_PROMPT, vec![input.clone()])
+                };
+                self.send(request, Operation::Edit).await
+            }
+            Action::Upscale(step) => {
+                let (width, height) = decode(&input.data)?.dimensions();
+                let target = (width * step.factor, height * step.factor);
+                if target.0.max(target.1) > MAX_EDGE {
+                    return Err(ImageError::InvalidArgument(format!(
+                        "{width}x{height} at {}x would exceed {MAX_EDGE}px per side",
+                        step.factor
+                    )));
+                }
+                let upscaler = context::upscaler(&step.upscaler, self.config)?;
+                let request = UpscaleRequest {
+                    image: input.clone(),
+                    factor: step.factor,
+                    format: "png".into(),
+                };
+                let image = first_image(upscaler.upscale(&request).await?.images)?;
+                let (data, mime_type) = fit_to(image.data, image.mime_type, target)?;
+                Ok(vec![Produced { label: None, data, mime_type }])
+            }
+            Action::Watermark(step) => {
+                let logo = decode(logo.unwrap_or_default())?;
+                let marked = watermark(&decode(&input.data)?, &logo, step)?;
+                Ok(vec![encode_png(&marked, None)?])
+            }
+            Action::Resize(step) => {
+                let image = decode(&input.data)?;
+                step.sizes
+                    .iter()
+                    .map(|size| {
+                        let (width, height) =
+                            parse_size(size).map_err(ImageError::InvalidArgument)?;
+                        let resized =
+                            post::Step::Fill(width, height, Crop::Center).apply(image.clone());
+                        encode_png(&resized, Some(size.clone()))
+                    })
+                    .collect()
+            }
+        }
+    }
+
+    /// A request for `model` with the pipeline's format and single-image defaults.
+    fn request(&self, model: &str, prompt: &str, input_images: Vec<InputImage>) -> ImageRequest {
+        ImageRequest {
+            model: resolve_model(model),
+            prompt: prompt.to_string(),
+            aspect_ratio: "1:1".into(),
+            size: "1K".into(),
+            quality: "auto".into(),
+            format: provider_format(&self.pipeline.format).to_string(),
+            count: 1,
+            seed: None,
+            thinking: None,
+            input_images,
+            background: None,
+            magic_prompt: None,
+            style_type: None,
+            moderation: None,
+            output_compression: None,
+            safety: None,
+            reference_images: vec![],
+            return_urls: false,
+            history: vec![],
+        }
+    }
+
+    /// Send `request` to its provider's live generator or editor.
+    async fn send(
+        &self,
+        mut request: ImageRequest,
+        operation: Operation,
+    ) -> Result<Vec<Produced>, ImageError> {
+        let provider = detect_provider(&request.model).map_err(ImageError::InvalidArgument)?;
+        request.input_images = fit_input_images(request.input_images, provider, true)?;
+        let ctx = ServiceContext::live(provider, self.config, operation)?;
+        let response = ctx.generator.generate(&request).await?;
+        if response.images.is_empty() {
+            return Err(no_images());
+        }
+        Ok(response
+            .images
+            .into_iter()
+            .map(|image| Produced { label: None, data: image.data, mime_type: image.mime_type })
+            .collect())
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, ImageError> {
+    std::fs::read(path).map_err(|e| {
+        ImageError::Io(std::io::Error::new(e.kind(), format!("{}: {e}", path.display())))
+    })
+}
+
+fn no_images() -> ImageError {
+    ImageError::NoImages { finish_reason: None, detail: "The step produced no image".into() }
+}
+
+fn first_image<T>(images: Vec<T>) -> Result<T, ImageError> {
+    images.into_iter().next().ok_or_else(no_images)
+}
+
+fn decode(data: &[u8]) -> Result<DynamicImage, ImageError> {
+    image::load_from_memory(data)
+        .map_err(|e| ImageError::ImageConversion(format!("Failed to decode image: {e}")))
+}
+
+fn encode_png(image: &DynamicImage, label: Option<String>) -> Result<Produced, ImageError> {
+    let mut png = Cursor::new(Vec::new());
+    image
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|e| ImageError::ImageConversion(format!("Failed to encode image: {e}")))?;
+    Ok(Produced { label, data: png.into_inner(), mime_type: "image/png".into() })
+}
+
+/// Overlay `logo` on a corner of `image`, `step.scale` of its width wide and faded to
+/// `step.opacity`.
+fn watermark(
+    image: &DynamicImage,
+    logo: &DynamicImage,
+    step: &WatermarkStep,
+) -> Result<DynamicImage, ImageError> {
+    let position = match parse_position(&step.position).map_err(ImageError::InvalidArgument)? {
+        (false, false) => Position::TopLeft,
+        (true, false) => Position::TopRight,
+        (false, true) => Position::BottomLeft,
+        (true, true) => Position::BottomRight,
+    };
+    let mark = Watermark {
+        mark: Mark::Image(logo.to_rgba8()),
+        position,
+        opacity: step.opacity,
+        scale: Some(step.scale),
+    };
+    Ok(DynamicImage::ImageRgba8(mark.apply(image).to_rgba8()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn watermark_lands_in_the_chosen_corner() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(100, 50, Rgba([0, 0, 0, 255])));
+        let logo = DynamicImage::ImageRgba8(RgbaImage::from_pixel(10, 5, Rgba([255, 0, 0, 255])));
+        let step = |position: &str| WatermarkStep {
+            input: "a".into(),
+            image: "logo.png".into(),
+            position: position.into(),
+            opacity: 1.0,
+            scale: 0.2,
+        };
+
+        let marked = watermark(&image, &logo, &step("bottom-right")).unwrap().to_rgba8();
+        assert_eq!(marked.get_pixel(95, 45), &Rgba([255, 0, 0, 255]));
+        assert_eq!(marked.get_pixel(5, 5), &Rgba([0, 0, 0, 255]));
+
+        let faded = WatermarkStep { opacity: 0.5, ..step("top-left") };
+        let marked = watermark(&image, &logo, &faded).unwrap().to_rgba8();
+        let pixel = marked.get_pixel(5, 5);
+        assert!(pixel[0] > 100 && pixel[0] < 155, "got {pixel:?}");
+    }
+}
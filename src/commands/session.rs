@@ -0,0 +1,273 @@
+//! `imagen session`: refine an image over several turns of conversation (Gemini).
+//!
+//! Every prompt after the first edits the previous result. The whole conversation,
+//! including the model's earlier images, is resent on each turn so Gemini keeps the
+//! context, and every intermediate image is saved as `NN-<prompt>.<ext>`.
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::cli::SessionArgs;
+use crate::config::{self, Config};
+use crate::context::{Operation, ServiceContext};
+use crate::error::ImageError;
+use crate::input::{fit_input_images, read_input_images};
+use crate::model::{detect_provider, resolve_model, Provider};
+use crate::output::{check_encoder, sanitize_for_filename, save_image, PostProcess};
+use crate::params::{
+    format_extension, nearest_aspect_ratio, parse_input_spec, provider_format, validate_format,
+    validate_input_paths, validate_size, validate_thinking,
+};
+use crate::ports::image_generator::ImageResponse;
+use crate::ports::{ConversationTurn, ImageRequest, InputImage};
+
+/// Prefix of the directory a session saves to when `--dir` is not given.
+pub const SESSION_DIR_PREFIX: &str = "imagen-session-";
+
+/// Conversation state carried from one turn to the next.
+struct Session {
+    /// Request fields shared by every turn; `prompt` and `history` are filled per turn.
+    template: ImageRequest,
+    /// Completed turns, oldest first.
+    history: Vec<ConversationTurn>,
+    /// `--input` images, attached to the first turn only.
+    pending_inputs: Vec<InputImage>,
+}
+
+impl Session {
+    fn request(&self, prompt: &str) -> ImageRequest {
+        ImageRequest {
+            prompt: prompt.to_string(),
+            input_images: self.pending_inputs.clone(),
+            history: self.history.clone(),
+            ..self.template.clone()
+        }
+    }
+
+    /// Append a completed turn; later turns build on its images.
+    fn push(&mut self, prompt: &str, response: ImageResponse) {
+        self.history.push(ConversationTurn {
+            prompt: prompt.to_string(),
+            input_images: std::mem::take(&mut self.pending_inputs),
+            images: response.images,
+        });
+    }
+
+    /// Drop the latest turn so the next prompt edits the one before it.
+    fn undo(&mut self) -> bool {
+        let Some(turn) = self.history.pop() else {
+            return false;
+        };
+        if self.history.is_empty() {
+            self.pending_inputs = turn.input_images;
+        }
+        true
+    }
+}
+
+/// Run an interactive session on stdin until `/quit` or end of input.
+///
+/// # Errors
+///
+/// Returns an error if the arguments are invalid, the provider is not configured, or
+/// the output directory cannot be created. Failed turns are reported and skipped.
+pub async fn run(args: &SessionArgs) -> Result<(), ImageError> {
+    let model = resolve_model(&args.model);
+    let provider = detect_provider(&model).map_err(ImageError::InvalidArgument)?;
+    if provider != Provider::Gemini {
+        return Err(ImageError::InvalidArgument(format!(
+            "{provider:?} models cannot hold an editing conversation. Use a Gemini model with \
+             imagen session"
+        )));
+    }
+    let aspect_ratio =
+        nearest_aspect_ratio(&args.aspect_ratio, provider).map_err(ImageError::InvalidArgument)?;
+    validate_size(&args.size).map_err(ImageError::InvalidArgument)?;
+    validate_format(&args.format).map_err(ImageError::InvalidArgument)?;
+    check_encoder(&args.format).map_err(ImageError::ImageConversion)?;
+    if let Some(ref thinking) = args.thinking {
+        validate_thinking(thinking, provider).map_err(ImageError::InvalidArgument)?;
+    }
+    let input_specs: Vec<(Option<String>, String)> =
+        args.input.iter().map(|spec| parse_input_spec(spec)).collect();
+    let paths: Vec<String> = input_specs.iter().map(|(_, path)| path.clone()).collect();
+    validate_input_paths(&paths).map_err(ImageError::InvalidArgument)?;
+
+    let config_path = config::discover_config_path(args.config.as_deref());
+    let config = Config::load(&config_path, args.profile.as_deref()).map_err(ImageError::Config)?;
+    let ctx = ServiceContext::live(provider, &config, Operation::Edit)?;
+
+    let dir = args.dir.as_ref().map_or_else(
+        || {
+            let timestamp = chrono::Utc::now().format("%Y-%m-%dT%H-%M-%S");
+            PathBuf::from(format!("{SESSION_DIR_PREFIX}{timestamp}"))
+        },
+        PathBuf::from,
+    );
+    std::fs::create_dir_all(&dir)?;
+
+    let mut session = Session {
+        template: ImageRequest {
+            model,
+            prompt: String::new(),
+            aspect_ratio,
+            size: args.size.clone(),
+            quality: "auto".into(),
+            format: provider_format(&args.format).to_string(),
+            count: 1,
+            seed: None,
+            thinking: args.thinking.clone(),
+            input_images: vec![],
+            background: None,
+            magic_prompt: None,
+            style_type: None,
+            moderation: None,
+            output_compression: None,
+            safety: None,
+            reference_images: vec![],
+            return_urls: false,
+            history: vec![],
+        },
+        history: Vec::new(),
+        pending_inputs: fit_input_images(read_input_images(&input_specs)?, provider, true)?,
+    };
+
+    eprintln!("Saving turns to {}", dir.display());
+    eprintln!("Type a prompt per turn; /undo drops the last turn, /quit ends the session.");
+    let mut lines = std::io::stdin().lock().lines();
+    loop {
+        eprint!("[{}] > ", session.history.len() + 1);
+        let _ = std::io::stderr().flush();
+        let Some(line) = lines.next().transpose()? else {
+            eprintln!();
+            break;
+        };
+        let prompt = line.trim();
+        match prompt {
+            "" => continue,
+            "/quit" | "/exit" => break,
+            "/undo" => {
+                if session.undo() {
+                    eprintln!("Dropped the last turn");
+                } else {
+                    eprintln!("Nothing to undo");
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let response = match ctx.generator.generate(&session.request(prompt)).await {
+            Ok(response) if !response.images.is_empty() => response,
+            Ok(_) => {
+                eprintln!("Error: no image returned; try rephrasing");
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Error: {e}");
+                continue;
+            }
+        };
+        let path = turn_path(&dir, session.history.len() + 1, prompt, &args.format);
+        let image = &response.images[0];
+        save_image(&image.data, &image.mime_type, &args.format, &path, &PostProcess::default())?;
+        tracing::info!(path = %path.display(), "Saved: {}", path.display());
+        session.push(prompt, response);
+    }
+    Ok(())
+}
+
+/// `dir/03-make-it-blue.png` for turn 3.
+fn turn_path(dir: &Path, turn: usize, prompt: &str, format: &str) -> PathBuf {
+    let slug = sanitize_for_filename(prompt, 50);
+    dir.join(format!("{turn:02}-{slug}.{}", format_extension(format)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::image_generator::GeneratedImage;
+
+    fn session() -> Session {
+        Session {
+            template: ImageRequest {
+                model: "gemini-3.1-flash-image-preview".into(),
+                prompt: String::new(),
+                aspect_ratio: "1:1".into(),
+                size: "1K".into(),
+                quality: "auto".into(),
+                format: "png".into(),
+                count: 1,
+                seed: None,
+                thinking: None,
+                input_images: vec![],
+                background: None,
+                magic_prompt: None,
+                style_type: None,
+                moderation: None,
+                output_compression: None,
+                safety: None,
+                reference_images: vec![],
+                return_urls: false,
+                history: vec![],
+            },
+            history: Vec::new(),
+            pending_inputs: vec![InputImage {
+                data: vec![1],
+                mime_type: "image/png".into(),
+                filename: "start.png".into(),
+                role: None,
+            }],
+        }
+    }
+
+    fn response(byte: u8) -> ImageResponse {
+        ImageResponse {
+            images: vec![GeneratedImage {
+                data: vec![byte],
+                mime_type: "image/png".into(),
+                signature: None,
+                url: None,
+            }],
+            failures: Vec::new(),
+            usage: None,
+        }
+    }
+
+    #[test]
+    fn turns_build_on_earlier_results() {
+        let mut session = session();
+        let first = session.request("add a hat");
+        assert_eq!(first.input_images.len(), 1);
+        assert!(first.history.is_empty());
+        session.push("add a hat", response(2));
+
+        let second = session.request("make it blue");
+        assert_eq!(second.prompt, "make it blue");
+        assert!(second.input_images.is_empty());
+        assert_eq!(second.history.len(), 1);
+        assert_eq!(second.history[0].input_images.len(), 1);
+        assert_eq!(second.history[0].images[0].data, [2]);
+    }
+
+    #[test]
+    fn undo_restores_the_previous_state() {
+        let mut session = session();
+        assert!(!session.undo());
+        session.push("add a hat", response(2));
+        session.push("make it blue", response(3));
+
+        assert!(session.undo());
+        assert_eq!(session.request("make it green").history.len(), 1);
+        assert!(session.undo());
+        assert_eq!(session.request("add a scarf").input_images.len(), 1);
+    }
+
+    #[test]
+    fn turn_files_are_numbered() {
+        assert_eq!(
+            turn_path(Path::new("out"), 3, "Make it blue!", "jpeg"),
+            PathBuf::from("out/03-make-it-blue.jpg")
+        );
+    }
+}
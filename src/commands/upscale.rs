@@ -0,0 +1,158 @@
+//! `imagen upscale`: enlarge an existing image locally or with a provider's upscaler.
+
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+
+use crate::cli::UpscaleArgs;
+use crate::config::{self, Config};
+use crate::context;
+use crate::error::ImageError;
+use crate::output::{check_encoder, save_image, PostProcess};
+use crate::params::{format_extension, mime_type_from_extension, provider_format, validate_format};
+use crate::ports::{InputImage, UpscaleRequest};
+
+/// Largest supported scale factor.
+const MAX_FACTOR: u32 = 8;
+
+/// Longest edge, in pixels, that an upscaled image may have.
+pub(crate) const MAX_EDGE: u32 = 16_384;
+
+/// Upscale `args.input` and save the result.
+///
+/// # Errors
+///
+/// Returns an error if the arguments are invalid, the input cannot be read, the
+/// upscaler fails, or the result cannot be saved.
+pub async fn run(args: &UpscaleArgs) -> Result<(), ImageError> {
+    if !(2..=MAX_FACTOR).contains(&args.factor) {
+        return Err(ImageError::InvalidArgument(format!(
+            "Unsupported upscale factor '{}'. Valid: 2-{MAX_FACTOR}",
+            args.factor
+        )));
+    }
+    let mime_type =
+        mime_type_from_extension(&args.input).map_err(ImageError::InvalidArgument)?.to_string();
+    let format = match args.format {
+        Some(ref format) => format.clone(),
+        None => format_for_mime(&mime_type).to_string(),
+    };
+    validate_format(&format).map_err(ImageError::InvalidArgument)?;
+    check_encoder(&format).map_err(ImageError::ImageConversion)?;
+
+    let (width, height) = image::image_dimensions(&args.input)
+        .map_err(|e| ImageError::ImageConversion(format!("Failed to read {}: {e}", args.input)))?;
+    let target = (width * args.factor, height * args.factor);
+    if target.0.max(target.1) > MAX_EDGE {
+        return Err(ImageError::InvalidArgument(format!(
+            "{width}x{height} at {}x would be {}x{}; the limit is {MAX_EDGE}px per side",
+            args.factor, target.0, target.1
+        )));
+    }
+
+    let config_path = config::discover_config_path(args.config.as_deref());
+    let config = Config::load(&config_path, args.profile.as_deref()).map_err(ImageError::Config)?;
+    let upscaler = context::upscaler(&args.upscaler, &config)?;
+
+    let request = UpscaleRequest {
+        image: InputImage {
+            data: std::fs::read(&args.input)?,
+            mime_type,
+            filename: file_name(&args.input),
+            role: None,
+        },
+        factor: args.factor,
+        format: provider_format(&format).to_string(),
+    };
+    let response = upscaler.upscale(&request).await?;
+    let image = response.images.into_iter().next().ok_or_else(|| ImageError::NoImages {
+        finish_reason: None,
+        detail: "The upscaler returned no image".into(),
+    })?;
+    let (data, mime_type) = fit_to(image.data, image.mime_type, target)?;
+
+    let output_path = args.output.as_ref().map_or_else(
+        || default_output_path(Path::new(&args.input), args.factor, &format),
+        PathBuf::from,
+    );
+    save_image(&data, &mime_type, &format, &output_path, &PostProcess::default())?;
+    tracing::info!(
+        path = %output_path.display(),
+        "Saved: {} ({}x{})",
+        output_path.display(),
+        target.0,
+        target.1
+    );
+    Ok(())
+}
+
+/// Output format matching an input MIME type (GIF input is saved as PNG).
+fn format_for_mime(mime_type: &str) -> &'static str {
+    match mime_type {
+        "image/jpeg" => "jpeg",
+        "image/webp" => "webp",
+        _ => "png",
+    }
+}
+
+/// `photos/cat.jpg` at 4x in png → `photos/cat-x4.png`.
+fn default_output_path(input: &Path, factor: u32, format: &str) -> PathBuf {
+    let stem = input.file_stem().unwrap_or_default().to_string_lossy();
+    input.with_file_name(format!("{stem}-x{factor}.{}", format_extension(format)))
+}
+
+fn file_name(path: &str) -> String {
+    Path::new(path).file_name().unwrap_or_default().to_string_lossy().to_string()
+}
+
+/// Resize an upscaler's result to exactly `target`, e.g. when a fixed-4x service served a
+/// 2x request. Results that already match are passed through untouched.
+pub(crate) fn fit_to(
+    data: Vec<u8>,
+    mime_type: String,
+    target: (u32, u32),
+) -> Result<(Vec<u8>, String), ImageError> {
+    let img = image::load_from_memory(&data)
+        .map_err(|e| ImageError::ImageConversion(format!("Failed to decode image: {e}")))?;
+    if (img.width(), img.height()) == target {
+        return Ok((data, mime_type));
+    }
+    let resized = img.resize_exact(target.0, target.1, image::imageops::FilterType::Lanczos3);
+    let mut png = Cursor::new(Vec::new());
+    resized
+        .write_to(&mut png, image::ImageFormat::Png)
+        .map_err(|e| ImageError::ImageConversion(format!("Failed to encode resized image: {e}")))?;
+    Ok((png.into_inner(), "image/png".into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_output_path_sits_next_to_input() {
+        assert_eq!(
+            default_output_path(Path::new("photos/cat.jpg"), 4, "png"),
+            PathBuf::from("photos/cat-x4.png")
+        );
+        assert_eq!(
+            default_output_path(Path::new("cat.jpeg"), 2, "jpeg"),
+            PathBuf::from("cat-x2.jpg")
+        );
+    }
+
+    #[test]
+    fn fit_to_resizes_only_when_needed() {
+        let mut png = Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(image::RgbImage::new(8, 4))
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+
+        let (same, _) = fit_to(png.clone(), "image/png".into(), (8, 4)).unwrap();
+        assert_eq!(same, png);
+
+        let (resized, mime) = fit_to(png, "image/webp".into(), (4, 2)).unwrap();
+        assert_eq!(mime, "image/png");
+        assert_eq!(image::load_from_memory(&resized).unwrap().width(), 4);
+    }
+}
@@ -1,11 +1,30 @@
 //! Configuration file loading with environment variable overrides.
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+
+use crate::model::resolve_model;
+
+/// Default SD web UI address when `[providers.sd_local] base_url` is not set.
+const DEFAULT_SD_LOCAL_URL: &str = "http://127.0.0.1:7860";
+
+/// Default Vertex AI location when none is configured.
+const DEFAULT_VERTEX_LOCATION: &str = "global";
+
+/// Default root for auto-named cassettes (`--mode record`).
+const DEFAULT_CASSETTE_DIR: &str = ".imagen/cassettes";
+
+/// Default cassette filename template, relative to the cassette root.
+const DEFAULT_CASSETTE_FILENAME: &str = "{timestamp}/image_generator.cassette.yaml";
+
+/// Default Azure `OpenAI` API version (the first to serve `gpt-image-1`).
+const DEFAULT_AZURE_API_VERSION: &str = "2025-04-01-preview";
 
 /// Top-level configuration.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Config {
     /// API key configuration.
     #[serde(default)]
@@ -14,15 +33,238 @@ pub struct Config {
     /// Default parameter values (used when CLI flags are at their defaults).
     #[serde(default)]
     pub defaults: DefaultsConfig,
+
+    /// Per-provider connection settings.
+    #[serde(default)]
+    pub providers: ProvidersConfig,
+
+    /// AWS settings for Bedrock models.
+    #[serde(default)]
+    pub aws: AwsConfig,
+
+    /// Output file naming.
+    #[serde(default)]
+    pub output: OutputConfig,
+
+    /// Azure `OpenAI` deployment settings.
+    #[serde(default)]
+    pub azure: AzureConfig,
+
+    /// Vertex AI settings for Gemini models.
+    #[serde(default)]
+    pub vertex: VertexConfig,
+
+    /// Where auto-named cassettes are recorded.
+    #[serde(default)]
+    pub cassette: CassetteConfig,
+
+    /// Request identification and timeouts shared by all providers.
+    #[serde(default)]
+    pub http: HttpConfig,
+
+    /// Run-summary log for usage analytics.
+    #[serde(default)]
+    pub log: LogConfig,
+
+    /// Per-image prices in USD by model name or alias, overriding the built-in table.
+    #[serde(default)]
+    pub pricing: HashMap<String, f64>,
+
+    /// Local generation history.
+    #[serde(default)]
+    pub history: HistoryConfig,
+
+    /// Named prompts, used as `imagen @name`.
+    #[serde(default)]
+    pub prompts: HashMap<String, String>,
+
+    /// Named style text, added to the prompt with `--style <name>`.
+    #[serde(default)]
+    pub styles: HashMap<String, String>,
+
+    /// Prompt enhancement with a text model (`--enhance`).
+    #[serde(default)]
+    pub enhance: EnhanceConfig,
+}
+
+/// Prompt enhancement (`[enhance]`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EnhanceConfig {
+    /// Text model that rewrites prompts; `--enhance-model` takes precedence. Defaults to
+    /// a small model from the image provider, or from whichever provider has a key.
+    pub model: Option<String>,
+    /// System prompt for the text model, replacing the built-in instructions.
+    pub instructions: Option<String>,
+}
+
+/// Generation history (`[history]`), kept in the local store.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryConfig {
+    /// Record every saved image. Defaults to `true`.
+    pub enabled: Option<bool>,
+    /// Days of history `imagen gc` keeps. Defaults to 90; `0` keeps everything.
+    pub retention_days: Option<u32>,
+}
+
+/// Run-summary log (`[log]`): one JSON line per run, off unless a path is set.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+    /// File the summaries are appended to; `IMAGEN_RUN_LOG` takes precedence.
+    pub path: Option<String>,
+    /// Size in bytes at which the file is rotated. Defaults to 10 MiB.
+    pub max_bytes: Option<u64>,
+    /// Rotated files kept next to it (`<path>.1`, `<path>.2`, ...). Defaults to 5.
+    pub keep: Option<usize>,
+}
+
+/// Request identification and timeouts (`[http]`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HttpConfig {
+    /// Send the `imagen/<version>` User-Agent and run ID. Defaults to `true`.
+    pub identify: Option<bool>,
+    /// Value for the `X-Imagen-Run-Id` header; `IMAGEN_RUN_ID` takes precedence.
+    pub run_id: Option<String>,
+    /// Seconds allowed to establish a connection, for providers without their own
+    /// `connect_timeout_secs`.
+    pub connect_timeout: Option<u64>,
+    /// Seconds allowed for a whole HTTP request, response body included (or `--timeout`).
+    /// Unset means no overall limit; only the per-provider read timeout applies.
+    pub request_timeout: Option<u64>,
+}
+
+/// Cassette recording location (`[cassette]`), used by `--mode record` without a path.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CassetteConfig {
+    /// Root directory for recordings; relative paths resolve against the working directory.
+    pub dir: Option<String>,
+    /// Filename template under `dir`. Placeholders: `{timestamp}`, `{date}`, `{time}`, `{slug}`.
+    pub filename: Option<String>,
+}
+
+/// Vertex AI settings (`[vertex]`). Environment variables take precedence.
+///
+/// Setting `project` routes Gemini models through Vertex AI instead of the API-key endpoint.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct VertexConfig {
+    /// Google Cloud project ID (or `GOOGLE_CLOUD_PROJECT`).
+    pub project: Option<String>,
+    /// Vertex AI location (or `GOOGLE_CLOUD_LOCATION`); defaults to `global`.
+    pub location: Option<String>,
+    /// Path to a credentials JSON file (overridden by `GOOGLE_APPLICATION_CREDENTIALS`).
+    pub credentials: Option<String>,
+}
+
+/// Azure `OpenAI` settings (`[azure]`). Environment variables take precedence.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AzureConfig {
+    /// Resource endpoint, e.g. `https://my-resource.openai.azure.com` (or `AZURE_OPENAI_ENDPOINT`).
+    pub azure_endpoint: Option<String>,
+    /// Deployment used when the model is plain `azure`.
+    pub deployment: Option<String>,
+    /// API version (or `OPENAI_API_VERSION`).
+    pub api_version: Option<String>,
+}
+
+/// Output file naming (`[output]`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OutputConfig {
+    /// Slug strategy for auto-generated filenames: prompt, hash, counter, ulid.
+    pub slug: Option<String>,
+    /// Directory for auto-generated filenames, instead of the current directory.
+    pub dir: Option<String>,
+    /// Template for auto-generated filenames, used instead of `slug`
+    /// (`{date}/{prompt:40}-{n}.{ext}`).
+    pub name_template: Option<String>,
+    /// Write a JSON sidecar next to every image, as `--sidecar` does.
+    #[serde(default)]
+    pub sidecar: bool,
+    /// Per-format extension overrides (`[output.extensions] jpeg = "jpeg"`).
+    #[serde(default)]
+    pub extensions: HashMap<String, String>,
 }
 
 /// API key configuration.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct KeysConfig {
     /// Gemini API key.
     pub gemini: Option<String>,
     /// `OpenAI` API key.
     pub openai: Option<String>,
+    /// Base URL of an `OpenAI`-compatible images API (e.g., a `LiteLLM` gateway).
+    pub openai_base_url: Option<String>,
+    /// Stability AI API key.
+    pub stability: Option<String>,
+    /// Replicate API token.
+    pub replicate: Option<String>,
+    /// Azure `OpenAI` API key.
+    pub azure_openai: Option<String>,
+    /// Together AI API key (Flux models).
+    pub together: Option<String>,
+    /// Ideogram API key.
+    pub ideogram: Option<String>,
+}
+
+/// Per-provider settings, keyed by provider name (`[providers.gemini]`, `[providers.openai]`).
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProvidersConfig {
+    /// Gemini settings.
+    #[serde(default)]
+    pub gemini: ProviderConfig,
+    /// `OpenAI` settings.
+    #[serde(default)]
+    pub openai: ProviderConfig,
+    /// Stability AI settings.
+    #[serde(default)]
+    pub stability: ProviderConfig,
+    /// Replicate settings.
+    #[serde(default)]
+    pub replicate: ProviderConfig,
+    /// Local Stable Diffusion web UI settings.
+    #[serde(default)]
+    pub sd_local: ProviderConfig,
+    /// AWS Bedrock settings (`base_url` overrides the regional endpoint).
+    #[serde(default)]
+    pub bedrock: ProviderConfig,
+    /// Together AI settings.
+    #[serde(default)]
+    pub together: ProviderConfig,
+    /// Ideogram settings.
+    #[serde(default)]
+    pub ideogram: ProviderConfig,
+}
+
+/// AWS settings (`[aws]`). Environment variables take precedence.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AwsConfig {
+    /// Region for Bedrock calls (overridden by `AWS_REGION` / `AWS_DEFAULT_REGION`).
+    pub region: Option<String>,
+    /// Shared-config profile for credentials (overridden by `AWS_PROFILE`).
+    pub profile: Option<String>,
+}
+
+/// Connection settings for a single provider.
+///
+/// Unset values fall back to the provider's built-in defaults.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProviderConfig {
+    /// Endpoint base URL (used by self-hosted providers such as `sd_local`).
+    pub base_url: Option<String>,
+    /// Seconds allowed to establish a connection.
+    pub connect_timeout_secs: Option<u64>,
+    /// Seconds allowed between reads of the response (covers render time).
+    pub read_timeout_secs: Option<u64>,
 }
 
 fn default_model() -> String {
@@ -41,10 +283,138 @@ fn default_format() -> String {
     "jpeg".to_string()
 }
 
+/// The profile to load: `flag` (`--profile`), else `IMAGEN_PROFILE`.
+#[must_use]
+pub fn selected_profile(flag: Option<&str>) -> Option<String> {
+    flag.map(str::to_string)
+        .or_else(|| std::env::var("IMAGEN_PROFILE").ok().filter(|name| !name.is_empty()))
+}
+
+/// Merge `overlay` into `base`: nested tables merge key by key, anything else replaces.
+fn merge_tables(base: &mut toml::Table, overlay: toml::Table) {
+    for (key, value) in overlay {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => {
+                merge_tables(base, overlay);
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Every setting in `table` (found under the dotted `prefix`) that the config rejects,
+/// as `path: reason`: unknown names such as a misspelled `[defaultss]`, and values of
+/// the wrong type.
+///
+/// Settings are tried one at a time, so one mistake does not hide the next.
+#[must_use]
+pub fn invalid_settings(table: &toml::Table, prefix: &[&str]) -> Vec<String> {
+    let mut problems = Vec::new();
+    for (key, value) in table {
+        locate_invalid(&[key.as_str()], value, prefix, &mut problems);
+    }
+    problems
+}
+
+/// Report the innermost settings under `path` that fail to deserialize on their own.
+///
+/// A table is only searched when its name is accepted with nothing in it, so an unknown
+/// section is reported once rather than for each of its keys.
+fn locate_invalid(path: &[&str], value: &toml::Value, prefix: &[&str], problems: &mut Vec<String>) {
+    let alone = |value: toml::Value| {
+        let nested = path.iter().rev().fold(value, |inner, key| {
+            toml::Value::Table(toml::Table::from_iter([((*key).to_string(), inner)]))
+        });
+        Config::deserialize(nested)
+    };
+    let Err(e) = alone(value.clone()) else { return };
+    if let toml::Value::Table(table) = value {
+        if alone(toml::Value::Table(toml::Table::new())).is_err() {
+            push_invalid(path, prefix, &e, problems);
+            return;
+        }
+        let found = problems.len();
+        for (key, inner) in table {
+            let inner_path: Vec<&str> = path.iter().copied().chain([key.as_str()]).collect();
+            locate_invalid(&inner_path, inner, prefix, problems);
+        }
+        if problems.len() > found {
+            return;
+        }
+    }
+    push_invalid(path, prefix, &e, problems);
+}
+
+/// Add `error` for the setting at `prefix` then `path` to `problems`.
+fn push_invalid(
+    path: &[&str],
+    prefix: &[&str],
+    error: &toml::de::Error,
+    problems: &mut Vec<String>,
+) {
+    let dotted: Vec<String> = prefix
+        .iter()
+        .chain(path)
+        .map(|key| toml_edit::Key::new(*key).display_repr().into_owned())
+        .collect();
+    problems.push(format!("{}: {}", dotted.join("."), error.message().trim()));
+}
+
+/// Replace `${VAR}` in every string under `value` (found at dotted `path`) with the
+/// environment variable's value; `$${` stands for a literal `${`.
+fn expand_env(value: &mut toml::Value, path: &str) -> Result<(), String> {
+    match value {
+        toml::Value::String(text) => {
+            *text = expand_vars(text).map_err(|name| {
+                format!("{path} references ${{{name}}}, but environment variable {name} is not set")
+            })?;
+        }
+        toml::Value::Array(items) => {
+            for (i, item) in items.iter_mut().enumerate() {
+                expand_env(item, &format!("{path}[{i}]"))?;
+            }
+        }
+        toml::Value::Table(table) => {
+            for (key, item) in table {
+                expand_env(item, &format!("{path}.{key}"))?;
+            }
+        }
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `text` with each `${VAR}` replaced, or the name of the first variable that is unset.
+fn expand_vars(text: &str) -> Result<String, String> {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if let Some(after) = rest.strip_prefix("$${") {
+            expanded.push_str("${");
+            rest = after;
+        } else if let Some((name, after)) =
+            rest.strip_prefix("${").and_then(|after| after.split_once('}'))
+        {
+            expanded.push_str(&std::env::var(name).map_err(|_| name.to_string())?);
+            rest = after;
+        } else {
+            expanded.push('$');
+            rest = &rest[1..];
+        }
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
 /// Default parameter values from config file.
 ///
 /// Each field is independently optional: omitting a field in `[defaults]` keeps the built-in CLI default.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct DefaultsConfig {
     /// Default model name.
     #[serde(default = "default_model")]
@@ -61,6 +431,55 @@ pub struct DefaultsConfig {
     /// Default output format.
     #[serde(default = "default_format")]
     pub format: String,
+    /// Models to fall back on, in order, when the model's provider is unavailable.
+    #[serde(default)]
+    pub fallback: Vec<String>,
+    /// What to do when the provider refuses a prompt: `soften` it, or send it to a model.
+    pub on_refusal: Option<String>,
+    /// Defaults for particular models (`[defaults.per_model."gpt-image-1"]`), keyed by
+    /// model name or alias, taking precedence over the fields above.
+    #[serde(default)]
+    pub per_model: HashMap<String, ModelDefaults>,
+}
+
+/// Defaults for one model (`[defaults.per_model.<model>]`); unset fields fall back to
+/// `[defaults]`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ModelDefaults {
+    /// Default aspect ratio.
+    pub aspect_ratio: Option<String>,
+    /// Default image size.
+    pub size: Option<String>,
+    /// Default quality.
+    pub quality: Option<String>,
+    /// Default output format.
+    pub format: Option<String>,
+}
+
+impl DefaultsConfig {
+    /// The defaults for `model` (a resolved name): its `per_model` entry over the general
+    /// ones. An entry under the exact name wins over one under an alias.
+    #[must_use]
+    pub fn for_model(&self, model: &str) -> Self {
+        let entry = self.per_model.get(model).or_else(|| {
+            let mut aliases: Vec<_> =
+                self.per_model.iter().filter(|(name, _)| resolve_model(name) == model).collect();
+            aliases.sort_unstable_by_key(|(name, _)| name.as_str());
+            aliases.first().map(|(_, entry)| *entry)
+        });
+        let entry = entry.cloned().unwrap_or_default();
+        Self {
+            model: self.model.clone(),
+            aspect_ratio: entry.aspect_ratio.unwrap_or_else(|| self.aspect_ratio.clone()),
+            size: entry.size.unwrap_or_else(|| self.size.clone()),
+            quality: entry.quality.unwrap_or_else(|| self.quality.clone()),
+            format: entry.format.unwrap_or_else(|| self.format.clone()),
+            fallback: self.fallback.clone(),
+            on_refusal: self.on_refusal.clone(),
+            per_model: HashMap::new(),
+        }
+    }
 }
 
 impl Default for DefaultsConfig {
@@ -71,6 +490,9 @@ impl Default for DefaultsConfig {
             size: default_size(),
             quality: default_quality(),
             format: default_format(),
+            fallback: Vec::new(),
+            on_refusal: None,
+            per_model: HashMap::new(),
         }
     }
 }
@@ -78,32 +500,247 @@ impl Default for DefaultsConfig {
 impl Config {
     /// Load configuration from the given path, or return defaults.
     ///
+    /// `profile` (or else `IMAGEN_PROFILE`) selects a table under `[profiles]`, whose
+    /// settings are merged over the rest of the file.
+    ///
     /// # Errors
     ///
-    /// Returns an error if the file exists but cannot be parsed.
-    pub fn load(path: &Path) -> Result<Self, String> {
+    /// Returns an error if the file exists but cannot be parsed, or if the profile is not
+    /// defined in it.
+    pub fn load(path: &Path, profile: Option<&str>) -> Result<Self, String> {
+        let profile = selected_profile(profile);
         if !path.exists() {
-            return Ok(Self::default());
+            return match profile {
+                Some(name) => {
+                    Err(format!("Unknown profile '{name}': {} does not exist", path.display()))
+                }
+                None => Ok(Self::default()),
+            };
         }
         let contents = std::fs::read_to_string(path)
             .map_err(|e| format!("Failed to read config {}: {e}", path.display()))?;
-        toml::from_str(&contents)
+        Self::parse(&contents, profile.as_deref())
             .map_err(|e| format!("Failed to parse config {}: {e}", path.display()))
     }
 
-    /// Get the Gemini API key, preferring environment variable.
+    /// Parse the text of a config file, merging the `profile` table under `[profiles]`
+    /// over the rest and expanding `${VAR}` references to environment variables.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text is not a valid config, if the profile is not defined
+    /// in it, or if a value references an environment variable that is not set.
+    pub fn parse(contents: &str, profile: Option<&str>) -> Result<Self, String> {
+        let mut table = Self::merged(contents, profile)?;
+        for (key, value) in &mut table {
+            expand_env(value, key)?;
+        }
+        Self::from_table(&table)
+    }
+
+    /// Like [`Config::parse`], but leaving `${VAR}` references as written, for checking a
+    /// file whose variables belong to another shell.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the text is not a valid config, or if the profile is not
+    /// defined in it.
+    pub fn parse_unexpanded(contents: &str, profile: Option<&str>) -> Result<Self, String> {
+        Self::from_table(&Self::merged(contents, profile)?)
+    }
+
+    /// Deserialize a merged table, naming the first setting that is unknown or invalid.
+    fn from_table(table: &toml::Table) -> Result<Self, String> {
+        match Self::deserialize(toml::Value::Table(table.clone())) {
+            Ok(config) => Ok(config),
+            Err(e) => Err(invalid_settings(table, &[]).into_iter().next().unwrap_or(e.to_string())),
+        }
+    }
+
+    /// The file as a table, with the `profile` table merged over the rest.
+    fn merged(contents: &str, profile: Option<&str>) -> Result<toml::Table, String> {
+        let mut table: toml::Table = toml::from_str(contents).map_err(|e| e.to_string())?;
+        let profiles = table.remove("profiles");
+        if let Some(name) = profile {
+            let overlay = profiles
+                .as_ref()
+                .and_then(|profiles| profiles.get(name))
+                .and_then(toml::Value::as_table)
+                .ok_or_else(|| {
+                    let defined = profiles.as_ref().and_then(toml::Value::as_table).map_or_else(
+                        || "none".to_string(),
+                        |profiles| profiles.keys().cloned().collect::<Vec<_>>().join(", "),
+                    );
+                    format!(
+                        "Unknown profile '{name}'; define it under [profiles.{name}] \
+                         (defined: {defined})"
+                    )
+                })?;
+            merge_tables(&mut table, overlay.clone());
+        }
+        Ok(table)
+    }
+
+    /// Get the Gemini API key: OS keychain, then environment variable, then file.
     #[must_use]
     pub fn gemini_key(&self) -> Option<String> {
-        std::env::var("GEMINI_API_KEY").ok().or_else(|| self.keys.gemini.clone())
+        key("gemini", "GEMINI_API_KEY", self.keys.gemini.as_ref())
     }
 
-    /// Get the `OpenAI` API key, preferring environment variable.
+    /// Get the `OpenAI` API key: OS keychain, then environment variable, then file.
     #[must_use]
     pub fn openai_key(&self) -> Option<String> {
-        std::env::var("OPENAI_API_KEY").ok().or_else(|| self.keys.openai.clone())
+        key("openai", "OPENAI_API_KEY", self.keys.openai.as_ref())
+    }
+
+    /// Get the `OpenAI` base URL override (`--api-base` or `keys.openai_base_url`).
+    #[must_use]
+    pub fn openai_base_url(&self) -> Option<&str> {
+        self.keys.openai_base_url.as_deref()
+    }
+
+    /// Get the Stability AI API key: OS keychain, then environment variable, then file.
+    #[must_use]
+    pub fn stability_key(&self) -> Option<String> {
+        key("stability", "STABILITY_API_KEY", self.keys.stability.as_ref())
+    }
+
+    /// Get the local SD web UI base URL, preferring environment variable.
+    #[must_use]
+    pub fn sd_local_url(&self) -> String {
+        std::env::var("IMAGEN_SD_URL")
+            .ok()
+            .or_else(|| self.providers.sd_local.base_url.clone())
+            .unwrap_or_else(|| DEFAULT_SD_LOCAL_URL.to_string())
+    }
+
+    /// Get the Azure `OpenAI` API key: OS keychain, then environment variable, then file.
+    #[must_use]
+    pub fn azure_openai_key(&self) -> Option<String> {
+        key("azure_openai", "AZURE_OPENAI_API_KEY", self.keys.azure_openai.as_ref())
+    }
+
+    /// Get the Azure `OpenAI` resource endpoint, preferring environment variable.
+    #[must_use]
+    pub fn azure_endpoint(&self) -> Option<String> {
+        std::env::var("AZURE_OPENAI_ENDPOINT").ok().or_else(|| self.azure.azure_endpoint.clone())
+    }
+
+    /// Get the Azure `OpenAI` API version, preferring environment variable.
+    #[must_use]
+    pub fn azure_api_version(&self) -> String {
+        std::env::var("OPENAI_API_VERSION")
+            .ok()
+            .or_else(|| self.azure.api_version.clone())
+            .unwrap_or_else(|| DEFAULT_AZURE_API_VERSION.to_string())
+    }
+
+    /// Whether Gemini models should be called through Vertex AI.
+    ///
+    /// `GOOGLE_GENAI_USE_VERTEXAI` decides when set; otherwise a `[vertex] project` opts in.
+    #[must_use]
+    pub fn use_vertex(&self) -> bool {
+        match std::env::var("GOOGLE_GENAI_USE_VERTEXAI") {
+            Ok(value) => matches!(value.to_ascii_lowercase().as_str(), "1" | "true"),
+            Err(_) => self.vertex.project.is_some(),
+        }
+    }
+
+    /// Get the Vertex AI project, preferring environment variable.
+    #[must_use]
+    pub fn vertex_project(&self) -> Option<String> {
+        std::env::var("GOOGLE_CLOUD_PROJECT").ok().or_else(|| self.vertex.project.clone())
+    }
+
+    /// Get the Vertex AI location, preferring environment variable.
+    #[must_use]
+    pub fn vertex_location(&self) -> String {
+        std::env::var("GOOGLE_CLOUD_LOCATION")
+            .ok()
+            .or_else(|| self.vertex.location.clone())
+            .unwrap_or_else(|| DEFAULT_VERTEX_LOCATION.to_string())
+    }
+
+    /// Get the root directory for auto-named cassettes.
+    #[must_use]
+    pub fn cassette_dir(&self) -> PathBuf {
+        PathBuf::from(self.cassette.dir.as_deref().unwrap_or(DEFAULT_CASSETTE_DIR))
+    }
+
+    /// Get the filename template for auto-named cassettes.
+    #[must_use]
+    pub fn cassette_filename(&self) -> &str {
+        self.cassette.filename.as_deref().unwrap_or(DEFAULT_CASSETTE_FILENAME)
+    }
+
+    /// Whether requests carry the `imagen/<version>` User-Agent and run ID.
+    #[must_use]
+    pub fn identify(&self) -> bool {
+        self.http.identify.unwrap_or(true)
+    }
+
+    /// Get the run ID tagging every request, preferring environment variable.
+    ///
+    /// Always `None` when `[http] identify = false`.
+    #[must_use]
+    pub fn run_id(&self) -> Option<String> {
+        if !self.identify() {
+            return None;
+        }
+        std::env::var("IMAGEN_RUN_ID").ok().or_else(|| self.http.run_id.clone())
+    }
+
+    /// Get the run-summary log path, preferring environment variable.
+    #[must_use]
+    pub fn run_log_path(&self) -> Option<PathBuf> {
+        std::env::var("IMAGEN_RUN_LOG").ok().or_else(|| self.log.path.clone()).map(PathBuf::from)
+    }
+
+    /// Get the Replicate API token: OS keychain, then environment variable, then file.
+    #[must_use]
+    pub fn replicate_key(&self) -> Option<String> {
+        key("replicate", "REPLICATE_API_TOKEN", self.keys.replicate.as_ref())
+    }
+
+    /// Get the Together AI API key: OS keychain, then environment variable, then file.
+    #[must_use]
+    pub fn together_key(&self) -> Option<String> {
+        key("together", "TOGETHER_API_KEY", self.keys.together.as_ref())
+    }
+
+    /// Get the Ideogram API key: OS keychain, then environment variable, then file.
+    #[must_use]
+    pub fn ideogram_key(&self) -> Option<String> {
+        key("ideogram", "IDEOGRAM_API_KEY", self.keys.ideogram.as_ref())
+    }
+
+    /// Every configured credential value, so recordings can scrub them.
+    #[must_use]
+    pub fn secrets(&self) -> Vec<String> {
+        [
+            self.gemini_key(),
+            self.openai_key(),
+            self.stability_key(),
+            self.azure_openai_key(),
+            self.replicate_key(),
+            self.together_key(),
+            self.ideogram_key(),
+            std::env::var("AWS_SECRET_ACCESS_KEY").ok(),
+            std::env::var("AWS_SESSION_TOKEN").ok(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
     }
 }
 
+/// The API key for `provider`: from the OS keychain, else `variable`, else the file.
+fn key(provider: &str, variable: &str, file: Option<&String>) -> Option<String> {
+    crate::keychain::get(provider)
+        .or_else(|| std::env::var(variable).ok())
+        .or_else(|| file.cloned())
+}
+
 /// Discover the config file path using the resolution order:
 /// 1. Explicit path (from `--config` flag)
 /// 2. `IMAGEN_CONFIG` environment variable
@@ -148,7 +785,7 @@ mod tests {
 
     #[test]
     fn load_nonexistent_returns_defaults() {
-        let config = Config::load(Path::new("/nonexistent/path/config.toml")).unwrap();
+        let config = Config::load(Path::new("/nonexistent/path/config.toml"), None).unwrap();
         assert_eq!(config.defaults.model, "nano-banana");
     }
 
@@ -174,7 +811,7 @@ format = "png"
         )
         .unwrap();
 
-        let config = Config::load(&path).unwrap();
+        let config = Config::load(&path, None).unwrap();
         assert_eq!(config.keys.gemini.as_deref(), Some("test-gemini-key"));
         assert_eq!(config.keys.openai.as_deref(), Some("test-openai-key"));
         assert_eq!(config.defaults.model, "gpt-1");
@@ -186,6 +823,171 @@ format = "png"
         let _ = std::fs::remove_dir_all(&dir);
     }
 
+    #[test]
+    fn per_model_defaults_layer_over_general_ones() {
+        let config: Config = toml::from_str(
+            r#"
+[defaults]
+quality = "medium"
+format = "png"
+
+[defaults.per_model."gpt-image-1"]
+quality = "high"
+
+[defaults.per_model.nano-banana]
+size = "2K"
+"#,
+        )
+        .unwrap();
+        let gpt = config.defaults.for_model("gpt-image-1");
+        assert_eq!(
+            (gpt.quality.as_str(), gpt.size.as_str(), gpt.format.as_str()),
+            ("high", "1K", "png")
+        );
+        let gemini = config.defaults.for_model(&resolve_model("nano-banana"));
+        assert_eq!((gemini.quality.as_str(), gemini.size.as_str()), ("medium", "2K"));
+        let other = config.defaults.for_model("flux-schnell");
+        assert_eq!((other.quality.as_str(), other.size.as_str()), ("medium", "1K"));
+    }
+
+    #[test]
+    fn load_output_slug() {
+        let dir = std::env::temp_dir().join("imagen_config_output_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            "[output]\nslug = \"hash\"\ndir = \"renders\"\nsidecar = true\nname_template = \"{model}/{n}\"\n\n[output.extensions]\njpeg = \"jpeg\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&path, None).unwrap();
+        assert_eq!(config.output.slug.as_deref(), Some("hash"));
+        assert_eq!(config.output.dir.as_deref(), Some("renders"));
+        assert!(config.output.sidecar);
+        assert_eq!(config.output.name_template.as_deref(), Some("{model}/{n}"));
+        assert_eq!(config.output.extensions.get("jpeg").map(String::as_str), Some("jpeg"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn load_azure_section() {
+        let dir = std::env::temp_dir().join("imagen_config_azure_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            "[keys]\nazure_openai = \"az-key\"\n\n[azure]\n\
+             azure_endpoint = \"https://res.openai.azure.com\"\ndeployment = \"img\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&path, None).unwrap();
+        assert_eq!(config.keys.azure_openai.as_deref(), Some("az-key"));
+        assert_eq!(config.azure.azure_endpoint.as_deref(), Some("https://res.openai.azure.com"));
+        assert_eq!(config.azure.deployment.as_deref(), Some("img"));
+        assert_eq!(config.azure.api_version, None);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn load_aws_section() {
+        let dir = std::env::temp_dir().join("imagen_config_aws_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "[aws]\nregion = \"us-west-2\"\nprofile = \"imagen\"\n").unwrap();
+
+        let config = Config::load(&path, None).unwrap();
+        assert_eq!(config.aws.region.as_deref(), Some("us-west-2"));
+        assert_eq!(config.aws.profile.as_deref(), Some("imagen"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn load_vertex_section() {
+        let dir = std::env::temp_dir().join("imagen_config_vertex_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "[vertex]\nproject = \"my-project\"\nlocation = \"us-central1\"\n")
+            .unwrap();
+
+        let config = Config::load(&path, None).unwrap();
+        assert_eq!(config.vertex.project.as_deref(), Some("my-project"));
+        assert_eq!(config.vertex.location.as_deref(), Some("us-central1"));
+        assert!(config.vertex.credentials.is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn load_cassette_section() {
+        let dir = std::env::temp_dir().join("imagen_config_cassette_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            "[cassette]\ndir = \"fixtures/imagen\"\nfilename = \"{slug}.yaml\"\n",
+        )
+        .unwrap();
+
+        let config = Config::load(&path, None).unwrap();
+        assert_eq!(config.cassette_dir(), PathBuf::from("fixtures/imagen"));
+        assert_eq!(config.cassette_filename(), "{slug}.yaml");
+
+        let defaults = Config::default();
+        assert_eq!(defaults.cassette_dir(), PathBuf::from(".imagen/cassettes"));
+        assert_eq!(defaults.cassette_filename(), "{timestamp}/image_generator.cassette.yaml");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn load_http_section() {
+        std::env::remove_var("IMAGEN_RUN_ID");
+        let config: Config = toml::from_str("[http]\nrun_id = \"nightly-42\"\n").unwrap();
+        assert!(config.identify());
+        assert_eq!(config.run_id().as_deref(), Some("nightly-42"));
+
+        let config: Config =
+            toml::from_str("[http]\nidentify = false\nrun_id = \"nightly-42\"\n").unwrap();
+        assert!(!config.identify());
+        assert_eq!(config.run_id(), None);
+    }
+
+    #[test]
+    fn load_provider_timeouts() {
+        let dir = std::env::temp_dir().join("imagen_config_providers_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+[providers.gemini]
+read_timeout_secs = 900
+
+[providers.openai]
+connect_timeout_secs = 3
+read_timeout_secs = 60
+
+[providers.sd_local]
+base_url = "http://gpu-box:7860"
+"#,
+        )
+        .unwrap();
+
+        let config = Config::load(&path, None).unwrap();
+        assert_eq!(config.providers.gemini.connect_timeout_secs, None);
+        assert_eq!(config.providers.gemini.read_timeout_secs, Some(900));
+        assert_eq!(config.providers.openai.connect_timeout_secs, Some(3));
+        assert_eq!(config.providers.openai.read_timeout_secs, Some(60));
+        assert_eq!(config.providers.sd_local.base_url.as_deref(), Some("http://gpu-box:7860"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn load_invalid_toml() {
         let dir = std::env::temp_dir().join("imagen_config_bad_test");
@@ -193,7 +995,107 @@ format = "png"
         let path = dir.join("bad.toml");
         std::fs::write(&path, "this is not valid toml {{{").unwrap();
 
-        assert!(Config::load(&path).is_err());
+        assert!(Config::load(&path, None).is_err());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn unknown_and_mistyped_settings_are_named() {
+        let table: toml::Table = toml::from_str(
+            r#"
+[defaultss]
+model = "gpt-1"
+
+[defaults]
+qualty = "high"
+size = 2
+
+[defaults.per_model.gpt-image-1]
+formt = "png"
+
+[prompts]
+hero = "wide banner"
+"#,
+        )
+        .unwrap();
+        let problems = invalid_settings(&table, &["profiles", "work"]);
+        assert_eq!(problems.len(), 4, "got: {problems:?}");
+        assert!(problems.iter().any(|p| p.starts_with("profiles.work.defaultss: unknown field")));
+        assert!(problems.iter().any(|p| p.starts_with("profiles.work.defaults.qualty: unknown")));
+        assert!(problems
+            .iter()
+            .any(|p| p.starts_with("profiles.work.defaults.size: invalid type")));
+        assert!(problems
+            .iter()
+            .any(|p| p.starts_with("profiles.work.defaults.per_model.gpt-image-1.formt: unknown")));
+
+        let err = Config::parse("[defaults]\nqualty = \"high\"\n", None).unwrap_err();
+        assert!(err.starts_with("defaults.qualty: unknown field `qualty`"), "got: {err}");
+    }
+
+    #[test]
+    fn env_references_expand_at_load() {
+        std::env::set_var("IMAGEN_TEST_WORK_KEY", "sk-work");
+        std::env::remove_var("IMAGEN_TEST_UNSET_KEY");
+        let config = Config::parse(
+            r#"
+[keys]
+openai = "${IMAGEN_TEST_WORK_KEY}"
+openai_base_url = "https://${IMAGEN_TEST_WORK_KEY}.example.com/$${path}/$5"
+
+[profiles.home.keys]
+gemini = "${IMAGEN_TEST_UNSET_KEY}"
+"#,
+            None,
+        )
+        .unwrap();
+        assert_eq!(config.keys.openai.as_deref(), Some("sk-work"));
+        assert_eq!(
+            config.keys.openai_base_url.as_deref(),
+            Some("https://sk-work.example.com/${path}/$5")
+        );
+
+        let err =
+            Config::parse("[keys]\ngemini = \"${IMAGEN_TEST_UNSET_KEY}\"\n", None).unwrap_err();
+        assert!(err.contains("keys.gemini") && err.contains("IMAGEN_TEST_UNSET_KEY"), "got: {err}");
+        let raw = Config::parse_unexpanded("[keys]\ngemini = \"${IMAGEN_TEST_UNSET_KEY}\"\n", None)
+            .unwrap();
+        assert_eq!(raw.keys.gemini.as_deref(), Some("${IMAGEN_TEST_UNSET_KEY}"));
+    }
+
+    #[test]
+    fn profile_merges_over_the_rest_of_the_file() {
+        let dir = std::env::temp_dir().join("imagen_config_profile_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(
+            &path,
+            r#"
+[keys]
+gemini = "personal-gemini-key"
+
+[defaults]
+model = "nano-banana"
+size = "2K"
+
+[profiles.work.keys]
+openai = "work-openai-key"
+
+[profiles.work.defaults]
+model = "gpt-1"
+"#,
+        )
+        .unwrap();
+
+        let work = Config::load(&path, Some("work")).unwrap();
+        assert_eq!(work.keys.openai.as_deref(), Some("work-openai-key"));
+        assert_eq!(work.keys.gemini.as_deref(), Some("personal-gemini-key"));
+        assert_eq!((work.defaults.model.as_str(), work.defaults.size.as_str()), ("gpt-1", "2K"));
+        let base = Config::load(&path, None).unwrap();
+        assert_eq!((base.defaults.model.as_str(), base.keys.openai), ("nano-banana", None));
+        let err = Config::load(&path, Some("home")).unwrap_err();
+        assert!(err.contains("'home'") && err.contains("defined: work"), "got: {err}");
 
         let _ = std::fs::remove_dir_all(&dir);
     }
@@ -201,11 +1103,12 @@ format = "png"
     #[test]
     fn gemini_key_env_override() {
         let config = Config {
-            keys: KeysConfig { gemini: Some("from-file".into()), openai: None },
+            keys: KeysConfig { gemini: Some("from-file".into()), ..KeysConfig::default() },
             ..Config::default()
         };
 
-        // Without env var, returns file value
+        // Without keychain or env var, returns file value
+        std::env::set_var("IMAGEN_NO_KEYRING", "1");
         std::env::remove_var("GEMINI_API_KEY");
         assert_eq!(config.gemini_key().as_deref(), Some("from-file"));
     }
@@ -0,0 +1,122 @@
+//! `--contact-sheet`: one grid image of every result of a run, to pick the winner from.
+//! `imagen compare --composite` lays out its models' images the same way.
+//!
+//! Each image is scaled to fit a square cell and labeled underneath, by default with its
+//! file name,
+//! in the watermark's bitmap font, on a dark background. The grid is as close to square
+//! as the number of images allows, filled row by row in the order the files were saved.
+
+use std::path::{Path, PathBuf};
+
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbImage, Rgba, RgbaImage};
+
+use crate::error::ImageError;
+use crate::watermark::{text_stamp, ADVANCE};
+
+/// Width and height of the square each image is fitted into.
+const CELL: u32 = 384;
+/// Space around cells and between a cell and its label.
+const GAP: u32 = 16;
+/// Height of a label: two-pixel glyphs of the 8-row font, plus their shadow.
+const LABEL: u32 = 17;
+const BACKGROUND: Rgba<u8> = Rgba([32, 32, 32, 255]);
+/// JPEG quality of the sheet.
+const QUALITY: u8 = 90;
+
+/// Lay out `tiles`, each an image and its label, in a grid.
+#[must_use]
+pub fn render(tiles: &[(String, DynamicImage)]) -> RgbImage {
+    let count = u32::try_from(tiles.len()).unwrap_or(u32::MAX).max(1);
+    let columns = count.isqrt() + u32::from(count.isqrt().pow(2) < count);
+    let rows = count.div_ceil(columns);
+    let (cell_width, cell_height) = (CELL + GAP, CELL + GAP + LABEL + GAP);
+    let (width, height) = (columns * cell_width + GAP, rows * cell_height + GAP);
+    let mut canvas = RgbaImage::from_pixel(width, height, BACKGROUND);
+    for ((label, img), i) in tiles.iter().zip(0..) {
+        let (left, top) = (GAP + i % columns * cell_width, GAP + i / columns * cell_height);
+        let fitted = img.resize(CELL, CELL, FilterType::Triangle).to_rgba8();
+        let x = left + (CELL - fitted.width()) / 2;
+        let y = top + (CELL - fitted.height()) / 2;
+        image::imageops::overlay(&mut canvas, &fitted, i64::from(x), i64::from(y));
+
+        let text = text_stamp(&shorten(label), CELL, 2 * 256, 2 * 256);
+        let x = left + CELL.saturating_sub(text.width()) / 2;
+        image::imageops::overlay(&mut canvas, &text, i64::from(x), i64::from(top + CELL + GAP));
+    }
+    DynamicImage::ImageRgba8(canvas).to_rgb8()
+}
+
+/// `label` cut to what fits under a cell at double size, keeping its end: the image
+/// number and extension are what tell a run's files apart.
+fn shorten(label: &str) -> String {
+    // The text stamp keeps one glyph column spare.
+    let fits = (CELL / (2 * ADVANCE) - 1) as usize;
+    if label.chars().count() <= fits {
+        return label.to_string();
+    }
+    let skip = label.chars().count() - (fits - 3);
+    let kept: String = label.chars().skip(skip).collect();
+    format!("...{kept}")
+}
+
+/// Each of `files` labeled with its file name, as [`save`] takes them.
+#[must_use]
+pub fn by_name(files: &[PathBuf]) -> Vec<(String, PathBuf)> {
+    let name = |file: &PathBuf| file.file_name().unwrap_or_default().to_string_lossy().to_string();
+    files.iter().map(|file| (name(file), file.clone())).collect()
+}
+
+/// Render the images saved at `files`, each with its label, into a sheet at `path`, as a
+/// JPEG. Files that cannot be read back are left out with a warning.
+///
+/// # Errors
+///
+/// Returns an error if none of `files` can be read, or the sheet cannot be written.
+pub fn save(files: &[(String, PathBuf)], path: &Path) -> Result<(), ImageError> {
+    let mut tiles = Vec::new();
+    for (label, file) in files {
+        match image::open(file) {
+            Ok(img) => tiles.push((label.clone(), img)),
+            Err(e) => crate::report::warn(format_args!(
+                "leaving {} off the contact sheet: {e}",
+                file.display()
+            )),
+        }
+    }
+    if tiles.is_empty() {
+        return Err(ImageError::ImageConversion("no images to put on the contact sheet".into()));
+    }
+    let mut encoded = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut encoded, QUALITY)
+        .encode_image(&render(&tiles))
+        .map_err(|e| ImageError::ImageConversion(format!("Failed to encode contact sheet: {e}")))?;
+    std::fs::write(path, encoded)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    #[test]
+    fn grid_is_nearly_square_and_labeled() {
+        let red = DynamicImage::ImageRgb8(RgbImage::from_pixel(800, 400, Rgb([255, 0, 0])));
+        let tiles: Vec<_> = (1..=3).map(|n| (format!("cat-{n}.png"), red.clone())).collect();
+        let sheet = render(&tiles);
+        // Two columns, two rows, the last cell empty.
+        assert_eq!(
+            sheet.dimensions(),
+            (2 * (CELL + GAP) + GAP, 2 * (CELL + 2 * GAP + LABEL) + GAP)
+        );
+        assert_eq!(sheet.get_pixel(GAP + CELL / 2, GAP + CELL / 2).0, [255, 0, 0]);
+        assert_eq!(sheet.get_pixel(GAP + CELL / 2, GAP + 10).0, BACKGROUND.0[..3], "letterboxed");
+        let label = (GAP..GAP + CELL).flat_map(|x| (0..LABEL).map(move |y| (x, y)));
+        let white = |(x, y): (u32, u32)| sheet.get_pixel(x, GAP + CELL + GAP + y).0 == [255; 3];
+        assert!(label.into_iter().any(white), "the first cell is labeled");
+
+        let long = text_stamp(&shorten(&"a".repeat(200)), CELL, 2 * 256, 2 * 256);
+        assert!(long.width() <= CELL && long.height() == LABEL, "long names are cut to fit");
+    }
+}
@@ -1,23 +1,192 @@
 //! Service context that bundles all port trait objects.
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::adapters::live::gemini::GeminiGenerator;
-use crate::adapters::live::openai::OpenAiGenerator;
+use crate::adapters::editing::EditingImageGenerator;
+use crate::adapters::fan_out::FanOutImageGenerator;
+use crate::adapters::live::aws::{resolve_credentials, resolve_profile, resolve_region};
+use crate::adapters::live::bedrock::BedrockGenerator;
+use crate::adapters::live::gemini::{GeminiGenerator, VertexTarget};
+use crate::adapters::live::google_auth;
+use crate::adapters::live::http::{build_client, HttpSettings, USER_AGENT};
+use crate::adapters::live::ideogram::IdeogramGenerator;
+use crate::adapters::live::openai::{AzureDeployment, OpenAiGenerator};
+use crate::adapters::live::replicate::ReplicateGenerator;
+use crate::adapters::live::sd_webui::SdWebUiGenerator;
+use crate::adapters::live::stability::StabilityGenerator;
+use crate::adapters::live::together::TogetherGenerator;
+use crate::adapters::local::LocalUpscaler;
 use crate::adapters::recording::image_generator::RecordingImageGenerator;
+use crate::adapters::recording::prompt_enhancer::RecordingPromptEnhancer;
 use crate::adapters::replaying::image_generator::ReplayingImageGenerator;
+use crate::adapters::replaying::prompt_enhancer::ReplayingPromptEnhancer;
+use crate::adapters::retrying::RetryingImageGenerator;
 use crate::cassette::config::load_cassette;
+use crate::cassette::format::Invocation;
 use crate::cassette::recorder::CassetteRecorder;
-use crate::config::Config;
+use crate::config::{Config, ProviderConfig};
 use crate::error::ImageError;
 use crate::model::Provider;
-use crate::ports::ImageGenerator;
+use crate::output::sanitize_for_filename;
+use crate::params::{max_count, one_image_per_call};
+use crate::ports::{ImageEditor, ImageGenerator, PromptEnhancer, Upscaler};
+use crate::report;
+
+/// Filename template for dev-build auto-recordings under `IMAGEN_REC_DIR`.
+const DEV_CASSETTE_FILENAME: &str = "{date}/{time}-{slug}.cassette.yaml";
+
+/// Default connect timeout for all providers.
+const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+/// Default read timeout for Gemini (4K renders can take several minutes).
+const GEMINI_READ_TIMEOUT_SECS: u64 = 600;
+/// Default read timeout for `OpenAI`.
+const OPENAI_READ_TIMEOUT_SECS: u64 = 300;
+/// Default read timeout for Stability AI.
+const STABILITY_READ_TIMEOUT_SECS: u64 = 300;
+/// Default read timeout for Replicate (each poll is a short request).
+const REPLICATE_READ_TIMEOUT_SECS: u64 = 60;
+/// Default read timeout for a local SD web UI (CPU renders can be slow).
+const SD_LOCAL_READ_TIMEOUT_SECS: u64 = 900;
+/// Default read timeout for AWS Bedrock.
+const BEDROCK_READ_TIMEOUT_SECS: u64 = 300;
+/// Default read timeout for Together AI.
+const TOGETHER_READ_TIMEOUT_SECS: u64 = 300;
+/// Default read timeout for Ideogram.
+const IDEOGRAM_READ_TIMEOUT_SECS: u64 = 300;
+/// Text model for `--enhance` with Gemini image models.
+const DEFAULT_GEMINI_ENHANCE_MODEL: &str = "gemini-2.5-flash";
+/// Text model for `--enhance` with `OpenAI` image models.
+const DEFAULT_OPENAI_ENHANCE_MODEL: &str = "gpt-4.1-mini";
+/// Attempts made for a request that fails with a transient error.
+const MAX_ATTEMPTS: u32 = 3;
+/// Delay before the first retry; doubles on each further attempt.
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(2);
+/// Most concurrent requests issued for one `--count` on providers without native counts,
+/// and for the requests of one sweep or batch.
+pub const MAX_PARALLEL_REQUESTS: usize = 4;
+
+/// What a context's generator does with each request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Create new images from the prompt (and any reference images).
+    Generate,
+    /// Modify the input images as the prompt describes (`imagen edit`).
+    Edit,
+}
+
+/// Where a run's images come from (`--mode`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mode {
+    /// Call the provider.
+    Live,
+    /// Call the provider and record a cassette, at the given path or an auto-generated one.
+    Record(Option<PathBuf>),
+    /// Answer every request from a cassette, without network access.
+    Replay(PathBuf),
+}
+
+impl Mode {
+    /// Parse a `--mode` value: `live`, `record`, `record:<path>`, or `replay:<path>`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for an unknown mode or a replay without a cassette path.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let (name, path) = match value.split_once(':') {
+            Some((name, path)) => (name, Some(path).filter(|p| !p.is_empty())),
+            None => (value, None),
+        };
+        match (name, path) {
+            ("live", None) => Ok(Self::Live),
+            ("live", Some(_)) => Err("--mode live takes no cassette path".to_string()),
+            ("record", path) => Ok(Self::Record(path.map(PathBuf::from))),
+            ("replay", Some(path)) => Ok(Self::Replay(PathBuf::from(path))),
+            ("replay", None) => {
+                Err("--mode replay needs a cassette: --mode replay:<path>".to_string())
+            }
+            _ => Err(format!(
+                "Unknown mode '{value}'. Valid: live, record, record:<path>, replay:<path>"
+            )),
+        }
+    }
+
+    /// Resolve the run's mode from `--mode`, falling back to the deprecated
+    /// `IMAGEN_RECORD` / `IMAGEN_REPLAY` variables.
+    ///
+    /// In debug builds, a run with no mode selected is recorded under `IMAGEN_REC_DIR`
+    /// when that is set; `--mode live` opts out.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for an invalid `--mode`, or when the sources disagree.
+    pub fn select(flag: Option<&str>, prompt: &str) -> Result<Self, ImageError> {
+        let env = |name| std::env::var(name).ok();
+        let mode = resolve_mode(flag, env("IMAGEN_RECORD"), env("IMAGEN_REPLAY"))
+            .map_err(ImageError::InvalidArgument)?;
+        if let Some(mode) = mode {
+            return Ok(mode);
+        }
+        match env("IMAGEN_REC_DIR") {
+            Some(rec_dir) if cfg!(debug_assertions) => {
+                Ok(Self::Record(Some(dev_recording_path(Path::new(&rec_dir), prompt))))
+            }
+            _ => Ok(Self::Live),
+        }
+    }
+}
+
+/// Combine `--mode` with the `IMAGEN_RECORD` / `IMAGEN_REPLAY` fallbacks, or `None`
+/// when none of them is set.
+///
+/// The flag wins over the environment, but only if the two agree on the kind of mode:
+/// `--mode record` under `IMAGEN_REPLAY` is a conflict, not an override.
+fn resolve_mode(
+    flag: Option<&str>,
+    record: Option<String>,
+    replay: Option<String>,
+) -> Result<Option<Mode>, String> {
+    let from_env = match (record, replay) {
+        (Some(_), Some(_)) => {
+            return Err("IMAGEN_RECORD and IMAGEN_REPLAY are both set; choose one, or pass \
+                        --mode"
+                .to_string())
+        }
+        (Some(value), None) => {
+            report::warn("IMAGEN_RECORD is deprecated; use --mode record[:<path>]");
+            Some(match value.as_str() {
+                "true" | "1" => Mode::Record(None),
+                path => Mode::Record(Some(PathBuf::from(path))),
+            })
+        }
+        (None, Some(path)) => {
+            report::warn("IMAGEN_REPLAY is deprecated; use --mode replay:<path>");
+            Some(Mode::Replay(PathBuf::from(path)))
+        }
+        (None, None) => None,
+    };
+    let Some(flag) = flag else {
+        return Ok(from_env);
+    };
+    let mode = Mode::parse(flag)?;
+    match (&mode, &from_env) {
+        (Mode::Record(_), Some(Mode::Replay(_))) | (Mode::Replay(_), Some(Mode::Record(_))) => {
+            Err(format!(
+                "--mode {flag} conflicts with {}; unset it or drop --mode",
+                if matches!(mode, Mode::Record(_)) { "IMAGEN_REPLAY" } else { "IMAGEN_RECORD" }
+            ))
+        }
+        _ => Ok(Some(mode)),
+    }
+}
 
 /// Bundles all port trait objects into a single context.
 pub struct ServiceContext {
     /// Image generator port.
     pub generator: Box<dyn ImageGenerator>,
+    /// Prompt enhancer port, present when the run uses `--enhance`.
+    pub enhancer: Option<Box<dyn PromptEnhancer>>,
 }
 
 /// Handle to a recording session that must be finished after use.
@@ -26,6 +195,15 @@ pub struct RecordingSession {
 }
 
 impl RecordingSession {
+    /// Store the (scrubbed) command line and effective parameters in the cassette header.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a thread panicked while recording to the cassette.
+    pub fn set_invocation(&self, invocation: Invocation) {
+        self.recorder.lock().expect("recorder lock poisoned").set_invocation(invocation);
+    }
+
     /// Finish the recording and write cassette files to disk.
     ///
     /// # Errors
@@ -41,37 +219,49 @@ impl RecordingSession {
 }
 
 impl ServiceContext {
-    /// Create a live context for the given provider.
+    /// Create a live context for the given provider and operation.
     ///
     /// # Errors
     ///
-    /// Returns an error if the API key is not configured.
-    pub fn live(provider: Provider, config: &Config) -> Result<Self, ImageError> {
-        let generator: Box<dyn ImageGenerator> = match provider {
-            Provider::Gemini => {
-                let key = config.gemini_key().ok_or(ImageError::MissingApiKey {
-                    provider: "Gemini".into(),
-                    env_var: "GEMINI_API_KEY".into(),
-                })?;
-                warn_if_key_invalid(&key, "Gemini");
-                Box::new(GeminiGenerator::new(key))
-            }
-            Provider::OpenAi => {
-                let key = config.openai_key().ok_or(ImageError::MissingApiKey {
-                    provider: "OpenAI".into(),
-                    env_var: "OPENAI_API_KEY".into(),
-                })?;
-                warn_if_key_invalid(&key, "OpenAI");
-                Box::new(OpenAiGenerator::new(key))
-            }
+    /// Returns an error if the provider needs an API key and none is configured, or
+    /// if `operation` is [`Operation::Edit`] and the provider cannot edit images.
+    pub fn live(
+        provider: Provider,
+        config: &Config,
+        operation: Operation,
+    ) -> Result<Self, ImageError> {
+        let generator = match operation {
+            Operation::Generate => live_generator(provider, config)?,
+            Operation::Edit => Box::new(EditingImageGenerator::new(live_editor(provider, config)?)),
         };
-        Ok(Self { generator })
+        let generator =
+            Box::new(RetryingImageGenerator::new(generator, MAX_ATTEMPTS, RETRY_BASE_DELAY));
+        // Fanned-out calls are retried one by one, so a single failure costs one call's
+        // images. Counts over the limit only get here with `--split`.
+        let per_call = if one_image_per_call(provider) { Some(1) } else { max_count(provider) };
+        if let Some(per_call) = per_call {
+            let generator =
+                FanOutImageGenerator::new(generator, MAX_PARALLEL_REQUESTS).per_call(per_call);
+            return Ok(Self { generator: Box::new(generator), enhancer: None });
+        }
+        Ok(Self { generator, enhancer: None })
+    }
+
+    /// Create a live context that makes exactly one attempt per request (no retries).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the provider needs an API key and none is configured.
+    pub fn single_attempt(provider: Provider, config: &Config) -> Result<Self, ImageError> {
+        Ok(Self { generator: live_generator(provider, config)?, enhancer: None })
     }
 
     /// Create a recording context that wraps a live adapter with a recorder.
     ///
     /// If `cassette_path` is `Some`, the cassette is written to that exact path.
-    /// Otherwise a timestamped path under `.imagen/cassettes/` is auto-generated.
+    /// Otherwise the path comes from the `[cassette]` dir and filename template
+    /// (by default `.imagen/cassettes/<timestamp>/image_generator.cassette.yaml`).
+    /// With an `enhance_model`, prompt enhancements are recorded in the same cassette.
     ///
     /// # Errors
     ///
@@ -79,19 +269,28 @@ impl ServiceContext {
     pub fn recording(
         provider: Provider,
         config: &Config,
+        operation: Operation,
         cassette_path: Option<&Path>,
+        prompt: &str,
+        enhance_model: Option<&str>,
     ) -> Result<(Self, RecordingSession), ImageError> {
-        let live_ctx = Self::live(provider, config)?;
+        let live_ctx = Self::live(provider, config, operation)?;
+        let enhancer = enhance_model.map(|model| live_enhancer(model, config)).transpose()?;
 
-        let timestamp = chrono::Utc::now().format("%Y-%m-%dT%H-%M-%S").to_string();
+        let now = chrono::Utc::now();
+        let timestamp = now.format("%Y-%m-%dT%H-%M-%S").to_string();
         let commit = get_commit_hash();
 
-        let path = if let Some(p) = cassette_path {
-            p.to_path_buf()
-        } else {
-            let output_dir = std::path::PathBuf::from(".imagen/cassettes").join(&timestamp);
-            output_dir.join("image_generator.cassette.yaml")
-        };
+        let path = cassette_path.map_or_else(
+            || {
+                config.cassette_dir().join(render_cassette_name(
+                    config.cassette_filename(),
+                    prompt,
+                    now,
+                ))
+            },
+            Path::to_path_buf,
+        );
 
         let recorder = Arc::new(Mutex::new(CassetteRecorder::new(
             path,
@@ -101,33 +300,350 @@ impl ServiceContext {
 
         let recording_gen = RecordingImageGenerator::new(live_ctx.generator, Arc::clone(&recorder));
 
-        let ctx = Self { generator: Box::new(recording_gen) };
+        let enhancer = enhancer.map(|enhancer| {
+            Box::new(RecordingPromptEnhancer::new(enhancer, Arc::clone(&recorder)))
+                as Box<dyn PromptEnhancer>
+        });
+        let ctx = Self { generator: Box::new(recording_gen), enhancer };
         let session = RecordingSession { recorder };
 
         Ok((ctx, session))
     }
 
-    /// Create a replaying context from a cassette file.
+    /// Create the context for a run's [`Mode`], with the recording session to finish
+    /// afterwards when recording. An `enhance_model` adds a prompt enhancer.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the live adapters or recording cannot be set up, or if the
+    /// cassette to replay cannot be loaded.
+    pub fn for_mode(
+        mode: &Mode,
+        provider: Provider,
+        config: &Config,
+        operation: Operation,
+        prompt: &str,
+        enhance_model: Option<&str>,
+    ) -> Result<(Self, Option<RecordingSession>), ImageError> {
+        match mode {
+            Mode::Live => {
+                let mut ctx = Self::live(provider, config, operation)?;
+                ctx.enhancer =
+                    enhance_model.map(|model| live_enhancer(model, config)).transpose()?;
+                Ok((ctx, None))
+            }
+            Mode::Record(path) => {
+                let (ctx, session) = Self::recording(
+                    provider,
+                    config,
+                    operation,
+                    path.as_deref(),
+                    prompt,
+                    enhance_model,
+                )?;
+                Ok((ctx, Some(session)))
+            }
+            Mode::Replay(path) => Ok((Self::replaying(path, enhance_model.is_some())?, None)),
+        }
+    }
+
+    /// Create a replaying context from a cassette file, replaying prompt enhancements
+    /// too when `enhance` is set.
     ///
     /// # Errors
     ///
     /// Returns an error if the cassette file cannot be loaded.
-    pub fn replaying(path: &Path) -> Result<Self, ImageError> {
+    pub fn replaying(path: &Path, enhance: bool) -> Result<Self, ImageError> {
         let replayer = load_cassette(path)
             .map_err(|e| ImageError::Config(format!("Failed to load cassette: {e}")))?;
         let replayer = Arc::new(Mutex::new(replayer));
+        let enhancer = enhance.then(|| {
+            Box::new(ReplayingPromptEnhancer::new(Arc::clone(&replayer))) as Box<dyn PromptEnhancer>
+        });
         let generator = Box::new(ReplayingImageGenerator::new(replayer));
-        Ok(Self { generator })
+        Ok(Self { generator, enhancer })
     }
 }
 
+/// Build the cassette path for a dev-build auto-recording under `IMAGEN_REC_DIR`.
+///
+/// Layout: `<rec_dir>/<YYYY-MM-DD>/<HH-MM-SS>-<sanitized-prompt>.cassette.yaml`.
+fn dev_recording_path(rec_dir: &Path, prompt: &str) -> PathBuf {
+    rec_dir.join(render_cassette_name(DEV_CASSETTE_FILENAME, prompt, chrono::Utc::now()))
+}
+
+/// Expand a cassette filename template.
+///
+/// `{timestamp}` is `YYYY-MM-DDTHH-MM-SS`, `{date}` is `YYYY-MM-DD`, `{time}` is
+/// `HH-MM-SS` (all UTC), and `{slug}` is the sanitized prompt.
+fn render_cassette_name(
+    template: &str,
+    prompt: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> String {
+    template
+        .replace("{timestamp}", &now.format("%Y-%m-%dT%H-%M-%S").to_string())
+        .replace("{date}", &now.format("%Y-%m-%d").to_string())
+        .replace("{time}", &now.format("%H-%M-%S").to_string())
+        .replace("{slug}", &sanitize_for_filename(prompt, 50))
+}
+
+/// HTTP client for fetching URL-delivered images (`--stream-download`), with the
+/// provider's configured timeouts.
+#[must_use]
+pub fn download_client(provider: Provider, config: &Config) -> reqwest::Client {
+    let http = match provider {
+        Provider::Replicate => {
+            http_settings(config, &config.providers.replicate, REPLICATE_READ_TIMEOUT_SECS)
+        }
+        Provider::Ideogram => {
+            http_settings(config, &config.providers.ideogram, IDEOGRAM_READ_TIMEOUT_SECS)
+        }
+        _ => http_settings(config, &config.providers.together, TOGETHER_READ_TIMEOUT_SECS),
+    };
+    build_client(&http)
+}
+
+/// Build the upscaler named by `imagen upscale --with` (`local`, `stability`, `replicate`).
+///
+/// # Errors
+///
+/// Returns an error for an unknown upscaler or if the service's API key is missing.
+pub fn upscaler(name: &str, config: &Config) -> Result<Box<dyn Upscaler>, ImageError> {
+    match name {
+        "local" => Ok(Box::new(LocalUpscaler)),
+        "stability" => {
+            let key = require_key(config.stability_key(), "Stability", "STABILITY_API_KEY")?;
+            let http =
+                http_settings(config, &config.providers.stability, STABILITY_READ_TIMEOUT_SECS);
+            Ok(Box::new(StabilityGenerator::new(key, &http)))
+        }
+        "replicate" => {
+            let key = require_key(config.replicate_key(), "Replicate", "REPLICATE_API_TOKEN")?;
+            let http =
+                http_settings(config, &config.providers.replicate, REPLICATE_READ_TIMEOUT_SECS);
+            Ok(Box::new(ReplicateGenerator::new(key, &http)))
+        }
+        other => Err(ImageError::InvalidArgument(format!(
+            "Unknown upscaler '{other}'. Valid: local, stability, replicate"
+        ))),
+    }
+}
+
+/// Build the live generator for a provider.
+fn live_generator(
+    provider: Provider,
+    config: &Config,
+) -> Result<Box<dyn ImageGenerator>, ImageError> {
+    let generator: Box<dyn ImageGenerator> = match provider {
+        Provider::Gemini => Box::new(gemini_generator(config)?),
+        Provider::OpenAi => Box::new(openai_generator(config)?),
+        Provider::AzureOpenAi => Box::new(azure_generator(config)?),
+        Provider::Stability => {
+            let key = require_key(config.stability_key(), "Stability", "STABILITY_API_KEY")?;
+            let http =
+                http_settings(config, &config.providers.stability, STABILITY_READ_TIMEOUT_SECS);
+            Box::new(StabilityGenerator::new(key, &http))
+        }
+        Provider::Replicate => {
+            let key = require_key(config.replicate_key(), "Replicate", "REPLICATE_API_TOKEN")?;
+            let http =
+                http_settings(config, &config.providers.replicate, REPLICATE_READ_TIMEOUT_SECS);
+            Box::new(ReplicateGenerator::new(key, &http))
+        }
+        Provider::SdLocal => {
+            let http =
+                http_settings(config, &config.providers.sd_local, SD_LOCAL_READ_TIMEOUT_SECS);
+            Box::new(SdWebUiGenerator::new(&config.sd_local_url(), &http))
+        }
+        Provider::Bedrock => Box::new(bedrock_generator(config)?),
+        Provider::Together => {
+            let key = require_key(config.together_key(), "Together", "TOGETHER_API_KEY")?;
+            let http =
+                http_settings(config, &config.providers.together, TOGETHER_READ_TIMEOUT_SECS);
+            Box::new(TogetherGenerator::new(key, &http))
+        }
+        Provider::Ideogram => {
+            let key = require_key(config.ideogram_key(), "Ideogram", "IDEOGRAM_API_KEY")?;
+            let http =
+                http_settings(config, &config.providers.ideogram, IDEOGRAM_READ_TIMEOUT_SECS);
+            Box::new(IdeogramGenerator::new(key, &http))
+        }
+    };
+    Ok(generator)
+}
+
+/// Check that `config` has the credentials `provider` needs, without making a request.
+///
+/// # Errors
+///
+/// Returns the error building the provider's generator would, such as a missing API key.
+pub fn check_credentials(provider: Provider, config: &Config) -> Result<(), ImageError> {
+    live_generator(provider, config).map(drop)
+}
+
+/// Build the live editor for a provider that has a dedicated image-editing path.
+fn live_editor(provider: Provider, config: &Config) -> Result<Box<dyn ImageEditor>, ImageError> {
+    let editor: Box<dyn ImageEditor> = match provider {
+        Provider::Gemini => Box::new(gemini_generator(config)?),
+        Provider::OpenAi => Box::new(openai_generator(config)?),
+        Provider::AzureOpenAi => Box::new(azure_generator(config)?),
+        _ => {
+            return Err(ImageError::InvalidArgument(format!(
+                "{provider:?} models cannot edit images. Use a Gemini or OpenAI model with \
+                 `imagen edit`."
+            )))
+        }
+    };
+    Ok(editor)
+}
+
+/// Text model for `--enhance`: `flag` (`--enhance-model`), else `[enhance] model`, else
+/// a small model from the image provider, or from whichever provider has credentials.
+#[must_use]
+pub fn enhance_model(flag: Option<&str>, provider: Provider, config: &Config) -> String {
+    if let Some(model) = flag.or(config.enhance.model.as_deref()) {
+        return model.to_string();
+    }
+    let openai = match provider {
+        Provider::Gemini => false,
+        Provider::OpenAi | Provider::AzureOpenAi => true,
+        _ => config.gemini_key().is_none() && !config.use_vertex() && config.openai_key().is_some(),
+    };
+    let model = if openai { DEFAULT_OPENAI_ENHANCE_MODEL } else { DEFAULT_GEMINI_ENHANCE_MODEL };
+    model.to_string()
+}
+
+/// Build the live enhancer for a text model: Gemini for `gemini-*`, `OpenAI` for `gpt-*`
+/// and `o*` reasoning models.
+fn live_enhancer(model: &str, config: &Config) -> Result<Box<dyn PromptEnhancer>, ImageError> {
+    if model.starts_with("gemini") {
+        Ok(Box::new(gemini_generator(config)?))
+    } else if model.starts_with("gpt") || model.starts_with('o') {
+        Ok(Box::new(openai_generator(config)?))
+    } else {
+        Err(ImageError::InvalidArgument(format!(
+            "Unknown enhance model '{model}'. Use a Gemini (gemini-*) or OpenAI (gpt-*) text model."
+        )))
+    }
+}
+
+/// Build a Gemini generator for AI Studio, or Vertex AI when it is enabled.
+fn gemini_generator(config: &Config) -> Result<GeminiGenerator, ImageError> {
+    let http = http_settings(config, &config.providers.gemini, GEMINI_READ_TIMEOUT_SECS);
+    if config.use_vertex() {
+        vertex_generator(config, &http)
+    } else {
+        let key = require_key(config.gemini_key(), "Gemini", "GEMINI_API_KEY")?;
+        Ok(GeminiGenerator::new(key, &http))
+    }
+}
+
+/// Build an `OpenAI` generator (or one for an `OpenAI`-compatible gateway).
+fn openai_generator(config: &Config) -> Result<OpenAiGenerator, ImageError> {
+    let key = require_key(config.openai_key(), "OpenAI", "OPENAI_API_KEY")?;
+    let http = http_settings(config, &config.providers.openai, OPENAI_READ_TIMEOUT_SECS);
+    Ok(OpenAiGenerator::new(key, config.openai_base_url(), &http))
+}
+
+/// Build a generator for an Azure `OpenAI` deployment.
+fn azure_generator(config: &Config) -> Result<OpenAiGenerator, ImageError> {
+    let key = require_key(config.azure_openai_key(), "Azure OpenAI", "AZURE_OPENAI_API_KEY")?;
+    let endpoint = config.azure_endpoint().ok_or_else(|| {
+        ImageError::Config(
+            "No Azure endpoint. Set AZURE_OPENAI_ENDPOINT or [azure] azure_endpoint.".into(),
+        )
+    })?;
+    let azure = AzureDeployment {
+        endpoint,
+        deployment: config.azure.deployment.clone(),
+        api_version: config.azure_api_version(),
+    };
+    let http = http_settings(config, &config.providers.openai, OPENAI_READ_TIMEOUT_SECS);
+    Ok(OpenAiGenerator::azure(key, azure, &http))
+}
+
+/// Build a Bedrock generator from the AWS credential chain and region settings.
+fn bedrock_generator(config: &Config) -> Result<BedrockGenerator, ImageError> {
+    let profile = resolve_profile(config.aws.profile.as_deref());
+    let credentials = resolve_credentials(&profile).ok_or_else(|| ImageError::MissingApiKey {
+        provider: "Bedrock".into(),
+        env_var: "AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY (or AWS_PROFILE)".into(),
+    })?;
+    let region = resolve_region(&profile, config.aws.region.as_deref()).ok_or_else(|| {
+        ImageError::Config("No AWS region for Bedrock. Set AWS_REGION or [aws] region.".into())
+    })?;
+    let http = http_settings(config, &config.providers.bedrock, BEDROCK_READ_TIMEOUT_SECS);
+    Ok(BedrockGenerator::new(
+        credentials,
+        region,
+        config.providers.bedrock.base_url.as_deref(),
+        &http,
+    ))
+}
+
+/// Build a Gemini generator that calls Vertex AI with Application Default Credentials.
+fn vertex_generator(config: &Config, http: &HttpSettings) -> Result<GeminiGenerator, ImageError> {
+    let credentials = google_auth::resolve_credentials(config.vertex.credentials.as_deref())
+        .map_err(ImageError::Config)?
+        .ok_or_else(|| ImageError::MissingApiKey {
+            provider: "Vertex AI".into(),
+            env_var: "GOOGLE_APPLICATION_CREDENTIALS (or run `gcloud auth application-default \
+                      login`)"
+                .into(),
+        })?;
+    let project = config
+        .vertex_project()
+        .or_else(|| credentials.project_id().map(ToString::to_string))
+        .ok_or_else(|| {
+            ImageError::Config(
+                "No Google Cloud project for Vertex AI. Set GOOGLE_CLOUD_PROJECT or [vertex] \
+                 project."
+                    .into(),
+            )
+        })?;
+    let target = VertexTarget { project, location: config.vertex_location() };
+    Ok(GeminiGenerator::vertex(target, credentials, http))
+}
+
+/// Resolve HTTP settings for a provider, filling unset timeouts from `[http]` and then
+/// the built-in defaults.
+fn http_settings(
+    config: &Config,
+    provider: &ProviderConfig,
+    default_read_secs: u64,
+) -> HttpSettings {
+    let connect = provider
+        .connect_timeout_secs
+        .or(config.http.connect_timeout)
+        .unwrap_or(DEFAULT_CONNECT_TIMEOUT_SECS);
+    let read = provider.read_timeout_secs.unwrap_or(default_read_secs);
+    HttpSettings {
+        connect_timeout: Some(Duration::from_secs(connect)),
+        read_timeout: Some(Duration::from_secs(read)),
+        request_timeout: config.http.request_timeout.map(Duration::from_secs),
+        user_agent: config.identify().then(|| USER_AGENT.to_string()),
+        run_id: config.run_id(),
+    }
+}
+
+/// Unwrap a configured API key, warning if it looks invalid.
+fn require_key(key: Option<String>, provider: &str, env_var: &str) -> Result<String, ImageError> {
+    let key = key.ok_or_else(|| ImageError::MissingApiKey {
+        provider: provider.into(),
+        env_var: env_var.into(),
+    })?;
+    warn_if_key_invalid(&key, provider);
+    Ok(key)
+}
+
 /// Log a warning if an API key looks invalid.
 fn warn_if_key_invalid(key: &str, provider: &str) {
     let trimmed = key.trim();
     if trimmed.is_empty() {
-        eprintln!("Warning: {provider} API key is empty");
+        report::warn(format_args!("{provider} API key is empty"));
     } else if trimmed.len() < 10 {
-        eprintln!("Warning: {provider} API key looks too short ({} chars)", trimmed.len());
+        report::warn(format_args!("{provider} API key looks too short ({} chars)", trimmed.len()));
     }
 }
 
@@ -141,3 +657,96 @@ fn get_commit_hash() -> String {
         .and_then(|o| String::from_utf8(o.stdout).ok())
         .map_or_else(|| "unknown".to_string(), |s| s.trim().to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_timeouts_layer_provider_over_shared_defaults() {
+        let mut config: Config =
+            toml::from_str("[http]\nconnect_timeout = 5\nrequest_timeout = 120").unwrap();
+        config.providers.openai.connect_timeout_secs = Some(3);
+
+        let gemini = http_settings(&config, &config.providers.gemini, GEMINI_READ_TIMEOUT_SECS);
+        assert_eq!(gemini.connect_timeout, Some(Duration::from_secs(5)));
+        assert_eq!(gemini.read_timeout, Some(Duration::from_secs(GEMINI_READ_TIMEOUT_SECS)));
+        assert_eq!(gemini.request_timeout, Some(Duration::from_secs(120)));
+        let openai = http_settings(&config, &config.providers.openai, OPENAI_READ_TIMEOUT_SECS);
+        assert_eq!(openai.connect_timeout, Some(Duration::from_secs(3)));
+
+        let defaults = http_settings(&Config::default(), &ProviderConfig::default(), 60);
+        assert_eq!(
+            defaults.connect_timeout,
+            Some(Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS))
+        );
+        assert_eq!(defaults.request_timeout, None);
+    }
+
+    #[test]
+    fn mode_values() {
+        assert_eq!(Mode::parse("live"), Ok(Mode::Live));
+        assert_eq!(Mode::parse("record"), Ok(Mode::Record(None)));
+        assert_eq!(Mode::parse("record:run.yaml"), Ok(Mode::Record(Some("run.yaml".into()))));
+        assert_eq!(Mode::parse("replay:run.yaml"), Ok(Mode::Replay("run.yaml".into())));
+        assert!(Mode::parse("replay").unwrap_err().contains("replay:<path>"));
+        assert!(Mode::parse("live:run.yaml").is_err());
+        assert!(Mode::parse("mock").unwrap_err().contains("Valid: live"));
+    }
+
+    #[test]
+    fn mode_falls_back_to_the_environment() {
+        let env = |v: &str| Some(v.to_string());
+        assert_eq!(resolve_mode(None, None, None), Ok(None));
+        assert_eq!(resolve_mode(None, env("1"), None), Ok(Some(Mode::Record(None))));
+        assert_eq!(
+            resolve_mode(None, env("out.yaml"), None),
+            Ok(Some(Mode::Record(Some("out.yaml".into()))))
+        );
+        assert_eq!(
+            resolve_mode(None, None, env("in.yaml")),
+            Ok(Some(Mode::Replay("in.yaml".into())))
+        );
+        assert_eq!(
+            resolve_mode(Some("replay:a.yaml"), None, env("b.yaml")),
+            Ok(Some(Mode::Replay("a.yaml".into())))
+        );
+        assert_eq!(resolve_mode(Some("live"), env("1"), None), Ok(Some(Mode::Live)));
+    }
+
+    #[test]
+    fn conflicting_modes_are_rejected() {
+        let env = |v: &str| Some(v.to_string());
+        assert!(resolve_mode(None, env("1"), env("in.yaml")).unwrap_err().contains("both set"));
+        assert!(resolve_mode(Some("record"), None, env("in.yaml"))
+            .unwrap_err()
+            .contains("conflicts with IMAGEN_REPLAY"));
+        assert!(resolve_mode(Some("replay:a.yaml"), env("1"), None)
+            .unwrap_err()
+            .contains("conflicts with IMAGEN_RECORD"));
+    }
+
+    #[test]
+    fn dev_recording_path_layout() {
+        let path = dev_recording_path(Path::new("/tmp/rec"), "A cat, on a mat!");
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        assert!(name.ends_with("-a-cat-on-a-mat.cassette.yaml"), "got: {name}");
+
+        let day = path.parent().unwrap();
+        assert_eq!(day.parent().unwrap(), Path::new("/tmp/rec"));
+        assert_eq!(day.file_name().unwrap().len(), "2026-01-01".len());
+    }
+
+    #[test]
+    fn cassette_name_template() {
+        let now = chrono::DateTime::parse_from_rfc3339("2026-03-01T09:15:30Z").unwrap().to_utc();
+        assert_eq!(
+            render_cassette_name("{timestamp}/image_generator.cassette.yaml", "ignored", now),
+            "2026-03-01T09-15-30/image_generator.cassette.yaml"
+        );
+        assert_eq!(
+            render_cassette_name("{date}/{slug}-{time}.yaml", "A red square", now),
+            "2026-03-01/a-red-square-09-15-30.yaml"
+        );
+    }
+}
@@ -1,5 +1,8 @@
 //! Unified error type for imagen.
 
+use std::path::PathBuf;
+use std::time::Duration;
+
 use thiserror::Error;
 
 /// Errors that can occur during image generation.
@@ -14,6 +17,17 @@ pub enum ImageError {
         message: String,
     },
 
+    /// The provider is rate limiting requests (HTTP 429) and said when to try again.
+    ///
+    /// Displays like the equivalent [`ImageError::Api`], so recordings read the same.
+    #[error("API error (429): {message}")]
+    RateLimited {
+        /// Error body from the API.
+        message: String,
+        /// How long the provider asked us to wait before retrying.
+        retry_after: Duration,
+    },
+
     /// A network error occurred.
     #[error("Network error: {0}")]
     Network(#[from] reqwest::Error),
@@ -30,10 +44,93 @@ pub enum ImageError {
     #[error("Invalid argument: {0}")]
     InvalidArgument(String),
 
+    /// The shared history/usage/cache store failed.
+    #[error("Store error: {0}")]
+    Store(#[from] rusqlite::Error),
+
     /// Image format conversion error.
     #[error("Image conversion error: {0}")]
     ImageConversion(String),
 
+    /// The operation needs network access but `--offline` was given.
+    #[error("Offline mode: {0}")]
+    Offline(String),
+
+    /// The provider answered successfully but returned no images.
+    ///
+    /// Usually a transient sampling or safety hiccup, so it is retryable.
+    #[error(
+        "No images in response (finish reason: {}). {detail}",
+        .finish_reason.as_deref().unwrap_or("none")
+    )]
+    NoImages {
+        /// Why generation stopped, when the provider reports it (e.g., `"SAFETY"`).
+        finish_reason: Option<String>,
+        /// Excerpt of the provider response, for diagnosis.
+        detail: String,
+    },
+
+    /// The provider refused the prompt before generating anything.
+    ///
+    /// Unlike [`ImageError::NoImages`], resending the same prompt will be refused again.
+    #[error("Prompt blocked (reason: {reason}){}", flagged_suffix(.flagged))]
+    PromptBlocked {
+        /// The provider's block reason (e.g., `"SAFETY"`, `"PROHIBITED_CONTENT"`).
+        reason: String,
+        /// Safety categories that triggered the block, with their rated probability.
+        flagged: Vec<String>,
+    },
+
+    /// The user declined the `--confirm` preview; nothing was sent.
+    #[error("Cancelled; no request was sent")]
+    Cancelled,
+
+    /// Some images of a multi-image response were saved and others were not.
+    ///
+    /// Each failed image is reported as a warning before this is returned.
+    #[error("Saved {saved} of {total} images; {} failed", .total - .saved)]
+    PartialFailure {
+        /// Images written to disk.
+        saved: usize,
+        /// Images the provider was asked for and reported on.
+        total: usize,
+    },
+
+    /// A `--transactional` run had failures, so none of its images were kept.
+    #[error("{failed} of {total} images failed; discarded the whole run (--transactional)")]
+    RolledBack {
+        /// Images that could not be generated or saved.
+        failed: usize,
+        /// Images the run asked for.
+        total: usize,
+    },
+
+    /// The run's projected cost is over `--max-cost`; nothing was sent.
+    #[error("Estimated cost ${estimate:.2} exceeds --max-cost ${limit:.2}; nothing was sent")]
+    OverBudget {
+        /// Projected cost in USD.
+        estimate: f64,
+        /// The budget in USD.
+        limit: f64,
+    },
+
+    /// An output file already exists, and neither `--force` nor `--no-clobber=false` was given.
+    #[error(
+        "{} already exists. Pass --force to overwrite it, or --no-clobber=false to save under \
+         a new name",
+        .0.display()
+    )]
+    OutputExists(PathBuf),
+
+    /// `imagen diff` found the images further apart than the allowed threshold.
+    #[error("Images differ: distance {distance:.4} exceeds threshold {threshold}")]
+    ImagesDiffer {
+        /// Measured distance, from 0 (identical) to 1.
+        distance: f64,
+        /// Largest distance accepted.
+        threshold: f64,
+    },
+
     /// No API key configured for the provider.
     #[error("No API key for {provider}. Set {env_var} or add it to config file.")]
     MissingApiKey {
@@ -43,3 +140,128 @@ pub enum ImageError {
         env_var: String,
     },
 }
+
+/// Gemini finish reasons for images withheld on safety grounds.
+const REFUSAL_FINISH_REASONS: &[&str] =
+    &["SAFETY", "IMAGE_SAFETY", "PROHIBITED_CONTENT", "IMAGE_PROHIBITED_CONTENT", "BLOCKLIST"];
+
+/// Text that marks an API error as a content filter's refusal, one or more per provider.
+const REFUSAL_MARKERS: &[&str] = &[
+    "moderation_blocked",
+    "content_policy_violation",
+    "content_moderation",
+    "blocked by our content filters",
+    "NSFW content detected",
+    "withheld by Ideogram's safety check",
+];
+
+/// `". Flagged: a, b"`, or nothing when no categories were flagged.
+fn flagged_suffix(flagged: &[String]) -> String {
+    if flagged.is_empty() {
+        String::new()
+    } else {
+        format!(". Flagged: {}", flagged.join(", "))
+    }
+}
+
+impl ImageError {
+    /// Whether retrying the same request may succeed.
+    ///
+    /// Empty responses, rate limits, transient server errors, and connection
+    /// failures are retryable; everything else is treated as permanent.
+    #[must_use]
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::NoImages { .. } | Self::RateLimited { .. } => true,
+            Self::Api { status, .. } => matches!(status, 429 | 500 | 502 | 503 | 504),
+            Self::Network(e) => e.is_timeout() || e.is_connect(),
+            _ => false,
+        }
+    }
+
+    /// Whether the provider refused the prompt on safety grounds: a blocked prompt, images
+    /// withheld by a safety check, or an API error from a content filter.
+    ///
+    /// Sending the same prompt to the same model again would be refused again.
+    #[must_use]
+    pub fn is_refusal(&self) -> bool {
+        match self {
+            Self::PromptBlocked { .. } => true,
+            Self::NoImages { finish_reason: Some(reason), .. } => {
+                REFUSAL_FINISH_REASONS.contains(&reason.as_str())
+            }
+            Self::Api { message, .. } => {
+                REFUSAL_MARKERS.iter().any(|marker| message.contains(marker))
+            }
+            _ => false,
+        }
+    }
+
+    /// How long the provider asked us to wait before retrying, if it said.
+    #[must_use]
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimited { retry_after, .. } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    /// Process exit status for this error: 2 when some images were saved, 1 otherwise.
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::PartialFailure { .. } => 2,
+            _ => 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_failure_exits_with_two() {
+        let err = ImageError::PartialFailure { saved: 3, total: 4 };
+        assert_eq!(err.to_string(), "Saved 3 of 4 images; 1 failed");
+        assert_eq!(err.exit_code(), 2);
+        assert_eq!(ImageError::Cancelled.exit_code(), 1);
+        let err = ImageError::RolledBack { failed: 1, total: 4 };
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[test]
+    fn no_images_display_includes_finish_reason() {
+        let err = ImageError::NoImages {
+            finish_reason: Some("SAFETY".into()),
+            detail: "Body: {}".into(),
+        };
+        assert_eq!(err.to_string(), "No images in response (finish reason: SAFETY). Body: {}");
+    }
+
+    #[test]
+    fn retryable_classification() {
+        assert!(ImageError::NoImages { finish_reason: None, detail: String::new() }.is_retryable());
+        assert!(ImageError::Api { status: 503, message: String::new() }.is_retryable());
+        assert!(ImageError::Api { status: 429, message: String::new() }.is_retryable());
+        assert!(!ImageError::Api { status: 400, message: String::new() }.is_retryable());
+        assert!(!ImageError::InvalidArgument(String::new()).is_retryable());
+        assert!(
+            !ImageError::PromptBlocked { reason: "SAFETY".into(), flagged: vec![] }.is_retryable()
+        );
+    }
+
+    #[test]
+    fn refusal_classification() {
+        assert!(ImageError::PromptBlocked { reason: "OTHER".into(), flagged: vec![] }.is_refusal());
+        let withheld = |reason: &str| ImageError::NoImages {
+            finish_reason: Some(reason.into()),
+            detail: String::new(),
+        };
+        assert!(withheld("IMAGE_SAFETY").is_refusal());
+        assert!(!withheld("MAX_TOKENS").is_refusal());
+        let api = |message: &str| ImageError::Api { status: 400, message: message.into() };
+        assert!(api(r#"{"error": {"code": "moderation_blocked"}}"#).is_refusal());
+        assert!(!api(r#"{"error": {"code": "invalid_size"}}"#).is_refusal());
+    }
+}
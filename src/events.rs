@@ -0,0 +1,197 @@
+//! `--events ndjson`: one JSON object per line on stdout as a command progresses.
+//!
+//! Orchestration tools read the stream to show live progress: each request as it is
+//! sent, each retry, each image as it lands on disk, and a closing summary of the whole
+//! command. Events are only written once [`enable`] has been called.
+
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::error::ImageError;
+use crate::ports::ImageRequest;
+use crate::run_log::{Outcome, RunSummary};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STARTED: OnceLock<Instant> = OnceLock::new();
+static TOTALS: Mutex<Totals> =
+    Mutex::new(Totals { images_requested: 0, images_saved: 0, cost_usd: Some(0.0) });
+
+/// What the runs of the command added up to, for the closing summary.
+struct Totals {
+    images_requested: usize,
+    images_saved: usize,
+    /// `None` once any run's cost is unknown.
+    cost_usd: Option<f64>,
+}
+
+/// One line of the stream.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// A request was sent to the provider.
+    RequestStarted {
+        /// The request's stable ID.
+        request_id: String,
+        /// The model it was sent to.
+        model: &'a str,
+        /// The prompt as sent.
+        prompt: &'a str,
+        /// The aspect ratio as sent.
+        aspect_ratio: &'a str,
+        /// Images asked for.
+        count: u32,
+    },
+    /// A request failed and will be sent again after `delay_ms`.
+    Retrying {
+        /// The request's stable ID.
+        request_id: String,
+        /// The attempt that failed, from 1.
+        attempt: u32,
+        /// Attempts made before giving up.
+        max_attempts: u32,
+        /// How long until the next attempt.
+        delay_ms: u64,
+        /// Why the last attempt failed.
+        error: String,
+    },
+    /// An image was written to its final path.
+    ImageSaved {
+        /// Absent for `--transactional` runs, whose images are moved into place together.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        /// Where the image was saved.
+        path: &'a Path,
+    },
+    /// The command ended; always the last line.
+    BatchSummary {
+        /// How the command ended.
+        result: Outcome,
+        /// The process exit status.
+        exit_code: i32,
+        /// The error that ended the command, if one did.
+        error: Option<String>,
+        /// Images the runs asked for.
+        images_requested: usize,
+        /// Images written to disk.
+        images_saved: usize,
+        /// Time since the command started.
+        duration_ms: u64,
+        /// Estimated cost of the runs, when every model's price is known.
+        cost_usd: Option<f64>,
+    },
+}
+
+/// A line as written: the event, stamped with when it happened.
+#[derive(Serialize)]
+struct Line<'a> {
+    timestamp: DateTime<Utc>,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Start writing events, timing the command from now.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+    STARTED.get_or_init(Instant::now);
+}
+
+/// Write `event` as one line, if events are enabled.
+///
+/// # Panics
+///
+/// Panics if `event` fails to serialize, which none of its variants can.
+pub fn emit(event: &Event<'_>) {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return;
+    }
+    let line = Line { timestamp: Utc::now(), event };
+    let mut line = serde_json::to_string(&line).expect("events serialize");
+    line.push('\n');
+    // One write per line, so events from concurrent requests don't interleave.
+    let _ = std::io::stdout().lock().write_all(line.as_bytes());
+}
+
+/// Emit [`Event::RequestStarted`] for `request`.
+pub fn request_started(request: &ImageRequest) {
+    emit(&Event::RequestStarted {
+        request_id: request.request_id(),
+        model: &request.model,
+        prompt: &request.prompt,
+        aspect_ratio: &request.aspect_ratio,
+        count: request.count,
+    });
+}
+
+/// Emit [`Event::Retrying`] for `request`, about to be sent again after `delay`.
+pub fn retrying(
+    request: &ImageRequest,
+    attempt: u32,
+    max_attempts: u32,
+    delay: Duration,
+    error: &ImageError,
+) {
+    emit(&Event::Retrying {
+        request_id: request.request_id(),
+        attempt,
+        max_attempts,
+        delay_ms: u64::try_from(delay.as_millis()).unwrap_or(u64::MAX),
+        error: error.to_string(),
+    });
+}
+
+/// Add a finished run to the closing summary.
+pub fn add_run(summary: &RunSummary) {
+    let mut totals = TOTALS.lock().unwrap_or_else(PoisonError::into_inner);
+    totals.images_requested += summary.images_requested;
+    totals.images_saved += summary.images_saved;
+    totals.cost_usd = totals.cost_usd.zip(summary.cost_usd).map(|(total, cost)| total + cost);
+}
+
+/// Emit [`Event::BatchSummary`] for a command that ended with `result`.
+pub fn finish(result: &Result<(), ImageError>) {
+    let totals = TOTALS.lock().unwrap_or_else(PoisonError::into_inner);
+    let elapsed = STARTED.get().map_or(Duration::ZERO, Instant::elapsed);
+    emit(&Event::BatchSummary {
+        result: Outcome::of(result),
+        exit_code: result.as_ref().map_or_else(ImageError::exit_code, |()| 0),
+        error: result.as_ref().err().map(ToString::to_string),
+        images_requested: totals.images_requested,
+        images_saved: totals.images_saved,
+        duration_ms: u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX),
+        cost_usd: totals.cost_usd,
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_tagged_lines() {
+        let event = Event::ImageSaved { request_id: None, path: Path::new("out/cat.png") };
+        let line = Line { timestamp: Utc::now(), event: &event };
+        let json = serde_json::to_value(&line).unwrap();
+        assert_eq!(json["event"], "image_saved");
+        assert_eq!(json["path"], "out/cat.png");
+        assert!(json.get("request_id").is_none() && json.get("timestamp").is_some());
+
+        let event = Event::Retrying {
+            request_id: "abc".into(),
+            attempt: 1,
+            max_attempts: 3,
+            delay_ms: 2000,
+            error: "API error (503): overloaded".into(),
+        };
+        let json = serde_json::to_value(&event).unwrap();
+        assert_eq!(
+            (json["event"].as_str(), json["delay_ms"].as_u64()),
+            (Some("retrying"), Some(2000))
+        );
+    }
+}
@@ -0,0 +1,1373 @@
+//! The generation pipeline behind `imagen`, `imagen edit`, `imagen batch`, and
+//! `imagen compare`.
+//!
+//! A run resolves its prompts, model, and settings from the command line and the
+//! config (`settings`), answers each request from the cache or the provider (live,
+//! recording, or replaying) with fallback models and refusal handling, then saves the
+//! images with their post-processing, metadata, and history (`save`).
+
+mod save;
+mod settings;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use futures_util::stream::{self, StreamExt};
+use futures_util::FutureExt as _;
+use notify::{RecursiveMode, Watcher};
+
+use crate::adapters::live::http::{is_loopback_url, NetworkSettings};
+use crate::cassette::format::Invocation;
+use crate::cassette::recorder::scrub_argv;
+use crate::cli::{BatchArgs, CompareArgs, GenerateArgs};
+use crate::config::{Config, DefaultsConfig};
+use crate::context::{
+    download_client, enhance_model, Mode, Operation, RecordingSession, ServiceContext,
+    MAX_PARALLEL_REQUESTS,
+};
+use crate::error::ImageError;
+use crate::history::{Entry, History};
+use crate::manifest::{file_sha256, BatchState, Job};
+use crate::model::{detect_provider, resolve_model, Provider};
+use crate::output::{
+    check_encoder, claim_output_path, next_version, preview_file_name, thumbnail_path, Existing,
+    Naming, PostProcess, PREVIEW_DIR,
+};
+use crate::params::{
+    format_extension, provider_compresses, provider_format, validate_format, validate_quality,
+    validate_size, warn_unsupported,
+};
+use crate::ports::image_generator::{ImageResponse, PartialImage, PartialSink, Usage};
+use crate::ports::prompt_enhancer::{DEFAULT_INSTRUCTIONS, SOFTEN_INSTRUCTIONS};
+use crate::ports::{EnhanceRequest, ImageGenerator, ImageRequest};
+use crate::pricing::{price_per_image, Estimate};
+use crate::report::RunReport;
+use crate::run_log::{Outcome, RunLog, RunSummary};
+use crate::store::Store;
+use crate::{cache, config, contact_sheet, events, manifest, progress, report};
+
+use save::{save_contact_sheet, save_run, show_saved, ImagePaths, Outputs, Sidecars, Target};
+use settings::{
+    effective_parameters, effective_settings, expand_requests, fallback_models, fallback_request,
+    job_ratios, load_images, naming, post_process, refusal_action, request_provider,
+    sized_settings, validate_jobs, validate_provider_options,
+};
+
+/// How long prompt-file events must settle before a `--watch` run starts, so an editor's
+/// save (often several writes and a rename) triggers one run.
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Run `imagen` or `imagen edit`: generate once, or with `--watch` again each time the
+/// prompt file changes, which blocks a worker of Tokio's multi-threaded runtime.
+///
+/// # Errors
+///
+/// Returns an error if the arguments or config are invalid, or the run fails.
+pub async fn run(cli: &GenerateArgs, operation: Operation) -> Result<(), ImageError> {
+    if cli.watch {
+        watch(cli, operation).await
+    } else {
+        generate(cli, operation, None, None).await
+    }
+}
+
+/// Generate from the prompt file, then again each time it changes (`--watch`).
+///
+/// Runs are numbered from the first version not yet on disk, and a failed run is
+/// reported without ending the session, which lasts until interrupted.
+async fn watch(cli: &GenerateArgs, operation: Operation) -> Result<(), ImageError> {
+    let watch_error = |e: notify::Error| ImageError::Io(std::io::Error::other(e));
+    let Some(file) = cli.prompt_file.as_deref().filter(|file| *file != "-").map(PathBuf::from)
+    else {
+        return Err(ImageError::InvalidArgument(
+            "--watch needs a prompt file (-p/--prompt-file) to watch".to_string(),
+        ));
+    };
+    let (events, changes) = std::sync::mpsc::channel();
+    let mut watcher = notify::recommended_watcher(events).map_err(watch_error)?;
+    // Editors often save by replacing the file, so watch the directory holding it.
+    let dir = file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    watcher.watch(dir, RecursiveMode::NonRecursive).map_err(watch_error)?;
+
+    let mut version = cli.output.as_deref().map_or(1, |output| next_version(Path::new(output)));
+    let mut last_prompt = None;
+    loop {
+        match std::fs::read_to_string(&file) {
+            Ok(prompt) if last_prompt.as_ref() != Some(&prompt) => {
+                tracing::info!("Version {version}:");
+                if let Err(e) = generate(cli, operation, None, Some(version)).await {
+                    tracing::error!("{e}");
+                }
+                version += 1;
+                last_prompt = Some(prompt);
+            }
+            Ok(_) => {}
+            Err(e) => report::warn(format_args!("cannot read {}: {e}", file.display())),
+        }
+        tracing::info!("Watching {} for changes (Ctrl-C to stop)", file.display());
+        tokio::task::block_in_place(|| wait_for_change(&changes, &file));
+    }
+}
+
+/// Block until an event touches `file`, then until events stop for [`WATCH_DEBOUNCE`].
+fn wait_for_change(changes: &Receiver<notify::Result<notify::Event>>, file: &Path) {
+    let name = file.file_name();
+    while let Ok(event) = changes.recv() {
+        match event {
+            Ok(event) if !event.kind.is_access() => {
+                if event.paths.iter().any(|path| path.file_name() == name) {
+                    break;
+                }
+            }
+            Ok(_) => {}
+            Err(e) => report::warn(format_args!("file watching failed: {e}")),
+        }
+    }
+    while changes.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+}
+
+/// Run `imagen batch`: one generation run per model the jobs use, in file order.
+///
+/// # Errors
+///
+/// Returns an error if the batch file or arguments are invalid, or the batch is over
+/// `--max-cost`. A model whose run fails outright is reported and the others still
+/// run; the batch then ends with [`ImageError::PartialFailure`].
+pub async fn run_batch(batch: &BatchArgs) -> Result<(), ImageError> {
+    let args = &batch.args;
+    if args.prompt.is_some() || args.prompt_file.is_some() {
+        return Err(ImageError::InvalidArgument(
+            "imagen batch reads its prompts from the file; drop the extra prompt".into(),
+        ));
+    }
+    if args.output.is_some() {
+        return Err(ImageError::InvalidArgument(
+            "imagen batch takes output paths from the file, or names files after each prompt; \
+             drop --output"
+                .into(),
+        ));
+    }
+    if batch.resume.is_some() && args.transactional {
+        return Err(ImageError::InvalidArgument(
+            "--resume keeps each finished prompt, which --transactional would discard; \
+             use one or the other"
+                .into(),
+        ));
+    }
+    let resume = batch.resume.as_deref().map(Resume::open).transpose()?;
+    let text = std::fs::read_to_string(&batch.file)?;
+    let jobs = manifest::parse(&batch.file, &text)
+        .map_err(|e| ImageError::InvalidArgument(format!("{}: {e}", batch.file)))?;
+
+    let mut groups: Vec<Vec<Job>> = Vec::new();
+    for job in jobs {
+        match groups.iter_mut().find(|group| group[0].model == job.model) {
+            Some(group) => group.push(job),
+            None => groups.push(vec![job]),
+        }
+    }
+    let batch = |jobs| Some(Batch { jobs, resume: resume.as_ref() });
+    if let [jobs] = groups.as_slice() {
+        return generate(args, Operation::Generate, batch(jobs), None).await;
+    }
+    if args.transactional {
+        return Err(ImageError::InvalidArgument(
+            "--transactional needs every job of the batch to use the same model".into(),
+        ));
+    }
+
+    // Prepare every model's run first, so the estimate and budget cover the whole batch.
+    let spent = RefCell::default();
+    let mut runs = groups
+        .iter()
+        .map(|jobs| prepare(args, Operation::Generate, batch(jobs), None))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut estimate = Estimate::default();
+    for run in &runs {
+        estimate += run.estimate();
+    }
+    if args.estimate {
+        print_estimate(args, estimate);
+        return Ok(());
+    }
+    check_budget(args.max_cost, &estimate)?;
+    spent.replace(estimate);
+    for run in &mut runs {
+        run.spent = Some(&spent);
+    }
+
+    let _progress = progress::batch(runs.iter().map(Run::images).sum());
+    let mut outcomes = Vec::new();
+    for run in runs.into_iter().filter(|run| !run.requests.is_empty()) {
+        let (images, label) = (run.images(), format!("jobs for model {}", run.template.model));
+        outcomes.push((label, images, run.send().await));
+    }
+    tally_runs(outcomes)
+}
+
+/// Run `imagen compare`: the prompt on every model of `--models` at once, each model's
+/// images named after it, and with `--composite` one grid of them all.
+///
+/// # Errors
+///
+/// Returns an error if the arguments are invalid or the comparison is over
+/// `--max-cost`. A model whose run fails is reported and the others still run; the
+/// comparison then ends with [`ImageError::PartialFailure`].
+pub async fn run_compare(compare: &CompareArgs) -> Result<(), ImageError> {
+    let args = &compare.args;
+    if compare.models.len() < 2 {
+        return Err(ImageError::InvalidArgument(
+            "imagen compare needs at least two --models".into(),
+        ));
+    }
+    let models = &compare.models;
+    let repeated = models.iter().enumerate().find(|&(i, model)| models[..i].contains(model));
+    if let Some((_, model)) = repeated {
+        return Err(ImageError::InvalidArgument(format!(
+            "--models lists {model} twice; its images would share file names"
+        )));
+    }
+    let refusal_model = args.on_refusal.as_deref().is_some_and(|action| action != "soften");
+    if args.watch || args.transactional || !args.fallback.is_empty() || refusal_model {
+        return Err(ImageError::InvalidArgument(
+            "imagen compare runs each model on its own; drop --watch, --transactional, \
+             --fallback, and --on-refusal <MODEL>"
+                .into(),
+        ));
+    }
+    // Read the prompt once, whichever way it is given, for all the models.
+    let config_path = config::discover_config_path(args.config.as_deref());
+    let config = Config::load(&config_path, args.profile.as_deref()).map_err(ImageError::Config)?;
+    let prompt = args.resolve_prompt(&config.prompts).map_err(ImageError::Io)?;
+    let jobs: Vec<Job> = compare
+        .models
+        .iter()
+        .map(|model| Job { model: Some(model.clone()), ..Job::prompt(prompt.clone()) })
+        .collect();
+
+    let saved: Vec<RefCell<Vec<PathBuf>>> = jobs.iter().map(|_| RefCell::default()).collect();
+    let spent = RefCell::default();
+    let mut runs = Vec::new();
+    for job in &jobs {
+        let batch = Batch { jobs: std::slice::from_ref(job), resume: None };
+        runs.push(prepare(args, Operation::Generate, Some(batch), None)?);
+    }
+    // Named before the models are added to the runs' file names.
+    let composite = compare.composite.then(|| runs[0].sheet_path("compare"));
+    let existing = runs[0].naming.existing;
+    for ((run, model), saved) in runs.iter_mut().zip(&compare.models).zip(&saved) {
+        run.label = Some(model.clone());
+        run.saved_to = Some(saved);
+        // Each model's images stand for that model, so the [defaults] fallback and refusal
+        // models are not used.
+        run.fallbacks.clear();
+        run.on_refusal =
+            run.on_refusal.take().filter(|action| matches!(action, OnRefusal::Soften(_)));
+    }
+    let mut estimate = Estimate::default();
+    for run in &runs {
+        estimate += run.estimate();
+    }
+    if args.estimate {
+        print_estimate(args, estimate);
+        return Ok(());
+    }
+    check_budget(args.max_cost, &estimate)?;
+    spent.replace(estimate);
+    for run in &mut runs {
+        run.spent = Some(&spent);
+    }
+
+    let _progress = progress::batch(runs.iter().map(Run::images).sum());
+    let images: Vec<usize> = runs.iter().map(Run::images).collect();
+    let results = futures_util::future::join_all(runs.into_iter().map(Run::send)).await;
+    let outcomes = compare.models.iter().cloned().zip(images).zip(results);
+    let result =
+        tally_runs(outcomes.map(|((model, images), result)| (model, images, result)).collect());
+
+    if let Some(path) = composite {
+        let mut files = Vec::new();
+        for (model, saved) in compare.models.iter().zip(saved) {
+            let saved = saved.into_inner();
+            let several = saved.len() > 1;
+            files.extend(saved.into_iter().zip(1..).map(|(file, n)| {
+                (if several { format!("{model} #{n}") } else { model.clone() }, file)
+            }));
+        }
+        save_contact_sheet(&files, &path, existing);
+    }
+    result
+}
+
+/// Add up the outcomes of a command's runs, each with a label for its warning and the
+/// number of images it asked for. A failed run is reported and counts as none saved.
+fn tally_runs(outcomes: Vec<(String, usize, Result<(), ImageError>)>) -> Result<(), ImageError> {
+    let (mut saved, mut total) = (0, 0);
+    let mut first_error = None;
+    for (label, images, result) in outcomes {
+        match result {
+            Ok(()) => (saved, total) = (saved + images, total + images),
+            Err(ImageError::PartialFailure { saved: s, total: t }) => {
+                (saved, total) = (saved + s, total + t);
+            }
+            Err(e) => {
+                report::warn(format_args!("{label} failed: {e}"));
+                total += images;
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match first_error {
+        Some(e) if saved == 0 => Err(e),
+        _ if saved < total => Err(ImageError::PartialFailure { saved, total }),
+        _ => Ok(()),
+    }
+}
+
+/// Build the requests from the command line, send them, and save the resulting images.
+///
+/// `batch` holds the jobs of `imagen batch`, which all use the same model; otherwise the
+/// single prompt comes from the command line. `version` numbers a `--watch` run.
+async fn generate(
+    cli: &GenerateArgs,
+    operation: Operation,
+    batch: Option<Batch<'_>>,
+    version: Option<u32>,
+) -> Result<(), ImageError> {
+    let batch_mode = batch.is_some();
+    let spent = RefCell::default();
+    let mut run = prepare(cli, operation, batch, version)?;
+    // With `--resume`, every request may have finished already.
+    if run.requests.is_empty() {
+        return Ok(());
+    }
+    let estimate = run.estimate();
+    if cli.estimate {
+        print_estimate(cli, estimate);
+        return Ok(());
+    }
+    check_budget(cli.max_cost, &estimate)?;
+    spent.replace(estimate);
+    run.spent = Some(&spent);
+    let _progress = batch_mode.then(|| progress::batch(run.images()));
+    run.send().await
+}
+
+/// Print the `--estimate` of a run, or keep it for the `--json` report.
+fn print_estimate(cli: &GenerateArgs, estimate: Estimate) {
+    if cli.json {
+        report::record_estimate(estimate);
+    } else {
+        println!("Estimated cost: {estimate}");
+    }
+}
+
+/// A validated run, ready to send: its requests and where their images go.
+struct Run<'a> {
+    cli: &'a GenerateArgs,
+    operation: Operation,
+    config: Config,
+    provider: Provider,
+    /// The settings every request starts from, as `--confirm` shows them.
+    template: ImageRequest,
+    jobs: Vec<Job>,
+    requests: Vec<ImageRequest>,
+    targets: Vec<Target>,
+    request_ids: Vec<String>,
+    /// With `--cache`, the images saved earlier for each request, when there are enough.
+    cached: Vec<Option<ImageResponse>>,
+    /// With `--enhance`, the text model that rewrites the prompts.
+    enhance_model: Option<String>,
+    format: String,
+    naming: Naming,
+    /// The local adjustments and watermark applied to every saved image.
+    post: PostProcess,
+    version: Option<u32>,
+    resume: Option<&'a Resume>,
+    /// With `imagen compare`, the model as given, added to every file name.
+    label: Option<String>,
+    /// With `imagen compare --composite`, receives every file the run saved.
+    saved_to: Option<&'a RefCell<Vec<PathBuf>>>,
+    /// The projected cost of what this run and the others sharing its `--max-cost` have
+    /// sent, which requests sent again must also fit under.
+    spent: Option<&'a RefCell<Estimate>>,
+    /// With `--fallback`, the models that take over requests the provider fails.
+    fallbacks: Vec<String>,
+    /// With `--on-refusal`, what to do with prompts the provider refuses.
+    on_refusal: Option<OnRefusal>,
+}
+
+/// What `--on-refusal` does with a prompt the provider refused on safety grounds.
+#[derive(Clone)]
+enum OnRefusal {
+    /// Have this text model tone the prompt down, then send it again.
+    Soften(String),
+    /// Send the prompt to this model (a resolved name) instead.
+    Model(String),
+}
+
+/// Load the config, resolve the prompts and model, validate every setting, and build
+/// the run's requests, all without contacting the provider.
+fn prepare<'a>(
+    cli: &'a GenerateArgs,
+    operation: Operation,
+    batch: Option<Batch<'a>>,
+    version: Option<u32>,
+) -> Result<Run<'a>, ImageError> {
+    let config = load_config(cli)?;
+    let jobs = resolve_jobs(cli, batch, &config)?;
+
+    // Resolve model and provider, then the defaults for that model
+    let effective_model =
+        jobs[0].model.clone().unwrap_or_else(|| effective_settings(cli, &config.defaults).model);
+    let resolved_model = resolve_model(&effective_model);
+    let provider = detect_provider(&resolved_model).map_err(ImageError::InvalidArgument)?;
+    let DefaultsConfig {
+        aspect_ratio: effective_aspect_ratio,
+        size: effective_size,
+        quality: effective_quality,
+        format: effective_format,
+        fallback,
+        on_refusal,
+        ..
+    } = sized_settings(cli, provider, &resolved_model, &config.defaults.for_model(&resolved_model));
+
+    let enhance_model =
+        cli.enhance.then(|| enhance_model(cli.enhance_model.as_deref(), provider, &config));
+
+    tracing::debug!("Model: {resolved_model} (resolved from '{effective_model}')");
+    tracing::debug!("Provider: {provider:?}");
+
+    // Validate parameters
+    let ratios = job_ratios(&jobs, &effective_aspect_ratio, provider, cli.strict_ratio)
+        .map_err(ImageError::InvalidArgument)?;
+    validate_jobs(cli, &jobs, provider).map_err(ImageError::InvalidArgument)?;
+    validate_size(&effective_size).map_err(ImageError::InvalidArgument)?;
+    validate_quality(&effective_quality).map_err(ImageError::InvalidArgument)?;
+    warn_unsupported(&effective_size, &effective_quality, provider, &resolved_model);
+    validate_format(&effective_format).map_err(ImageError::InvalidArgument)?;
+    check_encoder(&effective_format).map_err(ImageError::ImageConversion)?;
+    validate_provider_options(cli, &effective_format, provider)
+        .map_err(ImageError::InvalidArgument)?;
+    let fallbacks =
+        fallback_models(&fallback, &resolved_model).map_err(ImageError::InvalidArgument)?;
+    let on_refusal = refusal_action(cli, on_refusal.as_deref(), provider, &config)
+        .map_err(ImageError::InvalidArgument)?;
+    let naming = naming(cli, &config).map_err(ImageError::InvalidArgument)?;
+    let post = post_process(cli, &effective_format, provider)?;
+    let (input_images, reference_images) = load_images(cli, operation, provider)?;
+
+    // Build request
+    let template = ImageRequest {
+        model: resolved_model,
+        prompt: jobs[0].prompt.clone(),
+        aspect_ratio: effective_aspect_ratio.clone(),
+        size: effective_size.clone(),
+        quality: effective_quality.clone(),
+        format: provider_format(&effective_format).to_string(),
+        count: cli.count,
+        seed: cli.seed,
+        thinking: cli.thinking.clone(),
+        input_images,
+        background: cli.background.clone(),
+        magic_prompt: cli.magic_prompt.clone(),
+        style_type: cli.style_type.clone(),
+        moderation: cli.moderation.clone(),
+        output_compression: cli
+            .compression
+            .filter(|_| provider_compresses(&effective_format, provider)),
+        safety: cli.safety.clone(),
+        reference_images,
+        return_urls: cli.stream_download,
+        history: vec![],
+    };
+    let resume = batch.and_then(|batch| batch.resume);
+    let (requests, targets) =
+        expand_requests(cli, &template, &jobs, &ratios, naming.dir.as_deref(), resume);
+    let request_ids = announce_request_ids(cli, &requests);
+    let cached = if cli.cache {
+        let parameters = effective_parameters(
+            cli,
+            &template,
+            &request_ids,
+            operation,
+            &effective_format,
+            enhance_model.as_deref(),
+        );
+        cache::lookup(&requests, &request_ids, &parameters)
+    } else {
+        requests.iter().map(|_| None).collect()
+    };
+
+    Ok(Run {
+        cli,
+        operation,
+        config,
+        provider,
+        template,
+        jobs,
+        requests,
+        targets,
+        request_ids,
+        cached,
+        enhance_model,
+        format: effective_format,
+        naming,
+        post,
+        version,
+        resume,
+        label: None,
+        saved_to: None,
+        spent: None,
+        fallbacks,
+        on_refusal,
+    })
+}
+
+/// Load the config file in effect, with `--api-base` and `--timeout` applied over it.
+fn load_config(cli: &GenerateArgs) -> Result<Config, ImageError> {
+    let config_path = config::discover_config_path(cli.config.as_deref());
+    let mut config =
+        Config::load(&config_path, cli.profile.as_deref()).map_err(ImageError::Config)?;
+    if let Some(ref api_base) = cli.api_base {
+        config.keys.openai_base_url = Some(api_base.clone());
+    }
+    if let Some(timeout) = cli.timeout {
+        config.http.request_timeout = Some(timeout);
+    }
+    Ok(config)
+}
+
+impl Run<'_> {
+    /// Projected cost of the run's requests, leaving out those answered from the cache.
+    fn estimate(&self) -> Estimate {
+        let mut estimate = Estimate::default();
+        for (request, hit) in self.requests.iter().zip(&self.cached) {
+            if hit.is_none() {
+                estimate += Estimate::of(std::slice::from_ref(request), &self.config.pricing);
+            }
+        }
+        estimate
+    }
+
+    /// How many images the run asks for.
+    fn images(&self) -> usize {
+        self.requests.iter().map(|request| request.count as usize).sum()
+    }
+
+    /// Fail before anything is sent if a file the run would write is already there and
+    /// may not be replaced or renamed around.
+    fn check_outputs(&self) -> Result<(), ImageError> {
+        if self.naming.existing != Existing::Refuse {
+            return Ok(());
+        }
+        let outputs = self.outputs();
+        for (request, target) in self.requests.iter().zip(&self.targets) {
+            let paths = ImagePaths::new(&outputs, request, target);
+            let count = request.count as usize;
+            for path in (0..count).map(|index| paths.image(index, count)) {
+                claim_output_path(&path, Existing::Refuse)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the run's files go, before any are saved.
+    fn outputs(&self) -> Outputs<'_> {
+        Outputs {
+            version: self.version,
+            label: self.label.as_deref(),
+            ..Outputs::new(&self.format, &self.naming, &self.post, &self.targets)
+        }
+    }
+
+    /// Path for a grid of the run's images, named after its first request's images with
+    /// `-<kind>`: `cat-sheet.jpg`.
+    fn sheet_path(&self, kind: &str) -> PathBuf {
+        ImagePaths::new(&self.outputs(), &self.requests[0], &self.targets[0]).sheet(kind)
+    }
+
+    /// Send the requests and save the resulting images.
+    async fn send(mut self) -> Result<(), ImageError> {
+        let (cli, provider) = (self.cli, self.provider);
+        self.check_outputs()?;
+        let estimate = self.estimate();
+        // An enhanced prompt is confirmed once the text model has written it.
+        if cli.confirm
+            && self.enhance_model.is_none()
+            && !confirm_request(&self.template, &self.jobs, provider, &estimate)?
+        {
+            return Err(ImageError::Cancelled);
+        }
+
+        let parameters = effective_parameters(
+            cli,
+            &self.template,
+            &self.request_ids,
+            self.operation,
+            &self.format,
+            self.enhance_model.as_deref(),
+        );
+        let reused: Vec<bool> = self.cached.iter().map(Option::is_some).collect();
+        let (started, started_at) = (Instant::now(), chrono::Utc::now());
+        let results = self.fetch(&parameters, &estimate).await?;
+
+        let usage = report_usage(&self.requests, &results);
+        let downloads = (self.requests.iter().any(|request| request.return_urls))
+            .then(|| download_client(provider, &self.config));
+        let saved = RefCell::new(Vec::new());
+        let sidecars = (cli.sidecar || self.config.output.sidecar) && !cli.strip;
+        let outputs = Outputs {
+            resume: self.resume,
+            saved: Some(&saved),
+            sidecars: sidecars.then_some(Sidecars {
+                provider,
+                pricing: &self.config.pricing,
+                started_at,
+            }),
+            reused: &reused,
+            ..self.outputs()
+        };
+        let sheet = cli.contact_sheet.then(|| self.sheet_path("sheet"));
+        let result = save_run(cli, &self.requests, results, outputs, downloads.as_ref()).await;
+        // A failed transactional run leaves nothing on disk to remember.
+        let saved =
+            if cli.transactional && result.is_err() { Vec::new() } else { saved.into_inner() };
+        let files: Vec<PathBuf> = saved.iter().map(|(_, file)| file.clone()).collect();
+        let contact_sheet = sheet.and_then(|path| {
+            save_contact_sheet(&contact_sheet::by_name(&files), &path, self.naming.existing)
+        });
+        if let Some(saved_to) = self.saved_to {
+            saved_to.borrow_mut().extend(files.iter().cloned());
+        }
+        show_saved(cli, &files, contact_sheet.as_ref());
+        self.record_history(&parameters, saved, &reused);
+        let summary = self.summarize(parameters, started, usage, &estimate, &result);
+        events::add_run(&summary);
+        if let Some(log) = RunLog::from_config(&self.config) {
+            if let Err(e) = log.append(&summary) {
+                report::warn(format_args!("failed to write the run log: {e}"));
+            }
+        }
+        if cli.json {
+            let request_ids = self.request_ids.clone();
+            let thumbnails = match cli.thumbnail {
+                Some(_) => files.iter().map(|file| thumbnail_path(file)).collect(),
+                None => Vec::new(),
+            };
+            report::record_run(RunReport {
+                summary,
+                request_ids,
+                files,
+                thumbnails,
+                contact_sheet,
+            });
+        }
+        result
+    }
+
+    /// Answer every request, from the cache where it can and otherwise from the provider
+    /// (live, recording, or replaying), returning results in request order.
+    async fn fetch(
+        &mut self,
+        parameters: &BTreeMap<String, String>,
+        estimate: &Estimate,
+    ) -> Result<Vec<Result<ImageResponse, ImageError>>, ImageError> {
+        let (cli, provider, operation) = (self.cli, self.provider, self.operation);
+        let cached = std::mem::take(&mut self.cached);
+        let mut fresh = Vec::new().into_iter();
+        if cached.iter().any(Option::is_none) {
+            // Create context based on mode (live / recording / replaying)
+            let mode = Mode::select(cli.mode.as_deref(), &self.template.prompt)?;
+            let text_model = match &self.on_refusal {
+                Some(OnRefusal::Soften(model)) => Some(model.as_str()),
+                _ => None,
+            };
+            let (ctx, recording_session) = create_context(
+                cli,
+                &mode,
+                provider,
+                operation,
+                &self.config,
+                &self.template.prompt,
+                self.enhance_model.as_deref().or(text_model),
+            )?;
+            if let Some(ref session) = recording_session {
+                // Cassettes must hold the image bytes; recorded URLs would expire before replay.
+                for request in &mut self.requests {
+                    request.return_urls = false;
+                }
+                session.set_invocation(Invocation {
+                    argv: scrub_argv(std::env::args(), &self.config.secrets()),
+                    parameters: parameters.clone(),
+                });
+            }
+            if let Err(e) = self.enhance(&ctx, &cached, estimate).await {
+                drop(ctx);
+                if let Some(session) = recording_session {
+                    finish_recording(session);
+                }
+                return Err(e);
+            }
+            let sent: Vec<usize> = (0..cached.len()).filter(|&i| cached[i].is_none()).collect();
+            let misses: Vec<ImageRequest> =
+                sent.iter().map(|&i| self.requests[i].clone()).collect();
+
+            // Generate
+            let recording = recording_session.is_some();
+            let mut results = run_requests(&ctx, cli, &misses, recording).await;
+            self.fall_back(&mode, &ctx, &sent, &mut results).await;
+            self.handle_refusals(&mode, &ctx, &sent, &mut results).await;
+            fresh = results.into_iter();
+
+            // Drop the context to release the Arc reference before finishing the recording
+            drop(ctx);
+
+            // Always finish recording, even if generation failed
+            if let Some(session) = recording_session {
+                finish_recording(session);
+            }
+        }
+        Ok(cached
+            .into_iter()
+            .map(|hit| hit.map_or_else(|| fresh.next().expect("one result per request"), Ok))
+            .collect())
+    }
+
+    /// With `--fallback`, send each of the `sent` requests that failed with a transient
+    /// error to the fallback models in turn; see [`Run::send_to_models`].
+    async fn fall_back(
+        &mut self,
+        mode: &Mode,
+        ctx: &ServiceContext,
+        sent: &[usize],
+        results: &mut [Result<ImageResponse, ImageError>],
+    ) {
+        let models = self.fallbacks.clone();
+        let retryable = ImageError::is_retryable;
+        self.send_to_models(mode, ctx, sent, results, &models, retryable).await;
+    }
+
+    /// With `--on-refusal`, send each of the `sent` requests the provider refused once
+    /// more: softened by the text model, or to the other model.
+    async fn handle_refusals(
+        &mut self,
+        mode: &Mode,
+        ctx: &ServiceContext,
+        sent: &[usize],
+        results: &mut [Result<ImageResponse, ImageError>],
+    ) {
+        let refusal = ImageError::is_refusal;
+        match self.on_refusal.clone() {
+            Some(OnRefusal::Soften(model)) => self.soften(ctx, &model, sent, results).await,
+            Some(OnRefusal::Model(model)) => {
+                self.send_to_models(mode, ctx, sent, results, &[model], refusal).await;
+            }
+            None => {}
+        }
+    }
+
+    /// Send each of the `sent` requests whose error is `wanted` to `models` in turn, as
+    /// [`fallback_request`] translates it, until one answers.
+    ///
+    /// Under replay, the other models' answers are read from the same cassette, after the
+    /// model's own. They are not tried while recording, as a cassette holds one provider's
+    /// answers.
+    async fn send_to_models(
+        &mut self,
+        mode: &Mode,
+        ctx: &ServiceContext,
+        sent: &[usize],
+        results: &mut [Result<ImageResponse, ImageError>],
+        models: &[String],
+        wanted: fn(&ImageError) -> bool,
+    ) {
+        let mut pending: Vec<usize> =
+            (0..results.len()).filter(|&i| results[i].as_ref().is_err_and(wanted)).collect();
+        if pending.is_empty() || models.is_empty() {
+            return;
+        }
+        if matches!(mode, Mode::Record(_)) {
+            report::warn("other models are not tried while recording a cassette");
+            return;
+        }
+        for model in models {
+            let (cli, operation, defaults) = (self.cli, self.operation, &self.config.defaults);
+            let mut requests = Vec::new();
+            for &i in &pending {
+                let request = &self.requests[sent[i]];
+                match fallback_request(cli, request, operation, defaults, model, &self.format) {
+                    Ok(request) => requests.push((i, request)),
+                    Err(e) => report::warn(format_args!("cannot fall back to {model}: {e}")),
+                }
+            }
+            let Some(&(first, _)) = requests.first() else { continue };
+            if let Err(e) = self.spend(&requests) {
+                report::warn(format_args!("{e}; not falling back to {model}"));
+                continue;
+            }
+            let failure = results[first].as_ref().err().map(ToString::to_string);
+            let failure = failure.unwrap_or_default();
+            match requests.len() {
+                1 => report::warn(format_args!("{failure}; falling back to {model}")),
+                n => report::warn(format_args!(
+                    "{n} requests failed ({failure}); falling back to {model}"
+                )),
+            }
+            // A replayed cassette answers every model in turn.
+            let context = if matches!(mode, Mode::Replay(_)) {
+                None
+            } else {
+                let provider = detect_provider(model).expect("models are checked up front");
+                let (config, prompt) = (&self.config, &self.template.prompt);
+                match create_context(cli, mode, provider, operation, config, prompt, None) {
+                    Ok((context, _)) => Some(context),
+                    Err(e) => {
+                        report::warn(format_args!("cannot fall back to {model}: {e}"));
+                        continue;
+                    }
+                }
+            };
+            let generator = &*context.as_ref().unwrap_or(ctx).generator;
+            self.resend(generator, sent, results, requests).await;
+            pending.retain(|&i| results[i].is_err());
+            if pending.is_empty() {
+                return;
+            }
+        }
+    }
+
+    /// With `--on-refusal soften`, have the text `model` rewrite the prompt of each of the
+    /// `sent` requests the provider refused, once per distinct prompt, and send them again.
+    async fn soften(
+        &mut self,
+        ctx: &ServiceContext,
+        model: &str,
+        sent: &[usize],
+        results: &mut [Result<ImageResponse, ImageError>],
+    ) {
+        let Some(enhancer) = &ctx.enhancer else { return };
+        let mut softened: HashMap<String, Option<String>> = HashMap::new();
+        let mut requests = Vec::new();
+        for i in 0..results.len() {
+            let Err(refusal) = results[i].as_ref() else { continue };
+            if !refusal.is_refusal() {
+                continue;
+            }
+            let mut request = self.requests[sent[i]].clone();
+            if !softened.contains_key(&request.prompt) {
+                let rewrite = enhancer
+                    .enhance(&EnhanceRequest {
+                        model: model.to_string(),
+                        instructions: SOFTEN_INSTRUCTIONS.to_string(),
+                        prompt: request.prompt.clone(),
+                    })
+                    .await;
+                let prompt = match rewrite {
+                    Ok(prompt) => {
+                        report::warn(format_args!(
+                            "{refusal}; retrying with the prompt softened by {model}: {prompt}"
+                        ));
+                        Some(prompt)
+                    }
+                    Err(e) => {
+                        report::warn(format_args!("could not soften the prompt: {e}"));
+                        None
+                    }
+                };
+                softened.insert(request.prompt.clone(), prompt);
+            }
+            if let Some(prompt) = &softened[&request.prompt] {
+                request.prompt.clone_from(prompt);
+                requests.push((i, request));
+            }
+        }
+        if let Err(e) = self.spend(&requests) {
+            report::warn(format_args!("{e}; not sending the softened prompts"));
+            return;
+        }
+        self.resend(&*ctx.generator, sent, results, requests).await;
+    }
+
+    /// Count `requests` against `--max-cost` before they are sent again, failing (and
+    /// counting nothing) if they would take the projected cost over it.
+    fn spend(&self, requests: &[(usize, ImageRequest)]) -> Result<(), String> {
+        let Some(spent) = self.spent else { return Ok(()) };
+        let mut projected = spent.borrow().clone();
+        for (_, request) in requests {
+            projected += Estimate::of(std::slice::from_ref(request), &self.config.pricing);
+        }
+        check_budget(self.cli.max_cost, &projected).map_err(|e| match e {
+            ImageError::OverBudget { estimate, limit } => {
+                format!("projected cost ${estimate:.2} would exceed --max-cost ${limit:.2}")
+            }
+            e => e.to_string(),
+        })?;
+        spent.replace(projected);
+        Ok(())
+    }
+
+    /// Send `requests`, each standing in for the failed one at its index into `results`,
+    /// and keep their answers. A request that is answered replaces the one it stands for.
+    async fn resend(
+        &mut self,
+        generator: &dyn ImageGenerator,
+        sent: &[usize],
+        results: &mut [Result<ImageResponse, ImageError>],
+        requests: Vec<(usize, ImageRequest)>,
+    ) {
+        let answers: Vec<_> = stream::iter(&requests)
+            .map(|(_, request)| {
+                events::request_started(request);
+                generator.generate(request)
+            })
+            .buffered(MAX_PARALLEL_REQUESTS)
+            .collect()
+            .await;
+        for ((i, request), answer) in requests.into_iter().zip(answers) {
+            if answer.is_ok() {
+                self.request_ids[sent[i]] = request.request_id();
+                self.requests[sent[i]] = request;
+            }
+            results[i] = answer;
+        }
+    }
+
+    /// With `--enhance`, rewrite the prompt of every request still to be sent, asking the
+    /// text model once per distinct prompt, then show the result for `--confirm`.
+    async fn enhance(
+        &mut self,
+        ctx: &ServiceContext,
+        cached: &[Option<ImageResponse>],
+        estimate: &Estimate,
+    ) -> Result<(), ImageError> {
+        let (Some(model), Some(enhancer)) = (&self.enhance_model, &ctx.enhancer) else {
+            return Ok(());
+        };
+        let instructions =
+            self.config.enhance.instructions.as_deref().unwrap_or(DEFAULT_INSTRUCTIONS);
+        tracing::debug!("Enhancing prompts with {model}");
+        let mut rewritten: HashMap<String, String> = HashMap::new();
+        for (request, hit) in self.requests.iter_mut().zip(cached) {
+            if hit.is_some() {
+                continue;
+            }
+            if !rewritten.contains_key(&request.prompt) {
+                let prompt = enhancer
+                    .enhance(&EnhanceRequest {
+                        model: model.clone(),
+                        instructions: instructions.to_string(),
+                        prompt: request.prompt.clone(),
+                    })
+                    .await?;
+                tracing::info!("Enhanced prompt: {prompt}");
+                rewritten.insert(request.prompt.clone(), prompt);
+            }
+            request.prompt.clone_from(&rewritten[&request.prompt]);
+        }
+        for prompt in self.jobs.iter_mut().map(|job| &mut job.prompt) {
+            if let Some(text) = rewritten.get(prompt.as_str()) {
+                prompt.clone_from(text);
+            }
+        }
+        if let Some(text) = rewritten.get(&self.template.prompt) {
+            self.template.prompt.clone_from(text);
+        }
+        if self.cli.confirm
+            && !confirm_request(&self.template, &self.jobs, self.provider, estimate)?
+        {
+            return Err(ImageError::Cancelled);
+        }
+        Ok(())
+    }
+
+    /// Add each saved file, with the index of its request, to the generation history,
+    /// unless `[history] enabled = false`. Images `reused` from the cache cost nothing.
+    fn record_history(
+        &self,
+        parameters: &BTreeMap<String, String>,
+        saved: Vec<(usize, PathBuf)>,
+        reused: &[bool],
+    ) {
+        if saved.is_empty() || self.config.history.enabled == Some(false) {
+            return;
+        }
+        let Some(path) = Store::default_path() else { return };
+        let created_at = chrono::Utc::now();
+        let mut entries = Vec::with_capacity(saved.len());
+        for (index, file) in saved {
+            let request = &self.requests[index];
+            let sha256 = match file_sha256(&file) {
+                Ok(sha256) => sha256,
+                Err(e) => {
+                    report::warn(format_args!("not recording {} in history: {e}", file.display()));
+                    continue;
+                }
+            };
+            let mut parameters = parameters.clone();
+            parameters.insert("model".into(), request.model.clone());
+            parameters.insert("aspect_ratio".into(), request.aspect_ratio.clone());
+            parameters.insert("count".into(), request.count.to_string());
+            parameters.insert("request_id".into(), self.request_ids[index].clone());
+            if let Some(seed) = request.seed {
+                parameters.insert("seed".into(), seed.to_string());
+            }
+            entries.push(Entry {
+                created_at,
+                request_id: self.request_ids[index].clone(),
+                operation: format!("{:?}", self.operation).to_lowercase(),
+                provider: format!("{:?}", request_provider(request, self.provider)).to_lowercase(),
+                model: request.model.clone(),
+                prompt: request.prompt.clone(),
+                parameters,
+                output_path: std::path::absolute(&file).unwrap_or(file),
+                sha256,
+                cost_usd: if reused[index] {
+                    Some(0.0)
+                } else {
+                    price_per_image(request, &self.config.pricing)
+                },
+            });
+        }
+        if let Err(e) = History::open(&path).and_then(|mut history| history.record(&entries)) {
+            report::warn(format_args!("failed to record the run in history: {e}"));
+        }
+    }
+
+    /// The run's summary, for the run log and the `--json` report.
+    fn summarize(
+        &self,
+        parameters: BTreeMap<String, String>,
+        started: Instant,
+        usage: Option<Usage>,
+        estimate: &Estimate,
+        result: &Result<(), ImageError>,
+    ) -> RunSummary {
+        let mut summary = RunSummary {
+            timestamp: chrono::Utc::now(),
+            version: env!("CARGO_PKG_VERSION"),
+            operation: format!("{:?}", self.operation).to_lowercase(),
+            provider: format!("{:?}", self.provider).to_lowercase(),
+            model: self.template.model.clone(),
+            parameters,
+            duration_ms: u64::try_from(started.elapsed().as_millis()).unwrap_or(u64::MAX),
+            result: Outcome::Success,
+            images_requested: self.requests.iter().map(|request| request.count as usize).sum(),
+            images_saved: 0,
+            error: None,
+            exit_code: 0,
+            cost_usd: estimate.known_usd(),
+            usage,
+        };
+        summary.set_result(result);
+        summary
+    }
+}
+
+/// Refuse a run whose projected cost is over `--max-cost`, or cannot be worked out.
+fn check_budget(limit: Option<f64>, estimate: &Estimate) -> Result<(), ImageError> {
+    let Some(limit) = limit else { return Ok(()) };
+    match estimate.known_usd() {
+        Some(usd) if usd > limit => Err(ImageError::OverBudget { estimate: usd, limit }),
+        Some(_) => Ok(()),
+        None => Err(ImageError::InvalidArgument(format!(
+            "cannot check --max-cost against {estimate}; add the price under [pricing] in the config"
+        ))),
+    }
+}
+
+/// Total the token usage the provider reported for a run, printing each response's
+/// with `--verbose`. `None` when no response reported any.
+fn report_usage(
+    requests: &[ImageRequest],
+    results: &[Result<ImageResponse, ImageError>],
+) -> Option<Usage> {
+    let mut total: Option<Usage> = None;
+    for (request, result) in requests.iter().zip(results) {
+        let Ok(ImageResponse { usage: Some(usage), .. }) = result else { continue };
+        if let [_] = requests {
+            tracing::debug!("Usage: {usage}");
+        } else {
+            tracing::debug!("Usage ({}): {usage}", request.request_id());
+        }
+        *total.get_or_insert_default() += *usage;
+    }
+    total
+}
+
+/// Compute each request's ID, printing it for `--id` and `--verbose`.
+fn announce_request_ids(cli: &GenerateArgs, requests: &[ImageRequest]) -> Vec<String> {
+    let request_ids: Vec<String> = requests.iter().map(ImageRequest::request_id).collect();
+    for request_id in &request_ids {
+        if cli.id {
+            println!("{request_id}");
+        }
+        tracing::debug!("Request ID: {request_id}");
+    }
+    request_ids
+}
+
+/// Send every request of a run concurrently, at most [`MAX_PARALLEL_REQUESTS`] at a
+/// time, returning results in request order.
+///
+/// While recording they go one at a time, so the cassette lists its interactions in
+/// the order replay will ask for them. With `--stream`, the single request's previews
+/// are written as they arrive.
+async fn run_requests(
+    ctx: &ServiceContext,
+    cli: &GenerateArgs,
+    requests: &[ImageRequest],
+    sequential: bool,
+) -> Vec<Result<ImageResponse, ImageError>> {
+    let waiting = progress::waiting(&requests[0].model, requests.len());
+    if let ([request], true) = (requests, cli.stream) {
+        let dir = Path::new(cli.output.as_deref().unwrap_or_default()).with_file_name(PREVIEW_DIR);
+        let (sink, writer) = spawn_preview_writer(dir, request.request_id());
+        events::request_started(request);
+        let result = ctx.generator.generate_streaming(request, sink).await;
+        let _ = writer.join();
+        return vec![result];
+    }
+    if !sequential {
+        return stream::iter(requests)
+            .map(|request| {
+                events::request_started(request);
+                ctx.generator.generate(request).inspect(|_| waiting.inc(1))
+            })
+            .buffered(MAX_PARALLEL_REQUESTS)
+            .collect()
+            .await;
+    }
+    let mut results = Vec::with_capacity(requests.len());
+    for request in requests {
+        events::request_started(request);
+        results.push(ctx.generator.generate(request).await);
+        waiting.inc(1);
+    }
+    results
+}
+
+/// Write each streamed preview to `dir` as `<request_id>-<frame>.<ext>` as it arrives.
+///
+/// The thread ends once every sender of the returned sink has been dropped.
+fn spawn_preview_writer(dir: PathBuf, request_id: String) -> (PartialSink, JoinHandle<()>) {
+    let (sink, previews) = std::sync::mpsc::channel::<PartialImage>();
+    let writer = std::thread::spawn(move || {
+        for preview in previews {
+            let ext = format_extension(preview.mime_type.trim_start_matches("image/"));
+            let path = dir.join(preview_file_name(&request_id, preview.frame + 1, ext));
+            match std::fs::create_dir_all(&dir).and_then(|()| std::fs::write(&path, &preview.data))
+            {
+                Ok(()) => tracing::info!(path = %path.display(), "Preview: {}", path.display()),
+                Err(e) => {
+                    report::warn(format_args!("failed to write preview {}: {e}", path.display()));
+                }
+            }
+        }
+    });
+    (sink, writer)
+}
+
+/// The run's jobs, from the batch or else the command line, with `--style` applied to
+/// every prompt.
+fn resolve_jobs(
+    cli: &GenerateArgs,
+    batch: Option<Batch<'_>>,
+    config: &Config,
+) -> Result<Vec<Job>, ImageError> {
+    let mut jobs = if let Some(batch) = batch {
+        batch.jobs.to_vec()
+    } else {
+        let prompt = cli.resolve_prompt(&config.prompts).map_err(ImageError::Io)?;
+        vec![Job::prompt(prompt)]
+    };
+    for job in &mut jobs {
+        job.prompt =
+            cli.apply_styles(&job.prompt, &config.styles).map_err(ImageError::InvalidArgument)?;
+    }
+    Ok(jobs)
+}
+
+/// The jobs of an `imagen batch` run that share a model.
+#[derive(Clone, Copy)]
+struct Batch<'a> {
+    jobs: &'a [Job],
+    /// With `--resume`, where finished requests are recorded.
+    resume: Option<&'a Resume>,
+}
+
+/// The `--resume` state file of a batch and the requests it records as finished.
+struct Resume {
+    path: PathBuf,
+    state: RefCell<BatchState>,
+}
+
+impl Resume {
+    fn open(path: &str) -> Result<Self, ImageError> {
+        let path = PathBuf::from(path);
+        let state = BatchState::load(&path).map_err(ImageError::InvalidArgument)?;
+        Ok(Self { path, state: RefCell::new(state) })
+    }
+
+    /// The requests (and their targets) not yet finished, noting how many are skipped.
+    fn pending(
+        &self,
+        requests: Vec<ImageRequest>,
+        targets: Vec<Target>,
+    ) -> (Vec<ImageRequest>, Vec<Target>) {
+        let ids: Vec<String> = requests.iter().map(ImageRequest::request_id).collect();
+        let finished = self.state.borrow().finished(&ids);
+        let skipped = finished.iter().filter(|&&done| done).count();
+        if skipped > 0 {
+            tracing::info!(
+                "Resuming: {skipped} of {} requests already finished ({})",
+                requests.len(),
+                self.path.display()
+            );
+        }
+        requests
+            .into_iter()
+            .zip(targets)
+            .zip(finished)
+            .filter_map(|(pending, done)| (!done).then_some(pending))
+            .unzip()
+    }
+
+    /// Record that every image of `request` was saved, as `files`.
+    fn finish(&self, request: &ImageRequest, files: &[PathBuf]) {
+        let mut state = self.state.borrow_mut();
+        let recorded = state
+            .finish(request.request_id(), &request.prompt, files)
+            .and_then(|()| state.save(&self.path));
+        if let Err(e) = recorded {
+            report::warn(format_args!("failed to update {}: {e}", self.path.display()));
+        }
+    }
+}
+
+/// Show what is about to be sent and ask the user to confirm on stdin.
+fn confirm_request(
+    request: &ImageRequest,
+    jobs: &[Job],
+    provider: Provider,
+    estimate: &Estimate,
+) -> Result<bool, ImageError> {
+    let mut preview = render_preview(request, provider);
+    if let [_, rest @ ..] = jobs {
+        for job in rest {
+            preview.push_str("Prompt:\n");
+            for line in job.prompt.lines() {
+                let _ = writeln!(preview, "  {line}");
+            }
+        }
+    }
+    let _ = writeln!(preview, "Estimated cost: {estimate}");
+    eprint!("{preview}Send this request? [y/N] ");
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes"))
+}
+
+/// The exact prompt and the settings that affect what is generated and billed.
+fn render_preview(request: &ImageRequest, provider: Provider) -> String {
+    let mut preview = format!("Model: {} ({provider:?})\n", request.model);
+    let _ = writeln!(
+        preview,
+        "Images: {} at {}, {}, quality {}",
+        request.count, request.size, request.aspect_ratio, request.quality
+    );
+    if !request.input_images.is_empty() {
+        let names: Vec<&str> = request.input_images.iter().map(|i| i.filename.as_str()).collect();
+        let _ = writeln!(preview, "Inputs: {}", names.join(", "));
+    }
+    preview.push_str("Prompt:\n");
+    for line in request.prompt.lines() {
+        let _ = writeln!(preview, "  {line}");
+    }
+    preview
+}
+
+/// Write the cassette, reporting (but not failing on) write errors.
+fn finish_recording(session: RecordingSession) {
+    match session.finish() {
+        Ok(path) => tracing::info!("Cassette saved: {}", path.display()),
+        Err(e) => report::warn(format_args!("failed to save cassette: {e}")),
+    }
+}
+
+/// Select the service context for the run's `mode` (live / recording / replaying).
+///
+/// Returns the recording session alongside the context when recording.
+fn create_context(
+    cli: &GenerateArgs,
+    mode: &Mode,
+    provider: Provider,
+    operation: Operation,
+    config: &Config,
+    prompt: &str,
+    enhance_model: Option<&str>,
+) -> Result<(ServiceContext, Option<RecordingSession>), ImageError> {
+    let replaying = matches!(mode, Mode::Replay(_));
+
+    let local_only = match provider {
+        Provider::SdLocal => is_loopback_url(&config.sd_local_url()),
+        Provider::OpenAi => config.openai_base_url().is_some_and(is_loopback_url),
+        _ => false,
+    };
+    if cli.offline && !replaying && !local_only {
+        return Err(ImageError::Offline(format!(
+            "{provider:?} requires network access. Pass --mode replay:<path> to replay a \
+             cassette or use a localhost sd-local or --api-base endpoint."
+        )));
+    }
+    let local_enhance = enhance_model.is_some_and(|model| model.starts_with("gpt"))
+        && config.openai_base_url().is_some_and(is_loopback_url);
+    if cli.offline && !replaying && enhance_model.is_some() && !local_enhance {
+        return Err(ImageError::Offline(
+            "--enhance and --on-refusal soften require network access. Pass --mode \
+             replay:<path> to replay a cassette or use a gpt model behind a localhost --api-base \
+             endpoint."
+                .into(),
+        ));
+    }
+
+    match mode {
+        Mode::Replay(path) => tracing::debug!("Replaying from: {}", path.display()),
+        Mode::Record(_) => tracing::debug!("Recording mode enabled"),
+        Mode::Live => {}
+    }
+    if !replaying {
+        tracing::debug!("Network: {}", NetworkSettings::from_env().describe());
+    }
+
+    ServiceContext::for_mode(mode, provider, config, operation, prompt, enhance_model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preview_shows_the_exact_prompt() {
+        let request = ImageRequest {
+            aspect_ratio: "16:9".into(),
+            size: "2K".into(),
+            quality: "high".into(),
+            format: "png".into(),
+            count: 2,
+            ..ImageRequest::sample("gpt-image-1", "a cat\non a mat")
+        };
+        assert_eq!(
+            render_preview(&request, Provider::OpenAi),
+            "Model: gpt-image-1 (OpenAi)\nImages: 2 at 2K, 16:9, quality high\n\
+             Prompt:\n  a cat\n  on a mat\n"
+        );
+    }
+}
@@ -0,0 +1,498 @@
+//! Saving a run's images: naming each file, writing it through the run's
+//! post-processing, and recording what was saved.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::adapters::live::http::{download_image, Download};
+use crate::cli::GenerateArgs;
+use crate::error::ImageError;
+use crate::metadata::Sidecar;
+use crate::model::{detect_provider, Provider};
+use crate::output::{
+    claim_output_path, indexed_output_path, model_output_path, needs_conversion, open_in_viewer,
+    ratio_output_path, resolve_output_path, save_image, save_thumbnail, versioned_output_path,
+    Existing, NameFields, NameTemplate, Naming, PostProcess, Staging,
+};
+use crate::ports::image_generator::{GeneratedImage, ImageResponse};
+use crate::ports::ImageRequest;
+use crate::pricing::price_per_image;
+use crate::{contact_sheet, events, metadata, preview, progress, report};
+
+use super::settings::request_provider;
+use super::Resume;
+
+/// Write a contact sheet of the labeled `files` to `path`, returning where it went.
+/// Problems are warnings: the images themselves are already saved.
+pub(super) fn save_contact_sheet(
+    files: &[(String, PathBuf)],
+    path: &Path,
+    existing: Existing,
+) -> Option<PathBuf> {
+    if files.len() < 2 {
+        report::warn(format_args!(
+            "a contact sheet needs at least two images (-n, an aspect-ratio sweep, or \
+             several models); none was written"
+        ));
+        return None;
+    }
+    let written = claim_output_path(path, existing)
+        .and_then(|path| contact_sheet::save(files, &path).map(|()| path));
+    match written {
+        Ok(path) => {
+            tracing::info!(path = %path.display(), "Contact sheet: {}", path.display());
+            Some(path)
+        }
+        Err(e) => {
+            report::warn(format_args!("failed to write the contact sheet: {e}"));
+            None
+        }
+    }
+}
+
+/// Show a run's saved images: each in the terminal with `--preview`, and in the default
+/// viewer with `--open`, where the run's contact sheet stands in for its images.
+pub(super) fn show_saved(cli: &GenerateArgs, files: &[PathBuf], contact_sheet: Option<&PathBuf>) {
+    if let Some(protocol) = cli.preview.as_deref() {
+        let protocol = preview::Protocol::parse(protocol).expect("clap checks the protocol");
+        for file in files {
+            if let Err(e) = preview::show(file, protocol) {
+                report::warn(format_args!("cannot preview {}: {e}", file.display()));
+            }
+        }
+    }
+    if !cli.open {
+        return;
+    }
+    let viewed = contact_sheet.map_or(files, std::slice::from_ref);
+    for file in viewed {
+        if let Err(e) = open_in_viewer(file) {
+            report::warn(format_args!("cannot open {}: {e}", file.display()));
+        }
+    }
+}
+
+/// Save a run's results; with `--transactional`, all of them or none.
+pub(super) async fn save_run(
+    cli: &GenerateArgs,
+    requests: &[ImageRequest],
+    results: Vec<Result<ImageResponse, ImageError>>,
+    outputs: Outputs<'_>,
+    downloads: Option<&reqwest::Client>,
+) -> Result<(), ImageError> {
+    if !cli.transactional {
+        return save_sweep(requests, results, &outputs, downloads).await;
+    }
+    let staging = Staging::new();
+    let outputs = Outputs { staging: Some(&staging), ..outputs };
+    let saved = save_sweep(requests, results, &outputs, downloads).await;
+    finish_transaction(staging, saved, requests)
+}
+
+/// End a `--transactional` run: move its images into place if all of them were saved,
+/// otherwise discard every one.
+fn finish_transaction(
+    staging: Staging,
+    saved: Result<(), ImageError>,
+    requests: &[ImageRequest],
+) -> Result<(), ImageError> {
+    match saved {
+        Ok(()) => {
+            for path in staging.commit()? {
+                tracing::info!(path = %path.display(), "Saved: {}", path.display());
+                events::emit(&events::Event::ImageSaved { request_id: None, path: &path });
+            }
+            Ok(())
+        }
+        Err(ImageError::PartialFailure { saved, total }) => {
+            Err(ImageError::RolledBack { failed: total - saved, total })
+        }
+        Err(e) if requests.len() > 1 || requests[0].count > 1 => {
+            report::warn(&e);
+            let total = requests.iter().map(|request| request.count as usize).sum();
+            Err(ImageError::RolledBack { failed: total, total })
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Where one request's images go.
+pub(super) struct Target {
+    /// Explicit path (`--output`, or the batch job's `output`); otherwise auto-named.
+    pub(super) output: Option<String>,
+    /// One of several ratios of the same prompt, so its files get a ratio suffix.
+    pub(super) sweep: bool,
+}
+
+/// Where a run's images are written.
+pub(super) struct Outputs<'a> {
+    pub(super) format: &'a str,
+    pub(super) naming: &'a Naming,
+    pub(super) post: &'a PostProcess,
+    /// One per request.
+    pub(super) targets: &'a [Target],
+    /// With `--watch`, the run's version, added to every file name (`cat-v3.png`).
+    pub(super) version: Option<u32>,
+    /// With `imagen compare`, the model, added to every file name (`cat-gpt-1.png`).
+    pub(super) label: Option<&'a str>,
+    /// With `imagen batch --resume`, records each request once its images are saved.
+    pub(super) resume: Option<&'a Resume>,
+    /// Collects every file saved, with the index of its request, for the history.
+    pub(super) saved: Option<&'a RefCell<Vec<(usize, PathBuf)>>>,
+    /// With `--transactional`, holds every file back until the whole run succeeds.
+    pub(super) staging: Option<&'a Staging>,
+    /// With `--sidecar`, writes a JSON file next to every image.
+    pub(super) sidecars: Option<Sidecars<'a>>,
+    /// Per request, whether `--cache` answered it with files this run's save steps have
+    /// already been applied to.
+    pub(super) reused: &'a [bool],
+}
+
+/// What each image's JSON sidecar needs beyond its request and response.
+#[derive(Clone, Copy)]
+pub(super) struct Sidecars<'a> {
+    pub(super) provider: Provider,
+    pub(super) pricing: &'a HashMap<String, f64>,
+    pub(super) started_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl<'a> Outputs<'a> {
+    pub(super) fn new(
+        format: &'a str,
+        naming: &'a Naming,
+        post: &'a PostProcess,
+        targets: &'a [Target],
+    ) -> Self {
+        Self {
+            format,
+            naming,
+            post,
+            targets,
+            version: None,
+            label: None,
+            resume: None,
+            saved: None,
+            staging: None,
+            sidecars: None,
+            reused: &[],
+        }
+    }
+}
+
+/// Names the images of one request.
+pub(super) struct ImagePaths<'a> {
+    outputs: &'a Outputs<'a>,
+    request: &'a ImageRequest,
+    sweep: bool,
+    stem: Stem<'a>,
+}
+
+/// What a request's file names start from.
+enum Stem<'a> {
+    /// Its `--output`, or an auto-generated name shared by all its images.
+    Path(PathBuf),
+    /// `--name-template`, filled in for each image.
+    Template(&'a NameTemplate),
+}
+
+impl<'a> ImagePaths<'a> {
+    pub(super) fn new(
+        outputs: &'a Outputs<'a>,
+        request: &'a ImageRequest,
+        target: &Target,
+    ) -> Self {
+        let (format, naming) = (outputs.format, outputs.naming);
+        let stem = match (&naming.template, &target.output) {
+            (Some(template), None) => Stem::Template(template),
+            (_, output) => {
+                let id = request.request_id();
+                let path =
+                    resolve_output_path(output.as_deref(), &request.prompt, format, naming, &id);
+                Stem::Path(path)
+            }
+        };
+        Self { outputs, request, sweep: target.sweep, stem }
+    }
+
+    /// Path for image `index` (0-based) of `count`: the stem, then the `--watch`
+    /// version, the sweep's ratio, and the image number, unless the template has them.
+    pub(super) fn image(&self, index: usize, count: usize) -> PathBuf {
+        let (format, naming) = (self.outputs.format, self.outputs.naming);
+        let request = self.request;
+        let (mut path, template) = match self.stem {
+            Stem::Path(ref path) => (path.clone(), None),
+            Stem::Template(template) => {
+                let provider = detect_provider(&request.model)
+                    .map(|provider| format!("{provider:?}").to_lowercase())
+                    .unwrap_or_default();
+                let fields = NameFields {
+                    prompt: &request.prompt,
+                    model: &request.model,
+                    provider: &provider,
+                    seed: u32::try_from(index).ok().and_then(|index| request.image_seed(index)),
+                    n: index + 1,
+                    request_id: &request.request_id(),
+                    aspect_ratio: &request.aspect_ratio,
+                    size: &request.size,
+                    quality: &request.quality,
+                };
+                let name = template.render(&fields, naming.extension(format));
+                let path = match &naming.dir {
+                    Some(dir) => dir.join(name),
+                    None => name,
+                };
+                (path, Some(template))
+            }
+        };
+        if let Some(version) = self.outputs.version {
+            path = versioned_output_path(&path, version, format, naming);
+        }
+        if let Some(model) = self.outputs.label {
+            if !template.is_some_and(NameTemplate::names_model) {
+                path = model_output_path(&path, model, format, naming);
+            }
+        }
+        if self.sweep && !template.is_some_and(NameTemplate::names_ratio) {
+            path = ratio_output_path(&path, &request.aspect_ratio, format, naming);
+        }
+        if template.is_some_and(NameTemplate::numbers_images) {
+            return path;
+        }
+        indexed_output_path(&path, index, count, format, naming)
+    }
+
+    /// Path for a grid of the run's images, named after this request's images with a
+    /// `-<kind>` suffix: `cat-sheet.jpg`.
+    pub(super) fn sheet(&self, kind: &str) -> PathBuf {
+        let (format, naming) = (self.outputs.format, self.outputs.naming);
+        let mut path = match self.stem {
+            Stem::Path(ref path) => path.clone(),
+            Stem::Template(_) => self.image(0, 1),
+        };
+        if let Stem::Path(_) = self.stem {
+            if let Some(version) = self.outputs.version {
+                path = versioned_output_path(&path, version, format, naming);
+            }
+            if let Some(model) = self.outputs.label {
+                path = model_output_path(&path, model, format, naming);
+            }
+        }
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        path.with_file_name(format!("{stem}-{kind}.jpg"))
+    }
+}
+
+/// Save the results of a run: an aspect-ratio sweep, a batch of prompts, or both.
+///
+/// A single request behaves as it always has. Each file is named after its own prompt,
+/// and in a sweep of several ratios gets a ratio suffix (`cat-16x9.png`). A request that
+/// fails is reported without stopping the others; the run then ends with
+/// [`ImageError::PartialFailure`].
+async fn save_sweep(
+    requests: &[ImageRequest],
+    results: Vec<Result<ImageResponse, ImageError>>,
+    outputs: &Outputs<'_>,
+    downloads: Option<&reqwest::Client>,
+) -> Result<(), ImageError> {
+    if let ([request], [target]) = (requests, outputs.targets) {
+        let response = results.into_iter().next().expect("one result per request")?;
+        let paths = ImagePaths::new(outputs, request, target);
+        let files = save_images(0, &response, &paths, downloads).await?;
+        if let Some(resume) = outputs.resume {
+            resume.finish(request, &files);
+        }
+        return Ok(());
+    }
+
+    let batch = requests.iter().any(|request| request.prompt != requests[0].prompt);
+    let (mut saved, mut total) = (0, 0);
+    let mut first_error = None;
+    let results = requests.iter().zip(outputs.targets).zip(results).enumerate();
+    for (index, ((request, target), result)) in results {
+        let paths = ImagePaths::new(outputs, request, target);
+        let outcome = match result {
+            Ok(response) => save_images(index, &response, &paths, downloads).await.map(|files| {
+                if let Some(resume) = outputs.resume {
+                    resume.finish(request, &files);
+                }
+                files.len()
+            }),
+            Err(e) => Err(e),
+        };
+        progress::advance_batch(request.count as usize);
+        match outcome {
+            Ok(count) => (saved, total) = (saved + count, total + count),
+            Err(ImageError::PartialFailure { saved: s, total: t }) => {
+                (saved, total) = (saved + s, total + t);
+            }
+            Err(e) => {
+                let label = describe_request(request, target.sweep, batch);
+                report::warn(format_args!("{label} failed: {e}"));
+                total += request.count as usize;
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+    match first_error {
+        Some(e) if saved == 0 => Err(e),
+        _ if saved < total => Err(ImageError::PartialFailure { saved, total }),
+        _ => Ok(()),
+    }
+}
+
+/// How a failed request of a run is named: `aspect ratio 16:9`, `prompt "a cat"`, or
+/// `prompt "a cat" at 16:9`.
+fn describe_request(request: &ImageRequest, sweep: bool, batch: bool) -> String {
+    let ratio = &request.aspect_ratio;
+    match (batch, sweep) {
+        (true, true) => format!("prompt {:?} at {ratio}", request.prompt),
+        (true, false) => format!("prompt {:?}", request.prompt),
+        _ => format!("aspect ratio {ratio}"),
+    }
+}
+
+/// Save every image in the response to request number `index` of the run, named by
+/// `paths` and post-processed into the run's format, returning the files.
+///
+/// URL-delivered images are fetched with `downloads`, streaming straight to disk when
+/// they need no conversion. An image that fails to decode, download, or save is
+/// reported by index and skipped; the rest are still saved, and the run ends with
+/// [`ImageError::PartialFailure`].
+async fn save_images(
+    index: usize,
+    response: &ImageResponse,
+    paths: &ImagePaths<'_>,
+    downloads: Option<&reqwest::Client>,
+) -> Result<Vec<PathBuf>, ImageError> {
+    let (outputs, request) = (paths.outputs, paths.request);
+    // Cached images were saved with this run's format and steps; applying the steps
+    // again would rotate or watermark them twice.
+    let reused = outputs.reused.get(index) == Some(&true);
+    let finished;
+    let post = if reused {
+        finished = PostProcess { thumbnail: outputs.post.thumbnail, ..PostProcess::default() };
+        &finished
+    } else {
+        outputs.post
+    };
+    let format = outputs.format;
+
+    for failure in &response.failures {
+        report::warn(format_args!("image {} failed: {}", failure.index + 1, failure.reason));
+    }
+    let total = response.images.len() + response.failures.len();
+    let mut saved = Vec::new();
+    for (i, image) in response.images.iter().enumerate() {
+        let path = paths.image(i, response.images.len());
+        let output_path = match claim_output_path(&path, outputs.naming.existing) {
+            Ok(path) => path,
+            Err(e) if total == 1 => return Err(e),
+            Err(e) => {
+                report::warn(format_args!("image {} failed: {e}", i + 1));
+                continue;
+            }
+        };
+        let target = match outputs.staging {
+            Some(staging) => staging.stage(&output_path),
+            None => Ok(output_path.clone()),
+        };
+        let result = match target {
+            Ok(target) => match write_image(image, &target, format, post, downloads).await {
+                Ok(()) => annotate(&target, request, response, i, outputs, reused),
+                Err(e) => Err(e),
+            },
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(()) => {
+                // Staged images are reported once they are moved into place.
+                if outputs.staging.is_none() {
+                    tracing::info!(
+                        path = %output_path.display(),
+                        "Saved: {}",
+                        output_path.display()
+                    );
+                    events::emit(&events::Event::ImageSaved {
+                        request_id: Some(request.request_id()),
+                        path: &output_path,
+                    });
+                }
+                if let Some(files) = outputs.saved {
+                    files.borrow_mut().push((index, output_path.clone()));
+                }
+                saved.push(output_path);
+            }
+            // A lone image has nothing to salvage; surface its error as-is.
+            Err(e) if total == 1 => return Err(e),
+            Err(e) => report::warn(format_args!("failed to save {}: {e}", output_path.display())),
+        }
+    }
+
+    if saved.len() < total {
+        return Err(ImageError::PartialFailure { saved: saved.len(), total });
+    }
+    Ok(saved)
+}
+
+/// Record how image `index` of `response` was made with the file saved at `path`: a
+/// `parameters` chunk in PNGs unless `--strip` or the file is a `reused` cache hit that
+/// has one already, and with `--sidecar` a JSON file next to it.
+fn annotate(
+    path: &Path,
+    request: &ImageRequest,
+    response: &ImageResponse,
+    index: usize,
+    outputs: &Outputs<'_>,
+    reused: bool,
+) -> Result<(), ImageError> {
+    if outputs.format == "png" && !outputs.post.strip && !reused {
+        metadata::embed_png_parameters(path, request, index)?;
+    }
+    if let Some(sidecars) = outputs.sidecars {
+        let sidecar = Sidecar {
+            provider: format!("{:?}", request_provider(request, sidecars.provider)).to_lowercase(),
+            cost_usd: price_per_image(request, sidecars.pricing),
+            ..Sidecar::new(path, request, response, index, sidecars.started_at)
+        };
+        sidecar.write(path)?;
+    }
+    Ok(())
+}
+
+/// Write one image to `path`, creating its directory and downloading the image first
+/// when it was delivered by URL, then its thumbnail with `--thumbnail`.
+async fn write_image(
+    image: &GeneratedImage,
+    path: &Path,
+    format: &str,
+    post: &PostProcess,
+    downloads: Option<&reqwest::Client>,
+) -> Result<(), ImageError> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)?;
+    }
+    let convert = |data: &[u8], mime: &str| {
+        let _converting = needs_conversion(mime, format, post).then(|| progress::converting(path));
+        save_image(data, mime, format, path, post)
+    };
+    if let (Some(url), Some(client)) = (&image.url, downloads) {
+        let can_stream = |mime: &str| !needs_conversion(mime, format, post);
+        let download = progress::download(path);
+        let progress = |received, total| download.downloaded(received, total);
+        match download_image(client, url, path, &image.mime_type, can_stream, progress).await? {
+            Download::Streamed { sha256 } => tracing::debug!("SHA-256: {sha256}"),
+            Download::Buffered { data, mime_type } => {
+                drop(download);
+                convert(&data, &mime_type)?;
+            }
+        }
+    } else {
+        convert(&image.data, &image.mime_type)?;
+    }
+    // Made from the saved file, so it shows exactly what was written.
+    if let Some(size) = post.thumbnail {
+        save_thumbnail(path, size)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,518 @@
+//! The settings a run's requests are built from: command-line flags over config
+//! defaults, validated against what the provider supports.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use crate::cli::GenerateArgs;
+use crate::config::{Config, DefaultsConfig};
+use crate::context::{enhance_model, Operation};
+use crate::error::ImageError;
+use crate::manifest::Job;
+use crate::model::{detect_provider, resolve_model, Provider};
+use crate::output::{Existing, NameTemplate, Naming, PostProcess, SlugStrategy};
+use crate::params::{
+    fit_aspect_ratio, generation_size, parse_input_spec, parse_ratio_list, provider_compresses,
+    validate_api_base, validate_background, validate_bit_depth, validate_compression,
+    validate_count, validate_edit, validate_input_paths, validate_magic_prompt,
+    validate_moderation, validate_reference_images, validate_safety, validate_seed,
+    validate_stream, validate_stream_download, validate_style_type, validate_thinking,
+};
+use crate::ports::{ImageRequest, InputImage};
+use crate::watermark::{Mark, Position, Watermark};
+use crate::{input, post, report};
+
+use super::save::Target;
+use super::{OnRefusal, Resume};
+
+/// How the run names its files: the command line's `--slug`, `--name-template`, and
+/// `--output-dir`, falling back to `[output]` in the config, and whether `--force` or
+/// `--no-clobber` let it replace or sidestep existing files.
+pub(super) fn naming(cli: &GenerateArgs, config: &Config) -> Result<Naming, String> {
+    let slug_name = cli.slug.as_deref().or(config.output.slug.as_deref()).unwrap_or("prompt");
+    // A --slug on the command line outranks a configured template.
+    let configured_template = config.output.name_template.as_deref().filter(|_| cli.slug.is_none());
+    let template = cli.name_template.as_deref().or(configured_template);
+    Ok(Naming {
+        slug: SlugStrategy::parse(slug_name)?,
+        extensions: config.output.extensions.clone(),
+        dir: cli.output_dir.as_deref().or(config.output.dir.as_deref()).map(PathBuf::from),
+        template: template.map(NameTemplate::parse).transpose()?,
+        existing: match (cli.force, cli.no_clobber) {
+            (true, _) => Existing::Overwrite,
+            (false, true) => Existing::Refuse,
+            (false, false) => Existing::Rename,
+        },
+    })
+}
+
+/// The adjustments applied to every saved image: `--bit-depth`, `--grayscale`, the steps
+/// `--width`/`--height`, `--post`, and the watermark, whose logo is read once up front,
+/// and `--compression` unless `provider` applies it.
+pub(super) fn post_process(
+    cli: &GenerateArgs,
+    format: &str,
+    provider: Provider,
+) -> Result<PostProcess, ImageError> {
+    let mark = match (&cli.watermark, &cli.watermark_image) {
+        (Some(text), _) => Some(Mark::Text(text.clone())),
+        (None, Some(path)) => {
+            let logo = image::open(path).map_err(|e| {
+                ImageError::InvalidArgument(format!("--watermark-image {path}: {e}"))
+            })?;
+            Some(Mark::Image(logo.to_rgba8()))
+        }
+        (None, None) => None,
+    };
+    let watermark = mark
+        .map(|mark| {
+            if !(0.0..=1.0).contains(&cli.watermark_opacity) {
+                return Err(format!(
+                    "--watermark-opacity must be between 0.0 and 1.0, got {}",
+                    cli.watermark_opacity
+                ));
+            }
+            let position = Position::parse(&cli.watermark_position)?;
+            Ok(Watermark { mark, position, opacity: cli.watermark_opacity, scale: None })
+        })
+        .transpose()
+        .map_err(ImageError::InvalidArgument)?;
+
+    let mut steps = Vec::new();
+    if let Some((width, height)) = cli.dimensions() {
+        steps.push(post::Step::Fill(width, height, post::Crop::Center));
+    }
+    if let Some(ref spec) = cli.post {
+        steps.extend(post::parse(spec).map_err(ImageError::InvalidArgument)?);
+    }
+    steps.extend(watermark.map(post::Step::Watermark));
+
+    let compression = cli.compression.filter(|_| !provider_compresses(format, provider));
+    if compression.is_some() && format == "webp" && !cfg!(feature = "webp") {
+        report::warn(format_args!(
+            "--compression has no effect on WebP without imagen's `webp` feature; \
+             WebP is written losslessly"
+        ));
+    }
+    Ok(PostProcess {
+        bit_depth: cli.bit_depth,
+        grayscale: cli.grayscale,
+        steps,
+        compression,
+        strip: cli.strip,
+        thumbnail: cli.thumbnail,
+    })
+}
+
+/// Each job's aspect ratios, as the provider supports them: its own list, or `default`
+/// (`--aspect-ratio`). Ratios the provider lacks become the nearest it has, unless
+/// `strict` (`--strict-ratio`).
+pub(super) fn job_ratios(
+    jobs: &[Job],
+    default: &str,
+    provider: Provider,
+    strict: bool,
+) -> Result<Vec<Vec<String>>, String> {
+    jobs.iter()
+        .map(|job| {
+            let requested = parse_ratio_list(job.aspect_ratio.as_deref().unwrap_or(default))?;
+            let mut fitted: Vec<String> = Vec::new();
+            for ratio in &requested {
+                let nearest = fit_aspect_ratio(ratio, provider, strict)?;
+                if nearest != *ratio {
+                    tracing::info!(
+                        "Note: {provider:?} has no {ratio} aspect ratio; using the nearest, \
+                         {nearest} (--strict-ratio fails instead)"
+                    );
+                }
+                if let Some(earlier) = fitted.iter().position(|r| *r == nearest) {
+                    return Err(format!(
+                        "Aspect ratios {} and {ratio} would both be sent as {nearest}",
+                        requested[earlier]
+                    ));
+                }
+                fitted.push(nearest);
+            }
+            Ok(fitted)
+        })
+        .collect()
+}
+
+/// One request per job and aspect ratio, job by job, with where each one's images go; a
+/// single prompt and ratio is a run of one. A job's own `output` is relative to
+/// `output_dir` when there is one. With `--resume`, requests that already finished are
+/// left out.
+pub(super) fn expand_requests(
+    cli: &GenerateArgs,
+    template: &ImageRequest,
+    jobs: &[Job],
+    ratios: &[Vec<String>],
+    output_dir: Option<&Path>,
+    resume: Option<&Resume>,
+) -> (Vec<ImageRequest>, Vec<Target>) {
+    let (requests, targets) = jobs
+        .iter()
+        .zip(ratios)
+        .flat_map(|(job, ratios)| ratios.iter().map(move |ratio| (job, ratio, ratios.len() > 1)))
+        .map(|(job, ratio, sweep)| {
+            let request = ImageRequest {
+                prompt: job.prompt.clone(),
+                aspect_ratio: ratio.clone(),
+                count: job.count.unwrap_or(template.count),
+                seed: job.seed.or(template.seed),
+                ..template.clone()
+            };
+            let output = match (&job.output, output_dir) {
+                (Some(output), Some(dir)) => Some(dir.join(output).to_string_lossy().into_owned()),
+                (output, _) => output.clone().or_else(|| cli.output.clone()),
+            };
+            (request, Target { output, sweep })
+        })
+        .unzip();
+    match resume {
+        Some(resume) => resume.pending(requests, targets),
+        None => (requests, targets),
+    }
+}
+
+/// The settings a request was actually made with, after config defaults and alias
+/// resolution, for the cassette header. Unset options are omitted.
+pub(super) fn effective_parameters(
+    cli: &GenerateArgs,
+    request: &ImageRequest,
+    request_ids: &[String],
+    operation: Operation,
+    format: &str,
+    enhance_model: Option<&str>,
+) -> BTreeMap<String, String> {
+    let mut parameters = BTreeMap::from([
+        ("operation".to_string(), format!("{operation:?}").to_lowercase()),
+        ("model".to_string(), request.model.clone()),
+        ("aspect_ratio".to_string(), request.aspect_ratio.clone()),
+        ("size".to_string(), request.size.clone()),
+        ("quality".to_string(), request.quality.clone()),
+        ("format".to_string(), format.to_string()),
+        ("count".to_string(), request.count.to_string()),
+        ("request_id".to_string(), request_ids.join(", ")),
+    ]);
+    let watermarked = cli.watermark.is_some() || cli.watermark_image.is_some();
+    let optional = [
+        ("thinking", request.thinking.clone()),
+        ("background", request.background.clone()),
+        ("magic_prompt", request.magic_prompt.clone()),
+        ("style_type", request.style_type.clone()),
+        ("moderation", request.moderation.clone()),
+        ("safety", request.safety.clone()),
+        ("seed", request.seed.map(|seed| seed.to_string())),
+        ("enhance", enhance_model.map(str::to_string)),
+        ("compression", cli.compression.map(|level| level.to_string())),
+        ("bit_depth", cli.bit_depth.map(|d| d.to_string())),
+        ("grayscale", cli.grayscale.then(|| "true".to_string())),
+        ("strip", cli.strip.then(|| "true".to_string())),
+        ("thumbnail", cli.thumbnail.map(|size| size.to_string())),
+        ("contact_sheet", cli.contact_sheet.then(|| "true".to_string())),
+        ("fallback", (!cli.fallback.is_empty()).then(|| cli.fallback.join(", "))),
+        ("on_refusal", cli.on_refusal.clone()),
+        ("dimensions", cli.dimensions().map(|(width, height)| format!("{width}x{height}"))),
+        ("post", cli.post.clone()),
+        ("watermark", cli.watermark.clone()),
+        ("watermark_image", cli.watermark_image.clone()),
+        ("watermark_position", watermarked.then(|| cli.watermark_position.clone())),
+        ("watermark_opacity", watermarked.then(|| cli.watermark_opacity.to_string())),
+        ("output", cli.output.clone()),
+        ("inputs", (!cli.input.is_empty()).then(|| cli.input.join(", "))),
+        ("references", (!cli.reference.is_empty()).then(|| cli.reference.join(", "))),
+    ];
+    for (name, value) in optional {
+        if let Some(value) = value {
+            parameters.insert(name.to_string(), value);
+        }
+    }
+    parameters
+}
+
+/// The `--fallback` (or `[defaults] fallback`) models by their resolved names, each
+/// checked to have a provider, leaving out the run's own `model` and repeats.
+pub(super) fn fallback_models(names: &[String], model: &str) -> Result<Vec<String>, String> {
+    let mut models = Vec::new();
+    for name in names {
+        let resolved = resolve_model(name);
+        detect_provider(&resolved).map_err(|e| format!("--fallback {name}: {e}"))?;
+        if resolved != model && !models.contains(&resolved) {
+            models.push(resolved);
+        }
+    }
+    Ok(models)
+}
+
+/// The `--on-refusal` (or `[defaults] on_refusal`) action: `soften` with the text model
+/// `--enhance` would use, or else a model, checked to have a provider.
+pub(super) fn refusal_action(
+    cli: &GenerateArgs,
+    action: Option<&str>,
+    provider: Provider,
+    config: &Config,
+) -> Result<Option<OnRefusal>, String> {
+    let Some(action) = action else { return Ok(None) };
+    if action == "soften" {
+        let model = enhance_model(cli.enhance_model.as_deref(), provider, config);
+        return Ok(Some(OnRefusal::Soften(model)));
+    }
+    let model = resolve_model(action);
+    detect_provider(&model).map_err(|e| format!("--on-refusal {action}: {e}"))?;
+    Ok(Some(OnRefusal::Model(model)))
+}
+
+/// `request` as it would be sent to the fallback `model` instead: at that model's own
+/// size and quality defaults, under any given on the command line, and the nearest
+/// aspect ratio it has, with input images refitted to its limits and the options its
+/// provider lacks left out. Fails when the request cannot do without what the provider
+/// lacks: editing, reference images, or as many images in one request.
+pub(super) fn fallback_request(
+    cli: &GenerateArgs,
+    request: &ImageRequest,
+    operation: Operation,
+    defaults: &DefaultsConfig,
+    model: &str,
+    format: &str,
+) -> Result<ImageRequest, String> {
+    let provider = detect_provider(model)?;
+    if operation == Operation::Edit {
+        validate_edit(request.input_images.len(), provider)?;
+    }
+    if !request.reference_images.is_empty() {
+        validate_reference_images(provider)?;
+    }
+    validate_count(request.count, provider, false)?;
+    let settings = sized_settings(cli, provider, model, &defaults.for_model(model));
+    let aspect_ratio = match cli.dimensions() {
+        Some(_) => settings.aspect_ratio,
+        None => fit_aspect_ratio(&request.aspect_ratio, provider, cli.strict_ratio)?,
+    };
+    let fit = |images: &[InputImage]| {
+        input::fit_input_images(images.to_vec(), provider, !cli.no_auto_downscale)
+            .map_err(|e| e.to_string())
+    };
+    let supported = |value: &Option<String>, check: fn(&str, Provider) -> Result<(), String>| {
+        value.clone().filter(|value| check(value, provider).is_ok())
+    };
+    Ok(ImageRequest {
+        model: model.to_string(),
+        aspect_ratio,
+        size: settings.size,
+        quality: settings.quality,
+        seed: request.seed.filter(|_| validate_seed(provider).is_ok()),
+        thinking: supported(&request.thinking, validate_thinking),
+        input_images: fit(&request.input_images)?,
+        background: (request.background.clone())
+            .filter(|background| validate_background(background, format, provider).is_ok()),
+        magic_prompt: supported(&request.magic_prompt, validate_magic_prompt),
+        style_type: supported(&request.style_type, validate_style_type),
+        moderation: supported(&request.moderation, validate_moderation),
+        output_compression: cli.compression.filter(|_| provider_compresses(format, provider)),
+        safety: supported(&request.safety, validate_safety),
+        reference_images: fit(&request.reference_images)?,
+        return_urls: false,
+        ..request.clone()
+    })
+}
+
+/// The provider that answered `request`: the run's own, unless a fallback model did.
+pub(super) fn request_provider(request: &ImageRequest, run_provider: Provider) -> Provider {
+    detect_provider(&request.model).unwrap_or(run_provider)
+}
+
+/// Validate the seeds and image counts of the run's jobs.
+pub(super) fn validate_jobs(
+    cli: &GenerateArgs,
+    jobs: &[Job],
+    provider: Provider,
+) -> Result<(), String> {
+    if jobs.iter().any(|job| job.seed.is_some()) {
+        validate_seed(provider)?;
+    }
+    for job in jobs {
+        validate_count(job.count.unwrap_or(cli.count), provider, cli.split)?;
+    }
+    Ok(())
+}
+
+/// Validate flags that only some providers support.
+pub(super) fn validate_provider_options(
+    cli: &GenerateArgs,
+    format: &str,
+    provider: Provider,
+) -> Result<(), String> {
+    if let Some(ref thinking) = cli.thinking {
+        validate_thinking(thinking, provider)?;
+    }
+    if !cli.reference.is_empty() {
+        validate_reference_images(provider)?;
+    }
+    if cli.seed.is_some() {
+        validate_seed(provider)?;
+    }
+    if cli.stream_download {
+        validate_stream_download(provider)?;
+    }
+    if cli.stream {
+        validate_stream(provider, cli.count)?;
+        if !cli.input.is_empty() || cli.aspect_ratio.contains(',') {
+            return Err("--stream cannot be combined with input images or a ratio sweep".into());
+        }
+    }
+    if let Some(depth) = cli.bit_depth {
+        validate_bit_depth(depth, format)?;
+    }
+    if cli.compression.is_some() {
+        validate_compression(format)?;
+    }
+    if let Some(ref bg) = cli.background {
+        validate_background(bg, format, provider)?;
+    }
+    if let Some(ref api_base) = cli.api_base {
+        validate_api_base(api_base, provider)?;
+    }
+    if let Some(ref mode) = cli.magic_prompt {
+        validate_magic_prompt(mode, provider)?;
+    }
+    if let Some(ref style) = cli.style_type {
+        validate_style_type(style, provider)?;
+    }
+    if let Some(ref level) = cli.moderation {
+        validate_moderation(level, provider)?;
+    }
+    if let Some(ref level) = cli.safety {
+        validate_safety(level, provider)?;
+    }
+    Ok(())
+}
+
+/// Validate and read the `--input` and `--ref` images, in command-line order.
+pub(super) fn load_images(
+    cli: &GenerateArgs,
+    operation: Operation,
+    provider: Provider,
+) -> Result<(Vec<InputImage>, Vec<InputImage>), ImageError> {
+    let input_specs: Vec<(Option<String>, String)> =
+        cli.input.iter().map(|spec| parse_input_spec(spec)).collect();
+    if operation == Operation::Edit {
+        validate_edit(input_specs.len(), provider).map_err(ImageError::InvalidArgument)?;
+    }
+    let reference_specs: Vec<(Option<String>, String)> =
+        cli.reference.iter().map(|path| (None, path.clone())).collect();
+    let paths: Vec<String> =
+        input_specs.iter().chain(&reference_specs).map(|(_, path)| path.clone()).collect();
+    validate_input_paths(&paths).map_err(ImageError::InvalidArgument)?;
+
+    let fit = |specs| {
+        input::fit_input_images(input::read_input_images(specs)?, provider, !cli.no_auto_downscale)
+    };
+    Ok((fit(&input_specs)?, fit(&reference_specs)?))
+}
+
+/// Apply config-file defaults for any CLI flags still at their built-in defaults.
+pub(super) fn effective_settings(cli: &GenerateArgs, config: &DefaultsConfig) -> DefaultsConfig {
+    let builtin = DefaultsConfig::default();
+    DefaultsConfig {
+        model: apply_defaults(&cli.model, &builtin.model, &config.model),
+        aspect_ratio: apply_defaults(
+            &cli.aspect_ratio,
+            &builtin.aspect_ratio,
+            &config.aspect_ratio,
+        ),
+        size: apply_defaults(&cli.size, &builtin.size, &config.size),
+        quality: apply_defaults(&cli.quality, &builtin.quality, &config.quality),
+        format: apply_defaults(&cli.format, &builtin.format, &config.format),
+        fallback: if cli.fallback.is_empty() {
+            config.fallback.clone()
+        } else {
+            cli.fallback.clone()
+        },
+        on_refusal: cli.on_refusal.clone().or_else(|| config.on_refusal.clone()),
+        per_model: HashMap::new(),
+    }
+}
+
+/// [`effective_settings`], generating at the ratio and size nearest `--width` x `--height`
+/// when given.
+pub(super) fn sized_settings(
+    cli: &GenerateArgs,
+    provider: Provider,
+    model: &str,
+    defaults: &DefaultsConfig,
+) -> DefaultsConfig {
+    let mut settings = effective_settings(cli, defaults);
+    if let Some((width, height)) = cli.dimensions() {
+        (settings.aspect_ratio, settings.size) = generation_size(width, height, provider, model);
+        tracing::debug!(
+            "Generating at {} {}, then resizing to {width}x{height}",
+            settings.aspect_ratio,
+            settings.size
+        );
+    }
+    settings
+}
+
+/// Returns `cli_val` if it differs from `cli_default` (the user explicitly passed the flag),
+/// otherwise returns `config_val` (from the config-file defaults section).
+fn apply_defaults(cli_val: &str, cli_default: &str, config_val: &str) -> String {
+    if cli_val == cli_default {
+        config_val.to_string()
+    } else {
+        cli_val.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+
+    use super::*;
+    use crate::cli::Cli;
+
+    #[test]
+    fn config_default_overrides_cli_default() {
+        // When the CLI value is still "nano-banana" (the built-in default), the config default wins.
+        assert_eq!(apply_defaults("nano-banana", "nano-banana", "gpt-1"), "gpt-1");
+    }
+
+    #[test]
+    fn fallback_requests_fit_the_fallback_provider() {
+        let cli = Cli::parse_from(["imagen", "a cat"]);
+        let request = ImageRequest {
+            aspect_ratio: "21:9".into(),
+            seed: Some(7),
+            thinking: Some("high".into()),
+            safety: Some("few".into()),
+            ..ImageRequest::sample("gemini-3.1-flash-image-preview", "a cat")
+        };
+        let defaults = DefaultsConfig::default();
+        let fallback = |model| {
+            fallback_request(&cli.args, &request, Operation::Generate, &defaults, model, "jpeg")
+        };
+
+        let openai = fallback("gpt-image-1").unwrap();
+        assert_eq!((openai.model.as_str(), openai.prompt.as_str()), ("gpt-image-1", "a cat"));
+        assert_eq!((openai.seed, openai.thinking, openai.safety), (None, None, None));
+
+        let ideogram = fallback(&resolve_model("ideogram")).unwrap();
+        assert_eq!((ideogram.aspect_ratio.as_str(), ideogram.seed), ("16:9", Some(7)));
+
+        let edit = fallback_request(
+            &cli.args,
+            &request,
+            Operation::Edit,
+            &defaults,
+            "black-forest-labs/FLUX.1-schnell",
+            "jpeg",
+        );
+        assert!(edit.is_err(), "an edit cannot fall back to a model that cannot edit");
+    }
+
+    #[test]
+    fn explicit_cli_flag_overrides_config_default() {
+        // When the user explicitly sets a different model, that value wins.
+        assert_eq!(apply_defaults("dall-e", "nano-banana", "gpt-1"), "dall-e");
+    }
+}
@@ -0,0 +1,301 @@
+//! Generation history: one row in the shared store for every image imagen saves.
+//!
+//! Each row records the prompt, model, effective settings, where the file went, the
+//! SHA-256 of what was written, the estimated cost, and when it was saved, so earlier
+//! work can be searched, deduplicated, and reported on. Unlike the run log, history
+//! keeps the prompt and never leaves the machine. `[history] enabled = false` in the
+//! config turns it off. `imagen history` searches it.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::types::{Type, Value};
+use rusqlite::Row;
+use serde::Serialize;
+
+use crate::error::ImageError;
+use crate::store::Store;
+
+/// Schema for the `history` component of the store.
+const HISTORY_SCHEMA: &[&str] = &[
+    "CREATE TABLE history (
+         id          INTEGER PRIMARY KEY,
+         created_at  TEXT NOT NULL,
+         request_id  TEXT NOT NULL,
+         operation   TEXT NOT NULL,
+         provider    TEXT NOT NULL,
+         model       TEXT NOT NULL,
+         prompt      TEXT NOT NULL,
+         parameters  TEXT NOT NULL,
+         output_path TEXT NOT NULL,
+         sha256      TEXT NOT NULL,
+         cost_usd    REAL
+     );
+     CREATE INDEX history_created_at ON history (created_at);
+     CREATE INDEX history_model ON history (model, created_at);
+     CREATE INDEX history_sha256 ON history (sha256);",
+    // `--cache` looks entries up by request.
+    "CREATE INDEX history_request_id ON history (request_id);",
+];
+
+/// One saved image.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Entry {
+    /// When the image was saved.
+    pub created_at: DateTime<Utc>,
+    /// ID of the request that produced it.
+    pub request_id: String,
+    /// `generate` or `edit`.
+    pub operation: String,
+    /// Provider the request went to.
+    pub provider: String,
+    /// Resolved model name.
+    pub model: String,
+    /// Prompt text as sent.
+    pub prompt: String,
+    /// Effective settings of the request, as recorded in cassette headers.
+    pub parameters: BTreeMap<String, String>,
+    /// File the image was written to, made absolute where possible.
+    pub output_path: PathBuf,
+    /// Lowercase hex SHA-256 of the file as written.
+    pub sha256: String,
+    /// Estimated cost of the image in USD, when the model's price is known.
+    pub cost_usd: Option<f64>,
+}
+
+/// Which entries [`History::query`] returns.
+#[derive(Debug, Default)]
+pub struct Filter {
+    /// Request ID.
+    pub request_id: Option<String>,
+    /// Resolved model name.
+    pub model: Option<String>,
+    /// Earliest time saved.
+    pub since: Option<DateTime<Utc>>,
+    /// Text every matching prompt contains, ignoring case.
+    pub prompt_contains: Vec<String>,
+    /// Most entries returned.
+    pub limit: usize,
+}
+
+/// Where an entry's image went, as [`History::saved_files`] lists it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedFile {
+    /// Row ID, for [`History::delete`].
+    pub id: i64,
+    /// When the image was saved.
+    pub created_at: DateTime<Utc>,
+    /// File the image was written to.
+    pub output_path: PathBuf,
+}
+
+/// The history tables of the store.
+pub struct History {
+    store: Store,
+}
+
+impl History {
+    /// Open the history in the store at `path`, creating its tables if needed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store cannot be opened or migrated.
+    pub fn open(path: &Path) -> Result<Self, ImageError> {
+        let mut store = Store::open(path)?;
+        store.migrate("history", HISTORY_SCHEMA)?;
+        Ok(Self { store })
+    }
+
+    /// Append `entries` in one transaction.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store cannot be written.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an entry's parameters fail to serialize, which a map of strings cannot.
+    pub fn record(&mut self, entries: &[Entry]) -> Result<(), ImageError> {
+        self.store.write(|tx| {
+            for entry in entries {
+                tx.execute(
+                    "INSERT INTO history (created_at, request_id, operation, provider, model,
+                         prompt, parameters, output_path, sha256, cost_usd)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                    rusqlite::params![
+                        timestamp(entry.created_at),
+                        entry.request_id,
+                        entry.operation,
+                        entry.provider,
+                        entry.model,
+                        entry.prompt,
+                        serde_json::to_string(&entry.parameters).expect("strings serialize"),
+                        entry.output_path.to_string_lossy(),
+                        entry.sha256,
+                        entry.cost_usd,
+                    ],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Entries matching `filter`, newest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store cannot be read or holds a malformed row.
+    pub fn query(&self, filter: &Filter) -> Result<Vec<Entry>, ImageError> {
+        let mut sql = String::from(
+            "SELECT created_at, request_id, operation, provider, model, prompt, parameters,
+                    output_path, sha256, cost_usd
+             FROM history WHERE 1 = 1",
+        );
+        let mut params = Vec::new();
+        if let Some(request_id) = &filter.request_id {
+            sql.push_str(" AND request_id = ?");
+            params.push(Value::Text(request_id.clone()));
+        }
+        if let Some(model) = &filter.model {
+            sql.push_str(" AND model = ?");
+            params.push(Value::Text(model.clone()));
+        }
+        if let Some(since) = filter.since {
+            sql.push_str(" AND created_at >= ?");
+            params.push(Value::Text(timestamp(since)));
+        }
+        for text in &filter.prompt_contains {
+            sql.push_str(" AND instr(lower(prompt), lower(?)) > 0");
+            params.push(Value::Text(text.clone()));
+        }
+        sql.push_str(" ORDER BY created_at DESC, id DESC LIMIT ?");
+        params.push(Value::Integer(i64::try_from(filter.limit).unwrap_or(i64::MAX)));
+        self.store.read(|conn| {
+            let mut statement = conn.prepare(&sql)?;
+            let rows = statement.query_map(rusqlite::params_from_iter(params), entry)?;
+            rows.collect()
+        })
+    }
+
+    /// The file of every entry, oldest first.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store cannot be read or holds a malformed row.
+    pub fn saved_files(&self) -> Result<Vec<SavedFile>, ImageError> {
+        self.store.read(|conn| {
+            let mut statement = conn.prepare(
+                "SELECT id, created_at, output_path FROM history ORDER BY created_at, id",
+            )?;
+            let rows = statement.query_map([], |row| {
+                let created_at: String = row.get(1)?;
+                let output_path: String = row.get(2)?;
+                Ok(SavedFile {
+                    id: row.get(0)?,
+                    created_at: DateTime::parse_from_rfc3339(&created_at)
+                        .map_err(|e| {
+                            rusqlite::Error::FromSqlConversionFailure(1, Type::Text, Box::new(e))
+                        })?
+                        .with_timezone(&Utc),
+                    output_path: PathBuf::from(output_path),
+                })
+            })?;
+            rows.collect()
+        })
+    }
+
+    /// Remove the entries with these row IDs in one transaction, returning how many
+    /// there were.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the store cannot be written.
+    pub fn delete(&mut self, ids: &[i64]) -> Result<usize, ImageError> {
+        self.store.write(|tx| {
+            let mut statement = tx.prepare("DELETE FROM history WHERE id = ?1")?;
+            ids.iter().map(|id| statement.execute([id])).sum()
+        })
+    }
+}
+
+/// Fixed-width UTC timestamp, so the text sorts and compares in time order.
+fn timestamp(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// Read an [`Entry`] from a row of [`History::query`].
+fn entry(row: &Row<'_>) -> rusqlite::Result<Entry> {
+    let invalid = |column, e| rusqlite::Error::FromSqlConversionFailure(column, Type::Text, e);
+    let created_at: String = row.get(0)?;
+    let parameters: String = row.get(6)?;
+    let output_path: String = row.get(7)?;
+    Ok(Entry {
+        created_at: DateTime::parse_from_rfc3339(&created_at)
+            .map_err(|e| invalid(0, Box::new(e)))?
+            .with_timezone(&Utc),
+        request_id: row.get(1)?,
+        operation: row.get(2)?,
+        provider: row.get(3)?,
+        model: row.get(4)?,
+        prompt: row.get(5)?,
+        parameters: serde_json::from_str(&parameters).map_err(|e| invalid(6, Box::new(e)))?,
+        output_path: PathBuf::from(output_path),
+        sha256: row.get(8)?,
+        cost_usd: row.get(9)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(prompt: &str, model: &str, days_ago: i64) -> Entry {
+        Entry {
+            created_at: Utc::now() - chrono::TimeDelta::days(days_ago),
+            request_id: "3f2a9c1e7b4d5a60".into(),
+            operation: "generate".into(),
+            provider: "openai".into(),
+            model: model.into(),
+            prompt: prompt.into(),
+            parameters: BTreeMap::from([("size".to_string(), "1K".to_string())]),
+            output_path: PathBuf::from(format!("/tmp/{days_ago}.png")),
+            sha256: "ab".repeat(32),
+            cost_usd: Some(0.167),
+        }
+    }
+
+    #[test]
+    fn query_filters_by_model_time_and_prompt() {
+        let dir = std::env::temp_dir().join(format!("imagen_history_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("imagen.db");
+        let entries = [
+            entry("A castle at dusk", "gpt-image-1", 10),
+            entry("a castle at dawn", "gpt-image-1", 1),
+            entry("a castle in fog", "gemini-3-pro-image-preview", 2),
+            entry("a cat", "gpt-image-1", 0),
+        ];
+        History::open(&path).unwrap().record(&entries).unwrap();
+        // Reopening does not migrate twice.
+        let history = History::open(&path).unwrap();
+
+        let all = history.query(&Filter { limit: 10, ..Filter::default() }).unwrap();
+        let prompts: Vec<&str> = all.iter().map(|entry| entry.prompt.as_str()).collect();
+        assert_eq!(prompts, ["a cat", "a castle at dawn", "a castle in fog", "A castle at dusk"]);
+        assert_eq!(all[0].parameters, entries[3].parameters);
+        let castles = history
+            .query(&Filter {
+                request_id: None,
+                model: Some("gpt-image-1".into()),
+                since: Some(Utc::now() - chrono::TimeDelta::days(7)),
+                prompt_contains: vec!["CASTLE".into()],
+                limit: 10,
+            })
+            .unwrap();
+        assert_eq!(castles.len(), 1);
+        assert_eq!(castles[0].prompt, "a castle at dawn");
+        assert_eq!(history.query(&Filter { limit: 1, ..Filter::default() }).unwrap().len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
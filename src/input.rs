@@ -0,0 +1,344 @@
+//! Loading `--input` and `--ref` images from disk.
+//!
+//! Phone cameras store photos in sensor orientation and record the intended rotation in
+//! an EXIF tag that providers ignore, so edits of a portrait photo would come back
+//! sideways. Images carrying a non-trivial orientation are rotated upright here and
+//! re-encoded; all others are sent byte for byte.
+//!
+//! Images larger than the provider accepts are shrunk to fit by [`fit_input_images`]
+//! before they are sent, rather than being rejected by the API after the upload.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Cursor;
+use std::path::Path;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::imageops::FilterType;
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader};
+
+use crate::error::ImageError;
+use crate::model::Provider;
+use crate::params::{input_limit, mime_type_from_extension, InputLimit};
+use crate::ports::InputImage;
+
+/// JPEG quality used when a JPEG photo has to be re-encoded (rotated or downscaled).
+const REENCODE_JPEG_QUALITY: u8 = 95;
+
+/// Factor by which a downscaled image that is still too many bytes shrinks again.
+const BYTES_SHRINK_STEP: f64 = 0.75;
+
+/// Read input image files from disk into `InputImage` structs, keeping their order and roles.
+///
+/// # Errors
+///
+/// Returns an error if a file cannot be read, has an unsupported extension, or carries an
+/// orientation tag but cannot be decoded.
+pub fn read_input_images(
+    specs: &[(Option<String>, String)],
+) -> Result<Vec<InputImage>, ImageError> {
+    specs.iter().map(|(role, path)| read_input_image(role.clone(), path)).collect()
+}
+
+fn read_input_image(role: Option<String>, path: &str) -> Result<InputImage, ImageError> {
+    let data = std::fs::read(path).map_err(ImageError::Io)?;
+    let mime_type =
+        mime_type_from_extension(path).map_err(ImageError::InvalidArgument)?.to_string();
+    let filename = Path::new(path).file_name().unwrap_or_default().to_string_lossy().to_string();
+    let (data, mime_type) = normalize_orientation(data, mime_type)
+        .map_err(|e| ImageError::ImageConversion(format!("{path}: {e}")))?;
+    Ok(InputImage { data, mime_type, filename, role })
+}
+
+/// Rotate an image upright according to its EXIF orientation. Images without the tag
+/// (or that cannot be inspected) are returned unchanged; rotated JPEGs stay JPEG and
+/// everything else becomes PNG.
+fn normalize_orientation(data: Vec<u8>, mime_type: String) -> Result<(Vec<u8>, String), String> {
+    let Some(upright) = upright_image(&data)? else {
+        return Ok((data, mime_type));
+    };
+    encode(&upright, &mime_type)
+}
+
+/// Re-encode a processed input image: JPEG stays JPEG, everything else becomes PNG.
+fn encode(image: &DynamicImage, mime_type: &str) -> Result<(Vec<u8>, String), String> {
+    let mut out = Cursor::new(Vec::new());
+    let jpeg = mime_type == "image/jpeg";
+    if jpeg {
+        let encoder = JpegEncoder::new_with_quality(&mut out, REENCODE_JPEG_QUALITY);
+        DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)
+    } else {
+        image.write_to(&mut out, image::ImageFormat::Png)
+    }
+    .map_err(|e| format!("Failed to encode image: {e}"))?;
+    Ok((out.into_inner(), if jpeg { "image/jpeg" } else { "image/png" }.to_string()))
+}
+
+/// The decoded, upright image if `data` has an orientation other than "as stored".
+fn upright_image(data: &[u8]) -> Result<Option<DynamicImage>, String> {
+    let decoder = ImageReader::new(Cursor::new(data))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok());
+    let Some(mut decoder) = decoder else { return Ok(None) };
+    let orientation = decoder.orientation().unwrap_or(Orientation::NoTransforms);
+    if orientation == Orientation::NoTransforms {
+        return Ok(None);
+    }
+    let mut image = DynamicImage::from_decoder(decoder)
+        .map_err(|e| format!("Failed to decode image to apply its EXIF orientation: {e}"))?;
+    image.apply_orientation(orientation);
+    Ok(Some(image))
+}
+
+/// Shrink images that exceed `provider`'s input limits, keeping their aspect ratio, with a
+/// notice for each.
+///
+/// Images of the same size shrink together, by one scale at which every one of them
+/// fits, so images that must stay aligned (such as a photo and its mask) still line up
+/// even when only one of them is over the limit.
+///
+/// # Errors
+///
+/// Returns an error naming the image and the limit when an image is too large and
+/// `auto_downscale` is off, or when an oversized image cannot be decoded.
+pub fn fit_input_images(
+    images: Vec<InputImage>,
+    provider: Provider,
+    auto_downscale: bool,
+) -> Result<Vec<InputImage>, ImageError> {
+    fit_to_limit(images, input_limit(provider), auto_downscale)
+        .map_err(|e| ImageError::InvalidArgument(format!("{e} for {provider:?}")))
+}
+
+/// [`fit_input_images`] against `limit`; errors name the image but not the provider.
+fn fit_to_limit(
+    mut images: Vec<InputImage>,
+    limit: InputLimit,
+    auto_downscale: bool,
+) -> Result<Vec<InputImage>, String> {
+    let mut groups: BTreeMap<(u32, u32), Vec<usize>> = BTreeMap::new();
+    let mut oversized = BTreeSet::new();
+    for (index, image) in images.iter().enumerate() {
+        let Some((width, height)) = dimensions(&image.data) else { continue };
+        groups.entry((width, height)).or_default().push(index);
+        let pixels = u64::from(width) * u64::from(height);
+        let too_many_pixels = limit.max_pixels.is_some_and(|max| pixels > max);
+        let too_many_bytes = limit.max_bytes.is_some_and(|max| image.data.len() > max);
+        if !too_many_pixels && !too_many_bytes {
+            continue;
+        }
+        if !auto_downscale {
+            return Err(format!(
+                "{}: image is {width}x{height}, {}, over the {} limit; shrink it or drop \
+                 --no-auto-downscale",
+                image.filename,
+                megabytes(image.data.len()),
+                describe(limit)
+            ));
+        }
+        oversized.insert((width, height));
+    }
+    for size in oversized {
+        let members = &groups[&size];
+        let group: Vec<&InputImage> = members.iter().map(|&index| &images[index]).collect();
+        let fitted = shrink_together(&group, size, limit)?;
+        for (&index, image) in members.iter().zip(fitted) {
+            images[index] = image;
+        }
+    }
+    Ok(images)
+}
+
+/// Shrink `images`, all `width`x`height`, by the largest shared scale at which each fits
+/// `limit`, with a notice for each.
+#[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn shrink_together(
+    images: &[&InputImage],
+    (width, height): (u32, u32),
+    limit: InputLimit,
+) -> Result<Vec<InputImage>, String> {
+    let decoded = images
+        .iter()
+        .map(|image| {
+            image::load_from_memory(&image.data)
+                .map_err(|e| format!("{}: Failed to decode oversized image: {e}", image.filename))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let pixels = u64::from(width) * u64::from(height);
+    // Dimensions and scale factors stay far below 2^52, so the float math is exact enough.
+    let mut scale = limit
+        .max_pixels
+        .filter(|&max| pixels > max)
+        .map_or(1.0, |max| (max as f64 / pixels as f64).sqrt());
+    loop {
+        let size = |side: u32| ((f64::from(side) * scale).floor() as u32).max(1);
+        let (new_width, new_height) = (size(width), size(height));
+        let encoded = images
+            .iter()
+            .zip(&decoded)
+            .map(|(image, decoded)| {
+                let resized = decoded.resize_exact(new_width, new_height, FilterType::Lanczos3);
+                encode(&resized, &image.mime_type).map_err(|e| format!("{}: {e}", image.filename))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let fits =
+            encoded.iter().all(|(data, _)| limit.max_bytes.is_none_or(|max| data.len() <= max));
+        if fits || (new_width, new_height) == (1, 1) {
+            let fitted = images.iter().zip(encoded).map(|(image, (data, mime_type))| {
+                tracing::info!(
+                    "Notice: downscaled {} from {width}x{height}, {} to {new_width}x{new_height}, \
+                     {} (limit: {})",
+                    image.filename,
+                    megabytes(image.data.len()),
+                    megabytes(data.len()),
+                    describe(limit)
+                );
+                InputImage { data, mime_type, ..(*image).clone() }
+            });
+            return Ok(fitted.collect());
+        }
+        scale *= BYTES_SHRINK_STEP;
+    }
+}
+
+/// Width and height read from the image header, without decoding the pixels.
+fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    ImageReader::new(Cursor::new(data)).with_guessed_format().ok()?.into_dimensions().ok()
+}
+
+#[allow(clippy::cast_precision_loss)] // Display only.
+fn megabytes(bytes: usize) -> String {
+    format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// `9.4 MP and 10.0 MB`.
+#[allow(clippy::cast_precision_loss)] // Display only.
+fn describe(limit: InputLimit) -> String {
+    let pixels = limit.max_pixels.map(|max| format!("{:.1} MP", max as f64 / 1e6));
+    let bytes = limit.max_bytes.map(megabytes);
+    pixels.into_iter().chain(bytes).collect::<Vec<_>>().join(" and ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{GenericImageView, Rgb, RgbImage};
+
+    /// A 4x2 JPEG whose EXIF tag says to rotate it 90° clockwise for display
+    /// (orientation 6, as a phone held upright records it).
+    fn rotated_jpeg() -> Vec<u8> {
+        let mut jpeg = Cursor::new(Vec::new());
+        RgbImage::from_fn(4, 2, |x, _| if x < 2 { Rgb([255, 0, 0]) } else { Rgb([0, 0, 255]) })
+            .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+            .unwrap();
+        let jpeg = jpeg.into_inner();
+        #[rustfmt::skip]
+        let tiff: &[u8] = &[
+            b'M', b'M', 0, 42, 0, 0, 0, 8, // big-endian header, IFD at offset 8
+            0, 1, // one entry
+            0x01, 0x12, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0, // Orientation (SHORT) = 6
+            0, 0, 0, 0, // no further IFDs
+        ];
+        let payload = [b"Exif\0\0", tiff].concat();
+        let length = u16::try_from(payload.len() + 2).unwrap().to_be_bytes();
+        [&jpeg[..2], &[0xFF, 0xE1], &length, &payload, &jpeg[2..]].concat()
+    }
+
+    #[test]
+    fn rotates_photos_upright() {
+        let (data, mime_type) = normalize_orientation(rotated_jpeg(), "image/jpeg".into()).unwrap();
+        assert_eq!(mime_type, "image/jpeg");
+        let image = image::load_from_memory(&data).unwrap();
+        assert_eq!(image.dimensions(), (2, 4));
+        // The left (red) half of the stored image ends up on top.
+        assert!(image.get_pixel(0, 0)[0] > 200);
+        assert!(image.get_pixel(0, 3)[2] > 200);
+    }
+
+    fn png_input(width: u32, height: u32) -> InputImage {
+        let mut png = Cursor::new(Vec::new());
+        RgbImage::from_fn(width, height, |x, y| {
+            Rgb([u8::try_from(x % 256).unwrap(), u8::try_from(y % 256).unwrap(), 0])
+        })
+        .write_to(&mut png, image::ImageFormat::Png)
+        .unwrap();
+        InputImage {
+            data: png.into_inner(),
+            mime_type: "image/png".into(),
+            filename: "photo.png".into(),
+            role: None,
+        }
+    }
+
+    #[test]
+    fn oversized_images_shrink_to_the_pixel_limit() {
+        let limit = InputLimit { max_pixels: Some(100 * 50), max_bytes: None };
+        let fitted = fit_to_limit(vec![png_input(400, 200)], limit, true).unwrap();
+        let size = image::load_from_memory(&fitted[0].data).unwrap().dimensions();
+        assert_eq!(size, (100, 50));
+        assert_eq!(fitted[0].filename, "photo.png");
+
+        let small = png_input(40, 20);
+        assert_eq!(fit_to_limit(vec![small.clone()], limit, true).unwrap()[0].data, small.data);
+    }
+
+    #[test]
+    fn oversized_images_shrink_to_the_byte_limit() {
+        let limit = InputLimit { max_pixels: None, max_bytes: Some(2_000) };
+        let fitted = fit_to_limit(vec![png_input(200, 200)], limit, true).unwrap();
+        assert!(fitted[0].data.len() <= 2_000, "got {} bytes", fitted[0].data.len());
+    }
+
+    #[test]
+    fn images_of_one_size_shrink_together() {
+        let png = |image: RgbImage| {
+            let mut png = Cursor::new(Vec::new());
+            image.write_to(&mut png, image::ImageFormat::Png).unwrap();
+            png.into_inner()
+        };
+        // Noise barely compresses; a flat mask of the same size is tiny.
+        let noise = RgbImage::from_fn(200, 200, |x, y| {
+            let n = (x * 7919 + y * 104_729).wrapping_mul(2_654_435_761) >> 8;
+            Rgb([(n & 0xFF) as u8, (n >> 8 & 0xFF) as u8, (n >> 16 & 0xFF) as u8])
+        });
+        let photo = InputImage { data: png(noise), ..png_input(200, 200) };
+        let mask = InputImage {
+            data: png(RgbImage::new(200, 200)),
+            filename: "mask.png".into(),
+            ..png_input(200, 200)
+        };
+        let limit = InputLimit { max_pixels: None, max_bytes: Some(20_000) };
+        assert!(photo.data.len() > 20_000 && mask.data.len() <= 20_000);
+
+        // Only the photo is over the limit, but the mask shrinks with it to stay aligned.
+        let fitted = fit_to_limit(vec![photo, mask, png_input(20, 20)], limit, true);
+        let sizes: Vec<_> = fitted
+            .unwrap()
+            .iter()
+            .map(|image| image::load_from_memory(&image.data).unwrap().dimensions())
+            .collect();
+        assert!(sizes[0].0 < 200, "got {sizes:?}");
+        assert_eq!(sizes[1], sizes[0]);
+        assert_eq!(sizes[2], (20, 20));
+    }
+
+    #[test]
+    fn oversized_images_fail_without_auto_downscale() {
+        let limit = InputLimit { max_pixels: Some(1_000), max_bytes: None };
+        let err = fit_to_limit(vec![png_input(400, 200)], limit, false).unwrap_err();
+        assert!(err.starts_with("photo.png: image is 400x200"), "got: {err}");
+        assert!(err.contains("over the 0.0 MP limit"), "got: {err}");
+        assert!(err.contains("--no-auto-downscale"), "got: {err}");
+    }
+
+    #[test]
+    fn untagged_images_pass_through_unchanged() {
+        let mut png = Cursor::new(Vec::new());
+        RgbImage::new(4, 2).write_to(&mut png, image::ImageFormat::Png).unwrap();
+        let png = png.into_inner();
+        assert_eq!(normalize_orientation(png.clone(), "image/png".into()).unwrap().0, png);
+        let garbage = b"not an image".to_vec();
+        assert_eq!(normalize_orientation(garbage.clone(), "image/png".into()).unwrap().0, garbage);
+    }
+}
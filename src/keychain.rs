@@ -0,0 +1,78 @@
+//! API keys in the OS keychain (macOS Keychain, Windows Credential Manager, or the
+//! Secret Service on Linux), stored by `imagen config set-key`.
+//!
+//! Keys are saved under the service `imagen`, with the provider name (`gemini`,
+//! `openai`, ...) as the account. A keychain that is missing, locked, or unreachable
+//! reads as holding no keys. `IMAGEN_NO_KEYRING` skips the keychain entirely, for CI
+//! and other headless machines.
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+use crate::error::ImageError;
+
+/// Service name the keys are stored under.
+const SERVICE: &str = "imagen";
+
+/// Keys read so far this run, so each provider costs one keychain lookup.
+fn cache() -> &'static Mutex<HashMap<String, Option<String>>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Option<String>>>> = OnceLock::new();
+    CACHE.get_or_init(Mutex::default)
+}
+
+/// Whether `IMAGEN_NO_KEYRING` turns the keychain off.
+fn disabled() -> bool {
+    std::env::var_os("IMAGEN_NO_KEYRING").is_some_and(|value| !value.is_empty())
+}
+
+/// The key stored for `provider`, if any.
+#[must_use]
+pub fn get(provider: &str) -> Option<String> {
+    if disabled() {
+        return None;
+    }
+    let mut cache = cache().lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+    cache
+        .entry(provider.to_string())
+        .or_insert_with(|| {
+            keyring::Entry::new(SERVICE, provider)
+                .and_then(|entry| entry.get_password())
+                .ok()
+                .filter(|key| !key.is_empty())
+        })
+        .clone()
+}
+
+/// Store `key` for `provider`, replacing any key stored before.
+///
+/// # Errors
+///
+/// Returns an error if the keychain is unavailable or refuses the write.
+pub fn set(provider: &str, key: &str) -> Result<(), ImageError> {
+    keyring::Entry::new(SERVICE, provider)
+        .and_then(|entry| entry.set_password(key))
+        .map_err(|e| unavailable(&e))?;
+    cache().lock().unwrap_or_else(std::sync::PoisonError::into_inner).remove(provider);
+    Ok(())
+}
+
+/// Remove the key stored for `provider`; returns whether there was one.
+///
+/// # Errors
+///
+/// Returns an error if the keychain is unavailable or refuses the change.
+pub fn delete(provider: &str) -> Result<bool, ImageError> {
+    let deleted =
+        match keyring::Entry::new(SERVICE, provider).and_then(|entry| entry.delete_credential()) {
+            Ok(()) => true,
+            Err(keyring::Error::NoEntry) => false,
+            Err(e) => return Err(unavailable(&e)),
+        };
+    cache().lock().unwrap_or_else(std::sync::PoisonError::into_inner).remove(provider);
+    Ok(deleted)
+}
+
+/// A keychain failure, as a config error.
+fn unavailable(e: &keyring::Error) -> ImageError {
+    ImageError::Config(format!("OS keychain unavailable: {e}"))
+}
@@ -0,0 +1,43 @@
+//! imagen - AI image generation through one interface for Gemini, `OpenAI`, Stability AI,
+//! Replicate, AWS Bedrock, Together AI, and Ideogram.
+//!
+//! The `imagen` command line tool is built on this library, which other programs can use
+//! to generate images without shelling out. Each provider is an
+//! [`ImageGenerator`](ports::ImageGenerator) in [`adapters::live`];
+//! [`ServiceContext`](context::ServiceContext) builds the right one for a model, with
+//! retries, from a [`Config`](config::Config). [`model`] resolves model names and aliases
+//! to their provider, [`params`] checks settings against what each provider accepts, and
+//! [`cassette`] records and replays provider interactions for tests. [`generate`] runs
+//! the command line's whole pipeline for parsed [`cli`] arguments, and [`commands`]
+//! its subcommands.
+
+pub mod adapters;
+pub mod cache;
+pub mod cassette;
+pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod contact_sheet;
+pub mod context;
+pub mod error;
+pub mod events;
+pub mod generate;
+pub mod history;
+pub mod input;
+pub mod keychain;
+pub mod logging;
+pub mod manifest;
+pub mod metadata;
+pub mod model;
+pub mod output;
+pub mod params;
+pub mod pipeline;
+pub mod ports;
+pub mod post;
+pub mod preview;
+pub mod pricing;
+pub mod progress;
+pub mod report;
+pub mod run_log;
+pub mod store;
+pub mod watermark;
@@ -0,0 +1,143 @@
+//! Diagnostics on stderr, through `tracing`.
+//!
+//! The default text format reads like the messages imagen has always printed:
+//! `Warning: …` and `Error: …` prefixes, plain lines otherwise. `-v` adds debug detail,
+//! `-vv` adds trace events and how long each span (HTTP calls, conversion, cassette IO)
+//! took, and `-vvv` lets through the events of the libraries underneath. With
+//! `--log-format json`, each event is one JSON object per line instead.
+
+use std::fmt;
+use std::io::{self, Write as _};
+
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::{FmtSpan, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormattedFields, MakeWriter};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::{LookupSpan, Scope};
+
+use crate::progress;
+
+/// Install the stderr subscriber for `verbosity` (the number of `-v` flags), writing
+/// `format` (`text` or `json`).
+pub fn init(verbosity: u8, format: &str) {
+    let level = match verbosity {
+        0 => Level::INFO,
+        1 => Level::DEBUG,
+        _ => Level::TRACE,
+    };
+    let mut filter = Targets::new().with_target(env!("CARGO_CRATE_NAME"), level);
+    if verbosity >= 3 {
+        filter = filter.with_default(Level::TRACE);
+    }
+    let spans = if verbosity >= 2 { FmtSpan::CLOSE } else { FmtSpan::NONE };
+    let builder = tracing_subscriber::fmt()
+        .with_writer(Stderr)
+        .with_max_level(Level::TRACE)
+        .with_span_events(spans);
+    // A subscriber may already be set (tests); the first one wins.
+    let _ = match format {
+        "json" => builder.json().finish().with(filter).try_init(),
+        _ => builder.event_format(Text).finish().with(filter).try_init(),
+    };
+}
+
+/// Stderr, written an event at a time with any progress bars moved out of the way.
+struct Stderr;
+
+impl MakeWriter<'_> for Stderr {
+    type Writer = Buffered;
+
+    fn make_writer(&self) -> Buffered {
+        Buffered(Vec::new())
+    }
+}
+
+/// One event's output, written out when dropped.
+struct Buffered(Vec<u8>);
+
+impl io::Write for Buffered {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Buffered {
+    fn drop(&mut self) {
+        let _ = progress::suspend(|| io::stderr().write_all(&self.0));
+    }
+}
+
+/// The text format: the message, prefixed by severity for warnings and errors. Event
+/// fields and enclosing spans are only shown with `-v`.
+struct Text;
+
+impl<S, N> tracing_subscriber::fmt::FormatEvent<S, N> for Text
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> tracing_subscriber::fmt::FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let level = *event.metadata().level();
+        let detailed = level >= Level::DEBUG;
+        match level {
+            Level::ERROR => write!(writer, "Error: ")?,
+            Level::WARN => write!(writer, "Warning: ")?,
+            _ => {}
+        }
+        if detailed {
+            for span in ctx.event_scope().into_iter().flat_map(Scope::from_root) {
+                write!(writer, "{}", span.name())?;
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                    if !fields.is_empty() {
+                        write!(writer, "{{{fields}}}")?;
+                    }
+                }
+                write!(writer, ": ")?;
+            }
+        }
+        let mut fields = Fields { message: String::new(), rest: String::new() };
+        event.record(&mut fields);
+        write!(writer, "{}", fields.message)?;
+        if detailed {
+            write!(writer, "{}", fields.rest)?;
+        }
+        writeln!(writer)
+    }
+}
+
+/// An event's message, and its other fields as ` name=value` pairs.
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message.push_str(value);
+        } else {
+            self.record_debug(field, &format_args!("{value}"));
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        use fmt::Write as _;
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.rest, " {}={value:?}", field.name());
+        }
+    }
+}
@@ -1,209 +1,54 @@
 //! Imagen - AI image generation CLI.
 
-mod adapters;
-mod cassette;
-mod cli;
-mod config;
-mod context;
-mod error;
-mod model;
-mod output;
-mod params;
-mod ports;
-
-use std::path::Path;
+use std::io::IsTerminal as _;
 use std::process;
 
 use clap::Parser;
-
-use crate::cli::Cli;
-use crate::config::{Config, DefaultsConfig};
-use crate::context::ServiceContext;
-use crate::model::{detect_provider, resolve_model};
-use crate::output::{resolve_output_path, save_image};
-use crate::params::{
-    mime_type_from_extension, validate_aspect_ratio, validate_background, validate_format,
-    validate_input_paths, validate_quality, validate_size, validate_thinking,
-};
-use crate::ports::{ImageRequest, InputImage};
+use imagen::cli::{Cli, Command};
+use imagen::context::Operation;
+use imagen::error::ImageError;
+use imagen::{commands, events, generate, logging, progress, report};
 
 #[tokio::main]
 async fn main() {
     let cli = Cli::parse();
-
-    if let Err(e) = run(cli).await {
-        eprintln!("Error: {e}");
-        process::exit(1);
-    }
-}
-
-async fn run(cli: Cli) -> Result<(), error::ImageError> {
-    // Load config
-    let config_path = config::discover_config_path(cli.config.as_deref());
-    let config = Config::load(&config_path).map_err(error::ImageError::Config)?;
-
-    // Apply config-file defaults for any CLI flags still at their built-in defaults.
-    let cli_defaults = DefaultsConfig::default();
-    let effective_model = apply_defaults(&cli.model, &cli_defaults.model, &config.defaults.model);
-    let effective_aspect_ratio = apply_defaults(
-        &cli.aspect_ratio,
-        &cli_defaults.aspect_ratio,
-        &config.defaults.aspect_ratio,
-    );
-    let effective_size = apply_defaults(&cli.size, &cli_defaults.size, &config.defaults.size);
-    let effective_quality =
-        apply_defaults(&cli.quality, &cli_defaults.quality, &config.defaults.quality);
-    let effective_format =
-        apply_defaults(&cli.format, &cli_defaults.format, &config.defaults.format);
-
-    // Resolve prompt
-    let prompt = cli.resolve_prompt().map_err(error::ImageError::Io)?;
-
-    // Resolve model and provider
-    let resolved_model = resolve_model(&effective_model);
-    let provider = detect_provider(&resolved_model).map_err(error::ImageError::InvalidArgument)?;
-
-    if cli.verbose {
-        eprintln!("Model: {resolved_model} (resolved from '{effective_model}')");
-        eprintln!("Provider: {provider:?}");
-    }
-
-    // Validate parameters
-    validate_aspect_ratio(&effective_aspect_ratio, provider)
-        .map_err(error::ImageError::InvalidArgument)?;
-    validate_size(&effective_size).map_err(error::ImageError::InvalidArgument)?;
-    validate_quality(&effective_quality).map_err(error::ImageError::InvalidArgument)?;
-    validate_format(&effective_format).map_err(error::ImageError::InvalidArgument)?;
-    if let Some(ref thinking) = cli.thinking {
-        validate_thinking(thinking, provider).map_err(error::ImageError::InvalidArgument)?;
-    }
-    if let Some(ref bg) = cli.background {
-        validate_background(bg, &effective_format, provider)
-            .map_err(error::ImageError::InvalidArgument)?;
+    let args = match cli.command {
+        Some(Command::Edit(ref args)) => Some(&**args),
+        Some(Command::Batch(ref batch)) => Some(&batch.args),
+        Some(Command::Compare(ref compare)) => Some(&compare.args),
+        Some(_) => None,
+        None => Some(&cli.args),
+    };
+    let (json, ndjson) = args.map_or((false, false), |args| (args.json, args.events.is_some()));
+    let (verbosity, log_format) = args.map_or((0, "text"), |a| (a.verbose, a.log_format.as_str()));
+    logging::init(verbosity, log_format);
+    if ndjson {
+        events::enable();
     }
-    if !cli.input.is_empty() {
-        validate_input_paths(&cli.input).map_err(error::ImageError::InvalidArgument)?;
+    let terminal = std::io::stdout().is_terminal() && std::io::stderr().is_terminal();
+    if args.is_some() && terminal && !json && !ndjson && log_format == "text" {
+        progress::enable();
     }
 
-    // Read input images from disk
-    let input_images = read_input_images(&cli.input)?;
-
-    // Build request
-    let request = ImageRequest {
-        model: resolved_model,
-        prompt: prompt.clone(),
-        aspect_ratio: effective_aspect_ratio.clone(),
-        size: effective_size.clone(),
-        quality: effective_quality.clone(),
-        format: effective_format.clone(),
-        count: cli.count,
-        thinking: cli.thinking.clone(),
-        input_images,
-        background: cli.background.clone(),
-    };
-
-    // Create context based on mode (live / recording / replaying)
-    let replay_path = std::env::var("IMAGEN_REPLAY").ok();
-    let record_val = std::env::var("IMAGEN_RECORD").ok();
-    let cassette_path = record_val.as_deref().and_then(|v| match v {
-        "true" | "1" => None,
-        path => Some(std::path::PathBuf::from(path)),
-    });
-
-    let (ctx, recording_session) = if let Some(ref cassette_path) = replay_path {
-        if cli.verbose {
-            eprintln!("Replaying from: {cassette_path}");
-        }
-        (ServiceContext::replaying(Path::new(cassette_path))?, None)
-    } else if record_val.is_some() {
-        if cli.verbose {
-            eprintln!("Recording mode enabled");
-        }
-        let (ctx, session) =
-            ServiceContext::recording(provider, &config, cassette_path.as_deref())?;
-        (ctx, Some(session))
-    } else {
-        (ServiceContext::live(provider, &config)?, None)
-    };
-
-    // Generate
-    let result = ctx.generator.generate(&request).await;
-
-    // Drop the context to release the Arc reference before finishing the recording
-    drop(ctx);
-
-    // Always finish recording, even if generation failed
-    if let Some(session) = recording_session {
-        match session.finish() {
-            Ok(path) => eprintln!("Cassette saved: {}", path.display()),
-            Err(e) => eprintln!("Warning: failed to save cassette: {e}"),
-        }
+    let result = run(cli).await;
+    if json {
+        report::print(&result);
     }
-
-    let response = result?;
-
-    // Save images
-    for (i, image) in response.images.iter().enumerate() {
-        let suffix = if response.images.len() > 1 { format!("-{}", i + 1) } else { String::new() };
-
-        let base_path = resolve_output_path(cli.output.as_deref(), &prompt, &effective_format);
-        let output_path = if suffix.is_empty() {
-            base_path
-        } else {
-            let stem = base_path.file_stem().unwrap_or_default().to_string_lossy();
-            let ext = base_path.extension().unwrap_or_default().to_string_lossy();
-            base_path.with_file_name(format!("{stem}{suffix}.{ext}"))
-        };
-
-        save_image(&image.data, &image.mime_type, &effective_format, &output_path)?;
-        eprintln!("Saved: {}", output_path.display());
+    if ndjson {
+        events::finish(&result);
     }
-
-    Ok(())
-}
-
-/// Read input image files from disk into `InputImage` structs.
-fn read_input_images(paths: &[String]) -> Result<Vec<InputImage>, error::ImageError> {
-    paths
-        .iter()
-        .map(|path| {
-            let data = std::fs::read(path).map_err(error::ImageError::Io)?;
-            let mime_type = mime_type_from_extension(path)
-                .map_err(error::ImageError::InvalidArgument)?
-                .to_string();
-            let filename = Path::new(path)
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
-            Ok(InputImage { data, mime_type, filename })
-        })
-        .collect()
-}
-
-/// Returns `cli_val` if it differs from `cli_default` (the user explicitly passed the flag),
-/// otherwise returns `config_val` (from the config-file defaults section).
-fn apply_defaults(cli_val: &str, cli_default: &str, config_val: &str) -> String {
-    if cli_val == cli_default {
-        config_val.to_string()
-    } else {
-        cli_val.to_string()
+    if let Err(e) = result {
+        tracing::error!("{e}");
+        process::exit(e.exit_code());
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn config_default_overrides_cli_default() {
-        // When the CLI value is still "nano-banana" (the built-in default), the config default wins.
-        assert_eq!(apply_defaults("nano-banana", "nano-banana", "gpt-1"), "gpt-1");
-    }
-
-    #[test]
-    fn explicit_cli_flag_overrides_config_default() {
-        // When the user explicitly sets a different model, that value wins.
-        assert_eq!(apply_defaults("dall-e", "nano-banana", "gpt-1"), "dall-e");
+async fn run(cli: Cli) -> Result<(), ImageError> {
+    match cli.command {
+        Some(Command::Edit(ref args)) => generate::run(args, Operation::Edit).await,
+        Some(Command::Batch(ref batch)) => generate::run_batch(batch).await,
+        Some(Command::Compare(ref compare)) => generate::run_compare(compare).await,
+        Some(ref command) => commands::run(command).await,
+        None => generate::run(&cli.args, Operation::Generate).await,
     }
 }
@@ -0,0 +1,290 @@
+//! Batch manifests: the jobs `imagen batch` runs, one per line or row.
+//!
+//! ```text
+//! {"prompt": "a knight sprite", "model": "flux-schnell", "count": 4, "seed": 7}
+//! {"prompt": "a castle tileset", "aspect_ratio": "16:9", "output": "tiles/castle.png"}
+//! ```
+//!
+//! A `.jsonl` file holds one JSON object per line and a `.csv` file one row per job under
+//! a header row. Each job names a `prompt` and may override `model`, `aspect_ratio`,
+//! `count`, `output`, and `seed`; empty CSV cells keep the command-line value. Any other
+//! file is plain text with one prompt per line. Blank lines, and `#` comments outside
+//! CSV files, are skipped.
+//!
+//! With `--resume`, a [`BatchState`] file records each request whose images were all
+//! saved, so an interrupted batch can be run again without paying for them twice.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// One prompt of a batch and its per-job overrides.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Job {
+    /// Text prompt.
+    pub prompt: String,
+    /// Model name or alias [default: `--model`].
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Aspect ratio, or a comma list to sweep [default: `--aspect-ratio`].
+    #[serde(default)]
+    pub aspect_ratio: Option<String>,
+    /// Number of images [default: `--count`].
+    #[serde(default)]
+    pub count: Option<u32>,
+    /// Output file path [default: named after the prompt].
+    #[serde(default)]
+    pub output: Option<String>,
+    /// Seed [default: `--seed`].
+    #[serde(default)]
+    pub seed: Option<u32>,
+}
+
+impl Job {
+    /// A job with no overrides.
+    #[must_use]
+    pub fn prompt(prompt: String) -> Self {
+        Self { prompt, ..Self::default() }
+    }
+}
+
+/// Parse the jobs of a batch file, choosing the format from `file`'s extension.
+///
+/// # Errors
+///
+/// Returns an error naming the line or row of an invalid job, or if there are no jobs.
+pub fn parse(file: &str, text: &str) -> Result<Vec<Job>, String> {
+    let extension = Path::new(file).extension().and_then(|ext| ext.to_str());
+    let jobs = match extension.map(str::to_ascii_lowercase).as_deref() {
+        Some("jsonl") => parse_jsonl(text)?,
+        Some("csv") => parse_csv(text)?,
+        _ => lines(text).map(|(_, line)| Job::prompt(line.to_string())).collect(),
+    };
+    if jobs.is_empty() {
+        return Err("No prompts in the file".to_string());
+    }
+    for (index, job) in jobs.iter().enumerate() {
+        if job.prompt.trim().is_empty() {
+            return Err(format!("Job {} has an empty prompt", index + 1));
+        }
+        if job.count == Some(0) {
+            return Err(format!("Job {}: count must be at least 1", index + 1));
+        }
+    }
+    Ok(jobs)
+}
+
+/// Non-blank, non-comment lines with their 1-based line numbers, trimmed.
+fn lines(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+}
+
+fn parse_jsonl(text: &str) -> Result<Vec<Job>, String> {
+    lines(text)
+        .map(|(number, line)| serde_json::from_str(line).map_err(|e| format!("Line {number}: {e}")))
+        .collect()
+}
+
+fn parse_csv(text: &str) -> Result<Vec<Job>, String> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(text.as_bytes());
+    reader
+        .deserialize()
+        .map(|row| {
+            row.map_err(|e: csv::Error| match e.position() {
+                Some(position) => format!("Line {}: {e}", position.line()),
+                None => e.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Finished requests of a batch, kept in the `--resume` state file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BatchState {
+    /// Requests whose images were all saved, in the order they finished.
+    pub finished: Vec<FinishedRequest>,
+}
+
+/// A request of a batch that completed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FinishedRequest {
+    /// Request ID: a hash of the prompt and every setting.
+    pub request_id: String,
+    /// Prompt, so the file can be read at a glance.
+    pub prompt: String,
+    /// Files the request wrote, in order.
+    pub outputs: Vec<SavedFile>,
+}
+
+/// An output file and the SHA-256 of its contents when it was written.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SavedFile {
+    /// Path as it was written.
+    pub path: PathBuf,
+    /// Lowercase hex SHA-256.
+    pub sha256: String,
+}
+
+impl BatchState {
+    /// Load the state file at `path`; a missing file starts afresh.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file exists but cannot be read or parsed, rather than
+    /// silently running (and paying for) the whole batch again.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|e| format!("{}: {e}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {e}", path.display())),
+        }
+    }
+
+    /// Write the state to `path`, replacing the previous file only once the new one is
+    /// complete, so a crash mid-write keeps the old state.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the state fails to serialize, which its plain fields rule out.
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).expect("batch state is serializable");
+        let mut partial = path.as_os_str().to_owned();
+        partial.push(".tmp");
+        std::fs::write(&partial, json)?;
+        std::fs::rename(&partial, path)
+    }
+
+    /// Record that `request_id` saved every image, to `files`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be read to hash it.
+    pub fn finish(
+        &mut self,
+        request_id: String,
+        prompt: &str,
+        files: &[PathBuf],
+    ) -> std::io::Result<()> {
+        let outputs = files
+            .iter()
+            .map(|path| Ok(SavedFile { path: path.clone(), sha256: file_sha256(path)? }))
+            .collect::<std::io::Result<_>>()?;
+        self.finished.push(FinishedRequest { request_id, prompt: prompt.to_string(), outputs });
+        Ok(())
+    }
+
+    /// Which of `request_ids` already finished: each recorded request whose files are all
+    /// still on disk, unchanged, accounts for one request with its ID.
+    #[must_use]
+    pub fn finished(&self, request_ids: &[String]) -> Vec<bool> {
+        let mut intact: Vec<&FinishedRequest> =
+            self.finished.iter().filter(|request| request.intact()).collect();
+        request_ids
+            .iter()
+            .map(|id| match intact.iter().position(|request| &request.request_id == id) {
+                Some(index) => {
+                    intact.remove(index);
+                    true
+                }
+                None => false,
+            })
+            .collect()
+    }
+}
+
+impl FinishedRequest {
+    /// Whether every output is still on disk with the contents it was written with.
+    fn intact(&self) -> bool {
+        self.outputs
+            .iter()
+            .all(|file| file_sha256(&file.path).is_ok_and(|sha256| sha256 == file.sha256))
+    }
+}
+
+/// Lowercase hex SHA-256 of the file at `path`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read.
+pub fn file_sha256(path: &Path) -> std::io::Result<String> {
+    Ok(format!("{:x}", Sha256::digest(std::fs::read(path)?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_text_has_one_prompt_per_line() {
+        let jobs = parse("prompts.txt", "a cat\n\n# skipped\n  a dog on a log  \n").unwrap();
+        assert_eq!(jobs, [Job::prompt("a cat".into()), Job::prompt("a dog on a log".into())]);
+        assert!(parse("prompts.txt", "# nothing yet\n").is_err());
+    }
+
+    #[test]
+    fn jsonl_rows_override_settings() {
+        let text = r#"{"prompt": "a knight", "model": "flux-schnell", "count": 4, "seed": 7}
+# castles next
+{"prompt": "a castle", "aspect_ratio": "16:9", "output": "tiles/castle.png"}
+"#;
+        let jobs = parse("jobs.jsonl", text).unwrap();
+        assert_eq!(jobs[0].model.as_deref(), Some("flux-schnell"));
+        assert_eq!((jobs[0].count, jobs[0].seed), (Some(4), Some(7)));
+        assert_eq!(jobs[1].aspect_ratio.as_deref(), Some("16:9"));
+        assert_eq!(jobs[1].output.as_deref(), Some("tiles/castle.png"));
+
+        let err = parse("jobs.jsonl", "{\"prompt\": \"a cat\", \"ratio\": \"1:1\"}").unwrap_err();
+        assert!(err.starts_with("Line 1:") && err.contains("ratio"), "got: {err}");
+    }
+
+    #[test]
+    fn csv_rows_leave_empty_cells_unset() {
+        let text = "prompt,model,count,seed\n\"a knight, mounted\",flux-schnell,4,7\na castle,,,\n";
+        let jobs = parse("jobs.CSV", text).unwrap();
+        assert_eq!(jobs[0].prompt, "a knight, mounted");
+        assert_eq!((jobs[0].count, jobs[0].seed), (Some(4), Some(7)));
+        assert_eq!(jobs[1], Job::prompt("a castle".into()));
+
+        let err = parse("jobs.csv", "prompt,count\na cat,many\n").unwrap_err();
+        assert!(err.starts_with("Line 2:"), "got: {err}");
+        assert!(parse("jobs.csv", "prompt,count\na cat,0\n").unwrap_err().contains("at least 1"));
+    }
+
+    #[test]
+    fn state_skips_requests_whose_files_are_intact() {
+        let dir = std::env::temp_dir().join("imagen_batch_state_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let (cat, dog) = (dir.join("cat.png"), dir.join("dog.png"));
+        std::fs::write(&cat, b"cat").unwrap();
+        std::fs::write(&dog, b"dog").unwrap();
+
+        let path = dir.join("state.json");
+        assert!(BatchState::load(&path).unwrap().finished.is_empty());
+        let mut state = BatchState::default();
+        state.finish("aaaa".into(), "a cat", &[cat]).unwrap();
+        state.finish("bbbb".into(), "a dog", std::slice::from_ref(&dog)).unwrap();
+        state.save(&path).unwrap();
+
+        let state = BatchState::load(&path).unwrap();
+        let ids = ["aaaa", "aaaa", "bbbb", "cccc"].map(String::from);
+        // The same request twice is only done once; unknown IDs still run.
+        assert_eq!(state.finished(&ids), [true, false, true, false]);
+        // An output that was edited since no longer counts.
+        std::fs::write(&dog, b"a different dog").unwrap();
+        assert_eq!(state.finished(&ids), [true, false, false, false]);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(BatchState::load(&path).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
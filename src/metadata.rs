@@ -0,0 +1,362 @@
+//! Generation parameters saved with images, so other tools can read them back.
+//!
+//! PNG files get a `parameters` text chunk in the layout AUTOMATIC1111's web UI writes,
+//! which sd-webui's "PNG Info", `ComfyUI`, and most image browsers parse: the prompt, then
+//! one line of `Key: value` pairs. With `--sidecar`, every image also gets a JSON file
+//! next to it with the full request and what the provider said about it.
+//!
+//! Converted PNGs without an ICC profile also get an `sRGB` chunk, so color-managed
+//! viewers don't have to guess.
+
+use std::fmt::Write as _;
+use std::path::Path;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine as _;
+use chrono::{DateTime, Utc};
+use png::text_metadata::{EncodableTextChunk, ITXtChunk, TEXtChunk};
+use png::SrgbRenderingIntent;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+use crate::error::ImageError;
+use crate::output::sidecar_path;
+use crate::ports::image_generator::{ImageRequest, ImageResponse, Usage};
+
+/// Keyword of the text chunk sd-webui and `ComfyUI` read parameters from.
+const PARAMETERS_KEYWORD: &str = "parameters";
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Add a `parameters` chunk describing image `index` of `request` to the PNG at `path`.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read or written, or is not a PNG.
+pub fn embed_png_parameters(
+    path: &Path,
+    request: &ImageRequest,
+    index: usize,
+) -> Result<(), ImageError> {
+    let png = std::fs::read(path)?;
+    let size = png_size(&png).ok_or_else(|| {
+        ImageError::ImageConversion(format!("{} is not a PNG file", path.display()))
+    })?;
+    let seed = u32::try_from(index).ok().and_then(|index| request.image_seed(index));
+    let text = parameters(request, seed, size);
+    let tagged = with_text_chunk(&png, PARAMETERS_KEYWORD, &text)
+        .map_err(|e| ImageError::ImageConversion(format!("PNG metadata: {e}")))?;
+    std::fs::write(path, tagged)?;
+    Ok(())
+}
+
+/// What a sidecar file records about one saved image.
+#[derive(Debug, Serialize)]
+pub struct Sidecar<'a> {
+    /// File name of the image the sidecar describes.
+    pub file: String,
+    /// 1-based number of the image within its request.
+    pub image: usize,
+    /// The request ID.
+    pub request_id: String,
+    /// The resolved model.
+    pub model: &'a str,
+    /// The model's provider, lowercase.
+    pub provider: String,
+    /// The image's seed, when the request set one.
+    pub seed: Option<u32>,
+    /// The request as sent, with input image bytes replaced by their SHA-256.
+    pub request: Value,
+    /// Estimated price of the image in USD, when the model's price is known.
+    pub cost_usd: Option<f64>,
+    /// When the run started and the image was saved.
+    pub timings: Timings,
+    /// What the provider returned alongside the image.
+    pub response: ResponseMetadata<'a>,
+    /// The imagen version that wrote the sidecar.
+    pub version: &'static str,
+}
+
+/// When an image was made.
+#[derive(Debug, Serialize)]
+pub struct Timings {
+    /// When the run started.
+    pub started_at: DateTime<Utc>,
+    /// When the image was written.
+    pub saved_at: DateTime<Utc>,
+    /// Milliseconds from the start of the run to the image being written.
+    pub elapsed_ms: i64,
+}
+
+/// The provider's response, minus the image itself.
+#[derive(Debug, Serialize)]
+pub struct ResponseMetadata<'a> {
+    /// MIME type the provider delivered the image as.
+    pub mime_type: &'a str,
+    /// Where the image was downloaded from, when it was delivered by URL.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<&'a str>,
+    /// Tokens billed for the whole response, when the provider reports them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+    /// Images of the response that the provider failed or refused.
+    pub failed_images: usize,
+}
+
+impl<'a> Sidecar<'a> {
+    /// The sidecar for image `index` (0-based) of `response`, the answer to `request`,
+    /// saved as `path` in a run that started at `started_at`. The provider and cost are
+    /// left for the caller to fill in.
+    #[must_use]
+    pub fn new(
+        path: &Path,
+        request: &'a ImageRequest,
+        response: &'a ImageResponse,
+        index: usize,
+        started_at: DateTime<Utc>,
+    ) -> Self {
+        let image = &response.images[index];
+        let saved_at = Utc::now();
+        Self {
+            file: path.file_name().unwrap_or_default().to_string_lossy().into_owned(),
+            image: index + 1,
+            request_id: request.request_id(),
+            model: &request.model,
+            provider: String::new(),
+            seed: u32::try_from(index).ok().and_then(|index| request.image_seed(index)),
+            request: request_without_bytes(request),
+            cost_usd: None,
+            timings: Timings {
+                started_at,
+                saved_at,
+                elapsed_ms: (saved_at - started_at).num_milliseconds(),
+            },
+            response: ResponseMetadata {
+                mime_type: &image.mime_type,
+                url: image.url.as_deref(),
+                usage: response.usage,
+                failed_images: response.failures.len(),
+            },
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+
+    /// Write the sidecar next to the image at `path`, as `<path>.json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write(&self, path: &Path) -> Result<(), ImageError> {
+        let mut json = serde_json::to_vec_pretty(self).map_err(std::io::Error::from)?;
+        json.push(b'\n');
+        std::fs::write(sidecar_path(path), json)?;
+        Ok(())
+    }
+}
+
+/// `request` as JSON, with the bytes of its input, reference, and history images
+/// replaced by their `sha256`.
+fn request_without_bytes(request: &ImageRequest) -> Value {
+    fn strip(value: &mut Value, key: &str) {
+        let Some(Value::Array(images)) = value.get_mut(key) else { return };
+        for image in images.iter_mut().filter_map(Value::as_object_mut) {
+            let Some(Value::String(data)) = image.remove("data") else { continue };
+            if let Ok(bytes) = STANDARD.decode(data) {
+                image.insert("sha256".to_string(), format!("{:x}", Sha256::digest(bytes)).into());
+            }
+        }
+    }
+    let mut value = serde_json::to_value(request).expect("ImageRequest is always serializable");
+    strip(&mut value, "input_images");
+    strip(&mut value, "reference_images");
+    if let Some(Value::Array(turns)) = value.get_mut("history") {
+        for turn in turns {
+            strip(turn, "input_images");
+            strip(turn, "images");
+        }
+    }
+    value
+}
+
+/// The `parameters` text: the prompt, then `Seed`, `Size`, `Model`, and imagen's own
+/// settings as `Key: value` pairs.
+fn parameters(request: &ImageRequest, seed: Option<u32>, (width, height): (u32, u32)) -> String {
+    let mut pairs = Vec::new();
+    if let Some(seed) = seed {
+        pairs.push(("Seed", seed.to_string()));
+    }
+    pairs.push(("Size", format!("{width}x{height}")));
+    pairs.push(("Model", request.model.clone()));
+    pairs.push(("Aspect ratio", request.aspect_ratio.clone()));
+    pairs.push(("Quality", request.quality.clone()));
+    pairs.push(("Version", format!("imagen {}", env!("CARGO_PKG_VERSION"))));
+
+    let mut text = request.prompt.trim().to_string();
+    text.push('\n');
+    for (i, (key, value)) in pairs.iter().enumerate() {
+        let separator = if i == 0 { "" } else { ", " };
+        let _ = write!(text, "{separator}{key}: {}", quote(value));
+    }
+    text
+}
+
+/// `value` as sd-webui writes it: bare, or JSON-quoted when it holds a comma, colon, or
+/// newline that would break the line apart.
+fn quote(value: &str) -> String {
+    if value.contains([',', ':', '\n']) {
+        serde_json::Value::from(value).to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Width and height from the `IHDR` chunk, or `None` if `png` is not a PNG.
+fn png_size(png: &[u8]) -> Option<(u32, u32)> {
+    if !png.starts_with(PNG_SIGNATURE) || png.get(12..16)? != b"IHDR" {
+        return None;
+    }
+    let width = u32::from_be_bytes(png.get(16..20)?.try_into().ok()?);
+    let height = u32::from_be_bytes(png.get(20..24)?.try_into().ok()?);
+    Some((width, height))
+}
+
+/// `png` with a text chunk added right after `IHDR`: `tEXt` when the text fits in
+/// Latin-1, as the PNG spec requires, and UTF-8 `iTXt` otherwise.
+fn with_text_chunk(png: &[u8], keyword: &str, text: &str) -> Result<Vec<u8>, String> {
+    let mut chunk = Vec::new();
+    let encoded = if text.chars().all(|c| u32::from(c) <= 0xff) {
+        TEXtChunk::new(keyword, text).encode(&mut chunk)
+    } else {
+        ITXtChunk::new(keyword, text).encode(&mut chunk)
+    };
+    encoded.map_err(|e| e.to_string())?;
+    with_chunk(png, &chunk)
+}
+
+/// `png` tagged as sRGB by an `sRGB` chunk, for a PNG without an ICC profile.
+///
+/// # Errors
+///
+/// Returns an error if `png` is truncated.
+pub fn with_srgb_chunk(png: &[u8]) -> Result<Vec<u8>, String> {
+    let mut chunk = Vec::new();
+    SrgbRenderingIntent::Perceptual.encode(&mut chunk).map_err(|e| e.to_string())?;
+    with_chunk(png, &chunk)
+}
+
+/// `png` with the encoded `chunk` added right after `IHDR`, ahead of the image data.
+fn with_chunk(png: &[u8], chunk: &[u8]) -> Result<Vec<u8>, String> {
+    // Signature, then IHDR: length, type, 13 bytes of data, CRC.
+    let ihdr_end = PNG_SIGNATURE.len() + 4 + 4 + 13 + 4;
+    if png.len() < ihdr_end {
+        return Err("truncated PNG".to_string());
+    }
+    let mut tagged = Vec::with_capacity(png.len() + chunk.len());
+    tagged.extend_from_slice(&png[..ihdr_end]);
+    tagged.extend_from_slice(chunk);
+    tagged.extend_from_slice(&png[ihdr_end..]);
+    Ok(tagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> ImageRequest {
+        ImageRequest {
+            aspect_ratio: "16:9".into(),
+            quality: "high".into(),
+            format: "png".into(),
+            ..ImageRequest::sample("gpt-image-1.5", "a cat, on a mat\n")
+        }
+    }
+
+    fn encode_png() -> Vec<u8> {
+        let mut png = Vec::new();
+        image::DynamicImage::new_rgb8(3, 2)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    /// Keyword and text of each chunk.
+    type Chunks = Vec<(String, String)>;
+
+    /// The `tEXt` and `iTXt` chunks of `png`.
+    fn read_text(png: &[u8]) -> (Chunks, Chunks) {
+        let decoder = png::Decoder::new(std::io::Cursor::new(png));
+        let reader = decoder.read_info().unwrap();
+        let info = reader.info();
+        let latin1 =
+            info.uncompressed_latin1_text.iter().map(|c| (c.keyword.clone(), c.text.clone()));
+        let utf8 = info.utf8_text.iter().map(|c| (c.keyword.clone(), c.get_text().unwrap()));
+        (latin1.collect(), utf8.collect())
+    }
+
+    #[test]
+    fn parameters_follow_the_sd_webui_layout() {
+        let text = parameters(&request(), Some(7), (1536, 1024));
+        assert_eq!(
+            text,
+            format!(
+                "a cat, on a mat\nSeed: 7, Size: 1536x1024, Model: gpt-image-1.5, \
+                 Aspect ratio: \"16:9\", Quality: high, Version: imagen {}",
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+        assert!(!parameters(&request(), None, (1, 1)).contains("Seed"));
+    }
+
+    #[test]
+    fn text_chunk_round_trips_through_a_decoder() {
+        let png = encode_png();
+        assert_eq!(png_size(&png), Some((3, 2)));
+
+        let tagged = with_text_chunk(&png, PARAMETERS_KEYWORD, "a café\nSeed: 1").unwrap();
+        let (latin1, utf8) = read_text(&tagged);
+        assert_eq!(latin1, [("parameters".to_string(), "a café\nSeed: 1".to_string())]);
+        assert!(utf8.is_empty());
+        assert_eq!(image::load_from_memory(&tagged).unwrap().width(), 3);
+
+        let tagged = with_text_chunk(&png, PARAMETERS_KEYWORD, "猫").unwrap();
+        let (latin1, utf8) = read_text(&tagged);
+        assert!(latin1.is_empty());
+        assert_eq!(utf8, [("parameters".to_string(), "猫".to_string())]);
+    }
+
+    #[test]
+    fn sidecar_replaces_image_bytes_with_hashes() {
+        let mut request = request();
+        request.input_images.push(
+            serde_json::from_value(serde_json::json!({
+                "data": "aGVsbG8=", "mime_type": "image/png", "filename": "in.png",
+            }))
+            .unwrap(),
+        );
+        let response: ImageResponse = serde_json::from_value(serde_json::json!({
+            "images": [{"data": "", "mime_type": "image/png", "url": "https://cdn/x.png"}],
+            "failures": [{"index": 1, "reason": "blocked"}],
+        }))
+        .unwrap();
+
+        let sidecar = Sidecar::new(Path::new("out/cat.png"), &request, &response, 0, Utc::now());
+        let json = serde_json::to_value(&sidecar).unwrap();
+        assert_eq!(json["file"], "cat.png");
+        assert_eq!(json["request_id"], request.request_id());
+        assert_eq!(json["response"]["url"], "https://cdn/x.png");
+        assert_eq!(json["response"]["failed_images"], 1);
+        let input = &json["request"]["input_images"][0];
+        assert!(input.get("data").is_none());
+        // SHA-256 of "hello".
+        assert_eq!(
+            input["sha256"],
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+    }
+
+    #[test]
+    fn non_png_is_rejected() {
+        assert_eq!(png_size(b"\xff\xd8\xff\xe0 not a png"), None);
+    }
+}
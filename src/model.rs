@@ -7,6 +7,88 @@ pub enum Provider {
     Gemini,
     /// `OpenAI` API.
     OpenAi,
+    /// Azure `OpenAI` deployment of a `gpt-image` model (`azure/<deployment>`).
+    AzureOpenAi,
+    /// Stability AI Stable Image API.
+    Stability,
+    /// Replicate predictions API (`replicate/<owner>/<name>`).
+    Replicate,
+    /// Self-hosted Stable Diffusion web UI (Automatic1111 API).
+    SdLocal,
+    /// AWS Bedrock (Titan Image Generator, Nova Canvas, Stability models).
+    Bedrock,
+    /// Together AI images API (Flux models, `black-forest-labs/*`).
+    Together,
+    /// Ideogram API (strong at rendering text in images).
+    Ideogram,
+}
+
+/// Popular models by name, for building an
+/// [`ImageRequest`](crate::ports::ImageRequest) without spelling out model IDs. Any
+/// other model is still accepted as a string.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Model {
+    /// Gemini 3.1 Flash Image (`nano-banana`).
+    NanoBanana,
+    /// Gemini 3 Pro Image (`nano-banana-pro`).
+    NanoBananaPro,
+    /// `OpenAI` `gpt-image-1.5`.
+    GptImage15,
+    /// `OpenAI` `gpt-image-1`.
+    GptImage1,
+    /// `OpenAI` `gpt-image-1-mini`.
+    GptImage1Mini,
+    /// Stability AI SD 3.5 Large.
+    Sd35,
+    /// Stability AI Stable Image Ultra.
+    StableImageUltra,
+    /// Amazon Titan Image Generator v2, on Bedrock.
+    TitanImage,
+    /// Amazon Nova Canvas, on Bedrock.
+    NovaCanvas,
+    /// FLUX.1 [schnell], on Together AI.
+    FluxSchnell,
+    /// FLUX.1 [dev], on Together AI.
+    FluxDev,
+    /// FLUX1.1 [pro], on Together AI.
+    FluxPro,
+    /// Ideogram 3.0.
+    Ideogram,
+}
+
+impl Model {
+    /// The model's short name, as `--model` takes it.
+    #[must_use]
+    pub const fn alias(self) -> &'static str {
+        match self {
+            Self::NanoBanana => "nano-banana",
+            Self::NanoBananaPro => "nano-banana-pro",
+            Self::GptImage15 => "gpt-1.5",
+            Self::GptImage1 => "gpt-1",
+            Self::GptImage1Mini => "gpt-1-mini",
+            Self::Sd35 => "sd3.5",
+            Self::StableImageUltra => "stable-image-ultra",
+            Self::TitanImage => "titan-image",
+            Self::NovaCanvas => "nova-canvas",
+            Self::FluxSchnell => "flux-schnell",
+            Self::FluxDev => "flux-dev",
+            Self::FluxPro => "flux-pro",
+            Self::Ideogram => "ideogram",
+        }
+    }
+
+    /// The full model identifier sent to the provider.
+    #[must_use]
+    pub fn id(self) -> String {
+        resolve_model(self.alias())
+    }
+}
+
+impl From<Model> for String {
+    fn from(model: Model) -> Self {
+        model.id()
+    }
 }
 
 /// Short name aliases for popular models.
@@ -16,8 +98,60 @@ const ALIASES: &[(&str, &str)] = &[
     ("gpt-1.5", "gpt-image-1.5"),
     ("gpt-1", "gpt-image-1"),
     ("gpt-1-mini", "gpt-image-1-mini"),
+    ("sd3.5", "sd3.5-large"),
+    ("stable-image-ultra", "stable-image-ultra"),
+    ("titan-image", "amazon.titan-image-generator-v2:0"),
+    ("nova-canvas", "amazon.nova-canvas-v1:0"),
+    ("flux", "black-forest-labs/FLUX.1-schnell"),
+    ("flux-schnell", "black-forest-labs/FLUX.1-schnell"),
+    ("flux-dev", "black-forest-labs/FLUX.1-dev"),
+    ("flux-pro", "black-forest-labs/FLUX.1.1-pro"),
+    ("ideogram", "ideogram-v3"),
+];
+
+/// Representative model for each provider name (the cheapest/fastest of each family),
+/// used where a command takes a provider rather than a model.
+const PROVIDER_MODELS: &[(&str, &str)] = &[
+    ("gemini", "nano-banana"),
+    ("openai", "gpt-1-mini"),
+    ("azure", "azure"),
+    ("stability", "stable-image-core"),
+    ("replicate", "replicate/black-forest-labs/flux-schnell"),
+    ("sd-local", "sd-local"),
+    ("bedrock", "titan-image"),
+    ("together", "flux-schnell"),
+    ("ideogram", "ideogram"),
 ];
 
+/// Resolve a provider name (`gemini`, `openai`, ...) to its representative model.
+///
+/// # Errors
+///
+/// Returns an error listing the valid names if `name` is not a known provider.
+pub fn provider_model(name: &str) -> Result<String, String> {
+    PROVIDER_MODELS
+        .iter()
+        .find(|(provider, _)| *provider == name)
+        .map(|(_, model)| resolve_model(model))
+        .ok_or_else(|| {
+            let known: Vec<&str> = PROVIDER_MODELS.iter().map(|(p, _)| *p).collect();
+            format!("Unknown provider '{name}'. Valid: {}", known.join(", "))
+        })
+}
+
+/// Model names `imagen models` lists: every alias, then each provider's representative
+/// model that is not one.
+#[must_use]
+pub fn known_models() -> Vec<&'static str> {
+    let mut names: Vec<&str> = ALIASES.iter().map(|(alias, _)| *alias).collect();
+    for (_, model) in PROVIDER_MODELS {
+        if !names.contains(model) {
+            names.push(model);
+        }
+    }
+    names
+}
+
 /// Resolve a model name (alias or exact) to the full model identifier.
 #[must_use]
 pub fn resolve_model(name: &str) -> String {
@@ -39,8 +173,30 @@ pub fn detect_provider(model: &str) -> Result<Provider, String> {
         Ok(Provider::Gemini)
     } else if model.starts_with("gpt-image") {
         Ok(Provider::OpenAi)
+    } else if model.starts_with("azure/") || model == "azure" {
+        Ok(Provider::AzureOpenAi)
+    } else if model.starts_with("sd3") || model.starts_with("stable-image") {
+        Ok(Provider::Stability)
+    } else if model.starts_with("replicate/") {
+        Ok(Provider::Replicate)
+    } else if model == "sd-local" {
+        Ok(Provider::SdLocal)
+    } else if model.starts_with("amazon.titan-image")
+        || model.starts_with("amazon.nova-canvas")
+        || model.starts_with("stability.")
+    {
+        Ok(Provider::Bedrock)
+    } else if model.starts_with("black-forest-labs/") {
+        Ok(Provider::Together)
+    } else if model.starts_with("ideogram") {
+        Ok(Provider::Ideogram)
     } else {
-        Err(format!("Unknown provider for model '{model}'. Expected 'gemini-*' or 'gpt-image-*'."))
+        Err(format!(
+            "Unknown provider for model '{model}'. Expected 'gemini-*', 'gpt-image-*', \
+             'azure/<deployment>', 'sd3*', 'stable-image-*', 'replicate/<owner>/<name>', 'sd-local', \
+             'amazon.titan-image-*', 'amazon.nova-canvas-*', 'stability.*', \
+             'black-forest-labs/*', or 'ideogram-*'."
+        ))
     }
 }
 
@@ -48,6 +204,30 @@ pub fn detect_provider(model: &str) -> Result<Provider, String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn named_models_are_aliases_of_known_providers() {
+        let models = [
+            Model::NanoBanana,
+            Model::NanoBananaPro,
+            Model::GptImage15,
+            Model::GptImage1,
+            Model::GptImage1Mini,
+            Model::Sd35,
+            Model::StableImageUltra,
+            Model::TitanImage,
+            Model::NovaCanvas,
+            Model::FluxSchnell,
+            Model::FluxDev,
+            Model::FluxPro,
+            Model::Ideogram,
+        ];
+        for model in models {
+            assert!(ALIASES.iter().any(|(alias, _)| *alias == model.alias()), "{model:?}");
+            assert!(detect_provider(&model.id()).is_ok(), "{model:?}");
+        }
+        assert_eq!(String::from(Model::NanoBanana), "gemini-3.1-flash-image-preview");
+    }
+
     #[test]
     fn resolve_nano_banana() {
         assert_eq!(resolve_model("nano-banana"), "gemini-3.1-flash-image-preview");
@@ -83,6 +263,64 @@ mod tests {
         assert_eq!(detect_provider("gpt-image-1-mini").unwrap(), Provider::OpenAi);
     }
 
+    #[test]
+    fn detect_azure_openai_provider() {
+        assert_eq!(detect_provider("azure/gpt-image-prod").unwrap(), Provider::AzureOpenAi);
+        assert_eq!(detect_provider("azure").unwrap(), Provider::AzureOpenAi);
+    }
+
+    #[test]
+    fn resolve_stability_aliases() {
+        assert_eq!(resolve_model("sd3.5"), "sd3.5-large");
+        assert_eq!(resolve_model("stable-image-ultra"), "stable-image-ultra");
+    }
+
+    #[test]
+    fn detect_stability_provider() {
+        assert_eq!(detect_provider("sd3.5-large").unwrap(), Provider::Stability);
+        assert_eq!(detect_provider("sd3.5-medium").unwrap(), Provider::Stability);
+        assert_eq!(detect_provider("stable-image-ultra").unwrap(), Provider::Stability);
+        assert_eq!(detect_provider("stable-image-core").unwrap(), Provider::Stability);
+    }
+
+    #[test]
+    fn detect_replicate_provider() {
+        assert_eq!(
+            detect_provider("replicate/black-forest-labs/flux-schnell").unwrap(),
+            Provider::Replicate
+        );
+    }
+
+    #[test]
+    fn detect_sd_local_provider() {
+        assert_eq!(detect_provider("sd-local").unwrap(), Provider::SdLocal);
+    }
+
+    #[test]
+    fn detect_bedrock_provider() {
+        assert_eq!(resolve_model("titan-image"), "amazon.titan-image-generator-v2:0");
+        assert_eq!(detect_provider("amazon.titan-image-generator-v1").unwrap(), Provider::Bedrock);
+        assert_eq!(detect_provider("amazon.nova-canvas-v1:0").unwrap(), Provider::Bedrock);
+        assert_eq!(detect_provider("stability.sd3-5-large-v1:0").unwrap(), Provider::Bedrock);
+    }
+
+    #[test]
+    fn detect_together_provider() {
+        assert_eq!(resolve_model("flux"), "black-forest-labs/FLUX.1-schnell");
+        assert_eq!(resolve_model("flux-pro"), "black-forest-labs/FLUX.1.1-pro");
+        assert_eq!(detect_provider("black-forest-labs/FLUX.1-dev").unwrap(), Provider::Together);
+        assert_eq!(
+            detect_provider("replicate/black-forest-labs/flux-schnell").unwrap(),
+            Provider::Replicate
+        );
+    }
+
+    #[test]
+    fn detect_ideogram_provider() {
+        assert_eq!(resolve_model("ideogram"), "ideogram-v3");
+        assert_eq!(detect_provider("ideogram-v3").unwrap(), Provider::Ideogram);
+    }
+
     #[test]
     fn detect_unknown_provider() {
         assert!(detect_provider("dall-e-3").is_err());
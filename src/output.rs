@@ -1,21 +1,363 @@
 //! File naming, image saving, and format conversion.
 
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::error::ImageError;
+use crate::metadata::with_srgb_chunk;
 use crate::params::format_extension;
+use crate::post::Step;
 
-/// Generate an output filename from a prompt and format.
+/// How auto-generated filenames are stemmed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlugStrategy {
+    /// Kebab-case prompt plus a unix timestamp (`a-cat-1740422400`).
+    #[default]
+    Prompt,
+    /// The request ID, a stable hash of the prompt and parameters (`3f2a9c0e1b7d4a65`).
+    Hash,
+    /// The next free four-digit counter in the output directory (`0007`).
+    Counter,
+    /// A freshly generated ULID (`01JN5R8V3W6YQ2T9C4K7HXGZ1M`).
+    Ulid,
+}
+
+impl SlugStrategy {
+    /// Parse a strategy name (`prompt`, `hash`, `counter`, `ulid`).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unknown strategy names.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "prompt" => Ok(Self::Prompt),
+            "hash" => Ok(Self::Hash),
+            "counter" => Ok(Self::Counter),
+            "ulid" => Ok(Self::Ulid),
+            other => {
+                Err(format!("Invalid slug strategy '{other}'. Valid: prompt, hash, counter, ulid"))
+            }
+        }
+    }
+}
+
+/// What to do when an output file already exists.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Existing {
+    /// Stop with [`ImageError::OutputExists`].
+    #[default]
+    Refuse,
+    /// Replace the file (`--force`).
+    Overwrite,
+    /// Save under the next free `-2`, `-3`, ... name (`--no-clobber=false`).
+    Rename,
+}
+
+/// How output files are named.
+#[derive(Debug, Clone, Default)]
+pub struct Naming {
+    /// Slug strategy for auto-generated filenames.
+    pub slug: SlugStrategy,
+    /// Per-format extension overrides (e.g., `jpeg` -> `jpeg` instead of `jpg`).
+    pub extensions: HashMap<String, String>,
+    /// Directory for auto-generated filenames (`--output-dir`); the current directory
+    /// when unset.
+    pub dir: Option<PathBuf>,
+    /// Template for auto-generated filenames, used instead of `slug` when set.
+    pub template: Option<NameTemplate>,
+    /// What to do when a file of that name already exists.
+    pub existing: Existing,
+}
+
+impl Naming {
+    /// File extension for `format`, honoring any configured override.
+    #[must_use]
+    pub fn extension<'a>(&'a self, format: &str) -> &'a str {
+        self.extensions
+            .get(format)
+            .map(|ext| ext.trim_start_matches('.'))
+            .filter(|ext| !ext.is_empty())
+            .unwrap_or_else(|| format_extension(format))
+    }
+}
+
+/// Generate an output filename using the given naming options.
 ///
-/// Sanitizes the first 50 characters of the prompt to kebab-case,
-/// appends a unix timestamp, and adds the appropriate file extension.
+/// `Prompt` sanitizes the first 50 characters of the prompt to kebab-case and
+/// appends a unix timestamp; `Hash` uses `request_id`; `Counter` picks the next
+/// number after any all-digit filenames already in `dir`; `Ulid` generates a new
+/// ULID. The format's extension (or its configured override) is added in every case.
 #[must_use]
-pub fn auto_filename(prompt: &str, format: &str) -> String {
-    let sanitized = sanitize_for_filename(prompt, 50);
-    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
-    let ext = format_extension(format);
-    format!("{sanitized}-{timestamp}.{ext}")
+pub fn auto_filename(
+    prompt: &str,
+    format: &str,
+    naming: &Naming,
+    request_id: &str,
+    dir: &Path,
+) -> String {
+    let slug = match naming.slug {
+        SlugStrategy::Prompt => {
+            let sanitized = sanitize_for_filename(prompt, 50);
+            let timestamp =
+                SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+            format!("{sanitized}-{timestamp}")
+        }
+        SlugStrategy::Hash => request_id.to_string(),
+        SlugStrategy::Counter => format!("{:04}", next_counter(dir)),
+        SlugStrategy::Ulid => ulid::Ulid::new().to_string(),
+    };
+    let ext = naming.extension(format);
+    format!("{slug}.{ext}")
+}
+
+/// One past the highest all-digit file stem in `dir` (1 for an empty directory).
+fn next_counter(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 1;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let stem = name.split(['.', '-']).next()?.to_string();
+            if stem.is_empty() || !stem.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            stem.parse::<u64>().ok()
+        })
+        .max()
+        .map_or(1, |n| n + 1)
+}
+
+/// Characters of the prompt in `{prompt}` when the template gives no length.
+const TEMPLATE_PROMPT_CHARS: usize = 50;
+
+/// A filename template (`--name-template`), e.g. `{date}/{model}/{prompt:40}-{seed}-{n}.{ext}`.
+///
+/// Placeholders are filled in per image; see [`NameTemplate::parse`] for the list. `{{` and
+/// `}}` stand for literal braces, and `/` separates directories, which are created on save.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameTemplate(Vec<Part>);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Part {
+    Text(String),
+    Field(Field),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Prompt(usize),
+    Model,
+    Provider,
+    Seed,
+    N,
+    Id,
+    Ratio,
+    Size,
+    Quality,
+    Date,
+    Time,
+    Timestamp,
+    Ulid,
+    Ext,
+}
+
+/// What one image's templated filename is filled in from.
+#[derive(Debug, Clone, Copy)]
+pub struct NameFields<'a> {
+    /// The request's prompt.
+    pub prompt: &'a str,
+    /// The resolved model name.
+    pub model: &'a str,
+    /// The model's provider, lowercase.
+    pub provider: &'a str,
+    /// The image's seed, when the request set one.
+    pub seed: Option<u32>,
+    /// 1-based number of the image within its request.
+    pub n: usize,
+    /// The request ID.
+    pub request_id: &'a str,
+    /// Aspect ratio, as `16:9`.
+    pub aspect_ratio: &'a str,
+    /// Size setting.
+    pub size: &'a str,
+    /// Quality setting.
+    pub quality: &'a str,
+}
+
+impl NameTemplate {
+    /// Parse a template. Placeholders:
+    ///
+    /// - `{prompt}`: the prompt in kebab-case, at most 50 characters (`{prompt:N}` for N)
+    /// - `{model}`, `{provider}`: the model and its provider
+    /// - `{seed}`: the image's seed, or `random` when none was set
+    /// - `{n}`: the image's number within its request, from 1
+    /// - `{id}`: the request ID
+    /// - `{ratio}`, `{size}`, `{quality}`: the request's parameters (`16x9` for `16:9`)
+    /// - `{date}`, `{time}`: local date and time (`2025-02-24`, `14-30-05`)
+    /// - `{timestamp}`: unix seconds; `{ulid}`: a fresh ULID
+    /// - `{ext}`: the file extension, appended as `.{ext}` when the template leaves it out
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unknown placeholders, unbalanced braces, or an empty template.
+    pub fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '{' | '}' if chars.next_if_eq(&ch).is_some() => text.push(ch),
+                '{' => {
+                    let mut name = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => name.push(c),
+                            None => {
+                                return Err(format!(
+                                    "Unclosed '{{{name}' in name template (write '{{{{' for a literal brace)"
+                                ))
+                            }
+                        }
+                    }
+                    if !text.is_empty() {
+                        parts.push(Part::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(Part::Field(parse_field(&name)?));
+                }
+                '}' => {
+                    return Err("Unmatched '}' in name template (write '}}' for a literal brace)"
+                        .to_string())
+                }
+                _ => text.push(ch),
+            }
+        }
+        if !text.is_empty() {
+            parts.push(Part::Text(text));
+        }
+        if parts.is_empty() {
+            return Err("Name template is empty".to_string());
+        }
+        Ok(Self(parts))
+    }
+
+    /// The path for the image described by `fields`, with extension `ext`.
+    #[must_use]
+    pub fn render(&self, fields: &NameFields<'_>, ext: &str) -> PathBuf {
+        let now = chrono::Local::now();
+        let mut name = String::new();
+        for part in &self.0 {
+            let value = match *part {
+                Part::Text(ref text) => {
+                    name.push_str(text);
+                    continue;
+                }
+                Part::Field(Field::Prompt(max)) => sanitize_for_filename(fields.prompt, max),
+                Part::Field(Field::Model) => path_safe(fields.model),
+                Part::Field(Field::Provider) => path_safe(fields.provider),
+                Part::Field(Field::Seed) => {
+                    fields.seed.map_or_else(|| "random".to_string(), |seed| seed.to_string())
+                }
+                Part::Field(Field::N) => fields.n.to_string(),
+                Part::Field(Field::Id) => path_safe(fields.request_id),
+                Part::Field(Field::Ratio) => path_safe(&fields.aspect_ratio.replace(':', "x")),
+                Part::Field(Field::Size) => path_safe(fields.size),
+                Part::Field(Field::Quality) => path_safe(fields.quality),
+                Part::Field(Field::Date) => now.format("%Y-%m-%d").to_string(),
+                Part::Field(Field::Time) => now.format("%H-%M-%S").to_string(),
+                Part::Field(Field::Timestamp) => now.timestamp().to_string(),
+                Part::Field(Field::Ulid) => ulid::Ulid::new().to_string(),
+                Part::Field(Field::Ext) => ext.to_string(),
+            };
+            name.push_str(&value);
+        }
+        if !self.has(Field::Ext) {
+            name.push('.');
+            name.push_str(ext);
+        }
+        PathBuf::from(name)
+    }
+
+    /// Whether the template numbers a request's images itself with `{n}`, so they need
+    /// no `-1`, `-2`, ... suffix.
+    #[must_use]
+    pub fn numbers_images(&self) -> bool {
+        self.has(Field::N)
+    }
+
+    /// Whether the template names the aspect ratio with `{ratio}`, so sweep legs need no
+    /// `-16x9` suffix.
+    #[must_use]
+    pub fn names_ratio(&self) -> bool {
+        self.has(Field::Ratio)
+    }
+
+    /// Whether the template names the model with `{model}`, so `imagen compare` needs no
+    /// model suffix.
+    #[must_use]
+    pub fn names_model(&self) -> bool {
+        self.has(Field::Model)
+    }
+
+    fn has(&self, field: Field) -> bool {
+        self.0.contains(&Part::Field(field))
+    }
+}
+
+fn parse_field(spec: &str) -> Result<Field, String> {
+    let (name, length) = match spec.split_once(':') {
+        Some((name, length)) => (name, Some(length)),
+        None => (spec, None),
+    };
+    let field = match name {
+        "prompt" => Field::Prompt(TEMPLATE_PROMPT_CHARS),
+        "model" => Field::Model,
+        "provider" => Field::Provider,
+        "seed" => Field::Seed,
+        "n" => Field::N,
+        "id" => Field::Id,
+        "ratio" => Field::Ratio,
+        "size" => Field::Size,
+        "quality" => Field::Quality,
+        "date" => Field::Date,
+        "time" => Field::Time,
+        "timestamp" => Field::Timestamp,
+        "ulid" => Field::Ulid,
+        "ext" => Field::Ext,
+        other => {
+            return Err(format!(
+                "Unknown placeholder '{{{other}}}' in name template. Valid: prompt, model, \
+                 provider, seed, n, id, ratio, size, quality, date, time, timestamp, ulid, ext"
+            ))
+        }
+    };
+    match (field, length) {
+        (_, None) => Ok(field),
+        (Field::Prompt(_), Some(length)) => {
+            length.parse().ok().filter(|&length| length > 0).map(Field::Prompt).ok_or_else(|| {
+                format!("Invalid length in '{{{spec}}}': expected a positive number")
+            })
+        }
+        (_, Some(_)) => Err(format!("Only {{prompt}} takes a length, not '{{{spec}}}'")),
+    }
+}
+
+/// `value` with anything but letters, digits, `-`, `_`, and `.` replaced by `-`, so
+/// it stays within one path component.
+fn path_safe(value: &str) -> String {
+    let safe: String = value
+        .chars()
+        .map(|c| if c.is_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '-' })
+        .collect();
+    match safe.trim_matches('.') {
+        "" => "unknown".to_string(),
+        _ => safe,
+    }
 }
 
 /// Sanitize a string for use in a filename.
@@ -52,7 +394,61 @@ pub fn sanitize_for_filename(input: &str, max_len: usize) -> String {
     }
 }
 
-/// Save raw image bytes to a file, converting format if necessary.
+/// Local adjustments applied while saving, after the provider returns.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PostProcess {
+    /// Bits per channel of the saved image (8 or 16); `None` keeps the source depth.
+    pub bit_depth: Option<u8>,
+    /// Convert to grayscale, keeping any alpha channel.
+    pub grayscale: bool,
+    /// Resizing, cropping, and overlays, applied in order before the color and bit-depth
+    /// adjustments.
+    pub steps: Vec<Step>,
+    /// Encoder quality for jpeg, webp, and avif output, 0-100; `None` uses the encoder's
+    /// default. Setting it re-encodes images already in the target format.
+    pub compression: Option<u8>,
+    /// Re-encode every image from its pixels alone, dropping the EXIF, XMP, C2PA, text
+    /// chunks, and color profile the provider embedded, and adding no tags of our own.
+    pub strip: bool,
+    /// Also write a JPEG thumbnail that fits in a square of this many pixels, as
+    /// [`thumbnail_path`].
+    pub thumbnail: Option<u32>,
+}
+
+impl PostProcess {
+    /// Whether saving with these options leaves the pixels untouched. The compression
+    /// changes only the encoding.
+    fn is_identity(&self) -> bool {
+        self.bit_depth.is_none() && !self.grayscale && self.steps.is_empty()
+    }
+
+    /// Apply the steps, then the color and bit-depth adjustments.
+    fn apply(&self, img: image::DynamicImage) -> image::DynamicImage {
+        use image::DynamicImage;
+        if self.is_identity() {
+            return img;
+        }
+        // Padding and the watermark draw on a 16-bit RGBA copy; the layout below restores
+        // the source's channels and depth unless asked to change them.
+        let color = img.color();
+        let img = self.steps.iter().fold(img, |img, step| step.apply(img));
+        let gray = self.grayscale || !color.has_color();
+        let sixteen =
+            self.bit_depth.map_or(color.bytes_per_pixel() > color.channel_count(), |d| d == 16);
+        match (gray, sixteen, color.has_alpha()) {
+            (true, false, false) => DynamicImage::ImageLuma8(img.to_luma8()),
+            (true, false, true) => DynamicImage::ImageLumaA8(img.to_luma_alpha8()),
+            (true, true, false) => DynamicImage::ImageLuma16(img.to_luma16()),
+            (true, true, true) => DynamicImage::ImageLumaA16(img.to_luma_alpha16()),
+            (false, false, false) => DynamicImage::ImageRgb8(img.to_rgb8()),
+            (false, false, true) => DynamicImage::ImageRgba8(img.to_rgba8()),
+            (false, true, false) => DynamicImage::ImageRgb16(img.to_rgb16()),
+            (false, true, true) => DynamicImage::ImageRgba16(img.to_rgba16()),
+        }
+    }
+}
+
+/// Save raw image bytes to a file, converting format and applying `post` if necessary.
 ///
 /// # Errors
 ///
@@ -62,55 +458,648 @@ pub fn save_image(
     source_mime: &str,
     target_format: &str,
     output_path: &Path,
+    post: &PostProcess,
 ) -> Result<(), ImageError> {
-    let needs_conversion = !mime_matches_format(source_mime, target_format);
-
-    if needs_conversion {
-        convert_and_save(data, target_format, output_path)
+    if needs_conversion(source_mime, target_format, post) {
+        convert_and_save(data, target_format, output_path, post)
     } else {
         std::fs::write(output_path, data).map_err(ImageError::Io)
     }
 }
 
+/// Whether an image of `source_mime` must be decoded and re-encoded to be saved as
+/// `target_format` with `post` applied; if not, its bytes can be written as-is.
+#[must_use]
+pub fn needs_conversion(source_mime: &str, target_format: &str, post: &PostProcess) -> bool {
+    !mime_matches_format(source_mime, target_format)
+        || !post.is_identity()
+        || post.compression.is_some()
+        || post.strip
+}
+
 /// Check if a MIME type matches the requested output format.
 fn mime_matches_format(mime: &str, format: &str) -> bool {
-    matches!((mime, format), ("image/jpeg", "jpeg") | ("image/png", "png") | ("image/webp", "webp"))
+    matches!(
+        (mime, format),
+        ("image/jpeg", "jpeg")
+            | ("image/png", "png")
+            | ("image/webp", "webp")
+            | ("image/gif", "gif")
+            | ("image/avif", "avif")
+    )
+}
+
+/// Quality passed to libwebp for lossy WebP output (`webp` feature) without `--compression`.
+#[cfg(feature = "webp")]
+const WEBP_QUALITY: f32 = 90.0;
+
+/// Speed of the rav1e AVIF encoder (`avif` feature), 1-10: `cavif`'s default, which is
+/// also the `image` crate's.
+#[cfg(feature = "avif")]
+const AVIF_SPEED: u8 = 4;
+
+/// Check that this build can encode `format`, naming the missing Cargo feature if not.
+///
+/// Run before any request is sent so a missing encoder doesn't waste a paid generation.
+///
+/// # Errors
+///
+/// Returns an error if no compiled-in encoder can write `format`.
+pub fn check_encoder(format: &str) -> Result<(), String> {
+    let image_format = image_format(format)?;
+    if image_format.writing_enabled() || (format == "webp" && cfg!(feature = "webp")) {
+        return Ok(());
+    }
+    let feature = match format {
+        "webp" => "imagen's `webp` feature (or the `image` crate's `webp` feature)".to_string(),
+        "avif" => "imagen's `avif` feature".to_string(),
+        _ => format!("the `image` crate's `{format}` feature"),
+    };
+    Err(format!("This build of imagen cannot write {format} images. Rebuild it with {feature}."))
+}
+
+/// Map an output format name to the `image` crate's format.
+fn image_format(format: &str) -> Result<image::ImageFormat, String> {
+    match format {
+        "jpeg" => Ok(image::ImageFormat::Jpeg),
+        "png" => Ok(image::ImageFormat::Png),
+        "webp" => Ok(image::ImageFormat::WebP),
+        "tiff" => Ok(image::ImageFormat::Tiff),
+        "bmp" => Ok(image::ImageFormat::Bmp),
+        "gif" => Ok(image::ImageFormat::Gif),
+        "avif" => Ok(image::ImageFormat::Avif),
+        other => Err(format!("Unsupported format: {other}")),
+    }
 }
 
 /// Convert image bytes to the target format and save.
+#[tracing::instrument(name = "convert", level = "debug", skip_all,
+                      fields(format = target_format, path = %output_path.display()))]
 fn convert_and_save(
     data: &[u8],
     target_format: &str,
     output_path: &Path,
+    post: &PostProcess,
 ) -> Result<(), ImageError> {
-    let img = image::load_from_memory(data)
-        .map_err(|e| ImageError::ImageConversion(format!("Failed to decode image: {e}")))?;
+    check_encoder(target_format).map_err(ImageError::ImageConversion)?;
+    let (img, profile) = decode(data)?;
+    let img = encodable(post.apply(img), target_format);
+    // A profile describes one color space; an RGB profile can't tag grayscale output.
+    let profile = profile.filter(|profile| !post.strip && profile_matches(profile, &img));
 
-    let image_format = match target_format {
-        "jpeg" => image::ImageFormat::Jpeg,
-        "png" => image::ImageFormat::Png,
-        "webp" => image::ImageFormat::WebP,
-        other => {
-            return Err(ImageError::ImageConversion(format!("Unsupported format: {other}")));
+    #[cfg(feature = "webp")]
+    if target_format == "webp" {
+        warn_dropped_profile(profile.as_deref(), target_format);
+        return save_lossy_webp(
+            &img,
+            output_path,
+            post.compression.map_or(WEBP_QUALITY, f32::from),
+        );
+    }
+
+    let encoded = encode(&img, target_format, post, profile)?;
+    std::fs::write(output_path, encoded).map_err(ImageError::Io)
+}
+
+/// Decode image bytes, along with the ICC color profile embedded in them, if any. The
+/// EXIF orientation is applied, since re-encoding drops the tag.
+fn decode(data: &[u8]) -> Result<(image::DynamicImage, Option<Vec<u8>>), ImageError> {
+    use image::ImageDecoder as _;
+    let failed =
+        |e: image::ImageError| ImageError::ImageConversion(format!("Failed to decode image: {e}"));
+    let mut decoder = image::ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()
+        .map_err(ImageError::Io)?
+        .into_decoder()
+        .map_err(failed)?;
+    let profile = decoder.icc_profile().ok().flatten();
+    let orientation = decoder.orientation().unwrap_or(image::metadata::Orientation::NoTransforms);
+    let mut img = image::DynamicImage::from_decoder(decoder).map_err(failed)?;
+    img.apply_orientation(orientation);
+    Ok((img, profile))
+}
+
+/// Whether the ICC `profile` is for the color space of `img`: RGB or gray, named in the
+/// profile header's data color space field.
+fn profile_matches(profile: &[u8], img: &image::DynamicImage) -> bool {
+    let space: &[u8] = if img.color().has_color() { b"RGB " } else { b"GRAY" };
+    profile.get(16..20) == Some(space)
+}
+
+/// Encode `img` as `target_format`, at `post.compression` where the encoder has a quality
+/// setting, embedding the ICC `profile` where the format's encoder can. PNG without a
+/// profile is tagged sRGB instead, which is what providers produce, unless `post.strip`.
+fn encode(
+    img: &image::DynamicImage,
+    target_format: &str,
+    post: &PostProcess,
+    profile: Option<Vec<u8>>,
+) -> Result<Vec<u8>, ImageError> {
+    use image::codecs::{jpeg::JpegEncoder, png::PngEncoder, webp::WebPEncoder};
+    let mut encoded = Vec::new();
+    let tag_srgb = target_format == "png" && profile.is_none() && !post.strip;
+    // The JPEG and AVIF encoders take 1-100.
+    let quality = post.compression.map(|quality| quality.clamp(1, 100));
+    let written = match target_format {
+        "png" => with_profile(img, PngEncoder::new(&mut encoded), profile),
+        "jpeg" => {
+            let jpeg = match quality {
+                Some(quality) => JpegEncoder::new_with_quality(&mut encoded, quality),
+                None => JpegEncoder::new(&mut encoded),
+            };
+            with_profile(img, jpeg, profile)
+        }
+        "webp" => with_profile(img, WebPEncoder::new_lossless(&mut encoded), profile),
+        #[cfg(feature = "avif")]
+        "avif" => {
+            use image::codecs::avif::AvifEncoder;
+            warn_dropped_profile(profile.as_deref(), target_format);
+            img.write_with_encoder(match quality {
+                Some(quality) => {
+                    AvifEncoder::new_with_speed_quality(&mut encoded, AVIF_SPEED, quality)
+                }
+                None => AvifEncoder::new(&mut encoded),
+            })
+        }
+        _ => {
+            warn_dropped_profile(profile.as_deref(), target_format);
+            let image_format = image_format(target_format).map_err(ImageError::ImageConversion)?;
+            img.write_to(&mut std::io::Cursor::new(&mut encoded), image_format)
         }
     };
+    written.map_err(|e| {
+        ImageError::ImageConversion(format!("Failed to save as {target_format}: {e}"))
+    })?;
+    if tag_srgb {
+        encoded = with_srgb_chunk(&encoded)
+            .map_err(|e| ImageError::ImageConversion(format!("PNG color tag: {e}")))?;
+    }
+    Ok(encoded)
+}
+
+/// Write `img` with `encoder`, embedding the ICC `profile` if there is one.
+fn with_profile(
+    img: &image::DynamicImage,
+    mut encoder: impl image::ImageEncoder,
+    profile: Option<Vec<u8>>,
+) -> image::ImageResult<()> {
+    if let Some(profile) = profile {
+        encoder.set_icc_profile(profile).map_err(image::ImageError::Unsupported)?;
+    }
+    img.write_with_encoder(encoder)
+}
+
+/// Warn that `format` output loses the image's ICC color `profile`, when it has one.
+fn warn_dropped_profile(profile: Option<&[u8]>, format: &str) {
+    if profile.is_some() {
+        crate::report::warn(format_args!(
+            "{format} output cannot carry the image's ICC color profile; colors may shift \
+             slightly in color-managed apps"
+        ));
+    }
+}
+
+/// Narrow the pixel layout to one the target encoder accepts.
+///
+/// JPEG has no alpha channel and the JPEG, WebP, AVIF, and BMP encoders take 8-bit samples
+/// only, so alpha is dropped for JPEG and 16-bit/float images are reduced to 8 bits. GIF
+/// takes 8-bit RGBA, which it quantizes to a palette.
+fn encodable(img: image::DynamicImage, target_format: &str) -> image::DynamicImage {
+    use image::DynamicImage;
+    match target_format {
+        "jpeg" if !img.color().has_color() && !matches!(img, DynamicImage::ImageLuma8(_)) => {
+            DynamicImage::ImageLuma8(img.to_luma8())
+        }
+        "jpeg" if img.color().has_color() && !matches!(img, DynamicImage::ImageRgb8(_)) => {
+            DynamicImage::ImageRgb8(img.to_rgb8())
+        }
+        "webp" | "avif"
+            if img.color().has_alpha() && !matches!(img, DynamicImage::ImageRgba8(_)) =>
+        {
+            DynamicImage::ImageRgba8(img.to_rgba8())
+        }
+        "webp" | "avif"
+            if !img.color().has_alpha() && !matches!(img, DynamicImage::ImageRgb8(_)) =>
+        {
+            DynamicImage::ImageRgb8(img.to_rgb8())
+        }
+        "gif" if !matches!(img, DynamicImage::ImageRgba8(_)) => {
+            DynamicImage::ImageRgba8(img.to_rgba8())
+        }
+        "bmp" if img.color().bytes_per_pixel() > img.color().channel_count() => {
+            if img.color().has_alpha() {
+                DynamicImage::ImageRgba8(img.to_rgba8())
+            } else {
+                DynamicImage::ImageRgb8(img.to_rgb8())
+            }
+        }
+        _ => img,
+    }
+}
 
-    img.save_with_format(output_path, image_format)
-        .map_err(|e| ImageError::ImageConversion(format!("Failed to save as {target_format}: {e}")))
+/// Encode lossy WebP with libwebp at `quality`, 0-100.
+#[cfg(feature = "webp")]
+fn save_lossy_webp(
+    img: &image::DynamicImage,
+    output_path: &Path,
+    quality: f32,
+) -> Result<(), ImageError> {
+    let encoded = match img {
+        image::DynamicImage::ImageRgba8(rgba) => {
+            webp::Encoder::from_rgba(rgba, rgba.width(), rgba.height()).encode(quality)
+        }
+        other => {
+            let rgb = other.to_rgb8();
+            webp::Encoder::from_rgb(&rgb, rgb.width(), rgb.height()).encode(quality)
+        }
+    };
+    std::fs::write(output_path, &*encoded).map_err(ImageError::Io)
 }
 
-/// Resolve the output path: use explicit path or auto-generate.
+/// Resolve the output path: use explicit path or auto-generate in `naming.dir` (the
+/// current directory by default).
 #[must_use]
-pub fn resolve_output_path(explicit: Option<&str>, prompt: &str, format: &str) -> PathBuf {
+pub fn resolve_output_path(
+    explicit: Option<&str>,
+    prompt: &str,
+    format: &str,
+    naming: &Naming,
+    request_id: &str,
+) -> PathBuf {
     match explicit {
         Some(p) => PathBuf::from(p),
-        None => PathBuf::from(auto_filename(prompt, format)),
+        None => match &naming.dir {
+            Some(dir) => dir.join(auto_filename(prompt, format, naming, request_id, dir)),
+            None => {
+                PathBuf::from(auto_filename(prompt, format, naming, request_id, Path::new(".")))
+            }
+        },
+    }
+}
+
+/// Path for image `index` (0-based) of a batch of `count`.
+///
+/// A single image keeps `base` as-is; batches get `-1`, `-2`, ... before the extension.
+/// A base path without an extension gets the format's extension.
+#[must_use]
+pub fn indexed_output_path(
+    base: &Path,
+    index: usize,
+    count: usize,
+    format: &str,
+    naming: &Naming,
+) -> PathBuf {
+    if count <= 1 {
+        return base.to_path_buf();
+    }
+    with_suffix(base, &(index + 1).to_string(), format, naming)
+}
+
+/// Path for one leg of an aspect-ratio sweep: `cat.png` at `16:9` → `cat-16x9.png`.
+///
+/// A base path without an extension gets the format's extension.
+#[must_use]
+pub fn ratio_output_path(base: &Path, ratio: &str, format: &str, naming: &Naming) -> PathBuf {
+    with_suffix(base, &ratio.replace(':', "x"), format, naming)
+}
+
+/// Path for one model of `imagen compare`: `cat.png` from `gpt-1` → `cat-gpt-1.png`.
+/// Characters that don't belong in a file name become `-`.
+///
+/// A base path without an extension gets the format's extension.
+#[must_use]
+pub fn model_output_path(base: &Path, model: &str, format: &str, naming: &Naming) -> PathBuf {
+    let model: String = model
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-') { c } else { '-' })
+        .collect();
+    with_suffix(base, &model, format, naming)
+}
+
+/// Path for version `n` of a `--watch` session: `cat.png` → `cat-v3.png`.
+///
+/// A base path without an extension gets the format's extension.
+#[must_use]
+pub fn versioned_output_path(base: &Path, version: u32, format: &str, naming: &Naming) -> PathBuf {
+    with_suffix(base, &format!("v{version}"), format, naming)
+}
+
+/// One past the highest version already saved next to `base` as `<stem>-v<N>...`
+/// (1 when there is none), so a new `--watch` session doesn't overwrite an earlier one.
+#[must_use]
+pub fn next_version(base: &Path) -> u32 {
+    let prefix = format!("{}-v", base.file_stem().unwrap_or_default().to_string_lossy());
+    let dir = base.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 1;
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let rest = name.strip_prefix(&prefix)?;
+            let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+            digits.parse::<u32>().ok()
+        })
+        .max()
+        .map_or(1, |n| n + 1)
+}
+
+/// Where to save a file meant for `path`, which is `path` itself unless a file is already
+/// there: then the next free `cat-2.png`, `cat-3.png`, ... with [`Existing::Rename`].
+///
+/// # Errors
+///
+/// Returns [`ImageError::OutputExists`] when `path` exists and `existing` is
+/// [`Existing::Refuse`], or every numbered name is taken too.
+pub fn claim_output_path(path: &Path, existing: Existing) -> Result<PathBuf, ImageError> {
+    if existing == Existing::Overwrite || !path.exists() {
+        return Ok(path.to_path_buf());
+    }
+    if existing == Existing::Refuse {
+        return Err(ImageError::OutputExists(path.to_path_buf()));
+    }
+    let numbered = |n: u32| {
+        let mut name = path.file_stem().unwrap_or_default().to_os_string();
+        name.push(format!("-{n}"));
+        if let Some(ext) = path.extension() {
+            name.push(".");
+            name.push(ext);
+        }
+        path.with_file_name(name)
+    };
+    (2..=u32::MAX)
+        .map(numbered)
+        .find(|path| !path.exists())
+        .ok_or_else(|| ImageError::OutputExists(path.to_path_buf()))
+}
+
+/// `base` with `-<suffix>` added to its stem.
+fn with_suffix(base: &Path, suffix: &str, format: &str, naming: &Naming) -> PathBuf {
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = base
+        .extension()
+        .map_or_else(|| naming.extension(format).to_string(), |e| e.to_string_lossy().to_string());
+    base.with_file_name(format!("{stem}-{suffix}.{ext}"))
+}
+
+/// The JSON sidecar written next to an image with `--sidecar`: `cat.jpg` → `cat.jpg.json`.
+#[must_use]
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+/// The thumbnail written next to an image with `--thumbnail`: `cat.png` → `cat.thumb.jpg`.
+#[must_use]
+pub fn thumbnail_path(path: &Path) -> PathBuf {
+    path.with_extension("thumb.jpg")
+}
+
+/// JPEG quality of thumbnails: small files without visible blocking at gallery sizes.
+const THUMBNAIL_QUALITY: u8 = 85;
+
+/// Write a JPEG thumbnail of the image saved at `path` to [`thumbnail_path`], scaled to
+/// fit inside `size` x `size`. Transparent areas are flattened onto white.
+///
+/// # Errors
+///
+/// Returns an error if the image cannot be read back or the thumbnail cannot be written.
+pub fn save_thumbnail(path: &Path, size: u32) -> Result<PathBuf, ImageError> {
+    let data = std::fs::read(path)?;
+    let (img, _) = decode(&data)?;
+    let small = img.thumbnail(size, size).to_rgba8();
+    let mut flat = image::RgbImage::from_pixel(small.width(), small.height(), image::Rgb([255; 3]));
+    for (pixel, &image::Rgba([r, g, b, a])) in flat.pixels_mut().zip(small.pixels()) {
+        let over = |c: u8, under: u8| {
+            let blended =
+                (u16::from(c) * u16::from(a) + u16::from(under) * u16::from(255 - a)) / 255;
+            blended.to_le_bytes()[0]
+        };
+        *pixel = image::Rgb([over(r, pixel[0]), over(g, pixel[1]), over(b, pixel[2])]);
+    }
+    let mut encoded = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(&mut encoded, THUMBNAIL_QUALITY)
+        .encode_image(&flat)
+        .map_err(|e| ImageError::ImageConversion(format!("Failed to encode thumbnail: {e}")))?;
+    let thumbnail = thumbnail_path(path);
+    std::fs::write(&thumbnail, encoded)?;
+    Ok(thumbnail)
+}
+
+/// Prefix of the hidden directories [`Staging`] holds a run's files in.
+pub const STAGING_PREFIX: &str = ".imagen-staging-";
+
+/// Directory next to the output that `--stream` writes its previews to.
+pub const PREVIEW_DIR: &str = ".partial";
+
+/// Filename of `--stream` preview `frame` (counted from 1) of a request.
+#[must_use]
+pub fn preview_file_name(request_id: &str, frame: u32, ext: &str) -> String {
+    format!("{request_id}-{frame}.{ext}")
+}
+
+/// Whether `name` is one [`preview_file_name`] gives: a request ID, a frame number,
+/// and an extension.
+#[must_use]
+pub fn is_preview_file_name(name: &str) -> bool {
+    let Some((id, rest)) = name.split_once('-') else {
+        return false;
+    };
+    let Some((frame, ext)) = rest.split_once('.') else {
+        return false;
+    };
+    id.len() == 16
+        && id.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+        && !frame.is_empty()
+        && frame.bytes().all(|b| b.is_ascii_digit())
+        && !ext.is_empty()
+        && ext.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// Holds a run's files back until every one of them is written (`--transactional`).
+///
+/// Each file is written to a hidden staging directory next to its destination (so the
+/// final move is a same-filesystem rename) and moved into place on [`Self::commit`].
+/// Watchers of the output directory therefore never see a partial set.
+pub struct Staging {
+    /// Directory name shared by this run's staging directories.
+    name: String,
+    /// Staged file and final destination, in the order they were staged.
+    files: Mutex<Vec<(PathBuf, PathBuf)>>,
+}
+
+impl Default for Staging {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Staging {
+    /// Start staging with a fresh, run-unique directory name.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { name: format!("{STAGING_PREFIX}{}", ulid::Ulid::new()), files: Mutex::default() }
+    }
+
+    /// Where to write the file destined for `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the staging directory cannot be created.
+    pub fn stage(&self, path: &Path) -> Result<PathBuf, ImageError> {
+        let dir = self.dir_for(path);
+        std::fs::create_dir_all(&dir)?;
+        let staged = dir.join(path.file_name().unwrap_or_default());
+        self.lock().push((staged.clone(), path.to_path_buf()));
+        Ok(staged)
+    }
+
+    /// Move every staged file into place, with its [`sidecar_path`] and
+    /// [`thumbnail_path`] files if they were written, returning the final paths of the
+    /// staged files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a file cannot be moved; files already moved stay in place.
+    pub fn commit(self) -> Result<Vec<PathBuf>, ImageError> {
+        let files = self.lock().clone();
+        files
+            .into_iter()
+            .map(|(staged, path)| {
+                std::fs::rename(&staged, &path)?;
+                for companion in [sidecar_path, thumbnail_path] {
+                    if companion(&staged).exists() {
+                        std::fs::rename(companion(&staged), companion(&path))?;
+                    }
+                }
+                Ok(path)
+            })
+            .collect()
+    }
+
+    /// The staging directory for files destined for `path`'s directory.
+    fn dir_for(&self, path: &Path) -> PathBuf {
+        path.parent().unwrap_or(Path::new("")).join(&self.name)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<(PathBuf, PathBuf)>> {
+        self.files.lock().unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+}
+
+/// Open `path` in the platform's default viewer (`--open`).
+///
+/// # Errors
+///
+/// Returns an error if the opener cannot be run or reports a failure.
+pub fn open_in_viewer(path: &Path) -> Result<(), ImageError> {
+    let status = viewer_command(path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(ImageError::Io(std::io::Error::other(format!(
+            "{:?} exited with {status}",
+            viewer_command(path).get_program()
+        ))));
+    }
+    Ok(())
+}
+
+/// The command that hands `path` to the desktop: `open` on macOS, `start` on Windows,
+/// and `xdg-open` elsewhere. Each returns once the viewer has been launched.
+fn viewer_command(path: &Path) -> Command {
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        // `start` is built into cmd; its first quoted argument is the window title.
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+    command.arg(path);
+    command
+}
+
+/// Dropping the staging (after a commit or a failed run) deletes whatever is left in its
+/// directories.
+impl Drop for Staging {
+    fn drop(&mut self) {
+        let mut dirs: Vec<PathBuf> =
+            self.lock().iter().map(|(_, path)| self.dir_for(path)).collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in dirs {
+            let _ = std::fs::remove_dir_all(dir);
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::post::Crop;
+    use crate::watermark::{Mark, Position, Watermark};
+
+    #[test]
+    fn viewer_is_given_the_image_path() {
+        let command = viewer_command(Path::new("out/a cat.png"));
+        assert_eq!(command.get_args().last(), Some(std::ffi::OsStr::new("out/a cat.png")));
+        #[cfg(target_os = "linux")]
+        assert_eq!(command.get_program(), "xdg-open");
+    }
+
+    fn fields(n: usize) -> NameFields<'static> {
+        NameFields {
+            prompt: "A cat, sitting on a mat",
+            model: "replicate/owner/model",
+            provider: "replicate",
+            seed: Some(42),
+            n,
+            request_id: "3f2a9c0e1b7d4a65",
+            aspect_ratio: "16:9",
+            size: "1K",
+            quality: "high",
+        }
+    }
+
+    #[test]
+    fn name_template_fills_placeholders() {
+        let template = NameTemplate::parse("{model}/{prompt:5}-{seed}-{n}-{ratio}.{ext}").unwrap();
+        assert_eq!(
+            template.render(&fields(2), "png"),
+            PathBuf::from("replicate-owner-model/a-cat-42-2-16x9.png")
+        );
+        let template = NameTemplate::parse("{id}_{seed}").unwrap();
+        let unseeded = NameFields { seed: None, ..fields(1) };
+        assert_eq!(template.render(&unseeded, "jpg"), PathBuf::from("3f2a9c0e1b7d4a65_random.jpg"));
+        assert!(template.render(&fields(1), "jpg").to_string_lossy().ends_with(".jpg"));
+    }
+
+    #[test]
+    fn name_template_escapes_braces() {
+        let template = NameTemplate::parse("{{n}}-{n}}}").unwrap();
+        assert_eq!(template.render(&fields(3), "png"), PathBuf::from("{n}-3}.png"));
+    }
+
+    #[test]
+    fn name_template_rejects_bad_templates() {
+        for bad in ["{colour}", "{n", "n}", "{seed:4}", "{prompt:0}", "{prompt:x}", ""] {
+            assert!(NameTemplate::parse(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn name_template_reports_its_own_suffixes() {
+        let template = NameTemplate::parse("{date}/{prompt}-{n}").unwrap();
+        assert!(template.numbers_images());
+        assert!(!template.names_ratio());
+    }
 
     #[test]
     fn sanitize_basic() {
@@ -143,38 +1132,444 @@ mod tests {
         assert_eq!(sanitize_for_filename("  hello  ", 50), "hello");
     }
 
+    fn naming(slug: SlugStrategy) -> Naming {
+        Naming { slug, ..Naming::default() }
+    }
+
+    fn prompt_filename(prompt: &str, format: &str) -> String {
+        auto_filename(prompt, format, &Naming::default(), "0123456789abcdef", Path::new("."))
+    }
+
     #[test]
     fn auto_filename_format() {
-        let name = auto_filename("a cat", "jpeg");
+        let name = prompt_filename("a cat", "jpeg");
         assert!(name.starts_with("a-cat-"));
         assert_eq!(Path::new(&name).extension().unwrap(), "jpg");
     }
 
     #[test]
     fn auto_filename_png() {
-        let name = auto_filename("test", "png");
+        let name = prompt_filename("test", "png");
         assert_eq!(Path::new(&name).extension().unwrap(), "png");
     }
 
+    #[test]
+    fn slug_strategy_names() {
+        assert_eq!(SlugStrategy::parse("prompt").unwrap(), SlugStrategy::Prompt);
+        assert_eq!(SlugStrategy::parse("hash").unwrap(), SlugStrategy::Hash);
+        assert_eq!(SlugStrategy::parse("counter").unwrap(), SlugStrategy::Counter);
+        assert_eq!(SlugStrategy::parse("ulid").unwrap(), SlugStrategy::Ulid);
+        assert!(SlugStrategy::parse("uuid").is_err());
+    }
+
+    #[test]
+    fn hash_slug_uses_request_id() {
+        let name = auto_filename(
+            "a cat",
+            "png",
+            &naming(SlugStrategy::Hash),
+            "0123456789abcdef",
+            Path::new("."),
+        );
+        assert_eq!(name, "0123456789abcdef.png");
+    }
+
+    #[test]
+    fn ulid_slug_is_26_chars() {
+        let name = auto_filename("a cat", "jpeg", &naming(SlugStrategy::Ulid), "", Path::new("."));
+        let stem = Path::new(&name).file_stem().unwrap().to_string_lossy().to_string();
+        assert_eq!(stem.len(), 26);
+        assert_ne!(
+            name,
+            auto_filename("a cat", "jpeg", &naming(SlugStrategy::Ulid), "", Path::new("."))
+        );
+    }
+
+    #[test]
+    fn counter_slug_follows_existing_files() {
+        let dir = std::env::temp_dir().join("imagen_output_counter_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let first = auto_filename("x", "png", &naming(SlugStrategy::Counter), "", &dir);
+        assert_eq!(first, "0001.png");
+
+        for name in ["0001.png", "0007-2.jpg", "notes.txt"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        assert_eq!(auto_filename("x", "png", &naming(SlugStrategy::Counter), "", &dir), "0008.png");
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn extension_override() {
+        let mut naming = Naming::default();
+        naming.extensions.insert("jpeg".into(), ".jpeg".into());
+        assert_eq!(naming.extension("jpeg"), "jpeg");
+        assert_eq!(naming.extension("png"), "png");
+
+        let name = auto_filename("a cat", "jpeg", &naming, "", Path::new("."));
+        assert_eq!(Path::new(&name).extension().unwrap(), "jpeg");
+    }
+
+    #[test]
+    fn indexed_paths() {
+        let naming = Naming::default();
+        let base = Path::new("out/a-cat.jpeg");
+        assert_eq!(indexed_output_path(base, 0, 1, "jpeg", &naming), base);
+        assert_eq!(
+            indexed_output_path(base, 1, 3, "jpeg", &naming),
+            PathBuf::from("out/a-cat-2.jpeg")
+        );
+        assert_eq!(
+            indexed_output_path(Path::new("render"), 0, 2, "png", &naming),
+            PathBuf::from("render-1.png")
+        );
+    }
+
+    #[test]
+    fn ratio_paths() {
+        let naming = Naming::default();
+        assert_eq!(
+            ratio_output_path(Path::new("out/a-cat.jpg"), "16:9", "jpeg", &naming),
+            PathBuf::from("out/a-cat-16x9.jpg")
+        );
+        assert_eq!(
+            ratio_output_path(Path::new("kit"), "9:16", "png", &naming),
+            PathBuf::from("kit-9x16.png")
+        );
+        assert_eq!(
+            model_output_path(Path::new("out/a-cat.png"), "flux/dev", "png", &naming),
+            PathBuf::from("out/a-cat-flux-dev.png")
+        );
+    }
+
+    #[test]
+    fn versioned_paths_continue_after_existing_versions() {
+        let dir = std::env::temp_dir().join("imagen_output_version_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let base = dir.join("poster.png");
+        assert_eq!(next_version(&base), 1);
+
+        let naming = Naming::default();
+        assert_eq!(versioned_output_path(&base, 2, "png", &naming), dir.join("poster-v2.png"));
+        for name in ["poster-v2.png", "poster-v9-1.png", "poster-vx.png", "other-v40.png"] {
+            std::fs::write(dir.join(name), b"").unwrap();
+        }
+        assert_eq!(next_version(&base), 10);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn resolve_explicit() {
-        let path = resolve_output_path(Some("my-image.png"), "ignored", "jpeg");
+        let path =
+            resolve_output_path(Some("my-image.png"), "ignored", "jpeg", &Naming::default(), "");
         assert_eq!(path, PathBuf::from("my-image.png"));
     }
 
     #[test]
     fn resolve_auto() {
-        let path = resolve_output_path(None, "a cat", "jpeg");
+        let path = resolve_output_path(None, "a cat", "jpeg", &Naming::default(), "");
         assert!(path.to_str().unwrap().starts_with("a-cat-"));
         assert_eq!(path.extension().unwrap(), "jpg");
     }
 
+    #[test]
+    fn resolve_auto_in_output_dir() {
+        let naming = Naming { dir: Some(PathBuf::from("renders")), ..naming(SlugStrategy::Hash) };
+        let path = resolve_output_path(None, "a cat", "png", &naming, "3f2a9c0e1b7d4a65");
+        assert_eq!(path, Path::new("renders").join("3f2a9c0e1b7d4a65.png"));
+        let explicit = resolve_output_path(Some("cat.png"), "a cat", "png", &naming, "");
+        assert_eq!(explicit, PathBuf::from("cat.png"));
+    }
+
+    #[test]
+    fn claim_existing_output() {
+        let dir = std::env::temp_dir().join("imagen_claim_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cat.png");
+        assert_eq!(claim_output_path(&path, Existing::Refuse).unwrap(), path);
+
+        std::fs::write(&path, b"").unwrap();
+        std::fs::write(dir.join("cat-2.png"), b"").unwrap();
+        assert!(matches!(
+            claim_output_path(&path, Existing::Refuse),
+            Err(ImageError::OutputExists(existing)) if existing == path
+        ));
+        assert_eq!(claim_output_path(&path, Existing::Overwrite).unwrap(), path);
+        assert_eq!(claim_output_path(&path, Existing::Rename).unwrap(), dir.join("cat-3.png"));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
     #[test]
     fn mime_matches() {
         assert!(mime_matches_format("image/jpeg", "jpeg"));
         assert!(mime_matches_format("image/png", "png"));
         assert!(mime_matches_format("image/webp", "webp"));
+        assert!(mime_matches_format("image/gif", "gif"));
+        assert!(!mime_matches_format("image/png", "avif"));
         assert!(!mime_matches_format("image/jpeg", "png"));
         assert!(!mime_matches_format("image/png", "jpeg"));
     }
+
+    /// Encode a tiny image as PNG bytes.
+    fn png_bytes(img: &image::DynamicImage) -> Vec<u8> {
+        let mut buf = std::io::Cursor::new(Vec::new());
+        img.write_to(&mut buf, image::ImageFormat::Png).unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn builtin_encoders_available() {
+        assert!(check_encoder("jpeg").is_ok());
+        assert!(check_encoder("png").is_ok());
+        assert!(check_encoder("webp").is_ok());
+        assert!(check_encoder("tiff").is_ok());
+        assert!(check_encoder("bmp").is_ok());
+        assert!(check_encoder("gif").is_ok());
+        assert_eq!(check_encoder("avif").is_ok(), cfg!(feature = "avif"));
+        assert!(check_encoder("heic").is_err());
+    }
+
+    #[test]
+    fn converts_alpha_and_16_bit_sources() {
+        let dir = std::env::temp_dir().join(format!("imagen_convert_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rgba = image::DynamicImage::ImageRgba8(image::RgbaImage::new(4, 4));
+        let rgb16 = image::DynamicImage::ImageRgb16(image::ImageBuffer::new(4, 4));
+
+        let conversions = [
+            (&rgba, "jpeg"),
+            (&rgb16, "jpeg"),
+            (&rgba, "webp"),
+            (&rgb16, "webp"),
+            (&rgb16, "bmp"),
+            (&rgba, "gif"),
+            (&rgb16, "gif"),
+        ];
+        for (source, format) in conversions {
+            let path = dir.join(format!("out-{:?}.{format}", source.color()));
+            save_image(&png_bytes(source), "image/png", format, &path, &PostProcess::default())
+                .unwrap();
+            let written = image::open(&path).unwrap();
+            assert_eq!((written.width(), written.height()), (4, 4));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn post_process_color_and_depth() {
+        let rgba = image::DynamicImage::ImageRgba8(image::RgbaImage::new(2, 2));
+        let post =
+            |bit_depth, grayscale| PostProcess { bit_depth, grayscale, ..PostProcess::default() };
+        assert_eq!(post(None, false).apply(rgba.clone()).color(), image::ColorType::Rgba8);
+        assert_eq!(post(None, true).apply(rgba.clone()).color(), image::ColorType::La8);
+        assert_eq!(post(Some(16), false).apply(rgba.clone()).color(), image::ColorType::Rgba16);
+        assert_eq!(post(Some(16), true).apply(rgba.clone()).color(), image::ColorType::La16);
+
+        let gray16 = post(Some(16), true).apply(rgba);
+        assert_eq!(post(None, true).apply(gray16.clone()).color(), image::ColorType::La16);
+        assert_eq!(post(Some(8), false).apply(gray16).color(), image::ColorType::La8);
+    }
+
+    #[test]
+    fn post_process_resizes_and_crops_to_exact_dimensions() {
+        let wide = image::DynamicImage::ImageRgb8(image::RgbImage::new(1344, 768));
+        let fill = |width, height| PostProcess {
+            steps: vec![Step::Fill(width, height, Crop::Center)],
+            ..PostProcess::default()
+        };
+        let resized = fill(1920, 1080).apply(wide);
+        assert_eq!((resized.width(), resized.height()), (1920, 1080));
+        let square = fill(64, 64);
+        let cropped = square.apply(resized);
+        assert_eq!((cropped.width(), cropped.height()), (64, 64));
+    }
+
+    #[test]
+    fn watermark_keeps_the_source_layout() {
+        let mark = Watermark {
+            mark: Mark::Text("imagen".into()),
+            position: Position::default(),
+            opacity: 0.5,
+            scale: None,
+        };
+        let post = PostProcess { steps: vec![Step::Watermark(mark)], ..PostProcess::default() };
+        assert!(!post.is_identity());
+        let rgb = image::DynamicImage::ImageRgb8(image::RgbImage::new(64, 64));
+        assert_eq!(post.apply(rgb).color(), image::ColorType::Rgb8);
+        let gray = image::DynamicImage::ImageLuma16(image::ImageBuffer::new(64, 64));
+        assert_eq!(post.apply(gray).color(), image::ColorType::L16);
+    }
+
+    #[test]
+    fn compression_reencodes_at_the_requested_quality() {
+        let dir = std::env::temp_dir().join(format!("imagen_compression_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let byte = |value: u32| value.to_le_bytes()[0];
+        let noise = image::RgbImage::from_fn(64, 64, |x, y| {
+            image::Rgb([byte(x * 7 + y * 13), byte(x * y), byte(x ^ y)])
+        });
+        let mut source = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::ImageRgb8(noise)
+            .write_to(&mut source, image::ImageFormat::Jpeg)
+            .unwrap();
+        let source = source.into_inner();
+
+        let size = |compression| {
+            let post = PostProcess { compression, ..PostProcess::default() };
+            assert_eq!(needs_conversion("image/jpeg", "jpeg", &post), compression.is_some());
+            let path = dir.join(format!("q{compression:?}.jpg"));
+            save_image(&source, "image/jpeg", "jpeg", &path, &post).unwrap();
+            std::fs::metadata(&path).unwrap().len()
+        };
+        assert!(size(Some(20)) < size(Some(95)));
+        assert_eq!(size(None), source.len() as u64, "saved as returned");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn conversion_keeps_the_icc_profile() {
+        use image::{ImageDecoder as _, ImageEncoder as _};
+        let dir = std::env::temp_dir().join(format!("imagen_icc_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // A header-only stand-in for a Display P3 profile; the codecs copy it as-is.
+        let mut profile = vec![0u8; 128];
+        profile[16..20].copy_from_slice(b"RGB ");
+        profile[36..40].copy_from_slice(b"acsp");
+        let mut source = Vec::new();
+        let mut encoder = image::codecs::png::PngEncoder::new(&mut source);
+        encoder.set_icc_profile(profile.clone()).unwrap();
+        encoder.write_image(&[200; 4 * 4 * 3], 4, 4, image::ExtendedColorType::Rgb8).unwrap();
+        let profile_of = |path: &Path| {
+            let reader = image::ImageReader::open(path).unwrap().with_guessed_format().unwrap();
+            reader.into_decoder().unwrap().icc_profile().unwrap()
+        };
+
+        // The `webp` feature's lossy encoder has no way to embed a profile.
+        let formats: &[&str] =
+            if cfg!(feature = "webp") { &["jpeg", "png"] } else { &["jpeg", "webp", "png"] };
+        for &format in formats {
+            let path = dir.join(format!("p3.{format}"));
+            let post = PostProcess { bit_depth: Some(8), ..PostProcess::default() };
+            save_image(&source, "image/png", format, &path, &post).unwrap();
+            assert_eq!(profile_of(&path).as_ref(), Some(&profile), "{format}");
+        }
+
+        let gray = dir.join("gray.png");
+        let post = PostProcess { grayscale: true, ..PostProcess::default() };
+        save_image(&source, "image/png", "png", &gray, &post).unwrap();
+        assert_eq!(profile_of(&gray), None, "an RGB profile can't describe gray pixels");
+        let tagged = std::fs::read(&gray).unwrap();
+        assert!(tagged.windows(4).any(|chunk| chunk == b"sRGB"), "untagged PNGs are marked sRGB");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn strip_drops_all_metadata() {
+        use image::{ImageDecoder as _, ImageEncoder as _};
+        let dir = std::env::temp_dir().join(format!("imagen_strip_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut profile = vec![0u8; 128];
+        profile[16..20].copy_from_slice(b"RGB ");
+        // A little-endian TIFF header and an empty IFD.
+        let exif = b"II*\0\x08\0\0\0\0\0".to_vec();
+        let pixels = [90; 4 * 4 * 3];
+        let mut png = Vec::new();
+        let mut encoder = image::codecs::png::PngEncoder::new(&mut png);
+        encoder.set_icc_profile(profile.clone()).unwrap();
+        encoder.set_exif_metadata(exif.clone()).unwrap();
+        encoder.write_image(&pixels, 4, 4, image::ExtendedColorType::Rgb8).unwrap();
+        let mut jpeg = Vec::new();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut jpeg);
+        encoder.set_icc_profile(profile).unwrap();
+        encoder.set_exif_metadata(exif).unwrap();
+        encoder.write_image(&pixels, 4, 4, image::ExtendedColorType::Rgb8).unwrap();
+
+        let post = PostProcess { strip: true, ..PostProcess::default() };
+        assert!(needs_conversion("image/png", "png", &post), "same-format images are re-encoded");
+        for (source, mime, format) in [(&png, "image/png", "png"), (&jpeg, "image/jpeg", "jpeg")] {
+            let path = dir.join(format!("clean.{format}"));
+            save_image(source, mime, format, &path, &post).unwrap();
+            let reader = image::ImageReader::open(&path).unwrap().with_guessed_format().unwrap();
+            let mut decoder = reader.into_decoder().unwrap();
+            assert_eq!(decoder.icc_profile().unwrap(), None, "{format}");
+            assert_eq!(decoder.exif_metadata().unwrap(), None, "{format}");
+        }
+        let clean = std::fs::read(dir.join("clean.png")).unwrap();
+        assert!(!clean.windows(4).any(|chunk| chunk == b"sRGB"), "no tags of our own");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn post_processing_forces_conversion() {
+        let dir = std::env::temp_dir().join(format!("imagen_post_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let source = png_bytes(&image::DynamicImage::ImageRgb8(image::RgbImage::new(3, 3)));
+        let post = PostProcess { bit_depth: Some(16), grayscale: true, ..PostProcess::default() };
+        for format in ["png", "tiff"] {
+            let path = dir.join(format!("gray16.{format}"));
+            save_image(&source, "image/png", format, &path, &post).unwrap();
+            assert_eq!(image::open(&path).unwrap().color(), image::ColorType::L16);
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn preview_file_names_are_recognized() {
+        let name = preview_file_name("3f2a9c1e7b4d5a60", 2, "png");
+        assert_eq!(name, "3f2a9c1e7b4d5a60-2.png");
+        assert!(is_preview_file_name(&name));
+        for other in ["reference.png", "3f2a9c1e7b4d5a60.png", "3F2A9C1E7B4D5A60-2.png", "a-1.png"]
+        {
+            assert!(!is_preview_file_name(other), "{other}");
+        }
+    }
+
+    #[test]
+    fn thumbnail_fits_the_box_and_flattens_transparency() {
+        let dir = std::env::temp_dir().join(format!("imagen_thumb_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("cat.png");
+        image::RgbaImage::new(400, 200).save(&path).unwrap();
+
+        let thumbnail = save_thumbnail(&path, 100).unwrap();
+        assert_eq!(thumbnail, dir.join("cat.thumb.jpg"));
+        let small = image::open(&thumbnail).unwrap().to_rgb8();
+        assert_eq!(small.dimensions(), (100, 50));
+        assert!(small.get_pixel(50, 25).0.iter().all(|&c| c > 250), "transparent is white");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn staged_files_appear_only_on_commit() {
+        let dir = std::env::temp_dir().join(format!("imagen_staging_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, second) = (dir.join("cat-1.png"), dir.join("cat-2.png"));
+
+        let staging = Staging::new();
+        for path in [&first, &second] {
+            std::fs::write(staging.stage(path).unwrap(), b"png").unwrap();
+        }
+        let staged_sidecar = sidecar_path(&staging.dir_for(&second).join("cat-2.png"));
+        std::fs::write(staged_sidecar, b"{}").unwrap();
+        assert!(!first.exists() && !second.exists());
+        assert_eq!(staging.commit().unwrap(), vec![first.clone(), second.clone()]);
+        assert!(first.exists() && second.exists() && sidecar_path(&second).exists());
+        // Only the two images and a sidecar are left; the staging directory is gone.
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+
+        let third = dir.join("cat-3.png");
+        let staging = Staging::new();
+        std::fs::write(staging.stage(&third).unwrap(), b"png").unwrap();
+        drop(staging);
+        assert!(!third.exists());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
 }
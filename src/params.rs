@@ -1,6 +1,7 @@
 //! Parameter translation between CLI inputs and provider-specific formats.
 
 use crate::model::Provider;
+use crate::report;
 
 /// Translate an aspect ratio string to `OpenAI` pixel dimensions.
 ///
@@ -17,20 +18,234 @@ pub fn aspect_ratio_to_openai_size(ratio: &str) -> &'static str {
     }
 }
 
+/// Translate an aspect ratio to pixel dimensions with the given long edge.
+///
+/// Both sides are rounded to multiples of 64, as Stable Diffusion models expect.
+/// Unparseable ratios fall back to a square.
+#[must_use]
+pub fn aspect_ratio_to_dimensions(ratio: &str, long_edge: u32) -> (u32, u32) {
+    let parsed = ratio
+        .split_once(':')
+        .and_then(|(w, h)| Some((w.parse::<u32>().ok()?, h.parse::<u32>().ok()?)))
+        .filter(|&(w, h)| w > 0 && h > 0);
+    let Some((w, h)) = parsed else {
+        return (long_edge, long_edge);
+    };
+    let round = |v: u64| -> u32 {
+        let rounded = ((v + 32) / 64 * 64).max(64);
+        u32::try_from(rounded).unwrap_or(u32::MAX)
+    };
+    let long = u64::from(long_edge);
+    if w >= h {
+        (long_edge, round(long * u64::from(h) / u64::from(w)))
+    } else {
+        (round(long * u64::from(w) / u64::from(h)), long_edge)
+    }
+}
+
+/// Image sizes every provider accepts.
+pub const SIZES: &[&str] = &["1K", "2K", "4K"];
+
+/// Quality levels, for the providers that honor them.
+pub const QUALITIES: &[&str] = &["auto", "low", "medium", "high"];
+
+/// Gemini thinking levels.
+pub const THINKING_LEVELS: &[&str] = &["none", "minimal", "low", "medium", "high"];
+
+/// The aspect ratios providers offer, for building an
+/// [`ImageRequest`](crate::ports::ImageRequest) without spelling out `W:H`. Which of them
+/// a model can generate is in [`aspect_ratios`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AspectRatio {
+    /// `1:1`.
+    Square1x1,
+    /// `2:3`.
+    Portrait2x3,
+    /// `3:2`.
+    Landscape3x2,
+    /// `3:4`.
+    Portrait3x4,
+    /// `4:3`.
+    Landscape4x3,
+    /// `4:5`.
+    Portrait4x5,
+    /// `5:4`.
+    Landscape5x4,
+    /// `9:16`.
+    Tall9x16,
+    /// `16:9`.
+    Wide16x9,
+    /// `9:21`.
+    Tall9x21,
+    /// `21:9`.
+    Wide21x9,
+}
+
+impl AspectRatio {
+    /// The ratio as `W:H`.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Square1x1 => "1:1",
+            Self::Portrait2x3 => "2:3",
+            Self::Landscape3x2 => "3:2",
+            Self::Portrait3x4 => "3:4",
+            Self::Landscape4x3 => "4:3",
+            Self::Portrait4x5 => "4:5",
+            Self::Landscape5x4 => "5:4",
+            Self::Tall9x16 => "9:16",
+            Self::Wide16x9 => "16:9",
+            Self::Tall9x21 => "9:21",
+            Self::Wide21x9 => "21:9",
+        }
+    }
+}
+
+impl From<AspectRatio> for String {
+    fn from(ratio: AspectRatio) -> Self {
+        ratio.as_str().to_string()
+    }
+}
+
+/// Aspect ratios the given provider can generate.
+#[must_use]
+pub fn aspect_ratios(provider: Provider) -> &'static [&'static str] {
+    const GEMINI: &[&str] =
+        &["1:1", "2:3", "3:2", "3:4", "4:3", "4:5", "5:4", "9:16", "16:9", "21:9"];
+    const OPENAI: &[&str] =
+        &["1:1", "16:9", "9:16", "3:2", "2:3", "4:3", "3:4", "5:4", "4:5", "21:9"];
+    const STABILITY: &[&str] = &["1:1", "16:9", "9:16", "3:2", "2:3", "5:4", "4:5", "21:9", "9:21"];
+    const REPLICATE: &[&str] =
+        &["1:1", "16:9", "9:16", "3:2", "2:3", "4:3", "3:4", "5:4", "4:5", "21:9", "9:21"];
+    const IDEOGRAM: &[&str] = &["1:1", "16:9", "9:16", "3:2", "2:3", "4:3", "3:4", "5:4", "4:5"];
+
+    match provider {
+        Provider::Gemini => GEMINI,
+        Provider::OpenAi | Provider::AzureOpenAi => OPENAI,
+        Provider::Stability | Provider::Bedrock => STABILITY,
+        Provider::Replicate | Provider::SdLocal | Provider::Together => REPLICATE,
+        Provider::Ideogram => IDEOGRAM,
+    }
+}
+
+/// Sizes the given model renders differently, smallest first; larger ones render as the
+/// last. Empty when the provider ignores `--size`.
+#[must_use]
+pub fn supported_sizes(provider: Provider, model: &str) -> &'static [&'static str] {
+    match provider {
+        Provider::Gemini | Provider::SdLocal => SIZES,
+        // Nova Canvas renders 2K and 4K at 2048px, Flux on Together at 1440px.
+        Provider::Bedrock if model.starts_with("amazon.nova-canvas") => &SIZES[..2],
+        Provider::Together => &SIZES[..2],
+        Provider::Bedrock if model.starts_with("stability.") => &[],
+        Provider::OpenAi | Provider::AzureOpenAi | Provider::Bedrock => &SIZES[..1],
+        Provider::Stability | Provider::Replicate | Provider::Ideogram => &[],
+    }
+}
+
+/// Quality levels the given model honors; empty when it ignores `--quality`.
+#[must_use]
+pub fn supported_qualities(provider: Provider, model: &str) -> &'static [&'static str] {
+    match provider {
+        Provider::Bedrock if model.starts_with("stability.") => &[],
+        Provider::OpenAi | Provider::AzureOpenAi | Provider::Bedrock | Provider::Ideogram => {
+            QUALITIES
+        }
+        _ => &[],
+    }
+}
+
+/// Warnings for a `size` or `quality` the model ignores or cannot render as asked.
+#[must_use]
+pub fn parameter_warnings(
+    size: &str,
+    quality: &str,
+    provider: Provider,
+    model: &str,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+    let sizes = supported_sizes(provider, model);
+    match sizes.last() {
+        None if size != "1K" => {
+            warnings.push(format!("{provider:?} has no size setting; --size {size} has no effect"));
+        }
+        Some(_) if matches!(provider, Provider::OpenAi | Provider::AzureOpenAi) && size != "1K" => {
+            warnings.push(format!(
+                "{model} has no {size} size; it is sent as \"auto\", which ignores the aspect \
+                 ratio. Use --size 1K, or --width and --height to scale up"
+            ));
+        }
+        Some(largest) if !sizes.contains(&size) => {
+            warnings.push(format!(
+                "{model} renders at most {largest}; --size {size} renders as {largest}"
+            ));
+        }
+        _ => {}
+    }
+    if supported_qualities(provider, model).is_empty() && quality != "auto" {
+        warnings.push(format!(
+            "{provider:?} has no quality setting; --quality {quality} has no effect"
+        ));
+    }
+    warnings
+}
+
+/// Print [`parameter_warnings`] to stderr.
+pub fn warn_unsupported(size: &str, quality: &str, provider: Provider, model: &str) {
+    for warning in parameter_warnings(size, quality, provider, model) {
+        report::warn(warning);
+    }
+}
+
+/// Most images the given provider returns for one request, or `None` when it renders
+/// them one at a time or the limit depends on the model.
+#[must_use]
+pub fn max_count(provider: Provider) -> Option<u32> {
+    match provider {
+        Provider::Gemini => Some(1),
+        Provider::OpenAi | Provider::AzureOpenAi => Some(10),
+        Provider::Bedrock => Some(5),
+        Provider::Together => Some(4),
+        Provider::Ideogram => Some(8),
+        Provider::Stability | Provider::Replicate | Provider::SdLocal => None,
+    }
+}
+
+/// Whether `provider` ignores `count` and returns a single image per call, so `--count`
+/// has to be served with one call per image.
+#[must_use]
+pub fn one_image_per_call(provider: Provider) -> bool {
+    matches!(provider, Provider::Gemini | Provider::Stability)
+}
+
+/// Validate `--count` against the provider's per-request limit. Over the limit, the
+/// images are only sent as several requests with `split`, unless the provider renders
+/// them one at a time anyway.
+///
+/// # Errors
+///
+/// Returns an error if `count` is zero, or over the limit without `split`.
+pub fn validate_count(count: u32, provider: Provider, split: bool) -> Result<(), String> {
+    if count == 0 {
+        return Err("--count must be at least 1".to_string());
+    }
+    match max_count(provider) {
+        Some(max) if count > max && !split && !one_image_per_call(provider) => Err(format!(
+            "{provider:?} returns at most {max} images per request. Lower --count, or pass \
+             --split to send {} requests",
+            count.div_ceil(max)
+        )),
+        _ => Ok(()),
+    }
+}
+
 /// Validate that an aspect ratio is supported by the given provider.
 ///
 /// # Errors
 ///
 /// Returns an error if the ratio is not recognized.
 pub fn validate_aspect_ratio(ratio: &str, provider: Provider) -> Result<(), String> {
-    let valid_gemini = ["1:1", "2:3", "3:2", "3:4", "4:3", "4:5", "5:4", "9:16", "16:9", "21:9"];
-    let valid_openai = ["1:1", "16:9", "9:16", "3:2", "2:3", "4:3", "3:4", "5:4", "4:5", "21:9"];
-
-    let valid = match provider {
-        Provider::Gemini => &valid_gemini[..],
-        Provider::OpenAi => &valid_openai[..],
-    };
-
+    let valid = aspect_ratios(provider);
     if valid.contains(&ratio) {
         Ok(())
     } else {
@@ -38,15 +253,123 @@ pub fn validate_aspect_ratio(ratio: &str, provider: Provider) -> Result<(), Stri
     }
 }
 
+/// The aspect ratio to request from `provider` for `ratio`: the ratio itself when the
+/// provider supports it, else the supported ratio nearest in shape. `ratio` may be any
+/// `W:H`, with decimals (`2.39:1`).
+///
+/// # Errors
+///
+/// Returns an error if `ratio` is not `W:H`, or, with `strict`, if the provider does not
+/// support it exactly.
+///
+/// # Panics
+///
+/// Panics if the provider's table of [`aspect_ratios`] is empty or holds a ratio that is
+/// not `W:H`.
+pub fn fit_aspect_ratio(ratio: &str, provider: Provider, strict: bool) -> Result<String, String> {
+    let valid = aspect_ratios(provider);
+    if valid.contains(&ratio) {
+        return Ok(ratio.to_string());
+    }
+    let Some(wanted) = ratio_value(ratio) else {
+        return Err(format!("Invalid aspect ratio '{ratio}'. Use W:H, such as 16:9 or 2.39:1"));
+    };
+    if strict {
+        validate_aspect_ratio(ratio, provider)?;
+    }
+    // Compare shapes on a log scale, so 2:1 and 1:2 are equally far from 1:1.
+    let distance = |candidate: &&str| {
+        let value = ratio_value(candidate).expect("supported ratios are W:H");
+        (value.ln() - wanted.ln()).abs()
+    };
+    let nearest = valid
+        .iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .expect("every provider supports some ratio");
+    Ok((*nearest).to_string())
+}
+
+/// [`fit_aspect_ratio`], not strict, with a note on stderr when the ratio changes.
+///
+/// # Errors
+///
+/// Returns an error if `ratio` is not `W:H`.
+pub fn nearest_aspect_ratio(ratio: &str, provider: Provider) -> Result<String, String> {
+    let nearest = fit_aspect_ratio(ratio, provider, false)?;
+    if nearest != ratio {
+        tracing::info!(
+            "Note: {provider:?} has no {ratio} aspect ratio; using the nearest, {nearest}"
+        );
+    }
+    Ok(nearest)
+}
+
+/// The aspect ratio and size to generate for an exact `width` x `height` image: the
+/// provider's nearest ratio, at the smallest size whose long edge covers the larger side,
+/// or the model's largest when none does.
+///
+/// # Panics
+///
+/// Panics if `width` or `height` is zero.
+#[must_use]
+pub fn generation_size(
+    width: u32,
+    height: u32,
+    provider: Provider,
+    model: &str,
+) -> (String, String) {
+    let ratio = fit_aspect_ratio(&format!("{width}:{height}"), provider, false)
+        .expect("positive dimensions form a ratio");
+    let covering = match width.max(height) {
+        0..=1024 => 0,
+        1025..=2048 => 1,
+        _ => 2,
+    };
+    let sizes = supported_sizes(provider, model);
+    let size = sizes.get(covering).or(sizes.last()).unwrap_or(&"1K");
+    (ratio, (*size).to_string())
+}
+
+/// Width over height for a `W:H` ratio whose sides are positive numbers.
+fn ratio_value(ratio: &str) -> Option<f64> {
+    let (width, height) = ratio.split_once(':')?;
+    let width: f64 = width.trim().parse().ok()?;
+    let height: f64 = height.trim().parse().ok()?;
+    let value = width / height;
+    (width > 0.0 && height > 0.0 && value.is_finite()).then_some(value)
+}
+
+/// Split an `--aspect-ratio` value into the ratios to generate, in order.
+///
+/// `1:1,16:9,9:16` sweeps three ratios; a single ratio is a sweep of one.
+///
+/// # Errors
+///
+/// Returns an error for an empty entry or a ratio listed twice.
+pub fn parse_ratio_list(value: &str) -> Result<Vec<String>, String> {
+    let mut ratios: Vec<String> = Vec::new();
+    for ratio in value.split(',').map(str::trim) {
+        if ratio.is_empty() {
+            return Err(format!("Empty entry in aspect ratio list '{value}'"));
+        }
+        if ratios.iter().any(|r| r == ratio) {
+            return Err(format!("Aspect ratio '{ratio}' is listed twice"));
+        }
+        ratios.push(ratio.to_string());
+    }
+    Ok(ratios)
+}
+
 /// Validate the image size parameter.
 ///
 /// # Errors
 ///
 /// Returns an error if the size is not recognized.
 pub fn validate_size(size: &str) -> Result<(), String> {
-    match size {
-        "1K" | "2K" | "4K" => Ok(()),
-        _ => Err(format!("Unsupported size '{size}'. Valid: 1K, 2K, 4K")),
+    if SIZES.contains(&size) {
+        Ok(())
+    } else {
+        Err(format!("Unsupported size '{size}'. Valid: {}", SIZES.join(", ")))
     }
 }
 
@@ -56,9 +379,10 @@ pub fn validate_size(size: &str) -> Result<(), String> {
 ///
 /// Returns an error if the quality value is not recognized.
 pub fn validate_quality(quality: &str) -> Result<(), String> {
-    match quality {
-        "auto" | "low" | "medium" | "high" => Ok(()),
-        _ => Err(format!("Unsupported quality '{quality}'. Valid: auto, low, medium, high")),
+    if QUALITIES.contains(&quality) {
+        Ok(())
+    } else {
+        Err(format!("Unsupported quality '{quality}'. Valid: {}", QUALITIES.join(", ")))
     }
 }
 
@@ -69,11 +393,63 @@ pub fn validate_quality(quality: &str) -> Result<(), String> {
 /// Returns an error if the format is not recognized.
 pub fn validate_format(format: &str) -> Result<(), String> {
     match format {
-        "jpeg" | "png" | "webp" => Ok(()),
-        _ => Err(format!("Unsupported format '{format}'. Valid: jpeg, png, webp")),
+        "jpeg" | "png" | "webp" | "tiff" | "bmp" | "gif" | "avif" => Ok(()),
+        _ => Err(format!(
+            "Unsupported format '{format}'. Valid: jpeg, png, webp, tiff, bmp, gif, avif"
+        )),
+    }
+}
+
+/// Format to request from the provider for a given output format.
+///
+/// Providers only return jpeg, png, or webp; `tiff`, `bmp`, `gif`, and `avif` are produced
+/// locally by converting a lossless png.
+#[must_use]
+pub fn provider_format(format: &str) -> &str {
+    match format {
+        "tiff" | "bmp" | "gif" | "avif" => "png",
+        other => other,
+    }
+}
+
+/// Validate the output bit depth for the target format.
+///
+/// # Errors
+///
+/// Returns an error if the depth is not 8 or 16, or the format cannot store 16-bit samples.
+pub fn validate_bit_depth(depth: u8, format: &str) -> Result<(), String> {
+    match depth {
+        8 => Ok(()),
+        16 if matches!(format, "png" | "tiff") => Ok(()),
+        16 => Err(format!(
+            "--bit-depth 16 requires png or tiff output; {format} stores 8 bits per channel"
+        )),
+        _ => Err(format!("Unsupported bit depth '{depth}'. Valid: 8, 16")),
+    }
+}
+
+/// Validate `--compression` for the target format.
+///
+/// # Errors
+///
+/// Returns an error unless the format has a lossy encoder with a quality setting.
+pub fn validate_compression(format: &str) -> Result<(), String> {
+    match format {
+        "jpeg" | "webp" | "avif" => Ok(()),
+        _ => Err(format!(
+            "--compression applies to jpeg, webp, and avif output; {format} has no quality setting"
+        )),
     }
 }
 
+/// Whether the provider encodes `format` at the requested `--compression` itself (`OpenAI`'s
+/// `output_compression`), so the image is saved as returned instead of re-encoded.
+#[must_use]
+pub fn provider_compresses(format: &str, provider: Provider) -> bool {
+    matches!(provider, Provider::OpenAi | Provider::AzureOpenAi)
+        && matches!(format, "jpeg" | "webp")
+}
+
 /// Validate the thinking level parameter (Gemini only).
 ///
 /// # Errors
@@ -83,14 +459,210 @@ pub fn validate_thinking(thinking: &str, provider: Provider) -> Result<(), Strin
     if provider != Provider::Gemini {
         return Err("--thinking is only supported for Gemini models".to_string());
     }
-    match thinking {
-        "none" | "minimal" | "low" | "medium" | "high" => Ok(()),
+    if THINKING_LEVELS.contains(&thinking) {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unsupported thinking level '{thinking}'. Valid: {}",
+            THINKING_LEVELS.join(", ")
+        ))
+    }
+}
+
+/// Validate the magic prompt mode (Ideogram only).
+///
+/// # Errors
+///
+/// Returns an error if the mode is not recognized or the provider is not Ideogram.
+pub fn validate_magic_prompt(mode: &str, provider: Provider) -> Result<(), String> {
+    if provider != Provider::Ideogram {
+        return Err("--magic-prompt is only supported for Ideogram models".to_string());
+    }
+    match mode {
+        "auto" | "on" | "off" => Ok(()),
+        _ => Err(format!("Unsupported magic prompt mode '{mode}'. Valid: auto, on, off")),
+    }
+}
+
+/// Validate the content moderation level (`OpenAI` only).
+///
+/// # Errors
+///
+/// Returns an error if the level is not recognized or the provider is not `OpenAI`.
+pub fn validate_moderation(level: &str, provider: Provider) -> Result<(), String> {
+    if !matches!(provider, Provider::OpenAi | Provider::AzureOpenAi) {
+        return Err("--moderation is only supported for OpenAI models".to_string());
+    }
+    match level {
+        "auto" | "low" => Ok(()),
+        _ => Err(format!("Unsupported moderation level '{level}'. Valid: auto, low")),
+    }
+}
+
+/// Translate a `--safety` level to Gemini's `HarmBlockThreshold`.
+///
+/// Levels name how much is blocked, from `none` (nothing) to `most`.
+#[must_use]
+pub fn gemini_safety_threshold(level: &str) -> Option<&'static str> {
+    match level {
+        "none" => Some("BLOCK_NONE"),
+        "few" => Some("BLOCK_ONLY_HIGH"),
+        "some" => Some("BLOCK_MEDIUM_AND_ABOVE"),
+        "most" => Some("BLOCK_LOW_AND_ABOVE"),
+        _ => None,
+    }
+}
+
+/// Validate the safety filter level (Gemini only).
+///
+/// # Errors
+///
+/// Returns an error if the level is not recognized or the provider is not Gemini.
+pub fn validate_safety(level: &str, provider: Provider) -> Result<(), String> {
+    if provider != Provider::Gemini {
+        return Err("--safety is only supported for Gemini models".to_string());
+    }
+    match gemini_safety_threshold(level) {
+        Some(_) => Ok(()),
+        None => Err(format!("Unsupported safety level '{level}'. Valid: none, few, some, most")),
+    }
+}
+
+/// Validate the style type (Ideogram only).
+///
+/// # Errors
+///
+/// Returns an error if the style is not recognized or the provider is not Ideogram.
+pub fn validate_style_type(style: &str, provider: Provider) -> Result<(), String> {
+    if provider != Provider::Ideogram {
+        return Err("--style-type is only supported for Ideogram models".to_string());
+    }
+    match style {
+        "auto" | "general" | "realistic" | "design" | "fiction" => Ok(()),
         _ => Err(format!(
-            "Unsupported thinking level '{thinking}'. Valid: none, minimal, low, medium, high"
+            "Unsupported style type '{style}'. Valid: auto, general, realistic, design, fiction"
         )),
     }
 }
 
+/// Validate an `OpenAI`-compatible base URL (`OpenAI` only).
+///
+/// # Errors
+///
+/// Returns an error if the provider is not `OpenAI` or the URL is not http(s).
+pub fn validate_api_base(url: &str, provider: Provider) -> Result<(), String> {
+    if provider != Provider::OpenAi {
+        return Err("--api-base is only supported for OpenAI models".to_string());
+    }
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else {
+        Err(format!("Invalid API base '{url}'. Expected an http:// or https:// URL"))
+    }
+}
+
+/// Validate an `imagen edit` invocation (Gemini and `OpenAI` only).
+///
+/// # Errors
+///
+/// Returns an error if no input image was given or the provider cannot edit images.
+pub fn validate_edit(input_count: usize, provider: Provider) -> Result<(), String> {
+    if !matches!(provider, Provider::Gemini | Provider::OpenAi | Provider::AzureOpenAi) {
+        return Err(format!(
+            "{provider:?} models cannot edit images. Use a Gemini or OpenAI model with imagen edit"
+        ));
+    }
+    if input_count == 0 {
+        return Err("imagen edit needs at least one --input image to modify".to_string());
+    }
+    Ok(())
+}
+
+/// Validate that the provider accepts `--ref` style/composition reference images.
+///
+/// # Errors
+///
+/// Returns an error for providers other than Gemini.
+pub fn validate_reference_images(provider: Provider) -> Result<(), String> {
+    if provider == Provider::Gemini {
+        Ok(())
+    } else {
+        Err(format!(
+            "--ref is only supported for Gemini models, not {provider:?}. Use -i to pass input \
+             images instead"
+        ))
+    }
+}
+
+/// Largest input image a provider accepts, per its API documentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputLimit {
+    /// Most pixels (width x height) per image, if limited.
+    pub max_pixels: Option<u64>,
+    /// Most encoded bytes per image, if limited.
+    pub max_bytes: Option<usize>,
+}
+
+/// Input image limits of `provider`'s edit and image-to-image endpoints.
+#[must_use]
+pub fn input_limit(provider: Provider) -> InputLimit {
+    const MB: usize = 1024 * 1024;
+    let (max_pixels, max_bytes) = match provider {
+        // Larger images are tiled at 3072x3072 anyway; inline data is capped at 7 MB.
+        Provider::Gemini => (Some(3072 * 3072), Some(7 * MB)),
+        Provider::OpenAi | Provider::AzureOpenAi => (None, Some(50 * MB)),
+        Provider::Stability => (Some(9_437_184), Some(10 * MB)),
+        Provider::Bedrock => (Some(4_194_304), None),
+        Provider::Ideogram => (None, Some(10 * MB)),
+        Provider::Replicate | Provider::Together | Provider::SdLocal => (None, None),
+    };
+    InputLimit { max_pixels, max_bytes }
+}
+
+/// Validate that the provider accepts a seed (`--seed`).
+///
+/// # Errors
+///
+/// Returns an error for `OpenAI` models, whose API has no seed parameter.
+pub fn validate_seed(provider: Provider) -> Result<(), String> {
+    if matches!(provider, Provider::OpenAi | Provider::AzureOpenAi) {
+        Err(format!("--seed is not supported for {provider:?} models"))
+    } else {
+        Ok(())
+    }
+}
+
+/// Validate that the provider can deliver images by URL (`--stream-download`).
+///
+/// # Errors
+///
+/// Returns an error for providers that only return image bytes inline.
+pub fn validate_stream_download(provider: Provider) -> Result<(), String> {
+    if matches!(provider, Provider::Replicate | Provider::Together | Provider::Ideogram) {
+        Ok(())
+    } else {
+        Err(format!(
+            "--stream-download needs a provider that returns image URLs (Replicate, Together, \
+             Ideogram), not {provider:?}"
+        ))
+    }
+}
+
+/// Validate that the provider can stream previews while rendering (`--stream`).
+///
+/// # Errors
+///
+/// Returns an error for providers other than `OpenAI`, or for more than one image.
+pub fn validate_stream(provider: Provider, count: u32) -> Result<(), String> {
+    if !matches!(provider, Provider::OpenAi | Provider::AzureOpenAi) {
+        return Err(format!("--stream is only supported for OpenAI models, not {provider:?}"));
+    }
+    if count > 1 {
+        return Err("--stream previews a single image; use it without --count".to_string());
+    }
+    Ok(())
+}
+
 /// Validate the background parameter (`OpenAI` only).
 ///
 /// # Errors
@@ -102,14 +674,14 @@ pub fn validate_background(
     format: &str,
     provider: Provider,
 ) -> Result<(), String> {
-    if provider == Provider::Gemini {
+    if !matches!(provider, Provider::OpenAi | Provider::AzureOpenAi) {
         return Err("--background is only supported for OpenAI models".to_string());
     }
     match background {
-        "auto" | "transparent" => {}
+        "auto" | "transparent" | "opaque" => {}
         _ => {
             return Err(format!(
-                "Unsupported background '{background}'. Valid: auto, transparent"
+                "Unsupported background '{background}'. Valid: auto, transparent, opaque"
             ))
         }
     }
@@ -122,6 +694,27 @@ pub fn validate_background(
     Ok(())
 }
 
+/// Split an `--input` value into an optional role label and a path.
+///
+/// `ROLE=PATH` attaches a role (letters, digits, `-`, `_`); anything else is a plain path.
+/// A value that names an existing file is always treated as a plain path.
+#[must_use]
+pub fn parse_input_spec(spec: &str) -> (Option<String>, String) {
+    if std::path::Path::new(spec).exists() {
+        return (None, spec.to_string());
+    }
+    match spec.split_once('=') {
+        Some((role, path))
+            if !role.is_empty()
+                && !path.is_empty()
+                && role.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') =>
+        {
+            (Some(role.to_string()), path.to_string())
+        }
+        _ => (None, spec.to_string()),
+    }
+}
+
 /// Validate that input image paths exist and have recognized image extensions.
 ///
 /// # Errors
@@ -168,6 +761,10 @@ pub fn format_extension(format: &str) -> &'static str {
     match format {
         "png" => "png",
         "webp" => "webp",
+        "tiff" => "tiff",
+        "bmp" => "bmp",
+        "gif" => "gif",
+        "avif" => "avif",
         // jpeg and any unknown format default to jpg
         _ => "jpg",
     }
@@ -204,6 +801,22 @@ mod tests {
         assert_eq!(aspect_ratio_to_openai_size("7:3"), "auto");
     }
 
+    #[test]
+    fn aspect_ratio_dimensions() {
+        assert_eq!(aspect_ratio_to_dimensions("1:1", 1024), (1024, 1024));
+        assert_eq!(aspect_ratio_to_dimensions("16:9", 1024), (1024, 576));
+        assert_eq!(aspect_ratio_to_dimensions("2:3", 1024), (704, 1024));
+        assert_eq!(aspect_ratio_to_dimensions("bogus", 512), (512, 512));
+    }
+
+    #[test]
+    fn ratio_lists() {
+        assert_eq!(parse_ratio_list("16:9").unwrap(), ["16:9"]);
+        assert_eq!(parse_ratio_list("1:1, 16:9,9:16").unwrap(), ["1:1", "16:9", "9:16"]);
+        assert!(parse_ratio_list("1:1,,16:9").unwrap_err().contains("Empty entry"));
+        assert!(parse_ratio_list("1:1,1:1").unwrap_err().contains("listed twice"));
+    }
+
     #[test]
     fn validate_aspect_ratio_gemini() {
         assert!(validate_aspect_ratio("1:1", Provider::Gemini).is_ok());
@@ -217,6 +830,80 @@ mod tests {
         assert!(validate_aspect_ratio("16:9", Provider::OpenAi).is_ok());
     }
 
+    #[test]
+    fn validate_aspect_ratio_stability() {
+        assert!(validate_aspect_ratio("9:21", Provider::Stability).is_ok());
+        assert!(validate_aspect_ratio("4:3", Provider::Stability).is_err());
+    }
+
+    #[test]
+    fn exact_dimensions_pick_the_covering_size() {
+        let size = |width, height| generation_size(width, height, Provider::Gemini, "gemini");
+        assert_eq!(size(1920, 1080), ("16:9".to_string(), "2K".to_string()));
+        assert_eq!(size(512, 512), ("1:1".to_string(), "1K".to_string()));
+        assert_eq!(size(1080, 1350), ("4:5".to_string(), "2K".to_string()));
+        assert_eq!(size(5000, 2100), ("21:9".to_string(), "4K".to_string()));
+        let openai = generation_size(3840, 2160, Provider::OpenAi, "gpt-image-1");
+        assert_eq!(openai, ("16:9".to_string(), "1K".to_string()));
+        let core = generation_size(3840, 2160, Provider::Stability, "stable-image-core");
+        assert_eq!(core.1, "1K");
+    }
+
+    #[test]
+    fn counts_over_the_request_limit_need_split() {
+        assert!(validate_count(10, Provider::OpenAi, false).is_ok());
+        assert_eq!(
+            validate_count(12, Provider::OpenAi, false).unwrap_err(),
+            "OpenAi returns at most 10 images per request. Lower --count, or pass --split to \
+             send 2 requests"
+        );
+        assert!(validate_count(12, Provider::OpenAi, true).is_ok());
+        assert!(validate_count(6, Provider::Gemini, false).is_ok());
+        assert!(validate_count(0, Provider::Gemini, true).is_err());
+    }
+
+    #[test]
+    fn sizes_and_qualities_the_model_ignores_are_warned_about() {
+        let warnings = |size, quality, provider, model| {
+            parameter_warnings(size, quality, provider, model).join("\n")
+        };
+        assert_eq!(warnings("4K", "auto", Provider::Gemini, "gemini-3-pro-image-preview"), "");
+        assert_eq!(
+            warnings("1K", "high", Provider::Gemini, "gemini-3-pro-image-preview"),
+            "Gemini has no quality setting; --quality high has no effect"
+        );
+        assert!(warnings("4K", "high", Provider::OpenAi, "gpt-image-1")
+            .starts_with("gpt-image-1 has no 4K size; it is sent as \"auto\""));
+        assert_eq!(
+            warnings("4K", "high", Provider::Bedrock, "amazon.nova-canvas-v1:0"),
+            "amazon.nova-canvas-v1:0 renders at most 2K; --size 4K renders as 2K"
+        );
+        assert_eq!(
+            warnings("2K", "low", Provider::Bedrock, "stability.sd3-5-large-v1:0"),
+            "Bedrock has no size setting; --size 2K has no effect\n\
+             Bedrock has no quality setting; --quality low has no effect"
+        );
+    }
+
+    #[test]
+    fn any_ratio_fits_the_nearest_supported_one() {
+        let fit = |ratio, provider| fit_aspect_ratio(ratio, provider, false).unwrap();
+        assert_eq!(fit("16:9", Provider::Gemini), "16:9");
+        assert_eq!(fit("2.39:1", Provider::Gemini), "21:9");
+        assert_eq!(fit("1.85:1", Provider::OpenAi), "16:9");
+        assert_eq!(fit("1:2", Provider::Gemini), "9:16");
+        assert_eq!(fit("4:3", Provider::Stability), "5:4");
+        assert!(fit_aspect_ratio("2.39:1", Provider::Gemini, true)
+            .unwrap_err()
+            .contains("Unsupported aspect ratio"));
+        for bad in ["wide", "16x9", "0:1", "1:0", "-4:3", "inf:1"] {
+            assert!(
+                fit_aspect_ratio(bad, Provider::Gemini, false).unwrap_err().contains("Invalid"),
+                "{bad} should be rejected"
+            );
+        }
+    }
+
     #[test]
     fn validate_size_valid() {
         assert!(validate_size("1K").is_ok());
@@ -248,12 +935,16 @@ mod tests {
         assert!(validate_format("jpeg").is_ok());
         assert!(validate_format("png").is_ok());
         assert!(validate_format("webp").is_ok());
+        assert!(validate_format("tiff").is_ok());
+        assert!(validate_format("bmp").is_ok());
+        assert!(validate_format("gif").is_ok());
+        assert!(validate_format("avif").is_ok());
     }
 
     #[test]
     fn validate_format_invalid() {
-        assert!(validate_format("gif").is_err());
-        assert!(validate_format("bmp").is_err());
+        assert!(validate_format("heic").is_err());
+        assert!(validate_format("tga").is_err());
     }
 
     #[test]
@@ -280,6 +971,111 @@ mod tests {
         assert_eq!(format_extension("jpeg"), "jpg");
         assert_eq!(format_extension("png"), "png");
         assert_eq!(format_extension("webp"), "webp");
+        assert_eq!(format_extension("tiff"), "tiff");
+        assert_eq!(format_extension("bmp"), "bmp");
+        assert_eq!(format_extension("gif"), "gif");
+        assert_eq!(format_extension("avif"), "avif");
+    }
+
+    #[test]
+    fn provider_format_mapping() {
+        assert_eq!(provider_format("tiff"), "png");
+        assert_eq!(provider_format("bmp"), "png");
+        assert_eq!(provider_format("gif"), "png");
+        assert_eq!(provider_format("avif"), "png");
+        assert_eq!(provider_format("webp"), "webp");
+    }
+
+    #[test]
+    fn validate_bit_depth_values() {
+        assert!(validate_bit_depth(8, "jpeg").is_ok());
+        assert!(validate_bit_depth(16, "png").is_ok());
+        assert!(validate_bit_depth(16, "tiff").is_ok());
+        assert!(validate_bit_depth(16, "bmp").unwrap_err().contains("requires png or tiff"));
+        assert!(validate_bit_depth(12, "png").is_err());
+    }
+
+    #[test]
+    fn validate_edit_values() {
+        assert!(validate_edit(1, Provider::Gemini).is_ok());
+        assert!(validate_edit(2, Provider::AzureOpenAi).is_ok());
+        assert!(validate_edit(0, Provider::OpenAi).is_err());
+        assert!(validate_edit(1, Provider::Stability).unwrap_err().contains("cannot edit"));
+    }
+
+    #[test]
+    fn validate_reference_images_values() {
+        assert!(validate_reference_images(Provider::Gemini).is_ok());
+        assert!(validate_reference_images(Provider::OpenAi).unwrap_err().contains("--ref"));
+    }
+
+    #[test]
+    fn input_limits_per_provider() {
+        assert_eq!(input_limit(Provider::Stability).max_pixels, Some(9_437_184));
+        assert_eq!(input_limit(Provider::OpenAi).max_pixels, None);
+        assert_eq!(
+            input_limit(Provider::SdLocal),
+            InputLimit { max_pixels: None, max_bytes: None }
+        );
+    }
+
+    #[test]
+    fn validate_stream_values() {
+        assert!(validate_stream(Provider::OpenAi, 1).is_ok());
+        assert!(validate_stream(Provider::Gemini, 1).unwrap_err().contains("OpenAI"));
+        assert!(validate_stream(Provider::AzureOpenAi, 2).unwrap_err().contains("--count"));
+    }
+
+    #[test]
+    fn validate_seed_values() {
+        assert!(validate_seed(Provider::Stability).is_ok());
+        assert!(validate_seed(Provider::OpenAi).unwrap_err().contains("--seed"));
+    }
+
+    #[test]
+    fn validate_stream_download_values() {
+        assert!(validate_stream_download(Provider::Together).is_ok());
+        assert!(validate_stream_download(Provider::Gemini).unwrap_err().contains("image URLs"));
+    }
+
+    #[test]
+    fn validate_api_base_values() {
+        assert!(validate_api_base("http://localhost:4000/v1", Provider::OpenAi).is_ok());
+        assert!(validate_api_base("localhost:4000", Provider::OpenAi).is_err());
+        assert!(validate_api_base("https://gw.example.com/v1", Provider::Gemini).is_err());
+    }
+
+    #[test]
+    fn validate_ideogram_options() {
+        assert!(validate_magic_prompt("off", Provider::Ideogram).is_ok());
+        assert!(validate_magic_prompt("always", Provider::Ideogram).is_err());
+        assert!(validate_style_type("design", Provider::Ideogram).is_ok());
+        assert!(validate_style_type("anime", Provider::Ideogram).is_err());
+        let err = validate_style_type("design", Provider::OpenAi).unwrap_err();
+        assert!(err.contains("only supported for Ideogram"), "got: {err}");
+    }
+
+    #[test]
+    fn validate_moderation_and_safety() {
+        assert!(validate_moderation("low", Provider::OpenAi).is_ok());
+        assert!(validate_moderation("auto", Provider::AzureOpenAi).is_ok());
+        assert!(validate_moderation("off", Provider::OpenAi).is_err());
+        assert!(validate_moderation("low", Provider::Gemini).unwrap_err().contains("OpenAI"));
+
+        assert!(validate_safety("few", Provider::Gemini).is_ok());
+        assert!(validate_safety("low", Provider::Gemini).unwrap_err().contains("Valid: none"));
+        assert!(validate_safety("none", Provider::OpenAi).unwrap_err().contains("Gemini"));
+        assert_eq!(gemini_safety_threshold("most"), Some("BLOCK_LOW_AND_ABOVE"));
+    }
+
+    #[test]
+    fn compression_applies_to_lossy_formats() {
+        assert!(validate_compression("jpeg").is_ok());
+        assert!(validate_compression("avif").is_ok());
+        assert!(validate_compression("png").unwrap_err().contains("no quality setting"));
+        assert!(provider_compresses("webp", Provider::AzureOpenAi));
+        assert!(!provider_compresses("avif", Provider::OpenAi));
+        assert!(!provider_compresses("jpeg", Provider::Gemini));
     }
 
     #[test]
@@ -287,6 +1083,7 @@ mod tests {
         assert!(validate_background("auto", "png", Provider::OpenAi).is_ok());
         assert!(validate_background("transparent", "png", Provider::OpenAi).is_ok());
         assert!(validate_background("transparent", "webp", Provider::OpenAi).is_ok());
+        assert!(validate_background("opaque", "jpeg", Provider::AzureOpenAi).is_ok());
     }
 
     #[test]
@@ -322,6 +1119,25 @@ mod tests {
         let _ = std::fs::remove_file(&tmp);
     }
 
+    #[test]
+    fn parse_input_spec_with_role() {
+        assert_eq!(
+            parse_input_spec("subject=person.png"),
+            (Some("subject".to_string()), "person.png".to_string())
+        );
+        assert_eq!(
+            parse_input_spec("style-ref=dir/a=b.jpg"),
+            (Some("style-ref".to_string()), "dir/a=b.jpg".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_input_spec_plain_path() {
+        assert_eq!(parse_input_spec("photo.png"), (None, "photo.png".to_string()));
+        assert_eq!(parse_input_spec("=photo.png"), (None, "=photo.png".to_string()));
+        assert_eq!(parse_input_spec("a b=photo.png"), (None, "a b=photo.png".to_string()));
+    }
+
     #[test]
     fn mime_type_from_extension_valid() {
         assert_eq!(mime_type_from_extension("photo.png").unwrap(), "image/png");
@@ -0,0 +1,544 @@
+//! Pipeline definitions: a YAML file of steps that feed each other's images.
+//!
+//! ```yaml
+//! steps:
+//!   - id: hero
+//!     generate: { prompt: "A minimalist fox logo", model: gpt-1 }
+//!   - id: big
+//!     upscale: { input: hero, factor: 4 }
+//!   - id: variants
+//!     resize: { input: big, sizes: [512x512, 1200x630] }
+//! ```
+//!
+//! Each step names at most one `input` step, so the steps form a DAG. [`Pipeline::waves`]
+//! groups them into waves whose steps depend only on earlier waves and can run together.
+//! Execution lives in `commands::pipeline`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::model::{detect_provider, resolve_model};
+use crate::params::{
+    fit_aspect_ratio, validate_background, validate_edit, validate_format, validate_quality,
+    validate_size,
+};
+
+/// File in the output directory that remembers finished steps, for caching and resume.
+pub const STATE_FILE: &str = ".pipeline-state.json";
+
+/// A parsed and validated pipeline file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Pipeline {
+    /// Directory for step outputs, relative to the pipeline file [default: the file's stem].
+    #[serde(default)]
+    pub output_dir: Option<String>,
+    /// Format of every saved image.
+    #[serde(default = "default_format")]
+    pub format: String,
+    /// Steps, in file order.
+    pub steps: Vec<Step>,
+}
+
+/// One named step.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Step {
+    /// Name other steps use to refer to this step's images; also the output file stem.
+    pub id: String,
+    /// What the step does.
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// The operation a step performs. Every variant except `generate` transforms each image
+/// of its `input` step.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    /// Create images from a prompt.
+    Generate(GenerateStep),
+    /// Modify each input image as the prompt describes.
+    Edit(EditStep),
+    /// Enlarge each input image.
+    Upscale(UpscaleStep),
+    /// Cut each input image's subject out onto a transparent background (`OpenAI` edit).
+    RemoveBg(RemoveBgStep),
+    /// Overlay a logo on each input image.
+    Watermark(WatermarkStep),
+    /// Crop-and-resize each input image to one or more sizes.
+    Resize(ResizeStep),
+}
+
+/// `generate:` options.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenerateStep {
+    /// Text prompt.
+    pub prompt: String,
+    /// Model name or alias.
+    #[serde(default = "default_model")]
+    pub model: String,
+    /// Aspect ratio.
+    #[serde(default = "default_aspect_ratio")]
+    pub aspect_ratio: String,
+    /// Image size: 1K, 2K, 4K.
+    #[serde(default = "default_size")]
+    pub size: String,
+    /// Quality level (`OpenAI`).
+    #[serde(default = "default_quality")]
+    pub quality: String,
+    /// Number of images.
+    #[serde(default = "default_count")]
+    pub count: u32,
+}
+
+/// `edit:` options.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EditStep {
+    /// Step whose images are edited.
+    pub input: String,
+    /// How to change each image.
+    pub prompt: String,
+    /// Model name or alias (Gemini or `OpenAI`).
+    #[serde(default = "default_model")]
+    pub model: String,
+}
+
+/// `upscale:` options.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpscaleStep {
+    /// Step whose images are enlarged.
+    pub input: String,
+    /// Scale factor (2-8).
+    #[serde(default = "default_factor")]
+    pub factor: u32,
+    /// Upscaler: local, stability, replicate.
+    #[serde(default = "default_upscaler", rename = "with")]
+    pub upscaler: String,
+}
+
+/// `remove-bg:` options.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RemoveBgStep {
+    /// Step whose images are cut out.
+    pub input: String,
+    /// `OpenAI` model that performs the edit.
+    #[serde(default = "default_remove_bg_model")]
+    pub model: String,
+}
+
+/// `watermark:` options.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatermarkStep {
+    /// Step whose images are marked.
+    pub input: String,
+    /// Logo image, relative to the pipeline file.
+    pub image: String,
+    /// Corner: top-left, top-right, bottom-left, bottom-right.
+    #[serde(default = "default_position")]
+    pub position: String,
+    /// Logo opacity, 0-1.
+    #[serde(default = "default_opacity")]
+    pub opacity: f32,
+    /// Logo width as a fraction of the image width.
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+/// `resize:` options.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResizeStep {
+    /// Step whose images are resized.
+    pub input: String,
+    /// Target sizes as `WIDTHxHEIGHT`; each image is cropped to fill the size exactly.
+    pub sizes: Vec<String>,
+}
+
+fn default_format() -> String {
+    "png".into()
+}
+fn default_model() -> String {
+    "nano-banana".into()
+}
+fn default_aspect_ratio() -> String {
+    "1:1".into()
+}
+fn default_size() -> String {
+    "1K".into()
+}
+fn default_quality() -> String {
+    "auto".into()
+}
+fn default_count() -> u32 {
+    1
+}
+fn default_factor() -> u32 {
+    2
+}
+fn default_upscaler() -> String {
+    "local".into()
+}
+fn default_remove_bg_model() -> String {
+    "gpt-1".into()
+}
+fn default_position() -> String {
+    "bottom-right".into()
+}
+fn default_opacity() -> f32 {
+    0.5
+}
+fn default_scale() -> f32 {
+    0.2
+}
+
+impl Action {
+    /// The step this one reads its images from, if any.
+    #[must_use]
+    pub fn input(&self) -> Option<&str> {
+        match self {
+            Self::Generate(_) => None,
+            Self::Edit(EditStep { input, .. })
+            | Self::Upscale(UpscaleStep { input, .. })
+            | Self::RemoveBg(RemoveBgStep { input, .. })
+            | Self::Watermark(WatermarkStep { input, .. })
+            | Self::Resize(ResizeStep { input, .. }) => Some(input),
+        }
+    }
+
+    /// Check the step's own options, before anything is sent.
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            Self::Generate(step) => {
+                let provider = detect_provider(&resolve_model(&step.model))?;
+                fit_aspect_ratio(&step.aspect_ratio, provider, false)?;
+                validate_size(&step.size)?;
+                validate_quality(&step.quality)?;
+                if step.count == 0 {
+                    return Err("count must be at least 1".to_string());
+                }
+            }
+            Self::Edit(step) => validate_edit(1, detect_provider(&resolve_model(&step.model))?)?,
+            Self::Upscale(step) => {
+                if !(2..=8).contains(&step.factor) {
+                    return Err(format!("factor must be 2-8, got {}", step.factor));
+                }
+            }
+            Self::RemoveBg(step) => {
+                let provider = detect_provider(&resolve_model(&step.model))?;
+                validate_background("transparent", "png", provider)
+                    .map_err(|_| "remove-bg needs an OpenAI model".to_string())?;
+            }
+            Self::Watermark(step) => {
+                parse_position(&step.position)?;
+                if !(0.0..=1.0).contains(&step.opacity) {
+                    return Err(format!("opacity must be 0-1, got {}", step.opacity));
+                }
+                if !(step.scale > 0.0 && step.scale <= 1.0) {
+                    return Err(format!("scale must be above 0 and at most 1, got {}", step.scale));
+                }
+            }
+            Self::Resize(step) => {
+                if step.sizes.is_empty() {
+                    return Err("sizes must list at least one WIDTHxHEIGHT".to_string());
+                }
+                for size in &step.sizes {
+                    parse_size(size)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse a `WIDTHxHEIGHT` size such as `1200x630`.
+///
+/// # Errors
+///
+/// Returns an error unless both sides are positive integers.
+pub fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    size.split_once('x')
+        .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+        .filter(|&(w, h): &(u32, u32)| w > 0 && h > 0)
+        .ok_or_else(|| format!("Invalid size '{size}'. Expected WIDTHxHEIGHT, e.g. 1200x630"))
+}
+
+/// Parse a watermark corner into `(right, bottom)` flags.
+///
+/// # Errors
+///
+/// Returns an error for anything but the four corner names.
+pub fn parse_position(position: &str) -> Result<(bool, bool), String> {
+    match position {
+        "top-left" => Ok((false, false)),
+        "top-right" => Ok((true, false)),
+        "bottom-left" => Ok((false, true)),
+        "bottom-right" => Ok((true, true)),
+        other => Err(format!(
+            "Invalid position '{other}'. Valid: top-left, top-right, bottom-left, bottom-right"
+        )),
+    }
+}
+
+impl Pipeline {
+    /// Parse and validate a pipeline file's contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for invalid YAML, duplicate or malformed step IDs, references to
+    /// unknown steps, cycles, or invalid step options.
+    pub fn parse(yaml: &str) -> Result<Self, String> {
+        let pipeline: Self = serde_yaml::from_str(yaml).map_err(|e| e.to_string())?;
+        validate_format(&pipeline.format)?;
+        if pipeline.steps.is_empty() {
+            return Err("The pipeline has no steps".to_string());
+        }
+        let mut ids = HashMap::new();
+        for (index, step) in pipeline.steps.iter().enumerate() {
+            let valid = !step.id.is_empty()
+                && step.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+            if !valid {
+                return Err(format!(
+                    "Invalid step id '{}'. Use letters, digits, '-' and '_'",
+                    step.id
+                ));
+            }
+            if ids.insert(step.id.as_str(), index).is_some() {
+                return Err(format!("Duplicate step id '{}'", step.id));
+            }
+        }
+        for step in &pipeline.steps {
+            if let Some(input) = step.action.input() {
+                if !ids.contains_key(input) {
+                    return Err(format!("Step '{}' reads from unknown step '{input}'", step.id));
+                }
+            }
+            step.action.validate().map_err(|e| format!("Step '{}': {e}", step.id))?;
+        }
+        pipeline.waves()?;
+        Ok(pipeline)
+    }
+
+    /// Group step indices into waves: every step's input is in an earlier wave, so the
+    /// steps of one wave can run concurrently. File order is kept within a wave.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the steps caught in a cycle.
+    pub fn waves(&self) -> Result<Vec<Vec<usize>>, String> {
+        let index: HashMap<&str, usize> =
+            self.steps.iter().enumerate().map(|(i, step)| (step.id.as_str(), i)).collect();
+        let mut wave_of: Vec<Option<usize>> = vec![None; self.steps.len()];
+        let mut waves: Vec<Vec<usize>> = Vec::new();
+        let mut placed = 0;
+        while placed < self.steps.len() {
+            let ready: Vec<usize> = (0..self.steps.len())
+                .filter(|&i| wave_of[i].is_none())
+                .filter(|&i| {
+                    self.steps[i].action.input().is_none_or(|input| {
+                        wave_of[index[input]].is_some_and(|wave| wave < waves.len())
+                    })
+                })
+                .collect();
+            if ready.is_empty() {
+                let stuck: Vec<&str> = (0..self.steps.len())
+                    .filter(|&i| wave_of[i].is_none())
+                    .map(|i| self.steps[i].id.as_str())
+                    .collect();
+                return Err(format!("Steps form a cycle: {}", stuck.join(", ")));
+            }
+            for &i in &ready {
+                wave_of[i] = Some(waves.len());
+            }
+            placed += ready.len();
+            waves.push(ready);
+        }
+        Ok(waves)
+    }
+
+    /// Where step outputs go for a pipeline read from `file`.
+    #[must_use]
+    pub fn output_dir(&self, file: &Path) -> std::path::PathBuf {
+        let base = file.parent().unwrap_or(Path::new(""));
+        match self.output_dir {
+            Some(ref dir) => base.join(dir),
+            None => base.join(file.file_stem().unwrap_or_default()),
+        }
+    }
+}
+
+/// Content hash of a step: its options plus every file it reads. A finished step whose
+/// key is unchanged is not run again.
+///
+/// # Panics
+///
+/// Panics if `action` cannot be serialized, which its plain fields rule out.
+#[must_use]
+pub fn cache_key<'a>(action: &Action, inputs: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(serde_json::to_vec(action).expect("pipeline actions are serializable"));
+    for input in inputs {
+        hasher.update(Sha256::digest(input));
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+/// Finished steps of a pipeline run, kept in [`STATE_FILE`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RunState {
+    /// Per step ID: its cache key and the files it wrote (relative to the output dir).
+    pub steps: BTreeMap<String, FinishedStep>,
+}
+
+/// A step that completed.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FinishedStep {
+    /// [`cache_key`] of the run that produced the files.
+    pub key: String,
+    /// Output file names, in order.
+    pub outputs: Vec<String>,
+}
+
+impl RunState {
+    /// Load the state in `dir`; a missing or unreadable file starts afresh.
+    #[must_use]
+    pub fn load(dir: &Path) -> Self {
+        std::fs::read_to_string(dir.join(STATE_FILE))
+            .ok()
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the state to `dir`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save(&self, dir: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(dir.join(STATE_FILE), json)
+    }
+
+    /// The previous outputs of `id`, if it finished with the same `key` and its files
+    /// are all still there.
+    #[must_use]
+    pub fn cached(&self, id: &str, key: &str, dir: &Path) -> Option<&[String]> {
+        self.steps
+            .get(id)
+            .filter(|step| step.key == key)
+            .filter(|step| step.outputs.iter().all(|name| dir.join(name).is_file()))
+            .map(|step| step.outputs.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BRAND_KIT: &str = "
+steps:
+  - id: hero
+    generate: { prompt: a fox logo, model: gpt-1 }
+  - id: big
+    upscale: { input: hero, factor: 4 }
+  - id: cutout
+    remove-bg: { input: big }
+  - id: social
+    resize: { input: hero, sizes: [1200x630] }
+  - id: marked
+    watermark: { input: cutout, image: logo.png }
+";
+
+    #[test]
+    fn parses_steps_with_defaults() {
+        let pipeline = Pipeline::parse(BRAND_KIT).unwrap();
+        assert_eq!(pipeline.format, "png");
+        assert_eq!(pipeline.steps.len(), 5);
+        let Action::Upscale(ref upscale) = pipeline.steps[1].action else { panic!() };
+        assert_eq!((upscale.factor, upscale.upscaler.as_str()), (4, "local"));
+        let Action::Watermark(ref mark) = pipeline.steps[4].action else { panic!() };
+        assert_eq!(mark.position, "bottom-right");
+    }
+
+    #[test]
+    fn waves_follow_dependencies() {
+        let pipeline = Pipeline::parse(BRAND_KIT).unwrap();
+        assert_eq!(pipeline.waves().unwrap(), vec![vec![0], vec![1, 3], vec![2], vec![4]]);
+    }
+
+    #[test]
+    fn invalid_pipelines_are_rejected() {
+        let err = |yaml: &str| Pipeline::parse(yaml).unwrap_err();
+        assert!(err("steps: []").contains("no steps"));
+        assert!(err("steps:\n  - id: a\n    upscale: { input: nope }").contains("unknown step"));
+        assert!(err(
+            "steps:\n  - id: a\n    generate: { prompt: x }\n  - id: a\n    generate: { prompt: y }"
+        )
+        .contains("Duplicate"));
+        assert!(err("steps:\n  - id: a/b\n    generate: { prompt: x }").contains("Invalid step id"));
+        assert!(err(
+            "steps:\n  - id: a\n    upscale: { input: b }\n  - id: b\n    upscale: { input: a }"
+        )
+        .contains("cycle: a, b"));
+        assert!(err("steps:\n  - id: a\n    remove-bg: { input: a, model: nano-banana }")
+            .contains("OpenAI"));
+        assert!(err("steps:\n  - id: a\n    generate: { prompt: x, sizes: [1] }")
+            .contains("unknown field"));
+    }
+
+    #[test]
+    fn sizes_and_positions() {
+        assert_eq!(parse_size("1200x630"), Ok((1200, 630)));
+        assert!(parse_size("0x10").is_err());
+        assert!(parse_size("big").is_err());
+        assert_eq!(parse_position("top-right"), Ok((true, false)));
+        assert!(parse_position("center").is_err());
+    }
+
+    #[test]
+    fn output_dir_defaults_to_file_stem() {
+        let pipeline = Pipeline::parse(BRAND_KIT).unwrap();
+        assert_eq!(
+            pipeline.output_dir(Path::new("kits/brand-kit.yaml")),
+            Path::new("kits/brand-kit")
+        );
+    }
+
+    #[test]
+    fn cache_key_tracks_options_and_inputs() {
+        let pipeline = Pipeline::parse(BRAND_KIT).unwrap();
+        let action = &pipeline.steps[1].action;
+        let key = cache_key(action, [&b"png"[..]]);
+        assert_eq!(key, cache_key(action, [&b"png"[..]]));
+        assert_ne!(key, cache_key(action, [&b"jpg"[..]]));
+        assert_ne!(key, cache_key(&pipeline.steps[2].action, [&b"png"[..]]));
+    }
+
+    #[test]
+    fn cached_outputs_must_still_exist() {
+        let dir = std::env::temp_dir().join("imagen_pipeline_state_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("hero.png"), b"x").unwrap();
+        let mut state = RunState::default();
+        let finished = |outputs: &[&str]| FinishedStep {
+            key: "k".into(),
+            outputs: outputs.iter().map(ToString::to_string).collect(),
+        };
+        state.steps.insert("hero".into(), finished(&["hero.png"]));
+        state.steps.insert("gone".into(), finished(&["gone.png"]));
+        state.save(&dir).unwrap();
+
+        let state = RunState::load(&dir);
+        assert_eq!(state.cached("hero", "k", &dir), Some(&["hero.png".to_string()][..]));
+        assert_eq!(state.cached("hero", "other", &dir), None);
+        assert_eq!(state.cached("gone", "k", &dir), None);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,12 @@
+//! Image editor port for modifying existing images with a text instruction.
+
+use crate::ports::image_generator::{GenerateFuture, ImageRequest};
+
+/// Modifies existing images via an external API.
+///
+/// The source images travel in [`ImageRequest::input_images`] (never empty) and the
+/// prompt describes the change to make, e.g. `"make it nighttime"`.
+pub trait ImageEditor: Send + Sync {
+    /// Edit the request's input images as described by its prompt.
+    fn edit(&self, request: &ImageRequest) -> GenerateFuture<'_>;
+}
@@ -1,11 +1,22 @@
 //! Image generator port for AI image generation APIs.
 
+use std::fmt;
 use std::future::Future;
+use std::ops::AddAssign;
 use std::pin::Pin;
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
+use crate::config::DefaultsConfig;
 use crate::error::ImageError;
+use crate::model::{detect_provider, resolve_model};
+use crate::params::{
+    provider_format, validate_aspect_ratio, validate_background, validate_compression,
+    validate_count, validate_format, validate_magic_prompt, validate_moderation, validate_quality,
+    validate_reference_images, validate_safety, validate_seed, validate_size,
+    validate_stream_download, validate_style_type, validate_thinking,
+};
 
 /// An input/reference image provided by the user.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +28,9 @@ pub struct InputImage {
     pub mime_type: String,
     /// Original filename (e.g., `"photo.png"`).
     pub filename: String,
+    /// Optional role label describing how the image should be used (e.g., `"subject"`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
 }
 
 /// A request to generate images.
@@ -36,15 +50,334 @@ pub struct ImageRequest {
     pub format: String,
     /// Number of images to generate.
     pub count: u32,
+    /// Seed for reproducible results, where the provider accepts one (`--seed`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u32>,
     /// Thinking level for Gemini models (`"none"`, `"minimal"`, `"low"`, `"medium"`, `"high"`).
     #[serde(default)]
     pub thinking: Option<String>,
-    /// Input/reference images for image editing.
+    /// Input/reference images for image editing, in the order given on the command line.
     #[serde(default)]
     pub input_images: Vec<InputImage>,
     /// Background mode (`"auto"`, `"transparent"`) — `OpenAI` only.
     #[serde(default)]
     pub background: Option<String>,
+    /// Prompt expansion (`"auto"`, `"on"`, `"off"`) — Ideogram only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub magic_prompt: Option<String>,
+    /// Style type (`"auto"`, `"general"`, `"realistic"`, `"design"`, `"fiction"`) — Ideogram only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub style_type: Option<String>,
+    /// Content moderation strictness (`"auto"`, `"low"`) — `OpenAI` only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moderation: Option<String>,
+    /// Encoder quality for jpeg and webp output, 0-100 (`--compression`) — `OpenAI` only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output_compression: Option<u8>,
+    /// Safety filter threshold (`"none"`, `"few"`, `"some"`, `"most"`) — Gemini only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub safety: Option<String>,
+    /// Style/composition reference images (`--ref`), guidance only — Gemini only.
+    ///
+    /// Unlike `input_images`, these are never edited.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub reference_images: Vec<InputImage>,
+    /// Ask for download URLs instead of inline image bytes, where the provider supports
+    /// it (`--stream-download`). The caller then fetches [`GeneratedImage::url`] itself.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub return_urls: bool,
+    /// Earlier turns of a conversational editing session, oldest first — Gemini only.
+    ///
+    /// The prompt and input images above form the newest turn.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub history: Vec<ConversationTurn>,
+}
+
+/// One completed exchange in a conversational editing session.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConversationTurn {
+    /// The instruction sent on this turn.
+    pub prompt: String,
+    /// Images attached to the instruction.
+    #[serde(default)]
+    pub input_images: Vec<InputImage>,
+    /// Images the model answered with.
+    pub images: Vec<GeneratedImage>,
+}
+
+impl ImageRequest {
+    /// Start a request with the command line's defaults: `nano-banana`, `1:1`, `1K`,
+    /// `auto` quality, one `jpeg` image.
+    ///
+    /// ```
+    /// use imagen::model::Model;
+    /// use imagen::params::AspectRatio;
+    /// use imagen::ports::ImageRequest;
+    ///
+    /// let request = ImageRequest::builder()
+    ///     .prompt("a cat")
+    ///     .model(Model::NanoBanana)
+    ///     .aspect_ratio(AspectRatio::Wide16x9)
+    ///     .build()?;
+    /// assert_eq!(request.model, "gemini-3.1-flash-image-preview");
+    /// # Ok::<(), imagen::error::ImageError>(())
+    /// ```
+    pub fn builder() -> ImageRequestBuilder {
+        let defaults = DefaultsConfig::default();
+        ImageRequestBuilder {
+            request: Self {
+                model: resolve_model(&defaults.model),
+                prompt: String::new(),
+                aspect_ratio: defaults.aspect_ratio,
+                size: defaults.size,
+                quality: defaults.quality,
+                format: defaults.format,
+                count: 1,
+                seed: None,
+                thinking: None,
+                input_images: Vec::new(),
+                background: None,
+                magic_prompt: None,
+                style_type: None,
+                moderation: None,
+                output_compression: None,
+                safety: None,
+                reference_images: Vec::new(),
+                return_urls: false,
+                history: Vec::new(),
+            },
+        }
+    }
+
+    /// Stable content hash identifying this logical request.
+    ///
+    /// The hash covers the model, the trimmed prompt, every generation parameter,
+    /// and the bytes of any input images, so identical requests share an ID across
+    /// runs, logs, and cassettes. Returns 16 lowercase hex characters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the request cannot be serialized, which its plain fields rule out.
+    #[must_use]
+    pub fn request_id(&self) -> String {
+        let mut normalized = self.clone();
+        normalized.prompt = normalized.prompt.trim().to_string();
+        // How the bytes are delivered doesn't change them.
+        normalized.return_urls = false;
+        // Input filenames don't affect the output; hash the content only.
+        for img in normalized.input_images.iter_mut().chain(&mut normalized.reference_images) {
+            img.filename.clear();
+        }
+        let canonical =
+            serde_json::to_vec(&normalized).expect("ImageRequest is always serializable");
+        let digest = Sha256::digest(&canonical);
+        let prefix: [u8; 8] = digest[..8].try_into().expect("SHA-256 digest is 32 bytes");
+        format!("{:016x}", u64::from_be_bytes(prefix))
+    }
+
+    /// The builder's defaults with `model` and `prompt`, unvalidated, for tests to
+    /// adjust with struct update syntax.
+    #[cfg(test)]
+    pub(crate) fn sample(model: &str, prompt: &str) -> Self {
+        Self { model: model.into(), prompt: prompt.into(), ..Self::builder().request }
+    }
+
+    /// Seed for image `index` when the images are rendered one call at a time.
+    ///
+    /// Consecutive seeds keep the images distinct while the run stays reproducible.
+    #[must_use]
+    pub fn image_seed(&self, index: u32) -> Option<u32> {
+        self.seed.map(|seed| seed.wrapping_add(index))
+    }
+}
+
+/// Builds an [`ImageRequest`], checking it against what the model's provider accepts.
+///
+/// Created by [`ImageRequest::builder`]. The model and aspect ratio take a
+/// [`Model`](crate::model::Model) and [`AspectRatio`](crate::params::AspectRatio), or any
+/// string the command line would.
+#[derive(Debug, Clone)]
+#[must_use = "a builder does nothing until `build` is called"]
+pub struct ImageRequestBuilder {
+    request: ImageRequest,
+}
+
+impl ImageRequestBuilder {
+    /// The text prompt.
+    pub fn prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.request.prompt = prompt.into();
+        self
+    }
+
+    /// The model, by [`Model`](crate::model::Model), alias, or full identifier.
+    pub fn model(mut self, model: impl Into<String>) -> Self {
+        self.request.model = resolve_model(&model.into());
+        self
+    }
+
+    /// The aspect ratio, which the provider must support exactly.
+    pub fn aspect_ratio(mut self, ratio: impl Into<String>) -> Self {
+        self.request.aspect_ratio = ratio.into();
+        self
+    }
+
+    /// Image size (`1K`, `2K`, `4K`).
+    pub fn size(mut self, size: impl Into<String>) -> Self {
+        self.request.size = size.into();
+        self
+    }
+
+    /// Quality level (`auto`, `low`, `medium`, `high`).
+    pub fn quality(mut self, quality: impl Into<String>) -> Self {
+        self.request.quality = quality.into();
+        self
+    }
+
+    /// Format to ask the provider for: `jpeg`, `png`, or `webp`. For `tiff`, `bmp`,
+    /// `gif`, or `avif`, ask for `png` and let [`crate::output::save_image`] convert it.
+    pub fn format(mut self, format: impl Into<String>) -> Self {
+        self.request.format = format.into();
+        self
+    }
+
+    /// Number of images.
+    pub fn count(mut self, count: u32) -> Self {
+        self.request.count = count;
+        self
+    }
+
+    /// Seed for reproducible results.
+    pub fn seed(mut self, seed: u32) -> Self {
+        self.request.seed = Some(seed);
+        self
+    }
+
+    /// Gemini thinking level.
+    pub fn thinking(mut self, level: impl Into<String>) -> Self {
+        self.request.thinking = Some(level.into());
+        self
+    }
+
+    /// Add an input image to edit or build on.
+    pub fn input_image(mut self, image: InputImage) -> Self {
+        self.request.input_images.push(image);
+        self
+    }
+
+    /// Add a style or composition reference image (Gemini only).
+    pub fn reference_image(mut self, image: InputImage) -> Self {
+        self.request.reference_images.push(image);
+        self
+    }
+
+    /// `OpenAI` background mode (`auto`, `transparent`, `opaque`).
+    pub fn background(mut self, background: impl Into<String>) -> Self {
+        self.request.background = Some(background.into());
+        self
+    }
+
+    /// Ideogram prompt expansion (`auto`, `on`, `off`).
+    pub fn magic_prompt(mut self, mode: impl Into<String>) -> Self {
+        self.request.magic_prompt = Some(mode.into());
+        self
+    }
+
+    /// Ideogram style type.
+    pub fn style_type(mut self, style: impl Into<String>) -> Self {
+        self.request.style_type = Some(style.into());
+        self
+    }
+
+    /// `OpenAI` content moderation level (`auto`, `low`).
+    pub fn moderation(mut self, level: impl Into<String>) -> Self {
+        self.request.moderation = Some(level.into());
+        self
+    }
+
+    /// `OpenAI` encoder quality for jpeg and webp output, 0-100.
+    pub fn output_compression(mut self, compression: u8) -> Self {
+        self.request.output_compression = Some(compression);
+        self
+    }
+
+    /// Gemini safety filter level (`none`, `few`, `some`, `most`).
+    pub fn safety(mut self, level: impl Into<String>) -> Self {
+        self.request.safety = Some(level.into());
+        self
+    }
+
+    /// Ask for download URLs instead of inline image bytes.
+    pub fn return_urls(mut self, return_urls: bool) -> Self {
+        self.request.return_urls = return_urls;
+        self
+    }
+
+    /// Check the request and return it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ImageError::InvalidArgument`] if the prompt is empty, the model has no
+    /// known provider, the format is one providers cannot return, or a setting is one
+    /// the provider does not accept.
+    pub fn build(self) -> Result<ImageRequest, ImageError> {
+        self.check().map_err(ImageError::InvalidArgument)?;
+        Ok(self.request)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let request = &self.request;
+        if request.prompt.trim().is_empty() {
+            return Err("An image request needs a prompt".to_string());
+        }
+        let provider = detect_provider(&request.model)?;
+        validate_aspect_ratio(&request.aspect_ratio, provider)?;
+        validate_size(&request.size)?;
+        validate_quality(&request.quality)?;
+        validate_format(&request.format)?;
+        let returned = provider_format(&request.format);
+        if returned != request.format {
+            return Err(format!(
+                "Providers cannot return {}; request {returned} and convert it when saving",
+                request.format
+            ));
+        }
+        // `ServiceContext` splits counts over the provider's limit across calls.
+        validate_count(request.count, provider, true)?;
+        if request.seed.is_some() {
+            validate_seed(provider)?;
+        }
+        if let Some(ref thinking) = request.thinking {
+            validate_thinking(thinking, provider)?;
+        }
+        if !request.reference_images.is_empty() {
+            validate_reference_images(provider)?;
+        }
+        if let Some(ref background) = request.background {
+            validate_background(background, &request.format, provider)?;
+        }
+        if let Some(ref mode) = request.magic_prompt {
+            validate_magic_prompt(mode, provider)?;
+        }
+        if let Some(ref style) = request.style_type {
+            validate_style_type(style, provider)?;
+        }
+        if let Some(ref level) = request.moderation {
+            validate_moderation(level, provider)?;
+        }
+        if let Some(compression) = request.output_compression {
+            validate_compression(&request.format)?;
+            if compression > 100 {
+                return Err(format!("Compression must be 0-100, not {compression}"));
+            }
+        }
+        if let Some(ref level) = request.safety {
+            validate_safety(level, provider)?;
+        }
+        if request.return_urls {
+            validate_stream_download(provider)?;
+        }
+        Ok(())
+    }
 }
 
 /// A single generated image.
@@ -55,6 +388,13 @@ pub struct GeneratedImage {
     pub data: Vec<u8>,
     /// MIME type of the image (e.g., `"image/jpeg"`).
     pub mime_type: String,
+    /// Opaque token the provider requires when this image is sent back as a
+    /// conversation turn (Gemini's thought signature).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+    /// Where to fetch the image when it was delivered by URL; `data` is then empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
 }
 
 /// Response containing generated images.
@@ -62,16 +402,104 @@ pub struct GeneratedImage {
 pub struct ImageResponse {
     /// The generated images.
     pub images: Vec<GeneratedImage>,
+    /// Images the provider returned that could not be used; the rest are still in `images`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<ImageFailure>,
+    /// Tokens the provider billed for the response, when it reports them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<Usage>,
+}
+
+/// Token usage a provider reports for a response (`OpenAI` `usage`, Gemini `usageMetadata`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Usage {
+    /// Tokens of the prompt and any input images.
+    pub input_tokens: u64,
+    /// Tokens of the generated images, and of any text or thinking alongside them.
+    pub output_tokens: u64,
+}
+
+impl AddAssign for Usage {
+    fn add_assign(&mut self, other: Self) {
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// `1290 input + 4160 output tokens`.
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} input + {} output tokens", self.input_tokens, self.output_tokens)
+    }
+}
+
+/// One image of a multi-image response that could not be used.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ImageFailure {
+    /// Position of the image in the provider's response (0-based).
+    pub index: usize,
+    /// What went wrong, e.g. `"invalid base64: ..."`.
+    pub reason: String,
+}
+
+impl ImageResponse {
+    /// Build a response from the images that decoded and those that did not.
+    ///
+    /// A single bad image no longer sinks the others, but a response where every
+    /// image failed is still an error.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ImageError::Api`] naming the failures if no image was usable.
+    pub fn partial(
+        images: Vec<GeneratedImage>,
+        failures: Vec<ImageFailure>,
+    ) -> Result<Self, ImageError> {
+        if images.is_empty() && !failures.is_empty() {
+            let reasons: Vec<&str> = failures.iter().map(|f| f.reason.as_str()).collect();
+            return Err(ImageError::Api {
+                status: 200,
+                message: format!("No usable images: {}", reasons.join("; ")),
+            });
+        }
+        Ok(Self { images, failures, usage: None })
+    }
 }
 
 /// Boxed future type returned by [`ImageGenerator::generate`].
 pub type GenerateFuture<'a> =
     Pin<Box<dyn Future<Output = Result<ImageResponse, ImageError>> + Send + 'a>>;
 
+/// A progressive preview of an image that is still rendering (`--stream`).
+#[derive(Debug, Clone)]
+pub struct PartialImage {
+    /// Position of the preview in the stream (0-based).
+    pub frame: u32,
+    /// Raw image bytes.
+    pub data: Vec<u8>,
+    /// MIME type of the preview.
+    pub mime_type: String,
+}
+
+/// Receives [`PartialImage`]s as a streamed generation produces them.
+pub type PartialSink = std::sync::mpsc::Sender<PartialImage>;
+
 /// Generates images from text prompts via an external API.
 pub trait ImageGenerator: Send + Sync {
     /// Generate images for the given request.
     fn generate(&self, request: &ImageRequest) -> GenerateFuture<'_>;
+
+    /// Generate images, sending previews to `partials` while they render.
+    ///
+    /// Providers that cannot stream ignore `partials` and behave like [`Self::generate`].
+    fn generate_streaming(
+        &self,
+        request: &ImageRequest,
+        partials: PartialSink,
+    ) -> GenerateFuture<'_> {
+        drop(partials);
+        self.generate(request)
+    }
 }
 
 /// Serde helper for serializing `Vec<u8>` as base64 strings in cassettes.
@@ -98,18 +526,7 @@ mod tests {
 
     #[test]
     fn image_request_serialization() {
-        let request = ImageRequest {
-            model: "gemini-3.1-flash-image-preview".into(),
-            prompt: "a cat".into(),
-            aspect_ratio: "1:1".into(),
-            size: "1K".into(),
-            quality: "auto".into(),
-            format: "jpeg".into(),
-            count: 1,
-            thinking: None,
-            input_images: vec![],
-            background: None,
-        };
+        let request = ImageRequest::sample("gemini-3.1-flash-image-preview", "a cat");
         let json = serde_json::to_string(&request).unwrap();
         let deserialized: ImageRequest = serde_json::from_str(&json).unwrap();
         assert_eq!(deserialized.model, "gemini-3.1-flash-image-preview");
@@ -122,16 +539,8 @@ mod tests {
     #[test]
     fn image_request_with_thinking() {
         let request = ImageRequest {
-            model: "gemini-3.1-flash-image-preview".into(),
-            prompt: "a cat".into(),
-            aspect_ratio: "1:1".into(),
-            size: "1K".into(),
-            quality: "auto".into(),
-            format: "jpeg".into(),
-            count: 1,
             thinking: Some("medium".into()),
-            input_images: vec![],
-            background: None,
+            ..ImageRequest::sample("gemini-3.1-flash-image-preview", "a cat")
         };
         let json = serde_json::to_string(&request).unwrap();
         let deserialized: ImageRequest = serde_json::from_str(&json).unwrap();
@@ -141,26 +550,22 @@ mod tests {
     #[test]
     fn image_request_with_input_images() {
         let request = ImageRequest {
-            model: "gpt-image-1".into(),
-            prompt: "remove background".into(),
-            aspect_ratio: "1:1".into(),
-            size: "1K".into(),
-            quality: "auto".into(),
             format: "png".into(),
-            count: 1,
-            thinking: None,
             input_images: vec![InputImage {
                 data: vec![0xFF, 0xD8, 0xFF, 0xE0],
                 mime_type: "image/jpeg".into(),
                 filename: "photo.jpg".into(),
+                role: Some("subject".into()),
             }],
             background: Some("transparent".into()),
+            ..ImageRequest::sample("gpt-image-1", "remove background")
         };
         let json = serde_json::to_string(&request).unwrap();
         let deserialized: ImageRequest = serde_json::from_str(&json).unwrap();
         assert_eq!(deserialized.input_images.len(), 1);
         assert_eq!(deserialized.input_images[0].mime_type, "image/jpeg");
         assert_eq!(deserialized.input_images[0].filename, "photo.jpg");
+        assert_eq!(deserialized.input_images[0].role.as_deref(), Some("subject"));
         assert_eq!(deserialized.background.as_deref(), Some("transparent"));
     }
 
@@ -174,11 +579,58 @@ mod tests {
         assert!(deserialized.thinking.is_none());
     }
 
+    #[test]
+    fn reference_images_round_trip_as_base64() {
+        let json = r#"{"model":"m","prompt":"p","aspect_ratio":"1:1","size":"1K","quality":"auto","format":"png","count":1,"reference_images":[{"data":"AQID","mime_type":"image/png","filename":"style.png"}]}"#;
+        let request: ImageRequest = serde_json::from_str(json).unwrap();
+        assert_eq!(request.reference_images[0].data, vec![1, 2, 3]);
+        let value = serde_json::to_value(&request).unwrap();
+        assert_eq!(value["reference_images"][0]["data"], "AQID");
+
+        let mut without = request.clone();
+        without.reference_images.clear();
+        assert!(serde_json::to_value(&without).unwrap().get("reference_images").is_none());
+        assert_ne!(without.request_id(), request.request_id());
+    }
+
+    #[test]
+    fn request_id_is_stable_and_content_based() {
+        let request = ImageRequest {
+            format: "png".into(),
+            input_images: vec![InputImage {
+                data: vec![1, 2, 3],
+                mime_type: "image/png".into(),
+                filename: "a.png".into(),
+                role: None,
+            }],
+            ..ImageRequest::sample("gpt-image-1", "a cat")
+        };
+        let id = request.request_id();
+        assert_eq!(id.len(), 16);
+        assert!(id.chars().all(|c| c.is_ascii_hexdigit()));
+        assert_eq!(id, request.clone().request_id());
+
+        let mut padded = request.clone();
+        padded.prompt = "  a cat\n".into();
+        padded.input_images[0].filename = "renamed.png".into();
+        assert_eq!(padded.request_id(), id);
+
+        let mut changed = request.clone();
+        changed.aspect_ratio = "16:9".into();
+        assert_ne!(changed.request_id(), id);
+
+        let mut other_input = request;
+        other_input.input_images[0].data = vec![9];
+        assert_ne!(other_input.request_id(), id);
+    }
+
     #[test]
     fn generated_image_base64_round_trip() {
         let image = GeneratedImage {
             data: vec![0xFF, 0xD8, 0xFF, 0xE0], // JPEG magic bytes
             mime_type: "image/jpeg".into(),
+            signature: None,
+            url: None,
         };
         let json = serde_json::to_string(&image).unwrap();
         let deserialized: GeneratedImage = serde_json::from_str(&json).unwrap();
@@ -189,11 +641,67 @@ mod tests {
     #[test]
     fn image_response_serialization() {
         let response = ImageResponse {
-            images: vec![GeneratedImage { data: vec![1, 2, 3], mime_type: "image/png".into() }],
+            images: vec![GeneratedImage {
+                data: vec![1, 2, 3],
+                mime_type: "image/png".into(),
+                signature: None,
+                url: None,
+            }],
+            failures: Vec::new(),
+            usage: None,
         };
         let json = serde_json::to_string(&response).unwrap();
         let deserialized: ImageResponse = serde_json::from_str(&json).unwrap();
         assert_eq!(deserialized.images.len(), 1);
         assert_eq!(deserialized.images[0].data, vec![1, 2, 3]);
+        assert!(!json.contains("failures"));
+    }
+
+    #[test]
+    fn partial_response_keeps_good_images() {
+        let image = GeneratedImage {
+            data: vec![1],
+            mime_type: "image/png".into(),
+            signature: None,
+            url: None,
+        };
+        let failure = ImageFailure { index: 1, reason: "invalid base64: bad byte".into() };
+
+        let response = ImageResponse::partial(vec![image], vec![failure.clone()]).unwrap();
+        assert_eq!(response.images.len(), 1);
+        assert_eq!(response.failures, std::slice::from_ref(&failure));
+
+        let err = ImageResponse::partial(vec![], vec![failure]).unwrap_err();
+        assert!(err.to_string().contains("No usable images: invalid base64: bad byte"));
+        assert!(ImageResponse::partial(vec![], vec![]).unwrap().images.is_empty());
+    }
+
+    #[test]
+    fn builder_checks_settings_against_the_provider() {
+        use crate::model::Model;
+        use crate::params::AspectRatio;
+
+        let request = ImageRequest::builder()
+            .prompt("a cat")
+            .model(Model::Ideogram)
+            .aspect_ratio(AspectRatio::Wide16x9)
+            .count(12)
+            .seed(7)
+            .build()
+            .unwrap();
+        assert_eq!(request.model, "ideogram-v3");
+        assert_eq!((request.aspect_ratio.as_str(), request.size.as_str()), ("16:9", "1K"));
+        assert_eq!((request.count, request.seed), (12, Some(7)));
+
+        let error = |builder: ImageRequestBuilder| builder.build().unwrap_err().to_string();
+        let cat = || ImageRequest::builder().prompt("a cat");
+        assert!(error(ImageRequest::builder()).contains("needs a prompt"));
+        assert!(error(cat().model("dall-e-3")).contains("Unknown provider"));
+        assert!(error(cat().model(Model::Ideogram).aspect_ratio("21:9")).contains("21:9"));
+        assert!(error(cat().model("gpt-1").seed(7)).contains("--seed"));
+        assert!(error(cat().model("gpt-1").background("transparent")).contains("alpha"));
+        assert!(error(cat().format("png").output_compression(80)).contains("no quality"));
+        assert!(error(cat().format("tiff")).contains("request png"));
+        assert!(cat().model("gpt-1").background("transparent").format("png").build().is_ok());
     }
 }
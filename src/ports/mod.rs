@@ -3,6 +3,14 @@
 //! Each trait represents a boundary between the application core and an
 //! external system. Implementations live in `src/adapters/`.
 
+pub mod image_editor;
 pub mod image_generator;
+pub mod prompt_enhancer;
+pub mod upscaler;
 
-pub use image_generator::{ImageGenerator, ImageRequest, InputImage};
+pub use image_editor::ImageEditor;
+pub use image_generator::{
+    ConversationTurn, ImageGenerator, ImageRequest, ImageRequestBuilder, InputImage,
+};
+pub use prompt_enhancer::{EnhanceRequest, PromptEnhancer};
+pub use upscaler::{UpscaleRequest, Upscaler};
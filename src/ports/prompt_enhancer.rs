@@ -0,0 +1,42 @@
+//! Prompt enhancer port for expanding short prompts with a text model.
+
+use std::future::Future;
+use std::pin::Pin;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::ImageError;
+
+/// What the text model is told to do with the prompt, unless `[enhance] instructions`
+/// says otherwise.
+pub const DEFAULT_INSTRUCTIONS: &str = "You rewrite short image ideas into detailed prompts \
+    for an image generation model. Keep the subject and every detail the user gave, and add \
+    the composition, setting, lighting, color palette, and style or medium that suit them. \
+    Reply with the prompt only: a single paragraph under 120 words, with no preamble or quotes.";
+
+/// Instructions for rewriting a prompt the image model refused (`--on-refusal soften`).
+pub const SOFTEN_INSTRUCTIONS: &str = "An image generation model's safety filter refused the \
+    prompt below. Rewrite it so that it is clearly acceptable, keeping as much of the subject, \
+    composition, and style as you can: tone down violence, gore, nudity, and anything else \
+    likely to be flagged, and leave out real people's names. Reply with the prompt only, with \
+    no preamble or quotes.";
+
+/// A prompt to expand.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EnhanceRequest {
+    /// Text model that rewrites the prompt (e.g., `gemini-2.5-flash`, `gpt-4.1-mini`).
+    pub model: String,
+    /// Instructions sent as the model's system prompt.
+    pub instructions: String,
+    /// The prompt as the user wrote it.
+    pub prompt: String,
+}
+
+/// Boxed future type returned by [`PromptEnhancer::enhance`].
+pub type EnhanceFuture<'a> = Pin<Box<dyn Future<Output = Result<String, ImageError>> + Send + 'a>>;
+
+/// Rewrites image prompts with a text model.
+pub trait PromptEnhancer: Send + Sync {
+    /// Return the expanded prompt.
+    fn enhance(&self, request: &EnhanceRequest) -> EnhanceFuture<'_>;
+}
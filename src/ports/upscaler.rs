@@ -0,0 +1,25 @@
+//! Upscaler port for enlarging existing images.
+
+use serde::{Deserialize, Serialize};
+
+use crate::ports::image_generator::{GenerateFuture, InputImage};
+
+/// A request to upscale one image.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpscaleRequest {
+    /// The image to enlarge.
+    pub image: InputImage,
+    /// Requested scale factor (e.g., `4` for 4x width and height).
+    pub factor: u32,
+    /// Preferred format of the returned image (`"jpeg"`, `"png"`, `"webp"`).
+    pub format: String,
+}
+
+/// Enlarges images, via an external API or locally.
+///
+/// An adapter may return a different scale than requested when its service only offers
+/// fixed factors; callers resize the result to the exact target.
+pub trait Upscaler: Send + Sync {
+    /// Upscale the request's image, returning exactly one image.
+    fn upscale(&self, request: &UpscaleRequest) -> GenerateFuture<'_>;
+}
@@ -0,0 +1,278 @@
+//! Post-processing steps applied, in order, to each image as it is saved.
+//!
+//! `--post` takes them as a comma-separated list, such as
+//! `--post resize=1280x720,crop=center,rotate=90`:
+//!
+//! - `resize=WxH` scales the image to fit inside `W`x`H`, keeping its aspect ratio.
+//!   Followed by `crop=center` or `crop=smart`, it instead covers `W`x`H` and crops the
+//!   overflow: evenly from both sides, or around the most detailed part of the image.
+//! - `pad=WxH` fits the image inside `W`x`H` and pads it to exactly that size, with
+//!   transparency when the image has an alpha channel and black otherwise, or with
+//!   `pad=WxH:RRGGBB`.
+//! - `rotate=90`, `rotate=180`, or `rotate=270` turns the image clockwise.
+//!
+//! `--width`/`--height` and the watermark are steps too: [`PostProcess`] runs the exact
+//! resize first, then `--post`, then the watermark.
+//!
+//! [`PostProcess`]: crate::output::PostProcess
+
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
+
+use crate::pipeline::parse_size;
+use crate::watermark::Watermark;
+
+/// Which part of an image [`Step::Fill`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Crop {
+    /// The middle, cropping the overflow evenly from both sides.
+    Center,
+    /// The stretch with the most edges and texture, leaving out flat background.
+    Smart,
+}
+
+/// One post-processing step.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Step {
+    /// Scale to fit inside these dimensions, keeping the aspect ratio.
+    Fit(u32, u32),
+    /// Scale to cover these dimensions, then crop to exactly them.
+    Fill(u32, u32, Crop),
+    /// Fit inside these dimensions, then pad to exactly them with this color.
+    /// `None` pads with transparency, or black when the image has no alpha channel.
+    Pad(u32, u32, Option<[u8; 3]>),
+    /// Rotate clockwise by 90, 180, or 270 degrees.
+    Rotate(u32),
+    /// Stamp text or a logo over the image.
+    Watermark(Watermark),
+}
+
+/// Parse a `--post` list of steps, such as `resize=1280x720,crop=center`.
+///
+/// # Errors
+///
+/// Returns an error for unknown or malformed steps, and for a `crop` that does not
+/// directly follow a `resize`.
+pub fn parse(spec: &str) -> Result<Vec<Step>, String> {
+    let mut steps = Vec::new();
+    for part in spec.split(',').map(str::trim) {
+        let Some((name, value)) = part.split_once('=') else {
+            return Err(format!("Invalid --post step '{part}'. Expected NAME=VALUE"));
+        };
+        let step = match name {
+            "resize" => {
+                let (width, height) = parse_size(value)?;
+                Step::Fit(width, height)
+            }
+            "crop" => {
+                let crop = match value {
+                    "center" => Crop::Center,
+                    "smart" => Crop::Smart,
+                    other => {
+                        return Err(format!("Invalid crop '{other}'. Valid: center, smart"));
+                    }
+                };
+                let Some(Step::Fit(width, height)) = steps.pop() else {
+                    return Err(format!("crop={value} must directly follow a resize=WxH step"));
+                };
+                Step::Fill(width, height, crop)
+            }
+            "pad" => {
+                let (size, color) = match value.split_once(':') {
+                    Some((size, color)) => (size, Some(parse_color(color)?)),
+                    None => (value, None),
+                };
+                let (width, height) = parse_size(size)?;
+                Step::Pad(width, height, color)
+            }
+            "rotate" => match value {
+                "90" | "-270" => Step::Rotate(90),
+                "180" | "-180" => Step::Rotate(180),
+                "270" | "-90" => Step::Rotate(270),
+                other => {
+                    return Err(format!("Invalid rotation '{other}'. Valid: 90, 180, 270"));
+                }
+            },
+            other => {
+                return Err(format!(
+                    "Unknown --post step '{other}'. Valid: resize, crop, pad, rotate"
+                ));
+            }
+        };
+        steps.push(step);
+    }
+    Ok(steps)
+}
+
+/// Parse a `RRGGBB` hex color, with or without a leading `#`.
+fn parse_color(color: &str) -> Result<[u8; 3], String> {
+    let hex = color.strip_prefix('#').unwrap_or(color);
+    let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(r), Some(g), Some(b)) => Ok([r, g, b]),
+        _ => Err(format!("Invalid pad color '{color}'. Expected RRGGBB, e.g. ffffff")),
+    }
+}
+
+impl Step {
+    /// Apply the step. Steps keep the image's layout, except that padding and the
+    /// watermark may return 16-bit RGBA.
+    #[must_use]
+    pub fn apply(&self, img: DynamicImage) -> DynamicImage {
+        match *self {
+            Self::Fit(width, height) => img.resize(width, height, FilterType::Lanczos3),
+            Self::Fill(width, height, _) if img.dimensions() == (width, height) => img,
+            Self::Fill(width, height, Crop::Center) => {
+                img.resize_to_fill(width, height, FilterType::Lanczos3)
+            }
+            Self::Fill(width, height, Crop::Smart) => {
+                let (cover_width, cover_height) = cover(img.dimensions(), (width, height));
+                let covered = img.resize_exact(cover_width, cover_height, FilterType::Lanczos3);
+                let (x, y) = busiest_window(&covered, width, height);
+                covered.crop_imm(x, y, width, height)
+            }
+            Self::Pad(width, height, color) => pad(&img, width, height, color),
+            Self::Rotate(90) => img.rotate90(),
+            Self::Rotate(180) => img.rotate180(),
+            Self::Rotate(_) => img.rotate270(),
+            Self::Watermark(ref watermark) => watermark.apply(&img),
+        }
+    }
+}
+
+/// The smallest size with the aspect ratio of `source` that covers `target`.
+#[allow(clippy::cast_possible_truncation)] // At most the larger of the two sizes.
+fn cover((width, height): (u32, u32), target: (u32, u32)) -> (u32, u32) {
+    let (w, h) = (u64::from(width), u64::from(height));
+    let (tw, th) = (u64::from(target.0), u64::from(target.1));
+    if w * th > h * tw {
+        ((w * th).div_ceil(h).max(tw) as u32, target.1)
+    } else {
+        (target.0, (h * tw).div_ceil(w).max(th) as u32)
+    }
+}
+
+/// The top-left corner of the `width` x `height` window of `img` with the most detail,
+/// sliding along whichever axis overflows. Ties go to the window nearest the middle, so a
+/// flat image is cropped like [`Crop::Center`].
+fn busiest_window(img: &DynamicImage, width: u32, height: u32) -> (u32, u32) {
+    let luma = img.to_luma8();
+    let (image_width, image_height) = luma.dimensions();
+    let horizontal = image_width > width;
+    let (length, window) = if horizontal { (image_width, width) } else { (image_height, height) };
+    if length <= window {
+        return (0, 0);
+    }
+
+    // Detail along the sliding axis: the gradient of each row or column.
+    let mut energy = vec![0u64; length as usize];
+    for (x, y, pixel) in luma.enumerate_pixels() {
+        let right = luma.get_pixel((x + 1).min(image_width - 1), y)[0];
+        let below = luma.get_pixel(x, (y + 1).min(image_height - 1))[0];
+        let detail = u64::from(pixel[0].abs_diff(right)) + u64::from(pixel[0].abs_diff(below));
+        energy[if horizontal { x } else { y } as usize] += detail;
+    }
+
+    let window = window as usize;
+    let middle = (energy.len() - window) / 2;
+    let (mut best, mut best_sum) = (middle, energy[middle..middle + window].iter().sum::<u64>());
+    let mut sum = energy[..window].iter().sum::<u64>();
+    for start in 0..=energy.len() - window {
+        if start > 0 {
+            sum = sum + energy[start + window - 1] - energy[start - 1];
+        }
+        if sum > best_sum || (sum == best_sum && start.abs_diff(middle) < best.abs_diff(middle)) {
+            (best, best_sum) = (start, sum);
+        }
+    }
+    let offset = u32::try_from(best).expect("offsets are within the image");
+    if horizontal {
+        (offset, 0)
+    } else {
+        (0, offset)
+    }
+}
+
+/// `img` fitted inside `width` x `height` and centered on a canvas of exactly that size.
+fn pad(img: &DynamicImage, width: u32, height: u32, color: Option<[u8; 3]>) -> DynamicImage {
+    let fitted = img.resize(width, height, FilterType::Lanczos3).to_rgba16();
+    let fill = match color {
+        Some([r, g, b]) => Rgba([r, g, b, 255].map(|c| u16::from(c) * 257)),
+        None if img.color().has_alpha() => Rgba([0, 0, 0, 0]),
+        None => Rgba([0, 0, 0, u16::MAX]),
+    };
+    let mut canvas = ImageBuffer::from_pixel(width, height, fill);
+    let x = (width - fitted.width()) / 2;
+    let y = (height - fitted.height()) / 2;
+    image::imageops::overlay(&mut canvas, &fitted, i64::from(x), i64::from(y));
+    DynamicImage::ImageRgba16(canvas)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn parses_steps_in_order() {
+        assert_eq!(
+            parse("resize=1280x720,crop=center").unwrap(),
+            [Step::Fill(1280, 720, Crop::Center)]
+        );
+        assert_eq!(
+            parse("resize=800x800, pad=1000x1000:#ffffff, rotate=-90").unwrap(),
+            [Step::Fit(800, 800), Step::Pad(1000, 1000, Some([255, 255, 255])), Step::Rotate(270)]
+        );
+        assert!(parse("crop=center").is_err(), "crop needs a resize");
+        assert!(parse("resize=1280x720,rotate=90,crop=smart").is_err());
+        assert!(parse("rotate=45").is_err());
+        assert!(parse("pad=10x10:red").is_err());
+        assert!(parse("blur=2").is_err());
+        assert!(parse("resize").is_err());
+    }
+
+    #[test]
+    fn resize_fits_and_fill_crops() {
+        let wide = DynamicImage::ImageRgb8(RgbImage::new(400, 200));
+        assert_eq!(Step::Fit(100, 100).apply(wide.clone()).dimensions(), (100, 50));
+        let filled = Step::Fill(100, 100, Crop::Center).apply(wide.clone());
+        assert_eq!(filled.dimensions(), (100, 100));
+        let smart = Step::Fill(100, 100, Crop::Smart).apply(wide);
+        assert_eq!(smart.dimensions(), (100, 100));
+        assert_eq!(cover((1344, 768), (1920, 1080)), (1920, 1098));
+    }
+
+    #[test]
+    fn smart_crop_keeps_the_detailed_side() {
+        // Flat on the left, a checkerboard on the right.
+        let img = RgbImage::from_fn(300, 100, |x, y| {
+            if x >= 200 && (x + y) % 2 == 0 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([0, 0, 0])
+            }
+        });
+        let img = DynamicImage::ImageRgb8(img);
+        assert_eq!(busiest_window(&img, 100, 100), (200, 0));
+        let flat = DynamicImage::ImageRgb8(RgbImage::new(300, 100));
+        assert_eq!(busiest_window(&flat, 100, 100), (100, 0), "ties go to the middle");
+    }
+
+    #[test]
+    fn pad_centers_the_image() {
+        let tall = DynamicImage::ImageRgb8(RgbImage::from_pixel(50, 100, Rgb([255, 0, 0])));
+        let padded = Step::Pad(200, 100, Some([0, 0, 255])).apply(tall).to_rgb8();
+        assert_eq!(padded.dimensions(), (200, 100));
+        assert_eq!(padded.get_pixel(100, 50).0, [255, 0, 0]);
+        assert_eq!(padded.get_pixel(10, 50).0, [0, 0, 255]);
+    }
+
+    #[test]
+    fn rotates_clockwise() {
+        let mut img = RgbImage::new(20, 10);
+        img.put_pixel(0, 0, Rgb([255, 255, 255]));
+        let rotated = Step::Rotate(90).apply(DynamicImage::ImageRgb8(img)).to_rgb8();
+        assert_eq!(rotated.dimensions(), (10, 20));
+        assert_eq!(rotated.get_pixel(9, 0).0, [255, 255, 255]);
+    }
+}
@@ -0,0 +1,309 @@
+//! `--preview`: draw saved images in the terminal, for quick iteration over ssh.
+//!
+//! Terminals that understand an image protocol get the picture itself: the kitty
+//! graphics protocol, iTerm2 inline images, or sixel. Anywhere else it is drawn in
+//! half-block characters with 24-bit colors, two pixels per cell. Previews go to stderr
+//! with the rest of imagen's messages, so stdout stays clean for scripts.
+
+use std::fmt::Write as _;
+use std::io::{Cursor, IsTerminal as _, Write as _};
+use std::path::Path;
+
+use base64::Engine as _;
+use image::imageops::FilterType;
+use image::{DynamicImage, RgbImage};
+
+use crate::error::ImageError;
+use crate::progress;
+
+/// Most terminal columns a preview spans.
+const MAX_COLUMNS: u32 = 80;
+
+/// Terminal size assumed when it cannot be read.
+const FALLBACK_CELLS: (u32, u32) = (80, 24);
+
+/// Pixels per terminal cell assumed for sixel, which is drawn in pixels, not cells.
+const CELL_PIXELS: (u32, u32) = (8, 16);
+
+/// Kitty takes the image in chunks of at most this many base64 bytes.
+const KITTY_CHUNK: usize = 4096;
+
+/// How a preview is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// The kitty graphics protocol (kitty, Ghostty, `WezTerm`).
+    Kitty,
+    /// iTerm2 inline images (iTerm2, `WezTerm`).
+    Iterm,
+    /// Sixel graphics (foot, mlterm, xterm built with sixel support).
+    Sixel,
+    /// Colored half blocks, for any terminal with 24-bit color.
+    Blocks,
+}
+
+impl Protocol {
+    /// Parse a `--preview` value: `kitty`, `iterm`, `sixel`, `blocks`, or `auto` to detect
+    /// the terminal from the environment.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unknown protocol names.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "auto" => Ok(Self::detect(|name| std::env::var(name).ok())),
+            "kitty" => Ok(Self::Kitty),
+            "iterm" => Ok(Self::Iterm),
+            "sixel" => Ok(Self::Sixel),
+            "blocks" => Ok(Self::Blocks),
+            other => Err(format!(
+                "Invalid preview protocol '{other}'. Valid: auto, kitty, iterm, sixel, blocks"
+            )),
+        }
+    }
+
+    /// The protocol the terminal speaks, judged by the variables `env` returns. `TERM` and
+    /// `LC_TERMINAL` usually survive ssh; `TERM_PROGRAM` only works locally.
+    fn detect(env: impl Fn(&str) -> Option<String>) -> Self {
+        let term = env("TERM").unwrap_or_default();
+        let program = env("TERM_PROGRAM").unwrap_or_default();
+        if env("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" || program == "ghostty" {
+            Self::Kitty
+        } else if matches!(program.as_str(), "iTerm.app" | "WezTerm")
+            || env("LC_TERMINAL").as_deref() == Some("iTerm2")
+        {
+            Self::Iterm
+        } else if term.contains("sixel") || matches!(term.as_str(), "foot" | "mlterm") {
+            Self::Sixel
+        } else {
+            Self::Blocks
+        }
+    }
+}
+
+/// Draw the image at `path` on stderr with `protocol`, sized to fit the terminal.
+///
+/// # Errors
+///
+/// Returns an error if stderr is not a terminal, or the image cannot be read.
+pub fn show(path: &Path, protocol: Protocol) -> Result<(), ImageError> {
+    let stderr = std::io::stderr();
+    if !stderr.is_terminal() {
+        return Err(ImageError::InvalidArgument("--preview needs stderr to be a terminal".into()));
+    }
+    let image = image::open(path).map_err(|e| {
+        ImageError::ImageConversion(format!("Failed to decode {}: {e}", path.display()))
+    })?;
+    let size = terminal_size::terminal_size_of(&stderr)
+        .map_or(FALLBACK_CELLS, |(width, height)| (u32::from(width.0), u32::from(height.0)));
+    let drawing = render(&image, protocol, size)?;
+    progress::suspend(|| stderr.lock().write_all(drawing.as_bytes()))?;
+    Ok(())
+}
+
+/// The escape sequences that draw `image` with `protocol` in a terminal of `size`
+/// (columns, rows), ending on a fresh line.
+fn render(
+    image: &DynamicImage,
+    protocol: Protocol,
+    size: (u32, u32),
+) -> Result<String, ImageError> {
+    let (columns, rows) = fit(image.width(), image.height(), size);
+    match protocol {
+        Protocol::Kitty | Protocol::Iterm => {
+            // Send no more pixels than the cells can show; this may be going over ssh.
+            let scaled = image.thumbnail(columns * CELL_PIXELS.0 * 2, rows * CELL_PIXELS.1 * 2);
+            let mut png = Cursor::new(Vec::new());
+            scaled.write_to(&mut png, image::ImageFormat::Png).map_err(|e| {
+                ImageError::ImageConversion(format!("Failed to encode preview: {e}"))
+            })?;
+            let png = png.into_inner();
+            Ok(if protocol == Protocol::Kitty {
+                kitty(&png, columns, rows)
+            } else {
+                iterm(&png, columns, rows)
+            })
+        }
+        Protocol::Sixel => {
+            let (width, height) = (columns * CELL_PIXELS.0, rows * CELL_PIXELS.1);
+            Ok(sixel(&image.resize_exact(width, height, FilterType::Triangle).to_rgb8()))
+        }
+        Protocol::Blocks => {
+            Ok(blocks(&image.resize_exact(columns, rows * 2, FilterType::Triangle).to_rgb8()))
+        }
+    }
+}
+
+/// The most cells, in columns and rows, an image of `width`×`height` pixels can fill in
+/// a terminal of `size` at its own proportions. Cells are taken to be twice as tall as
+/// they are wide; one row is left for the prompt.
+fn fit(width: u32, height: u32, size: (u32, u32)) -> (u32, u32) {
+    let max_columns = size.0.clamp(1, MAX_COLUMNS);
+    let max_rows = size.1.saturating_sub(1).max(1);
+    let (width, height) = (u64::from(width.max(1)), u64::from(height.max(1)));
+    let rows_for = |columns: u32| (u64::from(columns) * height).div_ceil(2 * width);
+    if rows_for(max_columns) <= u64::from(max_rows) {
+        let rows = u32::try_from(rows_for(max_columns)).unwrap_or(max_rows);
+        return (max_columns, rows.max(1));
+    }
+    let columns = (u64::from(max_rows) * 2 * width / height).max(1);
+    (u32::try_from(columns).unwrap_or(max_columns).min(max_columns), max_rows)
+}
+
+/// Kitty graphics: the PNG, chunked, displayed over `columns`×`rows` cells. `q=2` keeps
+/// the terminal from answering on stdin.
+fn kitty(png: &[u8], columns: u32, rows: u32) -> String {
+    let data = base64::engine::general_purpose::STANDARD.encode(png);
+    let chunks: Vec<&str> = data
+        .as_bytes()
+        .chunks(KITTY_CHUNK)
+        .map(|chunk| std::str::from_utf8(chunk).expect("base64 is ASCII"))
+        .collect();
+    let mut out = String::new();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            let _ = write!(out, "\x1b_Ga=T,f=100,q=2,c={columns},r={rows},m={more};");
+        } else {
+            let _ = write!(out, "\x1b_Gm={more};");
+        }
+        out.push_str(chunk);
+        out.push_str("\x1b\\");
+    }
+    out.push('\n');
+    out
+}
+
+/// iTerm2 inline image: the PNG, displayed over `columns`×`rows` cells.
+fn iterm(png: &[u8], columns: u32, rows: u32) -> String {
+    let data = base64::engine::general_purpose::STANDARD.encode(png);
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={columns};height={rows};\
+         preserveAspectRatio=1:{data}\x07\n",
+        png.len()
+    )
+}
+
+/// Sixel graphics, with the colors reduced to a 6×6×6 cube.
+fn sixel(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let level = |channel: u8| (u16::from(channel) * 5 + 127) / 255;
+    let colors: Vec<u8> = image
+        .pixels()
+        .map(|pixel| {
+            let [r, g, b] = pixel.0.map(level);
+            u8::try_from(r * 36 + g * 6 + b).expect("216 colors")
+        })
+        .collect();
+
+    let mut out = format!("\x1bPq\"1;1;{width};{height}");
+    for color in 0..216u16 {
+        let percent = |level: u16| level * 20;
+        let (r, g, b) = (color / 36, color / 6 % 6, color % 6);
+        let _ = write!(out, "#{color};2;{};{};{}", percent(r), percent(g), percent(b));
+    }
+    let (width, height) = (width as usize, height as usize);
+    for top in (0..height).step_by(6) {
+        let band = top..(top + 6).min(height);
+        let mut used = [false; 216];
+        for y in band.clone() {
+            for &color in &colors[y * width..(y + 1) * width] {
+                used[usize::from(color)] = true;
+            }
+        }
+        for color in (0..216u8).filter(|&color| used[usize::from(color)]) {
+            let _ = write!(out, "#{color}");
+            let column = |x: usize| {
+                let bits = band
+                    .clone()
+                    .filter(|&y| colors[y * width + x] == color)
+                    .fold(0, |bits, y| bits | 1 << (y - top));
+                char::from(63 + bits)
+            };
+            let mut x = 0;
+            while x < width {
+                let sixel = column(x);
+                let run = (x..width).take_while(|&x| column(x) == sixel).count();
+                if run > 3 {
+                    let _ = write!(out, "!{run}{sixel}");
+                } else {
+                    (0..run).for_each(|_| out.push(sixel));
+                }
+                x += run;
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\\n");
+    out
+}
+
+/// Half blocks: each cell's upper half is one pixel (the foreground) and its lower half
+/// the pixel below (the background).
+fn blocks(image: &RgbImage) -> String {
+    let (width, height) = image.dimensions();
+    let mut out = String::new();
+    for y in (0..height).step_by(2) {
+        for x in 0..width {
+            let [r, g, b] = image.get_pixel(x, y).0;
+            let _ = write!(out, "\x1b[38;2;{r};{g};{b}m");
+            if y + 1 < height {
+                let [r, g, b] = image.get_pixel(x, y + 1).0;
+                let _ = write!(out, "\x1b[48;2;{r};{g};{b}m");
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m\n");
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_is_detected_from_the_terminal() {
+        let detect = |vars: &[(&str, &str)]| {
+            Protocol::detect(|name| {
+                vars.iter().find(|(var, _)| *var == name).map(|(_, value)| (*value).to_string())
+            })
+        };
+        assert_eq!(detect(&[("TERM", "xterm-kitty")]), Protocol::Kitty);
+        assert_eq!(
+            detect(&[("LC_TERMINAL", "iTerm2"), ("TERM", "xterm-256color")]),
+            Protocol::Iterm
+        );
+        assert_eq!(detect(&[("TERM", "foot")]), Protocol::Sixel);
+        assert_eq!(detect(&[("TERM", "xterm-256color")]), Protocol::Blocks);
+        assert!(Protocol::parse("ascii").is_err());
+    }
+
+    #[test]
+    fn previews_fit_the_terminal_at_the_image_proportions() {
+        // Square images take half as many rows as columns; cells are twice as tall.
+        assert_eq!(fit(1024, 1024, (200, 60)), (80, 40));
+        // Tall images are limited by the rows, keeping one free.
+        assert_eq!(fit(1024, 4096, (120, 41)), (20, 40));
+        assert_eq!(fit(1, 1, (0, 0)), (1, 1));
+    }
+
+    #[test]
+    fn encoders_wrap_the_image_in_their_escape_sequences() {
+        let image =
+            RgbImage::from_fn(3, 7, |x, _| image::Rgb([if x == 0 { 255 } else { 0 }, 0, 0]));
+        let drawing = sixel(&image);
+        assert!(drawing.starts_with("\x1bPq\"1;1;3;7") && drawing.ends_with("\x1b\\\n"));
+        // Red (color 180) fills column 0 of both bands: all six rows, then the seventh.
+        assert!(drawing.contains("#180~??$") && drawing.contains("#180@??$"), "{drawing}");
+
+        let drawing = blocks(&image);
+        assert_eq!(drawing.lines().count(), 4);
+        assert!(drawing.starts_with("\x1b[38;2;255;0;0m\x1b[48;2;255;0;0m▀"));
+
+        let drawing = kitty(&[0; 4000], 10, 5);
+        assert_eq!(drawing.matches("\x1b_G").count(), 2);
+        assert!(drawing.starts_with("\x1b_Ga=T,f=100,q=2,c=10,r=5,m=1;"));
+        assert!(drawing.contains("\x1b_Gm=0;"));
+    }
+}
@@ -0,0 +1,186 @@
+//! Projected cost of a run, from a table of per-image list prices.
+//!
+//! Prices are what each provider charged per image at the time of writing, looked up by
+//! model, quality, and size. They are estimates: token-billed models vary with the prompt
+//! and input images, and prices change. `[pricing]` in the config overrides the price of
+//! any model (by alias or full name), and prices models the table does not know.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::hash::BuildHasher;
+use std::ops::AddAssign;
+
+use serde::Serialize;
+
+use crate::model::resolve_model;
+use crate::ports::ImageRequest;
+
+/// List prices in USD per image: (model prefix, quality, size, price). `*` matches any
+/// quality or size, and the first matching row wins, so specific rows come first.
+const PRICES: &[(&str, &str, &str, f64)] = &[
+    // OpenAI, at the square size. `auto` lets the API pick, so assume the top tier.
+    ("gpt-image-1-mini", "low", "*", 0.005),
+    ("gpt-image-1-mini", "medium", "*", 0.011),
+    ("gpt-image-1-mini", "*", "*", 0.036),
+    ("gpt-image-1.5", "low", "*", 0.009),
+    ("gpt-image-1.5", "medium", "*", 0.034),
+    ("gpt-image-1.5", "*", "*", 0.133),
+    ("gpt-image-1", "low", "*", 0.011),
+    ("gpt-image-1", "medium", "*", 0.042),
+    ("gpt-image-1", "*", "*", 0.167),
+    // Gemini bills output tokens, which grow with the size.
+    ("gemini-3-pro-image", "*", "4K", 0.24),
+    ("gemini-3-pro-image", "*", "*", 0.134),
+    ("gemini-3.1-flash-image", "*", "4K", 0.151),
+    ("gemini-3.1-flash-image", "*", "2K", 0.101),
+    ("gemini-3.1-flash-image", "*", "*", 0.067),
+    ("gemini-2.5-flash-image", "*", "*", 0.039),
+    ("sd3.5-large-turbo", "*", "*", 0.04),
+    ("sd3.5-large", "*", "*", 0.065),
+    ("sd3.5-medium", "*", "*", 0.035),
+    ("sd3.5-flash", "*", "*", 0.025),
+    ("stable-image-ultra", "*", "*", 0.08),
+    ("stable-image-core", "*", "*", 0.03),
+    ("amazon.titan-image", "*", "*", 0.01),
+    ("amazon.nova-canvas", "*", "1K", 0.04),
+    ("amazon.nova-canvas", "*", "*", 0.06),
+    ("stability.sd3-5-large", "*", "*", 0.08),
+    ("stability.stable-image-ultra", "*", "*", 0.14),
+    ("stability.stable-image-core", "*", "*", 0.04),
+    ("black-forest-labs/FLUX.1-schnell", "*", "*", 0.003),
+    ("black-forest-labs/FLUX.1-dev", "*", "*", 0.025),
+    ("black-forest-labs/FLUX.1.1-pro", "*", "*", 0.04),
+    ("replicate/black-forest-labs/flux-schnell", "*", "*", 0.003),
+    ("replicate/black-forest-labs/flux-dev", "*", "*", 0.025),
+    ("replicate/black-forest-labs/flux-1.1-pro", "*", "*", 0.04),
+    ("ideogram", "*", "*", 0.06),
+    // Runs on your own hardware.
+    ("sd-local", "*", "*", 0.0),
+];
+
+/// Projected cost of a set of requests.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Estimate {
+    /// Images with a known price.
+    pub images: usize,
+    /// Their total price in USD.
+    pub usd: f64,
+    /// Images of models without a known price, per model.
+    pub unpriced: BTreeMap<String, usize>,
+}
+
+impl Estimate {
+    /// Price `requests`, with `overrides` (model → USD per image) taking precedence
+    /// over the built-in table.
+    #[must_use]
+    pub fn of(requests: &[ImageRequest], overrides: &HashMap<String, f64>) -> Self {
+        let mut estimate = Self::default();
+        for request in requests {
+            let images = request.count as usize;
+            match price_per_image(request, overrides) {
+                Some(price) => {
+                    estimate.images += images;
+                    estimate.usd += price * f64::from(request.count);
+                }
+                None => *estimate.unpriced.entry(request.model.clone()).or_default() += images,
+            }
+        }
+        estimate
+    }
+
+    /// The total in USD, or `None` when some images have no known price.
+    #[must_use]
+    pub fn known_usd(&self) -> Option<f64> {
+        self.unpriced.is_empty().then_some(self.usd)
+    }
+}
+
+impl AddAssign for Estimate {
+    fn add_assign(&mut self, other: Self) {
+        self.images += other.images;
+        self.usd += other.usd;
+        for (model, images) in other.unpriced {
+            *self.unpriced.entry(model).or_default() += images;
+        }
+    }
+}
+
+/// `$0.68 for 4 images`, plus `; no known price for 2 images of azure/prod`.
+impl fmt::Display for Estimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        write!(f, "${:.2} for {} image{}", self.usd, self.images, plural(self.images))?;
+        for (model, images) in &self.unpriced {
+            write!(f, "; no known price for {images} image{} of {model}", plural(*images))?;
+        }
+        Ok(())
+    }
+}
+
+/// USD per image of `request`: a configured override for its model, or the table's price.
+pub fn price_per_image<S: BuildHasher>(
+    request: &ImageRequest,
+    overrides: &HashMap<String, f64, S>,
+) -> Option<f64> {
+    let overridden =
+        overrides.iter().find(|(model, _)| resolve_model(model) == request.model).map(|(_, p)| *p);
+    overridden.or_else(|| {
+        PRICES
+            .iter()
+            .find(|(model, quality, size, _)| {
+                request.model.starts_with(model)
+                    && (*quality == "*" || *quality == request.quality)
+                    && (*size == "*" || *size == request.size)
+            })
+            .map(|(.., price)| *price)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(model: &str, quality: &str, size: &str, count: u32) -> ImageRequest {
+        ImageRequest {
+            size: size.into(),
+            quality: quality.into(),
+            format: "png".into(),
+            count,
+            ..ImageRequest::sample(model, "a cat")
+        }
+    }
+
+    #[test]
+    #[allow(clippy::float_cmp)] // Single images cost exactly the table's price.
+    fn prices_follow_model_quality_and_size() {
+        let none = HashMap::new();
+        let cost = |model, quality, size| Estimate::of(&[request(model, quality, size, 1)], &none);
+        assert_eq!(cost("gpt-image-1", "low", "1K").usd, 0.011);
+        assert_eq!(cost("gpt-image-1", "auto", "1K").usd, 0.167);
+        assert_eq!(cost("gpt-image-1-mini", "medium", "1K").usd, 0.011);
+        assert_eq!(cost("gemini-3-pro-image-preview", "auto", "4K").usd, 0.24);
+        assert_eq!(cost("sd3.5-large-turbo", "auto", "1K").usd, 0.04);
+    }
+
+    #[test]
+    fn sums_requests_and_reports_unknown_models() {
+        let requests = [
+            request("gpt-image-1", "high", "1K", 4),
+            request("azure/prod", "high", "1K", 2),
+            request("gpt-image-1", "low", "1K", 1),
+        ];
+        let estimate = Estimate::of(&requests, &HashMap::new());
+        assert_eq!(estimate.images, 5);
+        assert!((estimate.usd - 0.679).abs() < 1e-9);
+        assert_eq!(estimate.known_usd(), None);
+        assert_eq!(
+            estimate.to_string(),
+            "$0.68 for 5 images; no known price for 2 images of azure/prod"
+        );
+
+        let overrides =
+            HashMap::from([("azure/prod".to_string(), 0.2), ("gpt-1".to_string(), 0.1)]);
+        let estimate = Estimate::of(&requests, &overrides);
+        assert!((estimate.known_usd().unwrap() - 0.9).abs() < 1e-9);
+    }
+}
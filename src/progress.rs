@@ -0,0 +1,131 @@
+//! Progress on stderr while a command runs: a spinner while the provider works, one per
+//! image as it downloads or converts, and for `imagen batch` a bar over the whole batch.
+//!
+//! Nothing is drawn until [`enable`] has been called, which `main` only does when stdout
+//! and stderr are both terminals, so piped and scripted runs see plain messages. Log
+//! lines are written through [`suspend`], which keeps them above the bars.
+
+use std::path::Path;
+use std::sync::{Mutex, OnceLock, PoisonError};
+use std::time::Duration;
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+
+/// How often spinners move.
+const TICK: Duration = Duration::from_millis(100);
+
+static UI: OnceLock<MultiProgress> = OnceLock::new();
+static BATCH: Mutex<Option<ProgressBar>> = Mutex::new(None);
+
+/// Start drawing progress.
+pub fn enable() {
+    UI.get_or_init(MultiProgress::new);
+}
+
+/// Run `f`, which writes to stderr, with the bars hidden so its output is not drawn over.
+pub fn suspend<R>(f: impl FnOnce() -> R) -> R {
+    match UI.get() {
+        Some(ui) => ui.suspend(f),
+        None => f(),
+    }
+}
+
+/// A bar or spinner on screen, cleared when dropped. Inert while progress is disabled.
+pub struct Progress(Option<ProgressBar>);
+
+impl Progress {
+    fn show(template: &str, length: u64, message: String) -> Self {
+        Self(UI.get().map(|ui| {
+            let bar = ui.add(ProgressBar::new(length).with_style(style(template)));
+            bar.set_message(message);
+            bar.enable_steady_tick(TICK);
+            bar
+        }))
+    }
+
+    /// Count `n` more steps done.
+    pub fn inc(&self, n: u64) {
+        if let Some(bar) = &self.0 {
+            bar.inc(n);
+        }
+    }
+
+    /// Report `received` bytes of a download, out of `total` when the server said.
+    pub fn downloaded(&self, received: u64, total: Option<u64>) {
+        let Some(bar) = &self.0 else { return };
+        if let Some(total) = total.filter(|&total| bar.length() != Some(total)) {
+            bar.set_length(total);
+            bar.set_style(style("{spinner} Downloading {msg} {bar:20} {bytes}/{total_bytes}"));
+        }
+        bar.set_position(received);
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        if let Some(bar) = self.0.take() {
+            bar.finish_and_clear();
+            if let Some(ui) = UI.get() {
+                ui.remove(&bar);
+            }
+        }
+    }
+}
+
+/// The bar over a whole batch, from [`batch`]; cleared when dropped.
+pub struct BatchProgress {
+    _bar: Progress,
+}
+
+impl Drop for BatchProgress {
+    fn drop(&mut self) {
+        *BATCH.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+}
+
+/// A spinner labelled with `model` while it answers `requests` requests; [`Progress::inc`]
+/// as each one is answered.
+#[must_use]
+pub fn waiting(model: &str, requests: usize) -> Progress {
+    let template = if requests > 1 {
+        "{spinner} Waiting for {msg} ({pos}/{len} requests) {elapsed}"
+    } else {
+        "{spinner} Waiting for {msg} {elapsed}"
+    };
+    Progress::show(template, requests as u64, model.to_string())
+}
+
+/// A spinner for downloading the image saved to `path`, which becomes a bar with
+/// [`Progress::downloaded`] once the size is known.
+#[must_use]
+pub fn download(path: &Path) -> Progress {
+    Progress::show("{spinner} Downloading {msg} {bytes}", 0, file_name(path))
+}
+
+/// A spinner while the image saved to `path` is decoded, post-processed, and encoded.
+#[must_use]
+pub fn converting(path: &Path) -> Progress {
+    Progress::show("{spinner} Converting {msg}", 0, file_name(path))
+}
+
+/// A bar over the `images` of a batch, advanced by [`advance_batch`] until dropped.
+pub fn batch(images: usize) -> BatchProgress {
+    let progress = Progress::show("{bar:30} {pos}/{len} images", images as u64, String::new());
+    BATCH.lock().unwrap_or_else(PoisonError::into_inner).clone_from(&progress.0);
+    BatchProgress { _bar: progress }
+}
+
+/// Count `images` of the batch as done, saved or failed.
+pub fn advance_batch(images: usize) {
+    if let Some(bar) = &*BATCH.lock().unwrap_or_else(PoisonError::into_inner) {
+        bar.inc(images as u64);
+    }
+}
+
+fn style(template: &str) -> ProgressStyle {
+    ProgressStyle::with_template(template).expect("progress templates are valid")
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().into_owned()
+}
@@ -0,0 +1,159 @@
+//! `--json`: one JSON object on stdout describing the whole command, for scripts.
+//!
+//! Warnings are collected as they are printed, and each run's summary as it finishes.
+//! [`print`] writes them out once the command has ended, however it ended, so a script
+//! always gets an object to parse. Human-readable messages stay on stderr.
+
+use std::fmt::Display;
+use std::path::PathBuf;
+use std::sync::{Mutex, PoisonError};
+
+use serde::Serialize;
+
+use crate::error::ImageError;
+use crate::pricing::Estimate;
+use crate::run_log::{Outcome, RunSummary};
+
+/// What has been collected for the report so far.
+struct Collected {
+    warnings: Vec<String>,
+    runs: Vec<RunReport>,
+    estimate: Option<Estimate>,
+}
+
+static COLLECTED: Mutex<Collected> =
+    Mutex::new(Collected { warnings: Vec::new(), runs: Vec::new(), estimate: None });
+
+/// One run of the command (a batch mixing models has one per model).
+#[derive(Debug, Serialize)]
+pub struct RunReport {
+    /// The run's settings, timing, cost, and outcome, as written to the run log.
+    #[serde(flatten)]
+    pub summary: RunSummary,
+    /// Request IDs, in request order.
+    pub request_ids: Vec<String>,
+    /// Files saved, in request order.
+    pub files: Vec<PathBuf>,
+    /// With `--thumbnail`, the thumbnail of each file, in the same order.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub thumbnails: Vec<PathBuf>,
+    /// With `--contact-sheet`, the grid of all the run's images.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact_sheet: Option<PathBuf>,
+}
+
+/// The object `--json` prints.
+#[derive(Debug, Serialize)]
+struct Report {
+    result: Outcome,
+    exit_code: i32,
+    error: Option<String>,
+    /// Every file saved, across runs.
+    files: Vec<PathBuf>,
+    /// With `--thumbnail`, every thumbnail written, across runs.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    thumbnails: Vec<PathBuf>,
+    /// With `--estimate`, the projected cost; nothing was sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    estimate: Option<Estimate>,
+    runs: Vec<RunReport>,
+    warnings: Vec<String>,
+}
+
+/// Log `message` as a warning, keeping it for the report.
+pub fn warn(message: impl Display) {
+    let message = message.to_string();
+    tracing::warn!("{message}");
+    collected(|collected| collected.warnings.push(message));
+}
+
+/// Keep a finished run for the report.
+pub fn record_run(run: RunReport) {
+    collected(|collected| collected.runs.push(run));
+}
+
+/// Keep the `--estimate` of a command that sent nothing.
+pub fn record_estimate(estimate: Estimate) {
+    collected(|collected| collected.estimate = Some(estimate));
+}
+
+/// Print everything collected, with how the command ended, as one JSON object.
+///
+/// # Panics
+///
+/// Panics if the report cannot be serialized to JSON.
+pub fn print(result: &Result<(), ImageError>) {
+    let collected = collected(|collected| Collected {
+        warnings: std::mem::take(&mut collected.warnings),
+        runs: std::mem::take(&mut collected.runs),
+        estimate: collected.estimate.take(),
+    });
+    let report = Report::new(result, collected);
+    println!("{}", serde_json::to_string_pretty(&report).expect("report serializes"));
+}
+
+impl Report {
+    fn new(result: &Result<(), ImageError>, collected: Collected) -> Self {
+        let Collected { warnings, runs, estimate } = collected;
+        Self {
+            result: Outcome::of(result),
+            exit_code: result.as_ref().map_or_else(ImageError::exit_code, |()| 0),
+            error: result.as_ref().err().map(ToString::to_string),
+            files: runs.iter().flat_map(|run| run.files.iter().cloned()).collect(),
+            thumbnails: runs.iter().flat_map(|run| run.thumbnails.iter().cloned()).collect(),
+            estimate,
+            runs,
+            warnings,
+        }
+    }
+}
+
+fn collected<T>(f: impl FnOnce(&mut Collected) -> T) -> T {
+    f(&mut COLLECTED.lock().unwrap_or_else(PoisonError::into_inner))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report_gathers_files_across_runs() {
+        let run = |files: &[&str]| RunReport {
+            summary: RunSummary {
+                timestamp: chrono::Utc::now(),
+                version: "0.0.0",
+                operation: "generate".into(),
+                provider: "openai".into(),
+                model: "gpt-image-1".into(),
+                parameters: std::collections::BTreeMap::new(),
+                duration_ms: 1200,
+                result: Outcome::Success,
+                images_requested: files.len(),
+                images_saved: files.len(),
+                error: None,
+                exit_code: 0,
+                cost_usd: Some(0.04),
+                usage: None,
+            },
+            request_ids: vec!["abc".into()],
+            files: files.iter().map(PathBuf::from).collect(),
+            thumbnails: Vec::new(),
+            contact_sheet: None,
+        };
+        let collected = Collected {
+            warnings: vec!["image 2 failed: timeout".into()],
+            runs: vec![run(&["a-1.png", "a-2.png"]), run(&["b.png"])],
+            estimate: None,
+        };
+        let partial = Err(ImageError::PartialFailure { saved: 3, total: 4 });
+        let json = serde_json::to_value(Report::new(&partial, collected)).unwrap();
+        assert_eq!(json["result"], "partial");
+        assert_eq!(json["exit_code"], 2);
+        assert_eq!(json["files"], serde_json::json!(["a-1.png", "a-2.png", "b.png"]));
+        assert_eq!(json["runs"][1]["model"], "gpt-image-1");
+        assert_eq!(json["runs"][0]["duration_ms"], 1200);
+        assert_eq!(json["warnings"][0], "image 2 failed: timeout");
+        assert!(json.get("estimate").is_none());
+        assert!(json.get("thumbnails").is_none(), "only with --thumbnail");
+    }
+}
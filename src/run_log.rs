@@ -0,0 +1,216 @@
+//! Append-only JSONL log of run summaries, for aggregating usage across machines.
+//!
+//! Every run that reaches a provider appends one JSON object on its own line: when it
+//! ran, its settings, how long it took, and how it ended. The prompt itself is never
+//! written, so the log can leave the machine. Once the file reaches its size limit it is
+//! renamed to `<path>.1` (older files shift to `.2`, `.3`, ...) and a new file is started.
+
+use std::collections::BTreeMap;
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::ImageError;
+use crate::ports::image_generator::Usage;
+
+/// Size at which the log is rotated when `[log] max_bytes` is not set.
+const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Rotated files kept when `[log] keep` is not set.
+const DEFAULT_KEEP: usize = 5;
+
+/// How a run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Outcome {
+    /// Every image was saved.
+    Success,
+    /// Some images were saved (exit status 2).
+    Partial,
+    /// No image was kept.
+    Failed,
+}
+
+/// One line of the log.
+#[derive(Debug, Serialize)]
+pub struct RunSummary {
+    /// When the run finished.
+    pub timestamp: DateTime<Utc>,
+    /// imagen version that made the run.
+    pub version: &'static str,
+    /// `generate` or `edit`.
+    pub operation: String,
+    /// Provider the requests went to.
+    pub provider: String,
+    /// Resolved model name.
+    pub model: String,
+    /// Effective settings, as recorded in cassette headers.
+    pub parameters: BTreeMap<String, String>,
+    /// Wall-clock time from sending the first request to saving the last image.
+    pub duration_ms: u64,
+    /// How the run ended.
+    pub result: Outcome,
+    /// Images asked for across every request of the run.
+    pub images_requested: usize,
+    /// Images written to disk.
+    pub images_saved: usize,
+    /// Error message of a run that did not fully succeed.
+    pub error: Option<String>,
+    /// Process exit status.
+    pub exit_code: i32,
+    /// Estimated cost in USD, when the model's price is known.
+    pub cost_usd: Option<f64>,
+    /// Tokens the provider reported billing, summed over the run's responses.
+    pub usage: Option<Usage>,
+}
+
+impl Outcome {
+    /// How a run (or the whole command) that ended with `result` went.
+    #[must_use]
+    pub fn of(result: &Result<(), ImageError>) -> Self {
+        match result {
+            Ok(()) => Self::Success,
+            Err(ImageError::PartialFailure { .. }) => Self::Partial,
+            Err(_) => Self::Failed,
+        }
+    }
+}
+
+impl RunSummary {
+    /// Fill in the result fields from how the run ended.
+    pub fn set_result(&mut self, result: &Result<(), ImageError>) {
+        self.result = Outcome::of(result);
+        self.images_saved = match result {
+            Ok(()) => self.images_requested,
+            Err(ImageError::PartialFailure { saved, .. }) => *saved,
+            Err(_) => 0,
+        };
+        self.error = result.as_ref().err().map(ToString::to_string);
+        self.exit_code = result.as_ref().map_or_else(ImageError::exit_code, |()| 0);
+    }
+}
+
+/// Where summaries are appended, and when the file is rotated.
+pub struct RunLog {
+    path: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+}
+
+impl RunLog {
+    /// The configured log, or `None` when logging is off (no `[log] path` or `IMAGEN_RUN_LOG`).
+    #[must_use]
+    pub fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            path: config.run_log_path()?,
+            max_bytes: config.log.max_bytes.unwrap_or(DEFAULT_MAX_BYTES),
+            keep: config.log.keep.unwrap_or(DEFAULT_KEEP),
+        })
+    }
+
+    /// Append `summary` as one line, rotating the file first if it is full.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory, the file, or a rotated file cannot be written.
+    pub fn append(&self, summary: &RunSummary) -> std::io::Result<()> {
+        if let Some(parent) = self.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        if std::fs::metadata(&self.path).is_ok_and(|meta| meta.len() >= self.max_bytes) {
+            self.rotate()?;
+        }
+        let mut line = serde_json::to_string(summary).map_err(std::io::Error::other)?;
+        line.push('\n');
+        // One write per line, so concurrent runs appending to the same file don't interleave.
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())
+    }
+
+    /// Shift `<path>.N` to `<path>.N+1`, dropping the oldest, and move the log to `<path>.1`.
+    fn rotate(&self) -> std::io::Result<()> {
+        if self.keep == 0 {
+            return std::fs::remove_file(&self.path);
+        }
+        let _ = std::fs::remove_file(rotated(&self.path, self.keep));
+        for n in (1..self.keep).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                std::fs::rename(from, rotated(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated(&self.path, 1))
+    }
+}
+
+/// `runs.jsonl` → `runs.jsonl.3`.
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{n}"));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary() -> RunSummary {
+        RunSummary {
+            timestamp: Utc::now(),
+            version: env!("CARGO_PKG_VERSION"),
+            operation: "generate".into(),
+            provider: "openai".into(),
+            model: "gpt-image-1".into(),
+            parameters: BTreeMap::from([("size".to_string(), "1K".to_string())]),
+            duration_ms: 1200,
+            result: Outcome::Failed,
+            images_requested: 4,
+            images_saved: 0,
+            error: None,
+            exit_code: 0,
+            cost_usd: None,
+            usage: Some(Usage { input_tokens: 12, output_tokens: 272 }),
+        }
+    }
+
+    #[test]
+    fn result_fields_follow_the_outcome() {
+        let mut run = summary();
+        run.set_result(&Err(ImageError::PartialFailure { saved: 3, total: 4 }));
+        assert_eq!((run.result, run.images_saved, run.exit_code), (Outcome::Partial, 3, 2));
+        assert_eq!(run.error.as_deref(), Some("Saved 3 of 4 images; 1 failed"));
+
+        run.set_result(&Ok(()));
+        assert_eq!((run.result, run.images_saved, run.exit_code), (Outcome::Success, 4, 0));
+        assert!(run.error.is_none());
+    }
+
+    #[test]
+    fn appends_lines_and_rotates_when_full() {
+        let dir = std::env::temp_dir().join("imagen_run_log_test");
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("logs/runs.jsonl");
+        let log = RunLog { path: path.clone(), max_bytes: 1, keep: 2 };
+
+        for _ in 0..4 {
+            log.append(&summary()).unwrap();
+        }
+        let line = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(line.lines().count(), 1);
+        let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(json["result"], "failed");
+        assert_eq!(json["parameters"]["size"], "1K");
+        assert_eq!(json["usage"]["output_tokens"], 272);
+        assert!(rotated(&path, 1).exists());
+        assert!(rotated(&path, 2).exists());
+        assert!(!rotated(&path, 3).exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,290 @@
+//! Shared on-disk state: one `SQLite` database backing history, usage, and cache data.
+//!
+//! Many `imagen` processes may run at once (e.g. under `make -j`), so the database is
+//! opened in WAL mode: readers never block the writer, and `SQLite`'s own file locks
+//! serialize writers. Writes take the lock up front (`BEGIN IMMEDIATE`) and wait up to
+//! [`BUSY_TIMEOUT`] for other processes instead of failing with `SQLITE_BUSY`.
+//!
+//! Each subsystem owns its tables and registers their DDL with [`Store::migrate`], which
+//! applies any steps the database has not seen yet.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use rusqlite::{Connection, OptionalExtension, Transaction, TransactionBehavior};
+
+use crate::error::ImageError;
+
+/// File name of the store inside the data directory.
+const STORE_FILE: &str = "imagen.db";
+
+/// How long a write waits for another process to release the database.
+pub const BUSY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handle to the shared store.
+pub struct Store {
+    conn: Connection,
+}
+
+impl Store {
+    /// Default store location: `$IMAGEN_DATA_DIR/imagen.db`, else
+    /// `$XDG_DATA_HOME/imagen/imagen.db`, else `~/.local/share/imagen/imagen.db`.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = std::env::var("IMAGEN_DATA_DIR").ok().map(PathBuf::from).or_else(|| {
+            let data_home =
+                std::env::var("XDG_DATA_HOME").ok().map(PathBuf::from).or_else(|| {
+                    Some(PathBuf::from(std::env::var("HOME").ok()?).join(".local/share"))
+                })?;
+            Some(data_home.join("imagen"))
+        })?;
+        Some(dir.join(STORE_FILE))
+    }
+
+    /// Open (creating if needed) the store at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory cannot be created or the database cannot be opened.
+    pub fn open(path: &Path) -> Result<Self, ImageError> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        // WAL is persistent, but setting it takes a lock; skip when already enabled.
+        let mode: String = conn.query_row("PRAGMA journal_mode", [], |row| row.get(0))?;
+        if !mode.eq_ignore_ascii_case("wal") {
+            conn.query_row("PRAGMA journal_mode = WAL", [], |row| row.get::<_, String>(0))?;
+        }
+        conn.execute_batch(
+            "PRAGMA synchronous = NORMAL;
+             PRAGMA foreign_keys = ON;
+             CREATE TABLE IF NOT EXISTS schema_versions (
+                 component TEXT PRIMARY KEY,
+                 version   INTEGER NOT NULL
+             );",
+        )?;
+        Ok(Self { conn })
+    }
+
+    /// Bring `component`'s tables up to date.
+    ///
+    /// `steps[i]` upgrades the component from version `i` to `i + 1`. Pending steps run in
+    /// one write transaction, so concurrent processes apply each step exactly once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a migration fails; the transaction is rolled back.
+    pub fn migrate(&mut self, component: &str, steps: &[&str]) -> Result<(), ImageError> {
+        self.write(|tx| {
+            let current: usize = tx
+                .query_row(
+                    "SELECT version FROM schema_versions WHERE component = ?1",
+                    [component],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .unwrap_or(0);
+            if current >= steps.len() {
+                return Ok(());
+            }
+            for step in &steps[current..] {
+                tx.execute_batch(step)?;
+            }
+            tx.execute(
+                "INSERT INTO schema_versions (component, version) VALUES (?1, ?2)
+                 ON CONFLICT(component) DO UPDATE SET version = excluded.version",
+                rusqlite::params![component, steps.len()],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Run `f` in a write transaction, committing if it succeeds.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the lock cannot be taken within [`BUSY_TIMEOUT`] or `f` fails.
+    pub fn write<T>(
+        &mut self,
+        f: impl FnOnce(&Transaction<'_>) -> rusqlite::Result<T>,
+    ) -> Result<T, ImageError> {
+        let tx = self.conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let value = f(&tx)?;
+        tx.commit()?;
+        Ok(value)
+    }
+
+    /// Bytes that [`Store::compact`] would return to the filesystem: free pages plus the WAL.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be queried.
+    pub fn reclaimable_bytes(&self, path: &Path) -> Result<u64, ImageError> {
+        let pragma = |name: &str| -> rusqlite::Result<u64> {
+            self.conn.query_row(&format!("PRAGMA {name}"), [], |row| row.get(0))
+        };
+        let free = pragma("freelist_count")? * pragma("page_size")?;
+        Ok(free + file_len(&sidecar(path, "-wal")))
+    }
+
+    /// Fold the WAL into the main file and rebuild it without free pages.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if another process holds the database for longer than
+    /// [`BUSY_TIMEOUT`].
+    pub fn compact(&mut self) -> Result<(), ImageError> {
+        self.conn.execute_batch("VACUUM;")?;
+        self.conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+        Ok(())
+    }
+
+    /// Total size of the store on disk, including its WAL and shared-memory files.
+    #[must_use]
+    pub fn disk_usage(path: &Path) -> u64 {
+        file_len(path) + file_len(&sidecar(path, "-wal")) + file_len(&sidecar(path, "-shm"))
+    }
+
+    /// Run read-only queries against a consistent snapshot.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `f` fails.
+    pub fn read<T>(
+        &self,
+        f: impl FnOnce(&Connection) -> rusqlite::Result<T>,
+    ) -> Result<T, ImageError> {
+        Ok(f(&self.conn)?)
+    }
+}
+
+/// Path of a `SQLite` sidecar file (`-wal`, `-shm`) next to the database.
+fn sidecar(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+fn file_len(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |m| m.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COUNTER_V1: &str = "CREATE TABLE hits (id INTEGER PRIMARY KEY, who TEXT NOT NULL);";
+    const COUNTER_V2: &str = "ALTER TABLE hits ADD COLUMN at INTEGER NOT NULL DEFAULT 0;";
+
+    fn temp_store(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("imagen_store_{name}_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir.join(STORE_FILE)
+    }
+
+    #[test]
+    fn open_enables_wal_and_creates_parent() {
+        let path = temp_store("wal");
+        let store = Store::open(&path).unwrap();
+        let mode: String =
+            store.read(|c| c.query_row("PRAGMA journal_mode", [], |row| row.get(0))).unwrap();
+        assert_eq!(mode, "wal");
+        assert!(path.exists());
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn migrations_apply_once_and_resume() {
+        let path = temp_store("migrate");
+        let mut store = Store::open(&path).unwrap();
+        store.migrate("counter", &[COUNTER_V1]).unwrap();
+        store.migrate("counter", &[COUNTER_V1]).unwrap();
+        drop(store);
+
+        let mut store = Store::open(&path).unwrap();
+        store.migrate("counter", &[COUNTER_V1, COUNTER_V2]).unwrap();
+        store.write(|tx| tx.execute("INSERT INTO hits (who, at) VALUES ('a', 1)", [])).unwrap();
+        let version: i64 = store
+            .read(|c| {
+                c.query_row(
+                    "SELECT version FROM schema_versions WHERE component = 'counter'",
+                    [],
+                    |row| row.get(0),
+                )
+            })
+            .unwrap();
+        assert_eq!(version, 2);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn failed_write_rolls_back() {
+        let path = temp_store("rollback");
+        let mut store = Store::open(&path).unwrap();
+        store.migrate("counter", &[COUNTER_V1]).unwrap();
+        let result = store.write(|tx| {
+            tx.execute("INSERT INTO hits (who) VALUES ('a')", [])?;
+            tx.execute("INSERT INTO missing_table VALUES (1)", [])
+        });
+        assert!(matches!(result, Err(ImageError::Store(_))));
+        let count: i64 =
+            store.read(|c| c.query_row("SELECT COUNT(*) FROM hits", [], |row| row.get(0))).unwrap();
+        assert_eq!(count, 0);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn compact_reclaims_deleted_rows() {
+        let path = temp_store("compact");
+        let mut store = Store::open(&path).unwrap();
+        store.migrate("counter", &[COUNTER_V1]).unwrap();
+        store
+            .write(|tx| {
+                for i in 0..2000 {
+                    tx.execute("INSERT INTO hits (who) VALUES (?1)", [format!("{i:0>200}")])?;
+                }
+                tx.execute("DELETE FROM hits", [])
+            })
+            .unwrap();
+        assert!(store.reclaimable_bytes(&path).unwrap() > 0);
+
+        let before = Store::disk_usage(&path);
+        store.compact().unwrap();
+        assert!(Store::disk_usage(&path) < before);
+        assert_eq!(store.reclaimable_bytes(&path).unwrap(), 0);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn concurrent_writers_do_not_lose_rows() {
+        let path = temp_store("concurrent");
+        Store::open(&path).unwrap().migrate("counter", &[COUNTER_V1]).unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|worker| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    // Each thread opens its own connection, as separate processes would.
+                    let mut store = Store::open(&path).unwrap();
+                    store.migrate("counter", &[COUNTER_V1]).unwrap();
+                    for _ in 0..25 {
+                        store
+                            .write(|tx| tx.execute("INSERT INTO hits (who) VALUES (?1)", [worker]))
+                            .unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let store = Store::open(&path).unwrap();
+        let count: i64 =
+            store.read(|c| c.query_row("SELECT COUNT(*) FROM hits", [], |row| row.get(0))).unwrap();
+        assert_eq!(count, 200);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
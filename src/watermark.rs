@@ -0,0 +1,407 @@
+//! Text and logo watermarks stamped onto images while they are saved (`--watermark`,
+//! `--watermark-image`).
+//!
+//! Text is drawn with a built-in 5x8 bitmap font, scaled with the image, in white over
+//! a dark shadow so it reads on light and dark backgrounds alike. Logos are scaled down
+//! to at most a quarter of the image. Either is blended in at `--watermark-opacity`.
+
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageBuffer, Rgba, RgbaImage};
+
+type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+/// Where the watermark goes.
+#[allow(missing_docs)] // The names say where.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Position {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    #[default]
+    BottomRight,
+}
+
+/// Names accepted by [`Position::parse`], for `--watermark-position`.
+pub const POSITIONS: [&str; 9] = [
+    "top-left",
+    "top",
+    "top-right",
+    "left",
+    "center",
+    "right",
+    "bottom-left",
+    "bottom",
+    "bottom-right",
+];
+
+impl Position {
+    /// Parse a position name (`top-left`, `center`, `bottom-right`, ...).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for unknown position names.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        let position = match name {
+            "top-left" => Self::TopLeft,
+            "top" => Self::Top,
+            "top-right" => Self::TopRight,
+            "left" => Self::Left,
+            "center" => Self::Center,
+            "right" => Self::Right,
+            "bottom-left" => Self::BottomLeft,
+            "bottom" => Self::Bottom,
+            "bottom-right" => Self::BottomRight,
+            other => {
+                return Err(format!(
+                    "Invalid watermark position '{other}'. Valid: {}",
+                    POSITIONS.join(", ")
+                ))
+            }
+        };
+        Ok(position)
+    }
+
+    /// Horizontal and vertical alignment: 0 for the start, 1 the middle, 2 the end.
+    fn alignment(self) -> (u32, u32) {
+        match self {
+            Self::TopLeft => (0, 0),
+            Self::Top => (1, 0),
+            Self::TopRight => (2, 0),
+            Self::Left => (0, 1),
+            Self::Center => (1, 1),
+            Self::Right => (2, 1),
+            Self::BottomLeft => (0, 2),
+            Self::Bottom => (1, 2),
+            Self::BottomRight => (2, 2),
+        }
+    }
+}
+
+/// What is stamped.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Mark {
+    /// A line of text (printable ASCII; anything else is drawn as `?`).
+    Text(String),
+    /// A logo, drawn with its own transparency.
+    Image(RgbaImage),
+}
+
+/// A watermark and how to place it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watermark {
+    /// The text or logo.
+    pub mark: Mark,
+    /// Where it goes.
+    pub position: Position,
+    /// How opaque it is, from 0 (invisible) to 1.
+    pub opacity: f32,
+    /// A logo's width as a fraction of the image's; `None` keeps the logo's own size,
+    /// shrunk to fit a quarter of the image. Text scales with the image regardless.
+    pub scale: Option<f32>,
+}
+
+impl Watermark {
+    /// Stamp the watermark onto `img`, returned as 16-bit RGBA so no precision is lost
+    /// before the caller converts it back to the layout it needs.
+    #[must_use]
+    pub fn apply(&self, img: &DynamicImage) -> DynamicImage {
+        let mut canvas = img.to_rgba16();
+        let (width, height) = canvas.dimensions();
+        let margin = width.min(height) / 40;
+        let stamp = match &self.mark {
+            Mark::Text(text) => text_stamp(text, width.saturating_sub(2 * margin), width, height),
+            Mark::Image(logo) => logo_stamp(logo, self.scale, width, height),
+        };
+        let (horizontal, vertical) = self.position.alignment();
+        let x = offset(width, stamp.width(), margin, horizontal);
+        let y = offset(height, stamp.height(), margin, vertical);
+        blend(&mut canvas, &stamp, (x, y), self.opacity.clamp(0.0, 1.0));
+        DynamicImage::ImageRgba16(canvas)
+    }
+}
+
+/// Where something `size` long starts in `space`, `margin` in from the edge it is
+/// aligned to (0 start, 1 middle, 2 end).
+fn offset(space: u32, size: u32, margin: u32, align: u32) -> u32 {
+    match align {
+        0 => margin,
+        1 => space.saturating_sub(size) / 2,
+        _ => space.saturating_sub(size + margin),
+    }
+    .min(space.saturating_sub(size))
+}
+
+/// Alpha-blend `stamp` onto `canvas` with its top-left corner at `origin`, scaling the
+/// stamp's own alpha by `opacity`.
+fn blend(canvas: &mut Rgba16Image, stamp: &RgbaImage, (x0, y0): (u32, u32), opacity: f32) {
+    for (x, y, pixel) in stamp.enumerate_pixels() {
+        let (x, y) = (x0 + x, y0 + y);
+        if x >= canvas.width() || y >= canvas.height() {
+            continue;
+        }
+        let alpha = f32::from(pixel[3]) / 255.0 * opacity;
+        if alpha <= 0.0 {
+            continue;
+        }
+        let below = canvas.get_pixel_mut(x, y);
+        for channel in 0..3 {
+            let over = f32::from(pixel[channel]) * 257.0;
+            let under = f32::from(below[channel]);
+            below[channel] = to_u16(under + (over - under) * alpha);
+        }
+        let under = f32::from(below[3]);
+        below[3] = to_u16(under + (65535.0 - under) * alpha);
+    }
+}
+
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn to_u16(value: f32) -> u16 {
+    value.round().clamp(0.0, 65535.0) as u16
+}
+
+/// `logo` at `scale` of the image's width, or shrunk to fit a quarter of the image's
+/// width and height if it is larger.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)] // Bounded by the image size.
+fn logo_stamp(logo: &RgbaImage, scale: Option<f32>, width: u32, height: u32) -> RgbaImage {
+    if let Some(scale) = scale {
+        let logo_width = ((f64::from(width) * f64::from(scale)).round() as u32).max(1);
+        let logo_height = ((f64::from(logo.height()) * f64::from(logo_width)
+            / f64::from(logo.width().max(1)))
+        .round() as u32)
+            .clamp(1, height);
+        return image::imageops::resize(logo, logo_width, logo_height, FilterType::Lanczos3);
+    }
+    let (max_width, max_height) = ((width / 4).max(1), (height / 4).max(1));
+    if logo.width() <= max_width && logo.height() <= max_height {
+        return logo.clone();
+    }
+    DynamicImage::ImageRgba8(logo.clone())
+        .resize(max_width, max_height, FilterType::Lanczos3)
+        .to_rgba8()
+}
+
+/// Glyph columns, including the blank column after each character.
+pub const ADVANCE: u32 = GLYPH_WIDTH + 1;
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 8;
+
+/// `text` in white over a dark shadow, scaled for an image of `width` x `height` but no
+/// wider than `max_width` where the font allows.
+#[must_use]
+pub fn text_stamp(text: &str, max_width: u32, width: u32, height: u32) -> RgbaImage {
+    let columns =
+        u32::try_from(text.chars().count()).unwrap_or(u32::MAX).max(1).saturating_mul(ADVANCE);
+    let fits = max_width / (columns + 1);
+    let scale = (width.min(height) / 256).min(fits).max(1);
+    let shadow = (scale / 2).max(1);
+    let mut stamp = RgbaImage::new(columns * scale + shadow, GLYPH_HEIGHT * scale + shadow);
+    for (offset, color) in [(shadow, Rgba([0, 0, 0, 160])), (0, Rgba([255, 255, 255, 255]))] {
+        for (i, c) in (0..).zip(text.chars()) {
+            for (column, bits) in (0..).zip(glyph(c)) {
+                for row in (0..GLYPH_HEIGHT).filter(|row| bits >> row & 1 == 1) {
+                    let x = (i * ADVANCE + column) * scale + offset;
+                    let y = row * scale + offset;
+                    for (dx, dy) in (0..scale).flat_map(|dx| (0..scale).map(move |dy| (dx, dy))) {
+                        stamp.put_pixel(x + dx, y + dy, color);
+                    }
+                }
+            }
+        }
+    }
+    stamp
+}
+
+/// The columns of `c`, top row in the lowest bit.
+fn glyph(c: char) -> [u8; 5] {
+    let index = match c {
+        ' '..='~' => c as usize - ' ' as usize,
+        _ => '?' as usize - ' ' as usize,
+    };
+    FONT[index]
+}
+
+/// Printable ASCII, `' '` to `'~'`, five columns each.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5F, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x56, 0x20, 0x50],
+    [0x00, 0x08, 0x07, 0x03, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00],
+    [0x2A, 0x1C, 0x7F, 0x1C, 0x2A],
+    [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x80, 0x70, 0x30, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x00, 0x60, 0x60, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E],
+    [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x72, 0x49, 0x49, 0x49, 0x46],
+    [0x21, 0x41, 0x49, 0x4D, 0x33],
+    [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3C, 0x4A, 0x49, 0x49, 0x31],
+    [0x41, 0x21, 0x11, 0x09, 0x07],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x46, 0x49, 0x49, 0x29, 0x1E],
+    [0x00, 0x00, 0x14, 0x00, 0x00],
+    [0x00, 0x40, 0x34, 0x00, 0x00],
+    [0x00, 0x08, 0x14, 0x22, 0x41],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x59, 0x09, 0x06],
+    [0x3E, 0x41, 0x5D, 0x59, 0x4E],
+    [0x7C, 0x12, 0x11, 0x12, 0x7C],
+    [0x7F, 0x49, 0x49, 0x49, 0x36],
+    [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x49, 0x49, 0x49, 0x41],
+    [0x7F, 0x09, 0x09, 0x09, 0x01],
+    [0x3E, 0x41, 0x41, 0x51, 0x73],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F],
+    [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01],
+    [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x1C, 0x02, 0x7F],
+    [0x7F, 0x04, 0x08, 0x10, 0x7F],
+    [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06],
+    [0x3E, 0x41, 0x51, 0x21, 0x5E],
+    [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x26, 0x49, 0x49, 0x49, 0x32],
+    [0x03, 0x01, 0x7F, 0x01, 0x03],
+    [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F],
+    [0x3F, 0x40, 0x38, 0x40, 0x3F],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x03, 0x04, 0x78, 0x04, 0x03],
+    [0x61, 0x59, 0x49, 0x4D, 0x43],
+    [0x00, 0x7F, 0x41, 0x41, 0x41],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x41, 0x7F],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x03, 0x07, 0x08, 0x00],
+    [0x20, 0x54, 0x54, 0x78, 0x40],
+    [0x7F, 0x28, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x28],
+    [0x38, 0x44, 0x44, 0x28, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x00, 0x08, 0x7E, 0x09, 0x02],
+    [0x18, 0xA4, 0xA4, 0x9C, 0x78],
+    [0x7F, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7D, 0x40, 0x00],
+    [0x20, 0x40, 0x40, 0x3D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00],
+    [0x7C, 0x04, 0x78, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0xFC, 0x18, 0x24, 0x24, 0x18],
+    [0x18, 0x24, 0x24, 0x18, 0xFC],
+    [0x7C, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x24],
+    [0x04, 0x04, 0x3F, 0x44, 0x24],
+    [0x3C, 0x40, 0x40, 0x20, 0x7C],
+    [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x4C, 0x90, 0x90, 0x90, 0x7C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x77, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x02, 0x01, 0x02, 0x04, 0x02],
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canvas() -> DynamicImage {
+        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(400, 300, image::Rgb([0, 0, 255])))
+    }
+
+    #[test]
+    fn logo_lands_in_the_requested_corner() {
+        let logo = RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        let mark = |position| Watermark {
+            mark: Mark::Image(logo.clone()),
+            position,
+            opacity: 1.0,
+            scale: None,
+        };
+        let margin = 300 / 40;
+
+        let marked = mark(Position::BottomRight).apply(&canvas()).to_rgb8();
+        assert_eq!(marked.get_pixel(400 - margin - 1, 300 - margin - 1).0, [255, 0, 0]);
+        assert_eq!(marked.get_pixel(margin, margin).0, [0, 0, 255]);
+
+        let marked = mark(Position::TopLeft).apply(&canvas()).to_rgb8();
+        assert_eq!(marked.get_pixel(margin, margin).0, [255, 0, 0]);
+        let marked = mark(Position::Center).apply(&canvas()).to_rgb8();
+        assert_eq!(marked.get_pixel(200, 150).0, [255, 0, 0]);
+    }
+
+    #[test]
+    fn opacity_blends_with_the_image() {
+        let logo = RgbaImage::from_pixel(10, 10, Rgba([255, 0, 0, 255]));
+        let mark = Watermark {
+            mark: Mark::Image(logo),
+            position: Position::Center,
+            opacity: 0.5,
+            scale: None,
+        };
+        let marked = mark.apply(&canvas()).to_rgb8();
+        assert_eq!(marked.get_pixel(200, 150).0, [128, 0, 128]);
+        let hidden = Watermark { opacity: 0.0, ..mark };
+        assert_eq!(hidden.apply(&canvas()).to_rgb8(), canvas().to_rgb8());
+    }
+
+    #[test]
+    fn text_is_drawn_and_fits_the_image() {
+        let stamp = text_stamp("imagen", 380, 400, 300);
+        assert!(stamp.width() <= 380);
+        assert!(stamp.pixels().any(|pixel| pixel.0 == [255, 255, 255, 255]));
+
+        let long = text_stamp(&"x".repeat(200), 380, 400, 300);
+        assert_eq!(long.height(), GLYPH_HEIGHT + 1, "falls back to the smallest scale");
+
+        let mark = Watermark {
+            mark: Mark::Text("imagen".into()),
+            position: Position::BottomLeft,
+            opacity: 1.0,
+            scale: None,
+        };
+        assert_ne!(mark.apply(&canvas()).to_rgb8(), canvas().to_rgb8());
+    }
+
+    #[test]
+    fn logos_are_sized_to_the_image() {
+        let logo = RgbaImage::new(1000, 500);
+        let stamp = logo_stamp(&logo, None, 400, 300);
+        assert_eq!(stamp.dimensions(), (100, 50));
+        let stamp = logo_stamp(&logo, Some(0.5), 400, 300);
+        assert_eq!(stamp.dimensions(), (200, 100));
+        let stamp = logo_stamp(&logo, None, 4000, 3000);
+        assert_eq!(stamp.dimensions(), (1000, 500), "never enlarged");
+    }
+
+    #[test]
+    fn positions_parse() {
+        for name in POSITIONS {
+            assert!(Position::parse(name).is_ok(), "{name}");
+        }
+        assert!(Position::parse("middle").is_err());
+    }
+}
@@ -7,7 +7,15 @@ use assert_cmd::Command;
 use predicates::prelude::*;
 
 fn cmd() -> Command {
-    assert_cmd::cargo::cargo_bin_cmd!("imagen")
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("imagen");
+    // Keep history out of the real data directory.
+    cmd.env("IMAGEN_DATA_DIR", std::env::temp_dir().join("imagen_test_data"));
+    cmd.env_remove("IMAGEN_PROFILE");
+    // Never read the developer's own keys from the OS keychain.
+    cmd.env("IMAGEN_NO_KEYRING", "1");
+    // A missing prompt is read from piped stdin; don't wait on the test runner's.
+    cmd.write_stdin("");
+    cmd
 }
 
 #[test]
@@ -16,6 +24,33 @@ fn missing_prompt_exits_with_error() {
     cmd().assert().failure().stderr(predicate::str::contains("Provide a prompt string"));
 }
 
+#[test]
+fn prompt_is_read_from_stdin() {
+    let request_id = |args: &[&str], stdin: &str| {
+        let output = cmd()
+            .args(["--estimate", "--id", "--model", "gpt-1"])
+            .args(args)
+            .write_stdin(stdin)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap().lines().next().unwrap().to_string()
+    };
+    let path = std::env::temp_dir().join("imagen_test_stdin_prompt.txt");
+    std::fs::write(&path, "a cat\n").unwrap();
+    let from_file = request_id(&["-p", path.to_str().unwrap()], "");
+    assert_eq!(request_id(&["-"], "a cat\n"), from_file);
+    assert_eq!(request_id(&[], "a cat\n"), from_file);
+    let _ = std::fs::remove_file(&path);
+
+    cmd()
+        .args(["--confirm", "--model", "gpt-image-1", "-"])
+        .write_stdin("a cat\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--confirm reads its answer from stdin"));
+}
+
 #[test]
 fn invalid_model_exits_with_error() {
     // Model that doesn't start with "gemini" or "gpt-image" → detect_provider() rejects it
@@ -26,11 +61,74 @@ fn invalid_model_exits_with_error() {
         .stderr(predicate::str::contains("Unknown provider for model"));
 }
 
+#[test]
+fn profile_is_selected_by_flag_or_environment() {
+    let dir = std::env::temp_dir().join("imagen_test_profiles");
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.toml");
+    std::fs::write(&config, "[profiles.legacy.defaults]\nmodel = \"dall-e-3\"\n").unwrap();
+
+    // The profile's default model is used, and rejected before any request is made.
+    cmd()
+        .args(["--config", config.to_str().unwrap(), "--profile", "legacy", "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown provider for model"));
+    cmd()
+        .env("IMAGEN_PROFILE", "work")
+        .args(["--config", config.to_str().unwrap(), "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Unknown profile 'work'"));
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn config_init_set_show_check_and_path() {
+    let dir = std::env::temp_dir().join("imagen_test_config_cmd");
+    let _ = std::fs::remove_dir_all(&dir);
+    let path = dir.join("imagen/config.toml");
+    let config = || {
+        let mut cmd = cmd();
+        cmd.env("IMAGEN_CONFIG", &path).env_remove("OPENAI_API_KEY").arg("config");
+        cmd
+    };
+
+    config().arg("path").assert().success().stdout(format!("{}\n", path.display()));
+    config().arg("init").assert().success();
+    config().arg("init").assert().failure().stderr(predicate::str::contains("--force"));
+    config().args(["set", "defaults.model", "gpt-1"]).assert().success();
+    config().args(["set", "--profile", "work", "keys.openai", "sk-work-123"]).assert().success();
+    config()
+        .args(["set", "history.enabled", "sometimes"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("expected a boolean"));
+
+    let text = std::fs::read_to_string(&path).unwrap();
+    assert!(text.contains("model = \"gpt-1\"") && text.contains("# `imagen config show`"));
+    config().arg("show").assert().success().stdout(
+        predicate::str::contains("model = \"gpt-1\"").and(predicate::str::contains("openai").not()),
+    );
+    config().args(["show", "--profile", "work"]).assert().success().stdout(
+        predicate::str::contains("openai = \"<redacted>\"")
+            .and(predicate::str::contains("sk-work-123").not()),
+    );
+
+    config().arg("check").assert().failure().stdout(predicate::str::contains("cannot run"));
+    config().args(["check", "--profile", "work"]).assert().success();
+    std::fs::write(&path, text + "\n[defaults.per_model.gpt-1]\nqualty = \"high\"\n").unwrap();
+    config().args(["check", "--profile", "work"]).assert().failure().stdout(
+        predicate::str::starts_with("defaults.per_model.gpt-1.qualty: unknown field `qualty`"),
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
 #[test]
 fn invalid_aspect_ratio_exits_with_error() {
     // Validation fires before any cassette is opened; no API key needed
     cmd()
-        .args(["--model", "nano-banana", "--aspect-ratio", "100:200", "a cat"])
+        .args(["--model", "nano-banana", "--aspect-ratio", "100:200", "--strict-ratio", "a cat"])
         .assert()
         .failure()
         .stderr(predicate::str::contains("Unsupported aspect ratio"));
@@ -39,7 +137,7 @@ fn invalid_aspect_ratio_exits_with_error() {
 #[test]
 fn invalid_format_exits_with_error() {
     cmd()
-        .args(["--model", "nano-banana", "--format", "gif", "a cat"])
+        .args(["--model", "nano-banana", "--format", "heic", "a cat"])
         .assert()
         .failure()
         .stderr(predicate::str::contains("Unsupported format"));
@@ -63,18 +161,28 @@ fn background_with_gemini_exits_with_error() {
         .stderr(predicate::str::contains("only supported for OpenAI"));
 }
 
+#[test]
+fn api_base_with_gemini_exits_with_error() {
+    cmd()
+        .args(["--model", "nano-banana", "--api-base", "http://localhost:4000/v1", "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--api-base is only supported for OpenAI"));
+}
+
+#[test]
+fn style_type_with_gemini_exits_with_error() {
+    cmd()
+        .args(["--model", "nano-banana", "--style-type", "design", "a sign that says OPEN"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("only supported for Ideogram"));
+}
+
 #[test]
 fn background_transparent_with_jpeg_exits_with_error() {
     cmd()
-        .args([
-            "--model",
-            "gpt-1",
-            "--background",
-            "transparent",
-            "--format",
-            "jpeg",
-            "a cat",
-        ])
+        .args(["--model", "gpt-1", "--background", "transparent", "--format", "jpeg", "a cat"])
         .assert()
         .failure()
         .stderr(predicate::str::contains("alpha"));
@@ -88,3 +196,111 @@ fn missing_input_file_exits_with_error() {
         .failure()
         .stderr(predicate::str::contains("not found"));
 }
+
+#[test]
+fn offline_without_cassette_exits_with_error() {
+    cmd()
+        .env_remove("IMAGEN_REPLAY")
+        .env("GEMINI_API_KEY", "test-key-not-used")
+        .args(["--model", "nano-banana", "--offline", "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Offline mode"));
+}
+
+#[test]
+fn conflicting_modes_exit_with_error() {
+    cmd()
+        .env("IMAGEN_REPLAY", "cassette.yaml")
+        .args(["--model", "nano-banana", "--mode", "record", "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("conflicts with IMAGEN_REPLAY"));
+}
+
+#[test]
+fn watch_without_prompt_file_exits_with_error() {
+    cmd()
+        .args(["--watch", "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("--watch needs a prompt file"));
+}
+
+#[test]
+fn compare_needs_distinct_models() {
+    cmd()
+        .args(["compare", "--models", "nano-banana", "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("at least two --models"));
+    cmd()
+        .args(["compare", "--models", "gpt-1,nano-banana,gpt-1", "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("lists gpt-1 twice"));
+}
+
+#[test]
+fn edit_without_input_exits_with_error() {
+    cmd()
+        .args(["edit", "--model", "gpt-image-1", "make it nighttime"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("at least one --input image"));
+}
+
+#[test]
+fn edit_with_unsupported_provider_exits_with_error() {
+    cmd()
+        .args(["edit", "--model", "flux", "--input", "photo.png", "make it nighttime"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("cannot edit images"));
+}
+
+#[test]
+fn declined_confirmation_sends_nothing() {
+    // Declining happens before any adapter is built, so no API key is needed
+    cmd()
+        .args(["--confirm", "--model", "gpt-image-1", "a cat"])
+        .env_remove("OPENAI_API_KEY")
+        .write_stdin("n\n")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Prompt:\n  a cat"))
+        .stderr(predicate::str::contains("Cancelled; no request was sent"));
+}
+
+#[test]
+fn estimate_prints_cost_without_sending() {
+    cmd()
+        .args(["--estimate", "--model", "gpt-1", "--quality", "high", "-n", "4", "a cat"])
+        .env_remove("OPENAI_API_KEY")
+        .assert()
+        .success()
+        .stdout(predicate::str::contains("Estimated cost: $0.67 for 4 images"));
+}
+
+#[test]
+fn count_over_the_provider_limit_needs_split() {
+    cmd()
+        .args(["--estimate", "--model", "ideogram", "-n", "12", "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("pass --split to send 2 requests"));
+    cmd()
+        .args(["--estimate", "--split", "--model", "ideogram", "-n", "12", "a cat"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn max_cost_refuses_runs_over_budget() {
+    cmd()
+        .args(["--max-cost", "0.50", "--model", "gpt-1", "--quality", "high", "-n", "4", "a cat"])
+        .env_remove("OPENAI_API_KEY")
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains("Estimated cost $0.67 exceeds --max-cost $0.50"));
+}
@@ -0,0 +1,49 @@
+//! Library API tests — the crate used directly, without the `imagen` binary.
+
+use std::path::PathBuf;
+
+use clap::Parser;
+use imagen::cli::Cli;
+use imagen::context::{Operation, ServiceContext};
+use imagen::generate;
+use imagen::model::Model;
+use imagen::params::AspectRatio;
+use imagen::ports::ImageRequest;
+
+#[tokio::test]
+async fn replayed_generator_answers_a_request() {
+    let cassette =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_fixtures/gemini_cat.cassette.yaml");
+    let ctx = ServiceContext::replaying(&cassette, false).unwrap();
+    let request = ImageRequest::builder()
+        .prompt("a cat")
+        .model(Model::NanoBanana)
+        .aspect_ratio(AspectRatio::Square1x1)
+        .build()
+        .unwrap();
+    let response = ctx.generator.generate(&request).await.unwrap();
+    assert_eq!(response.images.len(), 1);
+    assert_eq!(response.images[0].mime_type, "image/jpeg");
+}
+
+#[tokio::test]
+async fn generate_runs_the_command_line_pipeline() {
+    let dir = std::env::temp_dir().join(format!("imagen_library_{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let config = dir.join("config.toml");
+    std::fs::write(&config, "[history]\nenabled = false\n").unwrap();
+    let cassette =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test_fixtures/gemini_cat.cassette.yaml");
+    let output = dir.join("cat.jpg");
+    let cli = Cli::parse_from([
+        "imagen".to_string(),
+        format!("--mode=replay:{}", cassette.display()),
+        format!("--config={}", config.display()),
+        format!("--output={}", output.display()),
+        "a cat".to_string(),
+    ]);
+    generate::run(&cli.args, Operation::Generate).await.unwrap();
+    assert!(std::fs::metadata(&output).unwrap().len() > 0);
+    let _ = std::fs::remove_dir_all(&dir);
+}
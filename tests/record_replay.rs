@@ -1,6 +1,6 @@
 //! Cassette replay integration tests — zero network I/O.
 //!
-//! All tests set `IMAGEN_REPLAY` to a cassette file path so that the binary
+//! All tests pass `--mode replay:<path>` with a cassette file so that the binary
 //! never contacts a live API endpoint.
 
 use assert_cmd::Command;
@@ -9,7 +9,15 @@ use predicates::prelude::*;
 use std::path::PathBuf;
 
 fn cmd() -> Command {
-    assert_cmd::cargo::cargo_bin_cmd!("imagen")
+    let mut cmd = assert_cmd::cargo::cargo_bin_cmd!("imagen");
+    // Keep history out of the real data directory.
+    cmd.env("IMAGEN_DATA_DIR", std::env::temp_dir().join("imagen_test_data"));
+    cmd.env_remove("IMAGEN_PROFILE");
+    // Never read the developer's own keys from the OS keychain.
+    cmd.env("IMAGEN_NO_KEYRING", "1");
+    // A missing prompt is read from piped stdin; don't wait on the test runner's.
+    cmd.write_stdin("");
+    cmd
 }
 
 /// Absolute path to the `test_fixtures` directory.
@@ -24,7 +32,7 @@ fn gemini_happy_path_creates_file() {
     let _ = std::fs::remove_file(&out);
 
     cmd()
-        .env("IMAGEN_REPLAY", cassette.to_str().unwrap())
+        .arg(format!("--mode=replay:{}", cassette.display()))
         .env_remove("GEMINI_API_KEY")
         .env_remove("OPENAI_API_KEY")
         .args(["--model", "nano-banana", "--output", out.to_str().unwrap(), "a cat"])
@@ -36,6 +44,77 @@ fn gemini_happy_path_creates_file() {
     let _ = std::fs::remove_file(&out);
 }
 
+#[test]
+fn json_report_lists_files_and_warnings() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let out = std::env::temp_dir().join("imagen_test_json_report.jpg");
+    let _ = std::fs::remove_file(&out);
+
+    let output = cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("GEMINI_API_KEY")
+        .args(["--model", "nano-banana", "--quality", "high", "--json"])
+        .args(["--output", out.to_str().unwrap(), "a cat"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["result"], "success");
+    assert_eq!(report["files"], serde_json::json!([out.to_str().unwrap()]));
+    assert_eq!(report["runs"][0]["model"], "gemini-3.1-flash-image-preview");
+    assert_eq!(report["runs"][0]["parameters"]["quality"], "high");
+    assert!(report["warnings"][0].as_str().unwrap().contains("no quality setting"));
+    let _ = std::fs::remove_file(&out);
+}
+
+#[test]
+fn events_stream_one_json_line_per_step() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let out = std::env::temp_dir().join("imagen_test_events.jpg");
+    let _ = std::fs::remove_file(&out);
+
+    let output = cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("GEMINI_API_KEY")
+        .args(["--model", "nano-banana", "--events", "ndjson"])
+        .args(["--output", out.to_str().unwrap(), "a cat"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let events: Vec<serde_json::Value> = String::from_utf8(output.stdout)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    let kinds: Vec<&str> = events.iter().map(|event| event["event"].as_str().unwrap()).collect();
+    assert_eq!(kinds, ["request_started", "image_saved", "batch_summary"]);
+    assert_eq!(events[0]["request_id"], events[1]["request_id"]);
+    assert_eq!(events[1]["path"], out.to_str().unwrap());
+    assert_eq!(
+        (events[2]["result"].as_str(), events[2]["images_saved"].as_u64()),
+        (Some("success"), Some(1))
+    );
+    let _ = std::fs::remove_file(&out);
+}
+
+#[test]
+fn deprecated_replay_variable_still_replays() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let out = std::env::temp_dir().join("imagen_test_replay_env.jpg");
+    let _ = std::fs::remove_file(&out);
+
+    cmd()
+        .env("IMAGEN_REPLAY", cassette.to_str().unwrap())
+        .env_remove("GEMINI_API_KEY")
+        .args(["--model", "nano-banana", "--output", out.to_str().unwrap(), "a cat"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("IMAGEN_REPLAY is deprecated"));
+
+    assert!(out.exists(), "Output file should have been created");
+    let _ = std::fs::remove_file(&out);
+}
+
 #[test]
 fn openai_happy_path_creates_file() {
     let cassette = fixtures_dir().join("openai_cat.cassette.yaml");
@@ -43,7 +122,7 @@ fn openai_happy_path_creates_file() {
     let _ = std::fs::remove_file(&out);
 
     cmd()
-        .env("IMAGEN_REPLAY", cassette.to_str().unwrap())
+        .arg(format!("--mode=replay:{}", cassette.display()))
         .env_remove("GEMINI_API_KEY")
         .env_remove("OPENAI_API_KEY")
         .args(["--model", "gpt-1", "--output", out.to_str().unwrap(), "a cat"])
@@ -55,6 +134,349 @@ fn openai_happy_path_creates_file() {
     let _ = std::fs::remove_file(&out);
 }
 
+#[test]
+fn openai_usage_is_printed_and_logged() {
+    let cassette = fixtures_dir().join("openai_cat.cassette.yaml");
+    let dir = std::env::temp_dir().join("imagen_test_openai_usage");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("runs.jsonl");
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("OPENAI_API_KEY")
+        .env("IMAGEN_RUN_LOG", &log)
+        .args(["--model", "gpt-1", "--verbose", "--output", dir.join("cat.jpg").to_str().unwrap()])
+        .arg("a cat")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Usage: 12 input + 272 output tokens"));
+
+    let summary: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&log).unwrap()).unwrap();
+    assert_eq!(summary["usage"]["input_tokens"], 12);
+    assert_eq!(summary["usage"]["output_tokens"], 272);
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn saved_images_are_recorded_in_history() {
+    let cassette = fixtures_dir().join("openai_cat.cassette.yaml");
+    let dir = std::env::temp_dir().join("imagen_test_history");
+    let _ = std::fs::remove_dir_all(&dir);
+    let out = dir.join("cat.jpg");
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("OPENAI_API_KEY")
+        .env("IMAGEN_DATA_DIR", &dir)
+        .args(["--model", "gpt-1", "--output", out.to_str().unwrap(), "a cat"])
+        .assert()
+        .success();
+
+    let history = |args: &[&str]| {
+        let output = cmd().env("IMAGEN_DATA_DIR", &dir).arg("history").args(args).output().unwrap();
+        assert!(output.status.success());
+        serde_json::from_slice::<Vec<serde_json::Value>>(&output.stdout).unwrap()
+    };
+    let found = history(&["search", "CAT", "--model", "gpt-1", "--since", "1h", "--json"]);
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0]["prompt"], "a cat");
+    assert_eq!(found[0]["model"], "gpt-image-1");
+    assert_eq!(found[0]["output_path"], out.to_str().unwrap());
+    assert_eq!(found[0]["parameters"]["size"], "1K");
+    assert_eq!(found[0]["sha256"].as_str().unwrap().len(), 64);
+    assert!(history(&["list", "--grep", "dog", "--json"]).is_empty());
+
+    cmd().env("IMAGEN_DATA_DIR", &dir).args(["history", "list"]).assert().success().stdout(
+        predicate::str::contains(out.to_str().unwrap()).and(predicate::str::contains("a cat")),
+    );
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn enhanced_prompt_is_replayed_and_sent() {
+    let cassette = fixtures_dir().join("openai_cat_enhanced.cassette.yaml");
+    let dir = std::env::temp_dir().join("imagen_test_enhance");
+    let _ = std::fs::remove_dir_all(&dir);
+    let enhanced = "A tabby cat asleep on a sunlit windowsill, soft morning light, watercolor";
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("OPENAI_API_KEY")
+        .env("IMAGEN_DATA_DIR", &dir)
+        .args(["--model", "gpt-1", "--enhance", "--output", dir.join("cat.jpg").to_str().unwrap()])
+        .arg("a cat")
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(format!("Enhanced prompt: {enhanced}")));
+
+    let output = cmd().env("IMAGEN_DATA_DIR", &dir).args(["history", "list", "--json"]).output();
+    let found: Vec<serde_json::Value> = serde_json::from_slice(&output.unwrap().stdout).unwrap();
+    assert_eq!(found[0]["prompt"], enhanced);
+    assert_eq!(found[0]["parameters"]["enhance"], "gpt-4.1-mini");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cache_reuses_an_identical_earlier_request() {
+    let cassette = fixtures_dir().join("openai_cat.cassette.yaml");
+    let dir = std::env::temp_dir().join("imagen_test_cache");
+    let _ = std::fs::remove_dir_all(&dir);
+    let (first, second) = (dir.join("first.jpg"), dir.join("second.jpg"));
+    let generate = |mode: String, out: &PathBuf| {
+        cmd()
+            .arg(mode)
+            .env_remove("OPENAI_API_KEY")
+            .env("IMAGEN_DATA_DIR", &dir)
+            .args(["--model", "gpt-1", "--cache", "--output", out.to_str().unwrap(), "a cat"])
+            .assert()
+            .success()
+    };
+
+    generate(format!("--mode=replay:{}", cassette.display()), &first)
+        .stderr(predicate::str::contains("Cached:").not());
+    // The cassette is never opened: the image comes from the first run.
+    generate("--mode=replay:missing.cassette.yaml".into(), &second)
+        .stderr(predicate::str::contains("Cached: reusing 1 image saved earlier"));
+    assert_eq!(std::fs::read(&first).unwrap(), std::fs::read(&second).unwrap());
+
+    // Once the earlier file is gone, the request goes to the provider again.
+    std::fs::remove_file(&first).unwrap();
+    std::fs::remove_file(&second).unwrap();
+    cmd()
+        .arg("--mode=replay:missing.cassette.yaml")
+        .env_remove("OPENAI_API_KEY")
+        .env("IMAGEN_DATA_DIR", &dir)
+        .args(["--model", "gpt-1", "--cache", "--output", second.to_str().unwrap(), "a cat"])
+        .assert()
+        .failure();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn cache_hits_are_saved_without_running_save_steps_again() {
+    let png = {
+        let mut buf = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(6, 3).write_to(&mut buf, image::ImageFormat::Png).unwrap();
+        base64::engine::general_purpose::STANDARD.encode(buf.into_inner())
+    };
+    let dir = std::env::temp_dir().join("imagen_test_cache_steps");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let cassette = dir.join("wide.cassette.yaml");
+    std::fs::write(
+        &cassette,
+        format!(
+            "name: wide\nrecorded_at: \"2026-02-01T00:00:00Z\"\ncommit: test\ninteractions:\n  - seq: 0\n    port: image_generator\n    method: generate\n    input: {{}}\n    output:\n      Ok:\n        images:\n          - data: {png}\n            mime_type: image/png\n"
+        ),
+    )
+    .unwrap();
+    let generate = |cassette: &PathBuf, name: &str, steps: &[&str]| {
+        let out = dir.join(name);
+        let output = cmd()
+            .arg(format!("--mode=replay:{}", cassette.display()))
+            .env_remove("GEMINI_API_KEY")
+            .env("IMAGEN_DATA_DIR", &dir)
+            .args(["--model", "nano-banana", "--cache", "--format", "png"])
+            .args(steps)
+            .args(["--output", out.to_str().unwrap(), "a cat"])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        let cached = String::from_utf8_lossy(&output.stderr).contains("Cached:");
+        (image::image_dimensions(&out).unwrap(), cached)
+    };
+    let missing = PathBuf::from("missing.cassette.yaml");
+
+    let rotate = ["--post", "rotate=90"];
+    assert_eq!(generate(&cassette, "first.png", &rotate), ((3, 6), false));
+    assert_eq!(generate(&missing, "second.png", &rotate), ((3, 6), true), "rotated once");
+    // A run without the step gets the provider's image, not the rotated file.
+    assert_eq!(generate(&cassette, "plain.png", &[]), ((6, 3), false));
+
+    // Nor is a watermarked file reused for a run with another mark, or stamped twice.
+    let pixels = |name: &str| image::open(dir.join(name)).unwrap().to_rgb8().into_raw();
+    let mark = |mark| ["--watermark", mark, "--watermark-opacity", "1"];
+    assert_eq!(generate(&cassette, "mark-a.png", &mark("A")), ((6, 3), false));
+    assert_eq!(generate(&cassette, "mark-b.png", &mark("B")), ((6, 3), false));
+    assert_ne!(pixels("mark-a.png"), pixels("mark-b.png"));
+    assert_eq!(generate(&missing, "mark-a-again.png", &mark("A")), ((6, 3), true));
+    assert_eq!(pixels("mark-a-again.png"), pixels("mark-a.png"), "stamped once");
+    let bytes = |name: &str| std::fs::read(dir.join(name)).unwrap();
+    assert_eq!(bytes("mark-a-again.png"), bytes("mark-a.png"), "tagged once");
+
+    // A stripped file only answers runs that strip, and the other way round.
+    let tagged = |name: &str| bytes(name).windows(10).any(|w| w == b"parameters");
+    assert_eq!(generate(&cassette, "stripped.png", &["--strip"]), ((6, 3), false));
+    assert_eq!(generate(&missing, "stripped-again.png", &["--strip"]), ((6, 3), true));
+    assert!(!tagged("stripped.png") && !tagged("stripped-again.png"));
+    assert_eq!(generate(&missing, "tagged.png", &[]), ((6, 3), true));
+    assert!(tagged("tagged.png"), "served the tagged file, not the stripped one");
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn replicate_happy_path_creates_file() {
+    let cassette = fixtures_dir().join("replicate_flux.cassette.yaml");
+    let out = std::env::temp_dir().join("imagen_test_replicate_happy.jpg");
+    let _ = std::fs::remove_file(&out);
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("REPLICATE_API_TOKEN")
+        .args([
+            "--model",
+            "replicate/black-forest-labs/flux-schnell",
+            "--output",
+            out.to_str().unwrap(),
+            "a cat",
+        ])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Saved:"));
+
+    assert!(out.exists(), "Output file should have been created");
+    let _ = std::fs::remove_file(&out);
+}
+
+#[test]
+fn offline_replay_creates_file() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let out = std::env::temp_dir().join("imagen_test_offline_replay.jpg");
+    let _ = std::fs::remove_file(&out);
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("GEMINI_API_KEY")
+        .env_remove("OPENAI_API_KEY")
+        .args(["--offline", "--output", out.to_str().unwrap(), "a cat"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains("Saved:"));
+
+    assert!(out.exists(), "Output file should have been created");
+    let _ = std::fs::remove_file(&out);
+}
+
+#[test]
+fn id_flag_prints_stable_request_id() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let out = std::env::temp_dir().join("imagen_test_request_id.jpg");
+
+    let run = || {
+        let output = cmd()
+            .arg(format!("--mode=replay:{}", cassette.display()))
+            .env_remove("GEMINI_API_KEY")
+            .args(["--id", "--force", "--output", out.to_str().unwrap(), "a cat"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    };
+
+    let first = run();
+    assert_eq!(first.len(), 16, "expected a 16-char hex ID, got: {first}");
+    assert!(first.chars().all(|c| c.is_ascii_hexdigit()));
+    assert_eq!(run(), first, "same request should produce the same ID");
+
+    let _ = std::fs::remove_file(&out);
+}
+
+#[test]
+fn existing_output_is_not_overwritten() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let dir = std::env::temp_dir().join("imagen_test_no_clobber");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let out = dir.join("cat.jpg");
+    std::fs::write(&out, b"keep me").unwrap();
+    let run = |extra: &[&str]| {
+        cmd()
+            .arg(format!("--mode=replay:{}", cassette.display()))
+            .env_remove("GEMINI_API_KEY")
+            .args(["--output", out.to_str().unwrap(), "a cat"])
+            .args(extra)
+            .assert()
+    };
+
+    run(&[]).failure().stderr(predicate::str::contains("already exists. Pass --force"));
+    assert_eq!(std::fs::read(&out).unwrap(), b"keep me");
+
+    run(&["--no-clobber=false"]).success();
+    assert_eq!(std::fs::read(&out).unwrap(), b"keep me");
+    assert!(dir.join("cat-2.jpg").exists());
+
+    run(&["--force"]).success();
+    assert_ne!(std::fs::read(&out).unwrap(), b"keep me");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn sidecar_describes_the_saved_image() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let out = std::env::temp_dir().join("imagen_test_sidecar.jpg");
+    let sidecar = std::env::temp_dir().join("imagen_test_sidecar.jpg.json");
+    let _ = std::fs::remove_file(&out);
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("GEMINI_API_KEY")
+        .args(["--model", "nano-banana", "--sidecar", "--output", out.to_str().unwrap(), "a cat"])
+        .assert()
+        .success();
+
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&sidecar).unwrap()).unwrap();
+    assert_eq!(json["file"], "imagen_test_sidecar.jpg");
+    assert_eq!(json["model"], "gemini-3.1-flash-image-preview");
+    assert_eq!(json["provider"], "gemini");
+    assert_eq!(json["request"]["prompt"], "a cat");
+    assert!(json["timings"]["elapsed_ms"].is_i64());
+
+    let _ = std::fs::remove_file(&out);
+    let _ = std::fs::remove_file(&sidecar);
+}
+
+#[test]
+fn cassette_exports_wiremock_mappings() {
+    let cassette = fixtures_dir().join("openai_cat.cassette.yaml");
+
+    let output =
+        cmd().args(["cassette", "to-mock-server", cassette.to_str().unwrap()]).output().unwrap();
+    assert!(output.status.success());
+
+    let doc: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mapping = &doc["mappings"][0];
+    assert_eq!(mapping["request"]["urlPath"], "/v1/images/generations");
+    assert_eq!(mapping["response"]["status"], 200);
+    assert!(mapping["response"]["jsonBody"]["data"][0]["b64_json"].is_string());
+}
+
+#[test]
+fn synthetic_rate_limit_replays_as_api_error() {
+    let cassette = std::env::temp_dir().join("imagen_test_synth_429.cassette.yaml");
+
+    cmd()
+        .args(["cassette", "synth", "--error", "429", "--provider", "openai"])
+        .args(["--model", "gpt-1", "-o", cassette.to_str().unwrap()])
+        .assert()
+        .success();
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("OPENAI_API_KEY")
+        .args(["--model", "gpt-1", "a cat"])
+        .assert()
+        .failure()
+        .code(1)
+        .stderr(predicate::str::contains("API error (429)"))
+        .stderr(predicate::str::contains("rate_limit_exceeded"));
+
+    let _ = std::fs::remove_file(&cassette);
+}
+
 #[test]
 fn auto_filename_uses_kebab_case_with_timestamp() {
     let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
@@ -66,7 +488,7 @@ fn auto_filename_uses_kebab_case_with_timestamp() {
     }
 
     cmd()
-        .env("IMAGEN_REPLAY", cassette.to_str().unwrap())
+        .arg(format!("--mode=replay:{}", cassette.display()))
         .env_remove("GEMINI_API_KEY")
         .env_remove("OPENAI_API_KEY")
         .args(["--model", "nano-banana", "a cat"])
@@ -85,6 +507,28 @@ fn auto_filename_uses_kebab_case_with_timestamp() {
     let _ = std::fs::remove_dir_all(&work_dir);
 }
 
+#[test]
+fn hash_slug_names_file_after_request_id() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let work_dir = std::env::temp_dir().join("imagen_test_hash_slug");
+    let _ = std::fs::remove_dir_all(&work_dir);
+    std::fs::create_dir_all(&work_dir).unwrap();
+
+    let output = cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("GEMINI_API_KEY")
+        .args(["--id", "--slug", "hash", "a cat"])
+        .current_dir(&work_dir)
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let request_id = String::from_utf8(output.stdout).unwrap().trim().to_string();
+
+    assert!(work_dir.join(format!("{request_id}.jpg")).exists());
+
+    let _ = std::fs::remove_dir_all(&work_dir);
+}
+
 #[test]
 fn format_png_converts_jpeg_to_png() {
     // Generate a real 1×1 JPEG using the image crate, embed it in a temporary
@@ -108,7 +552,7 @@ fn format_png_converts_jpeg_to_png() {
     let _ = std::fs::remove_file(&out);
 
     cmd()
-        .env("IMAGEN_REPLAY", cassette_path.to_str().unwrap())
+        .arg(format!("--mode=replay:{}", cassette_path.display()))
         .env_remove("GEMINI_API_KEY")
         .env_remove("OPENAI_API_KEY")
         .args([
@@ -136,3 +580,324 @@ fn format_png_converts_jpeg_to_png() {
     let _ = std::fs::remove_file(&out);
     let _ = std::fs::remove_file(&cassette_path);
 }
+
+#[test]
+fn ratio_sweep_saves_one_file_per_ratio() {
+    let interaction = |seq: u32| {
+        format!(
+            "  - seq: {seq}\n    port: image_generator\n    method: generate\n    input: {{}}\n    output:\n      Ok:\n        images:\n          - data: /9j/2Q==\n            mime_type: image/jpeg\n"
+        )
+    };
+    let cassette_content = format!(
+        "name: sweep-test\nrecorded_at: \"2026-02-01T00:00:00Z\"\ncommit: test\ninteractions:\n{}{}",
+        interaction(0),
+        interaction(1)
+    );
+    let cassette_path = std::env::temp_dir().join("imagen_test_sweep.cassette.yaml");
+    std::fs::write(&cassette_path, &cassette_content).unwrap();
+
+    let dir = std::env::temp_dir().join("imagen_test_sweep");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette_path.display()))
+        .env_remove("GEMINI_API_KEY")
+        .args(["--aspect-ratio", "1:1,16:9", "--output", dir.join("kit.jpg").to_str().unwrap()])
+        .arg("a cat")
+        .assert()
+        .success();
+
+    assert!(dir.join("kit-1x1.jpg").exists());
+    assert!(dir.join("kit-16x9.jpg").exists());
+    assert!(!dir.join("kit.jpg").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&cassette_path);
+}
+
+#[test]
+fn batch_saves_each_prompt_and_reports_failures() {
+    let cassette_content = "name: batch-test\nrecorded_at: \"2026-02-01T00:00:00Z\"\ncommit: test\ninteractions:\n  - seq: 0\n    port: image_generator\n    method: generate\n    input: {}\n    output:\n      Ok:\n        images:\n          - data: /9j/2Q==\n            mime_type: image/jpeg\n  - seq: 1\n    port: image_generator\n    method: generate\n    input: {}\n    output:\n      Err: 'API error (500): internal'\n";
+    let cassette_path = std::env::temp_dir().join("imagen_test_batch.cassette.yaml");
+    std::fs::write(&cassette_path, cassette_content).unwrap();
+
+    let dir = std::env::temp_dir().join("imagen_test_batch");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("prompts.txt"), "a cat\n\na dog\n").unwrap();
+
+    cmd()
+        .arg("batch")
+        .arg(format!("--mode=replay:{}", cassette_path.display()))
+        .env_remove("GEMINI_API_KEY")
+        .arg("prompts.txt")
+        .current_dir(&dir)
+        .assert()
+        .failure()
+        .code(2)
+        .stderr(predicate::str::contains("Warning: prompt \"a dog\" failed: API error (500)"))
+        .stderr(predicate::str::contains("Saved 1 of 2 images"));
+
+    let saved: Vec<_> = std::fs::read_dir(&dir)
+        .unwrap()
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "jpg"))
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    assert_eq!(saved.len(), 1);
+    assert!(saved[0].starts_with("a-cat-"), "got: {}", saved[0]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&cassette_path);
+}
+
+#[test]
+fn run_log_gets_one_line_per_run() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let dir = std::env::temp_dir().join("imagen_test_run_log");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let log = dir.join("runs.jsonl");
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("GEMINI_API_KEY")
+        .env("IMAGEN_RUN_LOG", &log)
+        .args(["--output", dir.join("cat.jpg").to_str().unwrap(), "a cat"])
+        .assert()
+        .success();
+
+    let line = std::fs::read_to_string(&log).unwrap();
+    let summary: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(summary["provider"], "gemini");
+    assert_eq!(summary["result"], "success");
+    assert_eq!(summary["images_saved"], 1);
+    assert!(!line.contains("a cat"), "the prompt must not be logged: {line}");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn batch_manifest_rows_override_settings() {
+    let interaction = |seq: u32| {
+        format!(
+            "  - seq: {seq}\n    port: image_generator\n    method: generate\n    input: {{}}\n    output:\n      Ok:\n        images:\n          - data: /9j/2Q==\n            mime_type: image/jpeg\n"
+        )
+    };
+    let cassette_content = format!(
+        "name: manifest-test\nrecorded_at: \"2026-02-01T00:00:00Z\"\ncommit: test\ninteractions:\n{}{}",
+        interaction(0),
+        interaction(1)
+    );
+    let cassette_path = std::env::temp_dir().join("imagen_test_manifest.cassette.yaml");
+    std::fs::write(&cassette_path, &cassette_content).unwrap();
+
+    let dir = std::env::temp_dir().join("imagen_test_manifest");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("jobs.csv"),
+        "prompt,aspect_ratio,output,seed\na knight,16:9,sprites/knight.jpg,7\na castle,,,\n",
+    )
+    .unwrap();
+
+    cmd()
+        .arg("batch")
+        .arg(format!("--mode=replay:{}", cassette_path.display()))
+        .env_remove("GEMINI_API_KEY")
+        .args(["jobs.csv", "--slug", "hash"])
+        .current_dir(&dir)
+        .assert()
+        .success();
+
+    assert!(dir.join("sprites/knight.jpg").exists());
+    let auto_named = std::fs::read_dir(&dir)
+        .unwrap()
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "jpg"))
+        .count();
+    assert_eq!(auto_named, 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&cassette_path);
+}
+
+#[test]
+fn batch_resume_skips_finished_prompts() {
+    let interaction = |seq: u32, output: &str| {
+        format!(
+            "  - seq: {seq}\n    port: image_generator\n    method: generate\n    input: {{}}\n    output:\n{output}"
+        )
+    };
+    let ok = "      Ok:\n        images:\n          - data: /9j/2Q==\n            mime_type: image/jpeg\n";
+    let cassette = |interactions: String| {
+        format!(
+            "name: resume-test\nrecorded_at: \"2026-02-01T00:00:00Z\"\ncommit: test\ninteractions:\n{interactions}"
+        )
+    };
+    let cassette_path = std::env::temp_dir().join("imagen_test_resume.cassette.yaml");
+    let dir = std::env::temp_dir().join("imagen_test_resume");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("prompts.txt"), "a cat\na dog\n").unwrap();
+    let run = || {
+        cmd()
+            .arg("batch")
+            .arg(format!("--mode=replay:{}", cassette_path.display()))
+            .env_remove("GEMINI_API_KEY")
+            .args(["prompts.txt", "--resume", "state.json", "--slug", "hash"])
+            .current_dir(&dir)
+            .assert()
+    };
+
+    // The first run saves the cat, but the dog fails.
+    let first = interaction(0, ok) + &interaction(1, "      Err: 'API error (500): internal'\n");
+    std::fs::write(&cassette_path, cassette(first)).unwrap();
+    run().code(2);
+
+    // Resuming sends only the dog: the cassette has a single interaction.
+    std::fs::write(&cassette_path, cassette(interaction(0, ok))).unwrap();
+    run().success().stderr(predicate::str::contains("Resuming: 1 of 2 requests already finished"));
+
+    let state: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(dir.join("state.json")).unwrap()).unwrap();
+    let prompts: Vec<&str> = state["finished"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|r| r["prompt"].as_str().unwrap())
+        .collect();
+    assert_eq!(prompts, ["a cat", "a dog"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_file(&cassette_path);
+}
+
+#[test]
+fn compare_saves_each_model_side_by_side() {
+    let cassette = fixtures_dir().join("gemini_cat.cassette.yaml");
+    let dir = std::env::temp_dir().join("imagen_test_compare");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let output = cmd()
+        .arg("compare")
+        .arg(format!("--mode=replay:{}", cassette.display()))
+        .env_remove("GEMINI_API_KEY")
+        .args(["--models", "nano-banana,gemini-2.5-flash-image", "--json"])
+        .args(["--output", dir.join("cat.jpg").to_str().unwrap(), "a cat"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    let models: Vec<&str> = report["runs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|run| run["model"].as_str().unwrap())
+        .collect();
+    assert_eq!(models, ["gemini-3.1-flash-image-preview", "gemini-2.5-flash-image"]);
+    assert!(dir.join("cat-nano-banana.jpg").exists());
+    assert!(dir.join("cat-gemini-2.5-flash-image.jpg").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn fallback_model_answers_when_the_provider_is_down() {
+    let cassette_content = "name: fallback-test\nrecorded_at: \"2026-02-01T00:00:00Z\"\ncommit: test\ninteractions:\n  - seq: 0\n    port: image_generator\n    method: generate\n    input: {}\n    output:\n      Err: 'API error (503): overloaded'\n  - seq: 1\n    port: image_generator\n    method: generate\n    input: {}\n    output:\n      Ok:\n        images:\n          - data: /9j/2Q==\n            mime_type: image/jpeg\n";
+    let cassette_path = std::env::temp_dir().join("imagen_test_fallback.cassette.yaml");
+    std::fs::write(&cassette_path, cassette_content).unwrap();
+    let out = std::env::temp_dir().join("imagen_test_fallback.jpg");
+    let sidecar = std::env::temp_dir().join("imagen_test_fallback.jpg.json");
+    let _ = std::fs::remove_file(&out);
+
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette_path.display()))
+        .env_remove("GEMINI_API_KEY")
+        .args(["--model", "nano-banana", "--fallback", "gpt-1", "--seed", "7", "--sidecar"])
+        .args(["--output", out.to_str().unwrap(), "a cat"])
+        .assert()
+        .success()
+        .stderr(predicate::str::contains(
+            "Warning: API error (503): overloaded; falling back to gpt-image-1",
+        ));
+
+    let json: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&sidecar).unwrap()).unwrap();
+    assert_eq!(json["model"], "gpt-image-1");
+    assert_eq!(json["provider"], "openai");
+    assert!(json["request"]["seed"].is_null(), "OpenAI takes no seed");
+
+    // The fallback has to fit under --max-cost too.
+    let _ = std::fs::remove_file(&out);
+    cmd()
+        .arg(format!("--mode=replay:{}", cassette_path.display()))
+        .env_remove("GEMINI_API_KEY")
+        .args(["--model", "nano-banana", "--fallback", "gpt-1", "--max-cost", "0.10"])
+        .args(["--output", out.to_str().unwrap(), "a cat"])
+        .assert()
+        .failure()
+        .stderr(predicate::str::contains(
+            "projected cost $0.23 would exceed --max-cost $0.10; not falling back to gpt-image-1",
+        ));
+    assert!(!out.exists());
+
+    let _ = std::fs::remove_file(&sidecar);
+    let _ = std::fs::remove_file(&cassette_path);
+}
+
+#[test]
+fn refused_prompt_is_softened_or_sent_to_another_model() {
+    let interaction = |seq: u32, port: &str, method: &str, output: &str| {
+        format!("  - seq: {seq}\n    port: {port}\n    method: {method}\n    input: {{}}\n    output:\n{output}")
+    };
+    let generate = |seq, output| interaction(seq, "image_generator", "generate", output);
+    let ok = "      Ok:\n        images:\n          - data: /9j/2Q==\n            mime_type: image/jpeg\n";
+    let cassette = |interactions: String| {
+        format!(
+            "name: refusal-test\nrecorded_at: \"2026-02-01T00:00:00Z\"\ncommit: test\ninteractions:\n{interactions}"
+        )
+    };
+    let cassette_path = std::env::temp_dir().join("imagen_test_refusal.cassette.yaml");
+    let out = std::env::temp_dir().join("imagen_test_refusal.jpg");
+    let sidecar = std::env::temp_dir().join("imagen_test_refusal.jpg.json");
+    let run = |args: &[&str]| {
+        let _ = std::fs::remove_file(&out);
+        cmd()
+            .arg(format!("--mode=replay:{}", cassette_path.display()))
+            .env_remove("GEMINI_API_KEY")
+            .env_remove("OPENAI_API_KEY")
+            .args(args)
+            .args(["--sidecar", "--output", out.to_str().unwrap(), "a knight in a bloody duel"])
+            .assert()
+            .success()
+    };
+    let request = || -> serde_json::Value {
+        serde_json::from_str(&std::fs::read_to_string(&sidecar).unwrap()).unwrap()
+    };
+
+    // Gemini blocks the prompt; the text model softens it and the retry succeeds.
+    let blocked = "      Err: 'Prompt blocked (reason: SAFETY). Flagged: HARM_CATEGORY_DANGEROUS_CONTENT (HIGH)'\n";
+    let softened = "a knight in a fencing duel";
+    let rewrite = interaction(0, "prompt_enhancer", "enhance", &format!("      Ok: {softened}\n"));
+    std::fs::write(&cassette_path, cassette(generate(0, blocked) + &rewrite + &generate(1, ok)))
+        .unwrap();
+    run(&["--model", "nano-banana", "--on-refusal", "soften"]).stderr(predicate::str::contains(
+        format!("Prompt blocked (reason: SAFETY). Flagged: HARM_CATEGORY_DANGEROUS_CONTENT (HIGH); retrying with the prompt softened by gemini-2.5-flash: {softened}"),
+    ));
+    assert_eq!(request()["request"]["prompt"], softened);
+
+    // OpenAI's moderation refuses it; the prompt goes to Gemini unchanged.
+    let moderated =
+        "      Err: 'API error (400): {\"error\": {\"code\": \"moderation_blocked\"}}'\n";
+    std::fs::write(&cassette_path, cassette(generate(0, moderated) + &generate(1, ok))).unwrap();
+    run(&["--model", "gpt-1", "--on-refusal", "nano-banana"])
+        .stderr(predicate::str::contains("; falling back to gemini-3.1-flash-image-preview"));
+    assert_eq!(request()["model"], "gemini-3.1-flash-image-preview");
+    assert_eq!(request()["request"]["prompt"], "a knight in a bloody duel");
+
+    let _ = std::fs::remove_file(&out);
+    let _ = std::fs::remove_file(&sidecar);
+    let _ = std::fs::remove_file(&cassette_path);
+}